@@ -0,0 +1,57 @@
+#!/bin/sh
+# Builds the firmware with each feature preset and reports how much flash and RAM it uses, so that a preset that
+# doesn't fit is noticed before it is flashed. Fails if a preset doesn't build, e.g. because its code overflows the
+# flash, and warns if its static RAM leaves less than STACK_RESERVE bytes for the stack, which the linker doesn't check.
+#
+# Usage: ./size-report.sh [preset...], which builds all presets if none are given.
+set -eu
+cd "$(dirname "$0")"
+
+# Bytes of RAM that should stay free for the stack.
+STACK_RESERVE=512
+
+# One preset per line: name, microcontroller, flash without the bootloader (the Nano's old 2 KB bootloader, which leaves
+# less room than Optiboot), RAM and the cargo arguments.
+PRESETS="
+nano atmega328p 30720 2048
+nano-terse atmega328p 30720 2048 --features terse-errors
+nano-de atmega328p 30720 2048 --features lang-de
+nano-semaphore atmega328p 30720 2048 --features semaphore
+mega atmega2560 253952 8192 --no-default-features --features mega --target avr-specs/avr-atmega2560.json
+mega-de atmega2560 253952 8192 --no-default-features --features mega,lang-de --target avr-specs/avr-atmega2560.json
+"
+
+# Reports one preset, and fails if it doesn't build.
+report() {
+    name=$1 mcu=$2 flash=$3 ram=$4
+    shift 4
+    if ! cargo build --release --quiet "$@"; then
+        echo "$name: build failed"
+        return 1
+    fi
+    avr-size -A "target/avr-$mcu/release/train-signalling.elf" | awk -v name="$name" -v flash="$flash" -v ram="$ram" -v reserve="$STACK_RESERVE" '
+        $1 == ".text" || $1 == ".data" { used_flash += $2 }
+        $1 == ".data" || $1 == ".bss" || $1 == ".noinit" { used_ram += $2 }
+        END {
+            printf "%-16s flash %6d/%6d (%3d%%)  RAM %5d/%5d (%3d%%)\n", name, used_flash, flash,
+                100 * used_flash / flash, used_ram, ram, 100 * used_ram / ram
+            if (used_ram > ram - reserve) {
+                printf "%s: warning: less than %d bytes of RAM are left for the stack\n", name, reserve
+            }
+        }'
+}
+
+selected=" $* "
+failed=0
+# the presets are read from a here-document instead of a pipe, so that the loop runs in this shell and can set failed.
+while read -r name mcu flash ram arguments; do
+    [ -n "$name" ] || continue
+    if [ "$selected" != "  " ] && [ "${selected#* "$name" }" = "$selected" ]; then
+        continue
+    fi
+    # shellcheck disable=SC2086 # the arguments are split on purpose.
+    report "$name" "$mcu" "$flash" "$ram" $arguments || failed=1
+done <<END
+$PRESETS
+END
+exit "$failed"
@@ -0,0 +1,65 @@
+//! Module for keeping time using timer 0.
+//!
+//! The timer fires an interrupt every millisecond, which also regularly wakes up the main loop.
+
+use core::cell::Cell;
+
+use avr_device::interrupt;
+use avr_device::interrupt::Mutex;
+
+// 16 MHz / 64 / 250 = 1 kHz
+const PRESCALER: u32 = 64;
+const TIMER_COUNTS: u32 = 250;
+const MILLIS_INCREMENT: u32 = PRESCALER * TIMER_COUNTS / 16_000;
+const MICROS_PER_COUNT: u32 = PRESCALER / 16;
+
+static MILLIS_COUNTER: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+
+/// Starts the millisecond clock.
+pub fn init(tc0: arduino_hal::pac::TC0) {
+    tc0.tccr0a.write(|w| w.wgm0().ctc());
+    tc0.ocr0a.write(|w| w.bits((TIMER_COUNTS - 1) as u8));
+    tc0.tccr0b.write(|w| w.cs0().prescale_64());
+    tc0.timsk0.write(|w| w.ocie0a().set_bit());
+
+    interrupt::free(|cs| MILLIS_COUNTER.borrow(cs).set(0));
+}
+
+#[cfg_attr(not(feature = "mega"), avr_device::interrupt(atmega328p))]
+#[cfg_attr(feature = "mega", avr_device::interrupt(atmega2560))]
+#[allow(non_snake_case)]
+fn TIMER0_COMPA() {
+    interrupt::free(|cs| {
+        let counter = MILLIS_COUNTER.borrow(cs);
+        counter.set(counter.get().wrapping_add(MILLIS_INCREMENT));
+    });
+}
+
+/// Returns the number of milliseconds since the clock was started. Wraps around after about 49 days.
+pub fn millis() -> u32 {
+    interrupt::free(|cs| MILLIS_COUNTER.borrow(cs).get())
+}
+
+/// Returns the number of microseconds since the clock was started, with a resolution of 4 µs. Wraps around after about 71 minutes. Can be called from interrupts.
+pub fn micros() -> u32 {
+    // the timer belongs to the clock, and its counter and compare flag are only read here.
+    let tc0 = unsafe { &*arduino_hal::pac::TC0::ptr() };
+    interrupt::free(|cs| {
+        let mut millis = MILLIS_COUNTER.borrow(cs).get();
+        let counts = u32::from(tc0.tcnt0.read().bits());
+        // the counter may have restarted after interrupts were disabled, before the interrupt counted the millisecond.
+        if tc0.tifr0.read().ocf0a().bit_is_set() && counts < TIMER_COUNTS - 1 {
+            millis = millis.wrapping_add(MILLIS_INCREMENT);
+        }
+        millis
+            .wrapping_mul(1000)
+            .wrapping_add(counts * MICROS_PER_COUNT)
+    })
+}
+
+/// Waits until at least the given duration has passed since the start time.
+pub fn wait_since(start: u32, duration_ms: u32) {
+    while millis().wrapping_sub(start) < duration_ms {
+        avr_device::asm::sleep();
+    }
+}
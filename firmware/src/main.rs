@@ -0,0 +1,3864 @@
+#![no_std]
+#![no_main]
+#![feature(let_chains, abi_avr_interrupt)]
+
+use core::cell::Cell;
+use core::cell::RefCell;
+use core::convert::Infallible;
+use core::sync::atomic::compiler_fence;
+use core::sync::atomic::Ordering;
+
+use arbitration::Arbiter;
+use arbitration::Arbitration;
+use arbitration::CommandSource;
+use arduino_hal::adc;
+use arduino_hal::hal::usart::BaudrateArduinoExt;
+use arduino_hal::hal::usart::Event;
+use arduino_hal::hal::Wdt;
+use arduino_hal::port::mode::Floating;
+use arduino_hal::port::mode::Input;
+use arduino_hal::port::mode::Output;
+use arduino_hal::port::Pin;
+use arduino_hal::prelude::*;
+use arduino_hal::spi;
+use arduino_hal::Delay;
+use arduino_hal::Eeprom;
+use arming::Arming;
+use arrayvec::ArrayVec;
+use auth::Authenticator;
+use auth::AUTHENTICATION_COUNTER_EEPROM_OFFSET;
+use aux_outputs::AspectChange;
+use aux_outputs::AuxInputs;
+use aux_outputs::AuxOutput;
+use aux_outputs::AuxRule;
+use avr_device::interrupt;
+use avr_device::interrupt::CriticalSection;
+use avr_device::interrupt::Mutex;
+use commands::get_next_command;
+use commands::AspectCommand;
+use commands::Command;
+use config::Config;
+use config::ConfigKey;
+use dcc::AccessoryCommand;
+use dcc::AccessoryMapping;
+use dcc::DccDecoder;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::digital::StatefulOutputPin;
+use fast_clock::FastClock;
+use fast_clock::MINUTES_PER_DAY;
+use head_id::HeadType;
+use journal::Journal;
+use journal::JOURNAL_EEPROM_OFFSET;
+use keypad::KeyMatrix;
+use lamp_aging::AgingEvent;
+use lamp_aging::LampAging;
+use lamp_test::LampTestLog;
+use lamp_test::LampTestScheduler;
+use loconet::LocoNetReceiver;
+use logging::Channel;
+use logging::LogFilter;
+use logging::Severity;
+use maintenance::LampSet;
+use maintenance::RawLampControl;
+use maintenance::SwitchTimings;
+use mast::Mast;
+use memory::HighWaterMark;
+use motorola::MotorolaDecoder;
+use nb::Error;
+#[cfg(not(feature = "semaphore"))]
+use panel::PanelOutput;
+use platform::AvrPlatform;
+use port::Port;
+use servo::ServoOutput;
+use signalling::arbitration;
+use signalling::arming;
+use signalling::auth;
+use signalling::aux_outputs;
+use signalling::bank::BankedSignal;
+use signalling::baud;
+use signalling::baud::BaudRate;
+use signalling::bidib;
+use signalling::bidib::BidibByte;
+use signalling::bidib::BidibNode;
+use signalling::binary;
+use signalling::binary::BinaryRequest;
+use signalling::binary::FrameReceiver;
+use signalling::cmri;
+use signalling::cmri::CmriByte;
+use signalling::cmri::CmriMapping;
+use signalling::cmri::CmriReceiver;
+use signalling::cmri::CmriRequest;
+use signalling::commands;
+use signalling::commands::CommandError;
+use signalling::config;
+use signalling::dcc;
+use signalling::debug_console;
+use signalling::debug_console::DebugConsole;
+#[cfg(feature = "mega")]
+use signalling::dmx;
+#[cfg(feature = "mega")]
+use signalling::dmx::DmxMapping;
+#[cfg(feature = "mega")]
+use signalling::dmx::DmxReceiver;
+use signalling::dwell::AspectQueue;
+use signalling::fast_clock;
+use signalling::framing::LineFramer;
+#[cfg(feature = "mega")]
+use signalling::gateway;
+#[cfg(feature = "mega")]
+use signalling::gateway::ReturnLines;
+use signalling::head_id;
+use signalling::journal;
+use signalling::keypad;
+use signalling::lamp_aging;
+use signalling::lamp_test;
+use signalling::level_crossing::LevelCrossing;
+use signalling::loconet;
+use signalling::logging;
+use signalling::maintenance;
+use signalling::mast;
+use signalling::max7219::Max7219;
+use signalling::mcp2515;
+use signalling::mcp2515::Mcp2515;
+use signalling::motorola;
+#[cfg(feature = "mega")]
+use signalling::mqtt;
+#[cfg(feature = "mega")]
+use signalling::mqtt::MqttClient;
+#[cfg(feature = "mega")]
+use signalling::mqtt::MqttEvent;
+use signalling::mqtt::MqttSettings;
+use signalling::openlcb::OpenLcbNode;
+#[cfg(not(feature = "semaphore"))]
+use signalling::panel;
+use signalling::platform::Platform;
+use signalling::presentation::PresentationMirror;
+use signalling::presentation::PresentationState;
+use signalling::presentation::StateChecksum;
+use signalling::random;
+use signalling::random::Rng;
+use signalling::random::XorShift32;
+use signalling::retransmit::RetransmitWindow;
+use signalling::ring_buffer::RingBuffer;
+use signalling::selectrix::SelectrixMapping;
+use signalling::selectrix::SelectrixReceiver;
+use signalling::semaphore::EndStops;
+#[cfg(feature = "semaphore")]
+use signalling::semaphore::SemaphoreSignal;
+use signalling::semaphore::Servo;
+use signalling::signals;
+use signalling::slew::SlewLimitedPin;
+use signalling::srcp;
+use signalling::srcp::SrcpReply;
+use signalling::srcp::SrcpSession;
+use signalling::stop_hold::StopHold;
+use signalling::voting::VotedPin;
+use signalling::w5500::NetworkSettings;
+use signalling::w5500::W5500;
+use signalling::warm_up::LampWarmUp;
+use signalling::wear_leveling;
+use signalling::wear_leveling::WearLevelledByte;
+#[cfg(feature = "mega")]
+use signalling::xpressnet;
+#[cfg(feature = "mega")]
+use signalling::xpressnet::XpressNetEvent;
+#[cfg(feature = "mega")]
+use signalling::xpressnet::XpressNetReceiver;
+use signalling::zs2::Zs2Indicator;
+#[cfg(not(feature = "semaphore"))]
+use signalling::zs3::Zs3Indicator;
+use signals::DwarfSignal;
+use signals::HVMainSignalAspect;
+#[cfg(not(feature = "semaphore"))]
+use signals::HVSignalGroup;
+use signals::LampRole;
+use signals::Signal;
+use signals::SignalAspect;
+
+pub mod clock;
+pub mod memory;
+pub mod platform;
+pub mod port;
+pub mod servo;
+
+// ----------------------------
+// Signal constants: adopt these per signal.
+// Aspects of the signalling system. Apart from this, only the construction of the signal at the start of main() differs between signalling systems.
+type BoardAspect = HVMainSignalAspect;
+// Signal ID, used in commands. Should be the same as the ID used by the control box.
+pub const SIGNAL_ID: &str = "F";
+// Layout segment of the signal, with levels separated by slashes, like "yard" or "station/east". Commands may be prefixed with a segment filter, like "yard/F:1", and are ignored if the filter doesn’t match.
+pub const LAYOUT_SEGMENT: &str = "";
+// Address of the signal in the frames of the binary protocol, if the BIN configuration option is enabled (see the serial protocol). Like the signal ID, it must be unique on an RS-485 bus.
+pub const BINARY_ADDRESS: u8 = 1;
+// A second, simple H/V main signal on the same board, like `Some(SecondSignal { id: "G", has_slow_aspect: false })`, whose commands are routed to it by its signal ID, and whose aspect is saved separately. It only shows stop, proceed and dark, and its red and green lamps are connected to pins A1 and A2, which can therefore not be used for Sh1 lamps, the dwarf signal, the level crossing or panel LEDs. None if there is no second signal.
+pub const SECOND_SIGNAL: Option<SecondSignal> = None;
+// Signal ID of the previous signal in an automatic block chain, which is told every aspect change of this signal with a "NXT" command, so that it can announce this signal. Empty if there is no such signal.
+pub const UPSTREAM_SIGNAL_ID: &str = "";
+// Whether the signal can show a slow aspect.
+pub const HAS_SLOW_ASPECT: bool = true;
+// Whether the signal has the capability to be deactivated with an indicator light.
+pub const HAS_DEACTIVATION_CAPABILITY: bool = false;
+// Whether the main signal can show the substitution signal (Zs1) with a blinking white lamp. The Zs1 lamp is connected to pin D11, which can therefore not be used for panel buttons.
+pub const HAS_SUBSTITUTION_SIGNAL: bool = false;
+// Whether the main signal can show the caution signal (Zs7) with three yellow lamps. The Zs7 lamps are connected to pins D12 (upper left), D13 (upper right) and A4 (bottom), which can therefore not be used for panel buttons.
+pub const HAS_CAUTION_SIGNAL: bool = false;
+// Whether the main signal can permit shunting (Sh1/Ra12) with two white lamps next to the red lamp. The Sh1 lamps are connected to pins A1 (lower left) and A2 (upper right), which can therefore not be used for panel LEDs.
+pub const HAS_SHUNTING_SIGNAL: bool = false;
+// Whether the main signal protects an entry to track-changing operation (Gleiswechselbetrieb), and can show the counter-track signal (Zs6) with a white diagonal stripe and the counter-track substitution signal (Zs8) with blinking white lamps. The Zs6 stripe is connected to pin D12 and the Zs8 lamps to pin D13, which can therefore not be used for Zs7 lamps, Zs3 indicators or panel buttons.
+pub const HAS_COUNTER_TRACK_SIGNALS: bool = false;
+// Whether a dwarf signal (Sperrsignal) next to the main signal shows Sh1 whenever any movement may pass the main signal, and Sh0 otherwise. The dwarf’s red lamps are connected to pin A1 and its white lamps to pin A2, which can therefore not be used for Sh1 lamps on the main signal or panel LEDs.
+pub const HAS_DWARF_SIGNAL: bool = false;
+// Whether a Zs3 speed indicator next to the main signal can show a speed together with the slow aspect, on a seven-segment display. Its segments a to g are connected to pins D9 to D13, A4 and A5, which can therefore not be used for notice lamps, Zs1 or Zs7 lamps or panel buttons.
+pub const HAS_SPEED_INDICATOR: bool = false;
+// Whether the Zs3 speed indicator is an LED matrix module with a MAX7219 instead of a seven-segment display. Its DIN, CLK and LOAD inputs are connected to pins D11, D13 and D12, which leaves pins D9, D10, A4 and A5 free.
+pub const SPEED_INDICATOR_USES_MAX7219: bool = false;
+// Whether a Zs2 direction indicator next to the main signal can show a route letter together with a proceed aspect, on an LED matrix module with a MAX7219. Its DIN, CLK and LOAD inputs are connected to pins D9, D10 and A4, which can therefore not be used for notice lamps, Zs7 lamps, Zs3 segments or panel buttons.
+pub const HAS_ROUTE_INDICATOR: bool = false;
+// Whether the announcement signal has reduced distance to the main signal.
+pub const HAS_REDUCED_SIGNAL_DISTANCE: bool = false;
+// Whether the announcement signal is mounted on the main signal’s mast and announces the next main signal. Its lamps are dark whenever the main signal shows stop.
+pub const HAS_ANNOUNCEMENT_AT_MAIN_MAST: bool = false;
+// End stops of the servos of semaphore signals (see the semaphore feature), as pulse widths in microseconds for stop and proceed, adjusted to the mechanics of the signal kit. The upper arm’s servo is connected to pin D9, the lower arm’s (for HAS_SLOW_ASPECT) to pin D10 and the distant disc’s (for HAS_ANNOUNCEMENT_AT_MAIN_MAST) to pin D11.
+pub const SEMAPHORE_UPPER_ARM_END_STOPS: EndStops = EndStops {
+    stop_us: 1000,
+    proceed_us: 2000,
+};
+pub const SEMAPHORE_LOWER_ARM_END_STOPS: EndStops = EndStops {
+    stop_us: 1000,
+    proceed_us: 2000,
+};
+pub const SEMAPHORE_DISC_END_STOPS: EndStops = EndStops {
+    stop_us: 1000,
+    proceed_us: 2000,
+};
+// Whether the notice lamps (Kennlicht) flash instead of being lit steadily, as some administrations require. Only allowed by some signalling systems, and needs the notice lamps of HAS_DEACTIVATION_CAPABILITY.
+pub const HAS_FLASHING_NOTICE_LAMPS: bool = false;
+// Whether the red lamp of the main signal is wired through two independent driver channels whose states are read back, for two-out-of-two voting. Aspects other than stop are only shown if both read-backs confirm the red lamp’s state before and after switching; otherwise the signal falls back to stop. The second channel is connected to pin A3, and the read-backs of the first and second channel, which are high while their channel drives the lamp, to pins A4 and A5. These pins can therefore not be used for the heater, Zs7 lamps, Zs3 segments, the Zs2 matrix or the panel.
+pub const HAS_RED_LAMP_VOTING: bool = false;
+// Whether the current aspect is kept a second time with a checksum, and checked against it in every loop iteration. If they differ, the RAM was corrupted, e.g. by a brownout, and the signal switches to stop and reboots. Costs a few bytes of RAM and flash memory.
+pub const HAS_STATE_MIRROR: bool = true;
+// Whether the signal head’s plug has an ID resistor, which tells the controller at boot which head is attached (see the head identification in the serial protocol). The ID pin is A6, which is only an analog input and therefore isn’t used otherwise, and is pulled up to 5 V through 100 kΩ on the board. Aspects that the attached head can’t show are rejected, even if the board is built for them.
+pub const HAS_HEAD_ID: bool = false;
+// Whether LEDs on a control desk panel mirror the main signal aspect.
+pub const HAS_PANEL: bool = false;
+// Whether the panel LEDs are connected via a 74HC595 shift register instead of directly.
+pub const PANEL_USES_SHIFT_REGISTER: bool = false;
+// Whether the control desk panel has buttons, which are wired as a key matrix with two rows and three columns.
+pub const HAS_PANEL_BUTTONS: bool = false;
+// The aspect commanded by each panel button, numbered row by row. None if the button is unused.
+pub const PANEL_BUTTON_ASPECTS: [Option<AspectCommand>; 6] = [
+    Some(AspectCommand::Zero),
+    Some(AspectCommand::One),
+    Some(AspectCommand::Two),
+    Some(AspectCommand::Deactivated),
+    Some(AspectCommand::Dark),
+    None,
+];
+// Whether a heater keeps the signal heads free of condensation and frost. Needs the internal temperature sensor, which the Mega doesn’t have. The heater relay is connected to pin A3, which can therefore not be used for directly connected panel LEDs.
+pub const HAS_HEATER: bool = false;
+// The heater switches on at or below the first temperature and off at or above the second temperature (°C).
+pub const HEATER_ON_AT_CELSIUS: i16 = 3;
+pub const HEATER_OFF_AT_CELSIUS: i16 = 6;
+// Calibration offset of the internal temperature sensor (°C), which deviates by several degrees between chips.
+pub const TEMPERATURE_OFFSET_CELSIUS: i16 = 0;
+// Whether a level crossing (Bahnübergang) is controlled together with the signal (see BX in the serial protocol), with two road lights that flash alternately while it is closed. The lights are connected to pins A1 (left) and A2 (right), which can therefore not be used for Sh1 lamps, the dwarf signal or panel LEDs.
+pub const HAS_LEVEL_CROSSING: bool = false;
+// Whether the level crossing has barriers. Their servo is connected to pin D10, which can therefore not be used for notice lamps, Zs3 segments, the Zs2 matrix or the lower arm of a semaphore.
+pub const HAS_LEVEL_CROSSING_BARRIERS: bool = false;
+// How long the road lights flash before the barriers are lowered, and how long the barriers take to move, in milliseconds.
+pub const LEVEL_CROSSING_WARNING_MS: u32 = 5000;
+pub const LEVEL_CROSSING_BARRIER_MOVE_MS: u32 = 6000;
+// End stops of the barrier servo, as pulse widths in microseconds for lowered (stop) and raised (proceed) barriers.
+pub const LEVEL_CROSSING_BARRIER_END_STOPS: EndStops = EndStops {
+    stop_us: 1000,
+    proceed_us: 2000,
+};
+// Whether sound modules with trigger inputs play sounds on aspect changes, like the clunk of a relay interlocking or a departure whistle (Zp9). Their triggers are connected to pins D12 and D13, which can therefore not be used for Zs7, Zs6 or Zs8 lamps, Zs3 indicators or panel buttons.
+pub const HAS_SOUND_TRIGGERS: bool = false;
+// The aspect changes that pulse the trigger on pin D12 and D13, respectively. A None aspect matches any aspect.
+pub const SOUND_TRIGGER_RULES: [AuxRule; 2] = [
+    AuxRule::Trigger {
+        from: None,
+        to: None,
+        pulse_ms: 100,
+    },
+    AuxRule::Trigger {
+        from: Some(AspectCommand::Zero),
+        to: Some(AspectCommand::One),
+        pulse_ms: 100,
+    },
+];
+// Whether a relay dims the signal lamps during the night of the layout’s fast clock (see FCLK in the serial protocol), for example by switching a resistor into their supply. The dimming relay is connected to pin A5, which can therefore not be used for Zs3 segments or panel buttons.
+pub const HAS_NIGHT_DIMMING: bool = false;
+// The night lasts from the first until the second fast-clock time, in minutes since midnight.
+pub const NIGHT_FROM_MINUTES: u16 = 22 * 60;
+pub const NIGHT_UNTIL_MINUTES: u16 = 6 * 60;
+// Whether the serial port is connected to a half-duplex RS-485 transceiver, so that many controllers can share one twisted pair with the control box. The transceiver’s driver enable and receiver enable inputs (DE and /RE) are connected to pin A0, which is high while the controller sends, and which can therefore not be used for the panel. The reply delay (see RDLY in the serial protocol) gives the control box time to turn its own transceiver around before a reply.
+pub const HAS_RS485_TRANSCEIVER: bool = false;
+// Whether the layout’s DCC command station can switch the signal with accessory commands, like any accessory decoder, or Märklin Motorola keyboards if the MM configuration option is enabled (see CFG in the serial protocol). The track signal is connected through an optocoupler to pin A0, which can therefore not be used for the RS-485 transceiver or the panel. The Nano’s input capture pin is taken by the signal lamps, so the edges are timed with the millisecond clock in a pin change interrupt instead, which the Mega doesn’t have on pin A0. Like panel buttons, accessory commands are never authenticated.
+pub const HAS_DCC_DECODER: bool = false;
+// The signal’s first DCC accessory address (1 to 2044), which also receives the extended accessory commands. Their aspect numbers are mapped to aspects by the DCC configuration options (see CFG in the serial protocol).
+pub const DCC_ADDRESS: u16 = 1;
+// The signal’s first Märklin Motorola accessory address (1 to 320), as numbered on the keyboards, which is used instead of DCC_ADDRESS if the MM configuration option is enabled.
+pub const MM_ADDRESS: u16 = 1;
+// The aspects commanded by the red and green output of each basic accessory address, starting at DCC_ADDRESS or MM_ADDRESS. None if the output is unused.
+pub const DCC_BASIC_ASPECTS: [[Option<AspectCommand>; 2]; 2] = [
+    [Some(AspectCommand::Zero), Some(AspectCommand::One)],
+    [None, Some(AspectCommand::Two)],
+];
+// Whether the signal is connected to the LocoNet bus of a Digitrax or Uhlenbrock system, where it receives switch requests for the addresses of DCC_ADDRESS and DCC_BASIC_ASPECTS, and reports the outputs that command the shown aspect as the positions of these turnouts (see the serial protocol). The bus is read through a comparator on pin A0, and pulled low through an open-collector transistor driven by pin A3, which can therefore not be used for the RS-485 transceiver, the DCC input, the heater or the panel. Like the DCC input, the bus is watched with the pin change interrupt of pin A0, which the Mega doesn’t have.
+pub const HAS_LOCONET: bool = false;
+// Whether the signal reads its aspect from a channel of the Selectrix bus of a Trix, Rautenhaus or MÜT central unit, which is set with the SX configuration option (see CFG in the serial protocol). The bus’s clock T0 is connected to pin A0 and its data line T1 to pin A3, which can therefore not be used for the RS-485 transceiver, the DCC input, LocoNet, the heater or the panel. Like the DCC input, the clock is watched with the pin change interrupt of pin A0, which the Mega doesn’t have.
+pub const HAS_SELECTRIX: bool = false;
+// The aspects commanded by the values of the bits of the channel that start at SELECTRIX_FIRST_BIT (1 to 8, numbered like on the central units): two aspects for one bit, four for two bits, and so on. None if the value is ignored.
+pub const SELECTRIX_FIRST_BIT: u8 = 1;
+pub const SELECTRIX_ASPECTS: [Option<AspectCommand>; 2] =
+    [Some(AspectCommand::Zero), Some(AspectCommand::One)];
+// The aspects commanded by the outputs of the C/MRI node, starting at output 0, if the CMRI configuration option is enabled (see CFG in the serial protocol). Like with an output signal mast of JMRI, switching an output on switches to its aspect, and the input with the same number is on while the signal shows the aspect. None if the output is unused.
+pub const CMRI_OUTPUT_ASPECTS: [Option<AspectCommand>; 3] = [
+    Some(AspectCommand::Zero),
+    Some(AspectCommand::One),
+    Some(AspectCommand::Two),
+];
+// Whether the signal is connected to the XpressNet bus of a Lenz or Roco command station, where it receives the accessory operation requests of handsets and PC interfaces for the addresses of DCC_ADDRESS and DCC_BASIC_ASPECTS (see the serial protocol). The bus is connected through a second RS-485 transceiver to the second serial port of the Mega on pins D18 and D19 (TX1 and RX1), and the transceiver’s DE and /RE inputs to pin D22. The Nano has no second serial port.
+pub const HAS_XPRESSNET: bool = false;
+// The signal’s own XpressNet address (1 to 31), which must differ from the addresses of the handsets and PC interfaces on the bus.
+pub const XPRESSNET_ADDRESS: u8 = 20;
+// Whether the signal is controlled through an MQTT broker, with an ESP8266 module with the ESP-AT firmware, e.g. an ESP-01, on the third serial port of the Mega on pins D16 and D17 (TX2 and RX2), see MQTT in the serial protocol. The module runs at 3.3 V, so its RX input needs a level shifter. The Nano has no second serial port.
+pub const HAS_MQTT: bool = false;
+// The WLAN and the broker that the module connects to.
+pub const MQTT_SETTINGS: MqttSettings = MqttSettings {
+    wlan_ssid: "Layout",
+    wlan_password: "",
+    broker_host: "192.168.1.2",
+    broker_port: 1883,
+};
+// Whether the signal is driven by a lighting console on a DMX512 bus, through a third RS-485 transceiver on the fourth serial port of the Mega on pin D15 (RX3), whose DE and /RE inputs are tied low, so that it only receives. The signal reads its aspect from the channel of the DMX configuration option and its Zs3 speed from the next channel, see DMX512 in the serial protocol. The Nano has no second serial port.
+pub const HAS_DMX: bool = false;
+// The aspects commanded by the value of the aspect channel, whose values 0 to 255 are split into equal ranges, one for each aspect. None if the range is ignored.
+pub const DMX_ASPECTS: [Option<AspectCommand>; 3] = [
+    Some(AspectCommand::Zero),
+    Some(AspectCommand::One),
+    Some(AspectCommand::Two),
+];
+// Whether the signal is a node on an OpenLCB (LCC) CAN bus, through an MCP2515 module whose SCK, SI, SO and CS inputs are connected to pins D13, D11, D12 and D10, or D52, D51, D50 and D53 on the Mega. On the Nano, these pins can therefore not be used for notice lamps, Zs1, Zs7, Zs3, Zs2, panel buttons or sound triggers. The module’s interrupt output isn’t used.
+pub const HAS_OPENLCB: bool = false;
+// Frequency of the crystal on the MCP2515 module, usually 8 or 16 MHz.
+pub const MCP2515_OSCILLATOR_HZ: u32 = 8_000_000;
+// Unique node ID of the signal on the OpenLCB bus, which must come from a range that is assigned to you or to your club.
+pub const OPENLCB_NODE_ID: [u8; 6] = [0x05, 0x01, 0x01, 0x01, 0x22, 0x00];
+// The aspects of the events that the signal consumes and produces, starting at aspect number 0 (see LCC in the serial protocol). None if the number is unused.
+pub const OPENLCB_EVENT_ASPECTS: [Option<AspectCommand>; 3] = [
+    Some(AspectCommand::Zero),
+    Some(AspectCommand::One),
+    Some(AspectCommand::Two),
+];
+// Whether the signal accepts the lines of the serial protocol over Ethernet, through a W5500 module whose SCK, MOSI, MISO and CS inputs are connected to pins D13, D11, D12 and D10, or D52, D51, D50 and D53 on the Mega, like the MCP2515 of OpenLCB, which can therefore not be used at the same time. On the Nano, these pins can therefore not be used for notice lamps, Zs1, Zs7, Zs3, Zs2, panel buttons or sound triggers either.
+pub const HAS_ETHERNET: bool = false;
+// Whether a debug console receives the lines of the diagnostics channel and panic messages instead of the serial port, so that they don’t get in the way of the control box (see the serial protocol). The console sends at 38 400 baud to the RX input of a USB serial adapter. On the Mega, it uses the first serial port that is free, the fourth on pin D14 (TX3) without DMX512, the third on pin D16 (TX2) without MQTT, or the second on pin D18 (TX1) without XpressNet and gateway. Otherwise, and on the Nano, it sends in software on pin D9, which can therefore not be used for notice lamps, Zs3 segments, Zs2 or the upper arm of a semaphore signal. Interrupts are then only disabled for a bit of 26 µs at a time, but the interrupts that run between the bits stretch them, so the console may garble bytes while many interrupts arrive, e.g. from the DCC decoder.
+pub const HAS_DEBUG_CONSOLE: bool = false;
+// Whether the signal is a gateway to the next controller of a daisy chain, which is connected to the second serial port of the Mega on pins D18 and D19 (TX1 and RX1), TX to RX and RX to TX, so that the controllers of a chain don’t need RS-485 transceivers. Lines that aren’t for this signal, and broadcasts, are passed on to the next controller, and its lines are sent back on the serial port (see the serial protocol). The port can therefore not be used for XpressNet. The Nano has no second serial port.
+pub const HAS_GATEWAY: bool = false;
+// The addresses of the signal in the network, and the TCP and UDP port that it listens on (see the serial protocol). The MAC address must be unique in the network, which it is among locally administered addresses, whose first byte is 0x02, if its last byte is the signal’s.
+pub const NETWORK_SETTINGS: NetworkSettings = NetworkSettings {
+    mac_address: [0x02, 0x00, 0x00, 0x00, 0x00, 0x46],
+    ip_address: [192, 168, 1, 70],
+    subnet_mask: [255, 255, 255, 0],
+    gateway: [192, 168, 1, 1],
+    port: 2323,
+};
+// Product ID (two bytes) and serial number (two bytes) of the BiDiB node, if the BIDIB configuration option is enabled (see CFG in the serial protocol), which must be unique among the nodes of the host.
+pub const BIDIB_PRODUCT_ID_AND_SERIAL: [u8; 4] = [0x00, 0x01, 0x00, 0x01];
+// The aspects of the BiDiB node’s accessory, starting at aspect number 0. None if the number is unused.
+pub const BIDIB_ASPECTS: [Option<AspectCommand>; 3] = [
+    Some(AspectCommand::Zero),
+    Some(AspectCommand::One),
+    Some(AspectCommand::Two),
+];
+// Whether commands that change the controller state must be authenticated with the shared secret below. Queries are always allowed.
+pub const REQUIRES_AUTHENTICATION: bool = false;
+// Shared secret for authenticating commands. Must be changed for every installation, and kept secret; the build fails if authentication is required with this placeholder.
+pub const AUTHENTICATION_KEY: [u8; 16] = *b"change this key!";
+
+// Build-time checks of the board configuration, which turn conflicting options into readable build errors.
+const _: () = {
+    // Pins used by each feature: whether the feature is enabled, the build error if one of its pins is already used by an enabled feature above it, and its pins (D0 to D13 are 0 to 13, A0 to A5 are 14 to 19).
+    const PIN_MAP: [(bool, &str, &[u8]); 32] = [
+        (true, "the serial port uses pins D0 and D1", &[0, 1]),
+        (
+            HAS_RS485_TRANSCEIVER,
+            "the RS-485 driver enable uses pin A0",
+            &[14],
+        ),
+        (
+            HAS_DCC_DECODER,
+            "the DCC input uses pin A0, which is already in use",
+            &[14],
+        ),
+        (
+            HAS_LOCONET,
+            "the LocoNet input and output use pins A0 and A3, which are already in use",
+            &[14, 17],
+        ),
+        (
+            HAS_SELECTRIX,
+            "the Selectrix clock and data use pins A0 and A3, which are already in use",
+            &[14, 17],
+        ),
+        (
+            !cfg!(feature = "semaphore"),
+            "the signal lamps use pins D2 to D5, D7 and D8",
+            &[2, 3, 4, 5, 7, 8],
+        ),
+        (
+            HAS_SLOW_ASPECT && !cfg!(feature = "semaphore"),
+            "the main yellow lamp uses pin D6",
+            &[6],
+        ),
+        (
+            cfg!(feature = "semaphore"),
+            "the upper arm servo uses pin D9",
+            &[9],
+        ),
+        (
+            HAS_SLOW_ASPECT && cfg!(feature = "semaphore"),
+            "the lower arm servo uses pin D10",
+            &[10],
+        ),
+        (
+            HAS_ANNOUNCEMENT_AT_MAIN_MAST && cfg!(feature = "semaphore"),
+            "the distant disc servo uses pin D11",
+            &[11],
+        ),
+        (
+            HAS_RED_LAMP_VOTING,
+            "the second red lamp channel and its read-backs use pins A3 to A5, which are already in use",
+            &[17, 18, 19],
+        ),
+        (
+            HAS_DEACTIVATION_CAPABILITY,
+            "the notice lamps use pins D9 and D10, which are already in use",
+            &[9, 10],
+        ),
+        (
+            HAS_SUBSTITUTION_SIGNAL,
+            "the Zs1 lamp uses pin D11, which is already in use",
+            &[11],
+        ),
+        (
+            HAS_CAUTION_SIGNAL,
+            "the Zs7 lamps use pins D12, D13 and A4, which are already in use",
+            &[12, 13, 18],
+        ),
+        (
+            HAS_SHUNTING_SIGNAL,
+            "the Sh1 lamps use pins A1 and A2, which are already in use",
+            &[15, 16],
+        ),
+        (
+            HAS_COUNTER_TRACK_SIGNALS,
+            "the Zs6 and Zs8 lamps use pins D12 and D13, which are already in use",
+            &[12, 13],
+        ),
+        (
+            HAS_DWARF_SIGNAL,
+            "the dwarf signal uses pins A1 and A2, which are already in use",
+            &[15, 16],
+        ),
+        (
+            HAS_SPEED_INDICATOR && !SPEED_INDICATOR_USES_MAX7219,
+            "the Zs3 segments use pins D9 to D13, A4 and A5, which are already in use",
+            &[9, 10, 11, 12, 13, 18, 19],
+        ),
+        (
+            HAS_SPEED_INDICATOR && SPEED_INDICATOR_USES_MAX7219,
+            "the Zs3 matrix uses pins D11 to D13, which are already in use",
+            &[11, 12, 13],
+        ),
+        (
+            HAS_ROUTE_INDICATOR,
+            "the Zs2 matrix uses pins D9, D10 and A4, which are already in use",
+            &[9, 10, 18],
+        ),
+        (
+            HAS_PANEL && PANEL_USES_SHIFT_REGISTER,
+            "the panel shift register uses pins A0 to A2, which are already in use",
+            &[14, 15, 16],
+        ),
+        (
+            HAS_PANEL && !PANEL_USES_SHIFT_REGISTER,
+            "the panel LEDs use pins A0 to A3, which are already in use",
+            &[14, 15, 16, 17],
+        ),
+        (
+            HAS_PANEL_BUTTONS,
+            "the panel buttons use pins D11 to D13, A4 and A5, which are already in use",
+            &[11, 12, 13, 18, 19],
+        ),
+        (
+            HAS_HEATER,
+            "the heater relay uses pin A3, which is already in use",
+            &[17],
+        ),
+        (
+            HAS_NIGHT_DIMMING,
+            "the dimming relay uses pin A5, which is already in use",
+            &[19],
+        ),
+        (
+            HAS_SOUND_TRIGGERS,
+            "the sound triggers use pins D12 and D13, which are already in use",
+            &[12, 13],
+        ),
+        (
+            HAS_LEVEL_CROSSING,
+            "the level crossing lights use pins A1 and A2, which are already in use",
+            &[15, 16],
+        ),
+        (
+            HAS_LEVEL_CROSSING_BARRIERS,
+            "the level crossing barrier servo uses pin D10, which is already in use",
+            &[10],
+        ),
+        (
+            SECOND_SIGNAL.is_some(),
+            "the second signal’s red and green lamps use pins A1 and A2, which are already in use",
+            &[15, 16],
+        ),
+        (
+            matches!(
+                SECOND_SIGNAL,
+                Some(SecondSignal {
+                    has_slow_aspect: true,
+                    ..
+                })
+            ),
+            "the second signal’s yellow lamp uses pin A3, which is already in use",
+            &[17],
+        ),
+        (
+            HAS_OPENLCB && !cfg!(feature = "mega"),
+            "the MCP2515 uses pins D10 to D13, which are already in use",
+            &[10, 11, 12, 13],
+        ),
+        (
+            HAS_ETHERNET && !cfg!(feature = "mega"),
+            "the W5500 uses pins D10 to D13, which are already in use",
+            &[10, 11, 12, 13],
+        ),
+    ];
+
+    /// Fails the build if two enabled features use the same pin.
+    const fn check_pin_map(pin_map: &[(bool, &str, &[u8])]) {
+        let mut used_pins = 0u32;
+        let mut feature = 0;
+        while feature < pin_map.len() {
+            let (is_enabled, conflict_error, pins) = pin_map[feature];
+            let mut pin = 0;
+            while is_enabled && pin < pins.len() {
+                if used_pins & 1 << pins[pin] != 0 {
+                    panic!("{}", conflict_error);
+                }
+                used_pins |= 1 << pins[pin];
+                pin += 1;
+            }
+            feature += 1;
+        }
+    }
+
+    /// Returns whether the signal has all lamps that the aspect command needs, as far as the board configuration determines them.
+    const fn has_lamps_for(command: AspectCommand) -> bool {
+        match command {
+            AspectCommand::Zero | AspectCommand::One | AspectCommand::Dark => true,
+            AspectCommand::Two => HAS_SLOW_ASPECT,
+            AspectCommand::Shunting => HAS_SHUNTING_SIGNAL,
+            AspectCommand::Substitution => HAS_SUBSTITUTION_SIGNAL,
+            AspectCommand::Caution => HAS_CAUTION_SIGNAL,
+            AspectCommand::CounterTrack | AspectCommand::CounterTrackSubstitution => {
+                HAS_COUNTER_TRACK_SIGNALS
+            }
+            AspectCommand::CounterTrackSlow => HAS_COUNTER_TRACK_SIGNALS && HAS_SLOW_ASPECT,
+            AspectCommand::Deactivated => HAS_DEACTIVATION_CAPABILITY,
+            // H/V main signals can't show these aspects at all.
+            AspectCommand::Three
+            | AspectCommand::ShuntingForbidden
+            | AspectCommand::ShuntingAllowed
+            | AspectCommand::Sv0
+            | AspectCommand::Sv1
+            | AspectCommand::Sv2
+            | AspectCommand::Sv3
+            | AspectCommand::Sv4
+            | AspectCommand::Sv5
+            | AspectCommand::Sv6
+            | AspectCommand::CrossingStop
+            | AspectCommand::CrossingProceed => false,
+        }
+    }
+
+    /// Fails the build with the given error if one of the aspects, e.g. of the panel buttons, can't be shown by the signal.
+    const fn check_commanded_aspects(aspects: &[Option<AspectCommand>], error: &str) {
+        let mut index = 0;
+        while index < aspects.len() {
+            if let Some(command) = aspects[index] {
+                if !has_lamps_for(command) {
+                    panic!("{}", error);
+                }
+            }
+            index += 1;
+        }
+    }
+
+    check_pin_map(&PIN_MAP);
+    if HAS_SPEED_INDICATOR && !HAS_SLOW_ASPECT {
+        panic!("the Zs3 speed indicator is only shown together with the slow aspect of HAS_SLOW_ASPECT");
+    }
+    if HAS_FLASHING_NOTICE_LAMPS && !BoardAspect::ALLOWS_FLASHING_NOTICE_LAMPS {
+        panic!("the signalling system of BoardAspect doesn’t allow flashing notice lamps");
+    }
+    if HAS_FLASHING_NOTICE_LAMPS && !HAS_DEACTIVATION_CAPABILITY {
+        panic!("flashing notice lamps need the notice lamps of HAS_DEACTIVATION_CAPABILITY");
+    }
+    if HAS_PANEL_BUTTONS {
+        check_commanded_aspects(
+            &PANEL_BUTTON_ASPECTS,
+            "a button in PANEL_BUTTON_ASPECTS commands an aspect whose lamps are not enabled",
+        );
+    }
+    if HAS_DCC_DECODER || HAS_LOCONET || HAS_XPRESSNET {
+        let mut address = 0;
+        while address < DCC_BASIC_ASPECTS.len() {
+            check_commanded_aspects(
+                &DCC_BASIC_ASPECTS[address],
+                "an output in DCC_BASIC_ASPECTS commands an aspect whose lamps are not enabled",
+            );
+            address += 1;
+        }
+        if DCC_ADDRESS == 0 || DCC_ADDRESS as usize + DCC_BASIC_ASPECTS.len() > 2045 {
+            panic!("the DCC accessory addresses of DCC_ADDRESS and DCC_BASIC_ASPECTS must be between 1 and 2044");
+        }
+        if MM_ADDRESS == 0 || MM_ADDRESS as usize + DCC_BASIC_ASPECTS.len() > 321 {
+            panic!("the MM accessory addresses of MM_ADDRESS and DCC_BASIC_ASPECTS must be between 1 and 320");
+        }
+    }
+    if HAS_SELECTRIX {
+        check_commanded_aspects(
+            &SELECTRIX_ASPECTS,
+            "a value in SELECTRIX_ASPECTS commands an aspect whose lamps are not enabled",
+        );
+        if SELECTRIX_ASPECTS.len() < 2
+            || !SELECTRIX_ASPECTS.len().is_power_of_two()
+            || SELECTRIX_FIRST_BIT == 0
+            || SELECTRIX_FIRST_BIT as u32 - 1 + SELECTRIX_ASPECTS.len().trailing_zeros() > 8
+        {
+            panic!("SELECTRIX_ASPECTS must have 2, 4, 8 … aspects, one for each value of the bits of the channel that start at SELECTRIX_FIRST_BIT");
+        }
+    }
+    if HAS_DMX {
+        check_commanded_aspects(
+            &DMX_ASPECTS,
+            "a range in DMX_ASPECTS commands an aspect whose lamps are not enabled",
+        );
+        if DMX_ASPECTS.is_empty() || DMX_ASPECTS.len() > 256 {
+            panic!("DMX_ASPECTS must have between 1 and 256 aspects, one for each range of the aspect channel");
+        }
+    }
+    check_commanded_aspects(
+        &CMRI_OUTPUT_ASPECTS,
+        "an output in CMRI_OUTPUT_ASPECTS commands an aspect whose lamps are not enabled",
+    );
+    check_commanded_aspects(
+        &OPENLCB_EVENT_ASPECTS,
+        "an event in OPENLCB_EVENT_ASPECTS commands an aspect whose lamps are not enabled",
+    );
+    if OPENLCB_EVENT_ASPECTS.len() > 256 {
+        panic!("OPENLCB_EVENT_ASPECTS can have at most 256 aspects, one for each aspect number");
+    }
+    if HAS_ETHERNET && HAS_OPENLCB {
+        panic!("the W5500 and the MCP2515 can’t share the SPI bus, since each of their drivers needs the whole bus");
+    }
+    if HAS_OPENLCB && !mcp2515::supports_oscillator(MCP2515_OSCILLATOR_HZ) {
+        panic!("the 125 kbit/s of OpenLCB can't be derived from MCP2515_OSCILLATOR_HZ");
+    }
+    check_commanded_aspects(
+        &BIDIB_ASPECTS,
+        "an aspect number in BIDIB_ASPECTS commands an aspect whose lamps are not enabled",
+    );
+    if BIDIB_ASPECTS.len() > 255 {
+        panic!("BIDIB_ASPECTS can have at most 255 aspects, since 255 marks an unknown aspect");
+    }
+    if CMRI_OUTPUT_ASPECTS.len() > 8 * cmri::INPUT_BYTES {
+        panic!("CMRI_OUTPUT_ASPECTS can have at most 24 aspects, one for each input of the node");
+    }
+    if HAS_XPRESSNET && DCC_ADDRESS as usize + DCC_BASIC_ASPECTS.len() > 1025 {
+        panic!("XpressNet only has the accessory addresses 1 to 1024");
+    }
+    if HAS_XPRESSNET && (XPRESSNET_ADDRESS == 0 || XPRESSNET_ADDRESS > 31) {
+        panic!("XPRESSNET_ADDRESS must be between 1 and 31");
+    }
+    if cfg!(feature = "semaphore")
+        && (HAS_DEACTIVATION_CAPABILITY
+            || HAS_SUBSTITUTION_SIGNAL
+            || HAS_CAUTION_SIGNAL
+            || HAS_SHUNTING_SIGNAL
+            || HAS_COUNTER_TRACK_SIGNALS
+            || HAS_SPEED_INDICATOR
+            || HAS_REDUCED_SIGNAL_DISTANCE
+            || HAS_FLASHING_NOTICE_LAMPS
+            || HAS_RED_LAMP_VOTING
+            || HAS_PANEL)
+    {
+        panic!("semaphore signals only have arms and a distant disc, so they can't have lamps, Zs3 indicators or panel LEDs");
+    }
+    if HAS_SOUND_TRIGGERS {
+        let mut rule = 0;
+        while rule < SOUND_TRIGGER_RULES.len() {
+            if !matches!(SOUND_TRIGGER_RULES[rule], AuxRule::Trigger { .. }) {
+                panic!("SOUND_TRIGGER_RULES may only contain trigger rules");
+            }
+            rule += 1;
+        }
+    }
+    if cfg!(feature = "mega") && (cfg!(feature = "semaphore") || HAS_LEVEL_CROSSING_BARRIERS) {
+        panic!("the servo outputs use the timer pins of the Nano, which are different on the Mega");
+    }
+    if cfg!(feature = "mega") && HAS_DCC_DECODER {
+        panic!(
+            "the DCC input needs the pin change interrupt of pin A0, which the Mega doesn’t have"
+        );
+    }
+    if cfg!(feature = "mega") && HAS_LOCONET {
+        panic!(
+            "the LocoNet input needs the pin change interrupt of pin A0, which the Mega doesn’t have"
+        );
+    }
+    if cfg!(feature = "mega") && HAS_SELECTRIX {
+        panic!("the Selectrix clock needs the pin change interrupt of pin A0, which the Mega doesn’t have");
+    }
+    if !cfg!(feature = "mega") && HAS_XPRESSNET {
+        panic!("XpressNet needs the second serial port of the Mega, which the Nano doesn’t have");
+    }
+    if !cfg!(feature = "mega") && HAS_MQTT {
+        panic!("MQTT needs the third serial port of the Mega, which the Nano doesn’t have");
+    }
+    if !cfg!(feature = "mega") && HAS_DMX {
+        panic!("DMX512 needs the fourth serial port of the Mega, which the Nano doesn’t have");
+    }
+    if !cfg!(feature = "mega") && HAS_GATEWAY {
+        panic!("the gateway needs the second serial port of the Mega, which the Nano doesn’t have");
+    }
+    if HAS_GATEWAY && HAS_XPRESSNET {
+        panic!("the gateway and XpressNet both need the second serial port of the Mega");
+    }
+    if cfg!(feature = "mega") && HAS_HEAD_ID {
+        panic!("the head ID needs the analog-only pin A6 of the Nano");
+    }
+    if cfg!(feature = "semaphore") && HAS_HEAD_ID {
+        panic!("the head ID identifies light signal heads, not semaphores");
+    }
+    if !HAS_TEMPERATURE_SENSOR && HAS_HEATER {
+        panic!("the heater needs the internal temperature sensor, which the Mega doesn’t have");
+    }
+    if HAS_LEVEL_CROSSING_BARRIERS && !HAS_LEVEL_CROSSING {
+        panic!("level crossing barriers need the level crossing of HAS_LEVEL_CROSSING");
+    }
+    if NIGHT_FROM_MINUTES >= MINUTES_PER_DAY || NIGHT_UNTIL_MINUTES >= MINUTES_PER_DAY {
+        panic!("NIGHT_FROM_MINUTES and NIGHT_UNTIL_MINUTES must be times of day, below 24 * 60");
+    }
+    if REQUIRES_AUTHENTICATION {
+        let placeholder = b"change this key!";
+        let mut byte = 0;
+        let mut is_placeholder = true;
+        while byte < AUTHENTICATION_KEY.len() {
+            is_placeholder &= AUTHENTICATION_KEY[byte] == placeholder[byte];
+            byte += 1;
+        }
+        if is_placeholder {
+            panic!("REQUIRES_AUTHENTICATION needs a secret AUTHENTICATION_KEY instead of the placeholder");
+        }
+    }
+};
+// EEPROM addresses of the rings that hold the saved aspects of the signal and the second signal, right below the baud rate fallback (see SignalGroup::save_aspect).
+const SAVED_ASPECT_EEPROM_OFFSETS: [u16; 2] = [
+    BAUD_RATE_FALLBACK_EEPROM_OFFSET - 2 * wear_leveling::RING_LENGTH,
+    BAUD_RATE_FALLBACK_EEPROM_OFFSET - wear_leveling::RING_LENGTH,
+];
+// How long an aspect that clears the signal must be shown before it is saved, so that a quick succession of aspects only writes the EEPROM once.
+const SAVED_ASPECT_SETTLE_MS: u32 = 5000;
+// EEPROM address of the emergency stop latch, the last byte of the Nano's EEPROM, so that the configuration can keep growing.
+const EMERGENCY_STOP_EEPROM_OFFSET: u16 = 1023;
+// EEPROM address of the baud rate that the controller falls back to while a new baud rate is on trial, or 0xff if the baud rate is confirmed (see the BAUD configuration option).
+const BAUD_RATE_FALLBACK_EEPROM_OFFSET: u16 = 1022;
+// Whether the microcontroller has an internal temperature sensor, which the ATmega2560 of the Mega lacks.
+const HAS_TEMPERATURE_SENSOR: bool = !cfg!(feature = "mega");
+// The head that the board is built for, which an attached head is compared with (see HAS_HEAD_ID).
+const BOARD_HEAD: HeadType = if HAS_SPEED_INDICATOR {
+    HeadType::ThreeLampWithSpeedIndicator
+} else if HAS_SLOW_ASPECT {
+    HeadType::ThreeLamp
+} else {
+    HeadType::TwoLamp
+};
+// How often the temperature is measured.
+const TEMPERATURE_SAMPLE_INTERVAL_MS: u32 = 1000;
+// Cargo features that the VER command reports, since they change the protocol and the flash usage.
+const FEATURES: [(&str, bool); 4] = [
+    ("mega", cfg!(feature = "mega")),
+    ("lang-de", cfg!(feature = "lang-de")),
+    ("terse-errors", cfg!(feature = "terse-errors")),
+    ("semaphore", cfg!(feature = "semaphore")),
+];
+
+// A lamp output, which ramps to its new state if configured with the SLEW option. Only the red lamp has a second channel, if it is voted.
+type LampPin = SlewLimitedPin<VotedPin<Pin<Output>, Pin<Input<Floating>>>, Delay>;
+
+fn lamp_pin(pin: Pin<Output>) -> LampPin {
+    SlewLimitedPin::new(VotedPin::new(pin), Delay::new())
+}
+
+/// Returns the checksum of everything that a signal shows, which is sent with every acknowledgement.
+fn state_checksum(aspect: BoardAspect, speed: Option<u8>, route: Option<u8>) -> StateChecksum {
+    PresentationState {
+        aspect,
+        speed,
+        route,
+    }
+    .checksum()
+}
+
+/// Returns whether both channels of the red lamp confirm its state, which is always the case if it is not voted (see HAS_RED_LAMP_VOTING).
+fn red_lamp_agrees(signal: &mut impl Signal<Pin = LampPin>) -> bool {
+    signal
+        .lamp(LampRole::MainRed)
+        .map_or(true, |lamp| lamp.inner_mut().agrees().unwrap_infallible())
+}
+
+/// Switches to stop after the channels of the red lamp disagreed. Returns the aspect that the signal fell back to and the error to report, depending on whether the red lamp confirms stop or the signal had to be switched dark.
+fn fall_back_to_stop(
+    signal: &mut impl Signal<Aspect = BoardAspect, Pin = LampPin, Error = Infallible>,
+) -> (BoardAspect, CommandError) {
+    signal
+        .switch_to_aspect(BoardAspect::STOP, &mut Delay::new())
+        .unwrap_infallible();
+    if red_lamp_agrees(signal) {
+        (BoardAspect::STOP, CommandError::FellBackToStop)
+    } else {
+        let dark = BoardAspect::try_from(AspectCommand::Dark).unwrap();
+        signal
+            .switch_to_aspect(dark, &mut Delay::new())
+            .unwrap_infallible();
+        (dark, CommandError::FellBackToDark)
+    }
+}
+
+/// Sends a message on the LocoNet bus if it was idle for long enough, and returns whether it was sent (see HAS_LOCONET). A message that wasn’t sent, or collided with another device’s message, should be sent again later.
+fn send_on_loconet(
+    message: &[u8],
+    output: &mut Pin<Output>,
+    input: &mut Pin<Input<Floating>>,
+) -> bool {
+    interrupt::free(|cs| {
+        let (receiver, last_edge, is_high) = &mut *LOCONET_RECEIVER.borrow(cs).borrow_mut();
+        if !*is_high
+            || !receiver.is_idle()
+            || clock::micros().wrapping_sub(*last_edge) < loconet::IDLE_BEFORE_SENDING_US
+        {
+            return false;
+        }
+        let is_sent = loconet::send(message, output, input, &mut Delay::new()).unwrap_infallible();
+        // the controller’s own edges are not received, and the bus was busy until now.
+        let exint = unsafe { &*arduino_hal::pac::EXINT::ptr() };
+        exint.pcifr.write(|w| w.pcif().bits(1 << 1));
+        *last_edge = clock::micros();
+        is_sent
+    })
+}
+
+/// Records an accepted state-changing command in the journal, and saves its sequence number, which is returned.
+fn record_in_journal(
+    journal: &mut Journal<BoardAspect>,
+    platform: &mut impl Platform,
+    source: CommandSource,
+    aspect: BoardAspect,
+) -> u16 {
+    let sequence = journal.record(source, aspect);
+    platform.write_persistent(JOURNAL_EEPROM_OFFSET, &sequence.to_le_bytes());
+    sequence
+}
+
+/// Returns the code under which an aspect is saved, the single-byte code of its aspect command.
+fn saved_aspect_code(aspect: BoardAspect) -> Option<u8> {
+    AspectCommand::from_command_id(aspect.command_id().as_bytes()).map(|command| command as u8)
+}
+
+/// A second signal on the board (see SECOND_SIGNAL).
+#[derive(Clone, Copy)]
+pub struct SecondSignal {
+    /// Signal ID of the second signal, used in commands.
+    pub id: &'static str,
+    /// Whether the second signal can show a slow aspect. Its yellow lamp is connected to pin A3, which can therefore not be used for the heater or directly connected panel LEDs.
+    pub has_slow_aspect: bool,
+}
+
+// Number of signals on the board, the signal and the second signal, if any.
+const SIGNAL_GROUPS: usize = 1 + SECOND_SIGNAL.is_some() as usize;
+
+/// A signal on the board, with the state that its commands are checked against, so that every signal is handled the same way. The signal is group 0, and the second signal group 1.
+struct SignalGroup {
+    // Signal ID that the commands of the signal are addressed with.
+    id: &'static str,
+    // Aspect that the signal shows.
+    aspect: BoardAspect,
+    arming: Arming<BoardAspect>,
+    // How long the signal has held stop, which must be long enough before it is cleared (see the HOLD configuration option).
+    stop_hold: StopHold<BoardAspect>,
+    // Aspect that the signal shows after a reboot (see save_aspect).
+    saved_aspect: WearLevelledByte,
+}
+
+impl SignalGroup {
+    fn new(platform: &mut impl Platform, group: usize) -> Self {
+        Self {
+            id: match SECOND_SIGNAL {
+                Some(second_signal) if group == 1 => second_signal.id,
+                _ => SIGNAL_ID,
+            },
+            aspect: BoardAspect::STOP,
+            arming: Arming::new(),
+            stop_hold: StopHold::new(),
+            saved_aspect: WearLevelledByte::load(platform, SAVED_ASPECT_EEPROM_OFFSETS[group]),
+        }
+    }
+
+    /// Checks an aspect command, given the aspect it asks for if the signal can show it, and returns that aspect, or the error that the command is rejected with. Only stop is allowed during an emergency stop, and nothing while the maintenance lock is engaged.
+    fn check_aspect(
+        &mut self,
+        next_aspect: Option<BoardAspect>,
+        emergency_stopped: bool,
+        maintenance_locked: bool,
+        config: &Config,
+        now: u32,
+    ) -> Result<BoardAspect, CommandError> {
+        if emergency_stopped && next_aspect != Some(BoardAspect::STOP) {
+            return Err(CommandError::EmergencyStop);
+        }
+        if maintenance_locked {
+            return Err(CommandError::Locked);
+        }
+        let next_aspect = next_aspect.ok_or(CommandError::Unsupported)?;
+        if config.require_arming
+            && arming::requires_arming(next_aspect)
+            && !self.arming.confirm(next_aspect, now)
+        {
+            Err(CommandError::NotArmed)
+        } else if !self
+            .stop_hold
+            .allows(next_aspect, u32::from(config.stop_hold_s) * 1000, now)
+        {
+            Err(CommandError::StopHeld)
+        } else {
+            Ok(next_aspect)
+        }
+    }
+
+    /// Saves the aspect that the signal shows after a reboot. Aspects that clear the signal are only written once they settled (see SAVED_ASPECT_SETTLE_MS), while all others are written right away, so that a signal that was put back never clears itself after a power cut.
+    fn save_aspect(&mut self, platform: &mut impl Platform, aspect: BoardAspect) {
+        let Some(code) = saved_aspect_code(aspect) else {
+            return;
+        };
+        if aspect.clears_signal() {
+            self.saved_aspect.defer(code, clock::millis());
+        } else {
+            self.saved_aspect.write(platform, code);
+        }
+    }
+
+    /// Returns the aspect saved for the signal, or None if none was saved yet.
+    fn saved_aspect(&self) -> Option<BoardAspect> {
+        self.saved_aspect
+            .value()
+            .and_then(AspectCommand::from_code)
+            .and_then(|command| BoardAspect::try_from(command).ok())
+    }
+}
+
+/// Reads the raw value of the internal temperature sensor, if the microcontroller has one.
+#[cfg(not(feature = "mega"))]
+fn read_temperature_sensor(adc: &mut arduino_hal::Adc) -> Option<u16> {
+    Some(adc.read_blocking(&adc::channel::Temperature))
+}
+
+#[cfg(feature = "mega")]
+fn read_temperature_sensor(_adc: &mut arduino_hal::Adc) -> Option<u16> {
+    None
+}
+
+/// Reads the voltage of the head’s ID resistor on pin A6 (see HAS_HEAD_ID), if the microcontroller has that pin.
+#[cfg(not(feature = "mega"))]
+fn read_head_id(adc: &mut arduino_hal::Adc) -> Option<u16> {
+    // the first conversion after switching on the ADC and its reference is inaccurate.
+    adc.read_blocking(&adc::channel::ADC6);
+    Some(adc.read_blocking(&adc::channel::ADC6))
+}
+
+#[cfg(feature = "mega")]
+fn read_head_id(_adc: &mut arduino_hal::Adc) -> Option<u16> {
+    None
+}
+
+/// Enables the pin change interrupt of pin A0, which times the edges of the track signal or the LocoNet bus, or clocks the Selectrix bus (see HAS_DCC_DECODER, HAS_LOCONET and HAS_SELECTRIX).
+#[cfg(not(feature = "mega"))]
+fn start_dcc_input(exint: &arduino_hal::pac::EXINT) {
+    exint.pcmsk1.write(|w| w.pcint().bits(1 << 0));
+    exint.pcicr.write(|w| w.pcie().bits(1 << 1));
+}
+
+#[cfg(feature = "mega")]
+fn start_dcc_input(_exint: &arduino_hal::pac::EXINT) {}
+
+type Serial = arduino_hal::hal::usart::Usart0<arduino_hal::DefaultClock>;
+
+/// The serial port, which panic messages are printed on, unless they go to the debug console (see HAS_DEBUG_CONSOLE).
+struct PanicOutput(Serial);
+
+impl PanicOutput {
+    fn flush(&mut self) {
+        self.0.flush();
+    }
+}
+
+impl ufmt::uWrite for PanicOutput {
+    type Error = Infallible;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        if HAS_DEBUG_CONSOLE {
+            DebugOutput.write_str(s)
+        } else {
+            self.0.write_str(s)
+        }
+    }
+}
+
+panic_serial::impl_panic_handler!(PanicOutput);
+
+// the serial port of the Mega that the debug console sends on, or None if it sends in software (see HAS_DEBUG_CONSOLE).
+#[cfg(feature = "mega")]
+const DEBUG_CONSOLE_USART: Option<u8> = if !HAS_DMX {
+    Some(3)
+} else if !HAS_MQTT {
+    Some(2)
+} else if !HAS_XPRESSNET && !HAS_GATEWAY {
+    Some(1)
+} else {
+    None
+};
+#[cfg(not(feature = "mega"))]
+const DEBUG_CONSOLE_USART: Option<u8> = None;
+
+// The debug console that sends in software on pin D9.
+type SoftwareDebugConsole = DebugConsole<Pin<Output>, Delay>;
+
+// the debug console, if it sends in software (see HAS_DEBUG_CONSOLE).
+static DEBUG_CONSOLE: Mutex<RefCell<Option<SoftwareDebugConsole>>> = Mutex::new(RefCell::new(None));
+
+/// The debug console, which drops the text if there is none.
+struct DebugOutput;
+
+impl ufmt::uWrite for DebugOutput {
+    type Error = Infallible;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        for byte in s.bytes() {
+            if DEBUG_CONSOLE_USART.is_some() {
+                send_on_debug_console_usart(byte);
+                continue;
+            }
+            // every bit gets its own critical section, so that interrupts can run between them.
+            for bit in 0..debug_console::BYTE_BITS {
+                interrupt::free(|cs| {
+                    // a panic while the console sends has to drop its message.
+                    let Ok(mut console) = DEBUG_CONSOLE.borrow(cs).try_borrow_mut() else {
+                        return;
+                    };
+                    if let Some(console) = console.as_mut() {
+                        console.send_bit(byte, bit).unwrap_infallible();
+                    }
+                });
+            }
+        }
+        Ok(())
+    }
+}
+static SERIAL: Mutex<RefCell<Option<&mut Serial>>> = Mutex::new(RefCell::new(None));
+// a small static buffer for receiving data in the interrupt.
+// 32 bytes takes fairly long and before this is exhausted
+const SERIAL_BUFFER_SIZE: usize = 32;
+// the interrupt only writes and the main loop only reads it, so neither needs a critical section.
+static SERIAL_BUFFER: RingBuffer<SERIAL_BUFFER_SIZE> = RingBuffer::new();
+static SERIAL_BUFFER_HIGH_WATER: Mutex<Cell<HighWaterMark>> =
+    Mutex::new(Cell::new(HighWaterMark::new()));
+// number of received bytes that were dropped because the main loop didn’t empty the interrupt buffer in time.
+static SERIAL_BUFFER_OVERRUNS: Mutex<Cell<u16>> = Mutex::new(Cell::new(0));
+// buffer for assembling command lines in the main loop.
+const LINE_BUFFER_SIZE: usize = 512;
+// time between the configuration commands sent by CLONE, which gives the target enough time to store each option in its EEPROM.
+const CLONE_LINE_INTERVAL_MS: u32 = 200;
+
+#[cfg(not(feature = "mega"))]
+#[avr_device::interrupt(atmega328p)]
+#[allow(non_snake_case)]
+fn USART_RX() {
+    receive_serial();
+}
+
+#[cfg(feature = "mega")]
+#[avr_device::interrupt(atmega2560)]
+#[allow(non_snake_case)]
+fn USART0_RX() {
+    receive_serial();
+}
+
+/// Moves the received bytes from the serial port into the static buffer. Called by the receive interrupt.
+fn receive_serial() {
+    // Interrupts don’t nest, so the interrupt handler already runs in a critical section, and doesn’t have to disable interrupts again.
+    let cs = unsafe { CriticalSection::new() };
+    // If serial port is occupied, try again later.
+    if let Some(serial) = SERIAL.borrow(cs).borrow_mut().as_mut() {
+        match serial.read() {
+            Ok(byte) => {
+                // this is the only place that pushes bytes.
+                if !unsafe { SERIAL_BUFFER.push(byte) } {
+                    let overruns = SERIAL_BUFFER_OVERRUNS.borrow(cs);
+                    overruns.set(overruns.get().saturating_add(1));
+                }
+                let high_water = SERIAL_BUFFER_HIGH_WATER.borrow(cs);
+                let mut mark = high_water.get();
+                mark.record(SERIAL_BUFFER.len());
+                high_water.set(mark);
+            }
+            // The buffer is now empty, we can stop reading.
+            Err(Error::WouldBlock) => {}
+            Err(Error::Other(_)) => unreachable!(),
+        }
+    }
+}
+
+/// The serial port, whose text is also kept for the network client, if there is one (see HAS_ETHERNET), and which is sent in frames while the serial port speaks the binary protocol.
+struct SerialOutput<'a> {
+    serial: &'a mut Serial,
+    network: &'a mut ArrayVec<u8, NETWORK_OUTPUT_SIZE>,
+    is_framed: bool,
+    // checksum of the current line, if lines end in a checksum (see the CSUM configuration option).
+    checksum: Option<u8>,
+    // sequence number of the command that is being answered, which every line of the answer ends with, and whether the current line already got it.
+    sequence_number: Option<u16>,
+    is_tagged: bool,
+}
+
+impl SerialOutput<'_> {
+    fn send(&mut self, s: &str) {
+        if HAS_ETHERNET {
+            let _ = self.network.try_extend_from_slice(s.as_bytes());
+        }
+        if !self.is_framed {
+            return self.serial.write_str(s).unwrap_infallible();
+        }
+        // every piece of text gets its own frames, so that nothing has to be buffered.
+        for text in s.as_bytes().chunks(binary::MAX_PAYLOAD) {
+            for byte in binary::text_frame(BINARY_ADDRESS, text) {
+                self.serial.write_byte(byte);
+            }
+        }
+    }
+}
+
+impl ufmt::uWrite for SerialOutput<'_> {
+    type Error = Infallible;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        let mut lines = s.split('\n');
+        self.send_in_line(lines.next().unwrap_or_default());
+        for line in lines {
+            self.end_line();
+            self.send_in_line(line);
+        }
+        Ok(())
+    }
+}
+
+impl SerialOutput<'_> {
+    /// Sends text within a line, with the sequence number before the comment, if there is one.
+    fn send_in_line(&mut self, text: &str) {
+        if let Some(sequence_number) = self.sequence_number
+            && !self.is_tagged
+            && let Some(start_of_comment) = text.find('#')
+        {
+            let (command, comment) = text.split_at(start_of_comment);
+            self.send_checksummed(command);
+            // the sequence number is written through write_str, which must not tag it again.
+            self.is_tagged = true;
+            ufmt::uwrite!(self, ":#{}", sequence_number).unwrap_infallible();
+            self.send_checksummed(comment);
+        } else {
+            self.send_checksummed(text);
+        }
+    }
+
+    fn send_checksummed(&mut self, text: &str) {
+        if let Some(checksum) = &mut self.checksum {
+            *checksum ^= commands::checksum(text.as_bytes());
+        }
+        self.send(text);
+    }
+
+    /// Ends a line with the sequence number and the checksum, if enabled.
+    fn end_line(&mut self) {
+        if let Some(sequence_number) = self.sequence_number
+            && !self.is_tagged
+        {
+            self.is_tagged = true;
+            ufmt::uwrite!(self, ":#{}", sequence_number).unwrap_infallible();
+        }
+        self.is_tagged = false;
+        if let Some(checksum) = self.checksum.replace(0) {
+            const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+            let suffix = [
+                b'*',
+                HEX_DIGITS[usize::from(checksum >> 4)],
+                HEX_DIGITS[usize::from(checksum & 0xf)],
+            ];
+            self.send(core::str::from_utf8(&suffix).unwrap_or_default());
+        }
+        self.send("\n");
+    }
+}
+
+const NETWORK_OUTPUT_SIZE: usize = 128;
+// text sent since the last loop iteration, which the main loop sends to the network client (see HAS_ETHERNET). Text that doesn’t fit is only sent on the serial port.
+static NETWORK_OUTPUT: Mutex<RefCell<ArrayVec<u8, NETWORK_OUTPUT_SIZE>>> =
+    Mutex::new(RefCell::new(ArrayVec::new_const()));
+
+/// Run some code (typically a closure) with access to the serial port. Nothing is sent while the serial port is a C/MRI or BiDiB node or answers SRCP commands, since the host would take the text for replies.
+fn with_serial(function: impl FnOnce(&mut SerialOutput)) {
+    interrupt::free(|cs| loop {
+        if SENDS_PACKETS.borrow(cs).get() {
+            break;
+        }
+        if let Some(serial) = SERIAL.borrow(cs).borrow_mut().as_mut() {
+            let network = &mut *NETWORK_OUTPUT.borrow(cs).borrow_mut();
+            let is_framed = USES_FRAMES.borrow(cs).get();
+            let checksum = APPENDS_CHECKSUMS.borrow(cs).get().then_some(0);
+            let sequence_number = REPLY_SEQUENCE_NUMBER.borrow(cs).get();
+            drive_bus(|| {
+                function(&mut SerialOutput {
+                    serial,
+                    network,
+                    is_framed,
+                    checksum,
+                    sequence_number,
+                    is_tagged: false,
+                });
+                serial.flush();
+            });
+            compiler_fence(Ordering::SeqCst);
+            break;
+        }
+    });
+}
+
+/// Sends an SRCP reply on the serial port, and to the network client, if there is one (see the SRCP configuration option).
+fn send_srcp_reply(reply: SrcpReply) {
+    interrupt::free(|cs| {
+        if let Some(serial) = SERIAL.borrow(cs).borrow_mut().as_mut() {
+            let network = &mut *NETWORK_OUTPUT.borrow(cs).borrow_mut();
+            drive_bus(|| {
+                let mut output = SerialOutput {
+                    serial,
+                    network,
+                    is_framed: false,
+                    checksum: None,
+                    sequence_number: None,
+                    is_tagged: false,
+                };
+                ufmt::uwrite!(output, "{}", reply).unwrap_infallible();
+                serial.flush();
+            });
+        }
+    });
+}
+
+/// Sends a packet on the serial port, which is a C/MRI or BiDiB node (see the CMRI and BIDIB configuration options).
+fn send_packet(packet: &[u8]) {
+    interrupt::free(|cs| {
+        if let Some(serial) = SERIAL.borrow(cs).borrow_mut().as_mut() {
+            drive_bus(|| {
+                for byte in packet {
+                    serial.write_byte(*byte);
+                }
+                serial.flush();
+            });
+        }
+    });
+}
+
+// whether the serial port is a C/MRI or BiDiB node or answers SRCP commands, which follows the configuration.
+static SENDS_PACKETS: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+// whether the serial port speaks the binary protocol, which follows the configuration.
+static USES_FRAMES: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+// whether lines on the serial port end in a checksum, which follows the configuration.
+static APPENDS_CHECKSUMS: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+// sequence number of the command on the serial port that is being answered, if it had one.
+static REPLY_SEQUENCE_NUMBER: Mutex<Cell<Option<u16>>> = Mutex::new(Cell::new(None));
+
+// driver enable pin of the RS-485 transceiver, if there is one (see HAS_RS485_TRANSCEIVER).
+static RS485_DRIVER_ENABLE: Mutex<RefCell<Option<Pin<Output>>>> = Mutex::new(RefCell::new(None));
+
+/// Runs the code, which must send at least one byte, while the RS-485 transceiver drives the bus, if there is one. The bus is only released once the last byte has left the USART completely, since it would be cut off otherwise.
+fn drive_bus(send: impl FnOnce()) {
+    interrupt::free(|cs| {
+        let mut driver_enable = RS485_DRIVER_ENABLE.borrow(cs).borrow_mut();
+        let Some(driver_enable) = driver_enable.as_mut() else {
+            send();
+            return;
+        };
+        // the USART belongs to the serial port, and only its transmit complete flag is used here, which is cleared by writing a one.
+        let usart = unsafe { &*arduino_hal::pac::USART0::ptr() };
+        usart.ucsr0a.modify(|_, w| w.txc0().set_bit());
+        driver_enable.set_high();
+        send();
+        while usart.ucsr0a.read().txc0().bit_is_clear() {}
+        driver_enable.set_low();
+    });
+}
+
+macro_rules! serial_writeln {
+    ($($t:tt)*) => {
+        with_serial(|serial|{
+            ufmt::uwriteln!(serial, $($t)*).unwrap_infallible();
+        });
+    };
+}
+
+// decoders of the track signal and the time of its last edge in microseconds (see HAS_DCC_DECODER).
+static TRACK_DECODERS: Mutex<RefCell<(DccDecoder, MotorolaDecoder, u32)>> =
+    Mutex::new(RefCell::new((DccDecoder::new(), MotorolaDecoder::new(), 0)));
+// whether the track signal is decoded as Märklin Motorola instead of DCC, which follows the configuration.
+static USES_MOTOROLA: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+// the last decoded accessory command, until the main loop picks it up.
+static ACCESSORY_COMMAND: Mutex<Cell<Option<AccessoryCommand>>> = Mutex::new(Cell::new(None));
+
+// receiver of the LocoNet bus, the time of its last edge in microseconds, and whether the bus is high since then (see HAS_LOCONET).
+static LOCONET_RECEIVER: Mutex<RefCell<(LocoNetReceiver, u32, bool)>> =
+    Mutex::new(RefCell::new((LocoNetReceiver::new(), 0, true)));
+
+// receiver of the Selectrix bus, the channel that the signal reads, which follows the configuration, and the last value of that channel, until the main loop picks it up (see HAS_SELECTRIX).
+static SELECTRIX_RECEIVER: Mutex<RefCell<SelectrixReceiver>> =
+    Mutex::new(RefCell::new(SelectrixReceiver::new()));
+static SELECTRIX_CHANNEL: Mutex<Cell<u8>> = Mutex::new(Cell::new(0));
+static SELECTRIX_VALUE: Mutex<Cell<Option<(u8, u8)>>> = Mutex::new(Cell::new(None));
+
+// receiver of the XpressNet bus, and the driver enable pin of its transceiver (see HAS_XPRESSNET).
+#[cfg(feature = "mega")]
+static XPRESSNET: Mutex<RefCell<(XpressNetReceiver, Option<Pin<Output>>)>> = Mutex::new(
+    RefCell::new((XpressNetReceiver::new(XPRESSNET_ADDRESS), None)),
+);
+
+/// Sets up the second serial port of the Mega for the XpressNet bus, with nine data bits and an interrupt for every received byte (see HAS_XPRESSNET).
+#[cfg(feature = "mega")]
+fn start_xpressnet(usart: arduino_hal::pac::USART1, driver_enable: Pin<Output>) {
+    interrupt::free(|cs| XPRESSNET.borrow(cs).borrow_mut().1 = Some(driver_enable));
+    // 16 MHz / 16 / 62 500 baud - 1 = 15, which is exact.
+    usart
+        .ubrr1
+        .write(|w| w.bits((16_000_000 / 16 / xpressnet::BAUD_RATE - 1) as u16));
+    // eight bits in the data register, and the ninth in the control register.
+    usart.ucsr1c.write(|w| w.ucsz1().chr8());
+    usart.ucsr1b.write(|w| {
+        w.ucsz12()
+            .set_bit()
+            .rxen1()
+            .set_bit()
+            .txen1()
+            .set_bit()
+            .rxcie1()
+            .set_bit()
+    });
+}
+
+/// Passes every byte of the XpressNet bus to its receiver, and answers requests for acknowledgement right away, since the command station only waits briefly for the answer. The bytes of the next controller of the gateway are kept for the main loop instead.
+#[cfg(feature = "mega")]
+#[avr_device::interrupt(atmega2560)]
+#[allow(non_snake_case)]
+fn USART1_RX() {
+    // the USART belongs to the XpressNet bus or the gateway, and is only used here once it was set up.
+    let usart = unsafe { &*arduino_hal::pac::USART1::ptr() };
+    if HAS_GATEWAY {
+        // this is the only place that pushes bytes, and a byte that doesn’t fit is lost like one of the serial port.
+        unsafe { GATEWAY_RECEIVED.push(usart.udr1.read().bits()) };
+        return;
+    }
+    // the ninth bit must be read before the data register, which releases both.
+    let ninth_bit = usart.ucsr1b.read().rxb81().bit_is_set();
+    let byte = u16::from(ninth_bit) << 8 | u16::from(usart.udr1.read().bits());
+    interrupt::free(|cs| {
+        let (receiver, driver_enable) = &mut *XPRESSNET.borrow(cs).borrow_mut();
+        match receiver.receive(byte) {
+            Some(XpressNetEvent::Command(command)) => {
+                ACCESSORY_COMMAND.borrow(cs).set(Some(command));
+            }
+            Some(XpressNetEvent::AcknowledgementRequest) => {
+                let Some(driver_enable) = driver_enable.as_mut() else {
+                    return;
+                };
+                // the bus is only released once the last byte has left the USART completely, like the RS-485 bus of the serial port.
+                usart.ucsr1a.modify(|_, w| w.txc1().set_bit());
+                driver_enable.set_high();
+                for byte in xpressnet::ACKNOWLEDGEMENT {
+                    while usart.ucsr1a.read().udre1().bit_is_clear() {}
+                    usart.udr1.write(|w| w.bits(byte));
+                }
+                while usart.ucsr1a.read().txc1().bit_is_clear() {}
+                driver_enable.set_low();
+            }
+            None => {}
+        }
+    });
+}
+
+// bytes received from the next controller of the daisy chain, which the main loop sends back on the serial port (see HAS_GATEWAY).
+#[cfg(feature = "mega")]
+static GATEWAY_RECEIVED: RingBuffer<64> = RingBuffer::new();
+
+/// Sets up the second serial port of the Mega for the next controller of the daisy chain, at the baud rate of the serial port, with an interrupt for every received byte (see HAS_GATEWAY).
+#[cfg(feature = "mega")]
+fn start_gateway(usart: &arduino_hal::pac::USART1, baud_rate: u32) {
+    // at double speed, rounded to the nearest divider, like the serial port.
+    usart.ucsr1a.write(|w| w.u2x1().set_bit());
+    usart
+        .ubrr1
+        .write(|w| w.bits(((16_000_000 / 4 / baud_rate - 1) / 2) as u16));
+    usart.ucsr1c.write(|w| w.ucsz1().chr8());
+    usart
+        .ucsr1b
+        .write(|w| w.rxen1().set_bit().txen1().set_bit().rxcie1().set_bit());
+}
+
+/// Passes a line on to the next controller of the daisy chain (see HAS_GATEWAY).
+#[cfg(feature = "mega")]
+fn forward_downstream(line: &[u8]) {
+    // the USART belongs to the gateway, and is only used here once it was set up.
+    let usart = unsafe { &*arduino_hal::pac::USART1::ptr() };
+    for byte in line {
+        while usart.ucsr1a.read().udre1().bit_is_clear() {}
+        usart.udr1.write(|w| w.bits(*byte));
+    }
+}
+
+/// Sends a line of the next controller of the daisy chain back on the serial port as it is, with its own checksum and sequence number (see HAS_GATEWAY).
+#[cfg(feature = "mega")]
+fn forward_upstream(line: &[u8]) {
+    // the controllers only send text, so a line that isn’t was garbled anyway.
+    if let Ok(line) = core::str::from_utf8(line) {
+        with_serial(|serial| serial.send(line));
+    }
+}
+
+/// Sets up the transmitter of the serial port of the Mega that the debug console sends on, at the baud rate of the console (see HAS_DEBUG_CONSOLE).
+#[cfg(feature = "mega")]
+fn start_debug_console_usart() {
+    // at double speed, rounded to the nearest divider, like the serial port.
+    let divider = ((16_000_000 / 4 / debug_console::BAUD_RATE - 1) / 2) as u16;
+    // the USART belongs to the debug console, since no other feature uses it.
+    match DEBUG_CONSOLE_USART {
+        Some(1) => {
+            let usart = unsafe { &*arduino_hal::pac::USART1::ptr() };
+            usart.ucsr1a.write(|w| w.u2x1().set_bit());
+            usart.ubrr1.write(|w| w.bits(divider));
+            usart.ucsr1c.write(|w| w.ucsz1().chr8());
+            usart.ucsr1b.write(|w| w.txen1().set_bit());
+        }
+        Some(2) => {
+            let usart = unsafe { &*arduino_hal::pac::USART2::ptr() };
+            usart.ucsr2a.write(|w| w.u2x2().set_bit());
+            usart.ubrr2.write(|w| w.bits(divider));
+            usart.ucsr2c.write(|w| w.ucsz2().chr8());
+            usart.ucsr2b.write(|w| w.txen2().set_bit());
+        }
+        Some(3) => {
+            let usart = unsafe { &*arduino_hal::pac::USART3::ptr() };
+            usart.ucsr3a.write(|w| w.u2x3().set_bit());
+            usart.ubrr3.write(|w| w.bits(divider));
+            usart.ucsr3c.write(|w| w.ucsz3().chr8());
+            usart.ucsr3b.write(|w| w.txen3().set_bit());
+        }
+        _ => {}
+    }
+}
+
+/// Sends a byte on the serial port of the Mega that the debug console sends on, once the transmitter is free. Interrupts keep running while it waits.
+#[cfg(feature = "mega")]
+fn send_on_debug_console_usart(byte: u8) {
+    // the USART belongs to the debug console, and is only used here once it was set up.
+    match DEBUG_CONSOLE_USART {
+        Some(1) => {
+            let usart = unsafe { &*arduino_hal::pac::USART1::ptr() };
+            while usart.ucsr1a.read().udre1().bit_is_clear() {}
+            usart.udr1.write(|w| w.bits(byte));
+        }
+        Some(2) => {
+            let usart = unsafe { &*arduino_hal::pac::USART2::ptr() };
+            while usart.ucsr2a.read().udre2().bit_is_clear() {}
+            usart.udr2.write(|w| w.bits(byte));
+        }
+        Some(3) => {
+            let usart = unsafe { &*arduino_hal::pac::USART3::ptr() };
+            while usart.ucsr3a.read().udre3().bit_is_clear() {}
+            usart.udr3.write(|w| w.bits(byte));
+        }
+        _ => {}
+    }
+}
+
+#[cfg(not(feature = "mega"))]
+fn send_on_debug_console_usart(_byte: u8) {}
+
+// bytes received from the ESP8266 module, which the main loop passes to the MQTT client (see HAS_MQTT).
+#[cfg(feature = "mega")]
+static MQTT_RECEIVED: Mutex<RefCell<ArrayVec<u8, 128>>> =
+    Mutex::new(RefCell::new(ArrayVec::new_const()));
+
+/// Sets up the third serial port of the Mega for the ESP8266 module, with an interrupt for every received byte (see HAS_MQTT).
+#[cfg(feature = "mega")]
+fn start_mqtt(usart: &arduino_hal::pac::USART2) {
+    // at double speed, 16 MHz / 8 / 115 200 baud - 1 = 16, which is 2.1 % too fast, but within the tolerance of the module.
+    usart.ucsr2a.write(|w| w.u2x2().set_bit());
+    usart
+        .ubrr2
+        .write(|w| w.bits((16_000_000 / 8 / mqtt::BAUD_RATE - 1) as u16));
+    usart.ucsr2c.write(|w| w.ucsz2().chr8());
+    usart
+        .ucsr2b
+        .write(|w| w.rxen2().set_bit().txen2().set_bit().rxcie2().set_bit());
+}
+
+/// Keeps every byte of the ESP8266 module for the main loop, since the MQTT client takes too long for an interrupt at 115 200 baud.
+#[cfg(feature = "mega")]
+#[avr_device::interrupt(atmega2560)]
+#[allow(non_snake_case)]
+fn USART2_RX() {
+    // the USART belongs to the module, and is only used here once it was set up.
+    let usart = unsafe { &*arduino_hal::pac::USART2::ptr() };
+    let byte = usart.udr2.read().bits();
+    interrupt::free(|cs| {
+        // a byte that doesn’t fit loses its line, which the client ignores.
+        let _ = MQTT_RECEIVED.borrow(cs).borrow_mut().try_push(byte);
+    });
+}
+
+/// Writes the commands of the MQTT client to the ESP8266 module (see HAS_MQTT).
+#[cfg(feature = "mega")]
+struct EspSerial;
+
+#[cfg(feature = "mega")]
+impl ufmt::uWrite for EspSerial {
+    type Error = Infallible;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        // the USART belongs to the module, and is only used here once it was set up.
+        let usart = unsafe { &*arduino_hal::pac::USART2::ptr() };
+        for byte in s.bytes() {
+            while usart.ucsr2a.read().udre2().bit_is_clear() {}
+            usart.udr2.write(|w| w.bits(byte));
+        }
+        Ok(())
+    }
+}
+
+// receiver of the DMX512 bus, the channel that the signal reads, which follows the configuration, and the last values of that channel and the next one, until the main loop picks them up (see HAS_DMX).
+#[cfg(feature = "mega")]
+static DMX_RECEIVER: Mutex<RefCell<DmxReceiver>> = Mutex::new(RefCell::new(DmxReceiver::new()));
+static DMX_CHANNEL: Mutex<Cell<u16>> = Mutex::new(Cell::new(1));
+#[cfg(feature = "mega")]
+static DMX_VALUES: Mutex<Cell<Option<[u8; 2]>>> = Mutex::new(Cell::new(None));
+
+/// Sets up the fourth serial port of the Mega for the DMX512 bus, receiving only, with an interrupt for every received byte (see HAS_DMX).
+#[cfg(feature = "mega")]
+fn start_dmx(usart: &arduino_hal::pac::USART3) {
+    // 16 MHz / 16 / 250 000 baud - 1 = 3, which is exact.
+    usart
+        .ubrr3
+        .write(|w| w.bits((16_000_000 / 16 / dmx::BAUD_RATE - 1) as u16));
+    usart.ucsr3c.write(|w| w.ucsz3().chr8().usbs3().stop2());
+    usart
+        .ucsr3b
+        .write(|w| w.rxen3().set_bit().rxcie3().set_bit());
+}
+
+/// Passes every byte of the DMX512 bus to its receiver, which only keeps two channels, so that the interrupt is short enough for the 44 µs of a byte.
+#[cfg(feature = "mega")]
+#[avr_device::interrupt(atmega2560)]
+#[allow(non_snake_case)]
+fn USART3_RX() {
+    // the USART belongs to the bus, and is only used here once it was set up.
+    let usart = unsafe { &*arduino_hal::pac::USART3::ptr() };
+    // the break holds the line low past the stop bit, which the USART reports as a framing error of the byte in the buffer, so the flag must be read before the byte.
+    let is_break = usart.ucsr3a.read().fe3().bit_is_set();
+    let byte = usart.udr3.read().bits();
+    interrupt::free(|cs| {
+        let channel = DMX_CHANNEL.borrow(cs).get();
+        let received = DMX_RECEIVER
+            .borrow(cs)
+            .borrow_mut()
+            .receive(byte, is_break, channel);
+        if let Some(values) = received {
+            DMX_VALUES.borrow(cs).set(Some(values));
+        }
+    });
+}
+
+/// Passes every bit of the Selectrix bus or every edge of the LocoNet bus to its receiver, or every edge of the track signal to the decoder of the selected protocol. Only one decoder runs, since the short MM pulses leave little time between the interrupts. Edges are missed while interrupts are disabled, e.g. while a line is sent, but command stations repeat every accessory command several times.
+#[cfg(not(feature = "mega"))]
+#[avr_device::interrupt(atmega328p)]
+#[allow(non_snake_case)]
+fn PCINT1() {
+    if HAS_SELECTRIX {
+        // the port is only read here, and both pins are inputs.
+        let pinc = unsafe { &*arduino_hal::pac::PORTC::ptr() }.pinc.read();
+        // the data line is read when the clock rises, at the end of its low pulse.
+        if pinc.pc0().bit_is_clear() {
+            return;
+        }
+        let bit = pinc.pc3().bit_is_set();
+        interrupt::free(|cs| {
+            let received = SELECTRIX_RECEIVER.borrow(cs).borrow_mut().receive_bit(bit);
+            if let Some((channel, value)) = received
+                && channel == SELECTRIX_CHANNEL.borrow(cs).get()
+            {
+                SELECTRIX_VALUE.borrow(cs).set(Some((channel, value)));
+            }
+        });
+        return;
+    }
+    let now = clock::micros();
+    if HAS_LOCONET {
+        // the port is only read here, and the pin is an input.
+        let portc = unsafe { &*arduino_hal::pac::PORTC::ptr() };
+        let is_high = portc.pinc.read().pc0().bit_is_set();
+        interrupt::free(|cs| {
+            let (receiver, last_edge, was_high) = &mut *LOCONET_RECEIVER.borrow(cs).borrow_mut();
+            if let Some(command) = receiver.receive_level(*was_high, now.wrapping_sub(*last_edge)) {
+                ACCESSORY_COMMAND.borrow(cs).set(Some(command));
+            }
+            *last_edge = now;
+            *was_high = is_high;
+        });
+        return;
+    }
+    interrupt::free(|cs| {
+        let (dcc_decoder, motorola_decoder, last_edge) =
+            &mut *TRACK_DECODERS.borrow(cs).borrow_mut();
+        let duration_us = now.wrapping_sub(*last_edge);
+        let command = if USES_MOTOROLA.borrow(cs).get() {
+            motorola_decoder.receive_edge(duration_us)
+        } else {
+            dcc_decoder.receive_half_bit(duration_us)
+        };
+        if let Some(command) = command {
+            ACCESSORY_COMMAND.borrow(cs).set(Some(command));
+        }
+        *last_edge = now;
+    });
+}
+
+// severity filter of the lines sent with log!, which follows the configuration.
+static LOG_FILTER: Mutex<Cell<LogFilter>> = Mutex::new(Cell::new(LogFilter::new()));
+
+/// Returns whether a line with the given severity is sent on the given channel (see the LOGP and LOGD configuration options).
+fn is_logged(channel: Channel, severity: Severity) -> bool {
+    interrupt::free(|cs| LOG_FILTER.borrow(cs).get()).allows(channel, severity)
+}
+
+/// Sends a line on the given channel with the given severity, unless the channel's severity filter suppresses it. Lines of the diagnostics channel go to the debug console instead of the serial port, if there is one (see HAS_DEBUG_CONSOLE). Commands for other controllers are sent with serial_writeln! instead, since they are not log lines.
+macro_rules! log {
+    ($channel:ident, $severity:ident, $($t:tt)*) => {
+        if is_logged(Channel::$channel, Severity::$severity) {
+            if HAS_DEBUG_CONSOLE && Channel::$channel == Channel::Diagnostics {
+                ufmt::uwriteln!(DebugOutput, $($t)*).unwrap_infallible();
+            } else {
+                serial_writeln!($($t)*);
+            }
+        }
+    };
+}
+
+/// Switches a lamp that was switched directly back to the state that the signal has set it to.
+fn restore_lamp(signal: &mut impl Signal<Pin = LampPin>, role: LampRole) {
+    if let Some(lamp) = signal.lamp(role) {
+        let is_on = lamp.is_set_high().unwrap_infallible();
+        lamp.inner_mut().set_state(is_on.into()).unwrap_infallible();
+    }
+}
+
+/// Returns a handle of the pin from D2 to D8 with the given index, as an output (see CFG:PIN).
+#[cfg(not(feature = "semaphore"))]
+fn mapped_pin(index: usize) -> Pin<Output> {
+    // the lamps only give up the handle of their old pin, so the handle of the new one is made up, like the registers that the interrupts use.
+    let pins = arduino_hal::pins!(unsafe { arduino_hal::Peripherals::steal() });
+    match index {
+        0 => pins.d2.into_output().downgrade(),
+        1 => pins.d3.into_output().downgrade(),
+        2 => pins.d4.into_output().downgrade(),
+        3 => pins.d5.into_output().downgrade(),
+        4 => pins.d6.into_output().downgrade(),
+        5 => pins.d7.into_output().downgrade(),
+        _ => pins.d8.into_output().downgrade(),
+    }
+}
+
+/// Moves the lamps that the configuration assigned to other pins from D2 to D8 to their new pins, so that the signal doesn’t have to be rebuilt by a reboot (see CFG:PIN). The lamps are switched off first, so that no lamp lights up with the state of the lamp that had its pin before, and then switched back to the state that the signal has set them to.
+#[cfg(not(feature = "semaphore"))]
+fn move_lamp_pins(signal: &mut impl Signal<Pin = LampPin>, old_config: &Config, config: &Config) {
+    let moved_lamps = || {
+        config::MAPPED_LAMPS
+            .into_iter()
+            .filter(|role| old_config.pin_index_of(*role) != config.pin_index_of(*role))
+    };
+    for role in moved_lamps() {
+        if let Some(lamp) = signal.lamp(role) {
+            lamp.inner_mut().set_low().unwrap_infallible();
+        }
+    }
+    for role in moved_lamps() {
+        if let Some(lamp) = signal.lamp(role)
+            && let Some(index) = config.pin_index_of(role)
+        {
+            lamp.inner_mut().replace_first_channel(mapped_pin(index));
+        }
+        restore_lamp(signal, role);
+    }
+}
+
+/// Ends the simulated defect of a lamp, if there is one, and switches the lamp back to the state that the signal has set it to (see the AGE configuration option).
+fn end_lamp_aging(lamp_aging: &mut LampAging<XorShift32>, signal: &mut impl Signal<Pin = LampPin>) {
+    if let Some(role) = lamp_aging.cancel() {
+        restore_lamp(signal, role);
+        log!(
+            Diagnostics,
+            Info,
+            "{}:SIM:END:{}",
+            SIGNAL_ID,
+            role.command_id()
+        );
+    }
+}
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    // must happen before anything else uses the stack.
+    let ram_noise = memory::paint_stack();
+
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+    // the configuration decides what is sent first, so it must be read before the serial port is set up.
+    let mut platform = AvrPlatform::new(Eeprom::new(dp.EEPROM), Wdt::new(dp.WDT, &dp.CPU.mcusr));
+    let saved_config = Config::load_saved(&mut platform);
+    let is_config_saved = saved_config.is_some();
+    let mut config = saved_config.unwrap_or_default();
+    interrupt::free(|cs| {
+        LOG_FILTER.borrow(cs).set(config.log_filter);
+        USES_MOTOROLA.borrow(cs).set(config.motorola_accessories);
+        SELECTRIX_CHANNEL.borrow(cs).set(config.selectrix_channel);
+        DMX_CHANNEL.borrow(cs).set(config.dmx_channel);
+        SENDS_PACKETS
+            .borrow(cs)
+            .set(config.cmri || config.bidib || config.srcp);
+        USES_FRAMES.borrow(cs).set(config.binary);
+        APPENDS_CHECKSUMS.borrow(cs).set(config.require_checksum);
+    });
+    // BiDiB needs a faster serial port, so changing the BIDIB configuration option takes effect after a reboot.
+    let mut bidib = config
+        .bidib
+        .then(|| BidibNode::new(BIDIB_PRODUCT_ID_AND_SERIAL, &BIDIB_ASPECTS));
+    let serial_baud_rate = config.baud_rate;
+    let baud_rate = if bidib.is_some() {
+        bidib::BAUD_RATE
+    } else {
+        serial_baud_rate.bits_per_second()
+    };
+    // a new baud rate is on trial until a command arrives at it. BiDiB has its own baud rate, and its packets are no commands.
+    let mut baud_rate_fallback = [0xff];
+    platform.read_persistent(BAUD_RATE_FALLBACK_EEPROM_OFFSET, &mut baud_rate_fallback);
+    let mut baud_rate_fallback = BaudRate::from_value(baud_rate_fallback[0]);
+    // a trial that BiDiB overrides, or whose configuration was replaced by the defaults, e.g. by a firmware update, ends without falling back, so that it can’t switch to a stale baud rate later.
+    if baud_rate_fallback.is_some() && (bidib.is_some() || !is_config_saved) {
+        baud_rate_fallback = None;
+        platform.write_persistent(BAUD_RATE_FALLBACK_EEPROM_OFFSET, &[0xff]);
+    }
+    // the TX pin must idle high before the USART takes it over, or the receiver sees a start bit and decodes a garbage byte.
+    let serial = arduino_hal::Usart::new(
+        dp.USART0,
+        pins.d0,
+        pins.d1.into_output_high(),
+        baud_rate.into_baudrate(),
+    );
+    let serial = &mut share_serial_port_with_panic(PanicOutput(serial)).0;
+    let mut pin_a0 = Some(pins.a0);
+    if HAS_RS485_TRANSCEIVER {
+        let driver_enable = pin_a0.take().unwrap().into_output().downgrade();
+        interrupt::free(|cs| *RS485_DRIVER_ENABLE.borrow(cs).borrow_mut() = Some(driver_enable));
+    }
+    if HAS_DCC_DECODER || HAS_SELECTRIX {
+        // the pin stays a floating input, and is only watched by its pin change interrupt.
+        pin_a0.take();
+        start_dcc_input(&dp.EXINT);
+    }
+    #[cfg(feature = "mega")]
+    if HAS_GATEWAY {
+        start_gateway(&dp.USART1, baud_rate);
+    }
+    #[cfg(feature = "mega")]
+    if HAS_XPRESSNET {
+        start_xpressnet(dp.USART1, pins.d22.into_output().downgrade());
+    }
+    #[cfg(feature = "mega")]
+    if HAS_MQTT {
+        start_mqtt(&dp.USART2);
+    }
+    #[cfg(feature = "mega")]
+    if HAS_DMX {
+        start_dmx(&dp.USART3);
+    }
+    #[cfg(feature = "mega")]
+    let mut mqtt = HAS_MQTT.then(|| MqttClient::new(SIGNAL_ID, &MQTT_SETTINGS));
+    // the LocoNet input is watched by the same interrupt, and read back while sending.
+    let loconet_input = HAS_LOCONET.then(|| {
+        start_dcc_input(&dp.EXINT);
+        pin_a0.take().unwrap().into_floating_input().downgrade()
+    });
+    // a C/MRI or BiDiB node and the binary protocol only send replies, and an SRCP server only announces itself.
+    if config.srcp {
+        drive_bus(|| serial.write_str(srcp::GREETING).unwrap_infallible());
+    } else if !config.cmri && bidib.is_none() && !config.binary {
+        drive_bus(|| {
+            if config.machine_mode {
+                ufmt::uwriteln!(serial, "{}:BOOT", SIGNAL_ID).unwrap_infallible();
+            } else {
+                ufmt::uwriteln!(
+                    serial,
+                    "# train-signalling {}, signal {}",
+                    env!("CARGO_PKG_VERSION"),
+                    SIGNAL_ID
+                )
+                .unwrap_infallible();
+            }
+        });
+    }
+    let mut authentication_counter = [0; 4];
+    platform.read_persistent(
+        AUTHENTICATION_COUNTER_EEPROM_OFFSET,
+        &mut authentication_counter,
+    );
+    let mut authenticator = Authenticator::new(
+        AUTHENTICATION_KEY,
+        u32::from_le_bytes(authentication_counter),
+    );
+    let mut journal_sequence = [0; 2];
+    platform.read_persistent(JOURNAL_EEPROM_OFFSET, &mut journal_sequence);
+    let mut journal = Journal::new(u16::from_le_bytes(journal_sequence));
+    let mut lamp_test_log = LampTestLog::load(&mut platform);
+    // the internal temperature sensor needs the internal reference voltage.
+    let mut adc = arduino_hal::Adc::new(
+        dp.ADC,
+        adc::AdcSettings {
+            ref_voltage: adc::ReferenceVoltage::Internal,
+            ..Default::default()
+        },
+    );
+
+    platform.start_watchdog();
+    clock::init(dp.TC0);
+    serial.listen(Event::RxComplete);
+    interrupt::free(|cs| {
+        *SERIAL.borrow(cs).borrow_mut() = Some(serial);
+    });
+    compiler_fence(Ordering::SeqCst);
+    unsafe { interrupt::enable() };
+
+    // heads can only be swapped while the controller is off, so the head is only identified at boot.
+    let attached_head = if HAS_HEAD_ID {
+        let attached_head = read_head_id(&mut adc).and_then(HeadType::from_reading);
+        if attached_head != Some(BOARD_HEAD) {
+            log!(
+                Protocol,
+                Warn,
+                "{}:HEAD:{}:{}",
+                SIGNAL_ID,
+                attached_head.map_or("NONE", HeadType::command_id),
+                BOARD_HEAD.command_id()
+            );
+        }
+        attached_head
+    } else {
+        None
+    };
+    // aspects are shown as the board is built if no head was identified.
+    let head_can_show = |aspect: BoardAspect, speed: Option<u8>| {
+        attached_head.map_or(true, |head| head.can_show(BOARD_HEAD, aspect, speed))
+    };
+
+    // pins A1 to A5 and D9 to D13 are shared between features that can't be enabled together.
+    let mut pin_a1 = Some(pins.a1);
+    let mut pin_a2 = Some(pins.a2);
+    let mut pin_a3 = Some(pins.a3);
+    let mut pin_a4 = Some(pins.a4);
+    let mut pin_a5 = Some(pins.a5);
+    // the output is low, so that the transistor releases the bus until something is sent.
+    let mut loconet_pins =
+        loconet_input.map(|input| (pin_a3.take().unwrap().into_output().downgrade(), input));
+    // the Selectrix data line stays a floating input, and is only read by the interrupt of the clock.
+    if HAS_SELECTRIX {
+        pin_a3.take();
+    }
+    let mut pin_d9 = Some(pins.d9);
+    #[cfg(feature = "mega")]
+    if HAS_DEBUG_CONSOLE && DEBUG_CONSOLE_USART.is_some() {
+        start_debug_console_usart();
+    }
+    if HAS_DEBUG_CONSOLE && DEBUG_CONSOLE_USART.is_none() {
+        let output = pin_d9.take().unwrap().into_output_high().downgrade();
+        interrupt::free(|cs| {
+            *DEBUG_CONSOLE.borrow(cs).borrow_mut() = Some(DebugConsole::new(output, Delay::new()));
+        });
+    }
+    let mut pin_d10 = Some(pins.d10);
+    let mut pin_d11 = Some(pins.d11);
+    let mut pin_d12 = Some(pins.d12);
+    let mut pin_d13 = Some(pins.d13);
+    // the MCP2515 or the W5500 is polled over the SPI bus.
+    let mut spi_bus = (HAS_OPENLCB || HAS_ETHERNET).then(|| {
+        #[cfg(not(feature = "mega"))]
+        let (spi, chip_select) = arduino_hal::Spi::new(
+            dp.SPI,
+            pin_d13.take().unwrap().into_output(),
+            pin_d11.take().unwrap().into_output(),
+            pin_d12.take().unwrap().into_pull_up_input(),
+            pin_d10.take().unwrap().into_output(),
+            spi::Settings::default(),
+        );
+        #[cfg(feature = "mega")]
+        let (spi, chip_select) = arduino_hal::Spi::new(
+            dp.SPI,
+            pins.d52.into_output(),
+            pins.d51.into_output(),
+            pins.d50.into_pull_up_input(),
+            pins.d53.into_output(),
+            spi::Settings::default(),
+        );
+        (spi, chip_select)
+    });
+    // OpenLCB is disabled if the MCP2515 doesn’t respond.
+    let mut openlcb = HAS_OPENLCB
+        .then(|| {
+            let (spi, chip_select) = spi_bus.take().unwrap();
+            let mut controller = Mcp2515::new(spi, chip_select, MCP2515_OSCILLATOR_HZ);
+            if !controller.start(&mut Delay::new()).unwrap_infallible() {
+                log!(Protocol, Error, "{}:FAULT:CAN", SIGNAL_ID);
+                return None;
+            }
+            Some((
+                controller,
+                OpenLcbNode::new(OPENLCB_NODE_ID, &OPENLCB_EVENT_ASPECTS),
+            ))
+        })
+        .flatten();
+    // Ethernet is disabled if the W5500 doesn’t respond.
+    let mut ethernet = HAS_ETHERNET
+        .then(|| {
+            let (spi, chip_select) = spi_bus.take().unwrap();
+            let mut controller = W5500::new(spi, chip_select);
+            if !controller
+                .start(&mut Delay::new(), &NETWORK_SETTINGS)
+                .unwrap_infallible()
+            {
+                log!(Protocol, Error, "{}:FAULT:ETH", SIGNAL_ID);
+                return None;
+            }
+            Some(controller)
+        })
+        .flatten();
+
+    // the servos get their pulses from the timers, so their pins only need to be outputs.
+    if cfg!(feature = "semaphore") || HAS_LEVEL_CROSSING_BARRIERS {
+        servo::init(dp.TC1, dp.TC2);
+    }
+
+    #[cfg(not(feature = "semaphore"))]
+    let signal = {
+        // the lamps on pins D2 to D8 are assigned by the configuration (see CFG:PIN), so that the wiring can differ without rebuilding the firmware.
+        let mut mapped_pins = [
+            Some(pins.d2.into_output().downgrade()),
+            Some(pins.d3.into_output().downgrade()),
+            Some(pins.d4.into_output().downgrade()),
+            Some(pins.d5.into_output().downgrade()),
+            Some(pins.d6.into_output().downgrade()),
+            Some(pins.d7.into_output().downgrade()),
+            Some(pins.d8.into_output().downgrade()),
+        ];
+        let mut red_lamp = VotedPin::new(
+            mapped_pins[config.pin_index_of(LampRole::MainRed).unwrap()]
+                .take()
+                .unwrap(),
+        );
+        if HAS_RED_LAMP_VOTING {
+            red_lamp = red_lamp.with_second_channel(
+                pin_a3.take().unwrap().into_output().downgrade(),
+                [
+                    pin_a4.take().unwrap().into_floating_input().downgrade(),
+                    pin_a5.take().unwrap().into_floating_input().downgrade(),
+                ],
+            );
+        }
+        let mut mapped_lamp = |role| {
+            lamp_pin(
+                mapped_pins[config.pin_index_of(role).unwrap()]
+                    .take()
+                    .unwrap(),
+            )
+        };
+        let mut signal = HVSignalGroup::new(
+            SlewLimitedPin::new(red_lamp, Delay::new()),
+            mapped_lamp(LampRole::MainGreen),
+            mapped_lamp(LampRole::AnnouncementGreenUpper),
+            mapped_lamp(LampRole::AnnouncementGreenLower),
+            mapped_lamp(LampRole::AnnouncementYellowUpper),
+            mapped_lamp(LampRole::AnnouncementYellowLower),
+        );
+
+        if HAS_DEACTIVATION_CAPABILITY {
+            signal = signal.with_deactivation_capability(
+                lamp_pin(pin_d9.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_d10.take().unwrap().into_output().downgrade()),
+            );
+        }
+        if HAS_SLOW_ASPECT {
+            signal = signal.with_slow_aspect(mapped_lamp(LampRole::MainYellow));
+        }
+
+        if HAS_REDUCED_SIGNAL_DISTANCE {
+            signal = signal.with_reduced_distance(None);
+        }
+        if HAS_ANNOUNCEMENT_AT_MAIN_MAST {
+            signal = signal.with_announcement_at_main_mast();
+        }
+        if HAS_FLASHING_NOTICE_LAMPS {
+            signal = signal.with_flashing_notice_lamps();
+        }
+
+        if HAS_SUBSTITUTION_SIGNAL {
+            signal = signal.with_substitution_signal(lamp_pin(
+                pin_d11.take().unwrap().into_output().downgrade(),
+            ));
+        }
+        if HAS_CAUTION_SIGNAL {
+            signal = signal.with_caution_signal([
+                lamp_pin(pin_d12.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_d13.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_a4.take().unwrap().into_output().downgrade()),
+            ]);
+        }
+        if HAS_COUNTER_TRACK_SIGNALS {
+            signal = signal
+                .with_counter_track_signal(lamp_pin(
+                    pin_d12.take().unwrap().into_output().downgrade(),
+                ))
+                .with_counter_track_substitution_signal(lamp_pin(
+                    pin_d13.take().unwrap().into_output().downgrade(),
+                ));
+        }
+        if HAS_SPEED_INDICATOR && SPEED_INDICATOR_USES_MAX7219 {
+            signal = signal.with_speed_indicator(Zs3Indicator::new_matrix(Max7219::new(
+                lamp_pin(pin_d11.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_d13.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_d12.take().unwrap().into_output().downgrade()),
+            )));
+        } else if HAS_SPEED_INDICATOR {
+            signal = signal.with_speed_indicator(Zs3Indicator::new([
+                lamp_pin(pin_d9.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_d10.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_d11.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_d12.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_d13.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_a4.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_a5.take().unwrap().into_output().downgrade()),
+            ]));
+        }
+        if HAS_SHUNTING_SIGNAL {
+            signal = signal.with_shunting_signal([
+                lamp_pin(pin_a1.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_a2.take().unwrap().into_output().downgrade()),
+            ]);
+        }
+
+        // the panel LEDs have the same pin type as the lamps, but are never ramped.
+        if HAS_PANEL {
+            let panel = if PANEL_USES_SHIFT_REGISTER {
+                PanelOutput::new_shift_register(
+                    lamp_pin(pin_a0.take().unwrap().into_output().downgrade()),
+                    lamp_pin(pin_a1.take().unwrap().into_output().downgrade()),
+                    lamp_pin(pin_a2.take().unwrap().into_output().downgrade()),
+                )
+            } else {
+                PanelOutput::new_direct(
+                    lamp_pin(pin_a0.take().unwrap().into_output().downgrade()),
+                    lamp_pin(pin_a1.take().unwrap().into_output().downgrade()),
+                    lamp_pin(pin_a2.take().unwrap().into_output().downgrade()),
+                    lamp_pin(pin_a3.take().unwrap().into_output().downgrade()),
+                )
+            };
+            signal = signal.with_panel(panel);
+        }
+        signal
+    };
+
+    #[cfg(feature = "semaphore")]
+    let signal = {
+        pin_d9.take().unwrap().into_output();
+        let mut signal: SemaphoreSignal<_, LampPin, _> = SemaphoreSignal::new(Servo::new(
+            ServoOutput::D9.connect(),
+            SEMAPHORE_UPPER_ARM_END_STOPS,
+        ));
+        if HAS_SLOW_ASPECT {
+            pin_d10.take().unwrap().into_output();
+            signal = signal.with_lower_arm(Servo::new(
+                ServoOutput::D10.connect(),
+                SEMAPHORE_LOWER_ARM_END_STOPS,
+            ));
+        }
+        if HAS_ANNOUNCEMENT_AT_MAIN_MAST {
+            pin_d11.take().unwrap().into_output();
+            signal = signal.with_distant_disc(Servo::new(
+                ServoOutput::D11.connect(),
+                SEMAPHORE_DISC_END_STOPS,
+            ));
+        }
+        signal
+    };
+
+    // further heads on the same mast, like a direction indicator, are added with `with_head`, so that the whole mast has a single signal ID.
+    let mut signal = Mast::new(signal)
+        .with_head(HAS_DWARF_SIGNAL.then(|| {
+            DwarfSignal::new(
+                lamp_pin(pin_a1.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_a2.take().unwrap().into_output().downgrade()),
+            )
+        }))
+        .with_head(HAS_ROUTE_INDICATOR.then(|| {
+            Zs2Indicator::new_matrix(Max7219::new(
+                lamp_pin(pin_d9.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_d10.take().unwrap().into_output().downgrade()),
+                lamp_pin(pin_a4.take().unwrap().into_output().downgrade()),
+            ))
+        }));
+
+    for role in LampRole::ALL {
+        if let Some(lamp) = signal.lamp(role) {
+            lamp.set_ramp_ms(config.lamp_slew_ms[role as usize]);
+        }
+    }
+
+    let mut keypad = if HAS_PANEL_BUTTONS {
+        Some(KeyMatrix::new(
+            [
+                pin_d12.take().unwrap().into_output_high().downgrade(),
+                pin_d13.take().unwrap().into_output_high().downgrade(),
+            ],
+            [
+                pin_d11.take().unwrap().into_pull_up_input().downgrade(),
+                pin_a4.take().unwrap().into_pull_up_input().downgrade(),
+                pin_a5.take().unwrap().into_pull_up_input().downgrade(),
+            ],
+        ))
+    } else {
+        None
+    };
+
+    let mut dcc_mapping = AccessoryMapping::new(DCC_ADDRESS, &DCC_BASIC_ASPECTS);
+    let mut motorola_mapping = AccessoryMapping::new(MM_ADDRESS, &DCC_BASIC_ASPECTS);
+    let mut selectrix_mapping = SelectrixMapping::new(SELECTRIX_FIRST_BIT, &SELECTRIX_ASPECTS);
+    #[cfg(feature = "mega")]
+    let mut dmx_mapping = DmxMapping::new(&DMX_ASPECTS);
+
+    let mut heater = if HAS_HEATER {
+        Some(
+            AuxOutput::new(
+                pin_a3.take().unwrap().into_output().downgrade(),
+                AuxRule::Heater {
+                    on_at_celsius: HEATER_ON_AT_CELSIUS,
+                    off_at_celsius: HEATER_OFF_AT_CELSIUS,
+                },
+            )
+            .unwrap_infallible(),
+        )
+    } else {
+        None
+    };
+    let mut level_crossing = if HAS_LEVEL_CROSSING {
+        let mut level_crossing = LevelCrossing::new(
+            pin_a1.take().unwrap().into_output().downgrade(),
+            pin_a2.take().unwrap().into_output().downgrade(),
+            LEVEL_CROSSING_WARNING_MS,
+        );
+        if HAS_LEVEL_CROSSING_BARRIERS {
+            pin_d10.take().unwrap().into_output();
+            level_crossing = level_crossing.with_barriers(
+                Servo::new(ServoOutput::D10.connect(), LEVEL_CROSSING_BARRIER_END_STOPS)
+                    .with_move_ms(LEVEL_CROSSING_BARRIER_MOVE_MS),
+            );
+        }
+        Some(level_crossing)
+    } else {
+        None
+    };
+    let mut sound_triggers = if HAS_SOUND_TRIGGERS {
+        Some([
+            AuxOutput::new(
+                pin_d12.take().unwrap().into_output().downgrade(),
+                SOUND_TRIGGER_RULES[0],
+            )
+            .unwrap_infallible(),
+            AuxOutput::new(
+                pin_d13.take().unwrap().into_output().downgrade(),
+                SOUND_TRIGGER_RULES[1],
+            )
+            .unwrap_infallible(),
+        ])
+    } else {
+        None
+    };
+    // all lamps of the second signal are on the port of the analog pins, so that every aspect change is a single write.
+    let mut second_signal = SECOND_SIGNAL.map(|second_signal| {
+        pin_a1.take().unwrap().into_output();
+        pin_a2.take().unwrap().into_output();
+        let signal = BankedSignal::<BoardAspect, _>::new(Port::Analog)
+            .with_lamp(LampRole::MainRed, 1)
+            .with_lamp(LampRole::MainGreen, 2);
+        if second_signal.has_slow_aspect {
+            pin_a3.take().unwrap().into_output();
+            signal.with_lamp(LampRole::MainYellow, 3)
+        } else {
+            signal
+        }
+    });
+    let mut night_dimmer = if HAS_NIGHT_DIMMING {
+        Some(
+            AuxOutput::new(
+                pin_a5.take().unwrap().into_output().downgrade(),
+                AuxRule::FastClockPeriod {
+                    from_minutes: NIGHT_FROM_MINUTES,
+                    until_minutes: NIGHT_UNTIL_MINUTES,
+                },
+            )
+            .unwrap_infallible(),
+        )
+    } else {
+        None
+    };
+
+    signal
+        .suppress_announcement(config.stub_track, &mut Delay::new())
+        .unwrap_infallible();
+    signal
+        .switch_to_aspect(BoardAspect::STOP, &mut Delay::new())
+        .unwrap_infallible();
+
+    // what the speed and direction indicators show next to the current aspect.
+    let mut current_speed = None;
+    let mut current_route = None;
+    // the error of the last switch that fell back, or None if it succeeded.
+    let mut last_switch_error = None;
+
+    // an emergency stop stays latched across reboots, until it is released.
+    let mut emergency_stop_latch = [0];
+    platform.read_persistent(EMERGENCY_STOP_EEPROM_OFFSET, &mut emergency_stop_latch);
+    let mut emergency_stopped = emergency_stop_latch == [1];
+
+    let mut groups: [SignalGroup; SIGNAL_GROUPS] =
+        core::array::from_fn(|group| SignalGroup::new(&mut platform, group));
+    if !emergency_stopped
+        && let Some(saved_aspect) = groups[0].saved_aspect()
+        && signal.supports_aspect(saved_aspect)
+        && head_can_show(saved_aspect, None)
+    {
+        signal
+            .switch_to_aspect(saved_aspect, &mut Delay::new())
+            .unwrap_infallible();
+        groups[0].aspect = saved_aspect;
+        if HAS_RED_LAMP_VOTING && !red_lamp_agrees(&mut signal) {
+            let error;
+            (groups[0].aspect, error) = fall_back_to_stop(&mut signal);
+            last_switch_error = Some(error);
+        }
+    }
+
+    // the group of the second signal only exists if the second signal does.
+    if let Some(second_signal) = &mut second_signal
+        && let Some(group) = groups.get_mut(1)
+    {
+        group.aspect = group
+            .saved_aspect()
+            .filter(|saved_aspect| {
+                !emergency_stopped && second_signal.supports_aspect(*saved_aspect)
+            })
+            .unwrap_or(BoardAspect::STOP);
+        second_signal
+            .switch_to_aspect(group.aspect, &mut Delay::new())
+            .unwrap_infallible();
+    }
+
+    // when a temporary aspect like Zs1 was switched to, for switching back to stop once it expires.
+    let mut temporary_aspect_since = None;
+    let mut maintenance_locked = false;
+    let mut arbiter = Arbiter::new();
+    // aspect commands of the signal that wait for the minimum dwell time of the shown aspect (see the DWELL configuration option).
+    let mut aspect_queue = AspectQueue::new();
+    let mut retransmit_window = RetransmitWindow::new();
+    // aspect that the upstream signal was last told about, if any.
+    let mut forwarded_aspect = None;
+    // the lowest bits of the temperature sensor are noisy, and so is RAM after power-on.
+    let mut rng = XorShift32::new(random::seed_from(&[
+        read_temperature_sensor(&mut adc).unwrap_or(0).into(),
+        ram_noise,
+    ]));
+    let mut lamp_aging = LampAging::new(XorShift32::new(rng.next_u32()), clock::millis());
+    let mut raw_lamp_control = RawLampControl::new();
+    let mut lamp_warm_up = LampWarmUp::new();
+    let mut lamp_test_scheduler = LampTestScheduler::new();
+    // when the last automatic lamp test ran since boot, if any.
+    let mut last_lamp_test = None;
+
+    let mut aux_inputs = AuxInputs {
+        now: 0,
+        temperature_celsius: 0,
+        fast_clock_minutes: None,
+        aspect_change: None,
+    };
+    // the aspect that the auxiliary outputs last saw, so that they notice aspect changes from every source.
+    let mut state_mirror = PresentationMirror::new(PresentationState {
+        aspect: groups[0].aspect,
+        speed: current_speed,
+        route: current_route,
+    });
+    let mut aux_aspect = groups[0].aspect;
+    // turnout positions that are yet to be reported on the LocoNet bus, starting with the aspect shown after boot.
+    let mut loconet_reports: ArrayVec<[u8; 4], { 2 * DCC_BASIC_ASPECTS.len() }> = ArrayVec::new();
+    if HAS_LOCONET
+        && let Some(command) =
+            AspectCommand::from_command_id(groups[0].aspect.command_id().as_bytes())
+    {
+        loconet_reports.extend(
+            dcc_mapping
+                .outputs_for(command)
+                .map(|(address, is_green)| loconet::switch_report(address, is_green)),
+        );
+    }
+    // the aspect shown after boot isn’t reported, but identified when other nodes ask for it.
+    if let Some((_, node)) = &mut openlcb
+        && let Some(command) =
+            AspectCommand::from_command_id(groups[0].aspect.command_id().as_bytes())
+    {
+        node.report_aspect(command);
+    }
+    // the layout’s fast clock, once it was broadcast.
+    let mut fast_clock: Option<FastClock> = None;
+    // make sure that the temperature is sampled immediately.
+    let mut last_temperature_sample = 0u32.wrapping_sub(TEMPERATURE_SAMPLE_INTERVAL_MS);
+
+    let mut serial_buffer: ArrayVec<u8, LINE_BUFFER_SIZE> = ArrayVec::new();
+    let mut serial_buffer_high_water = HighWaterMark::new();
+    let mut line_framer = LineFramer::new();
+    // number of lines that were dropped because they didn’t fit into the line buffer.
+    let mut serial_buffer_overruns = 0u16;
+    let mut srcp_session = SrcpSession::new(DCC_ADDRESS);
+    let mut cmri_receiver = CmriReceiver::new();
+    let mut cmri_mapping = CmriMapping::new(&CMRI_OUTPUT_ASPECTS);
+    // JMRI may transmit the outputs and poll right after, before the main loop handled the first request.
+    let mut cmri_requests: ArrayVec<CmriRequest, 4> = ArrayVec::new();
+    let mut frame_receiver = FrameReceiver::new();
+    // the line that the next controller of the daisy chain is sending back (see HAS_GATEWAY).
+    #[cfg(feature = "mega")]
+    let mut return_lines = ReturnLines::<64>::new();
+    // the aspect of the last ASPECT frame of the binary protocol, until it is handled.
+    let mut framed_aspect = None;
+
+    loop {
+        platform.feed_watchdog();
+
+        platform.sleep();
+        let received_at = clock::millis();
+        platform.receive(|byte| {
+            // the text of all serial protocols ends its lines with the configured terminator.
+            let mut push_text = |byte| {
+                if let Some(byte) =
+                    line_framer.receive_byte(byte, config.line_terminator, received_at)
+                    && line_framer.append(&mut serial_buffer, byte)
+                {
+                    serial_buffer_overruns = serial_buffer_overruns.saturating_add(1);
+                }
+            };
+            if let Some(node) = &mut bidib {
+                if let BidibByte::Text(byte) = node.receive_byte(byte) {
+                    push_text(byte);
+                }
+                return;
+            }
+            if config.binary {
+                match frame_receiver.receive_byte(byte, BINARY_ADDRESS) {
+                    Some(BinaryRequest::Text(text)) => text.into_iter().for_each(push_text),
+                    Some(BinaryRequest::Aspect(mapped)) => framed_aspect = Some(mapped),
+                    None => {}
+                }
+                return;
+            }
+            if !config.cmri {
+                push_text(byte);
+                return;
+            }
+            match cmri_receiver.receive_byte(byte, config.cmri_node_address) {
+                CmriByte::Text(byte) => push_text(byte),
+                CmriByte::Packet(Some(request)) => {
+                    let _ = cmri_requests.try_push(request);
+                }
+                CmriByte::Packet(None) => {}
+            }
+        });
+        // lines of the network are only added between the lines of the serial port, so that they don’t mix, and replies are sent in the next iteration.
+        if let Some(controller) = &mut ethernet {
+            let output =
+                interrupt::free(|cs| core::mem::take(&mut *NETWORK_OUTPUT.borrow(cs).borrow_mut()));
+            controller.send(&output).unwrap_infallible();
+            if serial_buffer.last().map_or(true, |byte| *byte == b'\n')
+                && let Some(line) = controller.receive_line().unwrap_infallible()
+                && serial_buffer.try_extend_from_slice(&line).is_err()
+            {
+                serial_buffer_overruns = serial_buffer_overruns.saturating_add(1);
+            }
+        }
+        serial_buffer_high_water.record(serial_buffer.len());
+        // a line whose terminator got lost would otherwise be prepended to the next command.
+        if line_framer.has_timed_out(u32::from(config.line_timeout_s) * 1000, clock::millis()) {
+            let start_of_partial_line = serial_buffer
+                .iter()
+                .rposition(|byte| *byte == b'\n')
+                .map_or(0, |position| position + 1);
+            log!(
+                Diagnostics,
+                Warn,
+                "{}:FRAMING:{}",
+                SIGNAL_ID,
+                serial_buffer.len() - start_of_partial_line
+            );
+            serial_buffer.truncate(start_of_partial_line);
+        }
+
+        // the saved aspect is replaced by stop, since the state that led to it can’t be trusted anymore.
+        if HAS_STATE_MIRROR
+            && !state_mirror.verify(PresentationState {
+                aspect: groups[0].aspect,
+                speed: current_speed,
+                route: current_route,
+            })
+        {
+            signal
+                .switch_to_aspect(BoardAspect::STOP, &mut Delay::new())
+                .unwrap_infallible();
+            groups[0].save_aspect(&mut platform, BoardAspect::STOP);
+            log!(Protocol, Error, "{}:FAULT:RAM", SIGNAL_ID);
+            platform.reboot();
+        }
+
+        let now = clock::millis();
+        for group in &mut groups {
+            group
+                .saved_aspect
+                .write_settled(&mut platform, SAVED_ASPECT_SETTLE_MS, now);
+        }
+        // nobody could reach the controller at the new baud rate, so it reboots with the one it used before.
+        if let Some(fallback) = baud_rate_fallback
+            && now >= baud::TRIAL_DURATION_MS
+        {
+            config.baud_rate = fallback;
+            config.save(&mut platform);
+            platform.write_persistent(BAUD_RATE_FALLBACK_EEPROM_OFFSET, &[0xff]);
+            platform.reboot();
+        }
+        if raw_lamp_control.has_timed_out(now) {
+            raw_lamp_control.end();
+            signal
+                .switch_to_aspect(groups[0].aspect, &mut Delay::new())
+                .unwrap_infallible();
+            (current_speed, current_route) = (None, None);
+        }
+        if let Some((is_on, lamps)) = raw_lamp_control.update_blinking(now) {
+            for role in lamps {
+                if let Some(lamp) = signal.lamp(role) {
+                    lamp.set_state(is_on.into()).unwrap_infallible();
+                }
+            }
+        }
+        if !raw_lamp_control.is_active() {
+            // only switches when the configuration changed, and not while lamps are switched directly.
+            signal
+                .suppress_announcement(config.stub_track, &mut Delay::new())
+                .unwrap_infallible();
+            signal.update(now).unwrap_infallible();
+        }
+        if let Some(second_signal) = &mut second_signal {
+            second_signal.update(now).unwrap_infallible();
+        }
+
+        if let Some(since) = temporary_aspect_since
+            && config.substitution_timeout_s != 0
+            && now.wrapping_sub(since) >= u32::from(config.substitution_timeout_s) * 1000
+            && !raw_lamp_control.is_active()
+        {
+            temporary_aspect_since = None;
+            end_lamp_aging(&mut lamp_aging, &mut signal);
+            let expired_aspect = groups[0].aspect;
+            signal
+                .switch_to_aspect(BoardAspect::STOP, &mut Delay::new())
+                .unwrap_infallible();
+            groups[0].aspect = BoardAspect::STOP;
+            (current_speed, current_route) = (None, None);
+            state_mirror.update(PresentationState {
+                aspect: groups[0].aspect,
+                speed: current_speed,
+                route: current_route,
+            });
+            log!(
+                Protocol,
+                Warn,
+                "{}:EXPIRED:{}:{}",
+                SIGNAL_ID,
+                expired_aspect.command_id(),
+                state_checksum(groups[0].aspect, current_speed, current_route)
+            );
+        }
+
+        lamp_test_scheduler.update(
+            config.lamp_test,
+            config.lamp_test_minutes,
+            now,
+            fast_clock
+                .as_ref()
+                .map(|fast_clock| fast_clock.minutes(now)),
+        );
+        // the test waits for stop, so that it never interrupts a train movement.
+        if lamp_test_scheduler.is_due()
+            && groups[0].aspect == BoardAspect::STOP
+            && !emergency_stopped
+            && !maintenance_locked
+            && !raw_lamp_control.is_active()
+        {
+            lamp_test_scheduler.finish();
+            end_lamp_aging(&mut lamp_aging, &mut signal);
+            // only the red lamp is read back, the other lamps can only be checked by watching the signal during the test.
+            let aspects: ArrayVec<BoardAspect, { maintenance::PROOF_ASPECTS.len() }> =
+                maintenance::PROOF_ASPECTS
+                    .into_iter()
+                    .filter_map(|command| BoardAspect::try_from(command).ok())
+                    .filter(|aspect| {
+                        signal.supports_aspect(*aspect) && head_can_show(*aspect, None)
+                    })
+                    .collect();
+            let mut failed_lamps = LampSet::default();
+            for aspect in aspects {
+                signal
+                    .switch_to_aspect(aspect, &mut Delay::new())
+                    .unwrap_infallible();
+                if HAS_RED_LAMP_VOTING && !red_lamp_agrees(&mut signal) {
+                    failed_lamps.insert(LampRole::MainRed);
+                }
+                platform.feed_watchdog();
+            }
+            signal
+                .switch_to_aspect(groups[0].aspect, &mut Delay::new())
+                .unwrap_infallible();
+            (current_speed, current_route) = (None, None);
+            lamp_test_log.record(failed_lamps, &mut platform);
+            last_lamp_test = Some(now);
+            if failed_lamps.is_empty() {
+                log!(Diagnostics, Info, "{}:TEST:{}", SIGNAL_ID, failed_lamps);
+            } else {
+                log!(Diagnostics, Warn, "{}:TEST:{}", SIGNAL_ID, failed_lamps);
+            }
+        }
+
+        if config.lamp_aging && !maintenance_locked {
+            // the lamps that the signal lights can differ from the main signal aspect, e.g. for a dark announcement signal.
+            let aging_event = lamp_aging.update(now, |role| {
+                signal
+                    .lamp(role)
+                    .is_some_and(|lamp| lamp.is_set_high().unwrap_infallible())
+            });
+            match aging_event {
+                Some(AgingEvent::Started(role, effect)) => {
+                    log!(
+                        Diagnostics,
+                        Info,
+                        "{}:SIM:{}:{}",
+                        SIGNAL_ID,
+                        effect.command_id(),
+                        role.command_id()
+                    );
+                }
+                Some(AgingEvent::Ended(role)) => {
+                    restore_lamp(&mut signal, role);
+                    log!(
+                        Diagnostics,
+                        Info,
+                        "{}:SIM:END:{}",
+                        SIGNAL_ID,
+                        role.command_id()
+                    );
+                }
+                None => {}
+            }
+            // like the warm-up, aging switches the lamp directly, so that the signal still knows whether it has lit the lamp.
+            if let Some((role, is_on)) = lamp_aging.lamp_state(now)
+                && let Some(lamp) = signal.lamp(role)
+            {
+                lamp.inner_mut().set_state(is_on.into()).unwrap_infallible();
+            }
+        } else {
+            end_lamp_aging(&mut lamp_aging, &mut signal);
+        }
+
+        if config.warm_up && !raw_lamp_control.is_active() {
+            // the warm-up switches the lamps directly, bypassing their ramp, so that the signal still knows which lamps it has lit.
+            lamp_warm_up.update(now, |role| {
+                signal
+                    .lamp(role)
+                    .is_some_and(|lamp| lamp.is_set_high().unwrap_infallible())
+            });
+            for (role, is_on) in lamp_warm_up.lamp_states(now) {
+                if let Some(lamp) = signal.lamp(role) {
+                    lamp.inner_mut().set_state(is_on.into()).unwrap_infallible();
+                }
+            }
+        } else {
+            for role in lamp_warm_up.cancel() {
+                if let Some(lamp) = signal.lamp(role) {
+                    let is_on = lamp.is_set_high().unwrap_infallible();
+                    lamp.inner_mut().set_state(is_on.into()).unwrap_infallible();
+                }
+            }
+        }
+
+        aux_inputs.now = now;
+        aux_inputs.aspect_change = AspectChange::between(aux_aspect, groups[0].aspect);
+        aux_aspect = groups[0].aspect;
+        if HAS_LOCONET && let Some(change) = aux_inputs.aspect_change {
+            // positions of earlier aspects are outdated.
+            loconet_reports.clear();
+            loconet_reports.extend(
+                dcc_mapping
+                    .outputs_for(change.to)
+                    .map(|(address, is_green)| loconet::switch_report(address, is_green)),
+            );
+        }
+        if let Some((output, input)) = &mut loconet_pins
+            && let Some(report) = loconet_reports.first()
+            && send_on_loconet(report, output, input)
+        {
+            loconet_reports.remove(0);
+        }
+        if let Some((controller, node)) = &mut openlcb {
+            if let Some(change) = aux_inputs.aspect_change {
+                node.report_aspect(change.to);
+            }
+            while controller.is_ready_to_send().unwrap_infallible()
+                && let Some(frame) = node.next_frame(now)
+            {
+                controller.send(&frame).unwrap_infallible();
+            }
+        }
+        if now.wrapping_sub(last_temperature_sample) >= TEMPERATURE_SAMPLE_INTERVAL_MS
+            && let Some(raw_temperature) = read_temperature_sensor(&mut adc)
+        {
+            last_temperature_sample = now;
+            aux_inputs.temperature_celsius = aux_outputs::celsius_from_internal_sensor(
+                raw_temperature,
+                TEMPERATURE_OFFSET_CELSIUS,
+            );
+            if let Some(heater) = &mut heater {
+                heater.update(&aux_inputs).unwrap_infallible();
+            }
+        }
+        aux_inputs.fast_clock_minutes = fast_clock
+            .as_ref()
+            .map(|fast_clock| fast_clock.minutes(now));
+        if let Some(night_dimmer) = &mut night_dimmer {
+            night_dimmer.update(&aux_inputs).unwrap_infallible();
+        }
+        for sound_trigger in sound_triggers.iter_mut().flatten() {
+            sound_trigger.update(&aux_inputs).unwrap_infallible();
+        }
+        if let Some(level_crossing) = &mut level_crossing {
+            level_crossing.update(now).unwrap_infallible();
+        }
+
+        let mut received_command = None;
+
+        // an emergency stop overtakes every line that waits in the buffer, and the lines before it are discarded, since they were meant for the situation before the stop.
+        let mut end_of_line = 0;
+        let mut end_of_emergency_stop = None;
+        for line in serial_buffer.split_inclusive(|byte| *byte == b'\n') {
+            end_of_line += line.len();
+            if line.ends_with(b"\n")
+                && matches!(
+                    get_next_command(line, SIGNAL_ID, LAYOUT_SEGMENT, false, false),
+                    Ok(Command::EmergencyStop)
+                )
+            {
+                end_of_emergency_stop = Some(end_of_line);
+                #[cfg(feature = "mega")]
+                if HAS_GATEWAY {
+                    forward_downstream(line);
+                }
+            }
+        }
+        if let Some(end_of_emergency_stop) = end_of_emergency_stop {
+            serial_buffer.drain(..end_of_emergency_stop);
+            received_command = Some((CommandSource::Serial, 0, Command::EmergencyStop));
+        }
+
+        // SRCP commands are taken before the text protocol sees them, and the next line waits for the next iteration if one commanded an aspect.
+        if config.srcp
+            && received_command.is_none()
+            && let Some(position_of_newline) = serial_buffer.iter().position(|byte| *byte == b'\n')
+        {
+            let line_received_at = clock::millis();
+            let shown_aspect =
+                AspectCommand::from_command_id(groups[0].aspect.command_id().as_bytes());
+            let handled = srcp_session.handle_line(
+                &serial_buffer[..=position_of_newline],
+                line_received_at,
+                |address, is_green| {
+                    shown_aspect.is_some_and(|aspect| {
+                        dcc_mapping
+                            .outputs_for(aspect)
+                            .any(|output| output == (address, is_green))
+                    })
+                },
+            );
+            if let Some((reply, accessory_command)) = handled {
+                serial_buffer.drain(0..=position_of_newline);
+                clock::wait_since(line_received_at, config.reply_delay_ms.into());
+                send_srcp_reply(reply);
+                if let Some(accessory_command) = accessory_command
+                    && let Some(mapped) =
+                        dcc_mapping.aspect_for(accessory_command, &config.dcc_aspects, now)
+                {
+                    // the speed of the locomotive comes with every aspect that can show it.
+                    let speed = mapped.speed.or(srcp_session.speed()).filter(|speed| {
+                        BoardAspect::try_from(mapped.aspect)
+                            .is_ok_and(|aspect| signal.supports_speed(aspect, *speed))
+                    });
+                    received_command = Some((
+                        CommandSource::Srcp,
+                        0,
+                        Command::Aspect(mapped.aspect, speed, None),
+                    ));
+                }
+            }
+        }
+
+        let maybe_position_of_newline = serial_buffer
+            .iter()
+            .enumerate()
+            .find(|(_, x)| **x == b'\n')
+            .filter(|_| received_command.is_none());
+        if let Some((position_of_newline, _)) = maybe_position_of_newline {
+            let line_received_at = clock::millis();
+            let (line, _) = serial_buffer.split_at(position_of_newline + 1);
+            let (line, authentication) = auth::split_authentication(line);
+            let sequence_number = commands::sequence_number(line);
+            interrupt::free(|cs| REPLY_SEQUENCE_NUMBER.borrow(cs).set(sequence_number));
+
+            let mut result = get_next_command(
+                line,
+                SIGNAL_ID,
+                LAYOUT_SEGMENT,
+                config.require_checksum,
+                config.strict_parsing,
+            );
+            // a line that isn’t for the signal may be for another signal on the board.
+            let mut group = 0;
+            while matches!(result, Err(CommandError::Ignored)) && group + 1 < SIGNAL_GROUPS {
+                group += 1;
+                result = get_next_command(
+                    line,
+                    groups[group].id,
+                    LAYOUT_SEGMENT,
+                    config.require_checksum,
+                    config.strict_parsing,
+                );
+            }
+            #[cfg(feature = "mega")]
+            if HAS_GATEWAY && gateway::is_forwarded(&result) {
+                forward_downstream(&serial_buffer[..=position_of_newline]);
+            }
+            // only delay replies to commands that are meant for us, and not to broadcasts that aren’t answered.
+            if !matches!(
+                result,
+                Err(CommandError::Ignored) | Ok(Command::FastClock(..))
+            ) {
+                clock::wait_since(line_received_at, config.reply_delay_ms.into());
+            }
+            let signal_id = groups[group].id;
+            match result {
+                Ok(command) => {
+                    if baud_rate_fallback.take().is_some() {
+                        platform.write_persistent(BAUD_RATE_FALLBACK_EEPROM_OFFSET, &[0xff]);
+                    }
+                    let is_authenticated = authentication
+                        .is_some_and(|authentication| authenticator.verify(line, &authentication));
+                    if is_authenticated {
+                        platform.write_persistent(
+                            AUTHENTICATION_COUNTER_EEPROM_OFFSET,
+                            &authenticator.last_counter().to_le_bytes(),
+                        );
+                    }
+                    // a retransmitted command was already executed, only its response got lost.
+                    if let Some(sequence_number) = sequence_number
+                        && command.changes_state()
+                        && retransmit_window.is_retransmit(sequence_number, line)
+                    {
+                        log!(Protocol, Info, "{}:DUP", signal_id);
+                    } else if REQUIRES_AUTHENTICATION
+                        && command.changes_state()
+                        && !is_authenticated
+                    {
+                        log!(
+                            Protocol,
+                            Error,
+                            "{}",
+                            CommandError::AuthenticationFailed.response(signal_id)
+                        );
+                    } else {
+                        received_command = Some((CommandSource::Serial, group, command));
+                    }
+                }
+                Err(CommandError::Ignored) => {}
+                Err(error) => log!(Protocol, Error, "{}", error.response(signal_id)),
+            }
+
+            serial_buffer.drain(0..=position_of_newline);
+        }
+        // only the responses to the line get its sequence number.
+        if received_command.is_none() {
+            interrupt::free(|cs| REPLY_SEQUENCE_NUMBER.borrow(cs).set(None));
+        }
+        if received_command.is_none()
+            && let Some(mapped) = framed_aspect.take()
+        {
+            // ASPECT frames have no room for the authentication.
+            if REQUIRES_AUTHENTICATION {
+                log!(
+                    Protocol,
+                    Error,
+                    "{}",
+                    CommandError::AuthenticationFailed.response(SIGNAL_ID)
+                );
+            } else {
+                received_command = Some((
+                    CommandSource::Serial,
+                    0,
+                    Command::Aspect(mapped.aspect, mapped.speed, None),
+                ));
+            }
+        }
+
+        // frames stay in the MCP2515 while a command is pending, so that no event is lost.
+        if let Some((controller, node)) = &mut openlcb {
+            while received_command.is_none()
+                && let Some(frame) = controller.receive().unwrap_infallible()
+            {
+                if let Some(aspect) = node.receive(&frame) {
+                    received_command = Some((
+                        CommandSource::OpenLcb,
+                        0,
+                        Command::Aspect(aspect, None, None),
+                    ));
+                }
+            }
+        }
+
+        // the next request is handled in the next iteration if a command was received, so that no transmission is lost.
+        while received_command.is_none() && !cmri_requests.is_empty() {
+            match cmri_requests.remove(0) {
+                CmriRequest::Initialize => {}
+                CmriRequest::Poll => {
+                    let inputs =
+                        AspectCommand::from_command_id(groups[0].aspect.command_id().as_bytes())
+                            .map_or([0; cmri::INPUT_BYTES], |aspect| {
+                                cmri_mapping.inputs_for(aspect)
+                            });
+                    send_packet(&cmri::reception(config.cmri_node_address, inputs));
+                }
+                CmriRequest::Transmit(outputs) => {
+                    if let Some(aspect) = cmri_mapping.aspect_for(outputs) {
+                        received_command =
+                            Some((CommandSource::Cmri, 0, Command::Aspect(aspect, None, None)));
+                    }
+                }
+            }
+        }
+
+        // the host gets the aspect that its last request led to before the next request is taken.
+        if let Some(node) = &mut bidib {
+            node.report_aspect(AspectCommand::from_command_id(
+                groups[0].aspect.command_id().as_bytes(),
+            ));
+            if received_command.is_none()
+                && let Some(aspect) = node.take_request()
+            {
+                received_command =
+                    Some((CommandSource::Bidib, 0, Command::Aspect(aspect, None, None)));
+            }
+            while let Some(packet) = node.next_packet() {
+                send_packet(&packet);
+            }
+        }
+
+        // the lines of the next controller of the daisy chain are sent back between the lines of this controller.
+        #[cfg(feature = "mega")]
+        if HAS_GATEWAY {
+            // this is the only place that pops bytes.
+            while let Some(byte) = unsafe { GATEWAY_RECEIVED.pop() } {
+                if let Some(line) = return_lines.receive_byte(byte) {
+                    forward_upstream(line);
+                }
+            }
+        }
+
+        // the broker gets the state that its last message led to before the next message is taken.
+        #[cfg(feature = "mega")]
+        if let Some(client) = &mut mqtt {
+            client.report_state(groups[0].aspect.command_id());
+            let received =
+                interrupt::free(|cs| core::mem::take(&mut *MQTT_RECEIVED.borrow(cs).borrow_mut()));
+            for byte in received {
+                client.receive_byte(byte);
+            }
+            if received_command.is_none() {
+                match client.take_event() {
+                    Some(MqttEvent::Command(aspect)) => {
+                        received_command =
+                            Some((CommandSource::Mqtt, 0, Command::Aspect(aspect, None, None)));
+                    }
+                    // without the layout software, nobody clears the signal once the train passed.
+                    Some(MqttEvent::BrokerLost) => {
+                        log!(Protocol, Error, "{}:FAULT:MQTT", SIGNAL_ID);
+                        received_command = Some((
+                            CommandSource::Mqtt,
+                            0,
+                            Command::Aspect(AspectCommand::Zero, None, None),
+                        ));
+                    }
+                    None => {}
+                }
+            }
+            if let Some(command) = client.next_command(now) {
+                let mut esp = EspSerial;
+                ufmt::uwrite!(esp, "{}", command).unwrap_infallible();
+            }
+        }
+
+        // a pending key press is picked up in the next iteration if a serial command was received.
+        if received_command.is_none()
+            && let Some(keypad) = &mut keypad
+            && let Some(key) = keypad.scan(now).unwrap_infallible()
+            && let Some(Some(aspect)) = PANEL_BUTTON_ASPECTS.get(key)
+        {
+            received_command = Some((
+                CommandSource::Panel,
+                0,
+                Command::Aspect(*aspect, None, None),
+            ));
+        }
+        // the last byte of a LocoNet message only ends when the bus stays idle.
+        if HAS_LOCONET {
+            let now_us = clock::micros();
+            interrupt::free(|cs| {
+                let (receiver, last_edge, is_high) = &mut *LOCONET_RECEIVER.borrow(cs).borrow_mut();
+                if *is_high
+                    && let Some(command) = receiver.receive_idle(now_us.wrapping_sub(*last_edge))
+                {
+                    ACCESSORY_COMMAND.borrow(cs).set(Some(command));
+                }
+            });
+        }
+        if received_command.is_none()
+            && let Some(accessory_command) =
+                interrupt::free(|cs| ACCESSORY_COMMAND.borrow(cs).take())
+        {
+            // LocoNet and XpressNet requests use the DCC addresses, and MM has no extended accessory commands, so the aspect numbers only apply to DCC.
+            let (source, mapping) = if HAS_LOCONET {
+                (CommandSource::LocoNet, &mut dcc_mapping)
+            } else if HAS_XPRESSNET {
+                (CommandSource::XpressNet, &mut dcc_mapping)
+            } else if config.motorola_accessories {
+                (CommandSource::Motorola, &mut motorola_mapping)
+            } else {
+                (CommandSource::Dcc, &mut dcc_mapping)
+            };
+            if let Some(mapped) = mapping.aspect_for(accessory_command, &config.dcc_aspects, now) {
+                received_command = Some((
+                    source,
+                    0,
+                    Command::Aspect(mapped.aspect, mapped.speed, None),
+                ));
+            }
+        }
+        if received_command.is_none()
+            && let Some((channel, value)) = interrupt::free(|cs| SELECTRIX_VALUE.borrow(cs).take())
+            && let Some(aspect) = selectrix_mapping.aspect_for(channel, value)
+        {
+            received_command = Some((
+                CommandSource::Selectrix,
+                0,
+                Command::Aspect(aspect, None, None),
+            ));
+        }
+        #[cfg(feature = "mega")]
+        if received_command.is_none()
+            && let Some(values) = interrupt::free(|cs| DMX_VALUES.borrow(cs).take())
+            && let Some((aspect, speed)) = dmx_mapping.command_for(values)
+        {
+            // the speed fader may stay up while the signal shows an aspect without a speed, which must not reject the aspect.
+            let speed = speed.filter(|speed| {
+                BoardAspect::try_from(aspect)
+                    .is_ok_and(|aspect| signal.supports_speed(aspect, *speed))
+            });
+            received_command = Some((CommandSource::Dmx, 0, Command::Aspect(aspect, speed, None)));
+        }
+
+        // aspect commands of the signal wait until the shown aspect was shown for the minimum dwell time, and stops overtake them, since they were meant for the situation before the stop.
+        aspect_queue.observe(groups[0].aspect, now);
+        for group in &mut groups {
+            group.stop_hold.observe(group.aspect, now);
+        }
+        let min_dwell_ms = config.min_dwell_ms.into();
+        match received_command {
+            Some(
+                (_, 0, Command::Aspect(AspectCommand::Zero, ..)) | (_, _, Command::EmergencyStop),
+            ) => {
+                aspect_queue.clear();
+            }
+            Some((source, 0, Command::Aspect(command, speed, route)))
+                if aspect_queue.must_wait(min_dwell_ms, now) =>
+            {
+                received_command = None;
+                if aspect_queue.push((source, command, speed, route)).is_err() {
+                    log!(
+                        Protocol,
+                        Error,
+                        "{}",
+                        CommandError::Busy.response(SIGNAL_ID)
+                    );
+                }
+                // the acknowledgement is sent once the command is executed, and can’t be told apart from the responses to later lines.
+                interrupt::free(|cs| REPLY_SEQUENCE_NUMBER.borrow(cs).set(None));
+            }
+            _ => {}
+        }
+        if received_command.is_none()
+            && let Some((source, command, speed, route)) = aspect_queue.pop_due(min_dwell_ms, now)
+        {
+            received_command = Some((source, 0, Command::Aspect(command, speed, route)));
+        }
+
+        // all command sources end up here, so that every command is handled the same way, whichever signal it is for.
+        if let Some((source, group, command)) = received_command {
+            let signal_id = groups[group].id;
+            // the second signal only shows simple aspects.
+            let can_show =
+                |aspect: &BoardAspect, speed: Option<u8>, route: Option<u8>| match &second_signal {
+                    Some(second_signal) if group == 1 => {
+                        speed.is_none() && route.is_none() && second_signal.supports_aspect(*aspect)
+                    }
+                    _ => {
+                        signal.supports_aspect(*aspect)
+                            && head_can_show(*aspect, speed)
+                            && speed.map_or(true, |speed| signal.supports_speed(*aspect, speed))
+                            && route.map_or(true, |route| signal.supports_route(*aspect, route))
+                    }
+                };
+            match command {
+                Command::Aspect(command, speed, route) => {
+                    let next_aspect = BoardAspect::try_from(command)
+                        .ok()
+                        .filter(|aspect| can_show(aspect, speed, route));
+                    match groups[group].check_aspect(
+                        next_aspect,
+                        emergency_stopped,
+                        maintenance_locked,
+                        &config,
+                        now,
+                    ) {
+                        Err(error) => log!(Protocol, Error, "{}", error.response(signal_id)),
+                        Ok(next_aspect) if group != 0 => {
+                            groups[group].save_aspect(&mut platform, next_aspect);
+                            if let Some(second_signal) = &mut second_signal {
+                                second_signal
+                                    .switch_to_aspect(next_aspect, &mut Delay::new())
+                                    .unwrap_infallible();
+                            }
+                            groups[group].aspect = next_aspect;
+                            let sequence =
+                                record_in_journal(&mut journal, &mut platform, source, next_aspect);
+                            log!(
+                                Protocol,
+                                Info,
+                                "{}:A:{}:{}:{}",
+                                signal_id,
+                                next_aspect.command_id(),
+                                state_checksum(next_aspect, None, None),
+                                sequence
+                            );
+                        }
+                        Ok(requested_aspect) => {
+                            let Arbitration {
+                                aspect: next_aspect,
+                                conflict,
+                            } = arbiter.request(source, requested_aspect, now);
+                            // the speed and route only belong to the requested aspect.
+                            let speed = speed.filter(|_| next_aspect == requested_aspect);
+                            let route = route.filter(|_| next_aspect == requested_aspect);
+                            if let Some(conflict) = conflict {
+                                log!(
+                                    Protocol,
+                                    Warn,
+                                    "{}:CONFLICT:{}:{}:{}:{}",
+                                    SIGNAL_ID,
+                                    conflict.source.command_id(),
+                                    conflict.aspect.command_id(),
+                                    conflict.other_source.command_id(),
+                                    conflict.other_aspect.command_id()
+                                );
+                            }
+                            end_lamp_aging(&mut lamp_aging, &mut signal);
+                            // temporary aspects are not saved, so that the signal shows stop after a reboot.
+                            let saved_aspect = if next_aspect.is_temporary() {
+                                BoardAspect::STOP
+                            } else {
+                                next_aspect
+                            };
+                            groups[0].save_aspect(&mut platform, saved_aspect);
+                            // the red lamp must be confirmed before it is switched off, and again afterwards.
+                            let red_lamp_was_confirmed = !HAS_RED_LAMP_VOTING
+                                || next_aspect == BoardAspect::STOP
+                                || red_lamp_agrees(&mut signal);
+                            if red_lamp_was_confirmed {
+                                match (speed, route) {
+                                    (_, Some(route)) => signal.switch_to_aspect_with_route(
+                                        next_aspect,
+                                        speed,
+                                        route,
+                                        &mut Delay::new(),
+                                    ),
+                                    (Some(speed), None) => signal.switch_to_aspect_with_speed(
+                                        next_aspect,
+                                        speed,
+                                        &mut Delay::new(),
+                                    ),
+                                    (None, None) => {
+                                        signal.switch_to_aspect(next_aspect, &mut Delay::new())
+                                    }
+                                }
+                                .unwrap_infallible();
+                            }
+                            if HAS_RED_LAMP_VOTING
+                                && !(red_lamp_was_confirmed && red_lamp_agrees(&mut signal))
+                            {
+                                let error;
+                                (groups[0].aspect, error) = fall_back_to_stop(&mut signal);
+                                (current_speed, current_route) = (None, None);
+                                last_switch_error = Some(error);
+                                state_mirror.update(PresentationState {
+                                    aspect: groups[0].aspect,
+                                    speed: current_speed,
+                                    route: current_route,
+                                });
+                                temporary_aspect_since = None;
+                                groups[0].save_aspect(&mut platform, BoardAspect::STOP);
+                                log!(Protocol, Error, "{}", error.response(SIGNAL_ID));
+                            } else {
+                                groups[0].aspect = next_aspect;
+                                (current_speed, current_route) = (speed, route);
+                                last_switch_error = None;
+                                state_mirror.update(PresentationState {
+                                    aspect: groups[0].aspect,
+                                    speed: current_speed,
+                                    route: current_route,
+                                });
+                                temporary_aspect_since = next_aspect.is_temporary().then_some(now);
+                                let checksum =
+                                    state_checksum(groups[0].aspect, current_speed, current_route);
+                                let sequence = record_in_journal(
+                                    &mut journal,
+                                    &mut platform,
+                                    source,
+                                    groups[0].aspect,
+                                );
+                                if let Some(speed) = speed
+                                    && let Some(route) = route
+                                {
+                                    log!(
+                                        Protocol,
+                                        Info,
+                                        "{}:A:{}:{}:{}:{}:{}",
+                                        SIGNAL_ID,
+                                        next_aspect.command_id(),
+                                        speed,
+                                        char::from(route),
+                                        checksum,
+                                        sequence
+                                    );
+                                } else if let Some(speed) = speed {
+                                    log!(
+                                        Protocol,
+                                        Info,
+                                        "{}:A:{}:{}:{}:{}",
+                                        SIGNAL_ID,
+                                        next_aspect.command_id(),
+                                        speed,
+                                        checksum,
+                                        sequence
+                                    );
+                                } else if let Some(route) = route {
+                                    log!(
+                                        Protocol,
+                                        Info,
+                                        "{}:A:{}:{}:{}:{}",
+                                        SIGNAL_ID,
+                                        next_aspect.command_id(),
+                                        char::from(route),
+                                        checksum,
+                                        sequence
+                                    );
+                                } else {
+                                    log!(
+                                        Protocol,
+                                        Info,
+                                        "{}:A:{}:{}:{}",
+                                        SIGNAL_ID,
+                                        next_aspect.command_id(),
+                                        checksum,
+                                        sequence
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+                Command::Arm(command) => {
+                    let armed_aspect = BoardAspect::try_from(command)
+                        .ok()
+                        .filter(|aspect| can_show(aspect, None, None));
+                    if let Some(armed_aspect) = armed_aspect {
+                        groups[group].arming.arm(armed_aspect, now);
+                        let aspect = groups[group].aspect;
+                        let sequence =
+                            record_in_journal(&mut journal, &mut platform, source, aspect);
+                        // only the signal shows a speed and a route.
+                        let checksum = if group == 0 {
+                            state_checksum(aspect, current_speed, current_route)
+                        } else {
+                            state_checksum(aspect, None, None)
+                        };
+                        log!(
+                            Protocol,
+                            Info,
+                            "{}:A:ARM:{}:{}:{}",
+                            signal_id,
+                            armed_aspect.command_id(),
+                            checksum,
+                            sequence
+                        );
+                    } else {
+                        log!(
+                            Protocol,
+                            Error,
+                            "{}",
+                            CommandError::Unsupported.response(signal_id)
+                        );
+                    }
+                }
+                // everything else belongs to the board, which is addressed with the first signal ID, and broadcasts are parsed with it already.
+                _ if group != 0 => {
+                    log!(
+                        Protocol,
+                        Error,
+                        "{}",
+                        CommandError::Unsupported.response(signal_id)
+                    );
+                }
+                // the lamps are under manual control while the maintenance lock is engaged. Hints are not answered, since they are sent by other signals.
+                Command::NextAspect(command) => {
+                    if !maintenance_locked && let Ok(next_aspect) = BoardAspect::try_from(command) {
+                        signal
+                            .show_next_aspect(next_aspect, &mut Delay::new())
+                            .unwrap_infallible();
+                    }
+                }
+                Command::MemoryReport => {
+                    let (interrupt_buffer_high_water, interrupt_buffer_overruns) =
+                        interrupt::free(|cs| {
+                            (
+                                SERIAL_BUFFER_HIGH_WATER.borrow(cs).get(),
+                                SERIAL_BUFFER_OVERRUNS.borrow(cs).get(),
+                            )
+                        });
+                    log!(
+                        Protocol,
+                        Info,
+                        "{}:MEM:{}:{}/{}:{}/{}:{}:{}",
+                        SIGNAL_ID,
+                        memory::free_stack_bytes(),
+                        interrupt_buffer_high_water.get(),
+                        SERIAL_BUFFER_SIZE,
+                        serial_buffer_high_water.get(),
+                        LINE_BUFFER_SIZE,
+                        interrupt_buffer_overruns,
+                        serial_buffer_overruns
+                    );
+                }
+                Command::TemperatureReport if !HAS_TEMPERATURE_SENSOR => {
+                    log!(
+                        Protocol,
+                        Error,
+                        "{}",
+                        CommandError::Unsupported.response(SIGNAL_ID)
+                    );
+                }
+                Command::TemperatureReport => {
+                    let heater_state = match &heater {
+                        Some(heater) if heater.is_on() => "1",
+                        Some(_) => "0",
+                        None => "-",
+                    };
+                    log!(
+                        Protocol,
+                        Info,
+                        "{}:TEMP:{}:{}",
+                        SIGNAL_ID,
+                        aux_inputs.temperature_celsius,
+                        heater_state
+                    );
+                }
+                Command::JournalReport => {
+                    for entry in journal.entries() {
+                        log!(
+                            Protocol,
+                            Info,
+                            "{}:HIST:{}:{}:{}",
+                            SIGNAL_ID,
+                            entry.sequence,
+                            entry.source.command_id(),
+                            entry.aspect.command_id()
+                        );
+                    }
+                    log!(Protocol, Info, "{}:HIST:END", SIGNAL_ID);
+                }
+                Command::HealthReport => match last_lamp_test {
+                    Some(tested_at) => log!(
+                        Protocol,
+                        Info,
+                        "{}:HEALTH:{}:{}:{}",
+                        SIGNAL_ID,
+                        lamp_test_log.tests,
+                        lamp_test_log.failed_lamps,
+                        now.wrapping_sub(tested_at) / 60_000
+                    ),
+                    None => log!(
+                        Protocol,
+                        Info,
+                        "{}:HEALTH:{}:{}:-",
+                        SIGNAL_ID,
+                        lamp_test_log.tests,
+                        lamp_test_log.failed_lamps
+                    ),
+                },
+                Command::StateReport => {
+                    let saved_aspect = groups[0]
+                        .saved_aspect()
+                        .map_or("-", |aspect| aspect.command_id());
+                    match last_switch_error {
+                        Some(error) => log!(
+                            Protocol,
+                            Info,
+                            "{}:Q:{}:{}:{}",
+                            SIGNAL_ID,
+                            groups[0].aspect.command_id(),
+                            saved_aspect,
+                            error.code()
+                        ),
+                        None => log!(
+                            Protocol,
+                            Info,
+                            "{}:Q:{}:{}:A",
+                            SIGNAL_ID,
+                            groups[0].aspect.command_id(),
+                            saved_aspect
+                        ),
+                    }
+                }
+                Command::CapabilityReport if !is_logged(Channel::Protocol, Severity::Info) => {}
+                Command::CapabilityReport => with_serial(|serial| {
+                    ufmt::uwrite!(serial, "{}:?:{}:", SIGNAL_ID, BoardAspect::SYSTEM_ID)
+                        .unwrap_infallible();
+                    let supported_aspects = maintenance::PROOF_ASPECTS
+                        .into_iter()
+                        .filter_map(|command| BoardAspect::try_from(command).ok())
+                        .filter(|aspect| signal.supports_aspect(*aspect));
+                    for (index, aspect) in supported_aspects.clone().enumerate() {
+                        let separator = if index == 0 { "" } else { "," };
+                        ufmt::uwrite!(serial, "{}{}", separator, aspect.command_id())
+                            .unwrap_infallible();
+                    }
+                    let has_speed_indicator = supported_aspects
+                        .clone()
+                        .any(|aspect| (1..=9).any(|speed| signal.supports_speed(aspect, speed)));
+                    let indicators = [
+                        ("ZS3", has_speed_indicator),
+                        ("KL", signal.lamp(LampRole::MainNotice).is_some()),
+                        ("REP", signal.lamp(LampRole::RepeaterNotice).is_some()),
+                    ];
+                    let mut indicators = indicators.iter().filter(|(_, present)| *present);
+                    match indicators.next() {
+                        Some((name, _)) => ufmt::uwrite!(serial, ":{}", name).unwrap_infallible(),
+                        None => ufmt::uwrite!(serial, ":-").unwrap_infallible(),
+                    }
+                    for (name, _) in indicators {
+                        ufmt::uwrite!(serial, ",{}", name).unwrap_infallible();
+                    }
+                    ufmt::uwriteln!(serial, ":{}", env!("CARGO_PKG_VERSION")).unwrap_infallible();
+                }),
+                Command::VersionReport if !is_logged(Channel::Protocol, Severity::Info) => {}
+                Command::VersionReport => with_serial(|serial| {
+                    ufmt::uwrite!(serial, "{}:VER:{}:", SIGNAL_ID, env!("CARGO_PKG_VERSION"))
+                        .unwrap_infallible();
+                    let mut features = FEATURES.iter().filter(|(_, enabled)| *enabled);
+                    match features.next() {
+                        Some((name, _)) => ufmt::uwrite!(serial, "{}", name).unwrap_infallible(),
+                        None => ufmt::uwrite!(serial, "-").unwrap_infallible(),
+                    }
+                    for (name, _) in features {
+                        ufmt::uwrite!(serial, ",{}", name).unwrap_infallible();
+                    }
+                    ufmt::uwriteln!(serial, "").unwrap_infallible();
+                }),
+                // the level crossing is independent of the signal, so the maintenance lock doesn’t apply.
+                Command::LevelCrossing(command) => {
+                    if let Some(level_crossing) = &mut level_crossing {
+                        level_crossing.command(command);
+                        let sequence = record_in_journal(
+                            &mut journal,
+                            &mut platform,
+                            source,
+                            groups[0].aspect,
+                        );
+                        log!(
+                            Protocol,
+                            Info,
+                            "{}:A:BX:{}:{}:{}",
+                            SIGNAL_ID,
+                            command.command_id(),
+                            state_checksum(groups[0].aspect, current_speed, current_route),
+                            sequence
+                        );
+                    } else {
+                        log!(
+                            Protocol,
+                            Error,
+                            "{}",
+                            CommandError::Unsupported.response(SIGNAL_ID)
+                        );
+                    }
+                }
+                // the emergency stop is a broadcast, so it isn't answered. It bypasses the maintenance lock, the arming and the arbitration.
+                Command::EmergencyStop => {
+                    emergency_stopped = true;
+                    platform.write_persistent(EMERGENCY_STOP_EEPROM_OFFSET, &[1]);
+                    raw_lamp_control.end();
+                    end_lamp_aging(&mut lamp_aging, &mut signal);
+                    // this also confirms the red lamp, if it is voted.
+                    let error;
+                    (groups[0].aspect, error) = fall_back_to_stop(&mut signal);
+                    (current_speed, current_route) = (None, None);
+                    last_switch_error = (error != CommandError::FellBackToStop).then_some(error);
+                    state_mirror.update(PresentationState {
+                        aspect: groups[0].aspect,
+                        speed: current_speed,
+                        route: current_route,
+                    });
+                    temporary_aspect_since = None;
+                    groups[0].save_aspect(&mut platform, BoardAspect::STOP);
+                    if let Some(second_signal) = &mut second_signal
+                        && let Some(group) = groups.get_mut(1)
+                    {
+                        second_signal
+                            .switch_to_aspect(BoardAspect::STOP, &mut Delay::new())
+                            .unwrap_infallible();
+                        group.aspect = BoardAspect::STOP;
+                        group.save_aspect(&mut platform, BoardAspect::STOP);
+                    }
+                    record_in_journal(&mut journal, &mut platform, source, groups[0].aspect);
+                }
+                Command::EmergencyRelease => {
+                    emergency_stopped = false;
+                    platform.write_persistent(EMERGENCY_STOP_EEPROM_OFFSET, &[0]);
+                    let sequence =
+                        record_in_journal(&mut journal, &mut platform, source, groups[0].aspect);
+                    log!(
+                        Protocol,
+                        Info,
+                        "{}:A:RELEASE:{}:{}",
+                        SIGNAL_ID,
+                        state_checksum(groups[0].aspect, current_speed, current_route),
+                        sequence
+                    );
+                }
+                Command::Lock => {
+                    maintenance_locked = true;
+                    let sequence =
+                        record_in_journal(&mut journal, &mut platform, source, groups[0].aspect);
+                    log!(
+                        Protocol,
+                        Info,
+                        "{}:A:LOCK:{}:{}",
+                        SIGNAL_ID,
+                        state_checksum(groups[0].aspect, current_speed, current_route),
+                        sequence
+                    );
+                }
+                Command::Unlock => {
+                    maintenance_locked = false;
+                    if raw_lamp_control.is_active() {
+                        raw_lamp_control.end();
+                        signal
+                            .switch_to_aspect(groups[0].aspect, &mut Delay::new())
+                            .unwrap_infallible();
+                        (current_speed, current_route) = (None, None);
+                    }
+                    let sequence =
+                        record_in_journal(&mut journal, &mut platform, source, groups[0].aspect);
+                    log!(
+                        Protocol,
+                        Info,
+                        "{}:A:UNLOCK:{}:{}",
+                        SIGNAL_ID,
+                        state_checksum(groups[0].aspect, current_speed, current_route),
+                        sequence
+                    );
+                }
+                Command::RawLamp(role, state) => {
+                    if !maintenance_locked {
+                        log!(
+                            Protocol,
+                            Error,
+                            "{}",
+                            CommandError::Locked.response(SIGNAL_ID)
+                        );
+                    } else if emergency_stopped {
+                        log!(
+                            Protocol,
+                            Error,
+                            "{}",
+                            CommandError::EmergencyStop.response(SIGNAL_ID)
+                        );
+                    } else if let Some(lamp) = signal.lamp(role) {
+                        let is_on = raw_lamp_control.set_lamp(role, state, clock::millis());
+                        lamp.set_state(is_on.into()).unwrap_infallible();
+                        let sequence = record_in_journal(
+                            &mut journal,
+                            &mut platform,
+                            source,
+                            groups[0].aspect,
+                        );
+                        log!(
+                            Protocol,
+                            Info,
+                            "{}:A:RAW:{}:{}",
+                            SIGNAL_ID,
+                            state_checksum(groups[0].aspect, current_speed, current_route),
+                            sequence
+                        );
+                    } else {
+                        log!(
+                            Protocol,
+                            Error,
+                            "{}",
+                            CommandError::Unsupported.response(SIGNAL_ID)
+                        );
+                    }
+                }
+                Command::StressTest(count) => {
+                    if !maintenance_locked {
+                        log!(
+                            Protocol,
+                            Error,
+                            "{}",
+                            CommandError::Locked.response(SIGNAL_ID)
+                        );
+                    } else if emergency_stopped {
+                        log!(
+                            Protocol,
+                            Error,
+                            "{}",
+                            CommandError::EmergencyStop.response(SIGNAL_ID)
+                        );
+                    } else {
+                        raw_lamp_control.end();
+                        end_lamp_aging(&mut lamp_aging, &mut signal);
+                        let aspects: ArrayVec<
+                            BoardAspect,
+                            { maintenance::STRESS_TEST_ASPECTS.len() },
+                        > = maintenance::STRESS_TEST_ASPECTS
+                            .into_iter()
+                            .filter_map(|command| BoardAspect::try_from(command).ok())
+                            .filter(|aspect| {
+                                signal.supports_aspect(*aspect) && head_can_show(*aspect, None)
+                            })
+                            .collect();
+                        let mut timings = SwitchTimings::default();
+                        let mut saved_aspect_writes: u16 = 0;
+                        // every aspect is deferred, even stop, since the signal switches back afterwards, so the EEPROM is only written when a single change takes longer than the settling time.
+                        for aspect in aspects.iter().cycle().take(count.into()) {
+                            let start = clock::millis();
+                            if let Some(code) = saved_aspect_code(*aspect) {
+                                groups[0].saved_aspect.defer(code, start);
+                            }
+                            signal
+                                .switch_to_aspect(*aspect, &mut Delay::new())
+                                .unwrap_infallible();
+                            let end = clock::millis();
+                            timings.record(end.wrapping_sub(start));
+                            saved_aspect_writes += u16::from(groups[0].saved_aspect.write_settled(
+                                &mut platform,
+                                SAVED_ASPECT_SETTLE_MS,
+                                end,
+                            ));
+                            platform.feed_watchdog();
+                        }
+                        signal
+                            .switch_to_aspect(groups[0].aspect, &mut Delay::new())
+                            .unwrap_infallible();
+                        // temporary aspects are not saved, as on any other switch.
+                        let saved_aspect = if groups[0].aspect.is_temporary() {
+                            BoardAspect::STOP
+                        } else {
+                            groups[0].aspect
+                        };
+                        groups[0].save_aspect(&mut platform, saved_aspect);
+                        (current_speed, current_route) = (None, None);
+                        log!(
+                            Protocol,
+                            Info,
+                            "{}:STRESS:{}:{}/{}/{}:{}",
+                            SIGNAL_ID,
+                            timings.count,
+                            timings.min_ms,
+                            timings.average_ms(),
+                            timings.max_ms,
+                            saved_aspect_writes
+                        );
+                    }
+                }
+                Command::SafeStateProof => {
+                    if !maintenance_locked {
+                        log!(
+                            Protocol,
+                            Error,
+                            "{}",
+                            CommandError::Locked.response(SIGNAL_ID)
+                        );
+                    } else if emergency_stopped {
+                        log!(
+                            Protocol,
+                            Error,
+                            "{}",
+                            CommandError::EmergencyStop.response(SIGNAL_ID)
+                        );
+                    } else {
+                        raw_lamp_control.end();
+                        end_lamp_aging(&mut lamp_aging, &mut signal);
+                        let aspects: ArrayVec<BoardAspect, { maintenance::PROOF_ASPECTS.len() }> =
+                            maintenance::PROOF_ASPECTS
+                                .into_iter()
+                                .filter_map(|command| BoardAspect::try_from(command).ok())
+                                .filter(|aspect| {
+                                    signal.supports_aspect(*aspect) && head_can_show(*aspect, None)
+                                })
+                                .collect();
+                        // blinking lamps are lit right after switching, so each step shows the lamps that the aspect lights.
+                        for aspect in aspects {
+                            signal
+                                .switch_to_aspect(aspect, &mut Delay::new())
+                                .unwrap_infallible();
+                            let mut lit_lamps = LampSet::default();
+                            for role in LampRole::ALL {
+                                if let Some(lamp) = signal.lamp(role)
+                                    && lamp.is_set_high().unwrap_infallible()
+                                {
+                                    lit_lamps.insert(role);
+                                }
+                            }
+                            let red_lamp_voting =
+                                match (HAS_RED_LAMP_VOTING, red_lamp_agrees(&mut signal)) {
+                                    (false, _) => "-",
+                                    (true, true) => "1",
+                                    (true, false) => "0",
+                                };
+                            log!(
+                                Protocol,
+                                Info,
+                                "{}:PROOF:{}:{}:{}",
+                                SIGNAL_ID,
+                                aspect.command_id(),
+                                lit_lamps,
+                                red_lamp_voting
+                            );
+                            platform.feed_watchdog();
+                        }
+                        signal
+                            .switch_to_aspect(groups[0].aspect, &mut Delay::new())
+                            .unwrap_infallible();
+                        (current_speed, current_route) = (None, None);
+                        log!(Protocol, Info, "{}:PROOF:END", SIGNAL_ID);
+                    }
+                }
+                Command::CloneConfig(target) => {
+                    if !maintenance_locked {
+                        log!(
+                            Protocol,
+                            Error,
+                            "{}",
+                            CommandError::Locked.response(SIGNAL_ID)
+                        );
+                    } else {
+                        for key in ConfigKey::all() {
+                            let sent_at = clock::millis();
+                            serial_writeln!("{}:CFG:{}:{}", target.as_str(), key, config.get(key));
+                            // the target's replies are not meant for this controller, and would overflow the receive buffer while it waits.
+                            while clock::millis().wrapping_sub(sent_at) < CLONE_LINE_INTERVAL_MS {
+                                platform.sleep();
+                                platform.receive(|_| {});
+                            }
+                            platform.feed_watchdog();
+                        }
+                        log!(Protocol, Info, "{}:CLONE:END", SIGNAL_ID);
+                    }
+                }
+                // the fast clock is not answered, since the replies of all signals would collide.
+                Command::FastClock(ratio, minutes) => {
+                    fast_clock = Some(FastClock::new(ratio, minutes, now));
+                }
+                // the answers of the other controllers are not meant for this controller, and would overflow the receive buffer while it waits for its slot.
+                Command::Discovery => {
+                    let waiting_since = clock::millis();
+                    while clock::millis().wrapping_sub(waiting_since)
+                        < commands::discovery_delay_ms(SIGNAL_ID)
+                    {
+                        platform.sleep();
+                        platform.receive(|_| {});
+                    }
+                    for group in &groups {
+                        serial_writeln!("{}:PONG", group.id);
+                    }
+                }
+                Command::Config(key, None) => {
+                    log!(
+                        Protocol,
+                        Info,
+                        "{}:CFG:{}:{}",
+                        SIGNAL_ID,
+                        key,
+                        config.get(key)
+                    );
+                }
+                Command::Config(key, Some(value)) => {
+                    #[cfg(not(feature = "semaphore"))]
+                    let old_config = config;
+                    if config.set(key, value).is_ok() {
+                        config.save(&mut platform);
+                        // the baud rate that the command was received at works, so a new one falls back to it.
+                        if key == ConfigKey::BaudRate {
+                            let fallback = if config.baud_rate == serial_baud_rate {
+                                0xff
+                            } else {
+                                serial_baud_rate as u8
+                            };
+                            platform
+                                .write_persistent(BAUD_RATE_FALLBACK_EEPROM_OFFSET, &[fallback]);
+                        }
+                        interrupt::free(|cs| {
+                            LOG_FILTER.borrow(cs).set(config.log_filter);
+                            USES_MOTOROLA.borrow(cs).set(config.motorola_accessories);
+                            SELECTRIX_CHANNEL.borrow(cs).set(config.selectrix_channel);
+                            DMX_CHANNEL.borrow(cs).set(config.dmx_channel);
+                            SENDS_PACKETS
+                                .borrow(cs)
+                                .set(config.cmri || bidib.is_some() || config.srcp);
+                            USES_FRAMES.borrow(cs).set(config.binary);
+                            APPENDS_CHECKSUMS.borrow(cs).set(config.require_checksum);
+                        });
+                        if let ConfigKey::LampSlew(role) = key
+                            && let Some(lamp) = signal.lamp(role)
+                        {
+                            lamp.set_ramp_ms(config.lamp_slew_ms[role as usize]);
+                        }
+                        #[cfg(not(feature = "semaphore"))]
+                        if let ConfigKey::LampPin(_) = key {
+                            move_lamp_pins(&mut signal, &old_config, &config);
+                        }
+                        let sequence = record_in_journal(
+                            &mut journal,
+                            &mut platform,
+                            source,
+                            groups[0].aspect,
+                        );
+                        log!(
+                            Protocol,
+                            Info,
+                            "{}:A:CFG:{}:{}",
+                            SIGNAL_ID,
+                            state_checksum(groups[0].aspect, current_speed, current_route),
+                            sequence
+                        );
+                    } else {
+                        log!(
+                            Protocol,
+                            Error,
+                            "{}",
+                            CommandError::ValueOutOfRange.response(SIGNAL_ID)
+                        );
+                    }
+                }
+            }
+        }
+
+        interrupt::free(|cs| REPLY_SEQUENCE_NUMBER.borrow(cs).set(None));
+
+        if !UPSTREAM_SIGNAL_ID.is_empty() && forwarded_aspect != Some(groups[0].aspect) {
+            serial_writeln!(
+                "{}:NXT:{}",
+                UPSTREAM_SIGNAL_ID,
+                groups[0].aspect.command_id()
+            );
+            forwarded_aspect = Some(groups[0].aspect);
+        }
+    }
+}
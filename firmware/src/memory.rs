@@ -0,0 +1,69 @@
+//! Module for measuring RAM usage at runtime.
+//!
+//! The AVR has no memory protection, so a stack overflowing into the static buffers silently corrupts them. To give an idea of how much headroom is left, the unused part of the stack is painted with a known pattern at boot; the amount of paint that was never overwritten is the minimum stack headroom observed so far.
+
+use core::ptr::addr_of;
+use core::ptr::addr_of_mut;
+
+/// Byte pattern used for painting unused stack memory.
+const STACK_PAINT: u8 = 0xc5;
+/// Number of bytes below the current stack position that are left alone while painting, so that the painting function doesn’t clobber its own stack frame.
+const PAINT_SAFETY_MARGIN: usize = 32;
+
+extern "C" {
+    // Provided by the avr-libc linker script: first address after all static data, where the heap (which we don’t use) would start.
+    static mut __heap_start: u8;
+}
+
+/// Paints all memory between the end of static data and the current stack position.
+///
+/// Must be called as early as possible after boot and with interrupts disabled, since any stack usage happening before this call will not be detected.
+///
+/// Returns a hash of the memory contents before painting. After power-on, RAM contains noise, so this can be used for seeding random numbers.
+#[inline(never)]
+pub fn paint_stack() -> u32 {
+    let stack_position = 0u8;
+    let stack_position = addr_of!(stack_position) as usize;
+    let start = unsafe { addr_of_mut!(__heap_start) };
+    let end = stack_position.saturating_sub(PAINT_SAFETY_MARGIN);
+    let mut current = start;
+    let mut hash = 0u32;
+    while (current as usize) < end {
+        unsafe {
+            hash = hash.rotate_left(5) ^ u32::from(current.read_volatile());
+            current.write_volatile(STACK_PAINT);
+            current = current.add(1);
+        }
+    }
+    hash
+}
+
+/// Returns the smallest amount of free stack space (in bytes) that was available since the stack was painted.
+pub fn free_stack_bytes() -> usize {
+    let start = unsafe { addr_of!(__heap_start) };
+    let mut untouched = 0;
+    while unsafe { start.add(untouched).read_volatile() } == STACK_PAINT {
+        untouched += 1;
+    }
+    untouched
+}
+
+/// Tracks the highest fill level that a static buffer has ever reached.
+#[derive(Clone, Copy, Default)]
+pub struct HighWaterMark(usize);
+
+impl HighWaterMark {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Records the current fill level of the buffer.
+    pub fn record(&mut self, fill_level: usize) {
+        self.0 = self.0.max(fill_level);
+    }
+
+    /// Returns the highest recorded fill level.
+    pub fn get(self) -> usize {
+        self.0
+    }
+}
@@ -0,0 +1,61 @@
+//! Module for the services of the Arduino board that the controller needs apart from its pins, behind the platform trait of the signalling library.
+
+use arduino_hal::hal::wdt;
+use arduino_hal::hal::Wdt;
+use arduino_hal::Eeprom;
+use signalling::platform::Platform;
+
+use crate::SERIAL_BUFFER;
+
+/// The ATmega’s EEPROM, watchdog and serial receive interrupt.
+pub struct AvrPlatform {
+    eeprom: Eeprom,
+    wdt: Wdt,
+}
+
+impl AvrPlatform {
+    /// Creates the platform. The watchdog doesn’t run until it is started.
+    pub fn new(eeprom: Eeprom, wdt: Wdt) -> Self {
+        Self { eeprom, wdt }
+    }
+
+    /// Starts the watchdog, which reboots the controller if it isn’t fed for four seconds.
+    pub fn start_watchdog(&mut self) {
+        self.wdt.start(wdt::Timeout::Ms4000).unwrap();
+    }
+
+    /// Reboots the controller by letting the watchdog expire as soon as possible.
+    pub fn reboot(&mut self) -> ! {
+        self.wdt.start(wdt::Timeout::Ms16).unwrap();
+        loop {
+            avr_device::asm::sleep();
+        }
+    }
+}
+
+impl Platform for AvrPlatform {
+    const PERSISTENT_SIZE: u16 = Eeprom::CAPACITY;
+
+    fn read_persistent(&mut self, offset: u16, data: &mut [u8]) {
+        self.eeprom.read(offset, data).unwrap();
+    }
+
+    fn write_persistent(&mut self, offset: u16, data: &[u8]) {
+        self.eeprom.write(offset, data).unwrap();
+    }
+
+    fn feed_watchdog(&mut self) {
+        self.wdt.feed();
+    }
+
+    fn sleep(&mut self) {
+        avr_device::asm::sleep();
+    }
+
+    fn receive(&mut self, mut received: impl FnMut(u8)) {
+        // the receive interrupt keeps filling the buffer while it is emptied, and the main loop is the only place that pops bytes.
+        while let Some(byte) = unsafe { SERIAL_BUFFER.pop() } {
+            received(byte);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Module for the I/O ports of the microcontroller as output banks, so that lamps on the same port are switched with a single register write.
+//!
+//! The pins have to be configured as outputs through the HAL before the port switches them. The HAL’s pin handles can be dropped afterwards, since the port register keeps its state.
+
+use core::convert::Infallible;
+
+use arduino_hal::pac;
+use avr_device::interrupt;
+use signalling::bank::OutputBank;
+
+/// An I/O port, whose bits are the port’s pins, e.g. bit 2 of port D for pin D2 on the Nano.
+#[derive(Clone, Copy)]
+pub enum Port {
+    /// Pins D8 to D13 on the Nano, or D53, D52, D51, D50 and D10 to D13 on the Mega.
+    B,
+    /// Pins D0 to D7 on the Nano, or D21 to D18 and D38 on the Mega.
+    D,
+    /// Pins A0 to A5 on the Nano (PORTC), or A0 to A7 on the Mega (PORTF).
+    Analog,
+}
+
+impl OutputBank for Port {
+    type Error = Infallible;
+
+    fn write(&mut self, mask: u8, states: u8) -> Result<(), Infallible> {
+        let update = |bits: u8| bits & !mask | states & mask;
+        // an interrupt handler that switches another pin of the port between the read and the write would be undone.
+        interrupt::free(|_| match self {
+            Self::B => {
+                let port = unsafe { &*pac::PORTB::ptr() };
+                port.portb
+                    .modify(|r, w| unsafe { w.bits(update(r.bits())) });
+            }
+            Self::D => {
+                let port = unsafe { &*pac::PORTD::ptr() };
+                port.portd
+                    .modify(|r, w| unsafe { w.bits(update(r.bits())) });
+            }
+            #[cfg(not(feature = "mega"))]
+            Self::Analog => {
+                let port = unsafe { &*pac::PORTC::ptr() };
+                port.portc
+                    .modify(|r, w| unsafe { w.bits(update(r.bits())) });
+            }
+            #[cfg(feature = "mega")]
+            Self::Analog => {
+                let port = unsafe { &*pac::PORTF::ptr() };
+                port.portf
+                    .modify(|r, w| unsafe { w.bits(update(r.bits())) });
+            }
+        });
+        Ok(())
+    }
+}
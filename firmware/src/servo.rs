@@ -0,0 +1,80 @@
+//! Module for the servo outputs of semaphore signals, using the hardware PWM of timers 1 and 2.
+//!
+//! Timer 1 drives the servos on pins D9 and D10 with a period of exactly 20 ms. Timer 2 only has 8 bits, so the servo on pin D11 gets a period of about 16 ms and a resolution of 64 µs, which servos accept and which is still fine enough for adjusting end stops.
+
+use core::convert::Infallible;
+
+use arduino_hal::pac;
+use embedded_hal::pwm::ErrorType;
+use embedded_hal::pwm::SetDutyCycle;
+use signalling::semaphore::SERVO_PERIOD_US;
+
+// 16 MHz / 8 = 2 MHz, so timer 1 counts half microseconds.
+const TIMER1_COUNTS: u16 = 2 * SERVO_PERIOD_US;
+// 16 MHz / 1024 = 15.625 kHz, so timer 2 counts 64 µs.
+const TIMER2_COUNT_US: u16 = 64;
+
+/// A servo output, i.e. one of the PWM channels of timers 1 and 2.
+pub enum ServoOutput {
+    /// OC1A on pin D9.
+    D9,
+    /// OC1B on pin D10.
+    D10,
+    /// OC2A on pin D11.
+    D11,
+}
+
+/// Starts the servo PWM of timers 1 and 2. The channels only take over their pins once they are connected.
+pub fn init(tc1: pac::TC1, tc2: pac::TC2) {
+    // fast PWM with ICR1 as top, so that the period is independent of the compare registers.
+    tc1.icr1.write(|w| w.bits(TIMER1_COUNTS - 1));
+    tc1.ocr1a.write(|w| w.bits(0));
+    tc1.ocr1b.write(|w| w.bits(0));
+    tc1.tccr1a.write(|w| w.wgm1().bits(0b10));
+    tc1.tccr1b.write(|w| w.wgm1().bits(0b11).cs1().prescale_8());
+
+    tc2.ocr2a.write(|w| w.bits(0));
+    tc2.tccr2a.write(|w| w.wgm2().pwm_fast());
+    tc2.tccr2b.write(|w| w.cs2().prescale_1024());
+}
+
+impl ServoOutput {
+    /// Connects the channel to its pin, which must be configured as an output. The servo gets no pulses until its duty cycle is set.
+    pub fn connect(self) -> Self {
+        // pins of channels that are not connected keep working as normal outputs.
+        let tc1 = unsafe { &*pac::TC1::ptr() };
+        let tc2 = unsafe { &*pac::TC2::ptr() };
+        match self {
+            Self::D9 => tc1.tccr1a.modify(|_, w| w.com1a().match_clear()),
+            Self::D10 => tc1.tccr1a.modify(|_, w| w.com1b().match_clear()),
+            Self::D11 => tc2.tccr2a.modify(|_, w| w.com2a().match_clear()),
+        }
+        self
+    }
+}
+
+impl ErrorType for ServoOutput {
+    type Error = Infallible;
+}
+
+/// The duty cycle of timer 2 is scaled as if it had a period of 20 ms, so that servos get the same pulse widths on every channel.
+impl SetDutyCycle for ServoOutput {
+    fn max_duty_cycle(&self) -> u16 {
+        match self {
+            Self::D9 | Self::D10 => TIMER1_COUNTS,
+            Self::D11 => SERVO_PERIOD_US / TIMER2_COUNT_US,
+        }
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        // the timers were taken by init(), and each channel only writes its own compare register.
+        let tc1 = unsafe { &*pac::TC1::ptr() };
+        let tc2 = unsafe { &*pac::TC2::ptr() };
+        match self {
+            Self::D9 => tc1.ocr1a.write(|w| w.bits(duty)),
+            Self::D10 => tc1.ocr1b.write(|w| w.bits(duty)),
+            Self::D11 => tc2.ocr2a.write(|w| w.bits(duty.min(0xff) as u8)),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+//! Module for arbitrating between aspect requests from different command sources, like the serial port and the control desk panel.
+//!
+//! If two sources request different aspects at almost the same time, at least one of them is acting on outdated information, and it’s unclear which one. The arbiter then resolves to the most restrictive of the requested aspects, and reports the conflict.
+
+use crate::signals::SignalAspect;
+
+/// Requests from different sources within this time are considered simultaneous.
+pub const CONFLICT_WINDOW_MS: u32 = 1000;
+
+/// A source of commands.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CommandSource {
+    /// The serial port.
+    Serial,
+    /// The buttons of the control desk panel.
+    Panel,
+    /// Accessory commands of the DCC command station.
+    Dcc,
+    /// Accessory commands of Märklin Motorola keyboards.
+    Motorola,
+    /// Switch requests on the LocoNet bus.
+    LocoNet,
+    /// Accessory operation requests on the XpressNet bus.
+    XpressNet,
+    /// A channel of the Selectrix bus.
+    Selectrix,
+    /// Outputs of the C/MRI node, which JMRI sets.
+    Cmri,
+    /// Events of the OpenLCB (LCC) bus.
+    OpenLcb,
+    /// Accessory commands of the BiDiB host.
+    Bidib,
+    /// Messages of the MQTT broker.
+    Mqtt,
+    /// Channels of the DMX512 lighting console.
+    Dmx,
+    /// Accessory commands of SRCP on the serial port.
+    Srcp,
+}
+
+impl CommandSource {
+    pub const ALL: [Self; 13] = [
+        Self::Serial,
+        Self::Panel,
+        Self::Dcc,
+        Self::Motorola,
+        Self::LocoNet,
+        Self::XpressNet,
+        Self::Selectrix,
+        Self::Cmri,
+        Self::OpenLcb,
+        Self::Bidib,
+        Self::Mqtt,
+        Self::Dmx,
+        Self::Srcp,
+    ];
+
+    pub fn command_id(self) -> &'static str {
+        match self {
+            Self::Serial => "SER",
+            Self::Panel => "PNL",
+            Self::Dcc => "DCC",
+            Self::Motorola => "MM",
+            Self::LocoNet => "LN",
+            Self::XpressNet => "XN",
+            Self::Selectrix => "SX",
+            Self::Cmri => "CMRI",
+            Self::OpenLcb => "LCC",
+            Self::Bidib => "BIDIB",
+            Self::Mqtt => "MQTT",
+            Self::Dmx => "DMX",
+            Self::Srcp => "SRCP",
+        }
+    }
+}
+
+/// Two sources that requested different aspects at almost the same time.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Conflict<Aspect: SignalAspect> {
+    /// The source of the current request and its aspect.
+    pub source: CommandSource,
+    pub aspect: Aspect,
+    /// The other source and the aspect it requested shortly before.
+    pub other_source: CommandSource,
+    pub other_aspect: Aspect,
+}
+
+/// The outcome of an aspect request.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Arbitration<Aspect: SignalAspect> {
+    /// The aspect to switch to.
+    pub aspect: Aspect,
+    /// The conflict that had to be resolved, if any. It should be reported.
+    pub conflict: Option<Conflict<Aspect>>,
+}
+
+/// Keeps track of the recent aspect requests from every source.
+pub struct Arbiter<Aspect: SignalAspect> {
+    // Last aspect requested by every source and the time of the request, indexed by source.
+    last_requests: [Option<(Aspect, u32)>; CommandSource::ALL.len()],
+}
+
+impl<Aspect: SignalAspect> Default for Arbiter<Aspect> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Aspect: SignalAspect> Arbiter<Aspect> {
+    pub const fn new() -> Self {
+        Self {
+            last_requests: [None; CommandSource::ALL.len()],
+        }
+    }
+
+    /// Records an aspect request and decides which aspect to switch to. If other sources requested different aspects recently, the most restrictive aspect wins.
+    pub fn request(
+        &mut self,
+        source: CommandSource,
+        aspect: Aspect,
+        now: u32,
+    ) -> Arbitration<Aspect> {
+        self.last_requests[source as usize] = Some((aspect, now));
+        let most_restrictive_other = CommandSource::ALL
+            .into_iter()
+            .filter(|other_source| *other_source != source)
+            .filter_map(|other_source| {
+                let (other_aspect, requested_at) = self.last_requests[other_source as usize]?;
+                (now.wrapping_sub(requested_at) < CONFLICT_WINDOW_MS && other_aspect != aspect)
+                    .then_some((other_source, other_aspect))
+            })
+            .min_by(|(_, first), (_, second)| first.cmp_restrictiveness(*second));
+
+        match most_restrictive_other {
+            None => Arbitration {
+                aspect,
+                conflict: None,
+            },
+            Some((other_source, other_aspect)) => Arbitration {
+                aspect: aspect.most_restrictive(other_aspect),
+                conflict: Some(Conflict {
+                    source,
+                    aspect,
+                    other_source,
+                    other_aspect,
+                }),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Arbiter;
+    use super::CommandSource;
+    use super::CONFLICT_WINDOW_MS;
+    use crate::signals::HVMainSignalAspect;
+    use crate::signals::SignalAspect;
+
+    fn source_pairs() -> impl Iterator<Item = (CommandSource, CommandSource)> {
+        CommandSource::ALL.into_iter().flat_map(|first| {
+            CommandSource::ALL
+                .into_iter()
+                .filter(move |second| *second != first)
+                .map(move |second| (first, second))
+        })
+    }
+
+    #[test]
+    fn simultaneous_requests_resolve_to_most_restrictive_aspect() {
+        for (first, second) in source_pairs() {
+            for (first_aspect, second_aspect) in [
+                (HVMainSignalAspect::Stop, HVMainSignalAspect::Proceed),
+                (HVMainSignalAspect::Proceed, HVMainSignalAspect::Stop),
+                (HVMainSignalAspect::Proceed, HVMainSignalAspect::ProceedSlow),
+                (HVMainSignalAspect::Dark, HVMainSignalAspect::ProceedSlow),
+            ] {
+                let mut arbiter = Arbiter::new();
+                let arbitration = arbiter.request(first, first_aspect, 1000);
+                assert!(arbitration.aspect == first_aspect);
+                assert!(arbitration.conflict.is_none());
+
+                let arbitration = arbiter.request(second, second_aspect, 1500);
+                assert!(
+                    arbitration.aspect == first_aspect.most_restrictive(second_aspect),
+                    "{} -> {}",
+                    first.command_id(),
+                    second.command_id()
+                );
+                let conflict = arbitration.conflict.unwrap();
+                assert!((conflict.source, conflict.aspect) == (second, second_aspect));
+                assert!((conflict.other_source, conflict.other_aspect) == (first, first_aspect));
+            }
+        }
+    }
+
+    #[test]
+    fn agreeing_and_late_requests_are_no_conflict() {
+        for (first, second) in source_pairs() {
+            let mut arbiter = Arbiter::new();
+            arbiter.request(first, HVMainSignalAspect::Stop, 1000);
+            let agreeing = arbiter.request(second, HVMainSignalAspect::Stop, 1100);
+            assert!(agreeing.conflict.is_none());
+            let late = arbiter.request(
+                second,
+                HVMainSignalAspect::Proceed,
+                1000 + CONFLICT_WINDOW_MS,
+            );
+            assert!(late.aspect == HVMainSignalAspect::Proceed);
+            assert!(late.conflict.is_none());
+        }
+    }
+
+    #[test]
+    fn same_source_may_change_its_mind() {
+        for source in CommandSource::ALL {
+            let mut arbiter = Arbiter::new();
+            arbiter.request(source, HVMainSignalAspect::Stop, 1000);
+            let arbitration = arbiter.request(source, HVMainSignalAspect::Proceed, 1001);
+            assert!(arbitration.aspect == HVMainSignalAspect::Proceed);
+            assert!(arbitration.conflict.is_none());
+        }
+    }
+}
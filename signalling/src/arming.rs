@@ -0,0 +1,41 @@
+//! Module for the two-step command sequence that protects aspects which blank the signal.
+
+use crate::signals::SignalAspect;
+
+/// An armed aspect must be confirmed within this time.
+pub const ARMING_TIMEOUT_MS: u32 = 10_000;
+
+/// Returns whether the aspect needs to be armed before it can be switched to, if arming is required at all. These are the aspects that blank the signal, which a single mistyped command should never cause.
+pub fn requires_arming(aspect: impl SignalAspect) -> bool {
+    aspect.blanks_signal()
+}
+
+/// Keeps track of an armed aspect that is waiting for its confirmation.
+pub struct Arming<Aspect: SignalAspect> {
+    // Armed aspect and the time at which it was armed.
+    armed: Option<(Aspect, u32)>,
+}
+
+impl<Aspect: SignalAspect> Default for Arming<Aspect> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Aspect: SignalAspect> Arming<Aspect> {
+    pub const fn new() -> Self {
+        Self { armed: None }
+    }
+
+    /// Arms the given aspect, replacing any previously armed aspect.
+    pub fn arm(&mut self, aspect: Aspect, now: u32) {
+        self.armed = Some((aspect, now));
+    }
+
+    /// Checks whether the given aspect was armed recently. The arming is used up in any case, so every confirmation needs its own arming.
+    pub fn confirm(&mut self, aspect: Aspect, now: u32) -> bool {
+        self.armed.take().is_some_and(|(armed_aspect, armed_at)| {
+            armed_aspect == aspect && now.wrapping_sub(armed_at) < ARMING_TIMEOUT_MS
+        })
+    }
+}
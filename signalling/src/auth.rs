@@ -0,0 +1,169 @@
+//! Module for authenticating commands with a shared secret.
+//!
+//! An authenticated command carries a suffix `@[Counter]:[Tag]`, where the counter must increase with every command and the tag is a SipHash-2-4 of the counter and the command, keyed with the shared secret. Since the counter is stored permanently, recorded commands can’t be replayed.
+
+use crate::commands::parse_decimal;
+use crate::commands::strip_comment;
+
+/// EEPROM address of the last accepted counter value (4 bytes).
+pub const AUTHENTICATION_COUNTER_EEPROM_OFFSET: u16 = 8;
+/// Counter value that erased EEPROM reads as, which means that no command was accepted yet. It is never accepted as a counter itself.
+pub const ERASED_COUNTER: u32 = u32::MAX;
+
+/// The authentication suffix of a command.
+pub struct Authentication {
+    counter: u32,
+    tag: u64,
+}
+
+/// Splits the authentication suffix off a command line.
+///
+/// Returns the command without the suffix (and without comments, if there was a suffix), and the authentication if it was present and well-formed.
+pub fn split_authentication(line: &[u8]) -> (&[u8], Option<Authentication>) {
+    let before_comment = strip_comment(line);
+    let Some(position_of_at) = before_comment.iter().position(|c| *c == b'@') else {
+        return (line, None);
+    };
+    let (command, suffix) = before_comment.split_at(position_of_at);
+    let mut parts = suffix[1..].trim_ascii().split(|c| *c == b':');
+    let counter = parts.next().and_then(parse_decimal);
+    let tag = parts.next().and_then(parse_hex);
+    let authentication = match (counter, tag, parts.next()) {
+        (Some(counter), Some(tag), None) => Some(Authentication { counter, tag }),
+        _ => None,
+    };
+    (command.trim_ascii(), authentication)
+}
+
+/// Verifies authenticated commands and keeps track of the rolling counter.
+pub struct Authenticator {
+    key: [u8; 16],
+    last_counter: u32,
+}
+
+impl Authenticator {
+    /// Creates the authenticator with the stored counter value, which is `ERASED_COUNTER` if none was stored yet.
+    pub fn new(key: [u8; 16], last_counter: u32) -> Self {
+        let last_counter = if last_counter == ERASED_COUNTER {
+            0
+        } else {
+            last_counter
+        };
+        Self { key, last_counter }
+    }
+
+    /// Returns the counter value of the last accepted command, which needs to be stored permanently.
+    pub fn last_counter(&self) -> u32 {
+        self.last_counter
+    }
+
+    /// Checks whether the command was authenticated with the shared secret and a fresh counter value. If so, the counter value is used up.
+    pub fn verify(&mut self, command: &[u8], authentication: &Authentication) -> bool {
+        if authentication.counter <= self.last_counter || authentication.counter == ERASED_COUNTER {
+            return false;
+        }
+        let expected_tag = siphash_2_4(
+            &self.key,
+            authentication.counter.to_le_bytes().iter().chain(command),
+        );
+        if expected_tag != authentication.tag {
+            return false;
+        }
+        self.last_counter = authentication.counter;
+        true
+    }
+}
+
+/// Computes SipHash-2-4 of the given message bytes.
+fn siphash_2_4<'a>(key: &[u8; 16], message: impl Iterator<Item = &'a u8>) -> u64 {
+    fn round(v: &mut [u64; 4]) {
+        v[0] = v[0].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(13) ^ v[0];
+        v[0] = v[0].rotate_left(32);
+        v[2] = v[2].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(16) ^ v[2];
+        v[0] = v[0].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(21) ^ v[0];
+        v[2] = v[2].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(17) ^ v[2];
+        v[2] = v[2].rotate_left(32);
+    }
+    fn compress(v: &mut [u64; 4], word: u64) {
+        v[3] ^= word;
+        round(v);
+        round(v);
+        v[0] ^= word;
+    }
+
+    let k0 = u64::from_le_bytes(key[..8].try_into().unwrap());
+    let k1 = u64::from_le_bytes(key[8..].try_into().unwrap());
+    let mut v = [
+        k0 ^ 0x736f6d6570736575,
+        k1 ^ 0x646f72616e646f6d,
+        k0 ^ 0x6c7967656e657261,
+        k1 ^ 0x7465646279746573,
+    ];
+
+    let mut length: u8 = 0;
+    let mut word = 0u64;
+    for byte in message {
+        word |= u64::from(*byte) << (8 * (length % 8));
+        length = length.wrapping_add(1);
+        if length % 8 == 0 {
+            compress(&mut v, word);
+            word = 0;
+        }
+    }
+    word |= u64::from(length) << 56;
+    compress(&mut v, word);
+
+    v[2] ^= 0xff;
+    for _ in 0..4 {
+        round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+fn parse_hex(text: &[u8]) -> Option<u64> {
+    if text.len() != 16 {
+        return None;
+    }
+    text.iter().try_fold(0u64, |value, digit| {
+        let digit = (*digit as char).to_digit(16)?;
+        Some(value << 4 | u64::from(digit))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::siphash_2_4;
+    use super::split_authentication;
+    use super::Authenticator;
+    use super::ERASED_COUNTER;
+
+    const KEY: [u8; 16] = *b"0123456789abcdef";
+
+    fn authenticated(command: &str, counter: u32) -> String {
+        let tag = siphash_2_4(&KEY, counter.to_le_bytes().iter().chain(command.as_bytes()));
+        format!("{command}@{counter}:{tag:016X}")
+    }
+
+    fn verify(authenticator: &mut Authenticator, line: &str) -> bool {
+        let (command, authentication) = split_authentication(line.as_bytes());
+        authenticator.verify(command, &authentication.unwrap())
+    }
+
+    #[test]
+    fn accepts_commands_after_erased_eeprom() {
+        let mut authenticator = Authenticator::new(KEY, ERASED_COUNTER);
+        assert!(verify(&mut authenticator, &authenticated("F:1", 1)));
+        assert_eq!(authenticator.last_counter(), 1);
+        // replays and the erased counter itself are rejected.
+        assert!(!verify(&mut authenticator, &authenticated("F:1", 1)));
+        assert!(!verify(
+            &mut authenticator,
+            &authenticated("F:1", ERASED_COUNTER)
+        ));
+        assert!(!verify(&mut authenticator, "F:1@2:0000000000000000"));
+    }
+}
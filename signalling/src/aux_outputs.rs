@@ -0,0 +1,198 @@
+//! Module for auxiliary outputs, which are switched by rules depending on the controller’s state.
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal::digital::PinState;
+
+use crate::commands::AspectCommand;
+use crate::fast_clock;
+use crate::signals::SignalAspect;
+
+/// The controller state that auxiliary output rules depend on.
+pub struct AuxInputs {
+    /// Current time in milliseconds.
+    pub now: u32,
+    /// Current temperature in degrees Celsius.
+    pub temperature_celsius: i16,
+    /// Current fast-clock time in minutes since midnight, or None if no fast clock was broadcast yet.
+    pub fast_clock_minutes: Option<u16>,
+    /// The aspect change since the outputs were last updated, if any.
+    pub aspect_change: Option<AspectChange>,
+}
+
+/// A change of the signal aspect, identified by the commands for the old and the new aspect, so that rules don’t depend on the signalling system.
+#[derive(Clone, Copy)]
+pub struct AspectChange {
+    pub from: AspectCommand,
+    pub to: AspectCommand,
+}
+
+impl AspectChange {
+    /// Returns the change from the first to the second aspect, or None if they are the same.
+    pub fn between<Aspect: SignalAspect>(from: Aspect, to: Aspect) -> Option<Self> {
+        if from == to {
+            return None;
+        }
+        Some(Self {
+            from: AspectCommand::from_command_id(from.command_id().as_bytes())?,
+            to: AspectCommand::from_command_id(to.command_id().as_bytes())?,
+        })
+    }
+}
+
+/// The rule that decides when an auxiliary output is switched on.
+#[derive(Clone, Copy)]
+pub enum AuxRule {
+    /// A heater keeping the signal heads free of condensation and frost. It switches on at or below the first temperature and off at or above the second temperature; in between, it keeps its state.
+    Heater {
+        on_at_celsius: i16,
+        off_at_celsius: i16,
+    },
+    /// An output that is on during a period of the layout’s fast clock, like a relay that dims the lamps at night. The period starts at the first time and ends at the second time, in minutes since midnight, and may span midnight. The output stays off while there is no fast clock.
+    FastClockPeriod {
+        from_minutes: u16,
+        until_minutes: u16,
+    },
+    /// An output that is pulsed for the given time in milliseconds when the aspect changes, like the trigger input of a sound module playing the clunk of a relay interlocking or a departure whistle (Zp9). Only changes from the first to the second aspect trigger it, where None means any aspect.
+    Trigger {
+        from: Option<AspectCommand>,
+        to: Option<AspectCommand>,
+        pulse_ms: u16,
+    },
+}
+
+/// An auxiliary output, like a heater relay.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used. Its parameters additionally include the output pin’s error type (which some functions also return).
+pub struct AuxOutput<Error, PinType: OutputPin<Error = Error>> {
+    pin: PinType,
+    rule: AuxRule,
+    is_on: bool,
+    // Time the trigger was last pulsed, until the pulse ends.
+    triggered_at: Option<u32>,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> AuxOutput<Error, PinType> {
+    /// Creates a new auxiliary output, which is initially off.
+    pub fn new(mut pin: PinType, rule: AuxRule) -> Result<Self, Error> {
+        pin.set_low()?;
+        Ok(Self {
+            pin,
+            rule,
+            is_on: false,
+            triggered_at: None,
+        })
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.is_on
+    }
+
+    /// Evaluates the output’s rule with the current controller state and switches the output accordingly.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    pub fn update(&mut self, inputs: &AuxInputs) -> Result<(), Error> {
+        let should_be_on = match self.rule {
+            AuxRule::Heater {
+                on_at_celsius,
+                off_at_celsius,
+            } => {
+                if inputs.temperature_celsius <= on_at_celsius {
+                    true
+                } else if inputs.temperature_celsius >= off_at_celsius {
+                    false
+                } else {
+                    self.is_on
+                }
+            }
+            AuxRule::FastClockPeriod {
+                from_minutes,
+                until_minutes,
+            } => inputs
+                .fast_clock_minutes
+                .is_some_and(|minutes| fast_clock::is_within(minutes, from_minutes, until_minutes)),
+            AuxRule::Trigger { from, to, pulse_ms } => {
+                if inputs.aspect_change.is_some_and(|change| {
+                    from.map_or(true, |from| from == change.from)
+                        && to.map_or(true, |to| to == change.to)
+                }) {
+                    self.triggered_at = Some(inputs.now);
+                }
+                self.triggered_at = self
+                    .triggered_at
+                    .filter(|at| inputs.now.wrapping_sub(*at) < u32::from(pulse_ms));
+                self.triggered_at.is_some()
+            }
+        };
+        if should_be_on != self.is_on {
+            self.pin.set_state(PinState::from(should_be_on))?;
+            self.is_on = should_be_on;
+        }
+        Ok(())
+    }
+}
+
+/// Converts a reading of the microcontroller’s internal temperature sensor, taken with the internal 1.1 V reference, to degrees Celsius.
+///
+/// This uses the typical values from the ATmega328P datasheet (352 at 25 °C, 83 steps per 70 °C). Individual chips deviate by several degrees, which is corrected by the given offset.
+pub fn celsius_from_internal_sensor(reading: u16, offset_celsius: i16) -> i16 {
+    let celsius = 25 + (i32::from(reading) - 352) * 70 / 83;
+    celsius as i16 + offset_celsius
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use super::AspectChange;
+    use super::AuxInputs;
+    use super::AuxOutput;
+    use super::AuxRule;
+    use crate::commands::AspectCommand;
+    use crate::mock::MockPin;
+    use crate::mock::MockPins;
+    use crate::signals::HVMainSignalAspect;
+
+    #[test]
+    fn trigger_pulses_only_on_matching_aspect_changes() {
+        let pins = MockPins::new();
+        let mut output: AuxOutput<Infallible, MockPin> = AuxOutput::new(
+            pins.pin(),
+            AuxRule::Trigger {
+                from: Some(AspectCommand::Zero),
+                to: None,
+                pulse_ms: 100,
+            },
+        )
+        .unwrap();
+        let mut inputs = AuxInputs {
+            now: 1000,
+            temperature_celsius: 20,
+            fast_clock_minutes: None,
+            aspect_change: AspectChange::between(
+                HVMainSignalAspect::Proceed,
+                HVMainSignalAspect::Stop,
+            ),
+        };
+        output.update(&inputs).unwrap();
+        assert_eq!(pins.states(), [false]);
+
+        inputs.aspect_change =
+            AspectChange::between(HVMainSignalAspect::Stop, HVMainSignalAspect::ProceedSlow);
+        output.update(&inputs).unwrap();
+        assert_eq!(pins.states(), [true]);
+        inputs.aspect_change = None;
+        inputs.now = 1099;
+        output.update(&inputs).unwrap();
+        assert_eq!(pins.states(), [true]);
+        inputs.now = 1100;
+        output.update(&inputs).unwrap();
+        assert_eq!(pins.states(), [false]);
+
+        assert!(
+            AspectChange::between(HVMainSignalAspect::Stop, HVMainSignalAspect::Stop).is_none()
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Module for signals whose lamps are all connected to one output port, which is written at once.
+//!
+//! Switching the lamps of a signal one by one passes through intermediate lamp combinations, which are only visible for microseconds but still aren’t valid aspects. If all lamps share one port register, like an AVR `PORTD`, a [`BankedSignal`] instead computes the lamp states of the new aspect and writes them with a single register write. Signals whose lamps are spread over several ports keep using the per-pin signal types.
+
+use core::marker::PhantomData;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::ErrorType;
+use embedded_hal::digital::OutputPin;
+
+use crate::blink::Blinker;
+use crate::signals::LampRole;
+use crate::signals::Signal;
+use crate::signals::SignalAspect;
+use crate::signals::BLINK_HALF_PERIOD_MS;
+
+/// A group of up to eight output pins that can be written with a single operation, like a port register.
+pub trait OutputBank {
+    type Error: embedded_hal::digital::Error;
+
+    /// Sets the pins whose bits are set in the mask to the corresponding bits of the new states, and leaves all other pins unchanged.
+    ///
+    /// # Errors
+    /// Errors are returned from the underlying hardware access.
+    fn write(&mut self, mask: u8, states: u8) -> Result<(), Self::Error>;
+}
+
+/// A single pin of an output bank, for switching a lamp directly.
+pub struct BankPin<Bank: OutputBank> {
+    bank: Bank,
+    bit: u8,
+}
+
+impl<Bank: OutputBank> ErrorType for BankPin<Bank> {
+    type Error = Bank::Error;
+}
+
+impl<Bank: OutputBank> OutputPin for BankPin<Bank> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.bank.write(1 << self.bit, 0)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.bank.write(1 << self.bit, 1 << self.bit)
+    }
+}
+
+/// Returns the signal head that a lamp belongs to, identified by its first lamp.
+fn head_of(role: LampRole) -> LampRole {
+    match role {
+        LampRole::MainRed
+        | LampRole::MainGreen
+        | LampRole::MainYellow
+        | LampRole::MainNotice
+        | LampRole::MainSubstitution
+        | LampRole::MainCautionLeft
+        | LampRole::MainCautionRight
+        | LampRole::MainCautionBottom
+        | LampRole::MainShuntingLower
+        | LampRole::MainShuntingUpper
+        | LampRole::MainCounterTrack
+        | LampRole::MainCounterTrackSubstitution => LampRole::MainRed,
+        LampRole::AnnouncementGreenUpper
+        | LampRole::AnnouncementGreenLower
+        | LampRole::AnnouncementYellowUpper
+        | LampRole::AnnouncementYellowLower
+        | LampRole::AnnouncementNotice => LampRole::AnnouncementGreenUpper,
+        LampRole::RepeaterNotice => LampRole::RepeaterNotice,
+        LampRole::DwarfRed | LampRole::DwarfWhite | LampRole::DwarfNotice => LampRole::DwarfRed,
+        LampRole::CrossingYellow | LampRole::CrossingWhite => LampRole::CrossingYellow,
+    }
+}
+
+/// A signal (or signal group) whose lamps are all connected to the same output bank, so that every aspect change is a single write.
+///
+/// The lamps are identified by their role, and the aspect’s [`SignalAspect::lights_lamp`] determines which of them are lit. A signal head that has no connected lamps, like the announcement signal of a lone main signal, is ignored; on all other heads, an aspect is supported if all lamps that it lights are connected.
+///
+/// # Type parameters
+///
+/// The bank is cloned for every lamp, so it should be a cheap handle to the hardware register.
+pub struct BankedSignal<Aspect: SignalAspect, Bank: OutputBank + Clone> {
+    bank: Bank,
+    lamps: [Option<BankPin<Bank>>; LampRole::ALL.len()],
+    // Lamps that blink in the current aspect.
+    blink_mask: u8,
+    blinker: Option<Blinker>,
+    _aspect: PhantomData<Aspect>,
+}
+
+impl<Aspect: SignalAspect, Bank: OutputBank + Clone> BankedSignal<Aspect, Bank> {
+    pub fn new(bank: Bank) -> Self {
+        Self {
+            bank,
+            lamps: core::array::from_fn(|_| None),
+            blink_mask: 0,
+            blinker: None,
+            _aspect: PhantomData,
+        }
+    }
+
+    /// Connects the lamp with the given role to the given bit (0 to 7) of the bank.
+    pub fn with_lamp(mut self, role: LampRole, bit: u8) -> Self {
+        assert!(bit < 8, "output banks only have eight pins");
+        self.lamps[role as usize] = Some(BankPin {
+            bank: self.bank.clone(),
+            bit,
+        });
+        self
+    }
+
+    /// Returns whether any lamp of the same signal head as the given lamp is connected.
+    fn has_head_of(&self, role: LampRole) -> bool {
+        LampRole::ALL
+            .into_iter()
+            .any(|other| head_of(other) == head_of(role) && self.lamps[other as usize].is_some())
+    }
+
+    /// Returns the mask of all connected lamps for which the given function returns true.
+    fn mask(&self, is_selected: impl Fn(LampRole) -> bool) -> u8 {
+        LampRole::ALL
+            .into_iter()
+            .filter(|role| is_selected(*role))
+            .filter_map(|role| self.lamps[role as usize].as_ref())
+            .fold(0, |mask, lamp| mask | 1 << lamp.bit)
+    }
+}
+
+impl<Aspect: SignalAspect, Bank: OutputBank + Clone> Signal for BankedSignal<Aspect, Bank> {
+    type Aspect = Aspect;
+    type Pin = BankPin<Bank>;
+    type Error = Bank::Error;
+
+    fn supports_aspect(&self, aspect: Aspect) -> bool {
+        LampRole::ALL
+            .into_iter()
+            .filter(|role| aspect.lights_lamp(*role) && self.has_head_of(*role))
+            .all(|role| self.lamps[role as usize].is_some())
+    }
+
+    fn switch_to_aspect(
+        &mut self,
+        aspect: Aspect,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Bank::Error> {
+        if !self.supports_aspect(aspect) {
+            panic!("illegal aspect for this light, not all lamps available");
+        }
+        let all_lamps = self.mask(|_| true);
+        let lit_lamps = self.mask(|role| aspect.lights_lamp(role));
+        self.bank.write(all_lamps, lit_lamps)?;
+        self.blink_mask = self.mask(|role| aspect.blinks_lamp(role));
+        // the blinker starts in its off phase, so its first toggle keeps the lit lamps on and starts the blinking.
+        self.blinker = (self.blink_mask != 0).then(|| Blinker::new(BLINK_HALF_PERIOD_MS));
+        Ok(())
+    }
+
+    fn lamp(&mut self, role: LampRole) -> Option<&mut BankPin<Bank>> {
+        self.lamps[role as usize].as_mut()
+    }
+
+    fn update(&mut self, now: u32) -> Result<(), Bank::Error> {
+        if let Some(blinker) = &mut self.blinker {
+            if let Some(is_on) = blinker.update(now) {
+                let states = if is_on { self.blink_mask } else { 0 };
+                self.bank.write(self.blink_mask, states)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BankedSignal;
+    use crate::mock::MockDelay;
+    use crate::mock::MockPins;
+    use crate::signals::HVMainSignalAspect;
+    use crate::signals::KsSignalAspect;
+    use crate::signals::LampRole;
+    use crate::signals::Signal;
+
+    #[test]
+    fn switches_all_lamps_with_one_write() {
+        let pins = MockPins::new();
+        for _ in 0..8 {
+            pins.pin();
+        }
+        let mut signal: BankedSignal<HVMainSignalAspect, _> = BankedSignal::new(pins.clone())
+            .with_lamp(LampRole::MainRed, 7)
+            .with_lamp(LampRole::MainGreen, 0)
+            .with_lamp(LampRole::MainYellow, 6);
+        assert!(!signal.supports_aspect(HVMainSignalAspect::Substitution));
+        let mut delay = MockDelay::default();
+        signal
+            .switch_to_aspect(HVMainSignalAspect::Stop, &mut delay)
+            .unwrap();
+        signal
+            .switch_to_aspect(HVMainSignalAspect::ProceedSlow, &mut delay)
+            .unwrap();
+
+        // unconnected pins are never changed.
+        assert_eq!(
+            pins.history(),
+            [
+                [false, false, false, false, false, false, false, true],
+                [true, false, false, false, false, false, true, false],
+            ]
+        );
+    }
+
+    #[test]
+    fn blinks_lamps_of_blinking_aspects() {
+        let pins = MockPins::new();
+        for _ in 0..2 {
+            pins.pin();
+        }
+        let mut signal: BankedSignal<KsSignalAspect, _> = BankedSignal::new(pins.clone())
+            .with_lamp(LampRole::MainGreen, 0)
+            .with_lamp(LampRole::MainYellow, 1);
+        let mut delay = MockDelay::default();
+        signal
+            .switch_to_aspect(KsSignalAspect::ExpectSpeedLimit, &mut delay)
+            .unwrap();
+        assert_eq!(pins.states(), [true, false]);
+        signal.update(500).unwrap();
+        assert_eq!(pins.states(), [true, false]);
+        signal.update(1000).unwrap();
+        assert_eq!(pins.states(), [false, false]);
+        signal
+            .switch_to_aspect(KsSignalAspect::ExpectStop, &mut delay)
+            .unwrap();
+        signal.update(1500).unwrap();
+        assert_eq!(pins.states(), [false, true]);
+    }
+}
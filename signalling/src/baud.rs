@@ -0,0 +1,56 @@
+//! Module for the baud rates of the serial port, which is selected by a configuration option.
+//!
+//! A baud rate that the control box doesn’t speak makes the controller unreachable, so a new baud rate is on trial after the reboot that applies it: unless a command is received at the new baud rate within [`TRIAL_DURATION_MS`], the controller falls back to the baud rate it used before.
+
+/// Time after booting with a new baud rate in which a command has to be received at it, in milliseconds.
+pub const TRIAL_DURATION_MS: u32 = 30_000;
+
+/// A baud rate of the serial port.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum BaudRate {
+    Baud9600 = 0,
+    Baud19200 = 1,
+    Baud38400 = 2,
+    Baud57600 = 3,
+    Baud115200 = 4,
+}
+
+impl BaudRate {
+    /// Returns the baud rate with the given value, as used by the configuration options.
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Baud9600),
+            1 => Some(Self::Baud19200),
+            2 => Some(Self::Baud38400),
+            3 => Some(Self::Baud57600),
+            4 => Some(Self::Baud115200),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of bits per second.
+    pub fn bits_per_second(self) -> u32 {
+        match self {
+            Self::Baud9600 => 9600,
+            Self::Baud19200 => 19_200,
+            Self::Baud38400 => 38_400,
+            Self::Baud57600 => 57_600,
+            Self::Baud115200 => 115_200,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BaudRate;
+
+    #[test]
+    fn values_round_trip() {
+        for value in 0..=4 {
+            assert_eq!(BaudRate::from_value(value).unwrap() as u8, value);
+        }
+        assert_eq!(BaudRate::from_value(5), None);
+        assert_eq!(BaudRate::Baud115200.bits_per_second(), 115_200);
+    }
+}
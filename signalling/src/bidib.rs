@@ -0,0 +1,465 @@
+//! Module for BiDiB, the bidirectional bus of Rocrail and the BiDiB tools, in which the signal is a node on the serial link of the host, with a single accessory whose aspects are the signal’s aspects.
+//!
+//! The host sends packets that start and end with MAGIC (0xfe), where a MAGIC can end one packet and start the next. Bytes that equal MAGIC or ESCAPE (0xfd) are sent as ESCAPE followed by the byte XOR 0x20. A packet holds one or more messages and ends with the CRC-8 of them, with the polynomial of the Dallas 1-Wire bus. Every message starts with its length, followed by the address stack, which only holds its terminating 0 for the node itself, the sequence number and the type of the message. The node numbers its own messages from 1 to 255, and starts over at 1.
+//!
+//! Messages of the host start with their length, which is below 0x20 for every message that the node handles, so bytes outside of packets still belong to the text protocol if a printable character follows a MAGIC.
+
+use arrayvec::ArrayVec;
+
+use crate::commands::AspectCommand;
+
+/// Baud rate of the serial link.
+pub const BAUD_RATE: u32 = 115_200;
+/// Longest packet that is received. Longer packets are ignored.
+const PACKET_CAPACITY: u8 = 48;
+/// Longest message that the node sends, with its length byte.
+const MAX_MESSAGE_LENGTH: usize = 14;
+const MAGIC: u8 = 0xfe;
+const ESCAPE: u8 = 0xfd;
+/// Class of the node in its unique ID: a node with accessories.
+const CLASS_ACCESSORY: u8 = 0x04;
+/// Vendor ID for nodes that are built by their users.
+const VENDOR_DIY: u8 = 0x0d;
+/// Protocol version that the node implements, minor version first.
+const PROTOCOL_VERSION: [u8; 2] = [8, 0];
+
+// Messages of the host.
+const MSG_SYS_GET_MAGIC: u8 = 0x01;
+const MSG_SYS_GET_P_VERSION: u8 = 0x02;
+const MSG_SYS_ENABLE: u8 = 0x03;
+const MSG_SYS_DISABLE: u8 = 0x04;
+const MSG_SYS_GET_UNIQUE_ID: u8 = 0x05;
+const MSG_SYS_GET_SW_VERSION: u8 = 0x06;
+const MSG_SYS_PING: u8 = 0x07;
+const MSG_SYS_IDENTIFY: u8 = 0x08;
+const MSG_SYS_RESET: u8 = 0x09;
+const MSG_GET_PKT_CAPACITY: u8 = 0x0a;
+const MSG_NODETAB_GETALL: u8 = 0x0b;
+const MSG_NODETAB_GETNEXT: u8 = 0x0c;
+const MSG_SYS_GET_ERROR: u8 = 0x0e;
+const MSG_FEATURE_GETALL: u8 = 0x10;
+const MSG_FEATURE_GETNEXT: u8 = 0x11;
+const MSG_FEATURE_GET: u8 = 0x12;
+const MSG_FEATURE_SET: u8 = 0x13;
+const MSG_ACCESSORY_SET: u8 = 0x38;
+const MSG_ACCESSORY_GET: u8 = 0x39;
+
+// Messages of the node.
+const MSG_SYS_MAGIC: u8 = 0x81;
+const MSG_SYS_PONG: u8 = 0x82;
+const MSG_SYS_P_VERSION: u8 = 0x83;
+const MSG_SYS_UNIQUE_ID: u8 = 0x84;
+const MSG_SYS_SW_VERSION: u8 = 0x85;
+const MSG_SYS_ERROR: u8 = 0x86;
+const MSG_SYS_IDENTIFY_STATE: u8 = 0x87;
+const MSG_NODETAB_COUNT: u8 = 0x88;
+const MSG_NODETAB: u8 = 0x89;
+const MSG_PKT_CAPACITY: u8 = 0x8a;
+const MSG_NODE_NA: u8 = 0x8b;
+const MSG_FEATURE: u8 = 0x90;
+const MSG_FEATURE_NA: u8 = 0x91;
+const MSG_FEATURE_COUNT: u8 = 0x92;
+const MSG_ACCESSORY_STATE: u8 = 0xb8;
+const MSG_ACCESSORY_NOTIFY: u8 = 0xba;
+
+/// Features of the node and their values, which the host can’t change: a single accessory, whose changes are reported, and which has no macros.
+const FEATURES: [(u8, u8); 3] = [(40, 1), (41, 1), (42, 0)];
+/// Bit of the execution state of an accessory that marks an error, whose code is sent instead of the waiting time, and the code for aspects that the accessory doesn’t have or rejected.
+const EXECUTE_ERROR: u8 = 0x80;
+const ERROR_VOID: u8 = 0x01;
+/// Aspect number of an accessory whose aspect is unknown.
+const UNKNOWN_ASPECT: u8 = 0xff;
+
+/// Parses a decimal version number at compile time.
+const fn version_number(version: &str) -> u8 {
+    let digits = version.as_bytes();
+    let mut number = 0;
+    let mut index = 0;
+    while index < digits.len() {
+        number = number * 10 + (digits[index] - b'0');
+        index += 1;
+    }
+    number
+}
+
+/// Software version, patch version first.
+const SOFTWARE_VERSION: [u8; 3] = [
+    version_number(env!("CARGO_PKG_VERSION_PATCH")),
+    version_number(env!("CARGO_PKG_VERSION_MINOR")),
+    version_number(env!("CARGO_PKG_VERSION_MAJOR")),
+];
+
+/// Returns the CRC-8 of the given bytes, which is 0 for bytes that end with their own CRC.
+pub(crate) fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 1 != 0 {
+                crc >> 1 ^ 0x8c
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// What a received byte was.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BidibByte {
+    /// A byte outside of packets, which belongs to the text protocol.
+    Text(u8),
+    /// A byte of a packet.
+    Packet,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Text,
+    Packet { is_escaped: bool },
+    // A packet that is too long, which is skipped until the next MAGIC.
+    Skipping,
+}
+
+/// A BiDiB node with a single accessory, which answers the messages of the host, and reports the aspect shown by the signal.
+///
+/// Bytes are received with [`Self::receive_byte`], aspect changes requested by the host are taken with [`Self::take_request`], and the packets to send are taken with [`Self::next_packet`].
+pub struct BidibNode {
+    unique_id: [u8; 7],
+    // Aspect of each aspect number of the accessory.
+    aspects: &'static [Option<AspectCommand>],
+    state: State,
+    packet: ArrayVec<u8, { PACKET_CAPACITY as usize }>,
+    // Sequence number of the last message that the node sent.
+    sequence: u8,
+    // Whether the host enabled spontaneous messages, which report aspect changes.
+    is_enabled: bool,
+    // Position of the next entry with NODETAB_GETNEXT and FEATURE_GETNEXT.
+    next_node: u8,
+    next_feature: usize,
+    // Aspect number that the host requested, until the request is taken, and until it is answered.
+    request: Option<u8>,
+    pending_state: Option<u8>,
+    shown_aspect: Option<AspectCommand>,
+    // Messages to send, without their sequence number. Messages that don’t fit are dropped, and the host asks again.
+    replies: ArrayVec<ArrayVec<u8, MAX_MESSAGE_LENGTH>, 4>,
+}
+
+impl BidibNode {
+    /// Creates a node with the given product ID and serial number, which are the last four bytes of its unique ID.
+    pub fn new(product_id_and_serial: [u8; 4], aspects: &'static [Option<AspectCommand>]) -> Self {
+        let mut unique_id = [CLASS_ACCESSORY, 0, VENDOR_DIY, 0, 0, 0, 0];
+        unique_id[3..].copy_from_slice(&product_id_and_serial);
+        Self {
+            unique_id,
+            aspects,
+            state: State::Text,
+            packet: ArrayVec::new(),
+            sequence: 0,
+            is_enabled: false,
+            next_node: 0,
+            next_feature: 0,
+            request: None,
+            pending_state: None,
+            shown_aspect: None,
+            replies: ArrayVec::new(),
+        }
+    }
+
+    /// Processes a byte received on the serial port.
+    pub fn receive_byte(&mut self, byte: u8) -> BidibByte {
+        self.state = match (self.state, byte) {
+            (State::Text, MAGIC) => State::Packet { is_escaped: false },
+            (State::Text, _) => return BidibByte::Text(byte),
+            (_, MAGIC) => {
+                if matches!(self.state, State::Packet { .. }) && crc8(&self.packet) == 0 {
+                    let packet = core::mem::take(&mut self.packet);
+                    self.handle_packet(&packet[..packet.len().saturating_sub(1)]);
+                }
+                self.packet.clear();
+                State::Packet { is_escaped: false }
+            }
+            (State::Packet { is_escaped: false }, 0x20..=0x7e) if self.packet.is_empty() => {
+                self.state = State::Text;
+                return BidibByte::Text(byte);
+            }
+            (State::Packet { is_escaped: false }, ESCAPE) => State::Packet { is_escaped: true },
+            (State::Packet { is_escaped }, _) => {
+                let byte = if is_escaped { byte ^ 0x20 } else { byte };
+                if self.packet.try_push(byte).is_ok() {
+                    State::Packet { is_escaped: false }
+                } else {
+                    State::Skipping
+                }
+            }
+            (State::Skipping, _) => State::Skipping,
+        };
+        BidibByte::Packet
+    }
+
+    /// Handles the messages of a packet, whose CRC was checked and removed.
+    fn handle_packet(&mut self, mut packet: &[u8]) {
+        while let [length, ref rest @ ..] = *packet {
+            if usize::from(length) > rest.len() {
+                return;
+            }
+            let (message, rest) = rest.split_at(usize::from(length));
+            packet = rest;
+            // messages for subnodes, which the node doesn’t have, are ignored.
+            if let [0, _sequence, message_type, ref data @ ..] = *message {
+                self.handle_message(message_type, data);
+            }
+        }
+    }
+
+    fn handle_message(&mut self, message_type: u8, data: &[u8]) {
+        match (message_type, data) {
+            (MSG_SYS_GET_MAGIC, _) => {
+                // the magic is always sent with the sequence number 0, and restarts the sequence.
+                self.replies.clear();
+                self.sequence = 0;
+                self.reply(MSG_SYS_MAGIC, &[0xfe, 0xaf]);
+            }
+            (MSG_SYS_GET_P_VERSION, _) => self.reply(MSG_SYS_P_VERSION, &PROTOCOL_VERSION),
+            (MSG_SYS_ENABLE, _) => self.is_enabled = true,
+            (MSG_SYS_DISABLE, _) => self.is_enabled = false,
+            (MSG_SYS_GET_UNIQUE_ID, _) => {
+                let unique_id = self.unique_id;
+                self.reply(MSG_SYS_UNIQUE_ID, &unique_id);
+            }
+            (MSG_SYS_GET_SW_VERSION, _) => self.reply(MSG_SYS_SW_VERSION, &SOFTWARE_VERSION),
+            (MSG_SYS_PING, &[data]) => self.reply(MSG_SYS_PONG, &[data]),
+            (MSG_SYS_IDENTIFY, &[state]) => self.reply(MSG_SYS_IDENTIFY_STATE, &[state]),
+            (MSG_SYS_RESET, _) => {
+                self.replies.clear();
+                self.sequence = 0;
+                self.is_enabled = false;
+                self.pending_state = None;
+            }
+            (MSG_GET_PKT_CAPACITY, _) => self.reply(MSG_PKT_CAPACITY, &[PACKET_CAPACITY]),
+            (MSG_SYS_GET_ERROR, _) => self.reply(MSG_SYS_ERROR, &[0]),
+            // the node table only holds the node itself, with the local address 0.
+            (MSG_NODETAB_GETALL, _) => {
+                self.next_node = 0;
+                self.reply(MSG_NODETAB_COUNT, &[1]);
+            }
+            (MSG_NODETAB_GETNEXT, _) if self.next_node == 0 => {
+                self.next_node = 1;
+                let mut entry = [1, 0, 0, 0, 0, 0, 0, 0, 0];
+                entry[2..].copy_from_slice(&self.unique_id);
+                self.reply(MSG_NODETAB, &entry);
+            }
+            (MSG_NODETAB_GETNEXT, _) => self.reply(MSG_NODE_NA, &[0xff]),
+            (MSG_FEATURE_GETALL, _) => {
+                self.next_feature = 0;
+                self.reply(MSG_FEATURE_COUNT, &[FEATURES.len() as u8]);
+            }
+            (MSG_FEATURE_GETNEXT, _) => match FEATURES.get(self.next_feature) {
+                Some((feature, value)) => {
+                    self.next_feature += 1;
+                    self.reply(MSG_FEATURE, &[*feature, *value]);
+                }
+                None => self.reply(MSG_FEATURE_NA, &[0xff]),
+            },
+            (MSG_FEATURE_GET | MSG_FEATURE_SET, &[feature, ..]) => {
+                match FEATURES.iter().find(|(number, _)| *number == feature) {
+                    Some((feature, value)) => self.reply(MSG_FEATURE, &[*feature, *value]),
+                    None => self.reply(MSG_FEATURE_NA, &[feature]),
+                }
+            }
+            (MSG_ACCESSORY_SET, &[0, aspect_number]) => {
+                if matches!(self.aspects.get(usize::from(aspect_number)), Some(Some(_))) {
+                    self.request = Some(aspect_number);
+                } else {
+                    self.reply_state(MSG_ACCESSORY_STATE, Some(aspect_number));
+                }
+            }
+            (MSG_ACCESSORY_GET, &[0]) => self.reply_state(MSG_ACCESSORY_STATE, None),
+            _ => {}
+        }
+    }
+
+    fn reply(&mut self, message_type: u8, data: &[u8]) {
+        let mut message = ArrayVec::new();
+        message.extend([3 + data.len() as u8, 0, 0, message_type]);
+        message.extend(data.iter().copied());
+        let _ = self.replies.try_push(message);
+    }
+
+    /// Sends the state of the accessory, which is an error if the given aspect number was requested, but isn’t shown.
+    fn reply_state(&mut self, message_type: u8, requested: Option<u8>) {
+        let shown = self
+            .aspects
+            .iter()
+            .position(|aspect| aspect.is_some() && *aspect == self.shown_aspect)
+            .map_or(UNKNOWN_ASPECT, |number| number as u8);
+        let (execute, wait) = match requested {
+            Some(requested) if requested != shown => (EXECUTE_ERROR, ERROR_VOID),
+            _ => (0, 0),
+        };
+        self.reply(
+            message_type,
+            &[0, shown, self.aspects.len() as u8, execute, wait],
+        );
+    }
+
+    /// Returns the aspect that the host requested, if any. [`Self::report_aspect`] answers the request with the aspect that the signal shows afterwards.
+    pub fn take_request(&mut self) -> Option<AspectCommand> {
+        let number = self.request.take()?;
+        self.pending_state = Some(number);
+        self.aspects[usize::from(number)]
+    }
+
+    /// Records the aspect that the signal shows, which must be called after the requests were handled. Answers the last request, or reports the aspect if it changed and the host enabled it.
+    pub fn report_aspect(&mut self, aspect: Option<AspectCommand>) {
+        let has_changed = self.shown_aspect != aspect;
+        self.shown_aspect = aspect;
+        if let Some(requested) = self.pending_state.take() {
+            self.reply_state(MSG_ACCESSORY_STATE, Some(requested));
+        } else if has_changed && self.is_enabled {
+            self.reply_state(MSG_ACCESSORY_NOTIFY, None);
+        }
+    }
+
+    /// Returns the next packet to send, if any, with its own sequence number.
+    pub fn next_packet(&mut self) -> Option<ArrayVec<u8, { 2 * MAX_MESSAGE_LENGTH + 4 }>> {
+        let mut message = self.replies.pop_at(0)?;
+        if message[3] != MSG_SYS_MAGIC {
+            self.sequence = self.sequence % 0xff + 1;
+            message[2] = self.sequence;
+        }
+        let crc = crc8(&message);
+        let mut packet = ArrayVec::new();
+        packet.push(MAGIC);
+        for byte in message.into_iter().chain([crc]) {
+            if matches!(byte, MAGIC | ESCAPE) {
+                packet.extend([ESCAPE, byte ^ 0x20]);
+            } else {
+                packet.push(byte);
+            }
+        }
+        packet.push(MAGIC);
+        Some(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crc8;
+    use super::BidibByte;
+    use super::BidibNode;
+    use crate::commands::AspectCommand;
+
+    const ASPECTS: [Option<AspectCommand>; 3] =
+        [Some(AspectCommand::Zero), None, Some(AspectCommand::Two)];
+
+    /// Returns the packet of a message of the host for the node.
+    fn packet(message_type: u8, data: &[u8]) -> Vec<u8> {
+        let mut message = vec![3 + data.len() as u8, 0, 1, message_type];
+        message.extend(data);
+        message.push(crc8(&message));
+        let mut packet = vec![0xfe];
+        for byte in message {
+            if matches!(byte, 0xfe | 0xfd) {
+                packet.extend([0xfd, byte ^ 0x20]);
+            } else {
+                packet.push(byte);
+            }
+        }
+        packet.push(0xfe);
+        packet
+    }
+
+    /// Sends the bytes to the node, and returns the messages of the packets that it sends, without escaping and CRC.
+    fn exchange(node: &mut BidibNode, bytes: &[u8]) -> Vec<Vec<u8>> {
+        for byte in bytes {
+            node.receive_byte(*byte);
+        }
+        let mut messages = Vec::new();
+        while let Some(packet) = node.next_packet() {
+            let mut message = Vec::new();
+            let mut bytes = packet[1..packet.len() - 1].iter();
+            while let Some(byte) = bytes.next() {
+                message.push(if *byte == 0xfd {
+                    bytes.next().unwrap() ^ 0x20
+                } else {
+                    *byte
+                });
+            }
+            assert_eq!(crc8(&message), 0);
+            message.pop();
+            messages.push(message);
+        }
+        messages
+    }
+
+    #[test]
+    fn crc_matches_check_value() {
+        assert_eq!(crc8(b"123456789"), 0xa1);
+    }
+
+    #[test]
+    fn enumerates_the_node_between_text() {
+        let mut node = BidibNode::new([0x12, 0x34, 0x56, 0x78], &ASPECTS);
+        let mut bytes: Vec<u8> = b"F:1\n".to_vec();
+        bytes.extend(packet(0x01, &[]));
+        let received: Vec<BidibByte> = bytes.iter().map(|byte| node.receive_byte(*byte)).collect();
+        assert_eq!(received[..4], b"F:1\n".map(BidibByte::Text));
+        // a printable character after the closing MAGIC is text again.
+        assert_eq!(node.receive_byte(b'F'), BidibByte::Text(b'F'));
+        assert_eq!(exchange(&mut node, &[]), [vec![5, 0, 0, 0x81, 0xfe, 0xaf]]);
+        let replies: Vec<Vec<u8>> = [
+            packet(0x05, &[]),
+            packet(0x0b, &[]),
+            packet(0x0c, &[]),
+            packet(0x0c, &[]),
+            packet(0x10, &[]),
+            packet(0x11, &[]),
+            packet(0x12, &[42]),
+        ]
+        .iter()
+        .flat_map(|bytes| exchange(&mut node, bytes))
+        .collect();
+        assert_eq!(
+            replies,
+            [
+                vec![10, 0, 1, 0x84, 0x04, 0, 0x0d, 0x12, 0x34, 0x56, 0x78],
+                vec![4, 0, 2, 0x88, 1],
+                vec![12, 0, 3, 0x89, 1, 0, 0x04, 0, 0x0d, 0x12, 0x34, 0x56, 0x78],
+                vec![4, 0, 4, 0x8b, 0xff],
+                vec![4, 0, 5, 0x92, 3],
+                vec![5, 0, 6, 0x90, 40, 1],
+                vec![5, 0, 7, 0x90, 42, 0],
+            ]
+        );
+        // broken CRCs are ignored.
+        let mut broken = packet(0x07, &[1]);
+        broken[5] ^= 1;
+        assert!(exchange(&mut node, &broken).is_empty());
+    }
+
+    #[test]
+    fn sets_and_reports_the_aspect() {
+        let mut node = BidibNode::new([0; 4], &ASPECTS);
+        node.report_aspect(Some(AspectCommand::Zero));
+        exchange(&mut node, &packet(0x03, &[]));
+        assert!(exchange(&mut node, &packet(0x38, &[0, 2])).is_empty());
+        assert!(node.take_request() == Some(AspectCommand::Two));
+        node.report_aspect(Some(AspectCommand::Two));
+        assert_eq!(
+            exchange(&mut node, &[]),
+            [vec![8, 0, 1, 0xb8, 0, 2, 3, 0, 0]]
+        );
+        // aspects that the accessory doesn’t have, and changes from other sources.
+        assert_eq!(
+            exchange(&mut node, &packet(0x38, &[0, 1])),
+            [vec![8, 0, 2, 0xb8, 0, 2, 3, 0x80, 0x01]]
+        );
+        node.report_aspect(Some(AspectCommand::One));
+        assert_eq!(
+            exchange(&mut node, &packet(0x39, &[0])),
+            [
+                vec![8, 0, 3, 0xba, 0, 0xff, 3, 0, 0],
+                vec![8, 0, 4, 0xb8, 0, 0xff, 3, 0, 0]
+            ]
+        );
+    }
+}
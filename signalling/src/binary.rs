@@ -0,0 +1,141 @@
+//! Module for the binary protocol, in which every command and every reply is a frame with a CRC, so that a line that was corrupted on a long, noisy cable is dropped instead of being taken for another command.
+//!
+//! A frame is `SOF address opcode length payload CRC`: the start of frame 0xa5, the address of the controller, the opcode, the length of the payload and the payload, followed by the CRC-8 of the address, opcode, length and payload, with the polynomial of the Dallas 1-Wire bus like in BiDiB. Frames for other addresses and frames with a wrong CRC are ignored, and the host repeats a command that isn’t answered.
+//!
+//! Frames with the opcode TEXT carry the text protocol: the payload of a host frame is appended to the received text, and the controller sends its text in TEXT frames, so that every line of the text protocol works unchanged. Lines may be split across several frames. Frames with the opcode ASPECT switch the signal more compactly, with the number of the aspect like in the DCC configuration options and the speed of the Zs3 indicator, or 0 for none.
+
+use arrayvec::ArrayVec;
+
+use crate::bidib::crc8;
+use crate::dcc::MappedAspect;
+
+pub const START_OF_FRAME: u8 = 0xa5;
+/// Longest payload of a frame. Longer frames are ignored.
+pub const MAX_PAYLOAD: usize = 64;
+/// Longest frame, with its start of frame, address, opcode, length and CRC.
+pub const FRAME_CAPACITY: usize = MAX_PAYLOAD + 5;
+const OPCODE_TEXT: u8 = 0x01;
+const OPCODE_ASPECT: u8 = 0x02;
+
+/// A command of the host.
+pub enum BinaryRequest {
+    /// Text of the text protocol, which may be only part of a line.
+    Text(ArrayVec<u8, MAX_PAYLOAD>),
+    /// Switches the signal to the aspect with the speed.
+    Aspect(MappedAspect),
+}
+
+/// Receives the frames of the host, byte by byte.
+#[derive(Default)]
+pub struct FrameReceiver {
+    // The frame received so far, without its start of frame, or None while waiting for the start of a frame.
+    frame: Option<ArrayVec<u8, FRAME_CAPACITY>>,
+}
+
+impl FrameReceiver {
+    pub const fn new() -> Self {
+        Self { frame: None }
+    }
+
+    /// Processes a received byte, and returns the command of the frame that it completes, if the frame is for the given address and intact.
+    pub fn receive_byte(&mut self, byte: u8, address: u8) -> Option<BinaryRequest> {
+        let Some(frame) = &mut self.frame else {
+            if byte == START_OF_FRAME {
+                self.frame = Some(ArrayVec::new());
+            }
+            return None;
+        };
+        frame.push(byte);
+        let length = match frame.as_slice() {
+            [_, _, length, ..] if usize::from(*length) > MAX_PAYLOAD => {
+                self.frame = None;
+                return None;
+            }
+            [_, _, length, ..] => usize::from(*length),
+            _ => return None,
+        };
+        if frame.len() < length + 4 {
+            return None;
+        }
+        let frame = self.frame.take()?;
+        let [frame_address, opcode, _, payload @ .., _] = frame.as_slice() else {
+            return None;
+        };
+        if crc8(&frame) != 0 || *frame_address != address {
+            return None;
+        }
+        match (*opcode, payload) {
+            (OPCODE_TEXT, text) => Some(BinaryRequest::Text(text.try_into().ok()?)),
+            (OPCODE_ASPECT, [number, speed]) => {
+                MappedAspect::new(*number, *speed).map(BinaryRequest::Aspect)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the TEXT frame with the given text of the controller with the given address, which must be at most [`MAX_PAYLOAD`] bytes long.
+pub fn text_frame(address: u8, text: &[u8]) -> ArrayVec<u8, FRAME_CAPACITY> {
+    let mut frame = ArrayVec::new();
+    frame.push(START_OF_FRAME);
+    frame.push(address);
+    frame.push(OPCODE_TEXT);
+    frame.push(text.len() as u8);
+    frame.try_extend_from_slice(text).unwrap();
+    frame.push(crc8(&frame[1..]));
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::text_frame;
+    use super::BinaryRequest;
+    use super::FrameReceiver;
+    use super::OPCODE_ASPECT;
+    use super::START_OF_FRAME;
+    use crate::bidib::crc8;
+    use crate::commands::AspectCommand;
+
+    fn receive(receiver: &mut FrameReceiver, bytes: &[u8]) -> Vec<BinaryRequest> {
+        bytes
+            .iter()
+            .filter_map(|byte| receiver.receive_byte(*byte, 7))
+            .collect()
+    }
+
+    #[test]
+    fn receives_text_and_aspects_for_the_address() {
+        let mut receiver = FrameReceiver::new();
+        let text = text_frame(7, b"F:1\n");
+        let mut aspect = vec![START_OF_FRAME, 7, OPCODE_ASPECT, 2, 3, 6];
+        aspect.push(crc8(&aspect[1..]));
+        // bytes outside of frames and frames for other addresses are ignored.
+        let bytes = [
+            b"F:0\n".as_slice(),
+            &text_frame(8, b"F:0\n"),
+            &text,
+            &aspect,
+        ]
+        .concat();
+        let requests = receive(&mut receiver, &bytes);
+        assert_eq!(requests.len(), 2);
+        assert!(matches!(&requests[0], BinaryRequest::Text(text) if text.as_slice() == b"F:1\n"));
+        assert!(matches!(
+            requests[1],
+            BinaryRequest::Aspect(mapped) if mapped.aspect == AspectCommand::Two && mapped.speed == Some(6)
+        ));
+    }
+
+    #[test]
+    fn drops_corrupted_frames() {
+        let mut receiver = FrameReceiver::new();
+        let mut corrupted = text_frame(7, b"F:1\n");
+        corrupted[5] = b'2';
+        assert!(receive(&mut receiver, &corrupted).is_empty());
+        // the next frame is received again.
+        assert_eq!(receive(&mut receiver, &text_frame(7, b"F:0\n")).len(), 1);
+        // so is a frame after one that is too long.
+        assert!(receive(&mut receiver, &[START_OF_FRAME, 7, 1, 200]).is_empty());
+        assert_eq!(receive(&mut receiver, &text_frame(7, b"F:0\n")).len(), 1);
+    }
+}
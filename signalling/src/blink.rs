@@ -0,0 +1,45 @@
+//! Module for timing blinking lamps without blocking the main loop.
+
+/// Keeps track of the phase of a blinking lamp.
+///
+/// The blinker doesn’t own any lamps; it only tells its user when the lamps need to be toggled. Time is given in milliseconds since boot, as returned by the clock.
+pub struct Blinker {
+    // Duration of each on and off phase.
+    half_period_ms: u32,
+    // Time of the last phase change.
+    last_toggle: u32,
+    // Whether the lamp is currently in its on phase.
+    is_on: bool,
+}
+
+impl Blinker {
+    /// Creates a new blinker with the given duration for the on and off phases each.
+    pub const fn new(half_period_ms: u32) -> Self {
+        Self {
+            half_period_ms,
+            last_toggle: 0,
+            is_on: false,
+        }
+    }
+
+    /// Restarts the blinking at the beginning of the on phase.
+    pub fn restart(&mut self, now: u32) {
+        self.last_toggle = now;
+        self.is_on = true;
+    }
+
+    /// Returns whether the lamp is currently in its on phase.
+    pub fn is_on(&self) -> bool {
+        self.is_on
+    }
+
+    /// Advances the blinker to the current time. If the phase changed, the new phase is returned (true meaning on).
+    pub fn update(&mut self, now: u32) -> Option<bool> {
+        if now.wrapping_sub(self.last_toggle) < self.half_period_ms {
+            return None;
+        }
+        self.last_toggle = now;
+        self.is_on = !self.is_on;
+        Some(self.is_on)
+    }
+}
@@ -0,0 +1,303 @@
+//! Module for the C/MRI serial protocol, in which the signal works as an SMINI node, so that JMRI can poll it and switch its aspects with its built-in C/MRI support.
+//!
+//! The host sends every packet as `SYN SYN STX UA TYPE data ETX`, where SYN is 0xff, STX 0x02, ETX 0x03, and UA is 65 plus the node address, which lets many nodes share an RS-485 bus. Data bytes that equal STX, ETX or DLE (0x10) are preceded by a DLE. The host initializes each node once (`I`), transmits the node’s outputs whenever they change (`T`), and polls it for its inputs (`P`), which the node answers with a packet of the same form (`R`). An SMINI has 48 outputs and 24 inputs.
+//!
+//! Text is never sent in SYN bytes, so bytes outside of packets still belong to the text protocol.
+
+use arrayvec::ArrayVec;
+
+use crate::commands::AspectCommand;
+
+/// Highest node address.
+pub const MAX_NODE_ADDRESS: u8 = 127;
+/// Number of output and input bytes of an SMINI.
+pub const OUTPUT_BYTES: usize = 6;
+pub const INPUT_BYTES: usize = 3;
+const SYN: u8 = 0xff;
+const STX: u8 = 0x02;
+const ETX: u8 = 0x03;
+const DLE: u8 = 0x10;
+/// Offset of the node address in the UA byte.
+const ADDRESS_OFFSET: u8 = 65;
+/// Longest data of a packet that is received, which is that of an initialization with every card type byte of an SMINI. Longer packets are ignored.
+const MAX_DATA_LENGTH: usize = 10;
+/// Longest reception packet, with every input byte escaped.
+const MAX_RECEPTION_LENGTH: usize = 6 + 2 * INPUT_BYTES;
+
+/// A request of the host for this node.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CmriRequest {
+    /// The host initializes the node, which an SMINI doesn’t need.
+    Initialize,
+    /// The host polls the inputs, which must be answered with [`reception`].
+    Poll,
+    /// The host sets the outputs.
+    Transmit([u8; OUTPUT_BYTES]),
+}
+
+/// What a received byte was.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CmriByte {
+    /// A byte outside of packets, which belongs to the text protocol.
+    Text(u8),
+    /// A byte of a packet, and the request that it completes, if any.
+    Packet(Option<CmriRequest>),
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Idle,
+    Sync,
+    Address,
+    Type {
+        is_for_node: bool,
+    },
+    Data {
+        is_for_node: bool,
+        kind: u8,
+        is_escaped: bool,
+    },
+}
+
+/// Receives the packets of the host from the bytes of the serial port.
+pub struct CmriReceiver {
+    state: State,
+    // Data of the current packet, or None if it is too long.
+    data: Option<ArrayVec<u8, MAX_DATA_LENGTH>>,
+}
+
+impl Default for CmriReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CmriReceiver {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Idle,
+            data: None,
+        }
+    }
+
+    /// Processes a byte received on the serial port by the node with the given address.
+    pub fn receive_byte(&mut self, byte: u8, node_address: u8) -> CmriByte {
+        self.state = match (self.state, byte) {
+            (State::Idle, SYN) => State::Sync,
+            (State::Idle, _) => return CmriByte::Text(byte),
+            (State::Sync, SYN) => State::Sync,
+            (State::Sync, STX) => State::Address,
+            (State::Sync, _) => State::Idle,
+            (State::Address, _) => State::Type {
+                is_for_node: byte == ADDRESS_OFFSET + node_address,
+            },
+            (State::Type { is_for_node }, _) => {
+                self.data = Some(ArrayVec::new());
+                State::Data {
+                    is_for_node,
+                    kind: byte,
+                    is_escaped: false,
+                }
+            }
+            (
+                State::Data {
+                    is_for_node,
+                    kind,
+                    is_escaped: false,
+                },
+                ETX,
+            ) => {
+                self.state = State::Idle;
+                let data = self.data.take().filter(|_| is_for_node);
+                return CmriByte::Packet(data.and_then(|data| parse(kind, &data)));
+            }
+            (
+                State::Data {
+                    is_for_node,
+                    kind,
+                    is_escaped: false,
+                },
+                DLE,
+            ) => State::Data {
+                is_for_node,
+                kind,
+                is_escaped: true,
+            },
+            (
+                State::Data {
+                    is_for_node, kind, ..
+                },
+                _,
+            ) => {
+                if self
+                    .data
+                    .as_mut()
+                    .is_some_and(|data| data.try_push(byte).is_err())
+                {
+                    self.data = None;
+                }
+                State::Data {
+                    is_for_node,
+                    kind,
+                    is_escaped: false,
+                }
+            }
+        };
+        CmriByte::Packet(None)
+    }
+}
+
+/// Parses the type and the data of a complete packet for this node.
+fn parse(kind: u8, data: &[u8]) -> Option<CmriRequest> {
+    match kind {
+        b'I' => Some(CmriRequest::Initialize),
+        b'P' => Some(CmriRequest::Poll),
+        // missing bytes leave their outputs off.
+        b'T' if data.len() <= OUTPUT_BYTES => {
+            let mut outputs = [0; OUTPUT_BYTES];
+            outputs[..data.len()].copy_from_slice(data);
+            Some(CmriRequest::Transmit(outputs))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the packet that answers a poll of the node with the given address and inputs.
+pub fn reception(
+    node_address: u8,
+    inputs: [u8; INPUT_BYTES],
+) -> ArrayVec<u8, MAX_RECEPTION_LENGTH> {
+    let mut packet = ArrayVec::new();
+    packet.extend([SYN, SYN, STX, ADDRESS_OFFSET + node_address, b'R']);
+    for byte in inputs {
+        if matches!(byte, STX | ETX | DLE) {
+            packet.push(DLE);
+        }
+        packet.push(byte);
+    }
+    packet.push(ETX);
+    packet
+}
+
+/// Maps the outputs of the node to aspect commands, and the shown aspect to its inputs.
+///
+/// Like an output signal mast of JMRI, each aspect has its own output, which JMRI switches on to show the aspect. The input with the same number is on while the signal shows the aspect, so that JMRI can check it.
+pub struct CmriMapping {
+    // Aspect commanded by each output, starting at output 0.
+    aspects: &'static [Option<AspectCommand>],
+    // The outputs of the last transmission.
+    outputs: [u8; OUTPUT_BYTES],
+}
+
+impl CmriMapping {
+    pub const fn new(aspects: &'static [Option<AspectCommand>]) -> Self {
+        Self {
+            aspects,
+            outputs: [0; OUTPUT_BYTES],
+        }
+    }
+
+    /// Returns the aspect commanded by the first output that the given outputs switch on, if any.
+    pub fn aspect_for(&mut self, outputs: [u8; OUTPUT_BYTES]) -> Option<AspectCommand> {
+        let last_outputs = core::mem::replace(&mut self.outputs, outputs);
+        let is_on = |outputs: &[u8; OUTPUT_BYTES], output: usize| {
+            outputs[output / 8] >> (output % 8) & 1 != 0
+        };
+        self.aspects
+            .iter()
+            .enumerate()
+            .find(|(output, _)| is_on(&outputs, *output) && !is_on(&last_outputs, *output))
+            .and_then(|(_, aspect)| *aspect)
+    }
+
+    /// Returns the inputs that report the given aspect as shown.
+    pub fn inputs_for(&self, aspect: AspectCommand) -> [u8; INPUT_BYTES] {
+        let mut inputs = [0; INPUT_BYTES];
+        for (input, _) in self
+            .aspects
+            .iter()
+            .enumerate()
+            .take(8 * INPUT_BYTES)
+            .filter(|(_, mapped)| **mapped == Some(aspect))
+        {
+            inputs[input / 8] |= 1 << (input % 8);
+        }
+        inputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::reception;
+    use super::CmriByte;
+    use super::CmriMapping;
+    use super::CmriReceiver;
+    use super::CmriRequest;
+    use crate::commands::AspectCommand;
+
+    fn receive(receiver: &mut CmriReceiver, bytes: &[u8]) -> Vec<CmriByte> {
+        bytes
+            .iter()
+            .map(|byte| receiver.receive_byte(*byte, 5))
+            .filter(|received| *received != CmriByte::Packet(None))
+            .collect()
+    }
+
+    #[test]
+    fn receives_packets_for_its_node_between_text() {
+        let mut receiver = CmriReceiver::new();
+        // node 5 is addressed as 'F'.
+        assert_eq!(
+            receive(&mut receiver, b"F:1\n\xff\xff\x02FP\x03"),
+            [
+                CmriByte::Text(b'F'),
+                CmriByte::Text(b':'),
+                CmriByte::Text(b'1'),
+                CmriByte::Text(b'\n'),
+                CmriByte::Packet(Some(CmriRequest::Poll)),
+            ]
+        );
+        // escaped bytes, and missing output bytes.
+        assert_eq!(
+            receive(&mut receiver, b"\xff\xff\x02FT\x10\x02\x10\x10\x01\x03"),
+            [CmriByte::Packet(Some(CmriRequest::Transmit([
+                0x02, 0x10, 0x01, 0, 0, 0
+            ])))]
+        );
+        // other nodes and too many output bytes.
+        assert!(receive(&mut receiver, b"\xff\xff\x02GP\x03").is_empty());
+        assert!(receive(
+            &mut receiver,
+            b"\xff\xff\x02FT\x01\x04\x05\x06\x07\x08\x09\x03"
+        )
+        .is_empty());
+        assert_eq!(
+            *reception(5, [0x01, 0x03, 0x80]),
+            [0xff, 0xff, 0x02, b'F', b'R', 0x01, 0x10, 0x03, 0x80, 0x03]
+        );
+    }
+
+    #[test]
+    fn outputs_switch_aspects_and_inputs_report_them() {
+        const ASPECTS: [Option<AspectCommand>; 10] = [
+            Some(AspectCommand::Zero),
+            Some(AspectCommand::One),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(AspectCommand::Zero),
+        ];
+        let mut mapping = CmriMapping::new(&ASPECTS);
+        assert!(mapping.aspect_for([0b10, 0, 0, 0, 0, 0]) == Some(AspectCommand::One));
+        // outputs that stay on don’t command their aspect again.
+        assert!(mapping.aspect_for([0b10, 0, 0, 0, 0, 0]).is_none());
+        assert!(mapping.aspect_for([0b01, 0, 0, 0, 0, 0]) == Some(AspectCommand::Zero));
+        assert!(mapping.aspect_for([0b01, 0b10, 0, 0, 0, 0]) == Some(AspectCommand::Zero));
+        assert_eq!(mapping.inputs_for(AspectCommand::Zero), [0b01, 0b10, 0]);
+        assert_eq!(mapping.inputs_for(AspectCommand::Two), [0, 0, 0]);
+    }
+}
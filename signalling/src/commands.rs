@@ -0,0 +1,1094 @@
+//! Module for parsing serial commands.
+
+use crate::config::ConfigKey;
+use crate::config::MAPPED_LAMPS;
+use crate::dcc::MAPPED_ASPECT_NUMBERS;
+use crate::level_crossing::LevelCrossingCommand;
+use crate::maintenance::RawLampState;
+use crate::signals::LampRole;
+
+use arrayvec::ArrayString;
+
+/// Why a line wasn’t executed. Every error but [`CommandError::Ignored`] is answered with its numeric code in an `E` response, which control software can parse, and unless only terse errors are enabled, with a text from the message catalog as a comment.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CommandError {
+    /// The line is empty, or not intended for this signal, and is ignored without a response.
+    Ignored,
+    /// The line is empty, ends in a carriage return, or doesn’t start with a signal ID, and the parser is strict.
+    MalformedLine,
+    MissingSignalId,
+    MissingCommand,
+    UnknownCommand,
+    InvalidRawLampCommand,
+    InvalidArmCommand,
+    InvalidNextAspectCommand,
+    InvalidSpeedOrRoute,
+    InvalidStressTestCommand,
+    InvalidLevelCrossingCommand,
+    InvalidCloneCommand,
+    UnknownConfigOption,
+    InvalidConfigValue,
+    ValueOutOfRange,
+    /// The signal can’t show the aspect, or lacks the hardware that the command needs.
+    Unsupported,
+    /// An electrical failure kept the signal from showing the aspect, and it fell back to stop.
+    FellBackToStop,
+    /// An electrical failure kept the signal from showing the aspect, and even from falling back to stop, so it is dark.
+    FellBackToDark,
+    /// The maintenance lock doesn’t allow the command.
+    Locked,
+    AuthenticationFailed,
+    /// The aspect blanks the signal, but wasn’t armed.
+    NotArmed,
+    ChecksumMismatch,
+    ChecksumMissing,
+    /// An emergency stop is latched.
+    EmergencyStop,
+    /// The queue of aspect commands that wait for the minimum dwell time is full.
+    Busy,
+    /// The aspect clears the signal, but the signal hasn’t held stop for the minimum time yet.
+    StopHeld,
+}
+
+impl CommandError {
+    /// Returns the code of the error in the `E` response, which is different for every error.
+    pub fn code(self) -> u8 {
+        match self {
+            // ignored lines get no response, so this code is never sent.
+            Self::Ignored => u8::MAX,
+            Self::MalformedLine => 0,
+            Self::Unsupported => 1,
+            Self::FellBackToStop => 2,
+            Self::FellBackToDark => 3,
+            Self::Locked => 4,
+            Self::AuthenticationFailed => 5,
+            Self::NotArmed => 6,
+            Self::ChecksumMismatch => 7,
+            Self::EmergencyStop => 8,
+            Self::Busy => 9,
+            Self::StopHeld => 10,
+            Self::MissingSignalId => 11,
+            Self::MissingCommand => 12,
+            Self::UnknownCommand => 13,
+            Self::InvalidRawLampCommand => 14,
+            Self::InvalidArmCommand => 15,
+            Self::InvalidNextAspectCommand => 16,
+            Self::InvalidSpeedOrRoute => 17,
+            Self::InvalidStressTestCommand => 18,
+            Self::InvalidLevelCrossingCommand => 19,
+            Self::InvalidCloneCommand => 20,
+            Self::UnknownConfigOption => 21,
+            Self::InvalidConfigValue => 22,
+            Self::ValueOutOfRange => 23,
+            Self::ChecksumMissing => 24,
+        }
+    }
+
+    /// Returns the text of the error from the message catalog.
+    #[cfg(not(feature = "terse-errors"))]
+    fn message(self) -> &'static str {
+        use crate::messages::*;
+        match self {
+            Self::Ignored => "",
+            Self::MalformedLine => MALFORMED_LINE,
+            Self::MissingSignalId => MISSING_SIGNAL_ID,
+            Self::MissingCommand => MISSING_COMMAND,
+            Self::UnknownCommand => UNKNOWN_COMMAND,
+            Self::InvalidRawLampCommand => INVALID_RAW_LAMP_COMMAND,
+            Self::InvalidArmCommand => INVALID_ARM_COMMAND,
+            Self::InvalidNextAspectCommand => INVALID_NEXT_ASPECT_COMMAND,
+            Self::InvalidSpeedOrRoute => INVALID_SPEED_OR_ROUTE,
+            Self::InvalidStressTestCommand => INVALID_STRESS_TEST_COMMAND,
+            Self::InvalidLevelCrossingCommand => INVALID_LEVEL_CROSSING_COMMAND,
+            Self::InvalidCloneCommand => INVALID_CLONE_COMMAND,
+            Self::UnknownConfigOption => UNKNOWN_CONFIG_OPTION,
+            Self::InvalidConfigValue => INVALID_CONFIG_VALUE,
+            Self::ValueOutOfRange => VALUE_OUT_OF_RANGE,
+            Self::Unsupported => UNSUPPORTED,
+            Self::FellBackToStop => FELL_BACK_TO_STOP,
+            Self::FellBackToDark => FELL_BACK_TO_DARK,
+            Self::Locked => LOCKED,
+            Self::AuthenticationFailed => AUTHENTICATION_FAILED,
+            Self::NotArmed => NOT_ARMED,
+            Self::ChecksumMismatch => CHECKSUM_MISMATCH,
+            Self::ChecksumMissing => CHECKSUM_MISSING,
+            Self::EmergencyStop => EMERGENCY_STOP,
+            Self::Busy => BUSY,
+            Self::StopHeld => STOP_HELD,
+        }
+    }
+
+    /// Returns the response of the signal with the given ID to this error.
+    pub fn response(self, signal_id: &str) -> ErrorResponse<'_> {
+        ErrorResponse {
+            signal_id,
+            error: self,
+        }
+    }
+}
+
+/// The `E` response to an error, without the line ending.
+pub struct ErrorResponse<'a> {
+    signal_id: &'a str,
+    error: CommandError,
+}
+
+impl ufmt::uDisplay for ErrorResponse<'_> {
+    fn fmt<W>(&self, formatter: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        ufmt::uwrite!(formatter, "{}:E:{}", self.signal_id, self.error.code())?;
+        #[cfg(not(feature = "terse-errors"))]
+        ufmt::uwrite!(formatter, "#{}", self.error.message())?;
+        Ok(())
+    }
+}
+
+/// Maximum length of the signal ID that a configuration is cloned to, including a layout segment filter.
+pub const MAX_CLONE_TARGET_LENGTH: usize = 32;
+
+/// A command sent to this signal.
+pub enum Command {
+    /// Switch the signal to another aspect, optionally with the Zs3 speed indicator showing the given speed in tens of km/h, and the Zs2 direction indicator showing the given route letter.
+    Aspect(AspectCommand, Option<u8>, Option<u8>),
+    /// Arm an aspect, which then has to be confirmed by switching to it.
+    Arm(AspectCommand),
+    /// The next signal along the line switched to this aspect, which a distant signal on this signal’s mast announces.
+    NextAspect(AspectCommand),
+    /// Report stack headroom and buffer usage.
+    MemoryReport,
+    /// Report the temperature and heater state.
+    TemperatureReport,
+    /// Report the firmware version and the features it was built with.
+    VersionReport,
+    /// Report the most recent accepted state-changing commands.
+    JournalReport,
+    /// Report the results of the automatic lamp tests.
+    HealthReport,
+    /// Report the displayed and the saved aspect, and whether the last switch succeeded.
+    StateReport,
+    /// Report the signalling system, the supported aspects, the optional indicators and the firmware version.
+    CapabilityReport,
+    /// Engage the maintenance lock, which rejects aspect commands and allows maintenance commands.
+    Lock,
+    /// Release the maintenance lock.
+    Unlock,
+    /// Switch a single lamp independently of the aspect. Only allowed while the maintenance lock is engaged.
+    RawLamp(LampRole, RawLampState),
+    /// Query a configuration option, or change it if a value is given.
+    Config(ConfigKey, Option<u16>),
+    /// Switch through the aspects the given number of times as fast as possible and report the timing. Only allowed while the maintenance lock is engaged.
+    StressTest(u16),
+    /// Switch through every aspect the signal supports and report which lamps are lit in each, as an acceptance test of the wiring. Only allowed while the maintenance lock is engaged.
+    SafeStateProof,
+    /// Send the whole configuration to the controller with the given signal ID, as configuration commands over the serial bus. Only allowed while the maintenance lock is engaged.
+    CloneConfig(ArrayString<MAX_CLONE_TARGET_LENGTH>),
+    /// Close or open the level crossing.
+    LevelCrossing(LevelCrossingCommand),
+    /// The layout’s fast clock runs at the given ratio and shows the given time, in minutes since midnight. This command is broadcast to all signals.
+    FastClock(u8, u16),
+    /// Answer with the signal ID in the signal's delay slot (see [`discovery_delay_ms`]), so that the control box can find all signals on a shared bus. This command is broadcast to all signals.
+    Discovery,
+    /// Switch every signal of the controller to stop immediately, and reject all other aspects until the emergency stop is released. This command is broadcast to all signals.
+    EmergencyStop,
+    /// Release the emergency stop, after which the signals accept aspects again.
+    EmergencyRelease,
+}
+
+impl Command {
+    /// Returns whether the command changes the state of the signal controller, as opposed to only querying it.
+    pub fn changes_state(&self) -> bool {
+        match self {
+            Self::Aspect(..)
+            | Self::Arm(_)
+            | Self::NextAspect(_)
+            | Self::Lock
+            | Self::Unlock
+            | Self::RawLamp(..)
+            | Self::StressTest(_)
+            | Self::SafeStateProof
+            | Self::LevelCrossing(_)
+            | Self::EmergencyRelease => true,
+            Self::Config(_, value) => value.is_some(),
+            // broadcasts can’t be rejected without an answer, and the fast clock only affects auxiliary outputs.
+            Self::MemoryReport
+            | Self::TemperatureReport
+            | Self::VersionReport
+            | Self::JournalReport
+            | Self::HealthReport
+            | Self::StateReport
+            | Self::CapabilityReport
+            | Self::Discovery
+            | Self::FastClock(..) => false,
+            // stopping is always safe, so an emergency stop must never be rejected for lack of authentication.
+            Self::EmergencyStop => false,
+            // cloning only reads this controller's configuration, and the target decides whether it accepts the commands.
+            Self::CloneConfig(_) => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AspectCommand {
+    Zero = 0,
+    One = 1,
+    Two = 2,
+    Three = 3,
+    Shunting,
+    Substitution,
+    Caution,
+    CounterTrack,
+    CounterTrackSlow,
+    CounterTrackSubstitution,
+    ShuntingForbidden,
+    ShuntingAllowed,
+    Sv0,
+    Sv1,
+    Sv2,
+    Sv3,
+    Sv4,
+    Sv5,
+    Sv6,
+    CrossingStop,
+    CrossingProceed,
+    Deactivated = b'A',
+    Dark = b'D',
+}
+
+impl AspectCommand {
+    pub const ALL: [Self; 23] = [
+        Self::Zero,
+        Self::One,
+        Self::Two,
+        Self::Three,
+        Self::Shunting,
+        Self::Substitution,
+        Self::Caution,
+        Self::CounterTrack,
+        Self::CounterTrackSlow,
+        Self::CounterTrackSubstitution,
+        Self::ShuntingForbidden,
+        Self::ShuntingAllowed,
+        Self::Sv0,
+        Self::Sv1,
+        Self::Sv2,
+        Self::Sv3,
+        Self::Sv4,
+        Self::Sv5,
+        Self::Sv6,
+        Self::CrossingStop,
+        Self::CrossingProceed,
+        Self::Deactivated,
+        Self::Dark,
+    ];
+
+    /// Returns the command with the given single-byte code, as returned by `command as u8`. Unlike the command IDs, the codes have a fixed width, so that they can be saved in a single EEPROM byte.
+    pub fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|command| *command as u8 == code)
+    }
+
+    pub fn from_command_id(command_id: &[u8]) -> Option<Self> {
+        match resolve_alias(command_id) {
+            b"0" => Some(Self::Zero),
+            b"1" => Some(Self::One),
+            b"2" => Some(Self::Two),
+            b"3" => Some(Self::Three),
+            b"S" => Some(Self::Shunting),
+            b"Z1" => Some(Self::Substitution),
+            b"Z7" => Some(Self::Caution),
+            b"Z6" => Some(Self::CounterTrack),
+            b"Z62" => Some(Self::CounterTrackSlow),
+            b"Z8" => Some(Self::CounterTrackSubstitution),
+            b"SH0" => Some(Self::ShuntingForbidden),
+            b"SH1" => Some(Self::ShuntingAllowed),
+            b"SV0" => Some(Self::Sv0),
+            b"SV1" => Some(Self::Sv1),
+            b"SV2" => Some(Self::Sv2),
+            b"SV3" => Some(Self::Sv3),
+            b"SV4" => Some(Self::Sv4),
+            b"SV5" => Some(Self::Sv5),
+            b"SV6" => Some(Self::Sv6),
+            b"BU0" => Some(Self::CrossingStop),
+            b"BU1" => Some(Self::CrossingProceed),
+            b"A" => Some(Self::Deactivated),
+            b"D" => Some(Self::Dark),
+            _ => None,
+        }
+    }
+}
+
+/// Names of aspects in the signal book, which are accepted instead of the command IDs in any case, e.g. `F:Hp1` for `F:1`, so that a signal can be set up from a serial terminal without looking the IDs up.
+const ASPECT_ALIASES: [(&[u8], &[u8]); 9] = [
+    (b"HP0", b"0"),
+    (b"HP1", b"1"),
+    (b"HP2", b"2"),
+    (b"KS1", b"1"),
+    (b"KS2", b"2"),
+    (b"ZS1", b"Z1"),
+    (b"ZS6", b"Z6"),
+    (b"ZS7", b"Z7"),
+    (b"ZS8", b"Z8"),
+];
+
+/// Returns the command ID of an aspect name, or the command itself if it isn’t one.
+fn resolve_alias(command: &[u8]) -> &[u8] {
+    ASPECT_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(command))
+        .map_or(command, |(_, command_id)| command_id)
+}
+
+/// Parses the next command from the single line input given, for the signal with the given ID in the given layout segment. A checksum suffix like `*3A` is verified, and required if `require_checksum` is set.
+///
+/// A lenient parser ignores blank lines and lines that don’t start with a signal ID, and accepts the signal ID in lowercase and lines ending in a carriage return, as sent by some control boxes. A `strict` parser rejects these lines instead, so that a misbehaving control box is noticed.
+///
+/// The result is either
+/// - the command that was sent to this signal, or
+/// - the error, which is [`CommandError::Ignored`] for lines that are not for this signal.
+pub fn get_next_command(
+    line: &[u8],
+    signal_id: &str,
+    layout_segment: &str,
+    require_checksum: bool,
+    strict: bool,
+) -> Result<Command, CommandError> {
+    if strict && (line.trim_ascii().is_empty() || line.contains(&b'\r')) {
+        return Err(CommandError::MalformedLine);
+    }
+    let before_comment = strip_comment(line).trim_ascii();
+    let (before_comment, is_checksum_valid) = split_checksum(before_comment);
+    let (before_comment, _) = split_sequence_number(before_comment);
+    let mut sections = before_comment.split(|c| *c == b':');
+    match sections.next() {
+        Some(address) => {
+            // the segment filter is everything before the last slash.
+            let address_signal_id = match address.iter().rposition(|c| *c == b'/') {
+                Some(position_of_slash) => {
+                    if !segment_matches(&address[..position_of_slash], layout_segment.as_bytes()) {
+                        return Err(CommandError::Ignored);
+                    }
+                    &address[position_of_slash + 1..]
+                }
+                None => address,
+            };
+            // an emergency stop is accepted regardless of the checksum, since a stop is always safe.
+            if address_signal_id == b"!" {
+                return match (sections.next(), sections.next()) {
+                    (Some(b"STOP"), None) => Ok(Command::EmergencyStop),
+                    _ => Err(CommandError::Ignored),
+                };
+            }
+            // broadcasts reach every signal, so their errors are not answered, since the replies would collide. Only discovery is answered, in a delay slot of each signal.
+            if address_signal_id == b"*" {
+                if is_checksum_valid != Some(true)
+                    && (is_checksum_valid.is_some() || require_checksum)
+                {
+                    return Err(CommandError::Ignored);
+                }
+                return match sections.next() {
+                    Some(b"FCLK") => parse_fast_clock(sections)
+                        .map(|(ratio, minutes)| Command::FastClock(ratio, minutes))
+                        .ok_or(CommandError::Ignored),
+                    Some(b"PING") if sections.next().is_none() => Ok(Command::Discovery),
+                    _ => Err(CommandError::Ignored),
+                };
+            }
+            let is_for_this_signal = if strict {
+                address_signal_id == signal_id.as_bytes()
+            } else {
+                address_signal_id.eq_ignore_ascii_case(signal_id.as_bytes())
+            };
+            if !is_for_this_signal {
+                // lines for other signals are well-formed, and so are comments, which have nothing before the hash.
+                if strict && !before_comment.is_empty() {
+                    if address_signal_id.is_empty() {
+                        return Err(CommandError::MissingSignalId);
+                    }
+                    if !is_signal_id(address_signal_id) {
+                        return Err(CommandError::MalformedLine);
+                    }
+                }
+                return Err(CommandError::Ignored);
+            }
+            match is_checksum_valid {
+                Some(false) => {
+                    return Err(CommandError::ChecksumMismatch);
+                }
+                None if require_checksum => {
+                    return Err(CommandError::ChecksumMissing);
+                }
+                _ => {}
+            }
+        }
+        None => {
+            return Err(CommandError::MissingSignalId);
+        }
+    }
+    match sections.next() {
+        None => Err(CommandError::MissingCommand),
+        Some(command) => match resolve_alias(command) {
+            b"A" => Ok(Command::Aspect(AspectCommand::Deactivated, None, None)),
+            b"D" => Ok(Command::Aspect(AspectCommand::Dark, None, None)),
+            b"0" => Ok(Command::Aspect(AspectCommand::Zero, None, None)),
+            // proceed aspects can carry a speed and a route, as in `2:6:R` for 60 km/h towards R.
+            b"1" => match parse_speed_and_route(sections) {
+                Some((speed, route)) => Ok(Command::Aspect(AspectCommand::One, speed, route)),
+                None => Err(CommandError::InvalidSpeedOrRoute),
+            },
+            b"2" => match parse_speed_and_route(sections) {
+                Some((speed, route)) => Ok(Command::Aspect(AspectCommand::Two, speed, route)),
+                None => Err(CommandError::InvalidSpeedOrRoute),
+            },
+            b"3" => Ok(Command::Aspect(AspectCommand::Three, None, None)),
+            b"S" => Ok(Command::Aspect(AspectCommand::Shunting, None, None)),
+            b"Z1" => Ok(Command::Aspect(AspectCommand::Substitution, None, None)),
+            b"Z7" => Ok(Command::Aspect(AspectCommand::Caution, None, None)),
+            b"Z6" => Ok(Command::Aspect(AspectCommand::CounterTrack, None, None)),
+            b"Z62" => Ok(Command::Aspect(AspectCommand::CounterTrackSlow, None, None)),
+            b"Z8" => Ok(Command::Aspect(
+                AspectCommand::CounterTrackSubstitution,
+                None,
+                None,
+            )),
+            b"SH0" => Ok(Command::Aspect(
+                AspectCommand::ShuntingForbidden,
+                None,
+                None,
+            )),
+            b"SH1" => Ok(Command::Aspect(AspectCommand::ShuntingAllowed, None, None)),
+            b"SV0" => Ok(Command::Aspect(AspectCommand::Sv0, None, None)),
+            b"SV1" => Ok(Command::Aspect(AspectCommand::Sv1, None, None)),
+            b"SV2" => Ok(Command::Aspect(AspectCommand::Sv2, None, None)),
+            b"SV3" => Ok(Command::Aspect(AspectCommand::Sv3, None, None)),
+            b"SV4" => Ok(Command::Aspect(AspectCommand::Sv4, None, None)),
+            b"SV5" => Ok(Command::Aspect(AspectCommand::Sv5, None, None)),
+            b"SV6" => Ok(Command::Aspect(AspectCommand::Sv6, None, None)),
+            b"BU0" => Ok(Command::Aspect(AspectCommand::CrossingStop, None, None)),
+            b"BU1" => Ok(Command::Aspect(AspectCommand::CrossingProceed, None, None)),
+            b"ARM" => match sections.next().and_then(AspectCommand::from_command_id) {
+                Some(aspect) => Ok(Command::Arm(aspect)),
+                None => Err(CommandError::InvalidArmCommand),
+            },
+            b"NXT" => match sections.next().and_then(AspectCommand::from_command_id) {
+                Some(aspect) => Ok(Command::NextAspect(aspect)),
+                None => Err(CommandError::InvalidNextAspectCommand),
+            },
+            b"MEM" => Ok(Command::MemoryReport),
+            b"TEMP" => Ok(Command::TemperatureReport),
+            b"VER" => Ok(Command::VersionReport),
+            b"HIST" => Ok(Command::JournalReport),
+            b"HEALTH" => Ok(Command::HealthReport),
+            b"Q" => Ok(Command::StateReport),
+            b"?" => Ok(Command::CapabilityReport),
+            b"RELEASE" => Ok(Command::EmergencyRelease),
+            b"LOCK" => Ok(Command::Lock),
+            b"UNLOCK" => Ok(Command::Unlock),
+            b"RAW" => {
+                let role = sections.next().and_then(LampRole::from_command_id);
+                let state = sections.next().and_then(RawLampState::from_command_id);
+                match (role, state) {
+                    (Some(role), Some(state)) => Ok(Command::RawLamp(role, state)),
+                    _ => Err(CommandError::InvalidRawLampCommand),
+                }
+            }
+            b"CFG" => {
+                let key = match sections.next() {
+                    Some(b"SLEW") => sections
+                        .next()
+                        .and_then(LampRole::from_command_id)
+                        .map(ConfigKey::LampSlew),
+                    Some(b"PIN") => sections
+                        .next()
+                        .and_then(LampRole::from_command_id)
+                        .filter(|role| MAPPED_LAMPS.contains(role))
+                        .map(ConfigKey::LampPin),
+                    Some(b"DCC") => sections
+                        .next()
+                        .and_then(parse_decimal)
+                        .filter(|number| *number < MAPPED_ASPECT_NUMBERS as u32)
+                        .map(|number| ConfigKey::DccAspect(number as u8)),
+                    command_id => command_id.and_then(ConfigKey::from_command_id),
+                };
+                let Some(key) = key else {
+                    return Err(CommandError::UnknownConfigOption);
+                };
+                let value = sections
+                    .next()
+                    .map(|value| parse_decimal(value).and_then(|value| u16::try_from(value).ok()));
+                match value {
+                    None => Ok(Command::Config(key, None)),
+                    Some(Some(value)) => Ok(Command::Config(key, Some(value))),
+                    Some(None) => Err(CommandError::InvalidConfigValue),
+                }
+            }
+            b"STRESS" => {
+                let count = sections
+                    .next()
+                    .and_then(parse_decimal)
+                    .and_then(|count| u16::try_from(count).ok())
+                    .filter(|count| *count > 0);
+                match count {
+                    Some(count) => Ok(Command::StressTest(count)),
+                    None => Err(CommandError::InvalidStressTestCommand),
+                }
+            }
+            b"PROOF" => Ok(Command::SafeStateProof),
+            b"CLONE" => {
+                let target = sections
+                    .next()
+                    .filter(|target| !target.is_empty() && !target.contains(&b'*'))
+                    .and_then(|target| core::str::from_utf8(target).ok())
+                    .and_then(|target| ArrayString::from(target).ok());
+                match target {
+                    Some(target) => Ok(Command::CloneConfig(target)),
+                    None => Err(CommandError::InvalidCloneCommand),
+                }
+            }
+            b"BX" => match sections
+                .next()
+                .and_then(LevelCrossingCommand::from_command_id)
+            {
+                Some(command) => Ok(Command::LevelCrossing(command)),
+                None => Err(CommandError::InvalidLevelCrossingCommand),
+            },
+            _ => Err(CommandError::UnknownCommand),
+        },
+    }
+}
+
+/// Checks whether the text can be a signal ID, which consists of uppercase letters and digits.
+fn is_signal_id(text: &[u8]) -> bool {
+    text.iter()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+/// Checks whether a segment filter matches the layout segment. Both consist of levels separated by slashes. Like in MQTT topic filters, a `+` level in the filter matches any single level of the segment.
+fn segment_matches(filter: &[u8], segment: &[u8]) -> bool {
+    let mut filter_levels = filter.split(|c| *c == b'/');
+    let mut segment_levels = segment.split(|c| *c == b'/');
+    loop {
+        match (filter_levels.next(), segment_levels.next()) {
+            (None, None) => return true,
+            (Some(b"+"), Some(_)) => {}
+            (Some(filter_level), Some(segment_level)) if filter_level == segment_level => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Parses the optional speed and route letter after an aspect, in this order. Returns `None` if either is malformed.
+fn parse_speed_and_route<'a>(
+    mut sections: impl Iterator<Item = &'a [u8]>,
+) -> Option<(Option<u8>, Option<u8>)> {
+    let mut section = sections.next();
+    let mut speed = None;
+    if let Some(text) = section {
+        if text.first().is_some_and(u8::is_ascii_digit) {
+            speed = Some(parse_decimal(text).and_then(|speed| u8::try_from(speed).ok())?);
+            section = sections.next();
+        }
+    }
+    let route = match section {
+        None => None,
+        Some([letter]) if letter.is_ascii_uppercase() => Some(*letter),
+        Some(_) => return None,
+    };
+    Some((speed, route))
+}
+
+/// Parses the ratio and time of a fast-clock broadcast, like `4:22:00`. Returns `None` if any of them is malformed.
+fn parse_fast_clock<'a>(mut sections: impl Iterator<Item = &'a [u8]>) -> Option<(u8, u16)> {
+    let ratio = u8::try_from(parse_decimal(sections.next()?)?).ok()?;
+    let hours = parse_decimal(sections.next()?)?;
+    let minutes = parse_decimal(sections.next()?)?;
+    if hours >= 24 || minutes >= 60 {
+        return None;
+    }
+    Some((ratio, (hours * 60 + minutes) as u16))
+}
+
+/// Returns the checksum of a line, the XOR of all of its characters, like in NMEA sentences.
+pub fn checksum(text: &[u8]) -> u8 {
+    text.iter().fold(0, |checksum, byte| checksum ^ byte)
+}
+
+/// Returns the line without its comment, which starts at the first hash that doesn’t start a sequence number.
+pub(crate) fn strip_comment(line: &[u8]) -> &[u8] {
+    let start_of_comment = (0..line.len()).find(|position| {
+        line[*position] == b'#'
+            && !(*position > 0
+                && line[*position - 1] == b':'
+                && line.get(*position + 1).is_some_and(u8::is_ascii_digit))
+    });
+    match start_of_comment {
+        Some(position) => &line[..position],
+        None => line,
+    }
+}
+
+/// Returns the sequence number of a line, like `42` in `F:1:#42`, which is echoed in the responses to the command. The authentication suffix must already be split off.
+pub fn sequence_number(line: &[u8]) -> Option<u16> {
+    let (before_checksum, _) = split_checksum(strip_comment(line).trim_ascii());
+    split_sequence_number(before_checksum).1
+}
+
+/// Splits the sequence number suffix `:#[Sequence number]` off a line.
+fn split_sequence_number(line: &[u8]) -> (&[u8], Option<u16>) {
+    let Some(position_of_hash) = line.iter().rposition(|c| *c == b'#') else {
+        return (line, None);
+    };
+    let sequence_number = parse_decimal(&line[position_of_hash + 1..])
+        .and_then(|sequence_number| u16::try_from(sequence_number).ok());
+    match (sequence_number, line[..position_of_hash].strip_suffix(b":")) {
+        (Some(sequence_number), Some(command)) => (command, Some(sequence_number)),
+        _ => (line, None),
+    }
+}
+
+/// Splits the checksum suffix `*[Two hexadecimal digits]` off a line, and returns whether the checksum matched, or None if there was no suffix.
+fn split_checksum(line: &[u8]) -> (&[u8], Option<bool>) {
+    match line {
+        [text @ .., b'*', high, low] => {
+            let digits = core::str::from_utf8(&[*high, *low])
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+            (text, Some(digits == Some(checksum(text))))
+        }
+        _ => (line, None),
+    }
+}
+
+/// Number of delay slots in which the signals answer a discovery broadcast.
+const DISCOVERY_SLOTS: u8 = 64;
+/// Duration of a delay slot, which fits an answer at 57600 baud, and the time an RS-485 transceiver needs to release the bus.
+const DISCOVERY_SLOT_MS: u32 = 5;
+
+/// Returns how long the signal with the given ID waits before it answers a discovery broadcast. The delay slot is derived from the CRC-8 of the ID, so that signals on the same bus usually answer one after another.
+pub fn discovery_delay_ms(signal_id: &str) -> u32 {
+    u32::from(crate::bidib::crc8(signal_id.as_bytes()) % DISCOVERY_SLOTS) * DISCOVERY_SLOT_MS
+}
+
+/// Parses an unsigned decimal number, as used for command arguments.
+pub(crate) fn parse_decimal(text: &[u8]) -> Option<u32> {
+    if text.is_empty() {
+        return None;
+    }
+    text.iter().try_fold(0u32, |value, digit| {
+        if !digit.is_ascii_digit() {
+            return None;
+        }
+        value.checked_mul(10)?.checked_add((digit - b'0').into())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::checksum;
+    use super::discovery_delay_ms;
+    use super::get_next_command;
+    use super::sequence_number;
+    use super::AspectCommand;
+    use super::Command;
+    use super::CommandError;
+    use crate::config::ConfigKey;
+    use crate::level_crossing::LevelCrossingCommand;
+    use crate::signals::LampRole;
+
+    fn parse(line: &str) -> Result<Command, CommandError> {
+        get_next_command(line.as_bytes(), "F", "station/east", false, false)
+    }
+
+    fn error(line: &str) -> CommandError {
+        match parse(line) {
+            Err(error) => error,
+            Ok(_) => panic!("expected an error for {line:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_aspects() {
+        assert!(matches!(
+            parse("F:1\n"),
+            Ok(Command::Aspect(AspectCommand::One, None, None))
+        ));
+        assert!(matches!(
+            parse("F:A # comment"),
+            Ok(Command::Aspect(AspectCommand::Deactivated, None, None))
+        ));
+        assert!(matches!(
+            parse("F:Z1"),
+            Ok(Command::Aspect(AspectCommand::Substitution, None, None))
+        ));
+        assert!(matches!(
+            parse("F:S"),
+            Ok(Command::Aspect(AspectCommand::Shunting, None, None))
+        ));
+        assert!(matches!(
+            parse("F:SV5"),
+            Ok(Command::Aspect(AspectCommand::Sv5, None, None))
+        ));
+        assert!(matches!(
+            parse("F:2:6"),
+            Ok(Command::Aspect(AspectCommand::Two, Some(6), None))
+        ));
+        assert!(matches!(
+            parse("F:1:R"),
+            Ok(Command::Aspect(AspectCommand::One, None, Some(b'R')))
+        ));
+        assert!(matches!(
+            parse("F:2:6:R"),
+            Ok(Command::Aspect(AspectCommand::Two, Some(6), Some(b'R')))
+        ));
+        assert!(matches!(
+            parse("F:NXT:2"),
+            Ok(Command::NextAspect(AspectCommand::Two))
+        ));
+    }
+
+    #[test]
+    fn accepts_aspect_names() {
+        assert!(matches!(
+            parse("F:Hp1"),
+            Ok(Command::Aspect(AspectCommand::One, None, None))
+        ));
+        assert!(matches!(
+            parse("F:hp2:6"),
+            Ok(Command::Aspect(AspectCommand::Two, Some(6), None))
+        ));
+        assert!(matches!(
+            parse("F:Ks2"),
+            Ok(Command::Aspect(AspectCommand::Two, None, None))
+        ));
+        assert!(matches!(
+            parse("F:ZS1"),
+            Ok(Command::Aspect(AspectCommand::Substitution, None, None))
+        ));
+        assert!(matches!(
+            parse("F:ARM:Zs8"),
+            Ok(Command::Arm(AspectCommand::CounterTrackSubstitution))
+        ));
+        assert_eq!(error("F:Hp3"), CommandError::UnknownCommand);
+    }
+
+    #[test]
+    fn aspect_commands_have_distinct_codes() {
+        for command in AspectCommand::ALL {
+            assert!(AspectCommand::from_code(command as u8) == Some(command));
+        }
+        // erased EEPROM doesn’t hold an aspect.
+        assert!(AspectCommand::from_code(0xff).is_none());
+    }
+
+    #[test]
+    fn ignores_other_signals_and_comments() {
+        assert!(matches!(parse("G:1"), Err(CommandError::Ignored)));
+        assert!(matches!(
+            parse("# only a comment"),
+            Err(CommandError::Ignored)
+        ));
+        assert!(matches!(parse(""), Err(CommandError::Ignored)));
+    }
+
+    #[test]
+    fn tolerates_sloppy_lines_unless_strict() {
+        let strict = |line: &str| get_next_command(line.as_bytes(), "F", "", false, true);
+        for line in ["f:1\r\n", "F:1\r\n", "f:1"] {
+            assert!(parse(line).is_ok(), "{line:?}");
+            assert_eq!(
+                strict(line).err(),
+                Some(CommandError::MalformedLine),
+                "{line:?}"
+            );
+        }
+        for line in ["\n", "\r\n", "garbage\n", "FOO BAR:1\n"] {
+            assert_eq!(parse(line).err(), Some(CommandError::Ignored), "{line:?}");
+            assert_eq!(
+                strict(line).err(),
+                Some(CommandError::MalformedLine),
+                "{line:?}"
+            );
+        }
+        assert_eq!(strict(":1\n").err(), Some(CommandError::MissingSignalId));
+        // well-formed lines for other signals and comments are still ignored.
+        assert_eq!(strict("G:1\n").err(), Some(CommandError::Ignored));
+        assert_eq!(strict("# banner\n").err(), Some(CommandError::Ignored));
+        assert!(strict("F:1\n").is_ok());
+    }
+
+    #[test]
+    fn matches_layout_segment_filters() {
+        assert!(parse("station/east/F:0").is_ok());
+        assert!(parse("station/+/F:0").is_ok());
+        assert!(matches!(
+            parse("station/west/F:0"),
+            Err(CommandError::Ignored)
+        ));
+        assert!(matches!(parse("station/F:0"), Err(CommandError::Ignored)));
+        assert!(matches!(parse("+/F:0"), Err(CommandError::Ignored)));
+    }
+
+    #[test]
+    fn parses_config_commands() {
+        assert!(matches!(
+            parse("F:CFG:RDLY"),
+            Ok(Command::Config(ConfigKey::ReplyDelay, None))
+        ));
+        assert!(matches!(
+            parse("F:CFG:RDLY:20"),
+            Ok(Command::Config(ConfigKey::ReplyDelay, Some(20)))
+        ));
+        assert!(matches!(
+            parse("F:CFG:SLEW:AGU:4"),
+            Ok(Command::Config(
+                ConfigKey::LampSlew(LampRole::AnnouncementGreenUpper),
+                Some(4)
+            ))
+        ));
+        assert!(matches!(
+            parse("F:CFG:PIN:MY"),
+            Ok(Command::Config(
+                ConfigKey::LampPin(LampRole::MainYellow),
+                None
+            ))
+        ));
+        assert!(parse("F:CFG:PIN:MN:2").is_err());
+        assert!(matches!(
+            parse("F:CFG:DCC:31:203"),
+            Ok(Command::Config(ConfigKey::DccAspect(31), Some(203)))
+        ));
+        assert!(parse("F:CFG:DCC:32:1").is_err());
+    }
+
+    #[test]
+    fn parses_stress_test_commands() {
+        assert!(matches!(
+            parse("F:STRESS:500"),
+            Ok(Command::StressTest(500))
+        ));
+        assert!(parse("F:STRESS:70000").is_err());
+    }
+
+    #[test]
+    fn parses_clone_commands() {
+        match parse("F:CLONE:station/west/G") {
+            Ok(Command::CloneConfig(target)) => assert_eq!(target.as_str(), "station/west/G"),
+            _ => panic!("expected a clone command"),
+        }
+        assert!(parse("F:CLONE").is_err());
+        assert!(parse("F:CLONE:*").is_err());
+        assert_eq!(
+            error("F:CLONE:ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456"),
+            CommandError::InvalidCloneCommand
+        );
+    }
+
+    #[test]
+    fn parses_level_crossing_commands() {
+        assert!(matches!(
+            parse("F:BX:close"),
+            Ok(Command::LevelCrossing(LevelCrossingCommand::Close))
+        ));
+        assert!(matches!(
+            parse("F:BX:open"),
+            Ok(Command::LevelCrossing(LevelCrossingCommand::Open))
+        ));
+        assert!(parse("F:BX:CLOSE").is_err());
+        assert!(parse("F:BX").is_err());
+    }
+
+    #[test]
+    fn parses_fast_clock_broadcasts_silently() {
+        assert!(matches!(
+            parse("*:FCLK:4:22:05"),
+            Ok(Command::FastClock(4, 1325))
+        ));
+        assert!(matches!(
+            parse("station/+/*:FCLK:0:6:00"),
+            Ok(Command::FastClock(0, 360))
+        ));
+        for line in [
+            "*:FCLK:4:24:00",
+            "*:FCLK:4:22",
+            "*:PING:1",
+            "*:1",
+            "station/west/*:FCLK:4:22:00",
+        ] {
+            assert!(matches!(parse(line), Err(CommandError::Ignored)), "{line}");
+        }
+    }
+
+    #[test]
+    fn parses_discovery_broadcasts() {
+        assert!(matches!(parse("*:PING"), Ok(Command::Discovery)));
+        assert!(matches!(parse("station/+/*:PING"), Ok(Command::Discovery)));
+        assert_eq!(discovery_delay_ms(""), 0);
+        assert!(discovery_delay_ms("F") != discovery_delay_ms("G"));
+        for signal_id in ["A", "F", "P2", "N12"] {
+            assert!(discovery_delay_ms(signal_id) < 64 * 5, "{signal_id}");
+        }
+    }
+
+    #[test]
+    fn parses_emergency_stops() {
+        assert!(matches!(parse("!:STOP"), Ok(Command::EmergencyStop)));
+        assert!(matches!(
+            parse("station/+/!:STOP"),
+            Ok(Command::EmergencyStop)
+        ));
+        // the checksum can't keep a stop from being accepted.
+        assert!(matches!(
+            get_next_command(b"!:STOP*00", "F", "", true, false),
+            Ok(Command::EmergencyStop)
+        ));
+        assert!(matches!(parse("F:RELEASE"), Ok(Command::EmergencyRelease)));
+        for line in ["!:STOP:1", "!:1", "station/west/!:STOP"] {
+            assert!(matches!(parse(line), Err(CommandError::Ignored)), "{line}");
+        }
+    }
+
+    #[test]
+    fn splits_off_sequence_numbers() {
+        for (line, expected) in [
+            ("F:1:#42", Some(42)),
+            ("F:2:6:#7 #comment", Some(7)),
+            ("F:1:#65535*4D", Some(65535)),
+            ("F:1:#65536", None),
+            ("F:1 #42", None),
+            ("F:1:#comment", None),
+        ] {
+            assert_eq!(sequence_number(line.as_bytes()), expected, "{line}");
+        }
+        assert!(matches!(
+            parse("F:1:#42"),
+            Ok(Command::Aspect(AspectCommand::One, None, None))
+        ));
+        assert!(matches!(
+            parse("F:2:6:#7 #comment"),
+            Ok(Command::Aspect(AspectCommand::Two, Some(6), None))
+        ));
+        assert!(matches!(parse("F:MEM:#1"), Ok(Command::MemoryReport)));
+        // the checksum covers the sequence number.
+        let line = format!("F:1:#42*{:02X}", checksum(b"F:1:#42"));
+        assert!(matches!(
+            get_next_command(line.as_bytes(), "F", "", true, false),
+            Ok(Command::Aspect(AspectCommand::One, None, None))
+        ));
+    }
+
+    #[test]
+    fn verifies_checksums() {
+        assert_eq!(checksum(b"F:1"), 0x4d);
+        for line in ["F:1*4D", "F:1*4d #comment", "station/east/F:1*34"] {
+            assert!(
+                matches!(
+                    get_next_command(line.as_bytes(), "F", "station/east", true, false),
+                    Ok(Command::Aspect(AspectCommand::One, None, None))
+                ),
+                "{line}"
+            );
+        }
+        assert_eq!(error("F:1*4E"), CommandError::ChecksumMismatch);
+        assert_eq!(error("F:1*XY"), CommandError::ChecksumMismatch);
+        assert!(matches!(
+            get_next_command(b"F:1", "F", "", true, false),
+            Err(CommandError::ChecksumMissing)
+        ));
+        // lines for other signals and broadcasts are never answered.
+        assert!(matches!(parse("G:1*4E"), Err(CommandError::Ignored)));
+        let broadcast = format!("*:FCLK:4:22:05*{:02X}", checksum(b"*:FCLK:4:22:05"));
+        assert!(matches!(
+            get_next_command(broadcast.as_bytes(), "F", "", true, false),
+            Ok(Command::FastClock(4, 1325))
+        ));
+        assert!(matches!(
+            get_next_command(b"*:FCLK:4:22:05", "F", "", true, false),
+            Err(CommandError::Ignored)
+        ));
+        assert!(matches!(
+            parse("*:FCLK:4:22:05*00"),
+            Err(CommandError::Ignored)
+        ));
+    }
+
+    #[test]
+    fn reports_malformed_commands() {
+        for line in [
+            "F",
+            "F:5",
+            "F:CFG:RDLY:x",
+            "F:CFG:SLEW",
+            "F:CFG:SLEW:MX:4",
+            "F:RAW:MX:1",
+            "F:ARM:4",
+            "F:NXT",
+            "F:2:x",
+            "F:1:RR",
+            "F:2:6:7",
+            "F:STRESS",
+            "F:STRESS:0",
+        ] {
+            assert_ne!(error(line), CommandError::Ignored, "{line}");
+        }
+    }
+
+    #[test]
+    fn error_codes_are_unique() {
+        let mut codes = [
+            CommandError::Ignored,
+            CommandError::MalformedLine,
+            CommandError::MissingSignalId,
+            CommandError::MissingCommand,
+            CommandError::UnknownCommand,
+            CommandError::InvalidRawLampCommand,
+            CommandError::InvalidArmCommand,
+            CommandError::InvalidNextAspectCommand,
+            CommandError::InvalidSpeedOrRoute,
+            CommandError::InvalidStressTestCommand,
+            CommandError::InvalidLevelCrossingCommand,
+            CommandError::InvalidCloneCommand,
+            CommandError::UnknownConfigOption,
+            CommandError::InvalidConfigValue,
+            CommandError::ValueOutOfRange,
+            CommandError::Unsupported,
+            CommandError::FellBackToStop,
+            CommandError::FellBackToDark,
+            CommandError::Locked,
+            CommandError::AuthenticationFailed,
+            CommandError::NotArmed,
+            CommandError::ChecksumMismatch,
+            CommandError::ChecksumMissing,
+            CommandError::EmergencyStop,
+            CommandError::Busy,
+            CommandError::StopHeld,
+        ]
+        .map(CommandError::code);
+        codes.sort_unstable();
+        assert!(codes.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[cfg(not(any(feature = "lang-de", feature = "terse-errors")))]
+    #[test]
+    fn explains_errors_in_english() {
+        for (line, response) in [
+            ("F", "F:E:12#Missing command"),
+            ("F:5", "F:E:13#Unknown command"),
+            ("F:CFG:RDLY:x", "F:E:22#Invalid config value"),
+            ("F:RAW:MX:1", "F:E:14#Invalid raw lamp command"),
+            ("F:1*4E", "F:E:7#Checksum mismatch"),
+        ] {
+            let mut text = String::new();
+            ufmt::uwrite!(text, "{}", error(line).response("F")).unwrap();
+            assert_eq!(text, response);
+        }
+    }
+
+    #[cfg(feature = "terse-errors")]
+    #[test]
+    fn sends_only_the_code_of_terse_errors() {
+        let mut text = String::new();
+        ufmt::uwrite!(text, "{}", CommandError::EmergencyStop.response("G")).unwrap();
+        assert_eq!(text, "G:E:8");
+    }
+}
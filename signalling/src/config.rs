@@ -0,0 +1,852 @@
+//! Module for runtime configuration that is persisted in the EEPROM.
+
+use crate::baud::BaudRate;
+use crate::cmri;
+use crate::commands::AspectCommand;
+use crate::dcc::MappedAspect;
+use crate::dcc::MAPPED_ASPECT_NUMBERS;
+use crate::dmx;
+use crate::fast_clock::MINUTES_PER_DAY;
+use crate::framing::LineTerminator;
+use crate::lamp_test::LampTestSchedule;
+use crate::logging::LogFilter;
+use crate::logging::Severity;
+use crate::platform::Platform;
+use crate::selectrix;
+use crate::signals::LampRole;
+use crate::slew;
+
+/// EEPROM address of the configuration. The bytes before it are used for the saved aspect.
+pub const CONFIG_EEPROM_OFFSET: u16 = 16;
+
+// Marks a valid configuration in the EEPROM. Must be changed whenever the serialized layout changes, so that stale configurations are replaced by the defaults.
+const CONFIG_MAGIC: u8 = 0xbe;
+
+/// A configuration option that can be changed over the serial connection.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ConfigKey {
+    /// Minimum delay between receiving a command and sending the reply, in milliseconds.
+    ReplyDelay,
+    /// Whether aspects that blank the signal have to be armed before they can be switched to.
+    RequireArming,
+    /// Whether the lamp aging simulation for museum displays is enabled.
+    LampAging,
+    /// Whether the controller boots in machine mode, for automated provisioning.
+    MachineMode,
+    /// Time after which the substitution signal switches back to stop, in seconds.
+    SubstitutionTimeout,
+    /// Whether the signal protects a stub track, so that its announcement stays dark.
+    StubTrack,
+    /// Whether lamps that were dark for a long time are warmed up before they are lit at full brightness.
+    WarmUp,
+    /// Least important severity of the lines sent on the protocol channel.
+    ProtocolLogLevel,
+    /// Least important severity of the lines sent on the diagnostics channel.
+    DiagnosticsLogLevel,
+    /// Whether the track input decodes Märklin Motorola instead of DCC accessory commands.
+    MotorolaAccessories,
+    /// When the automatic lamp test runs.
+    LampTest,
+    /// Time of the automatic lamp test, in minutes.
+    LampTestTime,
+    /// Selectrix channel whose bits command the aspect.
+    SelectrixChannel,
+    /// Whether the serial port speaks C/MRI instead of the text protocol.
+    Cmri,
+    /// C/MRI node address.
+    CmriNodeAddress,
+    /// Whether the serial port works as a BiDiB node instead of speaking the text protocol.
+    Bidib,
+    /// Whether the serial port answers SRCP commands instead of the text protocol.
+    Srcp,
+    /// Whether the serial port speaks the binary protocol with CRCs instead of plain text lines.
+    Binary,
+    /// Whether text commands must carry a checksum, and replies carry one.
+    RequireChecksum,
+    /// Whether malformed lines are rejected with an error instead of ignored.
+    StrictParsing,
+    /// Bytes that end a line of the text protocol.
+    LineTerminator,
+    /// Time after which an incomplete line is discarded.
+    LineTimeout,
+    /// Baud rate of the serial port.
+    BaudRate,
+    /// DMX512 channel whose value commands the aspect, followed by the channel of the Zs3 speed.
+    DmxChannel,
+    /// Minimum time that an aspect is shown before the next aspect command is executed, in milliseconds.
+    MinimumDwell,
+    /// Minimum time that the signal holds stop before it may be cleared, in seconds.
+    StopHold,
+    /// Duration of the ramp when switching the given lamp, in milliseconds.
+    LampSlew(LampRole),
+    /// Pin that the given lamp is connected to, which must be one of [`MAPPED_LAMPS`].
+    LampPin(LampRole),
+    /// Aspect that the given aspect number of DCC extended accessory commands is mapped to, which must be below [`MAPPED_ASPECT_NUMBERS`].
+    DccAspect(u8),
+}
+
+/// The lamps whose pins can be assigned at runtime, i.e. the lamps of an H/V signal group on pins D2 to D8.
+pub const MAPPED_LAMPS: [LampRole; 7] = [
+    LampRole::MainRed,
+    LampRole::MainGreen,
+    LampRole::MainYellow,
+    LampRole::AnnouncementGreenUpper,
+    LampRole::AnnouncementGreenLower,
+    LampRole::AnnouncementYellowUpper,
+    LampRole::AnnouncementYellowLower,
+];
+
+/// The first of the pins that the lamps of [`MAPPED_LAMPS`] are assigned to, which are consecutive.
+pub const FIRST_MAPPED_PIN: u8 = 2;
+
+/// The default pins of the lamps of [`MAPPED_LAMPS`].
+const DEFAULT_LAMP_PINS: [u8; MAPPED_LAMPS.len()] = [7, 8, 6, 4, 2, 5, 3];
+
+impl ConfigKey {
+    pub fn command_id(self) -> &'static str {
+        match self {
+            Self::ReplyDelay => "RDLY",
+            Self::RequireArming => "ARM",
+            Self::LampAging => "AGE",
+            Self::MachineMode => "MACH",
+            Self::SubstitutionTimeout => "ZS1",
+            Self::StubTrack => "STUB",
+            Self::WarmUp => "WARM",
+            Self::ProtocolLogLevel => "LOGP",
+            Self::DiagnosticsLogLevel => "LOGD",
+            Self::MotorolaAccessories => "MM",
+            Self::LampTest => "TEST",
+            Self::LampTestTime => "TTIM",
+            Self::SelectrixChannel => "SX",
+            Self::Cmri => "CMRI",
+            Self::CmriNodeAddress => "UA",
+            Self::Bidib => "BIDIB",
+            Self::Srcp => "SRCP",
+            Self::Binary => "BIN",
+            Self::RequireChecksum => "CSUM",
+            Self::StrictParsing => "STRICT",
+            Self::LineTerminator => "EOL",
+            Self::LineTimeout => "LTMO",
+            Self::BaudRate => "BAUD",
+            Self::DmxChannel => "DMX",
+            Self::MinimumDwell => "DWELL",
+            Self::StopHold => "HOLD",
+            Self::LampSlew(_) => "SLEW",
+            Self::LampPin(_) => "PIN",
+            Self::DccAspect(_) => "DCC",
+        }
+    }
+
+    /// Returns every configuration option, with the options for a lamp or an aspect number once for each lamp or aspect number they apply to.
+    pub fn all() -> impl Iterator<Item = Self> {
+        [
+            Self::ReplyDelay,
+            Self::RequireArming,
+            Self::LampAging,
+            Self::MachineMode,
+            Self::SubstitutionTimeout,
+            Self::StubTrack,
+            Self::WarmUp,
+            Self::ProtocolLogLevel,
+            Self::DiagnosticsLogLevel,
+            Self::MotorolaAccessories,
+            Self::LampTest,
+            Self::LampTestTime,
+            Self::SelectrixChannel,
+            Self::Cmri,
+            Self::CmriNodeAddress,
+            Self::Bidib,
+            Self::Srcp,
+            Self::Binary,
+            Self::RequireChecksum,
+            Self::StrictParsing,
+            Self::LineTerminator,
+            Self::LineTimeout,
+            Self::BaudRate,
+            Self::DmxChannel,
+            Self::MinimumDwell,
+            Self::StopHold,
+        ]
+        .into_iter()
+        .chain(LampRole::ALL.into_iter().map(Self::LampSlew))
+        .chain(MAPPED_LAMPS.into_iter().map(Self::LampPin))
+        .chain((0..MAPPED_ASPECT_NUMBERS as u8).map(Self::DccAspect))
+    }
+
+    /// Parses a configuration option without a lamp. Options for a lamp or an aspect number are followed by it, as in `SLEW:MR` or `DCC:5`, which the command parser handles.
+    pub fn from_command_id(command_id: &[u8]) -> Option<Self> {
+        match command_id {
+            b"RDLY" => Some(Self::ReplyDelay),
+            b"ARM" => Some(Self::RequireArming),
+            b"AGE" => Some(Self::LampAging),
+            b"MACH" => Some(Self::MachineMode),
+            b"ZS1" => Some(Self::SubstitutionTimeout),
+            b"STUB" => Some(Self::StubTrack),
+            b"WARM" => Some(Self::WarmUp),
+            b"LOGP" => Some(Self::ProtocolLogLevel),
+            b"LOGD" => Some(Self::DiagnosticsLogLevel),
+            b"MM" => Some(Self::MotorolaAccessories),
+            b"TEST" => Some(Self::LampTest),
+            b"TTIM" => Some(Self::LampTestTime),
+            b"SX" => Some(Self::SelectrixChannel),
+            b"CMRI" => Some(Self::Cmri),
+            b"UA" => Some(Self::CmriNodeAddress),
+            b"BIDIB" => Some(Self::Bidib),
+            b"SRCP" => Some(Self::Srcp),
+            b"BIN" => Some(Self::Binary),
+            b"CSUM" => Some(Self::RequireChecksum),
+            b"STRICT" => Some(Self::StrictParsing),
+            b"EOL" => Some(Self::LineTerminator),
+            b"LTMO" => Some(Self::LineTimeout),
+            b"BAUD" => Some(Self::BaudRate),
+            b"DMX" => Some(Self::DmxChannel),
+            b"DWELL" => Some(Self::MinimumDwell),
+            b"HOLD" => Some(Self::StopHold),
+            _ => None,
+        }
+    }
+}
+
+impl ufmt::uDisplay for ConfigKey {
+    fn fmt<W>(&self, formatter: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        formatter.write_str(self.command_id())?;
+        match self {
+            Self::LampSlew(role) | Self::LampPin(role) => {
+                formatter.write_str(":")?;
+                formatter.write_str(role.command_id())?;
+            }
+            Self::DccAspect(number) => ufmt::uwrite!(formatter, ":{}", number)?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// A configuration value was out of range for its option.
+pub struct InvalidConfigValue;
+
+/// Runtime configuration of the signal controller.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// Minimum delay between receiving a command and sending the reply, in milliseconds. Some PLCs miss replies that arrive too quickly after their own transmission.
+    pub reply_delay_ms: u8,
+    /// Whether the Deactivated and Dark aspects have to be armed with a separate command before they can be switched to, so that a mistyped command can’t blank the signal.
+    pub require_arming: bool,
+    /// Whether lamps occasionally flicker or fail, simulating aged lamps for museum displays. This never affects red lamps, and is announced over serial.
+    pub lamp_aging: bool,
+    /// Whether the first line sent after booting is a well-formed boot notification instead of a human-readable banner, so that provisioning scripts can rely on a deterministic handshake.
+    pub machine_mode: bool,
+    /// Time after which the substitution signal (Zs1) switches back to stop, in seconds, or 0 if it is shown until another aspect is commanded. Zs1 is only meant to be shown while a single train passes the signal.
+    pub substitution_timeout_s: u8,
+    /// Whether the signal protects a stub track, where there is no next main signal. Its announcement or distant signal then stays dark, while the main signal works as usual.
+    pub stub_track: bool,
+    /// Whether lamps that were dark for a long time are first lit with a low duty cycle for a moment, which extends the life of incandescent bulbs. LEDs don’t need this.
+    pub warm_up: bool,
+    /// Least important severity of the lines sent on each channel, so that e.g. the diagnostic announcements can be silenced in production. Errors are always sent.
+    pub log_filter: LogFilter,
+    /// Whether the track input decodes the accessory commands of Märklin Motorola keyboards instead of a DCC command station, so that the same controller works on both kinds of layouts.
+    pub motorola_accessories: bool,
+    /// When the signal switches through its aspects to test its lamps, which only happens while it shows stop. The results are kept in the EEPROM and reported by `F:HEALTH`.
+    pub lamp_test: LampTestSchedule,
+    /// Time of the automatic lamp test: the fast-clock time of day on the fast-clock schedule, or the minutes after boot on the real-time schedule, in minutes.
+    pub lamp_test_minutes: u16,
+    /// Selectrix channel that the signal reads its aspect from, so that it can be moved to a free channel without rebuilding the firmware.
+    pub selectrix_channel: u8,
+    /// Whether the serial port works as a C/MRI node, so that JMRI can poll the signal and switch its aspects without a script for the text protocol. Text commands are still accepted between the C/MRI packets, but no text is sent.
+    pub cmri: bool,
+    /// Address of the C/MRI node, which must be unique on the bus.
+    pub cmri_node_address: u8,
+    /// Whether the serial port works as a BiDiB node at 115200 baud, so that Rocrail and the BiDiB tools can enumerate the signal and switch its aspects. It excludes C/MRI, and changes take effect after a reboot.
+    pub bidib: bool,
+    /// Whether the serial port answers the SRCP commands of srcpd-based layout software, which switch the signal through its DCC accessory addresses. Lines of the text protocol are still accepted, but no text is sent. It excludes C/MRI and BiDiB.
+    pub srcp: bool,
+    /// Whether the serial port speaks the binary protocol, whose frames carry a CRC, so that commands corrupted on long, noisy cables are dropped instead of switching the wrong aspect. Text outside of frames is ignored. It excludes C/MRI, BiDiB and SRCP.
+    pub binary: bool,
+    /// Whether text commands are only accepted with a checksum suffix, and every line that is sent carries one, so that lines corrupted by e.g. traction current on nearby wires are rejected instead of switching the wrong aspect.
+    pub require_checksum: bool,
+    /// Whether blank lines, lines ending in a carriage return and lines that don’t start with a signal ID are rejected with an error instead of silently ignored, and the signal ID must be uppercase. Control boxes on a modular layout differ in how sloppy their lines are, and a strict parser points out the ones that need fixing.
+    pub strict_parsing: bool,
+    /// Bytes that end a line of the text protocol, which differ between the operating systems and terminal programs of control boxes.
+    pub line_terminator: LineTerminator,
+    /// Time after which a line whose terminator hasn’t arrived is discarded, in seconds, or 0 if it waits forever. A flaky USB adapter can lose the end of a line, which would otherwise be prepended to the next command.
+    pub line_timeout_s: u8,
+    /// Baud rate of the serial port, so that the signal can join a bus whose other devices can’t be changed. Changes take effect after a reboot, and are reverted unless a command is received at the new baud rate.
+    pub baud_rate: BaudRate,
+    /// DMX512 channel that the signal reads its aspect from, and whose next channel holds the Zs3 speed, so that the signal can be patched like any other fixture of the lighting console.
+    pub dmx_channel: u16,
+    /// Minimum time that every aspect is shown, in milliseconds, or 0 if commands are executed right away. Aspect commands that arrive earlier wait in a queue, so that rapid successive commands don’t make the signal flicker between aspects, which real signals never do. Commands to stop are never delayed.
+    pub min_dwell_ms: u16,
+    /// Minimum time that the signal holds stop before an aspect that clears it is accepted, in seconds, or 0 if it may be cleared right away. Real interlockings enforce such a time, so that a signal that was just put back to stop in front of a train isn’t cleared again right away.
+    pub stop_hold_s: u8,
+    /// Duration of the ramp when switching each lamp, in milliseconds, indexed by lamp role. Slowly switched lamps cause fewer transients on long lamp cables, which can otherwise disturb e.g. a DCC signal on an adjacent cable.
+    pub lamp_slew_ms: [u8; LampRole::ALL.len()],
+    /// Pin of each lamp of [`MAPPED_LAMPS`], so that the wiring can differ between signals without rebuilding the firmware. Every pin from D2 to D8 is assigned to exactly one lamp, and changes take effect after a reboot.
+    pub lamp_pins: [u8; MAPPED_LAMPS.len()],
+    /// Aspect that each aspect number of DCC extended accessory commands is mapped to, or None if the aspect number is ignored, so that the signal works with the aspect numbers of any command station.
+    pub dcc_aspects: [Option<MappedAspect>; MAPPED_ASPECT_NUMBERS],
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            reply_delay_ms: 0,
+            require_arming: false,
+            lamp_aging: false,
+            machine_mode: false,
+            substitution_timeout_s: 0,
+            stub_track: false,
+            warm_up: false,
+            log_filter: LogFilter::new(),
+            motorola_accessories: false,
+            lamp_test: LampTestSchedule::Never,
+            lamp_test_minutes: 0,
+            selectrix_channel: 0,
+            cmri: false,
+            cmri_node_address: 0,
+            bidib: false,
+            srcp: false,
+            binary: false,
+            require_checksum: false,
+            strict_parsing: false,
+            line_terminator: LineTerminator::LineFeed,
+            line_timeout_s: 0,
+            baud_rate: BaudRate::Baud57600,
+            dmx_channel: 1,
+            min_dwell_ms: 0,
+            stop_hold_s: 0,
+            lamp_slew_ms: [0; LampRole::ALL.len()],
+            lamp_pins: DEFAULT_LAMP_PINS,
+            dcc_aspects: DEFAULT_DCC_ASPECTS,
+        }
+    }
+}
+
+/// By default, the aspect numbers 0, 1 and 2 are mapped to Hp0, Hp1 and Hp2, and all others are ignored.
+const DEFAULT_DCC_ASPECTS: [Option<MappedAspect>; MAPPED_ASPECT_NUMBERS] = {
+    let mut dcc_aspects = [None; MAPPED_ASPECT_NUMBERS];
+    dcc_aspects[0] = Some(MappedAspect {
+        aspect: AspectCommand::Zero,
+        speed: None,
+    });
+    dcc_aspects[1] = Some(MappedAspect {
+        aspect: AspectCommand::One,
+        speed: None,
+    });
+    dcc_aspects[2] = Some(MappedAspect {
+        aspect: AspectCommand::Two,
+        speed: None,
+    });
+    dcc_aspects
+};
+
+impl Config {
+    pub const SERIALIZED_SIZE: usize =
+        30 + LampRole::ALL.len() + MAPPED_LAMPS.len() + MAPPED_ASPECT_NUMBERS;
+    pub const MAX_REPLY_DELAY_MS: u8 = 50;
+    pub const MAX_DWELL_MS: u16 = 10_000;
+
+    /// Deserializes a configuration as stored in the EEPROM. Invalid or stale configurations are replaced by the default configuration.
+    pub fn from_bytes(bytes: &[u8; Self::SERIALIZED_SIZE]) -> Self {
+        Self::from_saved_bytes(bytes).unwrap_or_default()
+    }
+
+    /// Deserializes a configuration as stored in the EEPROM, or returns None if it is invalid or stale.
+    fn from_saved_bytes(bytes: &[u8; Self::SERIALIZED_SIZE]) -> Option<Self> {
+        let (header, lamps) = bytes.split_at(30);
+        let (lamp_slew_ms, lamps) = lamps.split_at(LampRole::ALL.len());
+        let (lamp_pins, dcc_aspects) = lamps.split_at(MAPPED_LAMPS.len());
+        let [magic, reply_delay_ms, require_arming, lamp_aging, machine_mode, substitution_timeout_s, stub_track, warm_up, protocol_log_level, diagnostics_log_level, motorola_accessories, lamp_test, lamp_test_minutes @ .., selectrix_channel, cmri, cmri_node_address, bidib, srcp, binary, require_checksum, strict_parsing, line_terminator, line_timeout_s, baud_rate, dmx_channel_low, dmx_channel_high, min_dwell_ms_low, min_dwell_ms_high, stop_hold_s]: [u8; 30] =
+            header.try_into().unwrap();
+        let dmx_channel = u16::from_le_bytes([dmx_channel_low, dmx_channel_high]);
+        let min_dwell_ms = u16::from_le_bytes([min_dwell_ms_low, min_dwell_ms_high]);
+        if magic != CONFIG_MAGIC
+            || reply_delay_ms > Self::MAX_REPLY_DELAY_MS
+            || require_arming > 1
+            || lamp_aging > 1
+            || machine_mode > 1
+            || stub_track > 1
+            || warm_up > 1
+            || Severity::from_level(protocol_log_level).is_none()
+            || Severity::from_level(diagnostics_log_level).is_none()
+            || motorola_accessories > 1
+            || LampTestSchedule::from_value(lamp_test).is_none()
+            || u16::from_le_bytes(lamp_test_minutes) >= MINUTES_PER_DAY
+            || selectrix_channel > selectrix::MAX_CHANNEL
+            || cmri > 1
+            || cmri_node_address > cmri::MAX_NODE_ADDRESS
+            || bidib > 1
+            || srcp > 1
+            || binary > 1
+            || cmri + bidib + srcp + binary > 1
+            || require_checksum > 1
+            || strict_parsing > 1
+            || LineTerminator::from_value(line_terminator).is_none()
+            || BaudRate::from_value(baud_rate).is_none()
+            || !(1..=dmx::MAX_START_CHANNEL).contains(&dmx_channel)
+            || min_dwell_ms > Self::MAX_DWELL_MS
+            || lamp_slew_ms
+                .iter()
+                .any(|ramp_ms| *ramp_ms > slew::MAX_RAMP_MS)
+            || !Self::is_pin_assignment(lamp_pins)
+            || dcc_aspects
+                .iter()
+                .any(|byte| Self::dcc_aspect_from(byte & 0xf, byte >> 4).is_err())
+        {
+            return None;
+        }
+        Some(Self {
+            reply_delay_ms,
+            require_arming: require_arming == 1,
+            lamp_aging: lamp_aging == 1,
+            machine_mode: machine_mode == 1,
+            substitution_timeout_s,
+            stub_track: stub_track == 1,
+            warm_up: warm_up == 1,
+            log_filter: LogFilter {
+                protocol: Severity::from_level(protocol_log_level).unwrap(),
+                diagnostics: Severity::from_level(diagnostics_log_level).unwrap(),
+            },
+            motorola_accessories: motorola_accessories == 1,
+            lamp_test: LampTestSchedule::from_value(lamp_test).unwrap(),
+            lamp_test_minutes: u16::from_le_bytes(lamp_test_minutes),
+            selectrix_channel,
+            cmri: cmri == 1,
+            cmri_node_address,
+            bidib: bidib == 1,
+            srcp: srcp == 1,
+            binary: binary == 1,
+            require_checksum: require_checksum == 1,
+            strict_parsing: strict_parsing == 1,
+            line_terminator: LineTerminator::from_value(line_terminator).unwrap(),
+            line_timeout_s,
+            baud_rate: BaudRate::from_value(baud_rate).unwrap(),
+            dmx_channel,
+            min_dwell_ms,
+            stop_hold_s,
+            lamp_slew_ms: lamp_slew_ms.try_into().unwrap(),
+            lamp_pins: lamp_pins.try_into().unwrap(),
+            dcc_aspects: core::array::from_fn(|number| {
+                let byte = dcc_aspects[number];
+                Self::dcc_aspect_from(byte & 0xf, byte >> 4).unwrap_or(None)
+            }),
+        })
+    }
+
+    /// Returns whether every mapped pin is assigned to exactly one lamp.
+    fn is_pin_assignment(lamp_pins: &[u8]) -> bool {
+        (FIRST_MAPPED_PIN..FIRST_MAPPED_PIN + MAPPED_LAMPS.len() as u8).all(|pin| {
+            lamp_pins
+                .iter()
+                .filter(|lamp_pin| **lamp_pin == pin)
+                .count()
+                == 1
+        })
+    }
+
+    /// Returns the pin of a lamp, as an index into the pins from D2 to D8, or None if the lamp can’t be assigned to a pin.
+    pub fn pin_index_of(&self, role: LampRole) -> Option<usize> {
+        let lamp = MAPPED_LAMPS.iter().position(|mapped| *mapped == role)?;
+        Some(usize::from(self.lamp_pins[lamp] - FIRST_MAPPED_PIN))
+    }
+
+    /// Reads the configuration from the persistent storage, or returns the default configuration if none was saved.
+    pub fn load(platform: &mut impl Platform) -> Self {
+        Self::load_saved(platform).unwrap_or_default()
+    }
+
+    /// Reads the configuration from the persistent storage, or returns None if none was saved, or it is stale, e.g. because a firmware update changed its layout.
+    pub fn load_saved(platform: &mut impl Platform) -> Option<Self> {
+        let mut bytes = [0; Self::SERIALIZED_SIZE];
+        platform.read_persistent(CONFIG_EEPROM_OFFSET, &mut bytes);
+        Self::from_saved_bytes(&bytes)
+    }
+
+    /// Writes the configuration to the persistent storage, so that it is loaded after a reboot.
+    pub fn save(&self, platform: &mut impl Platform) {
+        platform.write_persistent(CONFIG_EEPROM_OFFSET, &self.to_bytes());
+    }
+
+    /// Serializes the configuration for storage in the EEPROM.
+    pub fn to_bytes(self) -> [u8; Self::SERIALIZED_SIZE] {
+        let mut bytes = [0; Self::SERIALIZED_SIZE];
+        let (header, lamps) = bytes.split_at_mut(30);
+        let (lamp_slew_ms, lamps) = lamps.split_at_mut(LampRole::ALL.len());
+        let (lamp_pins, dcc_aspects) = lamps.split_at_mut(MAPPED_LAMPS.len());
+        header[..12].copy_from_slice(&[
+            CONFIG_MAGIC,
+            self.reply_delay_ms,
+            self.require_arming.into(),
+            self.lamp_aging.into(),
+            self.machine_mode.into(),
+            self.substitution_timeout_s,
+            self.stub_track.into(),
+            self.warm_up.into(),
+            self.log_filter.protocol as u8,
+            self.log_filter.diagnostics as u8,
+            self.motorola_accessories.into(),
+            self.lamp_test as u8,
+        ]);
+        header[12..14].copy_from_slice(&self.lamp_test_minutes.to_le_bytes());
+        header[14..25].copy_from_slice(&[
+            self.selectrix_channel,
+            self.cmri.into(),
+            self.cmri_node_address,
+            self.bidib.into(),
+            self.srcp.into(),
+            self.binary.into(),
+            self.require_checksum.into(),
+            self.strict_parsing.into(),
+            self.line_terminator as u8,
+            self.line_timeout_s,
+            self.baud_rate as u8,
+        ]);
+        header[25..27].copy_from_slice(&self.dmx_channel.to_le_bytes());
+        header[27..29].copy_from_slice(&self.min_dwell_ms.to_le_bytes());
+        header[29] = self.stop_hold_s;
+        lamp_slew_ms.copy_from_slice(&self.lamp_slew_ms);
+        lamp_pins.copy_from_slice(&self.lamp_pins);
+        // the speed is stored in the upper and the number of the aspect in the lower four bits.
+        for (byte, mapped) in dcc_aspects.iter_mut().zip(self.dcc_aspects) {
+            *byte = mapped.map_or(0, |mapped| mapped.speed.unwrap_or(0) << 4 | mapped.number());
+        }
+        bytes
+    }
+
+    /// Returns the value of a configuration option.
+    pub fn get(&self, key: ConfigKey) -> u16 {
+        match key {
+            ConfigKey::ReplyDelay => self.reply_delay_ms.into(),
+            ConfigKey::RequireArming => self.require_arming.into(),
+            ConfigKey::LampAging => self.lamp_aging.into(),
+            ConfigKey::MachineMode => self.machine_mode.into(),
+            ConfigKey::SubstitutionTimeout => self.substitution_timeout_s.into(),
+            ConfigKey::StubTrack => self.stub_track.into(),
+            ConfigKey::WarmUp => self.warm_up.into(),
+            ConfigKey::ProtocolLogLevel => (self.log_filter.protocol as u8).into(),
+            ConfigKey::DiagnosticsLogLevel => (self.log_filter.diagnostics as u8).into(),
+            ConfigKey::MotorolaAccessories => self.motorola_accessories.into(),
+            ConfigKey::LampTest => (self.lamp_test as u8).into(),
+            ConfigKey::LampTestTime => self.lamp_test_minutes,
+            ConfigKey::SelectrixChannel => self.selectrix_channel.into(),
+            ConfigKey::Cmri => self.cmri.into(),
+            ConfigKey::CmriNodeAddress => self.cmri_node_address.into(),
+            ConfigKey::Bidib => self.bidib.into(),
+            ConfigKey::Srcp => self.srcp.into(),
+            ConfigKey::Binary => self.binary.into(),
+            ConfigKey::RequireChecksum => self.require_checksum.into(),
+            ConfigKey::StrictParsing => self.strict_parsing.into(),
+            ConfigKey::LineTerminator => (self.line_terminator as u8).into(),
+            ConfigKey::LineTimeout => self.line_timeout_s.into(),
+            ConfigKey::BaudRate => (self.baud_rate as u8).into(),
+            ConfigKey::DmxChannel => self.dmx_channel,
+            ConfigKey::MinimumDwell => self.min_dwell_ms,
+            ConfigKey::StopHold => self.stop_hold_s.into(),
+            ConfigKey::LampSlew(role) => self.lamp_slew_ms[role as usize].into(),
+            ConfigKey::LampPin(role) => self
+                .pin_index_of(role)
+                .map_or(0, |index| u16::from(FIRST_MAPPED_PIN) + index as u16),
+            ConfigKey::DccAspect(number) => self.dcc_aspects[usize::from(number)]
+                .map_or(0, |mapped| {
+                    u16::from(mapped.speed.unwrap_or(0)) * 100 + u16::from(mapped.number())
+                }),
+        }
+    }
+
+    /// Changes a configuration option. Returns an error if the value is out of range for the option, in which case the configuration is unchanged.
+    pub fn set(&mut self, key: ConfigKey, value: u16) -> Result<(), InvalidConfigValue> {
+        match key {
+            ConfigKey::ReplyDelay => {
+                self.reply_delay_ms = u8::try_from(value)
+                    .ok()
+                    .filter(|delay| *delay <= Self::MAX_REPLY_DELAY_MS)
+                    .ok_or(InvalidConfigValue)?;
+            }
+            ConfigKey::RequireArming => self.require_arming = Self::flag_from(value)?,
+            ConfigKey::LampAging => self.lamp_aging = Self::flag_from(value)?,
+            ConfigKey::MachineMode => self.machine_mode = Self::flag_from(value)?,
+            ConfigKey::SubstitutionTimeout => {
+                self.substitution_timeout_s =
+                    u8::try_from(value).map_err(|_| InvalidConfigValue)?;
+            }
+            ConfigKey::StubTrack => self.stub_track = Self::flag_from(value)?,
+            ConfigKey::WarmUp => self.warm_up = Self::flag_from(value)?,
+            ConfigKey::ProtocolLogLevel => self.log_filter.protocol = Self::severity_from(value)?,
+            ConfigKey::DiagnosticsLogLevel => {
+                self.log_filter.diagnostics = Self::severity_from(value)?;
+            }
+            ConfigKey::MotorolaAccessories => self.motorola_accessories = Self::flag_from(value)?,
+            ConfigKey::LampTest => {
+                self.lamp_test = u8::try_from(value)
+                    .ok()
+                    .and_then(LampTestSchedule::from_value)
+                    .ok_or(InvalidConfigValue)?;
+            }
+            ConfigKey::LampTestTime => {
+                if value >= MINUTES_PER_DAY {
+                    return Err(InvalidConfigValue);
+                }
+                self.lamp_test_minutes = value;
+            }
+            ConfigKey::SelectrixChannel => {
+                self.selectrix_channel = u8::try_from(value)
+                    .ok()
+                    .filter(|channel| *channel <= selectrix::MAX_CHANNEL)
+                    .ok_or(InvalidConfigValue)?;
+            }
+            // the serial port speaks only one of C/MRI, BiDiB, SRCP and the binary protocol.
+            ConfigKey::Cmri => {
+                let cmri = Self::flag_from(value)?;
+                if cmri && (self.bidib || self.srcp || self.binary) {
+                    return Err(InvalidConfigValue);
+                }
+                self.cmri = cmri;
+            }
+            ConfigKey::CmriNodeAddress => {
+                self.cmri_node_address = u8::try_from(value)
+                    .ok()
+                    .filter(|address| *address <= cmri::MAX_NODE_ADDRESS)
+                    .ok_or(InvalidConfigValue)?;
+            }
+            ConfigKey::Bidib => {
+                let bidib = Self::flag_from(value)?;
+                if bidib && (self.cmri || self.srcp || self.binary) {
+                    return Err(InvalidConfigValue);
+                }
+                self.bidib = bidib;
+            }
+            ConfigKey::Srcp => {
+                let srcp = Self::flag_from(value)?;
+                if srcp && (self.cmri || self.bidib || self.binary) {
+                    return Err(InvalidConfigValue);
+                }
+                self.srcp = srcp;
+            }
+            ConfigKey::Binary => {
+                let binary = Self::flag_from(value)?;
+                if binary && (self.cmri || self.bidib || self.srcp) {
+                    return Err(InvalidConfigValue);
+                }
+                self.binary = binary;
+            }
+            ConfigKey::RequireChecksum => self.require_checksum = Self::flag_from(value)?,
+            ConfigKey::StrictParsing => self.strict_parsing = Self::flag_from(value)?,
+            ConfigKey::LineTerminator => {
+                self.line_terminator = u8::try_from(value)
+                    .ok()
+                    .and_then(LineTerminator::from_value)
+                    .ok_or(InvalidConfigValue)?;
+            }
+            ConfigKey::LineTimeout => {
+                self.line_timeout_s = u8::try_from(value).map_err(|_| InvalidConfigValue)?;
+            }
+            ConfigKey::BaudRate => {
+                self.baud_rate = u8::try_from(value)
+                    .ok()
+                    .and_then(BaudRate::from_value)
+                    .ok_or(InvalidConfigValue)?;
+            }
+            ConfigKey::DmxChannel => {
+                if !(1..=dmx::MAX_START_CHANNEL).contains(&value) {
+                    return Err(InvalidConfigValue);
+                }
+                self.dmx_channel = value;
+            }
+            ConfigKey::MinimumDwell => {
+                if value > Self::MAX_DWELL_MS {
+                    return Err(InvalidConfigValue);
+                }
+                self.min_dwell_ms = value;
+            }
+            ConfigKey::StopHold => {
+                self.stop_hold_s = u8::try_from(value).map_err(|_| InvalidConfigValue)?;
+            }
+            ConfigKey::LampSlew(role) => {
+                self.lamp_slew_ms[role as usize] = u8::try_from(value)
+                    .ok()
+                    .filter(|ramp_ms| *ramp_ms <= slew::MAX_RAMP_MS)
+                    .ok_or(InvalidConfigValue)?;
+            }
+            // the lamp that had the pin before gets the old pin of this lamp, so that every pin stays assigned to exactly one lamp.
+            ConfigKey::LampPin(role) => {
+                let lamp = MAPPED_LAMPS
+                    .iter()
+                    .position(|mapped| *mapped == role)
+                    .ok_or(InvalidConfigValue)?;
+                let pin = u8::try_from(value)
+                    .ok()
+                    .filter(|pin| {
+                        (FIRST_MAPPED_PIN..FIRST_MAPPED_PIN + MAPPED_LAMPS.len() as u8)
+                            .contains(pin)
+                    })
+                    .ok_or(InvalidConfigValue)?;
+                let other_lamp = self
+                    .lamp_pins
+                    .iter()
+                    .position(|lamp_pin| *lamp_pin == pin)
+                    .unwrap();
+                self.lamp_pins[other_lamp] = self.lamp_pins[lamp];
+                self.lamp_pins[lamp] = pin;
+            }
+            // the value is the number of the aspect plus 100 times the speed.
+            ConfigKey::DccAspect(number) => {
+                let mapped = self
+                    .dcc_aspects
+                    .get_mut(usize::from(number))
+                    .ok_or(InvalidConfigValue)?;
+                let speed = u8::try_from(value / 100).map_err(|_| InvalidConfigValue)?;
+                *mapped = Self::dcc_aspect_from((value % 100) as u8, speed)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the mapped aspect with the given number and speed, or None for number 0, which ignores the aspect number.
+    fn dcc_aspect_from(number: u8, speed: u8) -> Result<Option<MappedAspect>, InvalidConfigValue> {
+        match (number, speed) {
+            (0, 0) => Ok(None),
+            _ => MappedAspect::new(number, speed)
+                .map(Some)
+                .ok_or(InvalidConfigValue),
+        }
+    }
+
+    fn severity_from(value: u16) -> Result<Severity, InvalidConfigValue> {
+        u8::try_from(value)
+            .ok()
+            .and_then(Severity::from_level)
+            .ok_or(InvalidConfigValue)
+    }
+
+    fn flag_from(value: u16) -> Result<bool, InvalidConfigValue> {
+        match value {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(InvalidConfigValue),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use super::ConfigKey;
+    use super::MAPPED_ASPECT_NUMBERS;
+    use crate::baud::BaudRate;
+    use crate::commands::AspectCommand;
+    use crate::dcc::MappedAspect;
+    use crate::framing::LineTerminator;
+    use crate::lamp_test::LampTestSchedule;
+    use crate::logging::LogFilter;
+    use crate::logging::Severity;
+    use crate::mock::MockPlatform;
+    use crate::signals::LampRole;
+
+    #[test]
+    fn assigning_a_lamp_pin_swaps_it_with_the_previous_lamp() {
+        let mut config = Config::default();
+        assert_eq!(config.get(ConfigKey::LampPin(LampRole::MainRed)), 7);
+        assert_eq!(config.get(ConfigKey::LampPin(LampRole::MainGreen)), 8);
+        assert!(config.set(ConfigKey::LampPin(LampRole::MainRed), 8).is_ok());
+        assert_eq!(config.get(ConfigKey::LampPin(LampRole::MainRed)), 8);
+        assert_eq!(config.get(ConfigKey::LampPin(LampRole::MainGreen)), 7);
+        assert!(config
+            .set(ConfigKey::LampPin(LampRole::MainRed), 9)
+            .is_err());
+        assert!(config
+            .set(ConfigKey::LampPin(LampRole::MainNotice), 2)
+            .is_err());
+        assert!(Config::from_bytes(&config.to_bytes()) == config);
+
+        // a duplicate pin invalidates the stored configuration.
+        let mut bytes = config.to_bytes();
+        let last_pin = Config::SERIALIZED_SIZE - MAPPED_ASPECT_NUMBERS - 1;
+        bytes[last_pin] = bytes[last_pin - 1];
+        assert!(Config::from_bytes(&bytes) == Config::default());
+    }
+
+    #[test]
+    fn dcc_aspects_are_set_as_number_and_speed() {
+        let mut config = Config::default();
+        assert_eq!(config.get(ConfigKey::DccAspect(2)), 3);
+        assert_eq!(config.get(ConfigKey::DccAspect(3)), 0);
+        assert!(config.set(ConfigKey::DccAspect(3), 603).is_ok());
+        assert!(config.dcc_aspects[3]
+            .is_some_and(|mapped| mapped.aspect == AspectCommand::Two && mapped.speed == Some(6)));
+        assert_eq!(config.get(ConfigKey::DccAspect(3)), 603);
+        assert!(config.set(ConfigKey::DccAspect(0), 0).is_ok());
+        assert!(config.dcc_aspects[0].is_none());
+        for (number, value) in [(4, 601), (4, 13), (4, 1003), (4, 100), (32, 1)] {
+            assert!(config.set(ConfigKey::DccAspect(number), value).is_err());
+        }
+        assert!(Config::from_bytes(&config.to_bytes()) == config);
+    }
+
+    #[test]
+    fn loads_the_saved_configuration_or_the_default() {
+        let mut platform = MockPlatform::new();
+        assert!(Config::load(&mut platform) == Config::default());
+        assert!(Config::load_saved(&mut platform).is_none());
+        let config = Config {
+            stub_track: true,
+            ..Config::default()
+        };
+        config.save(&mut platform);
+        assert!(Config::load(&mut platform) == config);
+        assert!(Config::load_saved(&mut platform) == Some(config));
+    }
+
+    #[test]
+    fn serial_port_modes_exclude_each_other() {
+        let mut config = Config::default();
+        assert!(config.set(ConfigKey::Cmri, 1).is_ok());
+        assert!(config.set(ConfigKey::Bidib, 1).is_err());
+        assert!(config.set(ConfigKey::Cmri, 0).is_ok());
+        assert!(config.set(ConfigKey::Bidib, 1).is_ok());
+        assert!(config.set(ConfigKey::Cmri, 1).is_err());
+        assert!(config.set(ConfigKey::Srcp, 1).is_err());
+        assert!(config.set(ConfigKey::Binary, 1).is_err());
+        assert!(config.set(ConfigKey::DmxChannel, 0).is_err());
+        assert!(config.set(ConfigKey::DmxChannel, 512).is_err());
+        assert!(Config::from_bytes(&config.to_bytes()) == config);
+
+        let mut bytes = config.to_bytes();
+        bytes[15] = 1;
+        assert!(Config::from_bytes(&bytes) == Config::default());
+    }
+
+    #[test]
+    fn setting_every_option_in_order_copies_a_configuration() {
+        let mut original = Config {
+            warm_up: true,
+            log_filter: LogFilter {
+                protocol: Severity::Error,
+                diagnostics: Severity::Trace,
+            },
+            motorola_accessories: true,
+            lamp_test: LampTestSchedule::RealTime,
+            lamp_test_minutes: 1439,
+            selectrix_channel: 103,
+            cmri: true,
+            cmri_node_address: 127,
+            line_terminator: LineTerminator::CarriageReturnLineFeed,
+            line_timeout_s: 2,
+            baud_rate: BaudRate::Baud115200,
+            dmx_channel: 511,
+            min_dwell_ms: 1500,
+            stop_hold_s: 30,
+            lamp_pins: [2, 3, 4, 5, 6, 7, 8],
+            ..Config::default()
+        };
+        original.lamp_slew_ms[LampRole::MainRed as usize] = 5;
+        original.dcc_aspects[0] = None;
+        original.dcc_aspects[31] = MappedAspect::new(2, 9);
+        let mut copy = Config::default();
+        for key in ConfigKey::all() {
+            assert!(copy.set(key, original.get(key)).is_ok());
+        }
+        assert!(copy == original);
+    }
+}
@@ -0,0 +1,440 @@
+//! Module for decoding the accessory commands of a DCC signal (NMRA S-9.2 and S-9.2.1), which lets the layout’s digital command station switch the signal like any other accessory decoder.
+//!
+//! DCC encodes every bit in the time between the edges of the track signal: both halves of a one bit last about 58 µs, and both halves of a zero bit at least 100 µs. A packet starts with a preamble of at least ten one bits, followed by its bytes, each of which is preceded by a zero bit. A one bit ends the packet, and its last byte is the XOR of all other bytes.
+
+use arrayvec::ArrayVec;
+
+use crate::commands::AspectCommand;
+
+/// Durations of the halves of one and zero bits that are accepted, in microseconds. These are the ranges that S-9.1 requires decoders to accept, widened by the resolution of the controller’s timestamps.
+const ONE_HALF_BIT_US: core::ops::RangeInclusive<u32> = 48..=68;
+const ZERO_HALF_BIT_US: core::ops::RangeInclusive<u32> = 86..=10_000;
+/// Minimum number of one bits before a packet.
+const MIN_PREAMBLE_BITS: u8 = 10;
+/// Maximum length of a packet, including its error detection byte.
+const MAX_PACKET_LENGTH: usize = 6;
+/// Repetitions of an accessory command within this time are ignored, since command stations send every command several times.
+pub const REPEAT_WINDOW_MS: u32 = 500;
+/// Number of extended accessory aspect numbers that can be mapped to aspects, which are the aspect numbers of S-9.2.1.
+pub const MAPPED_ASPECT_NUMBERS: usize = 32;
+
+/// The aspect commands that extended accessory aspect numbers can be mapped to, numbered from 1 in this order.
+pub const MAPPABLE_ASPECTS: [AspectCommand; 12] = [
+    AspectCommand::Zero,
+    AspectCommand::One,
+    AspectCommand::Two,
+    AspectCommand::Three,
+    AspectCommand::Shunting,
+    AspectCommand::Substitution,
+    AspectCommand::Caution,
+    AspectCommand::CounterTrack,
+    AspectCommand::CounterTrackSlow,
+    AspectCommand::CounterTrackSubstitution,
+    AspectCommand::Deactivated,
+    AspectCommand::Dark,
+];
+
+/// An aspect command that an accessory command is mapped to, with the speed of the Zs3 indicator.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct MappedAspect {
+    pub aspect: AspectCommand,
+    /// Speed in tens of km/h, if the aspect is shown with a speed.
+    pub speed: Option<u8>,
+}
+
+impl MappedAspect {
+    /// Returns the aspect with the given number in [`MAPPABLE_ASPECTS`] and the given speed, or 0 for no speed. Like in the serial protocol, speeds from 1 to 9 can only be shown with proceed and proceed slowly.
+    pub fn new(number: u8, speed: u8) -> Option<Self> {
+        let aspect = *MAPPABLE_ASPECTS.get(usize::from(number).checked_sub(1)?)?;
+        let speed = match speed {
+            0 => None,
+            1..=9 if matches!(aspect, AspectCommand::One | AspectCommand::Two) => Some(speed),
+            _ => return None,
+        };
+        Some(Self { aspect, speed })
+    }
+
+    /// Returns the number of the aspect in [`MAPPABLE_ASPECTS`].
+    pub fn number(self) -> u8 {
+        MAPPABLE_ASPECTS
+            .iter()
+            .position(|aspect| *aspect == self.aspect)
+            .map_or(0, |index| index as u8 + 1)
+    }
+}
+
+/// An accessory command for a single address. Addresses are output addresses, numbered from 1 like on most command stations, where every decoder has four consecutive addresses.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AccessoryCommand {
+    /// Switches one of the two outputs of an address, like the straight (green) or diverging (red) route of a turnout. Command stations activate the output, and deactivate it again shortly after.
+    Basic {
+        address: u16,
+        is_green: bool,
+        is_active: bool,
+    },
+    /// Shows a numbered aspect on an extended accessory decoder for signals.
+    Extended { address: u16, aspect: u8 },
+}
+
+impl AccessoryCommand {
+    /// Parses a packet with a valid error detection byte, and returns its accessory command, if it is one.
+    fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.iter().fold(0, |checksum, byte| checksum ^ byte) != 0 {
+            return None;
+        }
+        let (first, second) = match packet {
+            [first, second, ..] if first & 0xc0 == 0x80 => (*first, *second),
+            _ => return None,
+        };
+        // the upper three bits of the decoder address are sent inverted.
+        let decoder_address = u16::from(!second >> 4 & 0x7) << 6 | u16::from(first & 0x3f);
+        let output_pair = u16::from(second >> 1 & 0x3);
+        // decoder address 0 only has output address 0, which is invalid.
+        let address = (decoder_address * 4 + output_pair + 1)
+            .checked_sub(4)
+            .filter(|address| *address != 0)?;
+        match packet.len() {
+            3 if second & 0x80 != 0 => Some(Self::Basic {
+                address,
+                is_green: second & 0x01 != 0,
+                is_active: second & 0x08 != 0,
+            }),
+            4 if second & 0x89 == 0x01 => Some(Self::Extended {
+                address,
+                aspect: packet[2],
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes a DCC signal from the durations between its edges.
+pub struct DccDecoder {
+    // Whether the first half of the current bit was a one, if it was received.
+    first_half: Option<bool>,
+    // Number of one bits received in a row, while waiting for a packet.
+    preamble_bits: u8,
+    // Received bytes of the current packet, if one started.
+    packet: Option<ArrayVec<u8, MAX_PACKET_LENGTH>>,
+    // The byte that is being received, and how many of its bits were received.
+    byte: u8,
+    byte_bits: u8,
+}
+
+impl Default for DccDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DccDecoder {
+    pub const fn new() -> Self {
+        Self {
+            first_half: None,
+            preamble_bits: 0,
+            packet: None,
+            byte: 0,
+            byte_bits: 0,
+        }
+    }
+
+    /// Processes the duration since the previous edge of the signal, in microseconds, and returns the accessory command that it completes, if any. Should be called on every edge.
+    pub fn receive_half_bit(&mut self, duration_us: u32) -> Option<AccessoryCommand> {
+        let is_one = if ONE_HALF_BIT_US.contains(&duration_us) {
+            true
+        } else if ZERO_HALF_BIT_US.contains(&duration_us) {
+            false
+        } else {
+            // noise, or no DCC signal at all.
+            *self = Self::new();
+            return None;
+        };
+        match self.first_half.take() {
+            Some(first_half) if first_half == is_one => self.receive_bit(is_one),
+            // halves of different bits were paired, which resolves itself at the first zero bit of a packet.
+            _ => {
+                self.first_half = Some(is_one);
+                None
+            }
+        }
+    }
+
+    fn receive_bit(&mut self, is_one: bool) -> Option<AccessoryCommand> {
+        let Some(packet) = &mut self.packet else {
+            if is_one {
+                self.preamble_bits = self.preamble_bits.saturating_add(1);
+            } else if self.preamble_bits >= MIN_PREAMBLE_BITS {
+                self.packet = Some(ArrayVec::new());
+                self.byte_bits = 0;
+            } else {
+                self.preamble_bits = 0;
+            }
+            return None;
+        };
+        if self.byte_bits < 8 {
+            self.byte = self.byte << 1 | u8::from(is_one);
+            self.byte_bits += 1;
+            return None;
+        }
+        // the bit after a byte starts the next byte, or ends the packet.
+        if packet.try_push(self.byte).is_err() {
+            *self = Self::new();
+            return None;
+        }
+        self.byte_bits = 0;
+        if !is_one {
+            return None;
+        }
+        let packet = self.packet.take()?;
+        // the end bit may already be part of the next preamble.
+        self.preamble_bits = 1;
+        AccessoryCommand::parse(&packet)
+    }
+}
+
+/// Maps the accessory commands for the signal’s addresses to aspect commands.
+pub struct AccessoryMapping {
+    // The signal’s first address, which is also the address of its extended accessory commands.
+    address: u16,
+    // Aspects commanded by the red and green output of each basic accessory address, starting at the first address.
+    basic_aspects: &'static [[Option<AspectCommand>; 2]],
+    // Last command for the signal’s addresses and the time it was received.
+    last_command: Option<(AccessoryCommand, u32)>,
+}
+
+impl AccessoryMapping {
+    pub const fn new(address: u16, basic_aspects: &'static [[Option<AspectCommand>; 2]]) -> Self {
+        Self {
+            address,
+            basic_aspects,
+            last_command: None,
+        }
+    }
+
+    /// Returns the aspect commanded by an accessory command received at the given time, if it is for one of the signal’s addresses and not a repetition. Extended accessory commands are mapped with the given aspect of each aspect number, which is configurable. Deactivations of basic accessory outputs are ignored, since the signal keeps its aspect.
+    pub fn aspect_for(
+        &mut self,
+        command: AccessoryCommand,
+        extended_aspects: &[Option<MappedAspect>; MAPPED_ASPECT_NUMBERS],
+        now: u32,
+    ) -> Option<MappedAspect> {
+        let aspect = match command {
+            AccessoryCommand::Basic {
+                address,
+                is_green,
+                is_active: true,
+            } => address
+                .checked_sub(self.address)
+                .and_then(|index| self.basic_aspects.get(usize::from(index)))?
+                .get(usize::from(is_green))?
+                .map(|aspect| MappedAspect {
+                    aspect,
+                    speed: None,
+                }),
+            AccessoryCommand::Extended { address, aspect } if address == self.address => {
+                *extended_aspects.get(usize::from(aspect))?
+            }
+            _ => None,
+        }?;
+        let is_repetition = self
+            .last_command
+            .is_some_and(|(last_command, received_at)| {
+                last_command == command && now.wrapping_sub(received_at) < REPEAT_WINDOW_MS
+            });
+        self.last_command = Some((command, now));
+        (!is_repetition).then_some(aspect)
+    }
+
+    /// Returns the basic accessory outputs that command the given aspect, as their address and whether they are the green output, so that the signal can report them as switched on.
+    pub fn outputs_for(&self, aspect: AspectCommand) -> impl Iterator<Item = (u16, bool)> + '_ {
+        (self.address..)
+            .zip(self.basic_aspects)
+            .flat_map(move |(address, aspects)| {
+                [false, true]
+                    .into_iter()
+                    .filter(move |is_green| aspects[usize::from(*is_green)] == Some(aspect))
+                    .map(move |is_green| (address, is_green))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AccessoryCommand;
+    use super::AccessoryMapping;
+    use super::DccDecoder;
+    use super::MappedAspect;
+    use super::MAPPED_ASPECT_NUMBERS;
+    use super::REPEAT_WINDOW_MS;
+    use crate::commands::AspectCommand;
+
+    /// Returns the durations between the edges of a packet with the given bytes and their error detection byte.
+    fn encode(bytes: &[u8]) -> Vec<u32> {
+        let checksum = bytes.iter().fold(0, |checksum, byte| checksum ^ byte);
+        let mut bits = vec![true; 14];
+        for byte in bytes.iter().chain([&checksum]) {
+            bits.push(false);
+            bits.extend((0..8).rev().map(|bit| byte >> bit & 1 != 0));
+        }
+        bits.push(true);
+        bits.into_iter()
+            .flat_map(|is_one| if is_one { [58, 58] } else { [100, 100] })
+            .collect()
+    }
+
+    fn decode(decoder: &mut DccDecoder, durations: &[u32]) -> Vec<AccessoryCommand> {
+        durations
+            .iter()
+            .filter_map(|duration| decoder.receive_half_bit(*duration))
+            .collect()
+    }
+
+    #[test]
+    fn decodes_accessory_packets() {
+        let mut decoder = DccDecoder::new();
+        assert_eq!(
+            decode(&mut decoder, &encode(&[0x81, 0xf9])),
+            [AccessoryCommand::Basic {
+                address: 1,
+                is_green: true,
+                is_active: true
+            }]
+        );
+        // starting in the middle of a bit.
+        let mut decoder = DccDecoder::new();
+        assert_eq!(
+            decode(&mut decoder, &encode(&[0x82, 0xf2])[1..]),
+            [AccessoryCommand::Basic {
+                address: 6,
+                is_green: false,
+                is_active: false
+            }]
+        );
+        assert_eq!(
+            decode(&mut decoder, &encode(&[0x81, 0x71, 0x05])),
+            [AccessoryCommand::Extended {
+                address: 1,
+                aspect: 5
+            }]
+        );
+        // the highest decoder address bits are inverted.
+        assert_eq!(
+            decode(&mut decoder, &encode(&[0xbf, 0x87])),
+            [AccessoryCommand::Basic {
+                address: 2044,
+                is_green: true,
+                is_active: false
+            }]
+        );
+    }
+
+    #[test]
+    fn ignores_invalid_and_other_packets() {
+        let mut decoder = DccDecoder::new();
+        // idle packet and a locomotive speed packet.
+        assert!(decode(&mut decoder, &encode(&[0xff, 0x00])).is_empty());
+        assert!(decode(&mut decoder, &encode(&[0x03, 0x3f, 0x90])).is_empty());
+        // decoder address 0.
+        assert!(decode(&mut decoder, &encode(&[0x80, 0xf9])).is_empty());
+
+        let mut corrupted = encode(&[0x81, 0xf9]);
+        let last_checksum_bit = corrupted.len() - 4;
+        corrupted[last_checksum_bit..last_checksum_bit + 2].copy_from_slice(&[58, 58]);
+        assert!(decode(&mut decoder, &corrupted).is_empty());
+
+        let mut short_preamble = encode(&[0x81, 0xf9]);
+        short_preamble.drain(..10);
+        assert!(decode(&mut DccDecoder::new(), &short_preamble).is_empty());
+
+        let mut noisy = encode(&[0x81, 0xf9]);
+        noisy[40] = 20;
+        assert!(decode(&mut decoder, &noisy).is_empty());
+        assert_eq!(decode(&mut decoder, &encode(&[0x81, 0xf9])).len(), 1);
+    }
+
+    #[test]
+    fn maps_addresses_to_aspects_once() {
+        static BASIC_ASPECTS: [[Option<AspectCommand>; 2]; 2] = [
+            [Some(AspectCommand::Zero), Some(AspectCommand::One)],
+            [None, Some(AspectCommand::Two)],
+        ];
+        let mut extended_aspects = [None; MAPPED_ASPECT_NUMBERS];
+        extended_aspects[0] = MappedAspect::new(3, 6);
+        let mut mapping = AccessoryMapping::new(5, &BASIC_ASPECTS);
+        let mut aspect_at = |command, now| {
+            mapping
+                .aspect_for(command, &extended_aspects, now)
+                .map(|mapped| (mapped.aspect, mapped.speed))
+        };
+        let basic = |address, is_green, is_active| AccessoryCommand::Basic {
+            address,
+            is_green,
+            is_active,
+        };
+
+        assert!(matches!(
+            aspect_at(basic(5, true, true), 0),
+            Some((AspectCommand::One, None))
+        ));
+        assert!(aspect_at(basic(5, true, true), 100).is_none());
+        assert!(aspect_at(basic(5, true, false), 150).is_none());
+        assert!(matches!(
+            aspect_at(basic(5, true, true), 200 + REPEAT_WINDOW_MS),
+            Some((AspectCommand::One, None))
+        ));
+        assert!(matches!(
+            aspect_at(basic(6, true, true), 1000),
+            Some((AspectCommand::Two, None))
+        ));
+        assert!(matches!(
+            aspect_at(basic(5, false, true), 1100),
+            Some((AspectCommand::Zero, None))
+        ));
+        for command in [
+            basic(4, true, true),
+            basic(6, false, true),
+            basic(7, true, true),
+            AccessoryCommand::Extended {
+                address: 6,
+                aspect: 0,
+            },
+            AccessoryCommand::Extended {
+                address: 5,
+                aspect: 1,
+            },
+            AccessoryCommand::Extended {
+                address: 5,
+                aspect: 40,
+            },
+        ] {
+            assert!(aspect_at(command, 2000).is_none());
+        }
+        assert!(matches!(
+            aspect_at(
+                AccessoryCommand::Extended {
+                    address: 5,
+                    aspect: 0
+                },
+                3000
+            ),
+            Some((AspectCommand::Two, Some(6)))
+        ));
+
+        assert_eq!(
+            mapping.outputs_for(AspectCommand::Two).collect::<Vec<_>>(),
+            [(6, true)]
+        );
+        assert_eq!(mapping.outputs_for(AspectCommand::Dark).count(), 0);
+    }
+
+    #[test]
+    fn speeds_are_only_mapped_with_proceed_aspects() {
+        let proceed_slowly = MappedAspect::new(3, 4).unwrap();
+        assert!(proceed_slowly.aspect == AspectCommand::Two);
+        assert_eq!(proceed_slowly.number(), 3);
+        assert!(MappedAspect::new(12, 0).is_some_and(|dark| dark.aspect == AspectCommand::Dark));
+        for (number, speed) in [(0, 0), (13, 0), (1, 4), (2, 10)] {
+            assert!(MappedAspect::new(number, speed).is_none());
+        }
+    }
+}
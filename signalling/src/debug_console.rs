@@ -0,0 +1,69 @@
+//! Module for the debug console, a serial output in software on which diagnostics and panic messages are sent instead of the serial port, so that they don’t mix with the lines that the control box parses.
+//!
+//! Bytes are sent like on a serial port at 38 400 baud, with a start bit, eight data bits (the lowest first) and a stop bit. The line idles high, and the console only sends, so a USB serial adapter’s RX input is all it needs.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+
+/// Baud rate of the console.
+pub const BAUD_RATE: u32 = 38_400;
+/// Duration of a bit, in microseconds, rounded down from 26.04.
+const BIT_US: u32 = 26;
+/// Bits of a byte on the line, with the start and the stop bit.
+pub const BYTE_BITS: u8 = 10;
+
+/// The output of the debug console.
+pub struct DebugConsole<P, D> {
+    output: P,
+    delay: D,
+}
+
+impl<P: OutputPin, D: DelayNs> DebugConsole<P, D> {
+    /// Creates the console on the given output, which must already be set high, since the line idles high.
+    pub fn new(output: P, delay: D) -> Self {
+        Self { output, delay }
+    }
+
+    /// Sends a single byte, which takes about 260 µs.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    pub fn send_byte(&mut self, byte: u8) -> Result<(), P::Error> {
+        for bit in 0..BYTE_BITS {
+            self.send_bit(byte, bit)?;
+        }
+        Ok(())
+    }
+
+    /// Sends one of the bits of a byte on the line, from the start bit, 0, to the stop bit, `BYTE_BITS - 1`. The timing of a bit must not be disturbed, so interrupts should be disabled while it is sent, but they can run between the bits: an interrupt only stretches the bit before it, which the receiver tolerates as long as the interrupt takes less than about half a bit, 13 µs.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    pub fn send_bit(&mut self, byte: u8, bit: u8) -> Result<(), P::Error> {
+        let bits = u16::from(byte) << 1 | 1 << (BYTE_BITS - 1);
+        self.output.set_state((bits >> bit & 1 != 0).into())?;
+        self.delay.delay_us(BIT_US);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DebugConsole;
+    use super::BIT_US;
+    use crate::mock::MockDelay;
+    use crate::mock::MockPins;
+
+    #[test]
+    fn sends_bytes_lowest_bit_first() {
+        let pins = MockPins::new();
+        let mut console = DebugConsole::new(pins.pin(), MockDelay::default());
+        console.send_byte(b'F').unwrap();
+        let levels: Vec<bool> = pins.history().into_iter().map(|states| states[0]).collect();
+        assert_eq!(
+            levels,
+            [false, false, true, true, false, false, false, true, false, true]
+        );
+        assert_eq!(console.delay.total_ns, 10 * u64::from(BIT_US) * 1000);
+    }
+}
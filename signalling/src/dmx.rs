@@ -0,0 +1,129 @@
+//! Module for DMX512, the bus of lighting consoles, which continuously send the values of up to 512 channels, and two of which the signal reads its aspect and its Zs3 speed from.
+//!
+//! The bus runs at 250 000 baud with eight data bits and two stop bits. Each packet starts with a break, a low level longer than a byte, which the USART reports as a zero byte with a framing error. The start code follows in slot 0, which is 0 for packets with channel values; other start codes, like the one of RDM, are ignored. The values of channels 1 to 512 follow in slots 1 to 512, and consoles repeat the packet about 44 times a second.
+
+use crate::commands::AspectCommand;
+
+/// Baud rate of the bus.
+pub const BAUD_RATE: u32 = 250_000;
+/// Highest channel that the aspect can be read from, since the Zs3 speed is read from the next channel.
+pub const MAX_START_CHANNEL: u16 = 511;
+/// Number of channels in a packet.
+const CHANNELS: u16 = 512;
+/// Start code of packets with channel values.
+const NULL_START_CODE: u8 = 0;
+
+/// Receives the channels on the bus, byte by byte.
+#[derive(Default)]
+pub struct DmxReceiver {
+    // Slot of the next byte, or None while waiting for a break.
+    slot: Option<u16>,
+    // Value of the start channel in the current packet.
+    start_value: u8,
+}
+
+impl DmxReceiver {
+    pub const fn new() -> Self {
+        Self {
+            slot: None,
+            start_value: 0,
+        }
+    }
+
+    /// Processes a received byte, or a break if the USART reported a framing error, and returns the values of the given start channel and the channel after it once a packet contained both.
+    pub fn receive(&mut self, byte: u8, is_break: bool, start_channel: u16) -> Option<[u8; 2]> {
+        if is_break {
+            self.slot = Some(0);
+            return None;
+        }
+        let slot = self.slot?;
+        if slot == 0 && byte != NULL_START_CODE {
+            self.slot = None;
+            return None;
+        }
+        self.slot = Some(slot + 1).filter(|next| *next <= CHANNELS);
+        if slot == start_channel {
+            self.start_value = byte;
+        } else if slot == start_channel + 1 {
+            return Some([self.start_value, byte]);
+        }
+        None
+    }
+}
+
+/// Maps the values of the aspect and the speed channel to aspect commands.
+pub struct DmxMapping {
+    // Aspect commanded by each equal range of values of the aspect channel.
+    aspects: &'static [Option<AspectCommand>],
+    // The last range of the aspect channel and speed.
+    last_value: Option<(usize, Option<u8>)>,
+}
+
+impl DmxMapping {
+    pub const fn new(aspects: &'static [Option<AspectCommand>]) -> Self {
+        Self {
+            aspects,
+            last_value: None,
+        }
+    }
+
+    /// Returns the aspect and the Zs3 speed commanded by the values of the aspect and the speed channel, if they changed since the last values. The values 0 to 255 of the aspect channel are split into equal ranges, one for each aspect, and the speed channel shows its value divided by 16, in tens of km/h, so that values below 16 show no speed. Like a central unit, the console keeps the state of its channels, so the first values after a reboot command their aspect too.
+    pub fn command_for(&mut self, values: [u8; 2]) -> Option<(AspectCommand, Option<u8>)> {
+        let range = usize::from(values[0]) * self.aspects.len() / 256;
+        let speed = Some(values[1] / 16).filter(|speed| *speed != 0);
+        if self.last_value.replace((range, speed)) == Some((range, speed)) {
+            return None;
+        }
+        Some(((*self.aspects.get(range)?)?, speed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DmxMapping;
+    use super::DmxReceiver;
+    use crate::commands::AspectCommand;
+
+    fn receive(receiver: &mut DmxReceiver, start_code: u8, start_channel: u16) -> Vec<[u8; 2]> {
+        let mut values = Vec::new();
+        values.extend(receiver.receive(0, true, start_channel));
+        values.extend(receiver.receive(start_code, false, start_channel));
+        for channel in 1..=512u16 {
+            values.extend(receiver.receive(channel as u8, false, start_channel));
+        }
+        values
+    }
+
+    #[test]
+    fn receives_the_start_channel_and_the_next_one() {
+        let mut receiver = DmxReceiver::new();
+        // bytes before the first break are ignored.
+        assert!(receiver.receive(7, false, 1).is_none());
+        assert_eq!(receive(&mut receiver, 0, 1), [[1, 2]]);
+        assert_eq!(receive(&mut receiver, 0, 300), [[44, 45]]);
+        assert_eq!(receive(&mut receiver, 0, 511), [[255, 0]]);
+        // packets with other start codes are ignored.
+        assert!(receive(&mut receiver, 0xcc, 1).is_empty());
+        // and so are bytes after the last channel.
+        assert!(receiver.receive(1, false, 1).is_none());
+    }
+
+    #[test]
+    fn maps_changes_of_the_channels_to_aspects_and_speeds() {
+        const ASPECTS: [Option<AspectCommand>; 4] = [
+            Some(AspectCommand::Zero),
+            Some(AspectCommand::One),
+            Some(AspectCommand::Two),
+            None,
+        ];
+        let mut mapping = DmxMapping::new(&ASPECTS);
+        assert!(mapping.command_for([0, 0]) == Some((AspectCommand::Zero, None)));
+        assert!(mapping.command_for([63, 15]).is_none());
+        assert!(mapping.command_for([64, 15]) == Some((AspectCommand::One, None)));
+        assert!(mapping.command_for([150, 63]) == Some((AspectCommand::Two, Some(3))));
+        assert!(mapping.command_for([191, 255]) == Some((AspectCommand::Two, Some(15))));
+        // unmapped ranges are ignored.
+        assert!(mapping.command_for([192, 255]).is_none());
+        assert!(mapping.command_for([255, 255]).is_none());
+    }
+}
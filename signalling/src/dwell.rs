@@ -0,0 +1,99 @@
+//! Module for the minimum dwell time of aspects, which keeps rapid successive commands from making the signal flicker between aspects, which real signals never do.
+//!
+//! Aspect commands that arrive before the shown aspect was shown for the minimum dwell time wait in a short queue, and are executed in order, each after the previous aspect was shown for the dwell time. Commands to stop are never delayed, and are executed by the caller right away, so the queue is cleared for them.
+
+use arrayvec::ArrayVec;
+
+/// Number of aspect commands that can wait for their turn.
+pub const QUEUE_LENGTH: usize = 4;
+
+/// Queue of aspect commands `C` for a signal that shows aspects `A`.
+///
+/// Time is given in milliseconds since boot, as returned by the clock.
+pub struct AspectQueue<C, A> {
+    waiting: ArrayVec<C, QUEUE_LENGTH>,
+    // The shown aspect, and the time at which it was first seen.
+    shown: Option<(A, u32)>,
+}
+
+impl<C, A: Copy + PartialEq> AspectQueue<C, A> {
+    pub const fn new() -> Self {
+        Self {
+            waiting: ArrayVec::new_const(),
+            shown: None,
+        }
+    }
+
+    /// Notes the aspect that the signal shows, whatever switched it, so that its dwell time counts from the time it was first shown.
+    pub fn observe(&mut self, aspect: A, now: u32) {
+        if self.shown.map(|(shown, _)| shown) != Some(aspect) {
+            self.shown = Some((aspect, now));
+        }
+    }
+
+    /// Returns whether a command has to wait in the queue, since others wait already, or the shown aspect hasn’t been shown for the dwell time.
+    pub fn must_wait(&self, dwell_ms: u32, now: u32) -> bool {
+        !self.waiting.is_empty() || !self.has_dwelled(dwell_ms, now)
+    }
+
+    /// Appends a command to the queue. Returns the command as an error if the queue is full.
+    pub fn push(&mut self, command: C) -> Result<(), C> {
+        self.waiting
+            .try_push(command)
+            .map_err(|error| error.element())
+    }
+
+    /// Removes the oldest command, once the shown aspect has been shown for the dwell time.
+    pub fn pop_due(&mut self, dwell_ms: u32, now: u32) -> Option<C> {
+        if self.waiting.is_empty() || !self.has_dwelled(dwell_ms, now) {
+            return None;
+        }
+        Some(self.waiting.remove(0))
+    }
+
+    /// Discards all waiting commands, e.g. because a stop overtook them.
+    pub fn clear(&mut self) {
+        self.waiting.clear();
+    }
+
+    fn has_dwelled(&self, dwell_ms: u32, now: u32) -> bool {
+        self.shown
+            .map_or(true, |(_, since)| now.wrapping_sub(since) >= dwell_ms)
+    }
+}
+
+impl<C, A: Copy + PartialEq> Default for AspectQueue<C, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AspectQueue;
+    use super::QUEUE_LENGTH;
+
+    #[test]
+    fn executes_commands_after_the_dwell_time() {
+        let mut queue = AspectQueue::<u8, u8>::new();
+        queue.observe(0, 1000);
+        assert!(queue.must_wait(500, 1499));
+        assert!(!queue.must_wait(500, 1500));
+        // the aspect is switched, and the next commands arrive right away.
+        queue.observe(1, 1500);
+        queue.observe(1, 1600);
+        for command in 2..2 + QUEUE_LENGTH as u8 {
+            assert!(queue.must_wait(500, 1600));
+            assert_eq!(queue.push(command), Ok(()));
+        }
+        assert_eq!(queue.push(9), Err(9));
+        assert_eq!(queue.pop_due(500, 1999), None);
+        assert_eq!(queue.pop_due(500, 2000), Some(2));
+        queue.observe(2, 2000);
+        assert_eq!(queue.pop_due(500, 2499), None);
+        assert!(queue.must_wait(500, 2500));
+        queue.clear();
+        assert!(!queue.must_wait(500, 2500));
+        assert_eq!(queue.pop_due(500, 2500), None);
+    }
+}
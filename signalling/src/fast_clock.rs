@@ -0,0 +1,67 @@
+//! Module for following the layout’s fast clock, which runs faster than real time during operating sessions.
+
+/// Minutes in a day, after which the fast-clock time wraps around.
+pub const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// The layout’s fast clock, as last broadcast by the layout control software.
+///
+/// Between broadcasts, the clock runs on by itself at the broadcast ratio, so that broadcasts can be rare. A ratio of 0 stops the clock, for example while the operating session is paused.
+pub struct FastClock {
+    // How many fast-clock seconds pass per real second.
+    ratio: u8,
+    // Fast-clock time of the last broadcast, in minutes since midnight.
+    minutes_at_sync: u16,
+    // Real time of the last broadcast, in milliseconds.
+    synced_at_ms: u32,
+}
+
+impl FastClock {
+    /// Creates a fast clock that was just told its ratio and time, in minutes since midnight.
+    pub fn new(ratio: u8, minutes: u16, now: u32) -> Self {
+        Self {
+            ratio,
+            minutes_at_sync: minutes % MINUTES_PER_DAY,
+            synced_at_ms: now,
+        }
+    }
+
+    /// Returns the current fast-clock time, in minutes since midnight.
+    pub fn minutes(&self, now: u32) -> u16 {
+        // whole real seconds keep the product from overflowing, even at the highest ratio.
+        let fast_seconds = now.wrapping_sub(self.synced_at_ms) / 1000 * u32::from(self.ratio);
+        let minutes = u32::from(self.minutes_at_sync) + fast_seconds / 60;
+        (minutes % u32::from(MINUTES_PER_DAY)) as u16
+    }
+}
+
+/// Returns whether the fast-clock time lies in the period from the first time up to (but excluding) the second time. The period may span midnight.
+pub fn is_within(minutes: u16, from_minutes: u16, until_minutes: u16) -> bool {
+    if from_minutes <= until_minutes {
+        (from_minutes..until_minutes).contains(&minutes)
+    } else {
+        minutes >= from_minutes || minutes < until_minutes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_within;
+    use super::FastClock;
+
+    #[test]
+    fn runs_at_the_ratio_and_wraps_at_midnight() {
+        let clock = FastClock::new(4, 23 * 60 + 50, 1000);
+        assert_eq!(clock.minutes(1000), 23 * 60 + 50);
+        // 150 real seconds are 10 fast minutes.
+        assert_eq!(clock.minutes(151_000), 0);
+        assert_eq!(clock.minutes(166_000), 1);
+
+        let stopped = FastClock::new(0, 12 * 60, 0);
+        assert_eq!(stopped.minutes(3_600_000), 12 * 60);
+
+        assert!(is_within(23 * 60, 22 * 60, 6 * 60));
+        assert!(is_within(5 * 60, 22 * 60, 6 * 60));
+        assert!(!is_within(6 * 60, 22 * 60, 6 * 60));
+        assert!(!is_within(21 * 60, 22 * 60, 6 * 60));
+    }
+}
@@ -0,0 +1,196 @@
+//! Module for finding the ends of the lines of the text protocol in the received bytes.
+//!
+//! Control boxes end their lines with a line feed, a carriage return, or both. The configured terminator is translated into a single line feed, which ends the lines in the line buffer. A partial line whose terminator never arrives, e.g. because a flaky USB adapter lost it, can be discarded after a timeout, so that it doesn’t stay in the line buffer forever.
+//!
+//! A line that doesn’t fit into the line buffer is dropped as a whole, instead of cutting off a command, which could then be parsed as another command.
+
+use arrayvec::ArrayVec;
+
+/// The bytes that end a line.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum LineTerminator {
+    /// A line feed, as sent by Linux and macOS. Carriage returns are kept in the line.
+    LineFeed = 0,
+    /// A carriage return, as sent by many terminal programs. Line feeds are dropped.
+    CarriageReturn = 1,
+    /// A carriage return followed by a line feed, as sent by Windows. Lone line feeds and carriage returns are dropped.
+    CarriageReturnLineFeed = 2,
+}
+
+impl LineTerminator {
+    /// Returns the terminator with the given value, as used by the configuration options.
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::LineFeed),
+            1 => Some(Self::CarriageReturn),
+            2 => Some(Self::CarriageReturnLineFeed),
+            _ => None,
+        }
+    }
+}
+
+/// Translates the received bytes into the lines of the line buffer, and keeps track of how long the partial line has been waiting for its terminator.
+///
+/// Time is given in milliseconds since boot, as returned by the clock.
+#[derive(Default)]
+pub struct LineFramer {
+    // Whether the previous byte was a carriage return that may start a CRLF terminator.
+    pending_carriage_return: bool,
+    // Time at which the first byte of the partial line was received, if there is one.
+    partial_line_since: Option<u32>,
+    // Whether the rest of a line that didn’t fit into the line buffer is dropped, up to its terminator.
+    is_dropping_line: bool,
+}
+
+impl LineFramer {
+    pub const fn new() -> Self {
+        Self {
+            pending_carriage_return: false,
+            partial_line_since: None,
+            is_dropping_line: false,
+        }
+    }
+
+    /// Returns the byte to append to the line buffer for a received byte, which is a line feed at the end of a line, or None if the byte is dropped.
+    pub fn receive_byte(&mut self, byte: u8, terminator: LineTerminator, now: u32) -> Option<u8> {
+        let was_carriage_return = core::mem::take(&mut self.pending_carriage_return);
+        let translated = match (terminator, byte) {
+            (LineTerminator::LineFeed, _) => Some(byte),
+            (LineTerminator::CarriageReturn, b'\r') => Some(b'\n'),
+            (LineTerminator::CarriageReturn, b'\n') => None,
+            (LineTerminator::CarriageReturnLineFeed, b'\r') => {
+                self.pending_carriage_return = true;
+                None
+            }
+            (LineTerminator::CarriageReturnLineFeed, b'\n') => was_carriage_return.then_some(b'\n'),
+            _ => Some(byte),
+        };
+        match translated {
+            Some(b'\n') => self.partial_line_since = None,
+            // the rest of a dropped line was counted as an overrun already, so it doesn’t time out as well.
+            Some(_) if !self.is_dropping_line => {
+                self.partial_line_since.get_or_insert(now);
+            }
+            _ => {}
+        }
+        translated
+    }
+
+    /// Appends a byte returned by [`Self::receive_byte`] to the line buffer. If the buffer is full, the incomplete line at its end is dropped, and so is the rest of it until its terminator. Returns whether a line was dropped, so that the overruns can be counted.
+    pub fn append<const CAPACITY: usize>(
+        &mut self,
+        line_buffer: &mut ArrayVec<u8, CAPACITY>,
+        byte: u8,
+    ) -> bool {
+        if self.is_dropping_line {
+            self.is_dropping_line = byte != b'\n';
+            return false;
+        }
+        if line_buffer.try_push(byte).is_ok() {
+            return false;
+        }
+        let start_of_partial_line = line_buffer
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map_or(0, |position| position + 1);
+        line_buffer.truncate(start_of_partial_line);
+        self.partial_line_since = None;
+        self.is_dropping_line = byte != b'\n';
+        true
+    }
+
+    /// Returns whether the partial line has been waiting for its terminator for longer than the timeout, and forgets it if so, since the caller discards it. A timeout of 0 waits forever.
+    pub fn has_timed_out(&mut self, timeout_ms: u32, now: u32) -> bool {
+        let has_timed_out = timeout_ms != 0
+            && self
+                .partial_line_since
+                .is_some_and(|since| now.wrapping_sub(since) >= timeout_ms);
+        if has_timed_out {
+            self.partial_line_since = None;
+        }
+        has_timed_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LineFramer;
+    use super::LineTerminator;
+
+    use arrayvec::ArrayVec;
+
+    fn frame(text: &[u8], terminator: LineTerminator) -> Vec<u8> {
+        let mut framer = LineFramer::new();
+        text.iter()
+            .filter_map(|byte| framer.receive_byte(*byte, terminator, 0))
+            .collect()
+    }
+
+    #[test]
+    fn translates_terminators_into_line_feeds() {
+        assert_eq!(frame(b"F:1\r\n", LineTerminator::LineFeed), b"F:1\r\n");
+        assert_eq!(
+            frame(b"F:1\rF:2\n", LineTerminator::CarriageReturn),
+            b"F:1\nF:2"
+        );
+        assert_eq!(
+            frame(
+                b"F:1\r\nF:\n2\rX\r\n",
+                LineTerminator::CarriageReturnLineFeed
+            ),
+            b"F:1\nF:2X\n"
+        );
+    }
+
+    #[test]
+    fn drops_lines_that_overrun_the_buffer() {
+        let mut framer = LineFramer::new();
+        let mut line_buffer = ArrayVec::<u8, 8>::new();
+        let overruns = b"F:1\nF:STRESS:5\nF:2\n"
+            .iter()
+            .filter(|byte| framer.append(&mut line_buffer, **byte))
+            .count();
+        assert_eq!(overruns, 1);
+        assert_eq!(line_buffer.as_slice(), b"F:1\nF:2\n");
+    }
+
+    #[test]
+    fn times_out_partial_lines() {
+        let mut framer = LineFramer::new();
+        assert!(!framer.has_timed_out(1000, 5000));
+        framer.receive_byte(b'F', LineTerminator::LineFeed, 100);
+        framer.receive_byte(b':', LineTerminator::LineFeed, 900);
+        assert!(!framer.has_timed_out(0, 5000));
+        assert!(!framer.has_timed_out(1000, 1099));
+        assert!(framer.has_timed_out(1000, 1100));
+        assert!(!framer.has_timed_out(1000, 5000));
+        // a complete line doesn’t time out.
+        framer.receive_byte(b'F', LineTerminator::LineFeed, 6000);
+        framer.receive_byte(b'\n', LineTerminator::LineFeed, 6001);
+        assert!(!framer.has_timed_out(1000, 9000));
+    }
+
+    #[test]
+    fn dropped_lines_dont_time_out() {
+        let mut framer = LineFramer::new();
+        let mut line_buffer = ArrayVec::<u8, 4>::new();
+        let mut overruns = 0;
+        for (text, now) in [(&b"F:STRESS"[..], 100), (b":5", 200)] {
+            for byte in text {
+                if let Some(byte) = framer.receive_byte(*byte, LineTerminator::LineFeed, now) {
+                    overruns += usize::from(framer.append(&mut line_buffer, byte));
+                }
+            }
+        }
+        assert_eq!(overruns, 1);
+        assert!(!framer.has_timed_out(1000, 5000));
+        // the next line times out as usual.
+        for byte in b"\nF:" {
+            if let Some(byte) = framer.receive_byte(*byte, LineTerminator::LineFeed, 6000) {
+                framer.append(&mut line_buffer, byte);
+            }
+        }
+        assert!(framer.has_timed_out(1000, 7000));
+    }
+}
@@ -0,0 +1,77 @@
+//! Module for daisy-chaining controllers without an RS-485 bus, where every controller passes the lines for the others on to the next controller of the chain, and sends their replies back towards the control box.
+//!
+//! The lines for the next controller are the ones that this controller ignores, and the broadcasts, which reach every controller. The replies are collected into whole lines before they are sent on, so that they don’t mix with the lines that this controller sends itself.
+
+use arrayvec::ArrayVec;
+
+use crate::commands::Command;
+use crate::commands::CommandError;
+
+/// Returns whether a line, which was parsed into the given result, is passed on to the next controller.
+pub fn is_forwarded(result: &Result<Command, CommandError>) -> bool {
+    matches!(
+        result,
+        Err(CommandError::Ignored)
+            | Ok(Command::Discovery | Command::FastClock(..) | Command::EmergencyStop)
+    )
+}
+
+/// Collects the bytes that the next controller sends back into lines of up to `CAPACITY` bytes. Longer lines are sent back in pieces.
+#[derive(Default)]
+pub struct ReturnLines<const CAPACITY: usize> {
+    line: ArrayVec<u8, CAPACITY>,
+    // Whether the line was returned, and is cleared by the next byte.
+    is_returned: bool,
+}
+
+impl<const CAPACITY: usize> ReturnLines<CAPACITY> {
+    pub const fn new() -> Self {
+        Self {
+            line: ArrayVec::new_const(),
+            is_returned: false,
+        }
+    }
+
+    /// Appends a byte from the next controller, and returns the line to send back if the byte ended it or filled the buffer.
+    pub fn receive_byte(&mut self, byte: u8) -> Option<&[u8]> {
+        if core::mem::take(&mut self.is_returned) {
+            self.line.clear();
+        }
+        // a full buffer always leaves room for this byte, since it was returned with the previous one.
+        self.line.push(byte);
+        self.is_returned = byte == b'\n' || self.line.is_full();
+        self.is_returned.then_some(self.line.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_forwarded;
+    use super::ReturnLines;
+    use crate::commands::get_next_command;
+
+    #[test]
+    fn forwards_lines_for_other_controllers_and_broadcasts() {
+        let is_line_forwarded =
+            |line: &str| is_forwarded(&get_next_command(line.as_bytes(), "F", "", false, false));
+        assert!(is_line_forwarded("G:1\n"));
+        assert!(is_line_forwarded("# comment\n"));
+        assert!(is_line_forwarded("*:PING\n"));
+        assert!(is_line_forwarded("*:FCLK:4:22:05\n"));
+        assert!(is_line_forwarded("!:STOP\n"));
+        assert!(!is_line_forwarded("F:1\n"));
+        assert!(!is_line_forwarded("F:XYZ\n"));
+    }
+
+    #[test]
+    fn returns_whole_lines() {
+        let mut lines = ReturnLines::<4>::new();
+        let mut returned = Vec::new();
+        for byte in b"G:A\nG:A:1\n" {
+            if let Some(line) = lines.receive_byte(*byte) {
+                returned.push(line.to_vec());
+            }
+        }
+        assert_eq!(returned, [&b"G:A\n"[..], b"G:A:", b"1\n"]);
+    }
+}
@@ -0,0 +1,124 @@
+//! Module for identifying the signal head that is plugged into the controller, by an ID resistor in the head’s plug.
+//!
+//! Signal heads are swapped on a layout for repairs, and a head with fewer lamps than the controller was built for would show wrong aspects, like Hp1 instead of Hp2 on a head without the yellow lamp. Each head therefore connects the ID pin to ground through a resistor whose value identifies the head type, while the controller pulls the pin up to 5 V through [`ID_PULL_UP_OHMS`]. The voltage on the pin is read once at boot with the 1.1 V internal reference, which the low resistor values keep below.
+
+use crate::signals::LampRole;
+use crate::signals::SignalAspect;
+
+/// Resistance of the pull-up resistor of the ID pin on the controller board.
+pub const ID_PULL_UP_OHMS: u32 = 100_000;
+/// The lamps of a head that are identified by its type. Other lamps, like notice lamps or Zs1 lamps, are not checked.
+const HEAD_LAMPS: [LampRole; 3] = [LampRole::MainRed, LampRole::MainGreen, LampRole::MainYellow];
+/// Upper limits of the ADC readings of each head type in [`HeadType::ALL`], halfway between the nominal readings, so that resistor tolerances and the spread of the internal reference (±10%) don’t matter. Readings above the last limit mean that the head has no ID resistor, or that no head is plugged in.
+const READING_LIMITS: [u16; 4] = [128, 316, 631, 980];
+
+/// Type of a signal head, as identified by its ID resistor.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HeadType {
+    /// H/V main signal with a red and a green lamp, identified by 1 kΩ.
+    TwoLamp,
+    /// H/V main signal with a red, a green and a yellow lamp, identified by 4.7 kΩ.
+    ThreeLamp,
+    /// Ks signal with a red, a green and a yellow lamp, identified by 10 kΩ.
+    Ks,
+    /// H/V main signal with a red, a green and a yellow lamp and a Zs3 speed indicator, identified by 22 kΩ.
+    ThreeLampWithSpeedIndicator,
+}
+
+impl HeadType {
+    /// All head types, ordered by their ID resistor.
+    pub const ALL: [Self; 4] = [
+        Self::TwoLamp,
+        Self::ThreeLamp,
+        Self::Ks,
+        Self::ThreeLampWithSpeedIndicator,
+    ];
+
+    /// Returns the type of the head with the given 10-bit ADC reading of the ID pin, or None if the head has no ID resistor.
+    pub fn from_reading(reading: u16) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .zip(READING_LIMITS)
+            .find(|(_, limit)| reading < *limit)
+            .map(|(head, _)| head)
+    }
+
+    /// Returns the ID of this head type, as used in the serial protocol.
+    pub fn command_id(self) -> &'static str {
+        match self {
+            Self::TwoLamp => "HV2",
+            Self::ThreeLamp => "HV3",
+            Self::Ks => "KS",
+            Self::ThreeLampWithSpeedIndicator => "HV3Z",
+        }
+    }
+
+    /// Returns the lamps of [`HEAD_LAMPS`] that a head of this type has.
+    fn lamps(self) -> &'static [LampRole] {
+        match self {
+            Self::TwoLamp => &HEAD_LAMPS[..2],
+            Self::ThreeLamp | Self::Ks | Self::ThreeLampWithSpeedIndicator => &HEAD_LAMPS,
+        }
+    }
+
+    /// Returns whether a head of this type, plugged into a controller that was built for the given head type, can show the given aspect with the given speed.
+    ///
+    /// The head must have every lamp that the aspect lights, and a speed indicator for a speed. A head of another signalling system gives the lamps another meaning, so it can only show aspects that light no lamp but the red one.
+    pub fn can_show(
+        self,
+        board_head: HeadType,
+        aspect: impl SignalAspect,
+        speed: Option<u8>,
+    ) -> bool {
+        let lamps = if (self == Self::Ks) == (board_head == Self::Ks) {
+            self.lamps()
+        } else {
+            &HEAD_LAMPS[..1]
+        };
+        HEAD_LAMPS
+            .iter()
+            .all(|role| !aspect.lights_lamp(*role) || lamps.contains(role))
+            && (speed.is_none() || self == Self::ThreeLampWithSpeedIndicator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeadType;
+    use super::ID_PULL_UP_OHMS;
+    use crate::signals::HVMainSignalAspect;
+
+    #[test]
+    fn identifies_heads_by_their_resistor() {
+        // nominal readings of the ID resistors with a 5 V supply and the 1.1 V reference.
+        let reading = |ohms: u32| (5 * 1024 * ohms / (ID_PULL_UP_OHMS + ohms) * 10 / 11) as u16;
+        for (ohms, head) in [1_000, 4_700, 10_000, 22_000]
+            .into_iter()
+            .zip(HeadType::ALL)
+        {
+            assert_eq!(HeadType::from_reading(reading(ohms)), Some(head));
+        }
+        // a missing resistor saturates the ADC.
+        assert_eq!(HeadType::from_reading(1023), None);
+    }
+
+    #[test]
+    fn heads_only_show_aspects_they_have_lamps_for() {
+        use HVMainSignalAspect::*;
+        let board_head = HeadType::ThreeLampWithSpeedIndicator;
+        for (head, aspect, speed, can_show) in [
+            (board_head, ProceedSlow, Some(6), true),
+            (HeadType::ThreeLamp, ProceedSlow, None, true),
+            (HeadType::ThreeLamp, ProceedSlow, Some(6), false),
+            (HeadType::TwoLamp, Proceed, None, true),
+            (HeadType::TwoLamp, ProceedSlow, None, false),
+            // aspects that don’t light the head’s lamps are left to the rest of the signal.
+            (HeadType::TwoLamp, Deactivated, None, true),
+            // a Ks head would show Ks1 for Hp1.
+            (HeadType::Ks, Stop, None, true),
+            (HeadType::Ks, Proceed, None, false),
+        ] {
+            assert_eq!(head.can_show(board_head, aspect, speed), can_show);
+        }
+    }
+}
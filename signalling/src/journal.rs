@@ -0,0 +1,95 @@
+//! Module for the journal of accepted state-changing commands, which lets the control box notice and reconcile commands that it missed, e.g. panel button presses while it was disconnected.
+//!
+//! Every accepted state-changing command gets the next sequence number, which is sent with its acknowledgement. If the control box sees the sequence number skip, it missed commands, and can fetch the most recent entries to find out what happened.
+
+use crate::arbitration::CommandSource;
+use crate::signals::SignalAspect;
+
+/// Offset in the EEPROM where the last sequence number is saved, so that it keeps counting after a reboot.
+pub const JOURNAL_EEPROM_OFFSET: u16 = 12;
+
+/// Number of recent commands that the journal keeps.
+pub const JOURNAL_LENGTH: usize = 8;
+
+/// An accepted state-changing command.
+#[derive(Clone, Copy)]
+pub struct JournalEntry<Aspect: SignalAspect> {
+    pub sequence: u16,
+    pub source: CommandSource,
+    /// The aspect after the command.
+    pub aspect: Aspect,
+}
+
+/// The most recent accepted state-changing commands. Only the sequence number is saved; the entries are lost on a reboot.
+pub struct Journal<Aspect: SignalAspect> {
+    sequence: u16,
+    // Entries indexed by their sequence number modulo the journal length.
+    entries: [Option<JournalEntry<Aspect>>; JOURNAL_LENGTH],
+}
+
+impl<Aspect: SignalAspect> Journal<Aspect> {
+    /// Creates an empty journal that continues after the given sequence number.
+    pub fn new(sequence: u16) -> Self {
+        Self {
+            sequence,
+            entries: [None; JOURNAL_LENGTH],
+        }
+    }
+
+    /// Returns the sequence number of the last command.
+    pub fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// Records an accepted command, and returns its sequence number. The sequence number wraps around after 65535.
+    pub fn record(&mut self, source: CommandSource, aspect: Aspect) -> u16 {
+        self.sequence = self.sequence.wrapping_add(1);
+        self.entries[usize::from(self.sequence) % JOURNAL_LENGTH] = Some(JournalEntry {
+            sequence: self.sequence,
+            source,
+            aspect,
+        });
+        self.sequence
+    }
+
+    /// Returns the entries of the journal, oldest first.
+    pub fn entries(&self) -> impl Iterator<Item = JournalEntry<Aspect>> + '_ {
+        (0..JOURNAL_LENGTH as u16).rev().filter_map(|age| {
+            let sequence = self.sequence.wrapping_sub(age);
+            self.entries[usize::from(sequence) % JOURNAL_LENGTH]
+                .filter(|entry| entry.sequence == sequence)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Journal;
+    use super::JOURNAL_LENGTH;
+    use crate::arbitration::CommandSource;
+    use crate::signals::HVMainSignalAspect;
+
+    #[test]
+    fn keeps_the_most_recent_entries_across_wraparound() {
+        let mut journal = Journal::new(u16::MAX - 2);
+        assert_eq!(journal.entries().count(), 0);
+        assert_eq!(
+            journal.record(CommandSource::Panel, HVMainSignalAspect::Proceed),
+            u16::MAX - 1
+        );
+        assert_eq!(journal.entries().count(), 1);
+
+        for _ in 0..10 {
+            journal.record(CommandSource::Serial, HVMainSignalAspect::Stop);
+        }
+        assert_eq!(journal.sequence(), 8);
+        let sequences: Vec<u16> = journal.entries().map(|entry| entry.sequence).collect();
+        assert_eq!(sequences.len(), JOURNAL_LENGTH);
+        assert_eq!(sequences[0], 1);
+        assert_eq!(sequences[JOURNAL_LENGTH - 1], 8);
+        assert!(journal
+            .entries()
+            .all(|entry| entry.source == CommandSource::Serial
+                && entry.aspect == HVMainSignalAspect::Stop));
+    }
+}
@@ -0,0 +1,84 @@
+//! Module for scanning the buttons of a control desk panel, which are wired as a key matrix.
+
+use embedded_hal::digital::InputPin;
+use embedded_hal::digital::OutputPin;
+
+/// A key must be read in the same state for this long until a change is accepted.
+const DEBOUNCE_MS: u32 = 20;
+
+/// A matrix of buttons, where each button connects one row with one column.
+///
+/// Rows are driven low one at a time, and the columns are read with pull-up resistors, so a pressed key reads low. Keys are numbered row by row, starting at 0.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used for the rows and the kind of input pin used for the columns, as well as the number of rows and columns. Its parameters additionally include the pins’ common error type (which some functions also return).
+pub struct KeyMatrix<
+    Error,
+    RowPin: OutputPin<Error = Error>,
+    ColumnPin: InputPin<Error = Error>,
+    const ROWS: usize,
+    const COLUMNS: usize,
+> {
+    rows: [RowPin; ROWS],
+    columns: [ColumnPin; COLUMNS],
+    // Key states as bit sets indexed by key number; a set bit means pressed.
+    // Last scanned state, possibly still bouncing.
+    last_scan: u32,
+    // Time at which the last scanned state last changed.
+    last_scan_change: u32,
+    // Debounced state.
+    debounced: u32,
+}
+
+impl<
+        Error,
+        RowPin: OutputPin<Error = Error>,
+        ColumnPin: InputPin<Error = Error>,
+        const ROWS: usize,
+        const COLUMNS: usize,
+    > KeyMatrix<Error, RowPin, ColumnPin, ROWS, COLUMNS>
+{
+    /// Creates a new key matrix. At most 32 keys are supported.
+    pub fn new(rows: [RowPin; ROWS], columns: [ColumnPin; COLUMNS]) -> Self {
+        assert!(ROWS * COLUMNS <= 32, "too many keys in key matrix");
+        Self {
+            rows,
+            columns,
+            last_scan: 0,
+            last_scan_change: 0,
+            debounced: 0,
+        }
+    }
+
+    /// Scans all keys and returns the number of a key that was newly pressed, if any. Time is given in milliseconds since boot.
+    ///
+    /// If several keys are pressed at the same time, only the lowest-numbered key is reported.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    pub fn scan(&mut self, now: u32) -> Result<Option<usize>, Error> {
+        let mut scan = 0;
+        for (row_index, row) in self.rows.iter_mut().enumerate() {
+            row.set_low()?;
+            for (column_index, column) in self.columns.iter_mut().enumerate() {
+                if column.is_low()? {
+                    scan |= 1 << (row_index * COLUMNS + column_index);
+                }
+            }
+            row.set_high()?;
+        }
+
+        if scan != self.last_scan {
+            self.last_scan = scan;
+            self.last_scan_change = now;
+            return Ok(None);
+        }
+        if now.wrapping_sub(self.last_scan_change) < DEBOUNCE_MS || scan == self.debounced {
+            return Ok(None);
+        }
+        let newly_pressed = scan & !self.debounced;
+        self.debounced = scan;
+        Ok((newly_pressed != 0).then(|| newly_pressed.trailing_zeros() as usize))
+    }
+}
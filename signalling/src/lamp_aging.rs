@@ -0,0 +1,269 @@
+//! Module for simulating aging lamps, which occasionally flicker or fail, for museum displays.
+//!
+//! The simulation is purely visual: it only switches lamp pins on top of the current aspect and never changes the aspect itself. Lamps age according to how long they have been lit, so lamps of frequently shown aspects misbehave more often. Red lamps and the main signal’s yellow lamp are never affected, since a dark red lamp or a dark yellow lamp next to a lit green lamp would show a less restrictive aspect.
+
+use crate::random::Rng;
+use crate::signals::LampRole;
+
+/// How often the simulation decides whether a lamp starts misbehaving.
+const EFFECT_CHECK_INTERVAL_MS: u32 = 60_000;
+/// A lamp starts misbehaving at one in this many checks.
+const EFFECT_CHANCE: u32 = 16;
+/// How long a lamp flickers.
+const FLICKER_DURATION_MS: u32 = 4_000;
+/// Shortest and longest duration of a single flicker phase.
+const FLICKER_PHASE_MIN_MS: u32 = 30;
+const FLICKER_PHASE_MAX_MS: u32 = 200;
+/// How long a lamp stays dark after failing.
+const FAILURE_DURATION_MS: u32 = 20_000;
+
+/// A kind of simulated lamp defect.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AgingEffect {
+    /// The lamp flickers for a few seconds.
+    Flicker,
+    /// The lamp goes dark for a while.
+    Failure,
+}
+
+impl AgingEffect {
+    pub fn command_id(self) -> &'static str {
+        match self {
+            Self::Flicker => "FLICKER",
+            Self::Failure => "FAIL",
+        }
+    }
+}
+
+/// A change in the simulation that should be announced to the operators.
+pub enum AgingEvent {
+    /// The lamp started misbehaving. It is switched according to [`LampAging::lamp_state`] from now on.
+    Started(LampRole, AgingEffect),
+    /// The lamp is back to normal, and has to be switched on again by the user.
+    Ended(LampRole),
+}
+
+/// A simulated defect that is currently shown.
+struct ActiveEffect {
+    role: LampRole,
+    effect: AgingEffect,
+    started_at: u32,
+    // Time of the last flicker phase change, and the duration of the current phase.
+    last_toggle: u32,
+    phase_ms: u32,
+    is_on: bool,
+}
+
+/// State of the lamp aging simulation.
+///
+/// The simulation doesn’t own any lamps; it only tells its user which lamp to switch and when. Time is given in milliseconds since boot, as returned by the clock.
+///
+/// # Type parameters
+///
+/// This type is generic over the random number generator, which decides when and how lamps misbehave.
+pub struct LampAging<R: Rng> {
+    // Seconds that every lamp has been lit, indexed by lamp role.
+    lit_seconds: [u32; LampRole::ALL.len()],
+    last_second: u32,
+    last_effect_check: u32,
+    rng: R,
+    active: Option<ActiveEffect>,
+}
+
+impl<R: Rng> LampAging<R> {
+    /// Creates a new simulation. The random number generator should be seeded differently on every boot.
+    pub fn new(rng: R, now: u32) -> Self {
+        Self {
+            lit_seconds: [0; LampRole::ALL.len()],
+            last_second: now,
+            last_effect_check: now,
+            rng,
+            active: None,
+        }
+    }
+
+    /// Advances the simulation to the current time, given which lamps the signal has lit, returning an event if a lamp starts or stops misbehaving.
+    ///
+    /// The lit lamps are the ones the signal actually shows, which can differ from the lamps of the main signal aspect, e.g. for an announcement signal that is dark or announces the next main signal.
+    pub fn update(
+        &mut self,
+        now: u32,
+        mut is_lit: impl FnMut(LampRole) -> bool,
+    ) -> Option<AgingEvent> {
+        if now.wrapping_sub(self.last_second) >= 1000 {
+            self.last_second = now;
+            for role in LampRole::ALL {
+                if Self::can_age(role, is_lit(role)) {
+                    let seconds = &mut self.lit_seconds[role as usize];
+                    *seconds = seconds.saturating_add(1);
+                }
+            }
+        }
+
+        if let Some(active) = &self.active {
+            let duration = match active.effect {
+                AgingEffect::Flicker => FLICKER_DURATION_MS,
+                AgingEffect::Failure => FAILURE_DURATION_MS,
+            };
+            if now.wrapping_sub(active.started_at) < duration {
+                return None;
+            }
+            let role = active.role;
+            self.active = None;
+            return Some(AgingEvent::Ended(role));
+        }
+
+        if now.wrapping_sub(self.last_effect_check) < EFFECT_CHECK_INTERVAL_MS {
+            return None;
+        }
+        self.last_effect_check = now;
+        if self.rng.below(EFFECT_CHANCE) != 0 {
+            return None;
+        }
+
+        // pick a lit lamp, weighted by how long it has been lit
+        let mut can_age = [false; LampRole::ALL.len()];
+        for role in LampRole::ALL {
+            can_age[role as usize] = Self::can_age(role, is_lit(role));
+        }
+        let total: u32 = LampRole::ALL
+            .into_iter()
+            .filter(|role| can_age[*role as usize])
+            .map(|role| self.lit_seconds[role as usize])
+            .fold(0, u32::saturating_add);
+        if total == 0 {
+            return None;
+        }
+        let mut pick = self.rng.below(total);
+        let role = LampRole::ALL
+            .into_iter()
+            .filter(|role| can_age[*role as usize])
+            .find(|role| {
+                let seconds = self.lit_seconds[*role as usize];
+                if pick < seconds {
+                    return true;
+                }
+                pick -= seconds;
+                false
+            })?;
+        let effect = if self.rng.below(4) == 0 {
+            AgingEffect::Failure
+        } else {
+            AgingEffect::Flicker
+        };
+        let phase_ms = self.random_flicker_phase();
+        self.active = Some(ActiveEffect {
+            role,
+            effect,
+            started_at: now,
+            last_toggle: now,
+            phase_ms,
+            is_on: false,
+        });
+        Some(AgingEvent::Started(role, effect))
+    }
+
+    /// Returns the lamp affected by the current defect and the state it has to be in now (true meaning on), if there is a defect.
+    pub fn lamp_state(&mut self, now: u32) -> Option<(LampRole, bool)> {
+        let random_phase = self.random_flicker_phase();
+        let active = self.active.as_mut()?;
+        if active.effect == AgingEffect::Flicker
+            && now.wrapping_sub(active.last_toggle) >= active.phase_ms
+        {
+            active.last_toggle = now;
+            active.phase_ms = random_phase;
+            active.is_on = !active.is_on;
+        }
+        Some((active.role, active.is_on))
+    }
+
+    /// Ends the current defect immediately, returning the affected lamp. The user has to restore the lamp, e.g. by switching to an aspect.
+    pub fn cancel(&mut self) -> Option<LampRole> {
+        self.active.take().map(|active| active.role)
+    }
+
+    /// Returns whether the lamp, which is lit or not, may be affected by the simulation.
+    fn can_age(role: LampRole, is_lit: bool) -> bool {
+        match role {
+            LampRole::MainRed
+            | LampRole::MainYellow
+            | LampRole::MainNotice
+            | LampRole::AnnouncementNotice
+            | LampRole::RepeaterNotice => false,
+            // the Zs1 lamp already blinks, so a defect would be indistinguishable.
+            LampRole::MainSubstitution => false,
+            _ => is_lit,
+        }
+    }
+
+    fn random_flicker_phase(&mut self) -> u32 {
+        FLICKER_PHASE_MIN_MS + self.rng.below(FLICKER_PHASE_MAX_MS - FLICKER_PHASE_MIN_MS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AgingEffect;
+    use super::AgingEvent;
+    use super::LampAging;
+    use crate::mock::MockRng;
+    use crate::signals::HVMainSignalAspect;
+    use crate::signals::LampRole;
+    use crate::signals::SignalAspect;
+
+    #[test]
+    fn lit_lamp_fails_and_recovers() {
+        // zero always picks the first candidate and starts an effect.
+        let mut aging = LampAging::new(MockRng::new(vec![0]), 0);
+        let events: Vec<_> = (1..=60)
+            .filter_map(|second| {
+                aging.update(second * 1000, |role| {
+                    HVMainSignalAspect::Proceed.lights_lamp(role)
+                })
+            })
+            .collect();
+        assert!(matches!(
+            events[..],
+            [AgingEvent::Started(
+                LampRole::MainGreen,
+                AgingEffect::Failure
+            )]
+        ));
+        assert!(matches!(
+            aging.lamp_state(61_000),
+            Some((LampRole::MainGreen, false))
+        ));
+        assert!(matches!(
+            aging.update(80_000, |role| HVMainSignalAspect::Proceed.lights_lamp(role)),
+            Some(AgingEvent::Ended(LampRole::MainGreen))
+        ));
+        assert!(aging.lamp_state(80_001).is_none());
+    }
+
+    #[test]
+    fn red_lamps_never_age() {
+        let mut aging = LampAging::new(MockRng::new(vec![0]), 0);
+        assert!((1..=600)
+            .filter_map(|second| aging.update(second * 1000, |role| {
+                HVMainSignalAspect::Stop.lights_lamp(role)
+            }))
+            .all(|event| !matches!(event, AgingEvent::Started(LampRole::MainRed, _))));
+    }
+
+    #[test]
+    fn only_lamps_that_the_signal_lights_age() {
+        // the lamp is lit by an announcement signal that announces the next main signal, whatever the main signal aspect.
+        let is_lit = |role| matches!(role, LampRole::AnnouncementYellowUpper);
+        let mut aging = LampAging::new(MockRng::new(vec![0]), 0);
+        let events: Vec<_> = (1..=60)
+            .filter_map(|second| aging.update(second * 1000, is_lit))
+            .collect();
+        assert!(matches!(
+            events[..],
+            [AgingEvent::Started(
+                LampRole::AnnouncementYellowUpper,
+                AgingEffect::Failure
+            )]
+        ));
+    }
+}
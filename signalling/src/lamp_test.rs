@@ -0,0 +1,230 @@
+//! Module for the automatic lamp test, which switches through every aspect on a schedule and checks the lamps, so that failed lamps are noticed before visitors do.
+//!
+//! The test runs at boot, at a time of the layout’s fast clock, or daily in real time, and only while the signal shows stop, so that it never interrupts a train movement. Its results are kept in the EEPROM, so that they can be queried long after the test ran.
+
+use crate::fast_clock;
+use crate::fast_clock::MINUTES_PER_DAY;
+use crate::maintenance::LampSet;
+use crate::platform::Platform;
+
+/// EEPROM address of the lamp test log (6 bytes).
+pub const LAMP_TEST_EEPROM_OFFSET: u16 = 2;
+/// Real time between two lamp tests on the real-time schedule.
+const REAL_TIME_INTERVAL_MS: u32 = 24 * 60 * 60 * 1000;
+
+/// When the automatic lamp test runs.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LampTestSchedule {
+    /// Only the maintenance commands test the lamps.
+    Never = 0,
+    /// Once after every boot, which is usually the start of an operating session.
+    AtBoot = 1,
+    /// Daily, when the fast clock reaches the configured time of day.
+    FastClock = 2,
+    /// The configured number of minutes after boot, and every 24 hours after that.
+    RealTime = 3,
+}
+
+impl LampTestSchedule {
+    /// Returns the schedule with the given value, as used by the configuration options.
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Never),
+            1 => Some(Self::AtBoot),
+            2 => Some(Self::FastClock),
+            3 => Some(Self::RealTime),
+            _ => None,
+        }
+    }
+}
+
+/// Decides when the lamp test is due.
+///
+/// A due test stays due until it ran, since it has to wait until the signal shows stop. Time is given in milliseconds since boot, as returned by the clock.
+#[derive(Default)]
+pub struct LampTestScheduler {
+    is_due: bool,
+    // Whether the boot was seen, so that the test at boot only becomes due once.
+    has_booted: bool,
+    // Fast-clock time at the last update, if the fast clock was running.
+    last_fast_clock_minutes: Option<u16>,
+    // Configured minutes and the time of the next test on the real-time schedule, once it was scheduled.
+    next_real_time_test: Option<(u16, u32)>,
+}
+
+impl LampTestScheduler {
+    pub const fn new() -> Self {
+        Self {
+            is_due: false,
+            has_booted: false,
+            last_fast_clock_minutes: None,
+            next_real_time_test: None,
+        }
+    }
+
+    /// Advances the schedule with the given configured minutes to the current time, and the current fast-clock time if the layout broadcasts one. Should be called in every loop iteration.
+    pub fn update(
+        &mut self,
+        schedule: LampTestSchedule,
+        minutes: u16,
+        now: u32,
+        fast_clock_minutes: Option<u16>,
+    ) {
+        let is_first_update = !self.has_booted;
+        self.has_booted = true;
+        let last_fast_clock_minutes = self.last_fast_clock_minutes;
+        self.last_fast_clock_minutes = fast_clock_minutes;
+        match schedule {
+            LampTestSchedule::Never => {}
+            LampTestSchedule::AtBoot => self.is_due |= is_first_update,
+            // the fast clock may skip minutes, so the time only has to be passed since the last update.
+            LampTestSchedule::FastClock => {
+                if let (Some(last), Some(current)) = (last_fast_clock_minutes, fast_clock_minutes) {
+                    self.is_due |= fast_clock::is_within(
+                        minutes,
+                        (last + 1) % MINUTES_PER_DAY,
+                        (current + 1) % MINUTES_PER_DAY,
+                    );
+                }
+            }
+            LampTestSchedule::RealTime => {
+                let mut next_test_at = match self.next_real_time_test {
+                    Some((scheduled_minutes, next_test_at)) if scheduled_minutes == minutes => {
+                        next_test_at
+                    }
+                    _ => u32::from(minutes) * 60_000,
+                };
+                // times up to half the clock’s range ago are in the past.
+                while now.wrapping_sub(next_test_at) < u32::MAX / 2 {
+                    self.is_due = true;
+                    next_test_at = next_test_at.wrapping_add(REAL_TIME_INTERVAL_MS);
+                }
+                self.next_real_time_test = Some((minutes, next_test_at));
+            }
+        }
+        if schedule != LampTestSchedule::RealTime {
+            self.next_real_time_test = None;
+        }
+    }
+
+    /// Returns whether a lamp test is due.
+    pub fn is_due(&self) -> bool {
+        self.is_due
+    }
+
+    /// Records that the due lamp test ran.
+    pub fn finish(&mut self) {
+        self.is_due = false;
+    }
+}
+
+/// Summary of the automatic lamp tests, which is kept in the EEPROM.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct LampTestLog {
+    /// Number of lamp tests that ran, which wraps around after 65535.
+    pub tests: u16,
+    /// The lamps that failed the last lamp test.
+    pub failed_lamps: LampSet,
+}
+
+impl LampTestLog {
+    /// Reads the log from the persistent storage, or returns an empty log if none was saved.
+    pub fn load(platform: &mut impl Platform) -> Self {
+        let mut bytes = [0; 6];
+        platform.read_persistent(LAMP_TEST_EEPROM_OFFSET, &mut bytes);
+        let [tests @ .., _, _, _, _] = bytes;
+        let [_, _, failed_lamps @ ..] = bytes;
+        // an erased EEPROM has bits set that belong to no lamp.
+        LampSet::from_bits(u32::from_le_bytes(failed_lamps)).map_or_else(
+            Self::default,
+            |failed_lamps| Self {
+                tests: u16::from_le_bytes(tests),
+                failed_lamps,
+            },
+        )
+    }
+
+    /// Records the lamps that failed a lamp test, and writes the log to the persistent storage.
+    pub fn record(&mut self, failed_lamps: LampSet, platform: &mut impl Platform) {
+        self.tests = self.tests.wrapping_add(1);
+        self.failed_lamps = failed_lamps;
+        let mut bytes = [0; 6];
+        bytes[..2].copy_from_slice(&self.tests.to_le_bytes());
+        bytes[2..].copy_from_slice(&failed_lamps.bits().to_le_bytes());
+        platform.write_persistent(LAMP_TEST_EEPROM_OFFSET, &bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LampTestLog;
+    use super::LampTestSchedule;
+    use super::LampTestScheduler;
+    use super::REAL_TIME_INTERVAL_MS;
+    use crate::maintenance::LampSet;
+    use crate::mock::MockPlatform;
+    use crate::platform::Platform;
+    use crate::signals::LampRole;
+
+    fn is_due_after(
+        scheduler: &mut LampTestScheduler,
+        schedule: LampTestSchedule,
+        now: u32,
+        fast_clock_minutes: Option<u16>,
+    ) -> bool {
+        scheduler.update(schedule, 10, now, fast_clock_minutes);
+        let is_due = scheduler.is_due();
+        scheduler.finish();
+        is_due
+    }
+
+    #[test]
+    fn tests_are_due_on_their_schedule() {
+        let mut scheduler = LampTestScheduler::new();
+        assert!(is_due_after(
+            &mut scheduler,
+            LampTestSchedule::AtBoot,
+            0,
+            None
+        ));
+        assert!(!is_due_after(
+            &mut scheduler,
+            LampTestSchedule::AtBoot,
+            1,
+            None
+        ));
+
+        // the fast clock skips from 0:09 to 0:11.
+        let schedule = LampTestSchedule::FastClock;
+        assert!(!is_due_after(&mut scheduler, schedule, 2, Some(8)));
+        assert!(!is_due_after(&mut scheduler, schedule, 3, Some(9)));
+        assert!(is_due_after(&mut scheduler, schedule, 4, Some(11)));
+        assert!(!is_due_after(&mut scheduler, schedule, 5, Some(12)));
+        assert!(!is_due_after(&mut scheduler, schedule, 6, None));
+
+        let schedule = LampTestSchedule::RealTime;
+        assert!(!is_due_after(&mut scheduler, schedule, 599_999, None));
+        assert!(is_due_after(&mut scheduler, schedule, 600_000, None));
+        assert!(!is_due_after(&mut scheduler, schedule, 700_000, None));
+        let next_day = 600_000 + REAL_TIME_INTERVAL_MS;
+        assert!(!is_due_after(&mut scheduler, schedule, next_day - 1, None));
+        assert!(is_due_after(&mut scheduler, schedule, next_day, None));
+    }
+
+    #[test]
+    fn the_log_survives_a_reboot() {
+        let mut platform = MockPlatform::new();
+        // an erased EEPROM is an empty log.
+        platform.write_persistent(super::LAMP_TEST_EEPROM_OFFSET, &[0xff; 6]);
+        let mut log = LampTestLog::load(&mut platform);
+        assert!(log == LampTestLog::default());
+        let mut failed_lamps = LampSet::default();
+        failed_lamps.insert(LampRole::MainRed);
+        log.record(failed_lamps, &mut platform);
+        log.record(failed_lamps, &mut platform);
+        let log = LampTestLog::load(&mut platform);
+        assert_eq!(log.tests, 2);
+        assert!(log.failed_lamps == failed_lamps);
+    }
+}
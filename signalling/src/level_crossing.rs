@@ -0,0 +1,185 @@
+//! Module for level crossings (Bahnübergänge), which warn road traffic with flashing lights and optionally close barriers.
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal::digital::PinState;
+use embedded_hal::pwm::SetDutyCycle;
+
+use crate::blink::Blinker;
+use crate::semaphore::Servo;
+
+/// Duration of each flash of the road lights, in milliseconds, so that each light flashes once per second.
+const FLASH_HALF_PERIOD_MS: u32 = 500;
+
+/// A command for a level crossing.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LevelCrossingCommand {
+    /// Start the closing sequence.
+    Close,
+    /// Open the crossing for road traffic.
+    Open,
+}
+
+impl LevelCrossingCommand {
+    pub fn from_command_id(command_id: &[u8]) -> Option<Self> {
+        match command_id {
+            b"close" => Some(Self::Close),
+            b"open" => Some(Self::Open),
+            _ => None,
+        }
+    }
+
+    pub fn command_id(self) -> &'static str {
+        match self {
+            Self::Close => "close",
+            Self::Open => "open",
+        }
+    }
+}
+
+/// A level crossing with two road lights that flash alternately while it is closed, and optional barriers.
+///
+/// When the crossing is closed, the lights start flashing, and the barriers are lowered once the warning time has passed, so that road traffic can clear the crossing. When it is opened, the barriers are raised, and the lights go dark once the barriers are up. Everything happens when [`LevelCrossing::update`] is called.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used for the lights and the kind of PWM output used for the barrier servo. Its parameters additionally include the error type, which some functions also return.
+pub struct LevelCrossing<
+    Error,
+    PinType: OutputPin<Error = Error>,
+    PwmType: SetDutyCycle<Error = Error>,
+> {
+    left_light: PinType,
+    right_light: PinType,
+    // Servo of the barriers, whose stop end stop is lowered and whose proceed end stop is raised. Both barriers can share a servo output.
+    barriers: Option<Servo<PwmType>>,
+    // How long the lights flash before the barriers are lowered.
+    warning_ms: u32,
+    is_closed: bool,
+    // Time the lights started flashing, or None if they are dark or start flashing with the next update.
+    flashing_since: Option<u32>,
+    blinker: Blinker,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>, PwmType: SetDutyCycle<Error = Error>>
+    LevelCrossing<Error, PinType, PwmType>
+{
+    /// Creates an open level crossing without barriers. The lights flash for the given warning time in milliseconds before the barriers are lowered.
+    pub fn new(left_light: PinType, right_light: PinType, warning_ms: u32) -> Self {
+        Self {
+            left_light,
+            right_light,
+            barriers: None,
+            warning_ms,
+            is_closed: false,
+            flashing_since: None,
+            blinker: Blinker::new(FLASH_HALF_PERIOD_MS),
+        }
+    }
+
+    /// Adds the barriers, which are raised with the first update.
+    pub fn with_barriers(mut self, mut barriers: Servo<PwmType>) -> Self {
+        barriers.jump_to(true);
+        self.barriers = Some(barriers);
+        self
+    }
+
+    /// Starts the closing or opening sequence with the next update.
+    pub fn command(&mut self, command: LevelCrossingCommand) {
+        self.is_closed = command == LevelCrossingCommand::Close;
+    }
+
+    /// Returns whether the crossing was last commanded to close.
+    pub fn is_closed(&self) -> bool {
+        self.is_closed
+    }
+
+    /// Advances the closing or opening sequence to the current time.
+    pub fn update(&mut self, now: u32) -> Result<(), Error> {
+        if self.is_closed {
+            let flashing_since = match self.flashing_since {
+                Some(flashing_since) => flashing_since,
+                None => {
+                    self.blinker.restart(now);
+                    *self.flashing_since.insert(now)
+                }
+            };
+            if let Some(barriers) = &mut self.barriers {
+                if now.wrapping_sub(flashing_since) >= self.warning_ms {
+                    barriers.move_to(false);
+                }
+                barriers.update(now)?;
+            }
+        } else if let Some(barriers) = &mut self.barriers {
+            barriers.move_to(true);
+            barriers.update(now)?;
+            // the lights keep flashing while the barriers are raised.
+            if barriers.is_at_target() {
+                self.flashing_since = None;
+            }
+        } else {
+            self.flashing_since = None;
+        }
+
+        let (left, right) = if self.flashing_since.is_some() {
+            self.blinker.update(now);
+            (self.blinker.is_on(), !self.blinker.is_on())
+        } else {
+            (false, false)
+        };
+        self.left_light.set_state(PinState::from(left))?;
+        self.right_light.set_state(PinState::from(right))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use super::LevelCrossing;
+    use super::LevelCrossingCommand;
+    use crate::mock::MockPins;
+    use crate::mock::MockPwm;
+    use crate::mock::MockPwms;
+    use crate::semaphore::EndStops;
+    use crate::semaphore::Servo;
+
+    const BARRIERS: EndStops = EndStops {
+        stop_us: 1000,
+        proceed_us: 2000,
+    };
+
+    #[test]
+    fn barriers_close_after_the_warning_time_and_lights_stop_once_they_are_up() {
+        let pins = MockPins::new();
+        let pwms = MockPwms::new();
+        let mut crossing: LevelCrossing<Infallible, _, MockPwm> =
+            LevelCrossing::new(pins.pin(), pins.pin(), 3000)
+                .with_barriers(Servo::new(pwms.pwm(), BARRIERS).with_move_ms(2000));
+        crossing.update(0).unwrap();
+        assert_eq!(pins.states(), [false, false]);
+        assert_eq!(pwms.duty_cycles(), [2000]);
+
+        crossing.command(LevelCrossingCommand::Close);
+        crossing.update(1000).unwrap();
+        assert_eq!(pins.states(), [true, false]);
+        crossing.update(1500).unwrap();
+        assert_eq!(pins.states(), [false, true]);
+        crossing.update(3900).unwrap();
+        assert_eq!(pwms.duty_cycles(), [2000]);
+        // the barriers start moving once the warning time has passed.
+        crossing.update(4000).unwrap();
+        crossing.update(5000).unwrap();
+        assert_eq!(pwms.duty_cycles(), [1500]);
+        crossing.update(6000).unwrap();
+        assert_eq!(pwms.duty_cycles(), [1000]);
+
+        crossing.command(LevelCrossingCommand::Open);
+        crossing.update(7000).unwrap();
+        crossing.update(8000).unwrap();
+        assert_eq!(pwms.duty_cycles(), [1500]);
+        assert!(pins.states().contains(&true));
+        crossing.update(9000).unwrap();
+        assert_eq!(pwms.duty_cycles(), [2000]);
+        assert_eq!(pins.states(), [false, false]);
+    }
+}
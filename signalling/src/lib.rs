@@ -0,0 +1,63 @@
+//! Hardware-independent logic of the train signal controller: signal aspects and lamps, the serial protocol, and everything else that doesn’t need to touch the microcontroller directly.
+//!
+//! All hardware access goes through the embedded-hal traits, so the library can be tested on the host with the mock pins from the `mock` feature.
+
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+#![feature(byte_slice_trim_ascii)]
+
+pub mod arbitration;
+pub mod arming;
+pub mod auth;
+pub mod aux_outputs;
+pub mod bank;
+pub mod baud;
+pub mod bidib;
+pub mod binary;
+pub mod blink;
+pub mod cmri;
+pub mod commands;
+pub mod config;
+pub mod dcc;
+pub mod debug_console;
+pub mod dmx;
+pub mod dwell;
+pub mod fast_clock;
+pub mod framing;
+pub mod gateway;
+pub mod head_id;
+pub mod journal;
+pub mod keypad;
+pub mod lamp_aging;
+pub mod lamp_test;
+pub mod level_crossing;
+pub mod loconet;
+pub mod logging;
+pub mod maintenance;
+pub mod mast;
+pub mod max7219;
+pub mod mcp2515;
+pub mod messages;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod motorola;
+pub mod mqtt;
+pub mod openlcb;
+pub mod panel;
+pub mod platform;
+pub mod presentation;
+pub mod random;
+pub mod retransmit;
+pub mod ring_buffer;
+pub mod selectrix;
+pub mod semaphore;
+pub mod signals;
+pub mod slew;
+pub mod srcp;
+pub mod stop_hold;
+pub mod voting;
+pub mod w5500;
+pub mod warm_up;
+pub mod wear_leveling;
+pub mod xpressnet;
+pub mod zs2;
+pub mod zs3;
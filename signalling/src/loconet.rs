@@ -0,0 +1,307 @@
+//! Module for the LocoNet bus of Digitrax and Uhlenbrock systems, over which the signal receives switch requests like any accessory decoder, and reports the aspect it shows as the position of its turnouts.
+//!
+//! LocoNet is a wired-AND bus that idles high, on which every device can pull the line low. Bytes are sent like on a serial port, at 16 660 baud with a start bit, eight data bits (the lowest first) and a stop bit. A message starts with an opcode byte, which is the only byte with its highest bit set, and ends with a checksum byte that makes the XOR of all its bytes 0xff. Devices may only start sending once the bus was idle for a while, and check every bit they send, so that two devices that start at the same time notice the collision. The first device that notices it sends a break, which makes every device discard the message.
+
+use arrayvec::ArrayVec;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::InputPin;
+use embedded_hal::digital::OutputPin;
+
+use crate::dcc::AccessoryCommand;
+
+/// Duration of a bit, in microseconds.
+pub const BIT_US: u32 = 60;
+/// Time that the bus must be idle before a message may be sent: the carrier detect time of 20 bits, and the longest priority delay of 6 bits, which is the lowest priority.
+pub const IDLE_BEFORE_SENDING_US: u32 = 26 * BIT_US;
+/// Length of the break that aborts a message after a collision, in bits.
+const BREAK_BITS: u32 = 15;
+/// Length of a byte with its start and stop bit, in bits.
+const BYTE_BITS: u8 = 10;
+/// Longest message that is received. Longer messages, like programming or slot data, are never switch requests, and are skipped.
+const MAX_MESSAGE_LENGTH: usize = 8;
+/// Opcodes of the messages that request a turnout position, and that report the position of a turnout.
+const OPC_SW_REQ: u8 = 0xb0;
+const OPC_SW_REP: u8 = 0xb1;
+
+/// Parses a complete message, and returns its accessory command, if it is a switch request with a valid checksum.
+fn parse(message: &[u8]) -> Option<AccessoryCommand> {
+    if message.iter().fold(0, |checksum, byte| checksum ^ byte) != 0xff {
+        return None;
+    }
+    // the address is sent as seven and four bits, counted from 0, and the turnout is closed (green) or thrown (red).
+    match *message {
+        [OPC_SW_REQ, sw1, sw2, _] => Some(AccessoryCommand::Basic {
+            address: (u16::from(sw2 & 0x0f) << 7 | u16::from(sw1)) + 1,
+            is_green: sw2 & 0x20 != 0,
+            is_active: sw2 & 0x10 != 0,
+        }),
+        _ => None,
+    }
+}
+
+/// Returns the message that reports the given output of an address as switched on, which throttles and PC software show as the position of the turnout with that address. Addresses are numbered from 1, like DCC accessory addresses.
+pub fn switch_report(address: u16, is_green: bool) -> [u8; 4] {
+    let address = address - 1;
+    let sw1 = (address & 0x7f) as u8;
+    let sw2 = (address >> 7 & 0x0f) as u8 | if is_green { 0x20 } else { 0x10 };
+    [OPC_SW_REP, sw1, sw2, 0xff ^ OPC_SW_REP ^ sw1 ^ sw2]
+}
+
+/// Receives messages from the levels of the bus and their durations.
+pub struct LocoNetReceiver {
+    // Bits of the byte that is being received, starting with the start bit, and how many were received, or None while the bus is idle.
+    byte: Option<(u16, u8)>,
+    // Received bytes of the current message, if its opcode was received.
+    message: ArrayVec<u8, MAX_MESSAGE_LENGTH>,
+}
+
+impl Default for LocoNetReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocoNetReceiver {
+    pub const fn new() -> Self {
+        Self {
+            byte: None,
+            message: ArrayVec::new_const(),
+        }
+    }
+
+    /// Processes a level of the bus that lasted the given duration, in microseconds, and returns the accessory command that it completes, if any. Should be called on every edge, with the level before the edge.
+    pub fn receive_level(&mut self, is_high: bool, duration_us: u32) -> Option<AccessoryCommand> {
+        let (mut bits, mut count) = match self.byte {
+            Some(byte) => byte,
+            // a byte starts with the low start bit.
+            None if is_high => return None,
+            None => (0, 0),
+        };
+        let mut remaining = (duration_us + BIT_US / 2) / BIT_US;
+        while remaining > 0 && count < BYTE_BITS {
+            bits |= u16::from(is_high) << count;
+            count += 1;
+            remaining -= 1;
+        }
+        if count < BYTE_BITS {
+            self.byte = Some((bits, count));
+            return None;
+        }
+        // the rest of a high level is idle time.
+        self.byte = None;
+        if bits >> (BYTE_BITS - 1) == 0 {
+            // a missing stop bit is a break, or noise.
+            self.message.clear();
+            return None;
+        }
+        self.receive_byte((bits >> 1) as u8)
+    }
+
+    /// Processes the time since the last edge while the bus is high, in microseconds, and returns the accessory command that it completes, if any. The last bits of a byte are high, like the idle bus, so they only end with the next edge, which may be long after the message. Should be called regularly.
+    pub fn receive_idle(&mut self, duration_us: u32) -> Option<AccessoryCommand> {
+        let (_, count) = self.byte?;
+        // the level is received again at the next edge, when the receiver is idle and ignores it.
+        ((duration_us + BIT_US / 2) / BIT_US >= u32::from(BYTE_BITS - count))
+            .then(|| self.receive_level(true, duration_us))
+            .flatten()
+    }
+
+    /// Returns whether no byte is being received.
+    pub fn is_idle(&self) -> bool {
+        self.byte.is_none()
+    }
+
+    fn receive_byte(&mut self, byte: u8) -> Option<AccessoryCommand> {
+        if byte & 0x80 != 0 {
+            // an opcode starts a new message, even if the previous one is incomplete.
+            self.message.clear();
+        } else if self.message.is_empty() {
+            return None;
+        }
+        if self.message.try_push(byte).is_err() {
+            self.message.clear();
+            return None;
+        }
+        // the length is given by the opcode, or by the second byte of variable-length messages.
+        let length = match self.message[0] >> 5 & 0b11 {
+            0b00 => 2,
+            0b01 => 4,
+            0b10 => 6,
+            _ => usize::from(*self.message.get(1)?),
+        };
+        if self.message.len() < length {
+            return None;
+        }
+        let message = core::mem::take(&mut self.message);
+        parse(&message)
+    }
+}
+
+/// Sends a message on the bus, bit by bit, by pulling the bus low while the output is high. Each bit is checked on the input, which is high while the bus is high. The bus must have been idle for [`IDLE_BEFORE_SENDING_US`], and interrupts should be disabled, so that the bit times are kept.
+///
+/// Returns false if another device sent at the same time, in which case the message was aborted with a break, and should be sent again once the bus is idle.
+///
+/// # Errors
+/// Errors are returned from the HAL’s digital I/O functions.
+pub fn send<E>(
+    message: &[u8],
+    output: &mut impl OutputPin<Error = E>,
+    input: &mut impl InputPin<Error = E>,
+    delay: &mut impl DelayNs,
+) -> Result<bool, E> {
+    for byte in message {
+        let bits = u16::from(*byte) << 1 | 1 << (BYTE_BITS - 1);
+        for bit in 0..BYTE_BITS {
+            let is_high = bits >> bit & 1 != 0;
+            output.set_state((!is_high).into())?;
+            delay.delay_us(BIT_US / 2);
+            if input.is_high()? != is_high {
+                output.set_high()?;
+                delay.delay_us(BREAK_BITS * BIT_US);
+                output.set_low()?;
+                return Ok(false);
+            }
+            delay.delay_us(BIT_US / 2);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::convert::Infallible;
+    use std::rc::Rc;
+
+    use embedded_hal::digital::ErrorType;
+    use embedded_hal::digital::InputPin;
+    use embedded_hal::digital::OutputPin;
+
+    use super::send;
+    use super::switch_report;
+    use super::LocoNetReceiver;
+    use super::BIT_US;
+    use crate::dcc::AccessoryCommand;
+    use crate::mock::MockDelay;
+
+    /// Returns the levels of the bus and their durations while the given bytes are sent, one after the other.
+    fn encode(bytes: &[u8]) -> Vec<(bool, u32)> {
+        let mut levels: Vec<(bool, u32)> = Vec::new();
+        for byte in bytes {
+            let bits = u16::from(*byte) << 1 | 1 << 9;
+            for bit in 0..10 {
+                let is_high = bits >> bit & 1 != 0;
+                match levels.last_mut() {
+                    Some((level, duration)) if *level == is_high => *duration += BIT_US,
+                    _ => levels.push((is_high, BIT_US)),
+                }
+            }
+        }
+        levels
+    }
+
+    fn receive(receiver: &mut LocoNetReceiver, bytes: &[u8]) -> Vec<AccessoryCommand> {
+        let mut commands: Vec<AccessoryCommand> = encode(bytes)
+            .into_iter()
+            .filter_map(|(is_high, duration_us)| receiver.receive_level(is_high, duration_us))
+            .collect();
+        // the last level only ends with the next edge.
+        commands.extend(receiver.receive_idle(10 * BIT_US));
+        commands
+    }
+
+    #[test]
+    fn receives_switch_requests() {
+        let mut receiver = LocoNetReceiver::new();
+        // address 1000 closed, on.
+        let request = [0xb0, 0x67, 0x37, 0xff ^ 0xb0 ^ 0x67 ^ 0x37];
+        assert_eq!(
+            receive(&mut receiver, &request),
+            [AccessoryCommand::Basic {
+                address: 1000,
+                is_green: true,
+                is_active: true
+            }]
+        );
+        assert!(receiver.is_idle());
+        // other messages, broken checksums and messages that are cut off by the next opcode.
+        let power_on = [0x83, 0x7c];
+        assert!(receive(&mut receiver, &power_on).is_empty());
+        let broken = [0xb0, 0x67, 0x37, 0x00];
+        assert!(receive(&mut receiver, &broken).is_empty());
+        let request = [0xb0, 0x00, 0x10, 0xff ^ 0xb0 ^ 0x10];
+        assert_eq!(
+            receive(&mut receiver, &[[0xb0, 0x67].as_slice(), &request].concat()),
+            [AccessoryCommand::Basic {
+                address: 1,
+                is_green: false,
+                is_active: true
+            }]
+        );
+    }
+
+    #[test]
+    fn reports_turnout_positions() {
+        assert_eq!(switch_report(1000, true), [0xb1, 0x67, 0x27, 0x0e]);
+        assert_eq!(switch_report(1, false), [0xb1, 0x00, 0x10, 0x5e]);
+    }
+
+    /// A bus that is pulled low by the output, and by other devices while `is_pulled_low` is set.
+    #[derive(Clone, Default)]
+    struct Bus {
+        is_driven_low: Rc<Cell<bool>>,
+        is_pulled_low: Rc<Cell<bool>>,
+        driven_bits: Rc<Cell<u32>>,
+    }
+
+    impl ErrorType for Bus {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for Bus {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.is_driven_low.set(false);
+            self.driven_bits.set(self.driven_bits.get() + 1);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.is_driven_low.set(true);
+            self.driven_bits.set(self.driven_bits.get() + 1);
+            Ok(())
+        }
+    }
+
+    impl InputPin for Bus {
+        fn is_high(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.is_driven_low.get() && !self.is_pulled_low.get())
+        }
+
+        fn is_low(&mut self) -> Result<bool, Self::Error> {
+            self.is_high().map(|is_high| !is_high)
+        }
+    }
+
+    #[test]
+    fn sending_stops_at_collisions() {
+        let report = switch_report(1, false);
+        let bus = Bus::default();
+        let mut delay = MockDelay::default();
+        assert_eq!(
+            send(&report, &mut bus.clone(), &mut bus.clone(), &mut delay),
+            Ok(true)
+        );
+        assert_eq!(bus.driven_bits.get(), 40);
+        assert_eq!(delay.total_ns, 40 * u64::from(BIT_US) * 1000);
+        assert!(!bus.is_driven_low.get());
+
+        // another device pulls the bus low while the first high bit is sent, which is the lowest bit of the opcode.
+        let bus = Bus::default();
+        bus.is_pulled_low.set(true);
+        assert_eq!(
+            send(&report, &mut bus.clone(), &mut bus.clone(), &mut delay),
+            Ok(false)
+        );
+        assert!(!bus.is_driven_low.get());
+    }
+}
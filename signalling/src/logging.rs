@@ -0,0 +1,93 @@
+//! Module for the severity filters of the lines that the controller sends.
+//!
+//! Every line belongs to a channel: the protocol channel carries the replies to commands and the notifications that the control box relies on, and the diagnostics channel carries informational lines, like the announcements of the lamp aging simulation. Each channel has its own severity filter, so that verbose lines can be silenced in production. Errors are sent regardless of the filter, so that fault reports are never lost.
+
+/// Severity of a line, from the most to the least important.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Severity {
+    /// Errors and faults, which are always sent.
+    Error = 0,
+    /// Unusual situations that the control box should know about, like conflicting commands.
+    Warn = 1,
+    /// Normal replies and announcements.
+    Info = 2,
+    /// Detailed output for troubleshooting.
+    Trace = 3,
+}
+
+impl Severity {
+    /// Returns the severity with the given level, as used by the configuration options.
+    pub fn from_level(level: u8) -> Option<Self> {
+        match level {
+            0 => Some(Self::Error),
+            1 => Some(Self::Warn),
+            2 => Some(Self::Info),
+            3 => Some(Self::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// Channel that a line is sent on.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    /// Replies to commands and notifications of state changes.
+    Protocol,
+    /// Informational lines that no control box needs.
+    Diagnostics,
+}
+
+/// The least important severity that is sent on each channel.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct LogFilter {
+    pub protocol: Severity,
+    pub diagnostics: Severity,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogFilter {
+    /// Creates a filter that sends everything except trace lines.
+    pub const fn new() -> Self {
+        Self {
+            protocol: Severity::Info,
+            diagnostics: Severity::Info,
+        }
+    }
+
+    /// Returns whether a line with the given severity is sent on the given channel.
+    pub fn allows(self, channel: Channel, severity: Severity) -> bool {
+        let least_important = match channel {
+            Channel::Protocol => self.protocol,
+            Channel::Diagnostics => self.diagnostics,
+        };
+        severity <= least_important
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Channel;
+    use super::LogFilter;
+    use super::Severity;
+
+    #[test]
+    fn errors_pass_every_filter() {
+        let filter = LogFilter {
+            protocol: Severity::Warn,
+            diagnostics: Severity::Error,
+        };
+        assert!(filter.allows(Channel::Protocol, Severity::Warn));
+        assert!(!filter.allows(Channel::Protocol, Severity::Info));
+        assert!(!filter.allows(Channel::Diagnostics, Severity::Warn));
+        for channel in [Channel::Protocol, Channel::Diagnostics] {
+            assert!(filter.allows(channel, Severity::Error));
+        }
+        assert!(!LogFilter::new().allows(Channel::Diagnostics, Severity::Trace));
+    }
+}
@@ -0,0 +1,301 @@
+//! Module for maintenance functions that bypass the normal aspect logic.
+
+use crate::blink::Blinker;
+use crate::commands::AspectCommand;
+use crate::signals::LampRole;
+
+/// Raw lamp control is ended automatically if no raw lamp command was received for this long.
+pub const RAW_LAMP_TIMEOUT_MS: u32 = 60_000;
+/// Duration of each on and off phase of blinking lamps in raw lamp control.
+const RAW_LAMP_BLINK_HALF_PERIOD_MS: u32 = 500;
+/// The aspects that the stress test cycles through. Aspects that the signal doesn’t support are skipped.
+pub const STRESS_TEST_ASPECTS: [AspectCommand; 12] = [
+    AspectCommand::Zero,
+    AspectCommand::Shunting,
+    AspectCommand::Substitution,
+    AspectCommand::Caution,
+    AspectCommand::CounterTrackSubstitution,
+    AspectCommand::One,
+    AspectCommand::CounterTrack,
+    AspectCommand::Two,
+    AspectCommand::CounterTrackSlow,
+    AspectCommand::Three,
+    AspectCommand::Deactivated,
+    AspectCommand::Dark,
+];
+
+/// The aspects that the safe-state proof switches through, which are all aspects. Aspects that the signal doesn’t support are skipped.
+pub const PROOF_ASPECTS: [AspectCommand; 23] = [
+    AspectCommand::Zero,
+    AspectCommand::One,
+    AspectCommand::Two,
+    AspectCommand::Three,
+    AspectCommand::Shunting,
+    AspectCommand::Substitution,
+    AspectCommand::Caution,
+    AspectCommand::CounterTrack,
+    AspectCommand::CounterTrackSlow,
+    AspectCommand::CounterTrackSubstitution,
+    AspectCommand::ShuntingForbidden,
+    AspectCommand::ShuntingAllowed,
+    AspectCommand::Sv0,
+    AspectCommand::Sv1,
+    AspectCommand::Sv2,
+    AspectCommand::Sv3,
+    AspectCommand::Sv4,
+    AspectCommand::Sv5,
+    AspectCommand::Sv6,
+    AspectCommand::CrossingStop,
+    AspectCommand::CrossingProceed,
+    AspectCommand::Deactivated,
+    AspectCommand::Dark,
+];
+
+/// How a lamp is driven by raw lamp control.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RawLampState {
+    Off,
+    On,
+    Blinking,
+}
+
+impl RawLampState {
+    pub fn from_command_id(command_id: &[u8]) -> Option<Self> {
+        match command_id {
+            b"0" => Some(Self::Off),
+            b"1" => Some(Self::On),
+            b"blink" => Some(Self::Blinking),
+            _ => None,
+        }
+    }
+}
+
+/// State of raw lamp control, where lamps are switched independently of any aspect, e.g. by a production test jig.
+///
+/// This only keeps track of which lamps are blinking and when raw lamp control has to end; the lamps themselves are switched by the user.
+pub struct RawLampControl {
+    // Lamps that are currently blinking, as a bit set indexed by lamp role.
+    blinking_lamps: u32,
+    blinker: Blinker,
+    // Time of the last raw lamp command, or None if raw lamp control is not active.
+    last_command_time: Option<u32>,
+}
+
+impl Default for RawLampControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RawLampControl {
+    pub const fn new() -> Self {
+        Self {
+            blinking_lamps: 0,
+            blinker: Blinker::new(RAW_LAMP_BLINK_HALF_PERIOD_MS),
+            last_command_time: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.last_command_time.is_some()
+    }
+
+    /// Records a raw lamp command, starting raw lamp control if necessary.
+    ///
+    /// Returns the state that the lamp has to be switched to immediately (true meaning on).
+    pub fn set_lamp(&mut self, role: LampRole, state: RawLampState, now: u32) -> bool {
+        if !self.is_active() {
+            self.blinker.restart(now);
+        }
+        self.last_command_time = Some(now);
+
+        let bit = Self::bit_for(role);
+        match state {
+            RawLampState::Off => {
+                self.blinking_lamps &= !bit;
+                false
+            }
+            RawLampState::On => {
+                self.blinking_lamps &= !bit;
+                true
+            }
+            RawLampState::Blinking => {
+                self.blinking_lamps |= bit;
+                self.blinker.is_on()
+            }
+        }
+    }
+
+    /// Ends raw lamp control. The user has to switch to an aspect again afterwards.
+    pub fn end(&mut self) {
+        self.blinking_lamps = 0;
+        self.last_command_time = None;
+    }
+
+    /// Returns whether raw lamp control is active and has not received a command for too long.
+    pub fn has_timed_out(&self, now: u32) -> bool {
+        self.last_command_time
+            .is_some_and(|last| now.wrapping_sub(last) >= RAW_LAMP_TIMEOUT_MS)
+    }
+
+    /// Advances blinking to the current time. If blinking lamps need to be switched, returns their new state (true meaning on) and the lamps.
+    pub fn update_blinking(
+        &mut self,
+        now: u32,
+    ) -> Option<(bool, impl Iterator<Item = LampRole> + '_)> {
+        if self.blinking_lamps == 0 {
+            return None;
+        }
+        let is_on = self.blinker.update(now)?;
+        let lamps = LampRole::ALL
+            .into_iter()
+            .filter(|role| self.blinking_lamps & Self::bit_for(*role) != 0);
+        Some((is_on, lamps))
+    }
+
+    fn bit_for(role: LampRole) -> u32 {
+        1 << role as u32
+    }
+}
+
+/// Timing statistics of the aspect changes during a stress test.
+#[derive(Clone, Copy, Default)]
+pub struct SwitchTimings {
+    pub count: u16,
+    pub min_ms: u32,
+    pub max_ms: u32,
+    // Sum of all durations, for the average.
+    total_ms: u32,
+}
+
+impl SwitchTimings {
+    /// Records the duration of a single aspect change.
+    pub fn record(&mut self, duration_ms: u32) {
+        self.min_ms = if self.count == 0 {
+            duration_ms
+        } else {
+            self.min_ms.min(duration_ms)
+        };
+        self.max_ms = self.max_ms.max(duration_ms);
+        self.total_ms = self.total_ms.saturating_add(duration_ms);
+        self.count = self.count.saturating_add(1);
+    }
+
+    /// Returns the average duration of an aspect change, rounded down, or 0 if no change was recorded.
+    pub fn average_ms(&self) -> u32 {
+        self.total_ms
+            .checked_div(self.count.into())
+            .unwrap_or_default()
+    }
+}
+
+/// A set of lamps, like the lamps that were read back as lit in one step of the safe-state proof, or the lamps that failed a lamp test. Displayed as the lamp IDs separated by commas, or `-` if the set is empty.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct LampSet(u32);
+
+impl LampSet {
+    /// Bits of the lamp roles, which are the only bits that a set can have.
+    const ALL_BITS: u32 = (1 << LampRole::ALL.len()) - 1;
+
+    pub fn insert(&mut self, role: LampRole) {
+        self.0 |= RawLampControl::bit_for(role);
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the set with the given bits, as stored by [`LampSet::bits`], or None if a bit doesn’t belong to a lamp role.
+    pub(crate) fn from_bits(bits: u32) -> Option<Self> {
+        (bits & !Self::ALL_BITS == 0).then_some(Self(bits))
+    }
+
+    pub(crate) fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl ufmt::uDisplay for LampSet {
+    fn fmt<W>(&self, formatter: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        let mut lamps = LampRole::ALL
+            .into_iter()
+            .filter(|role| self.0 & RawLampControl::bit_for(*role) != 0);
+        match lamps.next() {
+            Some(role) => formatter.write_str(role.command_id())?,
+            None => formatter.write_str("-")?,
+        }
+        for role in lamps {
+            formatter.write_str(",")?;
+            formatter.write_str(role.command_id())?;
+        }
+        Ok(())
+    }
+}
+
+// every lamp role needs a bit in the set of blinking lamps and lit lamps.
+const _: () = assert!(LampRole::ALL.len() <= u32::BITS as usize);
+
+#[cfg(test)]
+mod tests {
+    use super::LampSet;
+    use super::RawLampControl;
+    use super::RawLampState;
+    use super::SwitchTimings;
+    use super::PROOF_ASPECTS;
+    use crate::commands::AspectCommand;
+    use crate::signals::LampRole;
+
+    #[test]
+    fn proof_covers_every_aspect_once() {
+        for command_id in [
+            "0", "1", "2", "3", "S", "Z1", "Z7", "Z6", "Z62", "Z8", "SH0", "SH1", "SV0", "SV1",
+            "SV2", "SV3", "SV4", "SV5", "SV6", "BU0", "BU1", "A", "D",
+        ] {
+            let aspect = AspectCommand::from_command_id(command_id.as_bytes()).unwrap();
+            assert_eq!(
+                PROOF_ASPECTS
+                    .iter()
+                    .filter(|proof| **proof == aspect)
+                    .count(),
+                1,
+                "{command_id}"
+            );
+        }
+    }
+
+    #[test]
+    fn lit_lamps_are_listed_in_role_order() {
+        let mut text = String::new();
+        let mut lamps = LampSet::default();
+        ufmt::uwrite!(text, "{}", lamps).unwrap();
+        assert_eq!(text, "-");
+        lamps.insert(LampRole::AnnouncementYellowUpper);
+        lamps.insert(LampRole::MainRed);
+        text.clear();
+        ufmt::uwrite!(text, "{}", lamps).unwrap();
+        assert_eq!(text, "MR,AYU");
+    }
+
+    #[test]
+    fn every_lamp_role_can_blink() {
+        let mut control = RawLampControl::new();
+        control.set_lamp(LampRole::MainRed, RawLampState::Blinking, 0);
+        control.set_lamp(LampRole::DwarfNotice, RawLampState::Blinking, 0);
+        let (_, lamps) = control.update_blinking(500).unwrap();
+        assert!(lamps.eq([LampRole::MainRed, LampRole::DwarfNotice]));
+    }
+
+    #[test]
+    fn switch_timings_track_minimum_maximum_and_average() {
+        let mut timings = SwitchTimings::default();
+        assert_eq!(timings.average_ms(), 0);
+        for duration_ms in [802, 0, 805] {
+            timings.record(duration_ms);
+        }
+        assert_eq!((timings.count, timings.min_ms, timings.max_ms), (3, 0, 805));
+        assert_eq!(timings.average_ms(), 535);
+    }
+}
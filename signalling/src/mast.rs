@@ -0,0 +1,505 @@
+//! Module for composing several signal heads on one mast into a single logical signal.
+//!
+//! Complex masts, like station exit signals, carry a main signal together with additional heads such as speed or direction indicators. A [`Mast`] drives all of them from one aspect, so that the whole mast has a single command address and the heads can never contradict the main signal.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+
+use crate::signals::DwarfSignal;
+use crate::signals::DwarfSignalAspect;
+use crate::signals::LampRole;
+use crate::signals::Signal;
+use crate::signals::SignalAspect;
+use crate::zs2::Zs2Indicator;
+
+/// An additional signal head on a mast, which derives what it shows from the aspect of the mast’s main signal.
+pub trait MastHead<Aspect: SignalAspect, Error> {
+    /// Shows whatever this head’s presentation rule derives from the main signal aspect.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    fn show(&mut self, aspect: Aspect, delay: &mut impl DelayNs) -> Result<(), Error>;
+
+    /// Returns whether this head can show the given route letter together with the main signal aspect. Only direction indicators support routes.
+    fn supports_route(&self, _aspect: Aspect, _route: u8) -> bool {
+        false
+    }
+
+    /// Shows what this head derives from the main signal aspect, with a direction indicator showing the given route letter or nothing.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    fn show_route(
+        &mut self,
+        aspect: Aspect,
+        _route: Option<u8>,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        self.show(aspect, delay)
+    }
+
+    /// Advances time-dependent parts of the head, like blinking lamps, to the current time.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    fn update(&mut self, _now: u32) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns whether what this head shows changes when the main signal switches from one aspect and route to the other. Heads that don’t know assume that it changes with every aspect and route.
+    fn changes(&self, from: (Aspect, Option<u8>), to: (Aspect, Option<u8>)) -> bool {
+        from != to
+    }
+}
+
+/// No additional heads, for a mast that was just created.
+impl<Aspect: SignalAspect, Error> MastHead<Aspect, Error> for () {
+    fn show(&mut self, _aspect: Aspect, _delay: &mut impl DelayNs) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn changes(&self, _from: (Aspect, Option<u8>), _to: (Aspect, Option<u8>)) -> bool {
+        false
+    }
+}
+
+/// A head that is only present on some boards.
+impl<Aspect: SignalAspect, Error, Head: MastHead<Aspect, Error>> MastHead<Aspect, Error>
+    for Option<Head>
+{
+    fn show(&mut self, aspect: Aspect, delay: &mut impl DelayNs) -> Result<(), Error> {
+        self.as_mut()
+            .map(|head| head.show(aspect, delay))
+            .transpose()?;
+        Ok(())
+    }
+
+    fn supports_route(&self, aspect: Aspect, route: u8) -> bool {
+        self.as_ref()
+            .is_some_and(|head| head.supports_route(aspect, route))
+    }
+
+    fn show_route(
+        &mut self,
+        aspect: Aspect,
+        route: Option<u8>,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        self.as_mut()
+            .map(|head| head.show_route(aspect, route, delay))
+            .transpose()?;
+        Ok(())
+    }
+
+    fn update(&mut self, now: u32) -> Result<(), Error> {
+        self.as_mut().map(|head| head.update(now)).transpose()?;
+        Ok(())
+    }
+
+    fn changes(&self, from: (Aspect, Option<u8>), to: (Aspect, Option<u8>)) -> bool {
+        self.as_ref().is_some_and(|head| head.changes(from, to))
+    }
+}
+
+/// Several heads, which are switched in order.
+impl<
+        Aspect: SignalAspect,
+        Error,
+        First: MastHead<Aspect, Error>,
+        Second: MastHead<Aspect, Error>,
+    > MastHead<Aspect, Error> for (First, Second)
+{
+    fn show(&mut self, aspect: Aspect, delay: &mut impl DelayNs) -> Result<(), Error> {
+        self.0.show(aspect, delay)?;
+        self.1.show(aspect, delay)
+    }
+
+    /// The route is shown by whichever head supports it.
+    fn supports_route(&self, aspect: Aspect, route: u8) -> bool {
+        self.0.supports_route(aspect, route) || self.1.supports_route(aspect, route)
+    }
+
+    fn show_route(
+        &mut self,
+        aspect: Aspect,
+        route: Option<u8>,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        self.0.show_route(aspect, route, delay)?;
+        self.1.show_route(aspect, route, delay)
+    }
+
+    fn update(&mut self, now: u32) -> Result<(), Error> {
+        self.0.update(now)?;
+        self.1.update(now)
+    }
+
+    fn changes(&self, from: (Aspect, Option<u8>), to: (Aspect, Option<u8>)) -> bool {
+        self.0.changes(from, to) || self.1.changes(from, to)
+    }
+}
+
+/// A single lamp that is lit for some main signal aspects, like a Zs3 speed indicator with a fixed speed or a Zs2 direction indicator with a fixed letter.
+pub struct Indicator<Error, PinType: OutputPin<Error = Error>, Aspect: SignalAspect> {
+    lamp: PinType,
+    // Presentation rule: whether the lamp is lit for a main signal aspect.
+    is_lit_for: fn(Aspect) -> bool,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>, Aspect: SignalAspect>
+    Indicator<Error, PinType, Aspect>
+{
+    /// Creates an indicator that is lit for the main signal aspects that the given rule returns true for.
+    pub fn new(lamp: PinType, is_lit_for: fn(Aspect) -> bool) -> Self {
+        Self { lamp, is_lit_for }
+    }
+}
+
+impl<Error, PinType: OutputPin<Error = Error>, Aspect: SignalAspect> MastHead<Aspect, Error>
+    for Indicator<Error, PinType, Aspect>
+{
+    fn show(&mut self, aspect: Aspect, _delay: &mut impl DelayNs) -> Result<(), Error> {
+        self.lamp.set_state((self.is_lit_for)(aspect).into())
+    }
+
+    fn changes(&self, from: (Aspect, Option<u8>), to: (Aspect, Option<u8>)) -> bool {
+        (self.is_lit_for)(from.0) != (self.is_lit_for)(to.0)
+    }
+}
+
+/// Returns the route that a direction indicator shows next to the given main signal aspect.
+fn shown_route<Aspect: SignalAspect>(aspect: Aspect, route: Option<u8>) -> Option<u8> {
+    route.filter(|_| !aspect.lights_lamp(LampRole::MainRed) && !aspect.blanks_signal())
+}
+
+/// A Zs2 direction indicator next to the main signal, which shows the commanded route together with aspects that let a train pass the signal, and is dark otherwise.
+impl<Aspect: SignalAspect, Error, PinType: OutputPin<Error = Error>> MastHead<Aspect, Error>
+    for Zs2Indicator<Error, PinType>
+{
+    fn show(&mut self, aspect: Aspect, delay: &mut impl DelayNs) -> Result<(), Error> {
+        self.show_route(aspect, None, delay)
+    }
+
+    fn supports_route(&self, aspect: Aspect, route: u8) -> bool {
+        !aspect.lights_lamp(LampRole::MainRed)
+            && !aspect.blanks_signal()
+            && self.supports_route(route)
+    }
+
+    fn show_route(
+        &mut self,
+        aspect: Aspect,
+        route: Option<u8>,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        Zs2Indicator::show(self, shown_route(aspect, route))
+    }
+
+    fn changes(&self, from: (Aspect, Option<u8>), to: (Aspect, Option<u8>)) -> bool {
+        shown_route(from.0, from.1) != shown_route(to.0, to.1)
+    }
+}
+
+/// A dwarf signal next to the main signal, which shows Sh1 whenever any movement may pass the main signal. Without a notice lamp, it is dark while the main signal is deactivated.
+impl<Aspect: SignalAspect, Error, PinType: OutputPin<Error = Error>> MastHead<Aspect, Error>
+    for DwarfSignal<Error, PinType>
+where
+    DwarfSignalAspect: From<Aspect>,
+{
+    fn show(&mut self, aspect: Aspect, delay: &mut impl DelayNs) -> Result<(), Error> {
+        let aspect = self.shown_aspect(aspect);
+        self.switch_to_aspect(aspect, delay)
+    }
+
+    fn changes(&self, from: (Aspect, Option<u8>), to: (Aspect, Option<u8>)) -> bool {
+        self.shown_aspect(from.0) != self.shown_aspect(to.0)
+    }
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> DwarfSignal<Error, PinType> {
+    /// Returns the aspect that the dwarf signal shows next to the given main signal aspect.
+    fn shown_aspect<Aspect: SignalAspect>(&self, aspect: Aspect) -> DwarfSignalAspect
+    where
+        DwarfSignalAspect: From<Aspect>,
+    {
+        let aspect = DwarfSignalAspect::from(aspect);
+        if self.supports_aspect(aspect) {
+            aspect
+        } else {
+            DwarfSignalAspect::Dark
+        }
+    }
+}
+
+/// A main signal together with the additional heads on its mast, which behaves like a single signal.
+///
+/// What a head shows is only valid together with the main signal aspect, e.g. a speed indicator only means something next to a proceed aspect. Therefore, heads only ever change while the main signal shows stop: when switching between two other aspects that change what a head shows, the main signal shows stop in between.
+pub struct Mast<Main: Signal, Heads: MastHead<Main::Aspect, Main::Error>> {
+    main_signal: Main,
+    heads: Heads,
+    // Aspect that was last switched to, if any.
+    aspect: Option<Main::Aspect>,
+    // Route that the direction indicators show.
+    route: Option<u8>,
+}
+
+impl<Main: Signal> Mast<Main, ()> {
+    /// Creates a mast with only the given main signal (or signal group), to which heads can be added.
+    pub fn new(main_signal: Main) -> Self {
+        Self {
+            main_signal,
+            heads: (),
+            aspect: None,
+            route: None,
+        }
+    }
+}
+
+impl<Main: Signal, Heads: MastHead<Main::Aspect, Main::Error>> Mast<Main, Heads> {
+    /// Adds another head to this mast, which is switched after the heads that were added before.
+    pub fn with_head<Head: MastHead<Main::Aspect, Main::Error>>(
+        self,
+        head: Head,
+    ) -> Mast<Main, (Heads, Head)> {
+        Mast {
+            main_signal: self.main_signal,
+            heads: (self.heads, head),
+            aspect: self.aspect,
+            route: self.route,
+        }
+    }
+}
+
+impl<Main: Signal, Heads: MastHead<Main::Aspect, Main::Error>> Mast<Main, Heads> {
+    /// Switches to the given aspect, with the main signal’s speed indicator showing the given speed or nothing, and the direction indicators showing the given route or nothing.
+    fn switch(
+        &mut self,
+        aspect: Main::Aspect,
+        speed: Option<u8>,
+        route: Option<u8>,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Main::Error> {
+        // without a previous aspect, the lamps may show anything.
+        let is_stop_interposed = self.aspect != Some(Main::Aspect::STOP)
+            && self.aspect.map_or(true, |previous| {
+                self.heads.changes((previous, self.route), (aspect, route))
+            });
+        if is_stop_interposed {
+            self.main_signal
+                .switch_to_aspect(Main::Aspect::STOP, delay)?;
+        }
+        self.heads.show_route(aspect, route, delay)?;
+        match speed {
+            Some(speed) => self
+                .main_signal
+                .switch_to_aspect_with_speed(aspect, speed, delay)?,
+            None if aspect != Main::Aspect::STOP || !is_stop_interposed => {
+                self.main_signal.switch_to_aspect(aspect, delay)?
+            }
+            None => {}
+        }
+        self.aspect = Some(aspect);
+        self.route = route;
+        Ok(())
+    }
+}
+
+impl<Main: Signal, Heads: MastHead<Main::Aspect, Main::Error>> Signal for Mast<Main, Heads> {
+    type Aspect = Main::Aspect;
+    type Pin = Main::Pin;
+    type Error = Main::Error;
+
+    fn supports_aspect(&self, aspect: Main::Aspect) -> bool {
+        self.main_signal.supports_aspect(aspect)
+    }
+
+    fn switch_to_aspect(
+        &mut self,
+        aspect: Main::Aspect,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Main::Error> {
+        self.switch(aspect, None, None, delay)
+    }
+
+    fn supports_speed(&self, aspect: Main::Aspect, speed: u8) -> bool {
+        self.main_signal.supports_speed(aspect, speed)
+    }
+
+    fn switch_to_aspect_with_speed(
+        &mut self,
+        aspect: Main::Aspect,
+        speed: u8,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Main::Error> {
+        self.switch(aspect, Some(speed), None, delay)
+    }
+
+    fn supports_route(&self, aspect: Main::Aspect, route: u8) -> bool {
+        self.main_signal.supports_aspect(aspect) && self.heads.supports_route(aspect, route)
+    }
+
+    fn switch_to_aspect_with_route(
+        &mut self,
+        aspect: Main::Aspect,
+        speed: Option<u8>,
+        route: u8,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Main::Error> {
+        if !self.supports_route(aspect, route)
+            || speed.is_some_and(|speed| !self.supports_speed(aspect, speed))
+        {
+            panic!("illegal aspect for this light, route can’t be shown");
+        }
+        self.switch(aspect, speed, Some(route), delay)
+    }
+
+    /// Only the main signal’s lamps can be switched directly.
+    fn lamp(&mut self, role: LampRole) -> Option<&mut Main::Pin> {
+        self.main_signal.lamp(role)
+    }
+
+    fn show_next_aspect(
+        &mut self,
+        next_aspect: Main::Aspect,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Main::Error> {
+        self.main_signal.show_next_aspect(next_aspect, delay)
+    }
+
+    fn suppress_announcement(
+        &mut self,
+        is_suppressed: bool,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Main::Error> {
+        self.main_signal.suppress_announcement(is_suppressed, delay)
+    }
+
+    fn update(&mut self, now: u32) -> Result<(), Main::Error> {
+        self.main_signal.update(now)?;
+        self.heads.update(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use super::Indicator;
+    use super::Mast;
+    use crate::mock::MockDelay;
+    use crate::mock::MockPins;
+    use crate::signals::DwarfSignal;
+    use crate::signals::HVMainSignal;
+    use crate::signals::HVMainSignalAspect;
+    use crate::signals::Signal;
+    use crate::zs2::Zs2Indicator;
+
+    #[test]
+    fn heads_only_change_while_the_main_signal_shows_stop() {
+        let pins = MockPins::new();
+        let main_signal: HVMainSignal<Infallible, _> =
+            HVMainSignal::new(pins.pin(), pins.pin()).with_yellow_lamp(pins.pin());
+        let mut mast = Mast::new(main_signal).with_head(Indicator::new(pins.pin(), |aspect| {
+            aspect == HVMainSignalAspect::ProceedSlow
+        }));
+        let mut delay = MockDelay::default();
+        for aspect in [
+            HVMainSignalAspect::Stop,
+            HVMainSignalAspect::ProceedSlow,
+            HVMainSignalAspect::Proceed,
+            HVMainSignalAspect::ProceedSlow,
+            HVMainSignalAspect::Stop,
+        ] {
+            mast.switch_to_aspect(aspect, &mut delay).unwrap();
+        }
+
+        // pins: red, green, yellow, speed indicator
+        let history = pins.history();
+        for (before, after) in history.iter().zip(&history[1..]) {
+            if before[3] != after[3] {
+                assert!(after[0], "indicator changed without stop");
+            }
+            if after[1] && after[2] {
+                assert!(after[3], "Hp2 without speed indicator");
+            }
+        }
+        assert_eq!(pins.states(), [true, false, false, false]);
+    }
+
+    #[test]
+    fn main_signal_switches_directly_without_changing_heads() {
+        let pins = MockPins::new();
+        let main_signal: HVMainSignal<Infallible, _> =
+            HVMainSignal::new(pins.pin(), pins.pin()).with_yellow_lamp(pins.pin());
+        let mut mast = Mast::new(main_signal);
+        let mut delay = MockDelay::default();
+        mast.switch_to_aspect(HVMainSignalAspect::Proceed, &mut delay)
+            .unwrap();
+        pins.clear_history();
+        mast.switch_to_aspect(HVMainSignalAspect::ProceedSlow, &mut delay)
+            .unwrap();
+
+        // pins: red, green, yellow
+        assert!(
+            pins.history().iter().all(|states| !states[0]),
+            "stop shown without changing heads"
+        );
+        assert_eq!(pins.states(), [false, true, true]);
+        mast.switch_to_aspect(HVMainSignalAspect::Stop, &mut delay)
+            .unwrap();
+        assert_eq!(pins.states(), [true, false, false]);
+    }
+
+    #[test]
+    fn direction_indicator_shows_the_route_while_proceeding() {
+        let pins = MockPins::new();
+        let main_signal: HVMainSignal<Infallible, _> = HVMainSignal::new(pins.pin(), pins.pin());
+        let mut mast =
+            Mast::new(main_signal).with_head(Zs2Indicator::new([(); 7].map(|_| pins.pin())));
+        let mut delay = MockDelay::default();
+        assert!(mast.supports_route(HVMainSignalAspect::Proceed, b'H'));
+        assert!(!mast.supports_route(HVMainSignalAspect::Stop, b'H'));
+
+        // pins: red, green, Zs2 segments a to g
+        mast.switch_to_aspect_with_route(HVMainSignalAspect::Proceed, None, b'H', &mut delay)
+            .unwrap();
+        assert_eq!(
+            pins.states(),
+            [false, true, false, true, true, false, true, true, true]
+        );
+        pins.clear_history();
+        mast.switch_to_aspect_with_route(HVMainSignalAspect::Proceed, None, b'L', &mut delay)
+            .unwrap();
+        assert!(
+            pins.history().iter().any(|states| states[0]),
+            "route changed without stop"
+        );
+        assert_eq!(
+            pins.states(),
+            [false, true, false, false, false, true, true, true, false]
+        );
+        mast.switch_to_aspect(HVMainSignalAspect::Stop, &mut delay)
+            .unwrap();
+        assert_eq!(&pins.states()[2..], [false; 7]);
+    }
+
+    #[test]
+    fn dwarf_signal_follows_the_main_signal() {
+        let pins = MockPins::new();
+        let main_signal: HVMainSignal<Infallible, _> =
+            HVMainSignal::new(pins.pin(), pins.pin()).with_shunting_lamps([pins.pin(), pins.pin()]);
+        let mut mast = Mast::new(main_signal).with_head(DwarfSignal::new(pins.pin(), pins.pin()));
+        let mut delay = MockDelay::default();
+
+        // pins: main red, main green, two main Sh1, dwarf red, dwarf white
+        mast.switch_to_aspect(HVMainSignalAspect::Stop, &mut delay)
+            .unwrap();
+        assert_eq!(pins.states(), [true, false, false, false, true, false]);
+        mast.switch_to_aspect(HVMainSignalAspect::StopWithShunting, &mut delay)
+            .unwrap();
+        assert_eq!(pins.states(), [true, false, true, true, false, true]);
+        mast.switch_to_aspect(HVMainSignalAspect::Proceed, &mut delay)
+            .unwrap();
+        assert_eq!(pins.states(), [false, true, false, false, false, true]);
+    }
+}
@@ -0,0 +1,199 @@
+//! Module for LED matrix modules driven by a MAX7219, which show the digits and letters of speed and direction indicators with only three pins.
+//!
+//! The MAX7219 is connected like a shift register and is written 16 bits at a time: a register address followed by its value. Commodity modules carry an 8×8 LED matrix whose rows are the MAX7219’s digit registers.
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal::digital::PinState;
+
+// Register addresses.
+const FIRST_ROW: u8 = 0x01;
+const DECODE_MODE: u8 = 0x09;
+const INTENSITY: u8 = 0x0a;
+const SCAN_LIMIT: u8 = 0x0b;
+const SHUTDOWN: u8 = 0x0c;
+const DISPLAY_TEST: u8 = 0x0f;
+
+// Columns of the 5×7 glyphs of the digits 0 to 9, leftmost first, with the top row in bit 0.
+const DIGIT_GLYPHS: [[u8; 5]; 10] = [
+    [0x3e, 0x51, 0x49, 0x45, 0x3e],
+    [0x00, 0x42, 0x7f, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46],
+    [0x21, 0x41, 0x45, 0x4b, 0x31],
+    [0x18, 0x14, 0x12, 0x7f, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3c, 0x4a, 0x49, 0x49, 0x30],
+    [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x06, 0x49, 0x49, 0x29, 0x1e],
+];
+
+// Glyphs of the letters A to Z, like the digits.
+const LETTER_GLYPHS: [[u8; 5]; 26] = [
+    [0x7e, 0x11, 0x11, 0x11, 0x7e],
+    [0x7f, 0x49, 0x49, 0x49, 0x36],
+    [0x3e, 0x41, 0x41, 0x41, 0x22],
+    [0x7f, 0x41, 0x41, 0x22, 0x1c],
+    [0x7f, 0x49, 0x49, 0x49, 0x41],
+    [0x7f, 0x09, 0x09, 0x09, 0x01],
+    [0x3e, 0x41, 0x49, 0x49, 0x7a],
+    [0x7f, 0x08, 0x08, 0x08, 0x7f],
+    [0x00, 0x41, 0x7f, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3f, 0x01],
+    [0x7f, 0x08, 0x14, 0x22, 0x41],
+    [0x7f, 0x40, 0x40, 0x40, 0x40],
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f],
+    [0x7f, 0x04, 0x08, 0x10, 0x7f],
+    [0x3e, 0x41, 0x41, 0x41, 0x3e],
+    [0x7f, 0x09, 0x09, 0x09, 0x06],
+    [0x3e, 0x41, 0x51, 0x21, 0x5e],
+    [0x7f, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7f, 0x01, 0x01],
+    [0x3f, 0x40, 0x40, 0x40, 0x3f],
+    [0x1f, 0x20, 0x40, 0x20, 0x1f],
+    [0x3f, 0x40, 0x38, 0x40, 0x3f],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x07, 0x08, 0x70, 0x08, 0x07],
+    [0x61, 0x51, 0x49, 0x45, 0x43],
+];
+
+/// An 8×8 LED matrix driven by a MAX7219, which shows a single digit or uppercase letter.
+///
+/// The MAX7219 is configured when the first character is shown, so that creating the driver doesn’t touch the hardware. Column 0 (left) of the matrix is the most significant bit of each row.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used. Its parameters additionally include the output pin’s error type (which some functions also return).
+pub struct Max7219<Error, PinType: OutputPin<Error = Error>> {
+    data: PinType,
+    clock: PinType,
+    load: PinType,
+    // Whether the MAX7219 was configured for the matrix.
+    is_configured: bool,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> Max7219<Error, PinType> {
+    /// Creates a driver for the MAX7219 whose DIN, CLK and LOAD (CS) inputs are connected to the given pins.
+    pub fn new(data: PinType, clock: PinType, load: PinType) -> Self {
+        Self {
+            data,
+            clock,
+            load,
+            is_configured: false,
+        }
+    }
+
+    /// Returns the glyph of the given ASCII character, if it is a digit or an uppercase letter.
+    fn glyph(character: u8) -> Option<[u8; 5]> {
+        match character {
+            b'0'..=b'9' => Some(DIGIT_GLYPHS[usize::from(character - b'0')]),
+            b'A'..=b'Z' => Some(LETTER_GLYPHS[usize::from(character - b'A')]),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the given ASCII character can be shown, i.e. whether it is a digit or an uppercase letter.
+    pub fn supports_character(character: u8) -> bool {
+        Self::glyph(character).is_some()
+    }
+
+    /// Shows the given ASCII character, centered on the matrix, or nothing.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    ///
+    /// # Panics
+    /// This function will panic if the character can’t be shown, which is a logic bug; see [`Self::supports_character`].
+    pub fn show_character(&mut self, character: Option<u8>) -> Result<(), Error> {
+        let glyph = match character {
+            Some(character) => match Self::glyph(character) {
+                Some(glyph) => glyph,
+                None => {
+                    panic!("illegal character for this matrix, only digits and letters available")
+                }
+            },
+            None => [0; 5],
+        };
+        if !self.is_configured {
+            // the registers are undefined after power-on.
+            self.write(DISPLAY_TEST, 0)?;
+            self.write(DECODE_MODE, 0)?;
+            self.write(SCAN_LIMIT, 7)?;
+            self.write(INTENSITY, 8)?;
+            self.write(SHUTDOWN, 1)?;
+            self.is_configured = true;
+        }
+        for row in 0..8 {
+            // the glyph occupies columns 1 to 5 and rows 0 to 6.
+            let row_bits = glyph
+                .iter()
+                .enumerate()
+                .filter(|(_, column)| *column & 1 << row != 0)
+                .fold(0, |row_bits, (column, _)| row_bits | 0x80 >> (column + 1));
+            self.write(FIRST_ROW + row, row_bits)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a value to a register of the MAX7219.
+    fn write(&mut self, register: u8, value: u8) -> Result<(), Error> {
+        let word = u16::from(register) << 8 | u16::from(value);
+        // shift out the most significant bit first, which the MAX7219 expects.
+        for bit in (0..16).rev() {
+            self.data.set_state(PinState::from(word & 1 << bit != 0))?;
+            self.clock.set_high()?;
+            self.clock.set_low()?;
+        }
+        self.load.set_high()?;
+        self.load.set_low()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use super::Max7219;
+    use crate::mock::MockPin;
+    use crate::mock::MockPins;
+
+    /// Decodes the 16-bit words that were shifted into the MAX7219, from the history of its data, clock and load pins.
+    fn written_words(pins: &MockPins) -> Vec<u16> {
+        let mut words = Vec::new();
+        let mut word = 0;
+        let history = pins.history();
+        for (before, after) in history.iter().zip(&history[1..]) {
+            if !before[1] && after[1] {
+                word = word << 1 | u16::from(after[0]);
+            }
+            if !before[2] && after[2] {
+                words.push(word);
+                word = 0;
+            }
+        }
+        words
+    }
+
+    #[test]
+    fn configures_the_matrix_and_shows_characters() {
+        let pins = MockPins::new();
+        let mut matrix: Max7219<Infallible, MockPin> =
+            Max7219::new(pins.pin(), pins.pin(), pins.pin());
+        matrix.show_character(Some(b'1')).unwrap();
+        matrix.show_character(None).unwrap();
+
+        let words = written_words(&pins);
+        assert_eq!(words[..5], [0x0f00, 0x0900, 0x0b07, 0x0a08, 0x0c01]);
+        // the 1 has its stem in column 3 and its foot in columns 2 to 4.
+        assert_eq!(
+            words[5..13],
+            [0x0110, 0x0230, 0x0310, 0x0410, 0x0510, 0x0610, 0x0738, 0x0800]
+        );
+        assert_eq!(
+            words[13..],
+            [0x0100, 0x0200, 0x0300, 0x0400, 0x0500, 0x0600, 0x0700, 0x0800]
+        );
+        assert!(!Max7219::<Infallible, MockPin>::supports_character(b'a'));
+    }
+}
@@ -0,0 +1,303 @@
+//! Module for the MCP2515 CAN controller, which connects the signal to a CAN bus over SPI, e.g. on the cheap modules with a TJA1050 transceiver.
+//!
+//! The MCP2515 receives and sends frames on its own, so it is only polled: every SPI transaction starts with an instruction byte, and the chip select is raised afterwards. The controller runs at the 125 kbit/s of OpenLCB, and only extended frames with their 29-bit identifiers are used.
+
+use arrayvec::ArrayVec;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiBus;
+
+/// Bit rate of the bus.
+pub const BIT_RATE: u32 = 125_000;
+/// Number of time quanta of each bit, which are set up in [`Mcp2515::start`].
+const TIME_QUANTA_PER_BIT: u32 = 16;
+
+// Instructions.
+const RESET: u8 = 0xc0;
+const READ: u8 = 0x03;
+const WRITE: u8 = 0x02;
+const READ_STATUS: u8 = 0xa0;
+const REQUEST_TO_SEND_TXB0: u8 = 0x81;
+const READ_RXB0: u8 = 0x90;
+const READ_RXB1: u8 = 0x94;
+
+// Register addresses.
+const CANSTAT: u8 = 0x0e;
+const CANCTRL: u8 = 0x0f;
+const CNF3: u8 = 0x28;
+const TXB0SIDH: u8 = 0x31;
+const RXB0CTRL: u8 = 0x60;
+const RXB1CTRL: u8 = 0x70;
+
+// Bits of the status that is read with READ_STATUS.
+const STATUS_RX0IF: u8 = 0x01;
+const STATUS_RX1IF: u8 = 0x02;
+const STATUS_TXB0REQ: u8 = 0x04;
+/// Operation mode in CANSTAT and CANCTRL, which is the configuration mode after a reset.
+const MODE_MASK: u8 = 0xe0;
+const MODE_CONFIGURATION: u8 = 0x80;
+/// Bit of SIDL that marks an extended frame.
+const SIDL_EXIDE: u8 = 0x08;
+
+/// A CAN frame with an extended identifier.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CanFrame {
+    /// The 29-bit identifier.
+    pub id: u32,
+    pub data: ArrayVec<u8, 8>,
+}
+
+impl CanFrame {
+    /// Creates a frame with the given identifier and data.
+    ///
+    /// # Panics
+    /// This function will panic if there are more than 8 data bytes, which is a logic bug.
+    pub fn new(id: u32, data: &[u8]) -> Self {
+        Self {
+            id,
+            data: data.try_into().unwrap(),
+        }
+    }
+}
+
+/// Returns whether the bit rate can be derived from an MCP2515 oscillator with the given frequency, like from the usual 8 or 16 MHz crystals.
+pub const fn supports_oscillator(oscillator_hz: u32) -> bool {
+    let prescaled_hz = 2 * TIME_QUANTA_PER_BIT * BIT_RATE;
+    oscillator_hz % prescaled_hz == 0 && oscillator_hz / prescaled_hz <= 64
+}
+
+/// An MCP2515 on an SPI bus, with its own chip select pin.
+///
+/// The MCP2515 is configured by [`Self::start`], so that creating the driver doesn’t touch the hardware.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of SPI bus and chip select pin used. Its parameters additionally include their common error type (which some functions also return).
+pub struct Mcp2515<Error, Bus: SpiBus<Error = Error>, ChipSelect: OutputPin<Error = Error>> {
+    bus: Bus,
+    chip_select: ChipSelect,
+    oscillator_hz: u32,
+}
+
+impl<Error, Bus: SpiBus<Error = Error>, ChipSelect: OutputPin<Error = Error>>
+    Mcp2515<Error, Bus, ChipSelect>
+{
+    /// Creates a driver for the MCP2515 with the given oscillator frequency (see [`supports_oscillator`]), whose SPI inputs are connected to the bus, and whose CS input is connected to the given pin.
+    pub fn new(bus: Bus, chip_select: ChipSelect, oscillator_hz: u32) -> Self {
+        Self {
+            bus,
+            chip_select,
+            oscillator_hz,
+        }
+    }
+
+    /// Resets the MCP2515 and starts receiving and sending frames. Returns false if the MCP2515 didn’t respond, e.g. because it is not connected.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s SPI and digital I/O functions.
+    pub fn start(&mut self, delay: &mut impl DelayNs) -> Result<bool, Error> {
+        self.chip_select.set_high()?;
+        self.transfer(&mut [RESET])?;
+        // the oscillator needs 128 cycles to start.
+        delay.delay_us(100);
+        let mut canstat = [READ, CANSTAT, 0];
+        self.transfer(&mut canstat)?;
+        if canstat[2] & MODE_MASK != MODE_CONFIGURATION {
+            return Ok(false);
+        }
+        // a bit has a synchronization segment of 1, a propagation segment of 2 and phase segments of 7 and 6 time quanta, and is sampled at 62.5 %.
+        let prescaler = (self.oscillator_hz / (2 * TIME_QUANTA_PER_BIT * BIT_RATE) - 1) as u8;
+        // CNF3 to CANINTE follow each other, and interrupts stay disabled.
+        self.transfer(&mut [WRITE, CNF3, 0x05, 0xb1, prescaler, 0x00])?;
+        // both receive buffers accept every frame, and the first one rolls over into the second one.
+        self.transfer(&mut [WRITE, RXB0CTRL, 0x64])?;
+        self.transfer(&mut [WRITE, RXB1CTRL, 0x60])?;
+        self.transfer(&mut [WRITE, CANCTRL, 0x00])?;
+        Ok(true)
+    }
+
+    /// Returns whether a frame can be sent, i.e. whether the previous one was sent.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s SPI and digital I/O functions.
+    pub fn is_ready_to_send(&mut self) -> Result<bool, Error> {
+        Ok(self.read_status()? & STATUS_TXB0REQ == 0)
+    }
+
+    /// Sends a frame, which should only be done if [`Self::is_ready_to_send`]; otherwise, the previous frame is overwritten if it wasn’t sent yet.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s SPI and digital I/O functions.
+    pub fn send(&mut self, frame: &CanFrame) -> Result<(), Error> {
+        let mut transaction: ArrayVec<u8, 15> = ArrayVec::new();
+        transaction.extend([WRITE, TXB0SIDH]);
+        transaction.extend([
+            (frame.id >> 21) as u8,
+            (frame.id >> 13) as u8 & 0xe0 | SIDL_EXIDE | (frame.id >> 16) as u8 & 0x03,
+            (frame.id >> 8) as u8,
+            frame.id as u8,
+            frame.data.len() as u8,
+        ]);
+        transaction.extend(frame.data.iter().copied());
+        self.transfer(&mut transaction)?;
+        self.transfer(&mut [REQUEST_TO_SEND_TXB0])
+    }
+
+    /// Returns the oldest received frame, if any. Frames with standard identifiers are skipped.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s SPI and digital I/O functions.
+    pub fn receive(&mut self) -> Result<Option<CanFrame>, Error> {
+        loop {
+            let status = self.read_status()?;
+            let instruction = if status & STATUS_RX0IF != 0 {
+                READ_RXB0
+            } else if status & STATUS_RX1IF != 0 {
+                READ_RXB1
+            } else {
+                return Ok(None);
+            };
+            // reading the buffer releases it for the next frame.
+            let mut buffer = [0; 14];
+            buffer[0] = instruction;
+            self.transfer(&mut buffer)?;
+            let [_, sidh, sidl, eid8, eid0, dlc, ref data @ ..] = buffer;
+            if sidl & SIDL_EXIDE == 0 {
+                continue;
+            }
+            let id = u32::from(sidh) << 21
+                | u32::from(sidl & 0xe0) << 13
+                | u32::from(sidl & 0x03) << 16
+                | u32::from(eid8) << 8
+                | u32::from(eid0);
+            let length = usize::from(dlc & 0x0f).min(data.len());
+            return Ok(Some(CanFrame::new(id, &data[..length])));
+        }
+    }
+
+    fn read_status(&mut self) -> Result<u8, Error> {
+        let mut status = [READ_STATUS, 0];
+        self.transfer(&mut status)?;
+        Ok(status[1])
+    }
+
+    /// Runs an SPI transaction, in which the bytes are sent and replaced by the received ones.
+    fn transfer(&mut self, bytes: &mut [u8]) -> Result<(), Error> {
+        self.chip_select.set_low()?;
+        let result = self
+            .bus
+            .transfer_in_place(bytes)
+            .and_then(|()| self.bus.flush());
+        self.chip_select.set_high()?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use embedded_hal::spi::ErrorType;
+    use embedded_hal::spi::SpiBus;
+
+    use super::supports_oscillator;
+    use super::CanFrame;
+    use super::Mcp2515;
+    use crate::mock::MockDelay;
+    use crate::mock::MockPin;
+    use crate::mock::MockPins;
+
+    /// An MCP2515 that implements the instructions used by the driver, and treats every transfer as a transaction.
+    struct FakeMcp2515 {
+        registers: [u8; 128],
+    }
+
+    impl ErrorType for FakeMcp2515 {
+        type Error = Infallible;
+    }
+
+    impl SpiBus for FakeMcp2515 {
+        fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+            match words[0] {
+                0xc0 => self.registers[0x0e] = 0x80,
+                0x03 => {
+                    let address = usize::from(words[1]);
+                    let length = words.len() - 2;
+                    words[2..].copy_from_slice(&self.registers[address..address + length]);
+                }
+                0x02 => {
+                    let address = usize::from(words[1]);
+                    self.registers[address..address + words.len() - 2].copy_from_slice(&words[2..]);
+                }
+                0xa0 => {
+                    let flags = self.registers[0x2c];
+                    words[1] = flags & 0x03 | (self.registers[0x30] & 0x08) >> 1;
+                }
+                0x81 => self.registers[0x30] |= 0x08,
+                instruction @ (0x90 | 0x94) => {
+                    let buffer = if instruction == 0x90 { 0x61 } else { 0x71 };
+                    words[1..].copy_from_slice(&self.registers[buffer..buffer + 13]);
+                    self.registers[0x2c] &= if instruction == 0x90 { !0x01 } else { !0x02 };
+                }
+                _ => unimplemented!(),
+            }
+            Ok(())
+        }
+
+        fn read(&mut self, _words: &mut [u8]) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
+        fn write(&mut self, _words: &[u8]) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
+        fn transfer(&mut self, _read: &mut [u8], _write: &[u8]) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn configures_the_controller_and_exchanges_frames() {
+        let pins = MockPins::new();
+        let mut controller: Mcp2515<Infallible, FakeMcp2515, MockPin> = Mcp2515::new(
+            FakeMcp2515 {
+                registers: [0; 128],
+            },
+            pins.pin(),
+            8_000_000,
+        );
+        assert_eq!(controller.start(&mut MockDelay::default()), Ok(true));
+        assert_eq!(
+            controller.bus.registers[0x28..0x2c],
+            [0x05, 0xb1, 0x01, 0x00]
+        );
+        assert_eq!(controller.bus.registers[0x60], 0x64);
+        assert!(pins.states()[0]);
+
+        let frame = CanFrame::new(0x1950_0abc, &[1, 2, 3]);
+        assert_eq!(controller.is_ready_to_send(), Ok(true));
+        controller.send(&frame).unwrap();
+        assert_eq!(
+            controller.bus.registers[0x30..0x39],
+            [0x08, 0xca, 0x88, 0x0a, 0xbc, 3, 1, 2, 3]
+        );
+        assert_eq!(controller.is_ready_to_send(), Ok(false));
+
+        // a standard frame in the first buffer, and an extended frame in the second one.
+        controller.bus.registers[0x61..0x63].copy_from_slice(&[0x12, 0x20]);
+        controller.bus.registers[0x71..0x78].copy_from_slice(&[0xca, 0x88, 0x0a, 0xbc, 2, 4, 5]);
+        controller.bus.registers[0x2c] = 0x03;
+        assert_eq!(
+            controller.receive(),
+            Ok(Some(CanFrame::new(0x1950_0abc, &[4, 5])))
+        );
+        assert_eq!(controller.receive(), Ok(None));
+        assert!(supports_oscillator(16_000_000));
+        assert!(!supports_oscillator(10_000_000));
+    }
+}
@@ -0,0 +1,64 @@
+//! Module for the human-readable texts in error responses.
+//!
+//! The language is selected at compile time: English by default, German with the `lang-de` feature. With the `terse-errors` feature, no texts are compiled in at all and error responses only contain the error code, which saves flash memory.
+
+#[cfg(not(any(feature = "lang-de", feature = "terse-errors")))]
+mod catalog {
+    pub const MALFORMED_LINE: &str = "Malformed line";
+    pub const MISSING_SIGNAL_ID: &str = "Missing signal ID";
+    pub const MISSING_COMMAND: &str = "Missing command";
+    pub const UNKNOWN_COMMAND: &str = "Unknown command";
+    pub const INVALID_RAW_LAMP_COMMAND: &str = "Invalid raw lamp command";
+    pub const INVALID_ARM_COMMAND: &str = "Invalid arm command";
+    pub const INVALID_NEXT_ASPECT_COMMAND: &str = "Invalid next aspect command";
+    pub const INVALID_SPEED_OR_ROUTE: &str = "Invalid speed or route";
+    pub const INVALID_STRESS_TEST_COMMAND: &str = "Invalid stress test command";
+    pub const INVALID_LEVEL_CROSSING_COMMAND: &str = "Invalid level crossing command";
+    pub const INVALID_CLONE_COMMAND: &str = "Invalid clone command";
+    pub const UNKNOWN_CONFIG_OPTION: &str = "Unknown config option";
+    pub const INVALID_CONFIG_VALUE: &str = "Invalid config value";
+    pub const VALUE_OUT_OF_RANGE: &str = "Value out of range";
+    pub const CHECKSUM_MISMATCH: &str = "Checksum mismatch";
+    pub const CHECKSUM_MISSING: &str = "Checksum missing";
+    pub const UNSUPPORTED: &str = "Not supported by this signal";
+    pub const FELL_BACK_TO_STOP: &str = "Electrical failure, fell back to stop";
+    pub const FELL_BACK_TO_DARK: &str = "Electrical failure, signal dark";
+    pub const LOCKED: &str = "Locked for maintenance";
+    pub const AUTHENTICATION_FAILED: &str = "Authentication failed";
+    pub const NOT_ARMED: &str = "Not armed";
+    pub const EMERGENCY_STOP: &str = "Emergency stop latched";
+    pub const BUSY: &str = "Busy, too many aspects waiting";
+    pub const STOP_HELD: &str = "Stop held, too early to clear";
+}
+
+#[cfg(all(feature = "lang-de", not(feature = "terse-errors")))]
+mod catalog {
+    pub const MALFORMED_LINE: &str = "Fehlerhafte Zeile";
+    pub const MISSING_SIGNAL_ID: &str = "Signal-ID fehlt";
+    pub const MISSING_COMMAND: &str = "Befehl fehlt";
+    pub const UNKNOWN_COMMAND: &str = "Unbekannter Befehl";
+    pub const INVALID_RAW_LAMP_COMMAND: &str = "Ungültiger Lampenbefehl";
+    pub const INVALID_ARM_COMMAND: &str = "Ungültiger Vorbereitungsbefehl";
+    pub const INVALID_NEXT_ASPECT_COMMAND: &str = "Ungültiger Folgesignalbefehl";
+    pub const INVALID_SPEED_OR_ROUTE: &str = "Ungültige Geschwindigkeit oder Richtung";
+    pub const INVALID_STRESS_TEST_COMMAND: &str = "Ungültiger Belastungstestbefehl";
+    pub const INVALID_LEVEL_CROSSING_COMMAND: &str = "Ungültiger Bahnübergangsbefehl";
+    pub const INVALID_CLONE_COMMAND: &str = "Ungültiger Klonbefehl";
+    pub const UNKNOWN_CONFIG_OPTION: &str = "Unbekannte Konfigurationsoption";
+    pub const INVALID_CONFIG_VALUE: &str = "Ungültiger Konfigurationswert";
+    pub const VALUE_OUT_OF_RANGE: &str = "Wert außerhalb des gültigen Bereichs";
+    pub const CHECKSUM_MISMATCH: &str = "Falsche Prüfsumme";
+    pub const CHECKSUM_MISSING: &str = "Prüfsumme fehlt";
+    pub const UNSUPPORTED: &str = "Von diesem Signal nicht unterstützt";
+    pub const FELL_BACK_TO_STOP: &str = "Elektrischer Fehler, auf Halt zurückgefallen";
+    pub const FELL_BACK_TO_DARK: &str = "Elektrischer Fehler, Signal dunkel";
+    pub const LOCKED: &str = "Für Wartung gesperrt";
+    pub const AUTHENTICATION_FAILED: &str = "Authentifizierung fehlgeschlagen";
+    pub const NOT_ARMED: &str = "Nicht vorbereitet";
+    pub const EMERGENCY_STOP: &str = "Nothalt eingerastet";
+    pub const BUSY: &str = "Beschäftigt, zu viele Signalbilder warten";
+    pub const STOP_HELD: &str = "Halt gehalten, Fahrtstellung zu früh";
+}
+
+#[cfg(not(feature = "terse-errors"))]
+pub use catalog::*;
@@ -0,0 +1,255 @@
+//! Module for mock implementations of the hardware traits, so that the signal logic can be tested on the host.
+
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::ErrorType;
+use embedded_hal::digital::InputPin;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::pwm;
+use embedded_hal::pwm::SetDutyCycle;
+
+use crate::bank::OutputBank;
+use crate::platform::Platform;
+use crate::random::Rng;
+use crate::semaphore::SERVO_PERIOD_US;
+
+#[derive(Default)]
+struct PinStates {
+    current: Vec<bool>,
+    history: Vec<Vec<bool>>,
+}
+
+/// A set of mock output pins, which records the states of all pins after every change.
+///
+/// Pins are numbered in the order they are created, starting at 0, and are initially low.
+#[derive(Clone, Default)]
+pub struct MockPins(Rc<RefCell<PinStates>>);
+
+impl MockPins {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new pin in this set.
+    pub fn pin(&self) -> MockPin {
+        let mut states = self.0.borrow_mut();
+        states.current.push(false);
+        MockPin {
+            index: states.current.len() - 1,
+            pins: self.clone(),
+        }
+    }
+
+    /// Returns another handle to an existing pin, e.g. for reading back a pin that the test drives through the first handle.
+    pub fn pin_at(&self, index: usize) -> MockPin {
+        assert!(index < self.0.borrow().current.len());
+        MockPin {
+            index,
+            pins: self.clone(),
+        }
+    }
+
+    /// Returns the current states of all pins (true meaning high).
+    pub fn states(&self) -> Vec<bool> {
+        self.0.borrow().current.clone()
+    }
+
+    /// Returns the states of all pins after every change since the history was last cleared, oldest first.
+    pub fn history(&self) -> Vec<Vec<bool>> {
+        self.0.borrow().history.clone()
+    }
+
+    pub fn clear_history(&self) {
+        self.0.borrow_mut().history.clear();
+    }
+}
+
+/// The first eight pins of the set form an output bank, whose bits are the pins in the order they were created. A write is recorded as a single change.
+impl OutputBank for MockPins {
+    type Error = Infallible;
+
+    fn write(&mut self, mask: u8, states: u8) -> Result<(), Self::Error> {
+        let mut pin_states = self.0.borrow_mut();
+        for (bit, state) in pin_states.current.iter_mut().take(8).enumerate() {
+            if mask & 1 << bit != 0 {
+                *state = states & 1 << bit != 0;
+            }
+        }
+        let current = pin_states.current.clone();
+        pin_states.history.push(current);
+        Ok(())
+    }
+}
+
+/// A single pin of a [`MockPins`] set.
+pub struct MockPin {
+    index: usize,
+    pins: MockPins,
+}
+
+impl MockPin {
+    fn set(&mut self, state: bool) {
+        let mut states = self.pins.0.borrow_mut();
+        states.current[self.index] = state;
+        let current = states.current.clone();
+        states.history.push(current);
+    }
+}
+
+impl ErrorType for MockPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for MockPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set(true);
+        Ok(())
+    }
+}
+
+/// Reading a mock pin returns its current state, so that tests can drive an input through another handle to the same pin.
+impl InputPin for MockPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.pins.0.borrow().current[self.index])
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.is_high().map(|is_high| !is_high)
+    }
+}
+
+/// A set of mock PWM outputs for servos, whose duty cycles are recorded.
+///
+/// Outputs are numbered in the order they are created, starting at 0. Their maximum duty cycle is the servo period, so that a duty cycle is a pulse width in microseconds.
+#[derive(Clone, Default)]
+pub struct MockPwms(Rc<RefCell<Vec<u16>>>);
+
+impl MockPwms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new output in this set, which is initially off.
+    pub fn pwm(&self) -> MockPwm {
+        let mut duty_cycles = self.0.borrow_mut();
+        duty_cycles.push(0);
+        MockPwm {
+            index: duty_cycles.len() - 1,
+            pwms: self.clone(),
+        }
+    }
+
+    /// Returns the current duty cycles of all outputs.
+    pub fn duty_cycles(&self) -> Vec<u16> {
+        self.0.borrow().clone()
+    }
+}
+
+/// A single output of a [`MockPwms`] set.
+pub struct MockPwm {
+    index: usize,
+    pwms: MockPwms,
+}
+
+impl pwm::ErrorType for MockPwm {
+    type Error = Infallible;
+}
+
+impl SetDutyCycle for MockPwm {
+    fn max_duty_cycle(&self) -> u16 {
+        SERVO_PERIOD_US
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        self.pwms.0.borrow_mut()[self.index] = duty;
+        Ok(())
+    }
+}
+
+/// A delay that returns immediately, but keeps track of the total time it should have waited.
+#[derive(Default)]
+pub struct MockDelay {
+    pub total_ns: u64,
+}
+
+impl DelayNs for MockDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.total_ns += u64::from(ns);
+    }
+}
+
+/// A platform whose persistent storage is a vector, and which receives the bytes that the test queues.
+pub struct MockPlatform {
+    pub storage: Vec<u8>,
+    pub received: Vec<u8>,
+    pub watchdog_feeds: usize,
+}
+
+impl Default for MockPlatform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockPlatform {
+    /// Creates a platform whose storage is erased, i.e. contains only 0xff bytes like a new EEPROM.
+    pub fn new() -> Self {
+        Self {
+            storage: vec![0xff; Self::PERSISTENT_SIZE.into()],
+            received: Vec::new(),
+            watchdog_feeds: 0,
+        }
+    }
+}
+
+impl Platform for MockPlatform {
+    const PERSISTENT_SIZE: u16 = 1024;
+
+    fn read_persistent(&mut self, offset: u16, data: &mut [u8]) {
+        let offset = usize::from(offset);
+        data.copy_from_slice(&self.storage[offset..offset + data.len()]);
+    }
+
+    fn write_persistent(&mut self, offset: u16, data: &[u8]) {
+        let offset = usize::from(offset);
+        self.storage[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    fn feed_watchdog(&mut self) {
+        self.watchdog_feeds += 1;
+    }
+
+    fn sleep(&mut self) {}
+
+    fn receive(&mut self, received: impl FnMut(u8)) {
+        self.received.drain(..).for_each(received);
+    }
+}
+
+/// A random number generator that returns the given numbers in order, starting over after the last one.
+pub struct MockRng {
+    numbers: Vec<u32>,
+    next: usize,
+}
+
+impl MockRng {
+    pub fn new(numbers: Vec<u32>) -> Self {
+        Self { numbers, next: 0 }
+    }
+}
+
+impl Rng for MockRng {
+    fn next_u32(&mut self) -> u32 {
+        let number = self.numbers[self.next % self.numbers.len()];
+        self.next += 1;
+        number
+    }
+}
@@ -0,0 +1,210 @@
+//! Module for decoding the accessory commands of a Märklin Motorola (MM) signal, for layouts that are still run with Märklin keyboards instead of a DCC command station.
+//!
+//! MM sends every bit as a pulse of the track signal followed by a pause, which together last 104 µs for accessory commands: a one is a long pulse and a short pause, and a zero a short pulse and a long pause. Locomotive commands are sent at half the rate, so their long pulses are too long for accessory commands, and they are ignored. A packet consists of nine trits, each sent as two bits, and ends with a gap of at least three bit times. Every packet is sent twice in a row, and only accepted if both copies match.
+
+use core::ops::RangeInclusive;
+
+use crate::dcc::AccessoryCommand;
+
+/// Durations of the short and long halves of a bit that are accepted, in microseconds. They are nominally 13 µs and 91 µs, and are widened by the resolution of the controller’s timestamps and by its interrupt latency.
+const SHORT_HALF_BIT_US: RangeInclusive<u32> = 1..=40;
+const LONG_HALF_BIT_US: RangeInclusive<u32> = 65..=120;
+/// Minimum duration of the gap after a packet, together with the pause of its last bit.
+const MIN_GAP_US: u32 = 200;
+/// Number of bits of a packet.
+const PACKET_BITS: u8 = 18;
+/// Number of decoder addresses. Decoder 80 is sent as address 0, and the address with all trits open is unused.
+const DECODER_ADDRESSES: u16 = 80;
+
+/// Parses the bits of a packet, the first bit in the lowest bit, and returns its accessory command, if it is one. The address of the command is the output pair as numbered on the keyboards, from 1 to 320.
+fn parse(bits: u32) -> Option<AccessoryCommand> {
+    let trit = |index: u32| bits >> (2 * index) & 0b11;
+    // the address trits are sent with the least significant first, as 00 (0), 11 (1) or 10 (open, 2).
+    let mut decoder = 0;
+    for index in (0..4).rev() {
+        decoder = decoder * 3
+            + match trit(index) {
+                0b00 => 0,
+                0b11 => 1,
+                0b01 => 2,
+                _ => return None,
+            };
+    }
+    // the fifth trit distinguishes locomotive functions, and is always 0 for accessories.
+    if trit(4) != 0b00 {
+        return None;
+    }
+    // the remaining bits are sent twice each: three bits select one of the decoder’s eight outputs, and the last bit switches it on or off.
+    let mut data = 0;
+    for index in 0..4 {
+        match trit(5 + index) {
+            0b00 => {}
+            0b11 => data |= 1 << index,
+            _ => return None,
+        }
+    }
+    let decoder = match decoder {
+        0 => DECODER_ADDRESSES,
+        DECODER_ADDRESSES => return None,
+        decoder => decoder,
+    };
+    Some(AccessoryCommand::Basic {
+        address: (decoder - 1) * 4 + (data & 0b110) / 2 + 1,
+        is_green: data & 0b001 != 0,
+        is_active: data & 0b1000 != 0,
+    })
+}
+
+/// Decodes an MM signal from the durations between its edges.
+pub struct MotorolaDecoder {
+    // Number of edges since the last gap, which alternate between the ends of the pulse and the pause of each bit.
+    edges: u8,
+    // Received bits of the current packet, the first bit in the lowest bit.
+    bits: u32,
+    // The previous packet, which the current one has to repeat.
+    previous_packet: Option<u32>,
+}
+
+impl Default for MotorolaDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MotorolaDecoder {
+    pub const fn new() -> Self {
+        Self {
+            edges: 0,
+            bits: 0,
+            previous_packet: None,
+        }
+    }
+
+    /// Processes the duration since the previous edge of the signal, in microseconds, and returns the accessory command that it completes, if any. Should be called on every edge.
+    pub fn receive_edge(&mut self, duration_us: u32) -> Option<AccessoryCommand> {
+        if duration_us >= MIN_GAP_US {
+            // the pause of the last bit is part of the gap.
+            let packet = (self.edges == PACKET_BITS * 2 - 1).then_some(self.bits);
+            let previous_packet = core::mem::replace(&mut self.previous_packet, packet);
+            self.edges = 0;
+            self.bits = 0;
+            if packet.is_some() && packet == previous_packet {
+                // a third copy must not complete another command.
+                self.previous_packet = None;
+                return parse(packet?);
+            }
+            return None;
+        }
+        let is_long = if LONG_HALF_BIT_US.contains(&duration_us) {
+            true
+        } else if SHORT_HALF_BIT_US.contains(&duration_us) {
+            false
+        } else {
+            // noise, locomotive commands or no MM signal at all.
+            *self = Self::new();
+            return None;
+        };
+        let bit = u32::from(self.edges / 2);
+        if self.edges % 2 == 0 {
+            self.bits |= u32::from(is_long) << bit;
+        } else if is_long == (self.bits >> bit & 1 != 0) {
+            // the pause must complement the pulse to a whole bit.
+            *self = Self::new();
+            return None;
+        }
+        self.edges += 1;
+        if self.edges >= PACKET_BITS * 2 {
+            *self = Self::new();
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MotorolaDecoder;
+    use crate::dcc::AccessoryCommand;
+
+    /// Returns the durations between the edges of a packet with the given address trits (0, 1 or 2 for open) and data bits, followed by its gap.
+    fn encode(address: [u8; 4], data: [bool; 4]) -> Vec<u32> {
+        let mut bits = Vec::new();
+        for trit in address {
+            bits.extend(match trit {
+                0 => [false, false],
+                1 => [true, true],
+                _ => [true, false],
+            });
+        }
+        bits.extend([false, false]);
+        for bit in data {
+            bits.extend([bit, bit]);
+        }
+        let mut durations: Vec<u32> = bits
+            .into_iter()
+            .flat_map(|is_one| if is_one { [92, 12] } else { [12, 92] })
+            .collect();
+        *durations.last_mut().unwrap() += 312;
+        durations
+    }
+
+    fn decode(decoder: &mut MotorolaDecoder, durations: &[u32]) -> Vec<AccessoryCommand> {
+        durations
+            .iter()
+            .filter_map(|duration| decoder.receive_edge(*duration))
+            .collect()
+    }
+
+    #[test]
+    fn decodes_repeated_accessory_packets() {
+        let mut decoder = MotorolaDecoder::new();
+        // decoder 1, output 1 (green of the first pair), on.
+        let packet = encode([1, 0, 0, 0], [true, false, false, true]);
+        assert!(decode(&mut decoder, &[[400].as_slice(), &packet].concat()).is_empty());
+        assert_eq!(
+            decode(&mut decoder, &[packet.as_slice(), &packet].concat()),
+            [AccessoryCommand::Basic {
+                address: 1,
+                is_green: true,
+                is_active: true
+            }]
+        );
+        // decoder 80 is sent as 0, output 6 (red of the fourth pair), off.
+        let packet = encode([0, 0, 0, 0], [false, true, true, false]);
+        assert_eq!(
+            decode(&mut decoder, &[[400].as_slice(), &packet, &packet].concat()),
+            [AccessoryCommand::Basic {
+                address: 320,
+                is_green: false,
+                is_active: false
+            }]
+        );
+        // decoder 5 has the trits 2, 1.
+        let packet = encode([2, 1, 0, 0], [false, true, false, true]);
+        assert_eq!(
+            decode(&mut decoder, &[[400].as_slice(), &packet, &packet].concat()),
+            [AccessoryCommand::Basic {
+                address: 18,
+                is_green: false,
+                is_active: true
+            }]
+        );
+    }
+
+    #[test]
+    fn ignores_single_and_invalid_packets() {
+        let mut decoder = MotorolaDecoder::new();
+        let first = encode([1, 0, 0, 0], [true, false, false, true]);
+        let second = encode([2, 0, 0, 0], [true, false, false, true]);
+        assert!(decode(&mut decoder, &[[400].as_slice(), &first, &second].concat()).is_empty());
+        // locomotive commands have twice the durations.
+        let locomotive: Vec<u32> = first.iter().map(|duration| duration * 2).collect();
+        assert!(decode(&mut decoder, &[locomotive.as_slice(), &locomotive].concat()).is_empty());
+        // a pause that doesn’t fit its pulse.
+        let mut broken = first.clone();
+        broken[1] = 92;
+        assert!(decode(&mut decoder, &[[400].as_slice(), &broken, &broken].concat()).is_empty());
+        // all trits open is no decoder.
+        let unused = encode([2, 2, 2, 2], [true, false, false, true]);
+        assert!(decode(&mut decoder, &[[400].as_slice(), &unused, &unused].concat()).is_empty());
+    }
+}
@@ -0,0 +1,399 @@
+//! Module for MQTT through an ESP8266 module with the ESP-AT firmware, e.g. an ESP-01, which joins the WLAN and connects to the broker, and which the signal controls with AT commands on a serial port.
+//!
+//! Every AT command is a line that ends with CR LF, which the module answers with a line `OK`, or `ERROR` or `FAIL` if it failed. Messages of subscribed topics arrive as lines `+MQTTSUBRECV:0,"topic",length,data`, and a lost connection to the broker or the WLAN as `+MQTTDISCONNECTED:0` or `WIFI DISCONNECT`. Quotes, commas and backslashes in the parameters of a command are escaped with a backslash.
+//!
+//! The signal subscribes to the topic `signals/[Signal ID]/set`, whose messages are the command IDs of aspects, and publishes the state that it shows to `signals/[Signal ID]/state` as a retained message, whenever it changes and after every message, which acknowledges it.
+
+use arrayvec::ArrayVec;
+use ufmt::uDisplay;
+use ufmt::uWrite;
+use ufmt::Formatter;
+
+use crate::commands::AspectCommand;
+
+/// Baud rate of the serial port of the module.
+pub const BAUD_RATE: u32 = 115_200;
+/// How long the module may take to answer a command, in milliseconds. Joining a WLAN takes several seconds.
+const REPLY_TIMEOUT_MS: u32 = 20_000;
+/// How long to wait before starting over after a failure, in milliseconds.
+const RETRY_INTERVAL_MS: u32 = 5_000;
+/// Longest line that is received. Longer lines are ignored.
+const MAX_LINE_LENGTH: usize = 64;
+const SUBSCRIPTION_PREFIX: &[u8] = b"+MQTTSUBRECV:0,\"";
+
+/// The WLAN and the broker that the module connects to.
+pub struct MqttSettings {
+    pub wlan_ssid: &'static str,
+    pub wlan_password: &'static str,
+    pub broker_host: &'static str,
+    pub broker_port: u16,
+}
+
+/// Something received from the broker that the signal handles.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MqttEvent {
+    /// A message on the topic of the signal commanded an aspect.
+    Command(AspectCommand),
+    /// The connection to the broker was lost, so the signal no longer gets the commands of the layout software.
+    BrokerLost,
+}
+
+/// Steps of connecting to the broker, and publishing once connected.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Step {
+    DisableEcho,
+    StationMode,
+    JoinWlan,
+    // Closes the connection of an earlier attempt, which fails if there is none.
+    Clean,
+    UserConfig,
+    Connect,
+    Subscribe,
+    Publish,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Link {
+    // The next command can be sent.
+    Idle,
+    // A command was sent at the given time, and its answer is pending.
+    Awaiting(u32),
+    // The last command failed, and the client starts over after RETRY_INTERVAL_MS.
+    Failed,
+    Waiting(u32),
+}
+
+/// An AT command for the module, which is sent by formatting it.
+pub struct AtCommand {
+    step: Step,
+    signal_id: &'static str,
+    settings: &'static MqttSettings,
+    state: &'static str,
+}
+
+/// Writes a parameter of an AT command in quotes.
+fn write_quoted<W: uWrite + ?Sized>(f: &mut Formatter<'_, W>, value: &str) -> Result<(), W::Error> {
+    f.write_char('"')?;
+    for part in value.split_inclusive(['"', ',', '\\']) {
+        match part.char_indices().last() {
+            Some((index, special @ ('"' | ',' | '\\'))) => {
+                f.write_str(&part[..index])?;
+                f.write_char('\\')?;
+                f.write_char(special)?;
+            }
+            _ => f.write_str(part)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl uDisplay for AtCommand {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        match self.step {
+            Step::DisableEcho => f.write_str("ATE0")?,
+            Step::StationMode => f.write_str("AT+CWMODE=1")?,
+            Step::JoinWlan => {
+                f.write_str("AT+CWJAP=")?;
+                write_quoted(f, self.settings.wlan_ssid)?;
+                f.write_char(',')?;
+                write_quoted(f, self.settings.wlan_password)?;
+            }
+            Step::Clean => f.write_str("AT+MQTTCLEAN=0")?,
+            // the client ID is the topic prefix, which is unique on the layout.
+            Step::UserConfig => ufmt::uwrite!(
+                f,
+                "AT+MQTTUSERCFG=0,1,\"signal-{}\",\"\",\"\",0,0,\"\"",
+                self.signal_id
+            )?,
+            Step::Connect => {
+                f.write_str("AT+MQTTCONN=0,")?;
+                write_quoted(f, self.settings.broker_host)?;
+                ufmt::uwrite!(f, ",{},0", self.settings.broker_port)?;
+            }
+            Step::Subscribe => {
+                ufmt::uwrite!(f, "AT+MQTTSUB=0,\"signals/{}/set\",1", self.signal_id)?
+            }
+            Step::Publish => {
+                ufmt::uwrite!(f, "AT+MQTTPUB=0,\"signals/{}/state\",", self.signal_id)?;
+                write_quoted(f, self.state)?;
+                f.write_str(",1,1")?;
+            }
+        }
+        f.write_str("\r\n")
+    }
+}
+
+/// An MQTT client that controls the module. Bytes from the module are received with [`Self::receive_byte`], events are taken with [`Self::take_event`], and the commands for the module are sent by polling [`Self::next_command`].
+pub struct MqttClient {
+    signal_id: &'static str,
+    settings: &'static MqttSettings,
+    step: Step,
+    link: Link,
+    // Whether the client subscribed to the topic of the signal and may publish.
+    is_connected: bool,
+    // The state shown by the signal, whether it still must be published, and whether a message must be acknowledged once it was handled.
+    state: Option<&'static str>,
+    needs_publish: bool,
+    needs_acknowledgement: bool,
+    event: Option<MqttEvent>,
+    // The line received so far, or None if it is too long.
+    line: Option<ArrayVec<u8, MAX_LINE_LENGTH>>,
+}
+
+impl MqttClient {
+    pub fn new(signal_id: &'static str, settings: &'static MqttSettings) -> Self {
+        Self {
+            signal_id,
+            settings,
+            step: Step::DisableEcho,
+            link: Link::Idle,
+            is_connected: false,
+            state: None,
+            needs_publish: false,
+            needs_acknowledgement: false,
+            event: None,
+            line: Some(ArrayVec::new()),
+        }
+    }
+
+    /// Processes a byte received from the module.
+    pub fn receive_byte(&mut self, byte: u8) {
+        if byte != b'\n' {
+            if self
+                .line
+                .as_mut()
+                .is_some_and(|line| line.try_push(byte).is_err())
+            {
+                self.line = None;
+            }
+            return;
+        }
+        if let Some(line) = self.line.replace(ArrayVec::new()) {
+            self.handle_line(line.strip_suffix(b"\r").unwrap_or(&line));
+        }
+    }
+
+    fn handle_line(&mut self, line: &[u8]) {
+        match line {
+            b"OK" if matches!(self.link, Link::Awaiting(_)) => {
+                self.link = Link::Idle;
+                self.step = match self.step {
+                    Step::DisableEcho => Step::StationMode,
+                    Step::StationMode => Step::JoinWlan,
+                    Step::JoinWlan => Step::Clean,
+                    Step::Clean => Step::UserConfig,
+                    Step::UserConfig => Step::Connect,
+                    Step::Connect => Step::Subscribe,
+                    Step::Subscribe | Step::Publish => {
+                        self.is_connected = true;
+                        Step::Publish
+                    }
+                };
+            }
+            b"ERROR" | b"FAIL" if matches!(self.link, Link::Awaiting(_)) => {
+                if self.step == Step::Clean {
+                    self.link = Link::Idle;
+                    self.step = Step::UserConfig;
+                } else {
+                    self.fail();
+                }
+            }
+            // the module also leaves the WLAN while joining it again.
+            b"+MQTTDISCONNECTED:0" | b"WIFI DISCONNECT" if self.is_connected => self.fail(),
+            _ => {
+                if let Some(aspect) = line
+                    .strip_prefix(SUBSCRIPTION_PREFIX)
+                    .and_then(parse_message)
+                {
+                    self.event = Some(MqttEvent::Command(aspect));
+                    self.needs_acknowledgement = true;
+                }
+            }
+        }
+    }
+
+    /// Starts over after the connection was lost or a command failed.
+    fn fail(&mut self) {
+        if self.is_connected {
+            self.event = Some(MqttEvent::BrokerLost);
+        }
+        self.is_connected = false;
+        self.step = Step::DisableEcho;
+        self.link = Link::Failed;
+        // the retained state may be stale once the client is connected again.
+        self.needs_publish = self.state.is_some();
+    }
+
+    /// Returns what was received from the broker since the last call, if anything. A lost connection replaces an earlier command.
+    pub fn take_event(&mut self) -> Option<MqttEvent> {
+        self.event.take()
+    }
+
+    /// Records the state that the signal shows, which must be called after the commands were handled, so that it is published when it changed, or to acknowledge a message.
+    pub fn report_state(&mut self, state: &'static str) {
+        if self.state != Some(state) {
+            self.state = Some(state);
+            self.needs_publish = true;
+        }
+        if self.needs_acknowledgement && self.event.is_none() {
+            self.needs_acknowledgement = false;
+            self.needs_publish = true;
+        }
+    }
+
+    /// Returns the next command to send to the module, if any, which is considered sent. Should be called regularly with the current time in milliseconds, since the module may not answer.
+    pub fn next_command(&mut self, now_ms: u32) -> Option<AtCommand> {
+        if let Link::Awaiting(sent_at) = self.link {
+            if now_ms.wrapping_sub(sent_at) < REPLY_TIMEOUT_MS {
+                return None;
+            }
+            self.fail();
+        }
+        if self.link == Link::Failed {
+            self.link = Link::Waiting(now_ms);
+        }
+        if let Link::Waiting(failed_at) = self.link {
+            if now_ms.wrapping_sub(failed_at) < RETRY_INTERVAL_MS {
+                return None;
+            }
+            self.link = Link::Idle;
+        }
+        let state = match self.step {
+            Step::Publish if !self.needs_publish => return None,
+            Step::Publish => {
+                self.needs_publish = false;
+                self.state?
+            }
+            _ => "",
+        };
+        self.link = Link::Awaiting(now_ms);
+        Some(AtCommand {
+            step: self.step,
+            signal_id: self.signal_id,
+            settings: self.settings,
+            state,
+        })
+    }
+}
+
+/// Parses the rest of a `+MQTTSUBRECV` line after its prefix, which is the topic, the length and the data, and returns the aspect that the data commands. Only the topic of the signal is subscribed to.
+fn parse_message(rest: &[u8]) -> Option<AspectCommand> {
+    let topic_end = rest.iter().position(|byte| *byte == b'"')?;
+    let rest = rest[topic_end + 1..].strip_prefix(b",")?;
+    let length_end = rest.iter().position(|byte| *byte == b',')?;
+    let data = &rest[length_end + 1..];
+    let length = core::str::from_utf8(&rest[..length_end])
+        .ok()?
+        .parse::<usize>()
+        .ok()?;
+    if length != data.len() {
+        return None;
+    }
+    AspectCommand::from_command_id(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MqttClient;
+    use super::MqttEvent;
+    use super::MqttSettings;
+    use super::REPLY_TIMEOUT_MS;
+    use super::RETRY_INTERVAL_MS;
+    use crate::commands::AspectCommand;
+
+    static SETTINGS: MqttSettings = MqttSettings {
+        wlan_ssid: "Layout",
+        wlan_password: "a\"b,c\\d",
+        broker_host: "192.168.1.2",
+        broker_port: 1883,
+    };
+
+    /// Returns the next command as a line without its CR LF.
+    fn next_command(client: &mut MqttClient, now_ms: u32) -> Option<String> {
+        let command = client.next_command(now_ms)?;
+        let mut line = String::new();
+        ufmt::uwrite!(line, "{}", command).unwrap();
+        Some(line.strip_suffix("\r\n").unwrap().to_string())
+    }
+
+    fn receive(client: &mut MqttClient, line: &str) {
+        for byte in line.bytes().chain(*b"\r\n") {
+            client.receive_byte(byte);
+        }
+    }
+
+    /// Returns a client that is connected to the broker.
+    fn connected_client() -> MqttClient {
+        let mut client = MqttClient::new("F", &SETTINGS);
+        for expected in [
+            "ATE0",
+            "AT+CWMODE=1",
+            "AT+CWJAP=\"Layout\",\"a\\\"b\\,c\\\\d\"",
+            "AT+MQTTCLEAN=0",
+            "AT+MQTTUSERCFG=0,1,\"signal-F\",\"\",\"\",0,0,\"\"",
+            "AT+MQTTCONN=0,\"192.168.1.2\",1883,0",
+            "AT+MQTTSUB=0,\"signals/F/set\",1",
+        ] {
+            assert_eq!(next_command(&mut client, 0).as_deref(), Some(expected));
+            // one command at a time.
+            assert!(next_command(&mut client, 0).is_none());
+            // the clean-up fails without an earlier connection.
+            receive(
+                &mut client,
+                if expected == "AT+MQTTCLEAN=0" {
+                    "ERROR"
+                } else {
+                    "OK"
+                },
+            );
+        }
+        client
+    }
+
+    #[test]
+    fn connects_and_exchanges_the_state() {
+        let mut client = connected_client();
+        assert!(next_command(&mut client, 0).is_none());
+        client.report_state("0");
+        assert_eq!(
+            next_command(&mut client, 0).as_deref(),
+            Some("AT+MQTTPUB=0,\"signals/F/state\",\"0\",1,1")
+        );
+        receive(&mut client, "OK");
+        receive(&mut client, "+MQTTSUBRECV:0,\"signals/F/set\",1,1");
+        assert!(client.take_event() == Some(MqttEvent::Command(AspectCommand::One)));
+        // the message is acknowledged with the state that the signal shows afterwards, even if it didn’t change.
+        client.report_state("0");
+        assert_eq!(
+            next_command(&mut client, 0).as_deref(),
+            Some("AT+MQTTPUB=0,\"signals/F/state\",\"0\",1,1")
+        );
+        receive(&mut client, "OK");
+        // unknown aspects and wrong lengths are ignored.
+        receive(&mut client, "+MQTTSUBRECV:0,\"signals/F/set\",1,X");
+        receive(&mut client, "+MQTTSUBRECV:0,\"signals/F/set\",2,1");
+        assert!(client.take_event().is_none());
+        client.report_state("0");
+        assert!(next_command(&mut client, 0).is_none());
+    }
+
+    #[test]
+    fn reports_a_lost_broker_and_starts_over() {
+        let mut client = connected_client();
+        client.report_state("1");
+        assert!(next_command(&mut client, 0).is_some());
+        receive(&mut client, "OK");
+        receive(&mut client, "+MQTTDISCONNECTED:0");
+        assert!(client.take_event() == Some(MqttEvent::BrokerLost));
+        assert!(next_command(&mut client, 10).is_none());
+        assert_eq!(
+            next_command(&mut client, 10 + RETRY_INTERVAL_MS).as_deref(),
+            Some("ATE0")
+        );
+        // a module that doesn’t answer is a failure too, but only a lost connection to the broker is reported.
+        assert!(next_command(&mut client, 10 + RETRY_INTERVAL_MS + REPLY_TIMEOUT_MS).is_none());
+        assert!(client.take_event().is_none());
+        receive(&mut client, "WIFI DISCONNECT");
+        assert!(client.take_event().is_none());
+    }
+}
@@ -0,0 +1,472 @@
+//! Module for OpenLCB, which the NMRA standardized as Layout Command Control (LCC), and in which the signal is a node on a CAN bus that consumes an event for each aspect, and produces it when the aspect is shown.
+//!
+//! Every node has a unique 48-bit node ID, but CAN frames only carry a 12-bit alias of it, which the node reserves after booting: it sends four Check ID (CID) frames with the alias and its node ID, waits 200 ms for other nodes to object, and then sends Reserve ID (RID) and Alias Map Definition (AMD) frames. Only then may it send messages, starting with Initialization Complete. A node that sees another node use its alias must give it up and reserve a new one.
+//!
+//! Messages carry a 12-bit message type indicator (MTI) in the frame’s identifier, and addressed messages carry the alias of their destination in their first two data bytes. Events are 64-bit IDs without a destination: producers report them with a Producer/Consumer Event Report (PCER), and every node that consumes them acts on them. Tools like JMRI find the producers and consumers of events with Identify messages.
+
+use arrayvec::ArrayVec;
+
+use crate::commands::AspectCommand;
+use crate::mcp2515::CanFrame;
+
+/// How long other nodes may object to a new alias after its CID frames, in milliseconds.
+pub const ALIAS_RESERVATION_MS: u32 = 200;
+
+// Bits of the frame identifiers: bit 28 is always set, bit 27 marks OpenLCB messages, bits 26 to 24 are the frame type or the sequence number of a CID frame, and bits 23 to 12 are the MTI or the type of a control frame.
+const FRAME_RESERVED: u32 = 0x1000_0000;
+const FRAME_OPENLCB_MESSAGE: u32 = 0x0800_0000;
+const FRAME_TYPE_GLOBAL_OR_ADDRESSED: u32 = 1;
+const CONTROL_RID: u16 = 0x700;
+const CONTROL_AMD: u16 = 0x701;
+const CONTROL_AME: u16 = 0x702;
+const CONTROL_AMR: u16 = 0x703;
+
+// MTIs of the messages that the node sends or answers.
+const MTI_INITIALIZATION_COMPLETE: u16 = 0x100;
+const MTI_VERIFY_NODE_ID_ADDRESSED: u16 = 0x488;
+const MTI_VERIFY_NODE_ID_GLOBAL: u16 = 0x490;
+const MTI_VERIFIED_NODE_ID: u16 = 0x170;
+const MTI_OPTIONAL_INTERACTION_REJECTED: u16 = 0x068;
+const MTI_TERMINATE_DUE_TO_ERROR: u16 = 0x0a8;
+const MTI_PROTOCOL_SUPPORT_INQUIRY: u16 = 0x828;
+const MTI_PROTOCOL_SUPPORT_REPLY: u16 = 0x668;
+const MTI_IDENTIFY_CONSUMERS: u16 = 0x8f4;
+const MTI_CONSUMER_IDENTIFIED_VALID: u16 = 0x4c4;
+const MTI_CONSUMER_IDENTIFIED_INVALID: u16 = 0x4c5;
+const MTI_IDENTIFY_PRODUCERS: u16 = 0x914;
+const MTI_PRODUCER_IDENTIFIED_VALID: u16 = 0x544;
+const MTI_PRODUCER_IDENTIFIED_INVALID: u16 = 0x545;
+const MTI_IDENTIFY_EVENTS_ADDRESSED: u16 = 0x968;
+const MTI_IDENTIFY_EVENTS_GLOBAL: u16 = 0x970;
+const MTI_PRODUCER_CONSUMER_EVENT_REPORT: u16 = 0x5b4;
+/// Bit of the MTI that marks addressed messages.
+const MTI_ADDRESSED: u16 = 0x008;
+/// Protocols that the node supports, as reported in the Protocol Support Reply: only the event exchange.
+const SUPPORTED_PROTOCOLS: [u8; 6] = [0x04, 0, 0, 0, 0, 0];
+/// Error code of Optional Interaction Rejected for messages that the node doesn’t implement.
+const ERROR_UNKNOWN_MTI: [u8; 2] = [0x10, 0x43];
+/// Number of frames of the alias reservation and of Initialization Complete: four CID frames, RID, AMD and Initialization Complete.
+const RESERVATION_FRAMES: u8 = 7;
+
+/// Returns the next state of the pseudo-random generator of aliases from the OpenLCB standard.
+fn next_alias_generator((upper, lower): (u32, u32)) -> (u32, u32) {
+    let upper_step = (upper << 9 | lower >> 15 & 0x1ff) & 0xff_ffff;
+    let lower_step = lower << 9 & 0xff_ffff;
+    let lower = lower + lower_step + 0x7a_4ba9;
+    let upper = upper + upper_step + 0x1b_0ca3;
+    (
+        (upper & 0xff_ffff) + (lower >> 24 & 0xff),
+        lower & 0xff_ffff,
+    )
+}
+
+/// Returns the alias for a state of the generator, which is 0 if the state can’t be used.
+fn alias_of((upper, lower): (u32, u32)) -> u16 {
+    ((upper ^ lower ^ upper >> 12 ^ lower >> 12) & 0xfff) as u16
+}
+
+/// An OpenLCB node, which reserves its alias and answers the messages of other nodes, and exchanges the events of the signal’s aspects. Frames are received with [`Self::receive`] and sent by polling [`Self::next_frame`].
+///
+/// The events of the aspects start with the node ID, followed by a zero byte and the number of the aspect, so that JMRI can learn them for a signal mast.
+pub struct OpenLcbNode {
+    node_id: [u8; 6],
+    // Aspect of each event, numbered from 0.
+    aspects: &'static [Option<AspectCommand>],
+    // State of the pseudo-random generator of aliases, which is seeded with the node ID, so that the node gets the same alias after every boot, unless it is taken.
+    alias_generator: (u32, u32),
+    alias: u16,
+    // Number of frames of the reservation that were sent, which are complete at RESERVATION_FRAMES.
+    reservation_frames: u8,
+    // When the last CID frame was sent, in milliseconds.
+    checked_at: u32,
+    shown_aspect: Option<AspectCommand>,
+    // Position in the identification of all events after Identify Events, with two frames for each event, or None if no identification is pending.
+    identification: Option<usize>,
+    // Messages that are yet to be sent. Messages that don’t fit are dropped, and the requesting node asks again.
+    frames: ArrayVec<CanFrame, 4>,
+}
+
+impl OpenLcbNode {
+    pub fn new(node_id: [u8; 6], aspects: &'static [Option<AspectCommand>]) -> Self {
+        let mut node_id_bytes = [0; 8];
+        node_id_bytes[2..].copy_from_slice(&node_id);
+        let node_id_number = u64::from_be_bytes(node_id_bytes);
+        let mut alias_generator = (
+            (node_id_number >> 24) as u32 & 0xff_ffff,
+            node_id_number as u32 & 0xff_ffff,
+        );
+        while alias_of(alias_generator) == 0 {
+            alias_generator = next_alias_generator(alias_generator);
+        }
+        Self {
+            node_id,
+            aspects,
+            alias_generator,
+            alias: alias_of(alias_generator),
+            reservation_frames: 0,
+            checked_at: 0,
+            shown_aspect: None,
+            identification: None,
+            frames: ArrayVec::new(),
+        }
+    }
+
+    /// Returns whether the node may send messages and answers them.
+    fn is_initialized(&self) -> bool {
+        self.reservation_frames == RESERVATION_FRAMES
+    }
+
+    /// Returns the event of the given aspect number.
+    fn event(&self, index: usize) -> [u8; 8] {
+        let mut event = [0; 8];
+        event[..6].copy_from_slice(&self.node_id);
+        event[7] = index as u8;
+        event
+    }
+
+    /// Returns the aspect number of the given event, if it is one of the node’s events.
+    fn index_of_event(&self, event: &[u8]) -> Option<usize> {
+        match *event {
+            [ref node_id @ .., 0, index]
+                if *node_id == self.node_id && usize::from(index) < self.aspects.len() =>
+            {
+                Some(usize::from(index))
+            }
+            _ => None,
+        }
+    }
+
+    fn message(&self, mti: u16, data: &[u8]) -> CanFrame {
+        CanFrame::new(
+            FRAME_RESERVED
+                | FRAME_OPENLCB_MESSAGE
+                | FRAME_TYPE_GLOBAL_OR_ADDRESSED << 24
+                | u32::from(mti) << 12
+                | u32::from(self.alias),
+            data,
+        )
+    }
+
+    fn control_frame(&self, control: u16, data: &[u8]) -> CanFrame {
+        CanFrame::new(
+            FRAME_RESERVED | u32::from(control) << 12 | u32::from(self.alias),
+            data,
+        )
+    }
+
+    fn queue(&mut self, frame: CanFrame) {
+        let _ = self.frames.try_push(frame);
+    }
+
+    /// Queues an addressed message to the node with the given alias.
+    fn queue_addressed(&mut self, mti: u16, destination: u16, data: &[u8]) {
+        let mut addressed_data: ArrayVec<u8, 8> = ArrayVec::new();
+        addressed_data.extend([(destination >> 8) as u8, destination as u8]);
+        addressed_data.extend(data.iter().copied());
+        self.queue(self.message(mti, &addressed_data));
+    }
+
+    /// Returns the Identified message of the given event as a consumer or producer, which is valid if the signal shows its aspect.
+    fn identified(&self, index: usize, is_producer: bool) -> CanFrame {
+        let is_valid = self.aspects[index].is_some() && self.aspects[index] == self.shown_aspect;
+        let mti = match (is_producer, is_valid) {
+            (false, true) => MTI_CONSUMER_IDENTIFIED_VALID,
+            (false, false) => MTI_CONSUMER_IDENTIFIED_INVALID,
+            (true, true) => MTI_PRODUCER_IDENTIFIED_VALID,
+            (true, false) => MTI_PRODUCER_IDENTIFIED_INVALID,
+        };
+        self.message(mti, &self.event(index))
+    }
+
+    /// Gives up the alias and starts reserving the next one.
+    fn reserve_next_alias(&mut self) {
+        self.frames.clear();
+        if self.reservation_frames > 5 {
+            // other nodes must forget the alias, which was defined with AMD.
+            self.frames
+                .push(self.control_frame(CONTROL_AMR, &self.node_id));
+        }
+        self.identification = None;
+        self.reservation_frames = 0;
+        self.alias_generator = next_alias_generator(self.alias_generator);
+        while alias_of(self.alias_generator) == 0 {
+            self.alias_generator = next_alias_generator(self.alias_generator);
+        }
+        self.alias = alias_of(self.alias_generator);
+    }
+
+    /// Returns the next frame to send, if any, which is considered sent. Should be called regularly with the current time in milliseconds, since the alias reservation waits for other nodes.
+    pub fn next_frame(&mut self, now_ms: u32) -> Option<CanFrame> {
+        if !self.frames.is_empty() {
+            return Some(self.frames.remove(0));
+        }
+        let frame = match self.reservation_frames {
+            sent @ 0..=3 => {
+                // CID7 to CID4 carry the node ID from its upper 12 bits down.
+                let sequence = 7 - sent;
+                let node_id_part = self.node_id[usize::from(sent) * 3 / 2..][..2]
+                    .iter()
+                    .fold(0, |part, byte| part << 8 | u32::from(*byte));
+                let node_id_part = if sent % 2 == 0 {
+                    node_id_part >> 4
+                } else {
+                    node_id_part & 0xfff
+                };
+                self.checked_at = now_ms;
+                CanFrame::new(
+                    FRAME_RESERVED
+                        | u32::from(sequence) << 24
+                        | node_id_part << 12
+                        | u32::from(self.alias),
+                    &[],
+                )
+            }
+            4 if now_ms.wrapping_sub(self.checked_at) < ALIAS_RESERVATION_MS => return None,
+            4 => self.control_frame(CONTROL_RID, &[]),
+            5 => self.control_frame(CONTROL_AMD, &self.node_id),
+            6 => self.message(MTI_INITIALIZATION_COMPLETE, &self.node_id),
+            _ => {
+                // events of unused aspect numbers are skipped.
+                let position = self.identification.take()?;
+                let index = (position / 2..self.aspects.len())
+                    .find(|index| self.aspects[*index].is_some())?;
+                let position = position.max(index * 2);
+                self.identification = Some(position + 1);
+                return Some(self.identified(position / 2, position % 2 != 0));
+            }
+        };
+        self.reservation_frames += 1;
+        Some(frame)
+    }
+
+    /// Records the aspect that the signal shows, and reports its event if it changed.
+    pub fn report_aspect(&mut self, aspect: AspectCommand) {
+        if self.shown_aspect.replace(aspect) == Some(aspect) || !self.is_initialized() {
+            return;
+        }
+        if let Some(index) = self
+            .aspects
+            .iter()
+            .position(|mapped| *mapped == Some(aspect))
+        {
+            self.queue(self.message(MTI_PRODUCER_CONSUMER_EVENT_REPORT, &self.event(index)));
+        }
+    }
+
+    /// Processes a received frame, and returns the aspect of the event that it reports, if any.
+    pub fn receive(&mut self, frame: &CanFrame) -> Option<AspectCommand> {
+        let source = (frame.id & 0xfff) as u16;
+        let is_message = frame.id & FRAME_OPENLCB_MESSAGE != 0;
+        let frame_type = frame.id >> 24 & 0x7;
+        let variable_field = (frame.id >> 12 & 0xfff) as u16;
+        if source == self.alias {
+            // another node checks the alias, or uses it.
+            if !is_message && frame_type >= 4 && self.reservation_frames > 5 {
+                self.queue(self.control_frame(CONTROL_RID, &[]));
+            } else {
+                self.reserve_next_alias();
+            }
+            return None;
+        }
+        if !is_message {
+            if frame_type == 0
+                && variable_field == CONTROL_AME
+                && self.reservation_frames > 5
+                && (frame.data.is_empty() || *frame.data == self.node_id)
+            {
+                self.queue(self.control_frame(CONTROL_AMD, &self.node_id));
+            }
+            return None;
+        }
+        if frame_type != FRAME_TYPE_GLOBAL_OR_ADDRESSED || !self.is_initialized() {
+            return None;
+        }
+        let mti = variable_field;
+        let data = if mti & MTI_ADDRESSED != 0 {
+            match *frame.data {
+                [flags_and_destination, destination, ref data @ ..]
+                    if u16::from(flags_and_destination & 0x0f) << 8 | u16::from(destination)
+                        == self.alias =>
+                {
+                    // later frames of a message that is split across frames are ignored.
+                    if flags_and_destination & 0x20 != 0 {
+                        return None;
+                    }
+                    data
+                }
+                _ => return None,
+            }
+        } else {
+            &frame.data[..]
+        };
+        match mti {
+            MTI_VERIFY_NODE_ID_GLOBAL if !data.is_empty() && *data != self.node_id => {}
+            MTI_VERIFY_NODE_ID_GLOBAL | MTI_VERIFY_NODE_ID_ADDRESSED => {
+                self.queue(self.message(MTI_VERIFIED_NODE_ID, &self.node_id));
+            }
+            MTI_PROTOCOL_SUPPORT_INQUIRY => {
+                self.queue_addressed(MTI_PROTOCOL_SUPPORT_REPLY, source, &SUPPORTED_PROTOCOLS);
+            }
+            MTI_IDENTIFY_EVENTS_GLOBAL | MTI_IDENTIFY_EVENTS_ADDRESSED => {
+                self.identification = Some(0);
+            }
+            MTI_IDENTIFY_CONSUMERS | MTI_IDENTIFY_PRODUCERS => {
+                if let Some(index) = self.index_of_event(data) {
+                    if self.aspects[index].is_some() {
+                        self.queue(self.identified(index, mti == MTI_IDENTIFY_PRODUCERS));
+                    }
+                }
+            }
+            MTI_PRODUCER_CONSUMER_EVENT_REPORT => {
+                return self.aspects[self.index_of_event(data)?];
+            }
+            MTI_OPTIONAL_INTERACTION_REJECTED | MTI_TERMINATE_DUE_TO_ERROR => {}
+            // other nodes must learn that the node doesn’t implement their request, so that they don’t wait for a reply.
+            _ if mti & MTI_ADDRESSED != 0 => {
+                let [error_upper, error_lower] = ERROR_UNKNOWN_MTI;
+                self.queue_addressed(
+                    MTI_OPTIONAL_INTERACTION_REJECTED,
+                    source,
+                    &[error_upper, error_lower, (mti >> 8) as u8, mti as u8],
+                );
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OpenLcbNode;
+    use super::ALIAS_RESERVATION_MS;
+    use crate::commands::AspectCommand;
+    use crate::mcp2515::CanFrame;
+
+    const NODE_ID: [u8; 6] = [0x05, 0x01, 0x01, 0x01, 0x22, 0x34];
+    const ASPECTS: [Option<AspectCommand>; 3] =
+        [Some(AspectCommand::Zero), None, Some(AspectCommand::Two)];
+    const OTHER_ALIAS: u32 = 0x123;
+
+    /// Returns a node that reserved its alias and sent Initialization Complete, and its alias.
+    fn initialized_node() -> (OpenLcbNode, u32) {
+        let mut node = OpenLcbNode::new(NODE_ID, &ASPECTS);
+        let check = node.next_frame(0).unwrap();
+        while node.next_frame(0).is_some() {}
+        while node.next_frame(ALIAS_RESERVATION_MS).is_some() {}
+        (node, check.id & 0xfff)
+    }
+
+    fn message(mti: u32, data: &[u8]) -> CanFrame {
+        CanFrame::new(0x1900_0000 | mti << 12 | OTHER_ALIAS, data)
+    }
+
+    #[test]
+    fn reserves_an_alias_and_gives_it_up_when_it_is_taken() {
+        let mut node = OpenLcbNode::new(NODE_ID, &ASPECTS);
+        let frames: Vec<CanFrame> = (0..4).filter_map(|_| node.next_frame(1000)).collect();
+        let alias = frames[0].id & 0xfff;
+        assert_ne!(alias, 0);
+        let headers: Vec<u32> = frames.iter().map(|frame| frame.id & !0xfff).collect();
+        assert_eq!(
+            headers,
+            [0x1705_0000, 0x1610_1000, 0x1501_2000, 0x1423_4000]
+        );
+        // other nodes have 200 ms to object.
+        assert!(node.next_frame(1000 + ALIAS_RESERVATION_MS - 1).is_none());
+        let now = 1000 + ALIAS_RESERVATION_MS;
+        assert_eq!(
+            node.next_frame(now),
+            Some(CanFrame::new(0x1070_0000 | alias, &[]))
+        );
+        assert_eq!(
+            node.next_frame(now),
+            Some(CanFrame::new(0x1070_1000 | alias, &NODE_ID))
+        );
+        // a node that checks the alias is told that it is reserved.
+        assert!(node
+            .receive(&CanFrame::new(0x1700_0000 | alias, &[]))
+            .is_none());
+        assert_eq!(
+            node.next_frame(now),
+            Some(CanFrame::new(0x1070_0000 | alias, &[]))
+        );
+        assert_eq!(
+            node.next_frame(now),
+            Some(CanFrame::new(0x1910_0000 | alias, &NODE_ID))
+        );
+        assert!(node.next_frame(now).is_none());
+
+        // a node that uses the alias takes it.
+        assert!(node
+            .receive(&CanFrame::new(0x1070_1000 | alias, &[1, 2, 3, 4, 5, 6]))
+            .is_none());
+        assert_eq!(
+            node.next_frame(now),
+            Some(CanFrame::new(0x1070_3000 | alias, &NODE_ID))
+        );
+        let check = node.next_frame(now).unwrap();
+        assert_eq!(check.id & !0xfff, 0x1705_0000);
+        assert_ne!(check.id & 0xfff, alias);
+    }
+
+    #[test]
+    fn exchanges_the_events_of_the_aspects() {
+        let (mut node, alias) = initialized_node();
+        let event = |index: u8| [0x05, 0x01, 0x01, 0x01, 0x22, 0x34, 0, index];
+        assert!(node.receive(&message(0x5b4, &event(2))) == Some(AspectCommand::Two));
+        // unused aspect numbers and events of other nodes.
+        assert!(node.receive(&message(0x5b4, &event(1))).is_none());
+        assert!(node
+            .receive(&message(0x5b4, &[0, 0, 0, 0, 0, 0, 0, 2]))
+            .is_none());
+
+        node.report_aspect(AspectCommand::Two);
+        assert_eq!(
+            node.next_frame(0),
+            Some(CanFrame::new(0x195b_4000 | alias, &event(2)))
+        );
+        // the aspect is only reported when it changes.
+        node.report_aspect(AspectCommand::Two);
+        assert!(node.next_frame(0).is_none());
+
+        node.receive(&message(0x914, &event(2)));
+        assert_eq!(
+            node.next_frame(0),
+            Some(CanFrame::new(0x1954_4000 | alias, &event(2)))
+        );
+        node.receive(&message(0x970, &[]));
+        let identified: Vec<CanFrame> = (0..5).filter_map(|_| node.next_frame(0)).collect();
+        assert_eq!(
+            identified,
+            [
+                CanFrame::new(0x194c_5000 | alias, &event(0)),
+                CanFrame::new(0x1954_5000 | alias, &event(0)),
+                CanFrame::new(0x194c_4000 | alias, &event(2)),
+                CanFrame::new(0x1954_4000 | alias, &event(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn answers_other_messages() {
+        let (mut node, alias) = initialized_node();
+        let destination = [(alias >> 8) as u8, alias as u8];
+        node.receive(&message(0x490, &[]));
+        node.receive(&message(0x490, &[1, 2, 3, 4, 5, 6]));
+        node.receive(&message(0x828, &destination));
+        // Simple Node Information isn’t implemented.
+        node.receive(&message(0xde8, &destination));
+        // addressed to another node.
+        node.receive(&message(0x828, &[0x01, 0x23]));
+        let replies: Vec<CanFrame> = (0..5).filter_map(|_| node.next_frame(0)).collect();
+        assert_eq!(
+            replies,
+            [
+                CanFrame::new(0x1917_0000 | alias, &NODE_ID),
+                CanFrame::new(0x1966_8000 | alias, &[0x01, 0x23, 0x04, 0, 0, 0, 0, 0]),
+                CanFrame::new(0x1906_8000 | alias, &[0x01, 0x23, 0x10, 0x43, 0x0d, 0xe8]),
+            ]
+        );
+    }
+}
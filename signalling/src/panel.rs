@@ -0,0 +1,103 @@
+//! Module for mirroring the signal aspect on the LEDs of a control desk panel.
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal::digital::PinState;
+
+use crate::signals::HVMainSignalAspect;
+
+// Bits of the panel LEDs, also used as the output bits of the shift register.
+const RED_LED: u8 = 1 << 0;
+const GREEN_LED: u8 = 1 << 1;
+const YELLOW_LED: u8 = 1 << 2;
+const WHITE_LED: u8 = 1 << 3;
+
+/// LEDs on a control desk panel that show the current main signal aspect.
+///
+/// The panel shows the same lamps as the main signal, with the white LED standing in for the notice lamp. For the Dark aspect, all LEDs are off.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used. Its parameters additionally include the output pin’s error type (which some functions also return).
+pub enum PanelOutput<Error, PinType: OutputPin<Error = Error>> {
+    /// Every LED is connected to its own pin.
+    Direct {
+        red_led: PinType,
+        green_led: PinType,
+        yellow_led: PinType,
+        white_led: PinType,
+    },
+    /// The LEDs are connected to the outputs Q0 (red), Q1 (green), Q2 (yellow) and Q3 (white) of a 74HC595 shift register, saving pins.
+    ShiftRegister {
+        data: PinType,
+        clock: PinType,
+        latch: PinType,
+    },
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> PanelOutput<Error, PinType> {
+    pub fn new_direct(
+        red_led: PinType,
+        green_led: PinType,
+        yellow_led: PinType,
+        white_led: PinType,
+    ) -> Self {
+        Self::Direct {
+            red_led,
+            green_led,
+            yellow_led,
+            white_led,
+        }
+    }
+
+    pub fn new_shift_register(data: PinType, clock: PinType, latch: PinType) -> Self {
+        Self::ShiftRegister { data, clock, latch }
+    }
+
+    /// Shows the given aspect on the panel LEDs.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    pub fn show_aspect(&mut self, aspect: HVMainSignalAspect) -> Result<(), Error> {
+        let leds = match aspect {
+            HVMainSignalAspect::Stop => RED_LED,
+            HVMainSignalAspect::StopWithShunting
+            | HVMainSignalAspect::Substitution
+            | HVMainSignalAspect::CounterTrackSubstitution => RED_LED | WHITE_LED,
+            HVMainSignalAspect::Caution => RED_LED | YELLOW_LED,
+            HVMainSignalAspect::Proceed | HVMainSignalAspect::ProceedCounterTrack => GREEN_LED,
+            HVMainSignalAspect::ProceedSlow | HVMainSignalAspect::ProceedSlowCounterTrack => {
+                GREEN_LED | YELLOW_LED
+            }
+            HVMainSignalAspect::Deactivated => WHITE_LED,
+            HVMainSignalAspect::Dark => 0,
+        };
+        match self {
+            Self::Direct {
+                red_led,
+                green_led,
+                yellow_led,
+                white_led,
+            } => {
+                red_led.set_state(Self::state_of(leds, RED_LED))?;
+                green_led.set_state(Self::state_of(leds, GREEN_LED))?;
+                yellow_led.set_state(Self::state_of(leds, YELLOW_LED))?;
+                white_led.set_state(Self::state_of(leds, WHITE_LED))?;
+            }
+            Self::ShiftRegister { data, clock, latch } => {
+                // shift out the most significant bit first, so that bit 0 ends up on Q0.
+                for bit in (0..8).rev() {
+                    data.set_state(Self::state_of(leds, 1 << bit))?;
+                    clock.set_high()?;
+                    clock.set_low()?;
+                }
+                latch.set_high()?;
+                latch.set_low()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn state_of(leds: u8, led: u8) -> PinState {
+        PinState::from(leds & led != 0)
+    }
+}
@@ -0,0 +1,24 @@
+//! Module for the services of the microcontroller board that the signal controller needs apart from its pins: persistent storage, the watchdog, sleeping, and receiving from the serial port.
+//!
+//! Lamps, servos and other outputs already go through the embedded-hal traits. Everything else that the controller needs from the board is behind the [`Platform`] trait, so that the same logic can run on other microcontrollers, like the RP2040 or an STM32, where the storage is a flash page instead of an EEPROM. Sending on the serial port only needs [`ufmt::uWrite`], which every serial port can implement.
+
+/// The board-specific services of a signal controller.
+pub trait Platform {
+    /// Size of the persistent storage in bytes.
+    const PERSISTENT_SIZE: u16;
+
+    /// Reads from the persistent storage at the given offset. Storage that was never written may contain anything. Panics if the data doesn’t fit into the storage.
+    fn read_persistent(&mut self, offset: u16, data: &mut [u8]);
+
+    /// Writes to the persistent storage at the given offset, so that the data survives a reboot. Panics if the data doesn’t fit into the storage.
+    fn write_persistent(&mut self, offset: u16, data: &[u8]);
+
+    /// Resets the watchdog, which reboots the controller if it isn’t fed for a few seconds.
+    fn feed_watchdog(&mut self);
+
+    /// Sleeps until the next interrupt, like a received byte or a clock tick.
+    fn sleep(&mut self);
+
+    /// Passes the bytes that were received on the serial port since the last call, in order.
+    fn receive(&mut self, received: impl FnMut(u8));
+}
@@ -0,0 +1,154 @@
+//! Module for the presentation state of the controller, i.e. everything that the signal currently shows.
+
+use crate::signals::SignalAspect;
+
+/// Everything that the signal currently shows.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PresentationState<Aspect: SignalAspect> {
+    pub aspect: Aspect,
+    /// Speed shown by the speed indicator (Zs3), in tens of km/h, or None if it is dark.
+    pub speed: Option<u8>,
+    /// Route letter shown by the direction indicator (Zs2), or None if it is dark.
+    pub route: Option<u8>,
+}
+
+impl<Aspect: SignalAspect> PresentationState<Aspect> {
+    /// Returns a checksum of the presentation state. It is sent with every acknowledgement, so that the control box can notice when its idea of the signal state diverged from the actual state, e.g. after a panel button press it missed.
+    ///
+    /// The checksum is computed over the state as it is acknowledged, e.g. `2:6:R` for Hp2 with speed 60 km/h towards route R.
+    pub fn checksum(&self) -> StateChecksum {
+        let speed = self.speed.map(|speed| [b':', b'0' + speed]);
+        let route = self.route.map(|route| [b':', route]);
+        StateChecksum(crc8(
+            self.aspect
+                .command_id()
+                .as_bytes()
+                .iter()
+                .chain(speed.iter().flatten())
+                .chain(route.iter().flatten()),
+        ))
+    }
+}
+
+/// A second copy of the presentation state with its checksum, for detecting corruption of the RAM that holds the state the controller works with.
+///
+/// The controller updates the mirror whenever it changes its state, and regularly verifies its state against it. If the RAM was corrupted, e.g. by a brownout or radiation, either the copies or the mirror and its checksum differ.
+pub struct PresentationMirror<Aspect: SignalAspect> {
+    state: PresentationState<Aspect>,
+    checksum: StateChecksum,
+}
+
+impl<Aspect: SignalAspect> PresentationMirror<Aspect> {
+    pub fn new(state: PresentationState<Aspect>) -> Self {
+        Self {
+            state,
+            checksum: state.checksum(),
+        }
+    }
+
+    /// Records a legitimate change of the presentation state.
+    pub fn update(&mut self, state: PresentationState<Aspect>) {
+        *self = Self::new(state);
+    }
+
+    /// Returns whether the mirror is intact and matches the given state.
+    pub fn verify(&self, state: PresentationState<Aspect>) -> bool {
+        self.state == state && self.state.checksum() == self.checksum
+    }
+}
+
+/// A CRC-8 of the presentation state, displayed as two uppercase hexadecimal digits.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct StateChecksum(pub u8);
+
+impl ufmt::uDisplay for StateChecksum {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+        let digits = [
+            DIGITS[usize::from(self.0 >> 4)],
+            DIGITS[usize::from(self.0 & 0xf)],
+        ];
+        // only ASCII digits, so this can’t fail.
+        f.write_str(core::str::from_utf8(&digits).unwrap())
+    }
+}
+
+/// Computes the CRC-8 with polynomial 0x07 and initial value 0 (also known as CRC-8/SMBUS).
+fn crc8<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u8 {
+    bytes.into_iter().fold(0, |mut crc, byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crc8;
+    use super::PresentationMirror;
+    use super::PresentationState;
+    use super::StateChecksum;
+    use crate::signals::HVMainSignalAspect;
+
+    #[test]
+    fn mirror_detects_corruption_of_either_copy() {
+        let stop = PresentationState {
+            aspect: HVMainSignalAspect::Stop,
+            speed: None,
+            route: None,
+        };
+        let proceed = PresentationState {
+            aspect: HVMainSignalAspect::Proceed,
+            ..stop
+        };
+        let proceed_with_speed = PresentationState {
+            speed: Some(6),
+            ..proceed
+        };
+        let mut mirror = PresentationMirror::new(stop);
+        assert!(mirror.verify(stop));
+        assert!(!mirror.verify(proceed));
+        mirror.update(proceed);
+        assert!(mirror.verify(proceed));
+        assert!(!mirror.verify(proceed_with_speed));
+
+        mirror.checksum.0 ^= 0x10;
+        assert!(!mirror.verify(proceed));
+    }
+
+    #[test]
+    fn crc8_matches_check_value() {
+        assert_eq!(crc8(b"123456789"), 0xf4);
+    }
+
+    #[test]
+    fn checksum_covers_the_acknowledged_state() {
+        let state = PresentationState {
+            aspect: HVMainSignalAspect::ProceedSlow,
+            speed: Some(6),
+            route: Some(b'R'),
+        };
+        assert_eq!(state.checksum().0, crc8(b"2:6:R"));
+        let without_speed = PresentationState {
+            speed: None,
+            ..state
+        };
+        assert_eq!(without_speed.checksum().0, crc8(b"2:R"));
+    }
+
+    #[test]
+    fn checksum_is_displayed_as_two_hex_digits() {
+        let mut text = String::new();
+        ufmt::uwrite!(text, "{}", StateChecksum(0x0a)).unwrap();
+        assert_eq!(text, "0A");
+    }
+}
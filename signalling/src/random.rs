@@ -0,0 +1,66 @@
+//! Module for pseudo-random numbers.
+//!
+//! Randomness only has to make controllers and boots behave differently, not withstand an attacker, so a small xorshift generator is enough. Everything that needs randomness takes an [`Rng`], so that host tests can inject deterministic sequences.
+
+/// A source of pseudo-random numbers.
+pub trait Rng {
+    /// Returns the next pseudo-random number.
+    fn next_u32(&mut self) -> u32;
+
+    /// Returns a pseudo-random number below the given bound, which must not be zero. Small bounds are very slightly biased, which doesn’t matter for our uses.
+    fn below(&mut self, bound: u32) -> u32 {
+        self.next_u32() % bound
+    }
+}
+
+/// The xorshift32 pseudo-random number generator.
+pub struct XorShift32 {
+    // Never zero, since zero is a fixed point of xorshift.
+    state: u32,
+}
+
+impl XorShift32 {
+    /// Creates a new generator. The seed should differ between boots, see [`seed_from`].
+    pub const fn new(seed: u32) -> Self {
+        Self { state: seed | 1 }
+    }
+}
+
+impl Rng for XorShift32 {
+    fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+}
+
+/// Combines several noise sources, like a noisy analog reading and the contents of uninitialized RAM, into a seed. Every source on its own may be predictable (RAM keeps its contents over a reset without power loss), so all available sources should be combined.
+pub fn seed_from(noise_sources: &[u32]) -> u32 {
+    noise_sources.iter().fold(0x811c_9dc5, |seed, noise| {
+        // multiplying by an odd constant spreads every bit of the noise over the upper bits.
+        (seed ^ noise).wrapping_mul(0x9e37_79b1).rotate_left(15)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::seed_from;
+    use super::Rng;
+    use super::XorShift32;
+
+    #[test]
+    fn xorshift_never_gets_stuck_at_zero() {
+        let mut rng = XorShift32::new(0);
+        assert!((0..1000).all(|_| rng.next_u32() != 0));
+    }
+
+    #[test]
+    fn every_noise_source_changes_the_seed() {
+        let seed = seed_from(&[1234, 0]);
+        assert_ne!(seed, seed_from(&[1235, 0]));
+        assert_ne!(seed, seed_from(&[1234, 1]));
+    }
+}
@@ -0,0 +1,56 @@
+//! Module for the detection of retransmitted commands, which a control box on a lossy link sends again when their response got lost.
+//!
+//! Commands with a sequence number are remembered together with a checksum of their text. A state-changing command that arrives again with the same sequence number and text was already executed, so it must not be executed a second time.
+
+use crate::commands::checksum;
+
+/// Number of recent commands that are remembered.
+pub const WINDOW_LENGTH: usize = 8;
+
+/// Remembers the most recent commands with sequence numbers.
+#[derive(Default)]
+pub struct RetransmitWindow {
+    // Sequence number and checksum of the text of the recent commands, overwritten in a circle.
+    recent: [Option<(u16, u8)>; WINDOW_LENGTH],
+    // Index of the oldest entry.
+    next: usize,
+}
+
+impl RetransmitWindow {
+    pub const fn new() -> Self {
+        Self {
+            recent: [None; WINDOW_LENGTH],
+            next: 0,
+        }
+    }
+
+    /// Returns whether the command with the given sequence number and text was received recently, and remembers it otherwise.
+    pub fn is_retransmit(&mut self, sequence_number: u16, command: &[u8]) -> bool {
+        let entry = Some((sequence_number, checksum(command)));
+        if self.recent.contains(&entry) {
+            return true;
+        }
+        self.recent[self.next] = entry;
+        self.next = (self.next + 1) % WINDOW_LENGTH;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetransmitWindow;
+    use super::WINDOW_LENGTH;
+
+    #[test]
+    fn detects_retransmits_within_the_window() {
+        let mut window = RetransmitWindow::new();
+        assert!(!window.is_retransmit(1, b"F:1:#1"));
+        assert!(window.is_retransmit(1, b"F:1:#1"));
+        // the same sequence number with another command is a new command.
+        assert!(!window.is_retransmit(1, b"F:0:#1"));
+        for sequence_number in 2..2 + WINDOW_LENGTH as u16 {
+            assert!(!window.is_retransmit(sequence_number, b"F:2"));
+        }
+        assert!(!window.is_retransmit(1, b"F:1:#1"));
+    }
+}
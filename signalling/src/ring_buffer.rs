@@ -0,0 +1,110 @@
+//! Module for handing bytes from an interrupt handler to the main loop without a critical section.
+//!
+//! The interrupt handler only advances the write position, and the main loop only advances the read position. Both positions are single bytes, which even the AVR loads and stores atomically, so neither side ever has to disable interrupts to access the buffer.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering;
+
+/// A single-producer single-consumer queue of up to `CAPACITY` bytes, which must be a power of two up to 128.
+pub struct RingBuffer<const CAPACITY: usize> {
+    bytes: UnsafeCell<[u8; CAPACITY]>,
+    // Number of bytes ever written and read, wrapping around at 256, so that the buffer can be completely full.
+    written: AtomicU8,
+    read: AtomicU8,
+}
+
+// The bytes between the read and the write position are only accessed by the consumer, and all others only by the producer.
+unsafe impl<const CAPACITY: usize> Sync for RingBuffer<CAPACITY> {}
+
+impl<const CAPACITY: usize> RingBuffer<CAPACITY> {
+    // The positions wrap around at 256, which must be a multiple of the capacity.
+    const HAS_VALID_CAPACITY: () = assert!(CAPACITY.is_power_of_two() && CAPACITY <= 128);
+
+    pub const fn new() -> Self {
+        let () = Self::HAS_VALID_CAPACITY;
+        Self {
+            bytes: UnsafeCell::new([0; CAPACITY]),
+            written: AtomicU8::new(0),
+            read: AtomicU8::new(0),
+        }
+    }
+
+    /// Returns the number of bytes that wait to be read.
+    pub fn len(&self) -> usize {
+        self.written
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.read.load(Ordering::Acquire))
+            .into()
+    }
+
+    /// Returns whether no bytes wait to be read.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends a byte. Returns false if the buffer is full, and the byte was dropped.
+    ///
+    /// # Safety
+    ///
+    /// Only a single context, like one interrupt handler, may push bytes, and it must not be interrupted by another push.
+    pub unsafe fn push(&self, byte: u8) -> bool {
+        let written = self.written.load(Ordering::Relaxed);
+        if usize::from(written.wrapping_sub(self.read.load(Ordering::Acquire))) == CAPACITY {
+            return false;
+        }
+        // the consumer doesn’t read this byte until the write position is advanced.
+        unsafe {
+            (*self.bytes.get())[usize::from(written) % CAPACITY] = byte;
+        }
+        self.written
+            .store(written.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Removes the oldest byte, if there is one.
+    ///
+    /// # Safety
+    ///
+    /// Only a single context, like the main loop, may pop bytes, and it must not be interrupted by another pop.
+    pub unsafe fn pop(&self) -> Option<u8> {
+        let read = self.read.load(Ordering::Relaxed);
+        if read == self.written.load(Ordering::Acquire) {
+            return None;
+        }
+        // the producer doesn’t overwrite this byte until the read position is advanced.
+        let byte = unsafe { (*self.bytes.get())[usize::from(read) % CAPACITY] };
+        self.read.store(read.wrapping_add(1), Ordering::Release);
+        Some(byte)
+    }
+}
+
+impl<const CAPACITY: usize> Default for RingBuffer<CAPACITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RingBuffer;
+
+    #[test]
+    fn hands_over_bytes_in_order_until_full() {
+        let buffer = RingBuffer::<4>::new();
+        for round in 0..100u8 {
+            for offset in 0..4 {
+                assert!(unsafe { buffer.push(round.wrapping_add(offset)) });
+            }
+            assert!(!unsafe { buffer.push(0) });
+            assert_eq!(buffer.len(), 4);
+            for offset in 0..3 {
+                assert_eq!(unsafe { buffer.pop() }, Some(round.wrapping_add(offset)));
+            }
+            assert_eq!(buffer.len(), 1);
+            assert_eq!(unsafe { buffer.pop() }, Some(round.wrapping_add(3)));
+            assert_eq!(unsafe { buffer.pop() }, None);
+            assert!(buffer.is_empty());
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Module for the Selectrix (SX) bus of Trix, Rautenhaus and MÜT central units, which continuously send the eight bits of each of their 112 channels, and from one of which the signal reads its aspect.
+//!
+//! The bus sends a bit every 50 µs: the clock line T0 is pulled low briefly for each bit, and the data line T1 is read when T0 rises again. The channels are sent in 16 frames of seven channels. Each frame starts with a sync of 12 bits, `0 0 0 1 S 1 A3 A2 1 A1 A0 1`, with the track power in S and the frame number in A3 to A0, followed by seven channels of 12 bits, `D0 D1 1 D2 D3 1 D4 D5 1 D6 D7 1`, from the highest channel of the frame down. Since the separators are always 1, only a sync has three zeros in a row. All channels are sent in about 77 ms.
+
+use crate::commands::AspectCommand;
+
+/// Highest channel that the signal can use. Channels 104 to 111 are used by the central units themselves.
+pub const MAX_CHANNEL: u8 = 103;
+/// Number of frames, and of channels in each frame.
+const FRAMES: u8 = 16;
+const CHANNELS_PER_FRAME: u8 = 7;
+/// Number of bits of a sync after its three zeros, and of a channel.
+const SYNC_BITS: u8 = 9;
+const CHANNEL_BITS: u8 = 12;
+
+/// Receives the channels on the bus, bit by bit.
+#[derive(Default)]
+pub struct SelectrixReceiver {
+    // Number of zeros in a row, up to the three of a sync.
+    zeros: u8,
+    // Position of the next bit after the three zeros of the last sync, or None while waiting for a sync.
+    position: Option<u8>,
+    // Number of the current frame, from its sync.
+    frame: u8,
+    // Received bits of the current channel.
+    data: u8,
+}
+
+impl SelectrixReceiver {
+    pub const fn new() -> Self {
+        Self {
+            zeros: 0,
+            position: None,
+            frame: 0,
+            data: 0,
+        }
+    }
+
+    /// Processes a bit of the data line, read when the clock line rises, and returns the number and the value of the channel that it completes, if any.
+    pub fn receive_bit(&mut self, bit: bool) -> Option<(u8, u8)> {
+        let zeros = self.zeros;
+        self.zeros = if bit { 0 } else { (zeros + 1).min(3) };
+        if zeros == 3 && bit {
+            // the first 1 of the sync.
+            self.position = Some(1);
+            self.frame = 0;
+            return None;
+        }
+        let position = self.position?;
+        self.position = Some(position + 1);
+        let (index, bit_position) = match position.checked_sub(SYNC_BITS) {
+            Some(offset) => (Some(offset / CHANNEL_BITS), offset % CHANNEL_BITS),
+            None => (None, position),
+        };
+        // every third bit of a sync and a channel is a separator, which is always 1, and the last one ends the channel.
+        if bit_position % 3 == 2 {
+            if !bit {
+                self.position = None;
+                return None;
+            }
+            let index = index.filter(|_| bit_position == CHANNEL_BITS - 1)?;
+            if index == CHANNELS_PER_FRAME - 1 {
+                self.position = None;
+            }
+            let channel =
+                (FRAMES - 1 - self.frame) * CHANNELS_PER_FRAME + (CHANNELS_PER_FRAME - 1 - index);
+            return Some((channel, self.data));
+        }
+        match index {
+            // the track power is ignored.
+            None if position == 1 => {}
+            None => self.frame = self.frame << 1 | u8::from(bit),
+            Some(_) => {
+                let data_bit = bit_position - bit_position / 3;
+                self.data = self.data & !(1 << data_bit) | u8::from(bit) << data_bit;
+            }
+        }
+        None
+    }
+}
+
+/// Maps the bits of the signal’s channel to aspect commands.
+pub struct SelectrixMapping {
+    // Lowest of the mapped bits, numbered from 1 like on the central units.
+    first_bit: u8,
+    // Aspect commanded by each value of the mapped bits, whose number follows from the number of aspects, which is a power of two.
+    aspects: &'static [Option<AspectCommand>],
+    // The last channel and the value of its mapped bits.
+    last_value: Option<(u8, u8)>,
+}
+
+impl SelectrixMapping {
+    pub const fn new(first_bit: u8, aspects: &'static [Option<AspectCommand>]) -> Self {
+        Self {
+            first_bit,
+            aspects,
+            last_value: None,
+        }
+    }
+
+    /// Returns the aspect commanded by a value of the given channel, if the mapped bits changed since the last value. The central unit keeps the state of the layout, so the first value after a reboot or a change of the channel commands its aspect too.
+    pub fn aspect_for(&mut self, channel: u8, value: u8) -> Option<AspectCommand> {
+        let bits = value >> (self.first_bit - 1) & (self.aspects.len() - 1) as u8;
+        if self.last_value.replace((channel, bits)) == Some((channel, bits)) {
+            return None;
+        }
+        *self.aspects.get(usize::from(bits))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SelectrixMapping;
+    use super::SelectrixReceiver;
+    use crate::commands::AspectCommand;
+
+    /// Returns the bits of a frame with the given number and the values of its channels, from the highest channel down.
+    fn encode(frame: u8, values: [u8; 7]) -> Vec<bool> {
+        let mut bits = vec![false, false, false, true, true, true];
+        for address_bit in [3, 2, 1, 0] {
+            bits.push(frame >> address_bit & 1 != 0);
+            if address_bit % 2 == 0 {
+                bits.push(true);
+            }
+        }
+        for value in values {
+            for data_bit in 0..8 {
+                bits.push(value >> data_bit & 1 != 0);
+                if data_bit % 2 == 1 {
+                    bits.push(true);
+                }
+            }
+        }
+        bits
+    }
+
+    fn receive(receiver: &mut SelectrixReceiver, bits: &[bool]) -> Vec<(u8, u8)> {
+        bits.iter()
+            .filter_map(|bit| receiver.receive_bit(*bit))
+            .collect()
+    }
+
+    #[test]
+    fn receives_every_channel_of_a_frame() {
+        let mut receiver = SelectrixReceiver::new();
+        let values = [0x00, 0xff, 0x01, 0x80, 0x55, 0xaa, 0x0f];
+        // the bits before the first sync are ignored.
+        let bits = [
+            [true, false].as_slice(),
+            &encode(15, values),
+            &encode(0, values),
+        ]
+        .concat();
+        let channels = receive(&mut receiver, &bits);
+        // frame 15 has the channels 6 down to 0, and frame 0 the channels 111 down to 105.
+        let expected: Vec<(u8, u8)> = [6, 111]
+            .into_iter()
+            .flat_map(|highest| {
+                (0..7).map(move |index| (highest - index, values[usize::from(index)]))
+            })
+            .collect();
+        assert_eq!(channels, expected);
+        // a missing separator after the second channel loses the rest of the frame.
+        let mut broken = encode(15, values);
+        broken[35] = false;
+        assert_eq!(receive(&mut receiver, &broken).len(), 1);
+    }
+
+    #[test]
+    fn maps_changes_of_the_channel_bits_to_aspects() {
+        const ASPECTS: [Option<AspectCommand>; 4] = [
+            Some(AspectCommand::Zero),
+            Some(AspectCommand::One),
+            Some(AspectCommand::Two),
+            None,
+        ];
+        let mut mapping = SelectrixMapping::new(3, &ASPECTS);
+        assert!(mapping.aspect_for(10, 0b0000_0100) == Some(AspectCommand::One));
+        // the other bits don’t matter.
+        assert!(mapping.aspect_for(10, 0b1100_0101).is_none());
+        assert!(mapping.aspect_for(10, 0b0000_1000) == Some(AspectCommand::Two));
+        // unmapped values are ignored.
+        assert!(mapping.aspect_for(10, 0b0000_1100).is_none());
+        assert!(mapping.aspect_for(11, 0b0000_1000) == Some(AspectCommand::Two));
+    }
+}
@@ -0,0 +1,312 @@
+//! Module for semaphore signals (Formsignale), whose arms and discs are moved by model servos instead of showing lamps.
+
+use core::marker::PhantomData;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+
+use crate::signals::HVMainSignalAspect;
+use crate::signals::LampRole;
+use crate::signals::Signal;
+
+/// Period of the servo pulses, in microseconds. Model servos expect a pulse every 20 ms, so PWM outputs for servos must have this period, or their maximum duty cycle must be scaled as if they had.
+pub const SERVO_PERIOD_US: u16 = 20_000;
+
+/// How long a servo takes by default to move from one end stop to the other, in milliseconds. The arms of real semaphores are pulled by wire and take about a second.
+const MOVE_MS: u32 = 1000;
+
+/// The end stops of a servo, i.e. the pulse widths of its two positions in microseconds. They are adjusted to the mechanics of each signal kit, and usually lie between 1000 and 2000 µs.
+#[derive(Clone, Copy)]
+pub struct EndStops {
+    /// Position for stop: a horizontal arm, a lower arm hanging down along the mast, or a disc facing the driver.
+    pub stop_us: u16,
+    /// Position for proceed: an arm raised diagonally, or a disc folded away.
+    pub proceed_us: u16,
+}
+
+/// A model servo, which moves smoothly between its end stops: it accelerates at the start of a movement and decelerates at the end, like an arm pulled by wire.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of PWM output used.
+pub struct Servo<PwmType: SetDutyCycle> {
+    pwm: PwmType,
+    end_stops: EndStops,
+    // How long a movement from one end stop to the other takes.
+    move_ms: u32,
+    // Whether the servo moves towards (or is at) the proceed end stop.
+    is_at_proceed: bool,
+    // Pulse width at the start of the current movement, and the current pulse width.
+    from_us: u16,
+    position_us: u16,
+    // Time the current movement started, or None if it starts with the next update.
+    started_at: Option<u32>,
+    is_moving: bool,
+}
+
+impl<PwmType: SetDutyCycle> Servo<PwmType> {
+    /// Creates a servo at the given PWM output. It is driven to the stop end stop with the first update, since its position at power-on is unknown.
+    pub fn new(pwm: PwmType, end_stops: EndStops) -> Self {
+        Self {
+            pwm,
+            end_stops,
+            move_ms: MOVE_MS,
+            is_at_proceed: false,
+            from_us: end_stops.stop_us,
+            position_us: end_stops.stop_us,
+            started_at: None,
+            is_moving: true,
+        }
+    }
+
+    /// Changes how long a movement from one end stop to the other takes, in milliseconds.
+    pub fn with_move_ms(mut self, move_ms: u32) -> Self {
+        self.move_ms = move_ms;
+        self
+    }
+
+    /// Drives the servo directly to the given end stop with the next update, without moving smoothly.
+    pub(crate) fn jump_to(&mut self, is_at_proceed: bool) {
+        let position_us = if is_at_proceed {
+            self.end_stops.proceed_us
+        } else {
+            self.end_stops.stop_us
+        };
+        self.is_at_proceed = is_at_proceed;
+        self.from_us = position_us;
+        self.position_us = position_us;
+        self.started_at = None;
+        self.is_moving = true;
+    }
+
+    /// Starts moving towards the given end stop with the next update, unless the servo already moves towards it.
+    pub(crate) fn move_to(&mut self, is_at_proceed: bool) {
+        if is_at_proceed != self.is_at_proceed {
+            self.is_at_proceed = is_at_proceed;
+            self.from_us = self.position_us;
+            self.started_at = None;
+            self.is_moving = true;
+        }
+    }
+
+    /// Returns whether the servo reached the end stop it last moved towards.
+    pub(crate) fn is_at_target(&self) -> bool {
+        !self.is_moving
+    }
+
+    /// Advances the movement to the current time.
+    pub(crate) fn update(&mut self, now: u32) -> Result<(), PwmType::Error> {
+        if !self.is_moving {
+            return Ok(());
+        }
+        let started_at = *self.started_at.get_or_insert(now);
+        let elapsed_ms = now.wrapping_sub(started_at).min(self.move_ms);
+        // smoothstep: 3t² - 2t³, with t in thousandths.
+        let t = (elapsed_ms * 1000)
+            .checked_div(self.move_ms)
+            .unwrap_or(1000);
+        let progress = t * t / 1000 * (3000 - 2 * t) / 1000;
+        let to_us = if self.is_at_proceed {
+            self.end_stops.proceed_us
+        } else {
+            self.end_stops.stop_us
+        };
+        let distance_us = i32::from(to_us) - i32::from(self.from_us);
+        self.position_us = (i32::from(self.from_us) + distance_us * progress as i32 / 1000) as u16;
+        self.pwm
+            .set_duty_cycle_fraction(self.position_us, SERVO_PERIOD_US)?;
+        self.is_moving = elapsed_ms < self.move_ms && self.position_us != to_us;
+        Ok(())
+    }
+}
+
+/// A semaphore main signal (Formhauptsignal) in the H/V signalling system, with an upper arm, an optional lower arm for Hp2, and an optional distant signal disc (Vorsignalscheibe) on its mast.
+///
+/// Semaphores can only show Hp0, Hp1 and Hp2. The servos move when [`Signal::update`] is called, and the upper arm is always raised last and lowered first, so that no movement shows a less restrictive aspect than the old and the new one. The distant disc announces the next main signal as told by [`Signal::show_next_aspect`], and faces the driver (Vr0) whenever the main signal shows stop. Without an arm of its own, it can’t announce Hp2, so it announces stop instead.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of PWM output used for the servos. Its parameters additionally include the error type (which some functions also return), and the kind of output pin that the [`Signal`] trait expects, although a semaphore has no lamps.
+pub struct SemaphoreSignal<
+    Error,
+    PinType: OutputPin<Error = Error>,
+    PwmType: SetDutyCycle<Error = Error>,
+> {
+    upper_arm: Servo<PwmType>,
+    // Raised together with the upper arm for Hp2, and hanging down along the mast otherwise.
+    lower_arm: Option<Servo<PwmType>>,
+    distant_disc: Option<Servo<PwmType>>,
+    // Aspect that was last switched to.
+    aspect: HVMainSignalAspect,
+    // Aspect of the next main signal, which the distant disc announces.
+    next_aspect: HVMainSignalAspect,
+    lamps: PhantomData<PinType>,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>, PwmType: SetDutyCycle<Error = Error>>
+    SemaphoreSignal<Error, PinType, PwmType>
+{
+    /// Creates a semaphore with only the upper arm, which shows stop once it is updated.
+    pub fn new(upper_arm: Servo<PwmType>) -> Self {
+        Self {
+            upper_arm,
+            lower_arm: None,
+            distant_disc: None,
+            aspect: HVMainSignalAspect::Stop,
+            next_aspect: HVMainSignalAspect::Stop,
+            lamps: PhantomData,
+        }
+    }
+
+    /// Adds the lower arm, for showing Hp2.
+    pub fn with_lower_arm(mut self, lower_arm: Servo<PwmType>) -> Self {
+        self.lower_arm = Some(lower_arm);
+        self
+    }
+
+    /// Adds the disc of a distant signal on the main signal’s mast, which announces the next main signal.
+    pub fn with_distant_disc(mut self, distant_disc: Servo<PwmType>) -> Self {
+        self.distant_disc = Some(distant_disc);
+        self
+    }
+}
+
+impl<Error, PinType: OutputPin<Error = Error>, PwmType: SetDutyCycle<Error = Error>> Signal
+    for SemaphoreSignal<Error, PinType, PwmType>
+{
+    type Aspect = HVMainSignalAspect;
+    type Pin = PinType;
+    type Error = Error;
+
+    fn supports_aspect(&self, aspect: HVMainSignalAspect) -> bool {
+        match aspect {
+            HVMainSignalAspect::Stop | HVMainSignalAspect::Proceed => true,
+            HVMainSignalAspect::ProceedSlow => self.lower_arm.is_some(),
+            _ => false,
+        }
+    }
+
+    /// The servos only start moving with the next update.
+    fn switch_to_aspect(
+        &mut self,
+        aspect: HVMainSignalAspect,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        // logic bug, since user code should ensure to never try to enable illegal aspects on signals that don’t support them
+        match aspect {
+            HVMainSignalAspect::Stop | HVMainSignalAspect::Proceed => {}
+            HVMainSignalAspect::ProceedSlow if self.lower_arm.is_some() => {}
+            HVMainSignalAspect::ProceedSlow => {
+                panic!("illegal aspect for this semaphore, no lower arm available")
+            }
+            _ => panic!("illegal aspect for this semaphore, only Hp0, Hp1 and Hp2 available"),
+        }
+        self.aspect = aspect;
+        self.upper_arm.move_to(aspect != HVMainSignalAspect::Stop);
+        if let Some(lower_arm) = &mut self.lower_arm {
+            lower_arm.move_to(aspect == HVMainSignalAspect::ProceedSlow);
+        }
+        self.show_next_aspect(self.next_aspect, delay)
+    }
+
+    fn lamp(&mut self, _role: LampRole) -> Option<&mut PinType> {
+        None
+    }
+
+    fn show_next_aspect(
+        &mut self,
+        next_aspect: HVMainSignalAspect,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        self.next_aspect = next_aspect;
+        if let Some(distant_disc) = &mut self.distant_disc {
+            distant_disc.move_to(
+                self.aspect != HVMainSignalAspect::Stop
+                    && matches!(
+                        next_aspect,
+                        HVMainSignalAspect::Proceed | HVMainSignalAspect::ProceedCounterTrack
+                    ),
+            );
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, now: u32) -> Result<(), Error> {
+        let is_stop = self.aspect == HVMainSignalAspect::Stop;
+        let is_lower_arm_at_target = self.lower_arm.as_ref().map_or(true, Servo::is_at_target);
+        if is_stop || is_lower_arm_at_target {
+            self.upper_arm.update(now)?;
+        }
+        let is_upper_arm_at_target = self.upper_arm.is_at_target();
+        if let Some(lower_arm) = &mut self.lower_arm {
+            if !is_stop || is_upper_arm_at_target {
+                lower_arm.update(now)?;
+            }
+        }
+        // like the lower arm, the disc may only announce proceed once the upper arm is raised.
+        if let Some(distant_disc) = &mut self.distant_disc {
+            if !distant_disc.is_at_proceed || is_upper_arm_at_target {
+                distant_disc.update(now)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use super::EndStops;
+    use super::SemaphoreSignal;
+    use super::Servo;
+    use crate::mock::MockDelay;
+    use crate::mock::MockPin;
+    use crate::mock::MockPwm;
+    use crate::mock::MockPwms;
+    use crate::signals::HVMainSignalAspect;
+    use crate::signals::Signal;
+
+    const ARM: EndStops = EndStops {
+        stop_us: 1000,
+        proceed_us: 2000,
+    };
+
+    #[test]
+    fn arms_move_smoothly_and_in_a_safe_order() {
+        let pwms = MockPwms::new();
+        let mut signal: SemaphoreSignal<Infallible, MockPin, MockPwm> =
+            SemaphoreSignal::new(Servo::new(pwms.pwm(), ARM))
+                .with_lower_arm(Servo::new(pwms.pwm(), ARM));
+        let mut delay = MockDelay::default();
+        signal.update(0).unwrap();
+        assert_eq!(pwms.duty_cycles(), [1000, 1000]);
+
+        // the lower arm is raised first, with a slow start.
+        signal
+            .switch_to_aspect(HVMainSignalAspect::ProceedSlow, &mut delay)
+            .unwrap();
+        signal.update(2000).unwrap();
+        signal.update(2100).unwrap();
+        assert_eq!(pwms.duty_cycles(), [1000, 1028]);
+        signal.update(2500).unwrap();
+        assert_eq!(pwms.duty_cycles(), [1000, 1500]);
+        signal.update(3000).unwrap();
+        signal.update(3000).unwrap();
+        signal.update(4000).unwrap();
+        assert_eq!(pwms.duty_cycles(), [2000, 2000]);
+
+        // for stop, the upper arm is lowered first.
+        signal
+            .switch_to_aspect(HVMainSignalAspect::Stop, &mut delay)
+            .unwrap();
+        signal.update(5000).unwrap();
+        signal.update(5500).unwrap();
+        assert_eq!(pwms.duty_cycles(), [1500, 2000]);
+        signal.update(6000).unwrap();
+        signal.update(6000).unwrap();
+        signal.update(7000).unwrap();
+        assert_eq!(pwms.duty_cycles(), [1000, 1000]);
+    }
+}
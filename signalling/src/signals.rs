@@ -0,0 +1,3519 @@
+use core::cmp::Ordering;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::digital::PinState;
+
+use crate::blink::Blinker;
+use crate::commands::AspectCommand;
+use crate::panel::PanelOutput;
+use crate::zs3::Zs3Indicator;
+use crate::zs3::Zs3vIndicator;
+
+pub mod sncf;
+
+/// Duration of each on and off phase of blinking lamps, like the green lamp in Ks1 blinking, the Zs1 lamp or the white lamp in Bü1, so that they flash about once per second.
+pub(crate) const BLINK_HALF_PERIOD_MS: u32 = 500;
+
+/// The error returned when an aspect command has no corresponding aspect in a signalling system.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UnsupportedAspect;
+
+/// An aspect in any signalling system.
+pub trait SignalAspect:
+    Copy + PartialEq + TryFrom<AspectCommand, Error = UnsupportedAspect>
+{
+    /// The most restrictive aspect, which is safe to show at any time.
+    const STOP: Self;
+
+    /// ID of the signalling system, as reported in the serial protocol.
+    const SYSTEM_ID: &'static str;
+
+    /// Whether the rules of this signalling system allow notice lamps that flash instead of being lit steadily.
+    const ALLOWS_FLASHING_NOTICE_LAMPS: bool;
+
+    fn command_id(self) -> &'static str;
+
+    fn from_command_id(command_id: &[u8]) -> Option<Self>;
+
+    /// Returns whether this aspect blanks the signal, i.e. the signal is deactivated or dark.
+    fn blanks_signal(self) -> bool;
+
+    /// Returns whether this aspect is only shown temporarily, like the Zs1 substitution signal. Such aspects are never saved, so that the signal shows stop after a reboot.
+    fn is_temporary(self) -> bool;
+
+    /// Returns whether this aspect allows a train to pass the signal without an order, like Hp1 and Hp2, as opposed to stop, the substitution signals and aspects that blank the signal.
+    fn clears_signal(self) -> bool;
+
+    /// Returns how restrictive this aspect is for a train driver: 0 is the most restrictive aspect, and higher numbers are less restrictive. Aspects that blank the signal are the least restrictive, since they don’t restrict the train by themselves.
+    fn restrictiveness(self) -> u8;
+
+    /// Compares how restrictive this aspect is to the other aspect, where more restrictive aspects are less, so that [`SignalAspect::STOP`] is the minimum.
+    fn cmp_restrictiveness(self, other: Self) -> Ordering {
+        self.restrictiveness().cmp(&other.restrictiveness())
+    }
+
+    /// Returns the more restrictive of this and the other aspect, or this aspect if both are equally restrictive, like Hp1 and Hp1 on the counter track. This is the aspect to show whenever it’s unclear which of two aspects is right.
+    fn most_restrictive(self, other: Self) -> Self {
+        match self.cmp_restrictiveness(other) {
+            Ordering::Greater => other,
+            Ordering::Less | Ordering::Equal => self,
+        }
+    }
+
+    /// Returns whether the lamp with the given role is lit in this aspect, if the signal has such a lamp.
+    fn lights_lamp(self, role: LampRole) -> bool;
+
+    /// Returns whether the lamp with the given role blinks in this aspect. Blinking lamps are also considered lit.
+    fn blinks_lamp(self, role: LampRole) -> bool;
+}
+
+/// A signal, or a group of signals, in any signalling system.
+///
+/// Code that only uses this trait works with every signalling system, so that boards for different systems only differ in how they construct their signals.
+pub trait Signal {
+    type Aspect: SignalAspect;
+    /// The kind of output pin used for the lamps.
+    type Pin: OutputPin<Error = Self::Error>;
+    /// The output pin’s error type.
+    type Error;
+
+    /// Returns whether this signal supports the given aspect, since some aspects require optional lights.
+    fn supports_aspect(&self, aspect: Self::Aspect) -> bool;
+
+    /// Switches this signal to the given aspect. Signals that need to wait for their lamps to settle use the given delay.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    ///
+    /// # Panics
+    /// This function will panic if an unsupported aspect is set on this signal due to missing lamps. This condition is considered a logic bug; user code must ensure that signals are only ever used with aspects that they are designed for. The function [`Self::supports_aspect`] can be used to test whether a signal supports a certain aspect beforehand.
+    fn switch_to_aspect(
+        &mut self,
+        aspect: Self::Aspect,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Self::Error>;
+
+    /// Returns whether this signal can show the given aspect together with a Zs3 speed indicator showing the given speed, in tens of km/h. Signals without a speed indicator don’t support any speed.
+    fn supports_speed(&self, _aspect: Self::Aspect, _speed: u8) -> bool {
+        false
+    }
+
+    /// Switches this signal to the given aspect, with the Zs3 speed indicator showing the given speed in tens of km/h.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    ///
+    /// # Panics
+    /// This function will panic if the signal doesn’t support the aspect with this speed, like [`Self::switch_to_aspect`]. The function [`Self::supports_speed`] can be used to test this beforehand.
+    fn switch_to_aspect_with_speed(
+        &mut self,
+        _aspect: Self::Aspect,
+        _speed: u8,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Self::Error> {
+        panic!("illegal aspect for this light, no speed indicator available")
+    }
+
+    /// Returns whether this signal can show the given aspect together with a Zs2 direction indicator showing the given route, an uppercase ASCII letter. Signals without a direction indicator don’t support any route.
+    fn supports_route(&self, _aspect: Self::Aspect, _route: u8) -> bool {
+        false
+    }
+
+    /// Switches this signal to the given aspect, with the Zs2 direction indicator showing the given route letter and the Zs3 speed indicator showing the given speed or nothing.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    ///
+    /// # Panics
+    /// This function will panic if the signal doesn’t support the aspect with this route and speed, like [`Self::switch_to_aspect`]. The functions [`Self::supports_route`] and [`Self::supports_speed`] can be used to test this beforehand.
+    fn switch_to_aspect_with_route(
+        &mut self,
+        _aspect: Self::Aspect,
+        _speed: Option<u8>,
+        _route: u8,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Self::Error> {
+        panic!("illegal aspect for this light, no direction indicator available")
+    }
+
+    /// Returns the lamp with the given role, if this signal has it.
+    ///
+    /// Switching lamps directly bypasses all aspect logic, so this is only intended for maintenance purposes like testing the wiring. Afterwards, an aspect should be switched to again.
+    fn lamp(&mut self, role: LampRole) -> Option<&mut Self::Pin>;
+
+    /// Tells this signal which aspect the next signal along the line shows, so that a distant signal on this signal’s mast can announce it. Signals that don’t announce the next signal ignore it.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    fn show_next_aspect(
+        &mut self,
+        _next_aspect: Self::Aspect,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Keeps the announcement or distant signal of this signal permanently dark, or lets it announce again. This is meant for signals in front of a stub track, where there is no next main signal to announce. Signals without an announcement ignore it.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    fn suppress_announcement(
+        &mut self,
+        _is_suppressed: bool,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Advances time-dependent parts of the aspect, like blinking lamps, to the current time. This must be called regularly from the main loop, except while lamps are switched directly.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    fn update(&mut self, _now: u32) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// An optical main signal aspect in the H/V signalling system.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HVMainSignalAspect {
+    // Hp0: Halt
+    Stop,
+    // Hp0 mit Sh1/Ra12: Halt für Zugfahrten, Rangierfahrverbot aufgehoben.
+    StopWithShunting,
+    // Hp0 mit Zs1 (Ersatzsignal): am Halt zeigenden Signal ohne schriftlichen Befehl vorbeifahren.
+    Substitution,
+    // Hp0 mit Zs7 (Vorsichtssignal): am Halt zeigenden Signal vorbeifahren, auf Sicht weiterfahren.
+    Caution,
+    // Hp0 mit Zs8 (Gegengleis-Ersatzsignal): am Halt zeigenden Signal ohne schriftlichen Befehl vorbei in das Gegengleis fahren.
+    CounterTrackSubstitution,
+    // Hp1: Fahrt
+    Proceed,
+    // Hp1 mit Zs6 (Gegengleisanzeiger): Fahrt in das Gegengleis.
+    ProceedCounterTrack,
+    // Hp2: Langsamfahrt mit 40km/h oder mit der im Buchfahrplan oder durch Zs3 angegebenen Geschwindigkeit.
+    ProceedSlow,
+    // Hp2 mit Zs6 (Gegengleisanzeiger): Langsamfahrt in das Gegengleis.
+    ProceedSlowCounterTrack,
+    // Signal betrieblich abgeschaltet, Kennlicht aktiv.
+    Deactivated,
+    // Signal dunkel, da übergeordnete Zugbeeinflussung (LZB oder ETCS) statt dem Lichtsignal gültig ist.
+    Dark,
+}
+
+impl SignalAspect for HVMainSignalAspect {
+    const STOP: Self = Self::Stop;
+    const SYSTEM_ID: &'static str = "HV";
+    // some administrations flash the Kennlicht, e.g. while the signal is deactivated.
+    const ALLOWS_FLASHING_NOTICE_LAMPS: bool = true;
+
+    fn command_id(self) -> &'static str {
+        match self {
+            Self::Stop => "0",
+            Self::StopWithShunting => "S",
+            Self::Substitution => "Z1",
+            Self::Caution => "Z7",
+            Self::CounterTrackSubstitution => "Z8",
+            Self::Proceed => "1",
+            Self::ProceedCounterTrack => "Z6",
+            Self::ProceedSlow => "2",
+            Self::ProceedSlowCounterTrack => "Z62",
+            Self::Deactivated => "A",
+            Self::Dark => "D",
+        }
+    }
+
+    fn from_command_id(command_id: &[u8]) -> Option<Self> {
+        match command_id {
+            b"0" => Some(Self::Stop),
+            b"S" => Some(Self::StopWithShunting),
+            b"Z1" => Some(Self::Substitution),
+            b"Z7" => Some(Self::Caution),
+            b"Z8" => Some(Self::CounterTrackSubstitution),
+            b"1" => Some(Self::Proceed),
+            b"Z6" => Some(Self::ProceedCounterTrack),
+            b"2" => Some(Self::ProceedSlow),
+            b"Z62" => Some(Self::ProceedSlowCounterTrack),
+            b"A" => Some(Self::Deactivated),
+            b"D" => Some(Self::Dark),
+            _ => None,
+        }
+    }
+
+    fn blanks_signal(self) -> bool {
+        matches!(self, Self::Deactivated | Self::Dark)
+    }
+
+    fn is_temporary(self) -> bool {
+        matches!(self, Self::Substitution | Self::CounterTrackSubstitution)
+    }
+
+    fn clears_signal(self) -> bool {
+        matches!(
+            self,
+            Self::Proceed
+                | Self::ProceedCounterTrack
+                | Self::ProceedSlow
+                | Self::ProceedSlowCounterTrack
+        )
+    }
+
+    fn restrictiveness(self) -> u8 {
+        match self {
+            Self::Stop => 0,
+            Self::StopWithShunting => 1,
+            Self::Substitution | Self::CounterTrackSubstitution => 2,
+            Self::Caution => 3,
+            Self::ProceedSlow | Self::ProceedSlowCounterTrack => 4,
+            Self::Proceed | Self::ProceedCounterTrack => 5,
+            Self::Deactivated => 6,
+            Self::Dark => 7,
+        }
+    }
+
+    fn lights_lamp(self, role: LampRole) -> bool {
+        match role {
+            LampRole::MainRed => matches!(
+                self,
+                Self::Stop
+                    | Self::StopWithShunting
+                    | Self::Substitution
+                    | Self::Caution
+                    | Self::CounterTrackSubstitution
+            ),
+            LampRole::MainShuntingLower | LampRole::MainShuntingUpper => {
+                self == Self::StopWithShunting
+            }
+            // the blinking Zs1 lamp is lit at least half of the time.
+            LampRole::MainSubstitution => self == Self::Substitution,
+            LampRole::MainCautionLeft
+            | LampRole::MainCautionRight
+            | LampRole::MainCautionBottom => self == Self::Caution,
+            // like Zs1, the blinking Zs8 lamps are lit at least half of the time.
+            LampRole::MainCounterTrackSubstitution => self == Self::CounterTrackSubstitution,
+            LampRole::MainCounterTrack => {
+                matches!(
+                    self,
+                    Self::ProceedCounterTrack | Self::ProceedSlowCounterTrack
+                )
+            }
+            LampRole::MainGreen => matches!(
+                self,
+                Self::Proceed
+                    | Self::ProceedCounterTrack
+                    | Self::ProceedSlow
+                    | Self::ProceedSlowCounterTrack
+            ),
+            LampRole::MainYellow => {
+                matches!(self, Self::ProceedSlow | Self::ProceedSlowCounterTrack)
+            }
+            LampRole::MainNotice => self == Self::Deactivated,
+            LampRole::RepeaterNotice => self != Self::Dark,
+            _ => HVAnnouncementSignalAspect::from(self).lights_lamp(role),
+        }
+    }
+
+    fn blinks_lamp(self, role: LampRole) -> bool {
+        matches!(
+            (role, self),
+            (LampRole::MainSubstitution, Self::Substitution)
+                | (
+                    LampRole::MainCounterTrackSubstitution,
+                    Self::CounterTrackSubstitution
+                )
+        )
+    }
+}
+
+impl TryFrom<AspectCommand> for HVMainSignalAspect {
+    type Error = UnsupportedAspect;
+
+    fn try_from(value: AspectCommand) -> Result<Self, Self::Error> {
+        match value {
+            AspectCommand::Zero => Ok(Self::Stop),
+            AspectCommand::One => Ok(Self::Proceed),
+            AspectCommand::Two => Ok(Self::ProceedSlow),
+            AspectCommand::Three => Err(UnsupportedAspect),
+            AspectCommand::Shunting => Ok(Self::StopWithShunting),
+            AspectCommand::Substitution => Ok(Self::Substitution),
+            AspectCommand::Caution => Ok(Self::Caution),
+            AspectCommand::CounterTrack => Ok(Self::ProceedCounterTrack),
+            AspectCommand::CounterTrackSlow => Ok(Self::ProceedSlowCounterTrack),
+            AspectCommand::CounterTrackSubstitution => Ok(Self::CounterTrackSubstitution),
+            AspectCommand::ShuntingForbidden
+            | AspectCommand::ShuntingAllowed
+            | AspectCommand::Sv0
+            | AspectCommand::Sv1
+            | AspectCommand::Sv2
+            | AspectCommand::Sv3
+            | AspectCommand::Sv4
+            | AspectCommand::Sv5
+            | AspectCommand::Sv6
+            | AspectCommand::CrossingStop
+            | AspectCommand::CrossingProceed => Err(UnsupportedAspect),
+            AspectCommand::Deactivated => Ok(Self::Deactivated),
+            AspectCommand::Dark => Ok(Self::Dark),
+        }
+    }
+}
+
+/// An optical announcement signal aspect in the H/V signalling system.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HVAnnouncementSignalAspect {
+    // Vr0: Halt erwarten
+    ExpectStop,
+    // Vr1: Fahrt erwarten
+    ExpectProceed,
+    // Vr2: Langsamfahrt erwarten
+    ExpectProceedSlow,
+    // Signal betrieblich abgeschaltet, Kennlicht aktiv.
+    Deactivated,
+    // Signal dunkel, da übergeordnete Zugbeeinflussung (LZB oder ETCS) statt dem Lichtsignal gültig ist.
+    Dark,
+}
+
+impl From<HVMainSignalAspect> for HVAnnouncementSignalAspect {
+    fn from(value: HVMainSignalAspect) -> Self {
+        match value {
+            // the main signal still shows stop.
+            HVMainSignalAspect::Stop
+            | HVMainSignalAspect::StopWithShunting
+            | HVMainSignalAspect::Substitution
+            | HVMainSignalAspect::Caution
+            | HVMainSignalAspect::CounterTrackSubstitution => Self::ExpectStop,
+            HVMainSignalAspect::Proceed | HVMainSignalAspect::ProceedCounterTrack => {
+                Self::ExpectProceed
+            }
+            HVMainSignalAspect::ProceedSlow | HVMainSignalAspect::ProceedSlowCounterTrack => {
+                Self::ExpectProceedSlow
+            }
+            HVMainSignalAspect::Deactivated => Self::Deactivated,
+            HVMainSignalAspect::Dark => Self::Dark,
+        }
+    }
+}
+
+impl TryFrom<AspectCommand> for HVAnnouncementSignalAspect {
+    type Error = UnsupportedAspect;
+
+    fn try_from(value: AspectCommand) -> Result<Self, Self::Error> {
+        HVMainSignalAspect::try_from(value).map(Self::from)
+    }
+}
+
+impl SignalAspect for HVAnnouncementSignalAspect {
+    const STOP: Self = Self::ExpectStop;
+    const SYSTEM_ID: &'static str = "HV";
+    const ALLOWS_FLASHING_NOTICE_LAMPS: bool = true;
+
+    fn command_id(self) -> &'static str {
+        match self {
+            Self::ExpectStop => "0",
+            Self::ExpectProceed => "1",
+            Self::ExpectProceedSlow => "2",
+            Self::Deactivated => "A",
+            Self::Dark => "D",
+        }
+    }
+
+    fn from_command_id(command_id: &[u8]) -> Option<Self> {
+        HVMainSignalAspect::from_command_id(command_id).map(Self::from)
+    }
+
+    fn blanks_signal(self) -> bool {
+        matches!(self, Self::Deactivated | Self::Dark)
+    }
+
+    fn is_temporary(self) -> bool {
+        false
+    }
+
+    fn clears_signal(self) -> bool {
+        // only the main signal can allow a train to pass.
+        false
+    }
+
+    fn restrictiveness(self) -> u8 {
+        HVMainSignalAspect::restrictiveness(match self {
+            Self::ExpectStop => HVMainSignalAspect::Stop,
+            Self::ExpectProceed => HVMainSignalAspect::Proceed,
+            Self::ExpectProceedSlow => HVMainSignalAspect::ProceedSlow,
+            Self::Deactivated => HVMainSignalAspect::Deactivated,
+            Self::Dark => HVMainSignalAspect::Dark,
+        })
+    }
+
+    fn lights_lamp(self, role: LampRole) -> bool {
+        match role {
+            LampRole::AnnouncementGreenUpper => {
+                matches!(self, Self::ExpectProceed | Self::ExpectProceedSlow)
+            }
+            LampRole::AnnouncementGreenLower => self == Self::ExpectProceed,
+            LampRole::AnnouncementYellowUpper => self == Self::ExpectStop,
+            LampRole::AnnouncementYellowLower => {
+                matches!(self, Self::ExpectStop | Self::ExpectProceedSlow)
+            }
+            // at reduced distance, the notice lamp is also lit in other aspects.
+            LampRole::AnnouncementNotice => self == Self::Deactivated,
+            _ => false,
+        }
+    }
+
+    fn blinks_lamp(self, _role: LampRole) -> bool {
+        false
+    }
+}
+
+/// A single lamp of a signal group, used for controlling lamps independently of any aspect. The roles are named after the H/V lamps; a Ks distant signal and the right-hand lamps of an Sv signal use the upper announcement lamps, and the upper red lamp of an SNCF carré uses the dwarf signal’s red lamp.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LampRole {
+    MainRed,
+    MainGreen,
+    MainYellow,
+    MainNotice,
+    MainSubstitution,
+    MainCautionLeft,
+    MainCautionRight,
+    MainCautionBottom,
+    MainShuntingLower,
+    MainShuntingUpper,
+    MainCounterTrack,
+    MainCounterTrackSubstitution,
+    AnnouncementGreenUpper,
+    AnnouncementGreenLower,
+    AnnouncementYellowUpper,
+    AnnouncementYellowLower,
+    AnnouncementNotice,
+    RepeaterNotice,
+    DwarfRed,
+    DwarfWhite,
+    DwarfNotice,
+    CrossingYellow,
+    CrossingWhite,
+}
+
+impl LampRole {
+    pub const ALL: [Self; 23] = [
+        Self::MainRed,
+        Self::MainGreen,
+        Self::MainYellow,
+        Self::MainNotice,
+        Self::MainSubstitution,
+        Self::MainCautionLeft,
+        Self::MainCautionRight,
+        Self::MainCautionBottom,
+        Self::MainShuntingLower,
+        Self::MainShuntingUpper,
+        Self::MainCounterTrack,
+        Self::MainCounterTrackSubstitution,
+        Self::AnnouncementGreenUpper,
+        Self::AnnouncementGreenLower,
+        Self::AnnouncementYellowUpper,
+        Self::AnnouncementYellowLower,
+        Self::AnnouncementNotice,
+        Self::RepeaterNotice,
+        Self::DwarfRed,
+        Self::DwarfWhite,
+        Self::DwarfNotice,
+        Self::CrossingYellow,
+        Self::CrossingWhite,
+    ];
+
+    pub fn command_id(self) -> &'static str {
+        match self {
+            Self::MainRed => "MR",
+            Self::MainGreen => "MG",
+            Self::MainYellow => "MY",
+            Self::MainNotice => "MN",
+            Self::MainSubstitution => "MZ",
+            Self::MainCautionLeft => "MCL",
+            Self::MainCautionRight => "MCR",
+            Self::MainCautionBottom => "MCB",
+            Self::MainShuntingLower => "MSL",
+            Self::MainShuntingUpper => "MSU",
+            Self::MainCounterTrack => "MZ6",
+            Self::MainCounterTrackSubstitution => "MZ8",
+            Self::AnnouncementGreenUpper => "AGU",
+            Self::AnnouncementGreenLower => "AGL",
+            Self::AnnouncementYellowUpper => "AYU",
+            Self::AnnouncementYellowLower => "AYL",
+            Self::AnnouncementNotice => "AN",
+            Self::RepeaterNotice => "RN",
+            Self::DwarfRed => "DR",
+            Self::DwarfWhite => "DW",
+            Self::DwarfNotice => "DN",
+            Self::CrossingYellow => "BY",
+            Self::CrossingWhite => "BW",
+        }
+    }
+
+    pub fn from_command_id(command_id: &[u8]) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|role| role.command_id().as_bytes() == command_id)
+    }
+}
+
+/// A lamp of an H/V main signal, used for describing aspect transitions.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MainLamp {
+    Red,
+    Green,
+    Yellow,
+    Notice,
+    Zs1,
+    // all three Zs7 lamps at once.
+    Zs7,
+    // both Sh1 lamps at once.
+    Sh1,
+    Zs6,
+    Zs8,
+}
+
+/// One step of an aspect transition: switching a single lamp of a main signal.
+type TransitionStep = (MainLamp, PinState);
+
+/// An optical main signal in the H/V signalling system.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used. Its parameters additionally include the output pin’s error type (which some functions also return).
+pub struct HVMainSignal<Error, PinType: OutputPin<Error = Error>> {
+    // First (main) red lamp.
+    red_lamp_1: PinType,
+    // Yellow lamp. May not exist if the signal cannot show Hp2 (Langsamfahrt).
+    yellow_lamp: Option<PinType>,
+    // Green lamp.
+    green_lamp: PinType,
+    // Notice lamp, used for Deactivated state.
+    notice_lamp: Option<PinType>,
+    // Whether the notice lamp flashes instead of being lit steadily.
+    flashes_notice_lamp: bool,
+    // Blinker for the notice lamp, while it is lit and flashes.
+    notice_blinker: Option<Blinker>,
+    // White Zs1 lamp, used for Substitution state.
+    substitution_lamp: Option<PinType>,
+    // Blinker for the Zs1 lamp while Substitution is shown, or for the Zs8 lamps while CounterTrackSubstitution is shown.
+    substitution_blinker: Option<Blinker>,
+    // Three yellow Zs7 lamps (upper left, upper right, bottom), used for Caution state.
+    caution_lamps: Option<[PinType; 3]>,
+    // Two white Sh1 lamps (lower left, upper right), used for StopWithShunting state.
+    shunting_lamps: Option<[PinType; 2]>,
+    // White Zs6 stripe, used for the counter-track proceed states.
+    counter_track_lamp: Option<PinType>,
+    // Three white Zs8 lamps forming a stripe, switched together and used for CounterTrackSubstitution state.
+    counter_track_substitution_lamp: Option<PinType>,
+    // Aspect that was last switched to; all lamps are off initially.
+    aspect: HVMainSignalAspect,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> HVMainSignal<Error, PinType> {
+    pub fn new(red_lamp: PinType, green_lamp: PinType) -> Self {
+        Self {
+            red_lamp_1: red_lamp,
+            yellow_lamp: None,
+            green_lamp,
+            notice_lamp: None,
+            flashes_notice_lamp: false,
+            notice_blinker: None,
+            substitution_lamp: None,
+            substitution_blinker: None,
+            caution_lamps: None,
+            shunting_lamps: None,
+            counter_track_lamp: None,
+            counter_track_substitution_lamp: None,
+            aspect: HVMainSignalAspect::Dark,
+        }
+    }
+
+    /// Adds a yellow lamp to this main signal.
+    pub fn with_yellow_lamp(mut self, yellow_lamp: PinType) -> Self {
+        self.yellow_lamp = Some(yellow_lamp);
+        self
+    }
+
+    /// Adds a notice lamp to this main signal.
+    pub fn with_notice_lamp(mut self, notice_lamp: PinType) -> Self {
+        self.notice_lamp = Some(notice_lamp);
+        self
+    }
+
+    /// Makes the notice lamp flash instead of being lit steadily.
+    pub fn with_flashing_notice_lamp(mut self) -> Self {
+        self.flashes_notice_lamp = true;
+        self
+    }
+
+    /// Adds a white Zs1 lamp to this main signal, for showing the substitution signal.
+    pub fn with_substitution_lamp(mut self, substitution_lamp: PinType) -> Self {
+        self.substitution_lamp = Some(substitution_lamp);
+        self
+    }
+
+    /// Adds the three yellow Zs7 lamps (upper left, upper right, bottom) to this main signal, for showing the caution signal.
+    pub fn with_caution_lamps(mut self, caution_lamps: [PinType; 3]) -> Self {
+        self.caution_lamps = Some(caution_lamps);
+        self
+    }
+
+    /// Adds the two white Sh1 lamps (lower left, upper right) to this main signal, for permitting shunting while it shows stop.
+    pub fn with_shunting_lamps(mut self, shunting_lamps: [PinType; 2]) -> Self {
+        self.shunting_lamps = Some(shunting_lamps);
+        self
+    }
+
+    /// Adds a white Zs6 stripe to this main signal, for showing that a proceed aspect leads onto the counter track.
+    pub fn with_counter_track_lamp(mut self, counter_track_lamp: PinType) -> Self {
+        self.counter_track_lamp = Some(counter_track_lamp);
+        self
+    }
+
+    /// Adds the white Zs8 lamps to this main signal, for showing the counter-track substitution signal.
+    pub fn with_counter_track_substitution_lamp(
+        mut self,
+        counter_track_substitution_lamp: PinType,
+    ) -> Self {
+        self.counter_track_substitution_lamp = Some(counter_track_substitution_lamp);
+        self
+    }
+
+    fn switch_optionally(pin: &mut Option<PinType>, state: PinState) -> Result<(), Error> {
+        pin.as_mut().map(|pin| pin.set_state(state)).transpose()?;
+        Ok(())
+    }
+
+    /// Returns the order in which the lamps are switched when changing from one aspect to another.
+    ///
+    /// Every sequence switches all lamps, so that the target aspect is reached even if the lamps were changed behind the signal’s back. The order is chosen so that no intermediate state shows an aspect that is less restrictive than both the old and the new aspect. Lit red always means stop, and green without yellow would be a transient proceed aspect, whose speed could be too high.
+    fn transition_steps(
+        from: HVMainSignalAspect,
+        to: HVMainSignalAspect,
+    ) -> &'static [TransitionStep] {
+        use HVMainSignalAspect::*;
+        use MainLamp::*;
+        use PinState::{High, Low};
+        match (from, to) {
+            // red first, then everything else is irrelevant
+            (_, Stop) => &[
+                (Red, High),
+                (Zs1, Low),
+                (Zs7, Low),
+                (Sh1, Low),
+                (Zs6, Low),
+                (Zs8, Low),
+                (Green, Low),
+                (Yellow, Low),
+                (Notice, Low),
+            ],
+            // Zs1, Zs7, Zs8 and Sh1 are only valid next to a lit red lamp, so they come last
+            (_, Substitution) => &[
+                (Red, High),
+                (Green, Low),
+                (Yellow, Low),
+                (Notice, Low),
+                (Zs6, Low),
+                (Zs7, Low),
+                (Zs8, Low),
+                (Sh1, Low),
+                (Zs1, High),
+            ],
+            (_, Caution) => &[
+                (Red, High),
+                (Green, Low),
+                (Yellow, Low),
+                (Notice, Low),
+                (Zs6, Low),
+                (Zs1, Low),
+                (Zs8, Low),
+                (Sh1, Low),
+                (Zs7, High),
+            ],
+            (_, CounterTrackSubstitution) => &[
+                (Red, High),
+                (Green, Low),
+                (Yellow, Low),
+                (Notice, Low),
+                (Zs6, Low),
+                (Zs1, Low),
+                (Zs7, Low),
+                (Sh1, Low),
+                (Zs8, High),
+            ],
+            (_, StopWithShunting) => &[
+                (Red, High),
+                (Green, Low),
+                (Yellow, Low),
+                (Notice, Low),
+                (Zs6, Low),
+                (Zs1, Low),
+                (Zs7, Low),
+                (Zs8, Low),
+                (Sh1, High),
+            ],
+            // upgrade: yellow may only extinguish after green was switched on successfully
+            (ProceedSlow | ProceedSlowCounterTrack, Proceed) => &[
+                (Green, High),
+                (Yellow, Low),
+                (Zs1, Low),
+                (Zs7, Low),
+                (Zs8, Low),
+                (Sh1, Low),
+                (Zs6, Low),
+                (Red, Low),
+                (Notice, Low),
+            ],
+            // Zs1, Zs7, Zs8 and Sh1 go off before red, so that they are never shown on their own
+            (
+                Stop
+                | StopWithShunting
+                | Substitution
+                | Caution
+                | CounterTrackSubstitution
+                | Proceed
+                | ProceedCounterTrack
+                | Deactivated
+                | Dark,
+                Proceed,
+            ) => &[
+                (Green, High),
+                (Zs1, Low),
+                (Zs7, Low),
+                (Zs8, Low),
+                (Sh1, Low),
+                (Zs6, Low),
+                (Red, Low),
+                (Yellow, Low),
+                (Notice, Low),
+            ],
+            // Zs6 is only valid next to a proceed aspect, so it comes last
+            (ProceedSlow | ProceedSlowCounterTrack, ProceedCounterTrack) => &[
+                (Green, High),
+                (Yellow, Low),
+                (Zs1, Low),
+                (Zs7, Low),
+                (Zs8, Low),
+                (Sh1, Low),
+                (Red, Low),
+                (Notice, Low),
+                (Zs6, High),
+            ],
+            (
+                Stop
+                | StopWithShunting
+                | Substitution
+                | Caution
+                | CounterTrackSubstitution
+                | Proceed
+                | ProceedCounterTrack
+                | Deactivated
+                | Dark,
+                ProceedCounterTrack,
+            ) => &[
+                (Green, High),
+                (Zs1, Low),
+                (Zs7, Low),
+                (Zs8, Low),
+                (Sh1, Low),
+                (Red, Low),
+                (Yellow, Low),
+                (Notice, Low),
+                (Zs6, High),
+            ],
+            // downgrade: yellow must be lit before green is (re-)confirmed
+            (Proceed | ProceedCounterTrack, ProceedSlow) => &[
+                (Yellow, High),
+                (Green, High),
+                (Zs1, Low),
+                (Zs7, Low),
+                (Zs8, Low),
+                (Sh1, Low),
+                (Zs6, Low),
+                (Red, Low),
+                (Notice, Low),
+            ],
+            // switch yellow on before green to avoid transient proceed aspect
+            (
+                Stop
+                | StopWithShunting
+                | Substitution
+                | Caution
+                | CounterTrackSubstitution
+                | ProceedSlow
+                | ProceedSlowCounterTrack
+                | Deactivated
+                | Dark,
+                ProceedSlow,
+            ) => &[
+                (Yellow, High),
+                (Green, High),
+                (Zs1, Low),
+                (Zs7, Low),
+                (Zs8, Low),
+                (Sh1, Low),
+                (Zs6, Low),
+                (Red, Low),
+                (Notice, Low),
+            ],
+            (Proceed | ProceedCounterTrack, ProceedSlowCounterTrack) => &[
+                (Yellow, High),
+                (Green, High),
+                (Zs1, Low),
+                (Zs7, Low),
+                (Zs8, Low),
+                (Sh1, Low),
+                (Red, Low),
+                (Notice, Low),
+                (Zs6, High),
+            ],
+            (
+                Stop
+                | StopWithShunting
+                | Substitution
+                | Caution
+                | CounterTrackSubstitution
+                | ProceedSlow
+                | ProceedSlowCounterTrack
+                | Deactivated
+                | Dark,
+                ProceedSlowCounterTrack,
+            ) => &[
+                (Yellow, High),
+                (Green, High),
+                (Zs1, Low),
+                (Zs7, Low),
+                (Zs8, Low),
+                (Sh1, Low),
+                (Red, Low),
+                (Notice, Low),
+                (Zs6, High),
+            ],
+            // green off before yellow to avoid transient proceed aspect
+            (_, Deactivated) => &[
+                (Notice, High),
+                (Green, Low),
+                (Yellow, Low),
+                (Zs1, Low),
+                (Zs7, Low),
+                (Zs8, Low),
+                (Sh1, Low),
+                (Zs6, Low),
+                (Red, Low),
+            ],
+            (_, Dark) => &[
+                (Green, Low),
+                (Yellow, Low),
+                (Zs1, Low),
+                (Zs7, Low),
+                (Zs8, Low),
+                (Sh1, Low),
+                (Zs6, Low),
+                (Red, Low),
+                (Notice, Low),
+            ],
+        }
+    }
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> Signal for HVMainSignal<Error, PinType> {
+    type Aspect = HVMainSignalAspect;
+    type Pin = PinType;
+    type Error = Error;
+
+    fn supports_aspect(&self, aspect: HVMainSignalAspect) -> bool {
+        match aspect {
+            // always supported
+            HVMainSignalAspect::Stop | HVMainSignalAspect::Dark | HVMainSignalAspect::Proceed => {
+                true
+            }
+            HVMainSignalAspect::ProceedSlow => self.yellow_lamp.is_some(),
+            HVMainSignalAspect::Deactivated => self.notice_lamp.is_some(),
+            HVMainSignalAspect::Substitution => self.substitution_lamp.is_some(),
+            HVMainSignalAspect::Caution => self.caution_lamps.is_some(),
+            HVMainSignalAspect::StopWithShunting => self.shunting_lamps.is_some(),
+            HVMainSignalAspect::ProceedCounterTrack => self.counter_track_lamp.is_some(),
+            HVMainSignalAspect::ProceedSlowCounterTrack => {
+                self.counter_track_lamp.is_some() && self.yellow_lamp.is_some()
+            }
+            HVMainSignalAspect::CounterTrackSubstitution => {
+                self.counter_track_substitution_lamp.is_some()
+            }
+        }
+    }
+
+    /// Switching stops at the first failing lamp, so that later lamps (which might make the aspect less restrictive) are never switched.
+    fn switch_to_aspect(
+        &mut self,
+        aspect: HVMainSignalAspect,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        // logic bug, since user code should ensure to never try to enable illegal aspects on signals that don’t support them
+        match aspect {
+            HVMainSignalAspect::ProceedSlow | HVMainSignalAspect::ProceedSlowCounterTrack
+                if self.yellow_lamp.is_none() =>
+            {
+                panic!("illegal aspect for this light, no yellow available")
+            }
+            HVMainSignalAspect::ProceedCounterTrack
+            | HVMainSignalAspect::ProceedSlowCounterTrack
+                if self.counter_track_lamp.is_none() =>
+            {
+                panic!("illegal aspect for this light, no Zs6 lamp available")
+            }
+            HVMainSignalAspect::CounterTrackSubstitution
+                if self.counter_track_substitution_lamp.is_none() =>
+            {
+                panic!("illegal aspect for this light, no Zs8 lamps available")
+            }
+            HVMainSignalAspect::Deactivated if self.notice_lamp.is_none() => {
+                panic!("illegal aspect for this light, no notice lamp available")
+            }
+            HVMainSignalAspect::Substitution if self.substitution_lamp.is_none() => {
+                panic!("illegal aspect for this light, no Zs1 lamp available")
+            }
+            HVMainSignalAspect::Caution if self.caution_lamps.is_none() => {
+                panic!("illegal aspect for this light, no Zs7 lamps available")
+            }
+            HVMainSignalAspect::StopWithShunting if self.shunting_lamps.is_none() => {
+                panic!("illegal aspect for this light, no Sh1 lamps available")
+            }
+            _ => {}
+        }
+
+        self.substitution_blinker = None;
+        self.notice_blinker = None;
+        for (lamp, state) in Self::transition_steps(self.aspect, aspect) {
+            match lamp {
+                MainLamp::Red => self.red_lamp_1.set_state(*state)?,
+                MainLamp::Green => self.green_lamp.set_state(*state)?,
+                MainLamp::Yellow => Self::switch_optionally(&mut self.yellow_lamp, *state)?,
+                MainLamp::Notice => Self::switch_optionally(&mut self.notice_lamp, *state)?,
+                MainLamp::Zs1 => Self::switch_optionally(&mut self.substitution_lamp, *state)?,
+                MainLamp::Zs6 => Self::switch_optionally(&mut self.counter_track_lamp, *state)?,
+                MainLamp::Zs8 => {
+                    Self::switch_optionally(&mut self.counter_track_substitution_lamp, *state)?
+                }
+                MainLamp::Zs7 => {
+                    for lamp in self.caution_lamps.iter_mut().flatten() {
+                        lamp.set_state(*state)?;
+                    }
+                }
+                MainLamp::Sh1 => {
+                    for lamp in self.shunting_lamps.iter_mut().flatten() {
+                        lamp.set_state(*state)?;
+                    }
+                }
+            }
+        }
+        if aspect.is_temporary() {
+            // the blinker starts in its off phase, so its first toggle keeps the lit lamp on and starts the blinking.
+            self.substitution_blinker = Some(Blinker::new(BLINK_HALF_PERIOD_MS));
+        }
+        if aspect == HVMainSignalAspect::Deactivated && self.flashes_notice_lamp {
+            self.notice_blinker = Some(Blinker::new(BLINK_HALF_PERIOD_MS));
+        }
+        self.aspect = aspect;
+        Ok(())
+    }
+
+    fn lamp(&mut self, role: LampRole) -> Option<&mut PinType> {
+        match role {
+            LampRole::MainRed => Some(&mut self.red_lamp_1),
+            LampRole::MainGreen => Some(&mut self.green_lamp),
+            LampRole::MainYellow => self.yellow_lamp.as_mut(),
+            LampRole::MainNotice => self.notice_lamp.as_mut(),
+            LampRole::MainSubstitution => self.substitution_lamp.as_mut(),
+            LampRole::MainCautionLeft => self.caution_lamps.as_mut().map(|lamps| &mut lamps[0]),
+            LampRole::MainCautionRight => self.caution_lamps.as_mut().map(|lamps| &mut lamps[1]),
+            LampRole::MainCautionBottom => self.caution_lamps.as_mut().map(|lamps| &mut lamps[2]),
+            LampRole::MainShuntingLower => self.shunting_lamps.as_mut().map(|lamps| &mut lamps[0]),
+            LampRole::MainShuntingUpper => self.shunting_lamps.as_mut().map(|lamps| &mut lamps[1]),
+            LampRole::MainCounterTrack => self.counter_track_lamp.as_mut(),
+            LampRole::MainCounterTrackSubstitution => self.counter_track_substitution_lamp.as_mut(),
+            _ => None,
+        }
+    }
+
+    fn update(&mut self, now: u32) -> Result<(), Error> {
+        if let Some(substitution_blinker) = &mut self.substitution_blinker {
+            if let Some(is_on) = substitution_blinker.update(now) {
+                let blinking_lamp = if self.aspect == HVMainSignalAspect::CounterTrackSubstitution {
+                    &mut self.counter_track_substitution_lamp
+                } else {
+                    &mut self.substitution_lamp
+                };
+                Self::switch_optionally(blinking_lamp, is_on.into())?;
+            }
+        }
+        if let Some(notice_blinker) = &mut self.notice_blinker {
+            if let Some(is_on) = notice_blinker.update(now) {
+                Self::switch_optionally(&mut self.notice_lamp, is_on.into())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An optical announcement signal in the H/V signalling system.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used. Its parameters additionally include the output pin’s error type (which some functions also return).
+pub struct HVAnnouncementSignal<Error, PinType: OutputPin<Error = Error>> {
+    // Upper right green lamp.
+    green_lamp_upper: PinType,
+    // Lower left green lamp.
+    green_lamp_lower: PinType,
+    // Upper right green lamp.
+    yellow_lamp_upper: PinType,
+    // Lower left green lamp.
+    yellow_lamp_lower: PinType,
+    // Notice lamp, used for Deactivated state.
+    notice_lamp: Option<PinType>,
+    // Whether the notice lamp flashes instead of being lit steadily.
+    flashes_notice_lamp: bool,
+    // Blinker for the notice lamp, while it is lit and flashes.
+    notice_blinker: Option<Blinker>,
+    // Whether this signal is a repeater signal or is at reduced breaking distance from the corresponding main signal.
+    pub is_repeater_or_reduced_distance: bool,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> HVAnnouncementSignal<Error, PinType> {
+    pub fn new(
+        green_lamp_upper: PinType,
+        green_lamp_lower: PinType,
+        yellow_lamp_upper: PinType,
+        yellow_lamp_lower: PinType,
+    ) -> Self {
+        Self {
+            green_lamp_upper,
+            green_lamp_lower,
+            yellow_lamp_upper,
+            yellow_lamp_lower,
+            notice_lamp: None,
+            flashes_notice_lamp: false,
+            notice_blinker: None,
+            is_repeater_or_reduced_distance: false,
+        }
+    }
+
+    /// Adds a notice lamp to this announcement signal.
+    pub fn with_notice_lamp(mut self, notice_lamp: PinType) -> Self {
+        self.notice_lamp = Some(notice_lamp);
+        self
+    }
+
+    /// Makes the notice lamp flash instead of being lit steadily, both for the Deactivated state and at reduced distance.
+    pub fn with_flashing_notice_lamp(mut self) -> Self {
+        self.flashes_notice_lamp = true;
+        self
+    }
+
+    fn switch_optionally(pin: &mut Option<PinType>, state: PinState) -> Result<(), Error> {
+        pin.as_mut().map(|pin| pin.set_state(state)).transpose()?;
+        Ok(())
+    }
+
+    fn notice_lamp_for_distance(&self) -> PinState {
+        match self.is_repeater_or_reduced_distance {
+            true => PinState::High,
+            false => PinState::Low,
+        }
+    }
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> Signal for HVAnnouncementSignal<Error, PinType> {
+    type Aspect = HVAnnouncementSignalAspect;
+    type Pin = PinType;
+    type Error = Error;
+
+    fn supports_aspect(&self, aspect: HVAnnouncementSignalAspect) -> bool {
+        match aspect {
+            HVAnnouncementSignalAspect::Deactivated => self.notice_lamp.is_some(),
+            // always supported
+            _ => true,
+        }
+    }
+
+    fn switch_to_aspect(
+        &mut self,
+        aspect: HVAnnouncementSignalAspect,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        let normal_notice_lamp_state = self.notice_lamp_for_distance();
+        Self::switch_optionally(&mut self.notice_lamp, normal_notice_lamp_state)?;
+        match aspect {
+            HVAnnouncementSignalAspect::ExpectStop => {
+                self.yellow_lamp_upper.set_high()?;
+                self.yellow_lamp_lower.set_high()?;
+                self.green_lamp_lower.set_low()?;
+                self.green_lamp_upper.set_low()?;
+            }
+            HVAnnouncementSignalAspect::ExpectProceed => {
+                self.green_lamp_lower.set_high()?;
+                self.green_lamp_upper.set_high()?;
+                self.yellow_lamp_upper.set_low()?;
+                self.yellow_lamp_lower.set_low()?;
+            }
+            HVAnnouncementSignalAspect::ExpectProceedSlow => {
+                self.yellow_lamp_lower.set_high()?;
+                self.green_lamp_upper.set_high()?;
+                self.yellow_lamp_upper.set_low()?;
+                self.green_lamp_lower.set_low()?;
+            }
+            HVAnnouncementSignalAspect::Deactivated => {
+                if self.notice_lamp.is_none() {
+                    panic!("illegal aspect for this light, no notice lamp available");
+                }
+
+                Self::switch_optionally(&mut self.notice_lamp, PinState::High)?;
+                self.yellow_lamp_upper.set_low()?;
+                self.yellow_lamp_lower.set_low()?;
+                self.green_lamp_lower.set_low()?;
+                self.green_lamp_upper.set_low()?;
+            }
+            HVAnnouncementSignalAspect::Dark => {
+                self.green_lamp_lower.set_low()?;
+                self.green_lamp_upper.set_low()?;
+                self.yellow_lamp_upper.set_low()?;
+                self.yellow_lamp_lower.set_low()?;
+                Self::switch_optionally(&mut self.notice_lamp, PinState::Low)?;
+            }
+        }
+        let is_notice_lamp_lit = aspect == HVAnnouncementSignalAspect::Deactivated
+            || (aspect != HVAnnouncementSignalAspect::Dark && self.is_repeater_or_reduced_distance);
+        self.notice_blinker = (self.flashes_notice_lamp && is_notice_lamp_lit)
+            .then(|| Blinker::new(BLINK_HALF_PERIOD_MS));
+        Ok(())
+    }
+
+    fn lamp(&mut self, role: LampRole) -> Option<&mut PinType> {
+        match role {
+            LampRole::AnnouncementGreenUpper => Some(&mut self.green_lamp_upper),
+            LampRole::AnnouncementGreenLower => Some(&mut self.green_lamp_lower),
+            LampRole::AnnouncementYellowUpper => Some(&mut self.yellow_lamp_upper),
+            LampRole::AnnouncementYellowLower => Some(&mut self.yellow_lamp_lower),
+            LampRole::AnnouncementNotice => self.notice_lamp.as_mut(),
+            _ => None,
+        }
+    }
+
+    fn update(&mut self, now: u32) -> Result<(), Error> {
+        if let Some(notice_blinker) = &mut self.notice_blinker {
+            if let Some(is_on) = notice_blinker.update(now) {
+                Self::switch_optionally(&mut self.notice_lamp, is_on.into())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A grouping of an announcement and main signal in the H/V signaling system.
+pub struct HVSignalGroup<Error, PinType: OutputPin<Error = Error>> {
+    main_signal: HVMainSignal<Error, PinType>,
+    announcement_signal: HVAnnouncementSignal<Error, PinType>,
+    // A repeater signal’s notice lamp. Other signal wiring is connected to normal announcement lamps, since it’s always identical.
+    repeater_signal_notice_lamp: Option<PinType>,
+    // Whether the repeater signal’s notice lamp flashes instead of being lit steadily.
+    flashes_repeater_notice_lamp: bool,
+    // Blinker for the repeater signal’s notice lamp, while it is lit and flashes.
+    repeater_notice_blinker: Option<Blinker>,
+    // Whether the announcement signal is mounted on the main signal’s mast, so that it announces the next main signal and is dark while the main signal shows stop.
+    is_announcement_at_main_mast: bool,
+    // Aspect of the next main signal along the line, if it is known.
+    next_aspect: Option<HVMainSignalAspect>,
+    // Whether the announcement signal and repeater signal are permanently dark, since there is no next main signal.
+    is_announcement_suppressed: bool,
+    // Zs3 speed indicator next to the main signal, which shows a speed together with the slow aspect.
+    speed_indicator: Option<Zs3Indicator<Error, PinType>>,
+    // Zs3v speed announcement indicator next to the announcement signal, which announces the speed of the main signal.
+    speed_announcement_indicator: Option<Zs3vIndicator<Error, PinType>>,
+    // LEDs on a control desk panel that mirror the main signal aspect.
+    panel: Option<PanelOutput<Error, PinType>>,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> HVSignalGroup<Error, PinType> {
+    /// Creates a new signal group without a slow aspect.
+    pub fn new(
+        main_red_lamp: PinType,
+        main_green_lamp: PinType,
+        announcement_green_lamp_upper: PinType,
+        announcement_green_lamp_lower: PinType,
+        announcement_yellow_lamp_upper: PinType,
+        announcement_yellow_lamp_lower: PinType,
+    ) -> Self {
+        Self {
+            main_signal: HVMainSignal::new(main_red_lamp, main_green_lamp),
+            announcement_signal: HVAnnouncementSignal::new(
+                announcement_green_lamp_upper,
+                announcement_green_lamp_lower,
+                announcement_yellow_lamp_upper,
+                announcement_yellow_lamp_lower,
+            ),
+            repeater_signal_notice_lamp: None,
+            flashes_repeater_notice_lamp: false,
+            repeater_notice_blinker: None,
+            is_announcement_at_main_mast: false,
+            next_aspect: None,
+            is_announcement_suppressed: false,
+            speed_indicator: None,
+            speed_announcement_indicator: None,
+            panel: None,
+        }
+    }
+
+    /// Adds the ability to signal a slow aspect on the main signal.
+    pub fn with_slow_aspect(mut self, main_yellow_lamp: PinType) -> Self {
+        self.main_signal = self.main_signal.with_yellow_lamp(main_yellow_lamp);
+        self
+    }
+
+    /// Makes this signal group as having a reduced breaking distance between announcement and main signal. If a notice lamp was already provided, it does not need to be provided a second time.
+    pub fn with_reduced_distance(mut self, announcement_notice_lamp: Option<PinType>) -> Self {
+        if let Some(announcement_notice_lamp) = announcement_notice_lamp {
+            self.announcement_signal = self
+                .announcement_signal
+                .with_notice_lamp(announcement_notice_lamp);
+        }
+        self.announcement_signal.is_repeater_or_reduced_distance = true;
+        self
+    }
+
+    /// Adds deactivation capability to the signals in the signal group.
+    pub fn with_deactivation_capability(
+        mut self,
+        main_notice_lamp: PinType,
+        announcement_notice_lamp: PinType,
+    ) -> Self {
+        self.main_signal = self.main_signal.with_notice_lamp(main_notice_lamp);
+        self.announcement_signal = self
+            .announcement_signal
+            .with_notice_lamp(announcement_notice_lamp);
+        self
+    }
+
+    /// Adds a white Zs1 lamp to the main signal, for showing the substitution signal.
+    pub fn with_substitution_signal(mut self, main_substitution_lamp: PinType) -> Self {
+        self.main_signal = self
+            .main_signal
+            .with_substitution_lamp(main_substitution_lamp);
+        self
+    }
+
+    /// Adds the three yellow Zs7 lamps (upper left, upper right, bottom) to the main signal, for showing the caution signal.
+    pub fn with_caution_signal(mut self, main_caution_lamps: [PinType; 3]) -> Self {
+        self.main_signal = self.main_signal.with_caution_lamps(main_caution_lamps);
+        self
+    }
+
+    /// Adds the two white Sh1 lamps (lower left, upper right) to the main signal, for permitting shunting while it shows stop.
+    pub fn with_shunting_signal(mut self, main_shunting_lamps: [PinType; 2]) -> Self {
+        self.main_signal = self.main_signal.with_shunting_lamps(main_shunting_lamps);
+        self
+    }
+
+    /// Adds a white Zs6 stripe to the main signal, for showing proceed aspects onto the counter track at entries to track-changing operation (Gleiswechselbetrieb).
+    pub fn with_counter_track_signal(mut self, main_counter_track_lamp: PinType) -> Self {
+        self.main_signal = self
+            .main_signal
+            .with_counter_track_lamp(main_counter_track_lamp);
+        self
+    }
+
+    /// Adds the white Zs8 lamps to the main signal, for showing the counter-track substitution signal.
+    pub fn with_counter_track_substitution_signal(
+        mut self,
+        main_counter_track_substitution_lamp: PinType,
+    ) -> Self {
+        self.main_signal = self
+            .main_signal
+            .with_counter_track_substitution_lamp(main_counter_track_substitution_lamp);
+        self
+    }
+
+    /// Adds a notice lamp for a repeater signal, which otherwise shares pins with the announcement signal.
+    pub fn with_repeater_signal(mut self, repeater_notice_lamp: PinType) -> Self {
+        self.repeater_signal_notice_lamp = Some(repeater_notice_lamp);
+        self
+    }
+
+    /// Makes all notice lamps of the group flash instead of being lit steadily: those of the main and announcement signals for the Deactivated state, the announcement signal’s at reduced distance, and the repeater signal’s.
+    pub fn with_flashing_notice_lamps(mut self) -> Self {
+        self.main_signal = self.main_signal.with_flashing_notice_lamp();
+        self.announcement_signal = self.announcement_signal.with_flashing_notice_lamp();
+        self.flashes_repeater_notice_lamp = true;
+        self
+    }
+
+    /// Marks the announcement signal as mounted on the main signal’s mast, where it announces the next main signal as told by [`Signal::show_next_aspect`]. Its lamps are then dark whenever the main signal shows stop.
+    pub fn with_announcement_at_main_mast(mut self) -> Self {
+        self.is_announcement_at_main_mast = true;
+        self
+    }
+
+    /// Adds a Zs3 speed indicator to the main signal, which can show a speed together with the slow aspect. It is dark in all other aspects.
+    pub fn with_speed_indicator(mut self, speed_indicator: Zs3Indicator<Error, PinType>) -> Self {
+        self.speed_indicator = Some(speed_indicator);
+        self
+    }
+
+    /// Adds a Zs3v speed announcement indicator to the announcement signal, which announces the speed of the main signal’s speed indicator. It is dark while the announcement signal is mounted on the main signal’s mast, since the speed of the next main signal isn’t known.
+    pub fn with_speed_announcement_indicator(
+        mut self,
+        speed_announcement_indicator: Zs3vIndicator<Error, PinType>,
+    ) -> Self {
+        self.speed_announcement_indicator = Some(speed_announcement_indicator);
+        self
+    }
+
+    /// Adds control desk panel LEDs which always mirror the main signal aspect.
+    pub fn with_panel(mut self, panel: PanelOutput<Error, PinType>) -> Self {
+        self.panel = Some(panel);
+        self
+    }
+
+    /// Returns the aspect that the announcement signal shows next to the given main signal aspect.
+    fn announcement_aspect(&self, aspect: HVMainSignalAspect) -> HVAnnouncementSignalAspect {
+        if self.is_announcement_suppressed {
+            return HVAnnouncementSignalAspect::Dark;
+        }
+        if !self.is_announcement_at_main_mast {
+            return aspect.into();
+        }
+        match aspect {
+            HVMainSignalAspect::Deactivated | HVMainSignalAspect::Dark => aspect.into(),
+            _ if aspect.lights_lamp(LampRole::MainRed) => HVAnnouncementSignalAspect::Dark,
+            // without a known next aspect that it can show, the announcement signal expects the next main signal to show stop, which is always safe.
+            _ => self
+                .next_aspect
+                .map(HVAnnouncementSignalAspect::from)
+                .filter(|announced| self.announcement_signal.supports_aspect(*announced))
+                .unwrap_or(HVAnnouncementSignalAspect::ExpectStop),
+        }
+    }
+
+    fn switch_optionally(pin: &mut Option<PinType>, state: PinState) -> Result<(), Error> {
+        pin.as_mut().map(|pin| pin.set_state(state)).transpose()?;
+        Ok(())
+    }
+
+    /// Switches to the given aspect, with the speed indicator showing the given speed or nothing.
+    fn switch(
+        &mut self,
+        aspect: HVMainSignalAspect,
+        speed: Option<u8>,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        // for safety, the announcement signal must show stop (or be dark) at least while the main signal is switching
+        self.announcement_signal
+            .switch_to_aspect(self.announcement_aspect(HVMainSignalAspect::Stop), delay)?;
+        let announced_speed = speed
+            .filter(|_| !self.is_announcement_at_main_mast && !self.is_announcement_suppressed);
+        if let Some(speed_announcement_indicator) = &mut self.speed_announcement_indicator {
+            if speed_announcement_indicator.speed() != announced_speed {
+                speed_announcement_indicator.show(None)?;
+            }
+        }
+        // the speed indicator only changes while the main signal shows stop, so that a proceed aspect never comes with a wrong speed.
+        if let Some(speed_indicator) = &mut self.speed_indicator {
+            if speed_indicator.speed() != speed {
+                if !self.main_signal.aspect.lights_lamp(LampRole::MainRed) {
+                    self.main_signal
+                        .switch_to_aspect(HVMainSignalAspect::Stop, delay)?;
+                }
+                speed_indicator.show(speed)?;
+            }
+        }
+        // switch main signal first to make sure that the announcement signal never announces a main signal aspect that isn’t currently valid.
+        self.main_signal.switch_to_aspect(aspect, delay)?;
+        // if necessary, wait until the main signal aspect has settled; with Sh1, Zs1 or Zs7, the main signal still shows stop.
+        if !aspect.lights_lamp(LampRole::MainRed) {
+            delay.delay_ms(800);
+        }
+        if let Some(speed_announcement_indicator) = &mut self.speed_announcement_indicator {
+            speed_announcement_indicator.show(announced_speed)?;
+        }
+        self.announcement_signal
+            .switch_to_aspect(self.announcement_aspect(aspect), delay)?;
+        Self::switch_optionally(
+            &mut self.repeater_signal_notice_lamp,
+            if aspect == HVMainSignalAspect::Dark || self.is_announcement_suppressed {
+                PinState::Low
+            } else {
+                PinState::High
+            },
+        )?;
+        self.repeater_notice_blinker = (self.flashes_repeater_notice_lamp
+            && aspect != HVMainSignalAspect::Dark
+            && !self.is_announcement_suppressed)
+            .then(|| Blinker::new(BLINK_HALF_PERIOD_MS));
+        if let Some(panel) = &mut self.panel {
+            panel.show_aspect(aspect)?;
+        }
+        Ok(())
+    }
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> Signal for HVSignalGroup<Error, PinType> {
+    type Aspect = HVMainSignalAspect;
+    type Pin = PinType;
+    type Error = Error;
+
+    fn supports_aspect(&self, aspect: HVMainSignalAspect) -> bool {
+        self.main_signal.supports_aspect(aspect)
+            && self
+                .announcement_signal
+                .supports_aspect(self.announcement_aspect(aspect))
+    }
+
+    fn switch_to_aspect(
+        &mut self,
+        aspect: HVMainSignalAspect,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        self.switch(aspect, None, delay)
+    }
+
+    fn supports_speed(&self, aspect: HVMainSignalAspect, speed: u8) -> bool {
+        aspect == HVMainSignalAspect::ProceedSlow
+            && self.supports_aspect(aspect)
+            && self
+                .speed_indicator
+                .as_ref()
+                .is_some_and(|speed_indicator| speed_indicator.supports_speed(speed))
+            && self
+                .speed_announcement_indicator
+                .as_ref()
+                .map_or(true, |indicator| indicator.supports_speed(speed))
+    }
+
+    fn switch_to_aspect_with_speed(
+        &mut self,
+        aspect: HVMainSignalAspect,
+        speed: u8,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        if !self.supports_speed(aspect, speed) {
+            panic!("illegal aspect for this light, speed can’t be shown");
+        }
+        self.switch(aspect, Some(speed), delay)
+    }
+
+    fn lamp(&mut self, role: LampRole) -> Option<&mut PinType> {
+        match role {
+            LampRole::MainRed
+            | LampRole::MainGreen
+            | LampRole::MainYellow
+            | LampRole::MainNotice
+            | LampRole::MainSubstitution
+            | LampRole::MainCautionLeft
+            | LampRole::MainCautionRight
+            | LampRole::MainCautionBottom
+            | LampRole::MainShuntingLower
+            | LampRole::MainShuntingUpper
+            | LampRole::MainCounterTrack
+            | LampRole::MainCounterTrackSubstitution => self.main_signal.lamp(role),
+            LampRole::RepeaterNotice => self.repeater_signal_notice_lamp.as_mut(),
+            _ => self.announcement_signal.lamp(role),
+        }
+    }
+
+    fn show_next_aspect(
+        &mut self,
+        next_aspect: HVMainSignalAspect,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        self.next_aspect = Some(next_aspect);
+        if self.is_announcement_at_main_mast {
+            self.announcement_signal
+                .switch_to_aspect(self.announcement_aspect(self.main_signal.aspect), delay)?;
+        }
+        Ok(())
+    }
+
+    /// The main signal keeps its aspect and speed.
+    fn suppress_announcement(
+        &mut self,
+        is_suppressed: bool,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        if self.is_announcement_suppressed == is_suppressed {
+            return Ok(());
+        }
+        self.is_announcement_suppressed = is_suppressed;
+        let speed = self
+            .speed_indicator
+            .as_ref()
+            .and_then(|speed_indicator| speed_indicator.speed());
+        self.switch(self.main_signal.aspect, speed, delay)
+    }
+
+    fn update(&mut self, now: u32) -> Result<(), Error> {
+        self.main_signal.update(now)?;
+        self.announcement_signal.update(now)?;
+        if let Some(repeater_notice_blinker) = &mut self.repeater_notice_blinker {
+            if let Some(is_on) = repeater_notice_blinker.update(now) {
+                Self::switch_optionally(&mut self.repeater_signal_notice_lamp, is_on.into())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A signal in the Ks signalling system.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used. Its parameters additionally include the output pin’s error type (which some functions also return).
+pub struct KsSignal<Error, PinType: OutputPin<Error = Error>> {
+    other_pins: ExtraKsPins<Error, PinType>,
+    // Green lamp.
+    green_lamp: PinType,
+    // Notice lamp, used for Deactivated state.
+    notice_lamp: Option<PinType>,
+    // White Zs1 lamp, used for Substitution state. Only main signals can have it.
+    substitution_lamp: Option<PinType>,
+    // Blinker for the green lamp, while Ks1 blinking is shown.
+    green_blinker: Option<Blinker>,
+    // Blinker for the Zs1 lamp, while Substitution is shown.
+    substitution_blinker: Option<Blinker>,
+}
+
+/// A signal aspect in the Ks signalling system.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum KsSignalAspect {
+    // Hp0: Halt
+    Stop,
+    // Hp0 mit Zs1 (Ersatzsignal): am Halt zeigenden Signal ohne schriftlichen Befehl vorbeifahren.
+    Substitution,
+    // Ks1: Fahrt
+    Proceed,
+    // Ks1 blinkend: Fahrt, Geschwindigkeitsbeschränkung erwarten (mit Zs3v)
+    ExpectSpeedLimit,
+    // Ks2: Halt erwarten
+    ExpectStop,
+    // Signal betrieblich abgeschaltet, Kennlicht aktiv.
+    Deactivated,
+    // Signal dunkel, da übergeordnete Zugbeeinflussung (LZB oder ETCS) statt dem Lichtsignal gültig ist.
+    Dark,
+}
+
+impl SignalAspect for KsSignalAspect {
+    const STOP: Self = Self::Stop;
+    const SYSTEM_ID: &'static str = "KS";
+    // a blinking lamp on a Ks signal means a speed limit, so the notice lamp must stay steady.
+    const ALLOWS_FLASHING_NOTICE_LAMPS: bool = false;
+
+    fn command_id(self) -> &'static str {
+        match self {
+            Self::Stop => "0",
+            Self::Substitution => "Z1",
+            Self::Proceed => "1",
+            Self::ExpectStop => "2",
+            Self::ExpectSpeedLimit => "3",
+            Self::Deactivated => "A",
+            Self::Dark => "D",
+        }
+    }
+
+    fn from_command_id(command_id: &[u8]) -> Option<Self> {
+        AspectCommand::from_command_id(command_id).and_then(|command| Self::try_from(command).ok())
+    }
+
+    fn blanks_signal(self) -> bool {
+        matches!(self, Self::Deactivated | Self::Dark)
+    }
+
+    fn is_temporary(self) -> bool {
+        self == Self::Substitution
+    }
+
+    fn clears_signal(self) -> bool {
+        matches!(
+            self,
+            Self::Proceed | Self::ExpectSpeedLimit | Self::ExpectStop
+        )
+    }
+
+    fn restrictiveness(self) -> u8 {
+        match self {
+            Self::Stop => 0,
+            Self::Substitution => 1,
+            Self::ExpectStop => 2,
+            Self::ExpectSpeedLimit => 3,
+            Self::Proceed => 4,
+            Self::Deactivated => 5,
+            Self::Dark => 6,
+        }
+    }
+
+    fn lights_lamp(self, role: LampRole) -> bool {
+        match role {
+            LampRole::MainRed => matches!(self, Self::Stop | Self::Substitution),
+            // the blinking Zs1 lamp is lit at least half of the time.
+            LampRole::MainSubstitution => self == Self::Substitution,
+            // the blinking green lamp is lit at least half of the time.
+            LampRole::MainGreen => matches!(self, Self::Proceed | Self::ExpectSpeedLimit),
+            LampRole::MainYellow => self == Self::ExpectStop,
+            LampRole::MainNotice => self == Self::Deactivated,
+            _ => false,
+        }
+    }
+
+    fn blinks_lamp(self, role: LampRole) -> bool {
+        match role {
+            LampRole::MainSubstitution => self == Self::Substitution,
+            LampRole::MainGreen => self == Self::ExpectSpeedLimit,
+            _ => false,
+        }
+    }
+}
+
+impl TryFrom<AspectCommand> for KsSignalAspect {
+    type Error = UnsupportedAspect;
+
+    fn try_from(value: AspectCommand) -> Result<Self, Self::Error> {
+        match value {
+            AspectCommand::Zero => Ok(Self::Stop),
+            AspectCommand::One => Ok(Self::Proceed),
+            AspectCommand::Two => Ok(Self::ExpectStop),
+            AspectCommand::Three => Ok(Self::ExpectSpeedLimit),
+            AspectCommand::Substitution => Ok(Self::Substitution),
+            AspectCommand::Shunting
+            | AspectCommand::Caution
+            | AspectCommand::CounterTrack
+            | AspectCommand::CounterTrackSlow
+            | AspectCommand::CounterTrackSubstitution
+            | AspectCommand::ShuntingForbidden
+            | AspectCommand::ShuntingAllowed
+            | AspectCommand::Sv0
+            | AspectCommand::Sv1
+            | AspectCommand::Sv2
+            | AspectCommand::Sv3
+            | AspectCommand::Sv4
+            | AspectCommand::Sv5
+            | AspectCommand::Sv6
+            | AspectCommand::CrossingStop
+            | AspectCommand::CrossingProceed => Err(UnsupportedAspect),
+            AspectCommand::Deactivated => Ok(Self::Deactivated),
+            AspectCommand::Dark => Ok(Self::Dark),
+        }
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
+enum ExtraKsPins<Error, PinType: OutputPin<Error = Error>> {
+    MultiBlockSignal {
+        red_lamp: PinType,
+        yellow_lamp: PinType,
+    },
+    MainSignal {
+        red_lamp: PinType,
+    },
+    AnnouncementSignal {
+        yellow_lamp: PinType,
+    },
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> ExtraKsPins<Error, PinType> {
+    pub fn red_lamp(&mut self) -> Option<&mut PinType> {
+        match self {
+            ExtraKsPins::MultiBlockSignal { red_lamp, .. } => Some(red_lamp),
+            ExtraKsPins::MainSignal { red_lamp } => Some(red_lamp),
+            ExtraKsPins::AnnouncementSignal { .. } => None,
+        }
+    }
+    pub fn yellow_lamp(&mut self) -> Option<&mut PinType> {
+        match self {
+            ExtraKsPins::MultiBlockSignal { yellow_lamp, .. } => Some(yellow_lamp),
+            ExtraKsPins::MainSignal { .. } => None,
+            ExtraKsPins::AnnouncementSignal { yellow_lamp } => Some(yellow_lamp),
+        }
+    }
+    pub fn has_red_lamp(&self) -> bool {
+        match self {
+            ExtraKsPins::MultiBlockSignal { .. } => true,
+            ExtraKsPins::MainSignal { .. } => true,
+            ExtraKsPins::AnnouncementSignal { .. } => false,
+        }
+    }
+    pub fn has_yellow_lamp(&self) -> bool {
+        match self {
+            ExtraKsPins::MultiBlockSignal { .. } => true,
+            ExtraKsPins::MainSignal { .. } => false,
+            ExtraKsPins::AnnouncementSignal { .. } => true,
+        }
+    }
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> KsSignal<Error, PinType> {
+    pub fn new_main(red_lamp: PinType, green_lamp: PinType) -> Self {
+        Self {
+            other_pins: ExtraKsPins::MainSignal { red_lamp },
+            green_lamp,
+            notice_lamp: None,
+            substitution_lamp: None,
+            green_blinker: None,
+            substitution_blinker: None,
+        }
+    }
+    pub fn new_announcement(green_lamp: PinType, yellow_lamp: PinType) -> Self {
+        Self {
+            other_pins: ExtraKsPins::AnnouncementSignal { yellow_lamp },
+            green_lamp,
+            notice_lamp: None,
+            substitution_lamp: None,
+            green_blinker: None,
+            substitution_blinker: None,
+        }
+    }
+    pub fn new_multi_block(red_lamp: PinType, green_lamp: PinType, yellow_lamp: PinType) -> Self {
+        Self {
+            other_pins: ExtraKsPins::MultiBlockSignal {
+                red_lamp,
+                yellow_lamp,
+            },
+            green_lamp,
+            notice_lamp: None,
+            substitution_lamp: None,
+            green_blinker: None,
+            substitution_blinker: None,
+        }
+    }
+
+    /// Adds a notice lamp to this main signal.
+    pub fn with_notice_lamp(mut self, notice_lamp: PinType) -> Self {
+        self.notice_lamp = Some(notice_lamp);
+        self
+    }
+
+    /// Adds a white Zs1 lamp to this main signal, for showing the substitution signal.
+    pub fn with_substitution_lamp(mut self, substitution_lamp: PinType) -> Self {
+        self.substitution_lamp = Some(substitution_lamp);
+        self
+    }
+
+    fn switch_optionally(pin: Option<&mut PinType>, state: PinState) -> Result<(), Error> {
+        pin.map(|pin| pin.set_state(state)).transpose()?;
+        Ok(())
+    }
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> Signal for KsSignal<Error, PinType> {
+    type Aspect = KsSignalAspect;
+    type Pin = PinType;
+    type Error = Error;
+
+    fn supports_aspect(&self, aspect: KsSignalAspect) -> bool {
+        match aspect {
+            // always supported
+            KsSignalAspect::Dark | KsSignalAspect::Proceed => true,
+            KsSignalAspect::Stop => self.other_pins.has_red_lamp(),
+            KsSignalAspect::Substitution => {
+                self.other_pins.has_red_lamp() && self.substitution_lamp.is_some()
+            }
+            // only signals that announce the next signal can announce its speed limit.
+            KsSignalAspect::ExpectStop | KsSignalAspect::ExpectSpeedLimit => {
+                self.other_pins.has_yellow_lamp()
+            }
+            KsSignalAspect::Deactivated => self.notice_lamp.is_some(),
+        }
+    }
+
+    fn switch_to_aspect(
+        &mut self,
+        aspect: KsSignalAspect,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        // to ensure safety, first switch on the new aspect’s light,
+        // then switch off any previously enabled aspect lights.
+        // this may lead to an intermittent unclear aspect, but in that case the driver has to assume stop aspect anyways.
+        self.green_blinker = None;
+        self.substitution_blinker = None;
+        // Zs1 is only valid next to a lit red lamp, so it goes off before any other lamp changes.
+        if aspect != KsSignalAspect::Substitution {
+            Self::switch_optionally(self.substitution_lamp.as_mut(), PinState::Low)?;
+        }
+        match aspect {
+            KsSignalAspect::Stop => {
+                if !self.other_pins.has_red_lamp() {
+                    panic!("illegal aspect for this light, no red available");
+                }
+                Self::switch_optionally(self.other_pins.red_lamp(), PinState::High)?;
+
+                self.green_lamp.set_low()?;
+                Self::switch_optionally(self.other_pins.yellow_lamp(), PinState::Low)?;
+                Self::switch_optionally(self.notice_lamp.as_mut(), PinState::Low)?;
+            }
+            KsSignalAspect::Substitution => {
+                if !self.other_pins.has_red_lamp() || self.substitution_lamp.is_none() {
+                    panic!("illegal aspect for this light, no red or Zs1 lamp available");
+                }
+                Self::switch_optionally(self.other_pins.red_lamp(), PinState::High)?;
+
+                self.green_lamp.set_low()?;
+                Self::switch_optionally(self.other_pins.yellow_lamp(), PinState::Low)?;
+                Self::switch_optionally(self.notice_lamp.as_mut(), PinState::Low)?;
+                Self::switch_optionally(self.substitution_lamp.as_mut(), PinState::High)?;
+                // the blinker starts in its off phase, so its first toggle keeps the lit lamp on and starts the blinking.
+                self.substitution_blinker = Some(Blinker::new(BLINK_HALF_PERIOD_MS));
+            }
+            KsSignalAspect::Proceed => {
+                self.green_lamp.set_high()?;
+
+                Self::switch_optionally(self.other_pins.red_lamp(), PinState::Low)?;
+                Self::switch_optionally(self.other_pins.yellow_lamp(), PinState::Low)?;
+                Self::switch_optionally(self.notice_lamp.as_mut(), PinState::Low)?;
+            }
+            KsSignalAspect::ExpectSpeedLimit => {
+                if !self.other_pins.has_yellow_lamp() {
+                    panic!("illegal aspect for this light, cannot announce");
+                }
+
+                self.green_lamp.set_high()?;
+
+                Self::switch_optionally(self.other_pins.red_lamp(), PinState::Low)?;
+                Self::switch_optionally(self.other_pins.yellow_lamp(), PinState::Low)?;
+                Self::switch_optionally(self.notice_lamp.as_mut(), PinState::Low)?;
+                // the blinker starts in its off phase, so its first toggle keeps the lit lamp on and starts the blinking.
+                self.green_blinker = Some(Blinker::new(BLINK_HALF_PERIOD_MS));
+            }
+            KsSignalAspect::ExpectStop => {
+                // logic bug, since user code should ensure to never try to enable illegal aspects on signals that don’t support them
+                if !self.other_pins.has_yellow_lamp() {
+                    panic!("illegal aspect for this light, no yellow available");
+                }
+
+                // switch yellow on before green to avoid transient proceed aspect (whose speed would be too high)
+                Self::switch_optionally(self.other_pins.yellow_lamp(), PinState::High)?;
+
+                self.green_lamp.set_low()?;
+                Self::switch_optionally(self.other_pins.red_lamp(), PinState::Low)?;
+                Self::switch_optionally(self.notice_lamp.as_mut(), PinState::Low)?;
+            }
+            KsSignalAspect::Deactivated => {
+                if self.notice_lamp.is_none() {
+                    panic!("illegal aspect for this light, no notice lamp available");
+                }
+
+                Self::switch_optionally(self.notice_lamp.as_mut(), PinState::High)?;
+
+                Self::switch_optionally(self.other_pins.yellow_lamp(), PinState::Low)?;
+                self.green_lamp.set_low()?;
+                Self::switch_optionally(self.other_pins.red_lamp(), PinState::Low)?;
+            }
+            KsSignalAspect::Dark => {
+                Self::switch_optionally(self.notice_lamp.as_mut(), PinState::Low)?;
+                self.green_lamp.set_low()?;
+                Self::switch_optionally(self.other_pins.yellow_lamp(), PinState::Low)?;
+                Self::switch_optionally(self.other_pins.red_lamp(), PinState::Low)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn lamp(&mut self, role: LampRole) -> Option<&mut PinType> {
+        match role {
+            LampRole::MainRed => self.other_pins.red_lamp(),
+            LampRole::MainGreen => Some(&mut self.green_lamp),
+            LampRole::MainYellow => self.other_pins.yellow_lamp(),
+            LampRole::MainNotice => self.notice_lamp.as_mut(),
+            LampRole::MainSubstitution => self.substitution_lamp.as_mut(),
+            _ => None,
+        }
+    }
+
+    fn update(&mut self, now: u32) -> Result<(), Error> {
+        if let Some(green_blinker) = &mut self.green_blinker {
+            if let Some(is_on) = green_blinker.update(now) {
+                self.green_lamp.set_state(is_on.into())?;
+            }
+        }
+        if let Some(substitution_blinker) = &mut self.substitution_blinker {
+            if let Some(is_on) = substitution_blinker.update(now) {
+                Self::switch_optionally(self.substitution_lamp.as_mut(), is_on.into())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A grouping of a main signal and the distant signal announcing it in the Ks signalling system.
+pub struct KsSignalGroup<Error, PinType: OutputPin<Error = Error>> {
+    main_signal: KsSignal<Error, PinType>,
+    distant_signal: KsSignal<Error, PinType>,
+    // A repeater signal’s additional light (Zusatzlicht). Other signal wiring is connected to normal distant signal lamps, since it’s always identical.
+    repeater_signal_additional_lamp: Option<PinType>,
+    // Whether the distant signal is mounted on the main signal’s mast, so that it announces the next main signal and is dark while the main signal shows stop.
+    is_distant_at_main_mast: bool,
+    // Aspect of the next main signal along the line, if it is known.
+    next_aspect: Option<KsSignalAspect>,
+    // Whether the distant signal and repeater signal are permanently dark, since there is no next main signal.
+    is_distant_suppressed: bool,
+    // Zs3 speed indicator next to the main signal, which shows a speed together with Ks1.
+    speed_indicator: Option<Zs3Indicator<Error, PinType>>,
+    // Zs3v speed announcement indicator next to the distant signal, which announces the speed of the main signal.
+    speed_announcement_indicator: Option<Zs3vIndicator<Error, PinType>>,
+    // Aspect that was last switched to.
+    aspect: KsSignalAspect,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> KsSignalGroup<Error, PinType> {
+    pub fn new(
+        main_red_lamp: PinType,
+        main_green_lamp: PinType,
+        distant_green_lamp: PinType,
+        distant_yellow_lamp: PinType,
+    ) -> Self {
+        Self {
+            main_signal: KsSignal::new_main(main_red_lamp, main_green_lamp),
+            distant_signal: KsSignal::new_announcement(distant_green_lamp, distant_yellow_lamp),
+            repeater_signal_additional_lamp: None,
+            is_distant_at_main_mast: false,
+            next_aspect: None,
+            is_distant_suppressed: false,
+            speed_indicator: None,
+            speed_announcement_indicator: None,
+            aspect: KsSignalAspect::Dark,
+        }
+    }
+
+    /// Adds deactivation capability to the signals in the signal group.
+    pub fn with_deactivation_capability(
+        mut self,
+        main_notice_lamp: PinType,
+        distant_notice_lamp: PinType,
+    ) -> Self {
+        self.main_signal = self.main_signal.with_notice_lamp(main_notice_lamp);
+        self.distant_signal = self.distant_signal.with_notice_lamp(distant_notice_lamp);
+        self
+    }
+
+    /// Adds a white Zs1 lamp to the main signal, for showing the substitution signal.
+    pub fn with_substitution_signal(mut self, main_substitution_lamp: PinType) -> Self {
+        self.main_signal = self
+            .main_signal
+            .with_substitution_lamp(main_substitution_lamp);
+        self
+    }
+
+    /// Adds the additional light (Zusatzlicht) for a repeater signal, which otherwise shares pins with the distant signal.
+    pub fn with_repeater_signal(mut self, repeater_additional_lamp: PinType) -> Self {
+        self.repeater_signal_additional_lamp = Some(repeater_additional_lamp);
+        self
+    }
+
+    /// Marks the distant signal as mounted on the main signal’s mast, where it announces the next main signal as told by [`Signal::show_next_aspect`]. Its lamps are then dark whenever the main signal shows stop.
+    pub fn with_distant_at_main_mast(mut self) -> Self {
+        self.is_distant_at_main_mast = true;
+        self
+    }
+
+    /// Adds a Zs3 speed indicator to the main signal, which can show a speed together with Ks1. It is dark in all other aspects.
+    pub fn with_speed_indicator(mut self, speed_indicator: Zs3Indicator<Error, PinType>) -> Self {
+        self.speed_indicator = Some(speed_indicator);
+        self
+    }
+
+    /// Adds a Zs3v speed announcement indicator to the distant signal, which announces the speed of the main signal’s speed indicator together with Ks1 blinking. It is dark while the distant signal is mounted on the main signal’s mast, since the speed of the next main signal isn’t known.
+    pub fn with_speed_announcement_indicator(
+        mut self,
+        speed_announcement_indicator: Zs3vIndicator<Error, PinType>,
+    ) -> Self {
+        self.speed_announcement_indicator = Some(speed_announcement_indicator);
+        self
+    }
+
+    /// Returns the aspect that the distant signal shows next to the given main signal aspect.
+    fn announced_aspect(&self, aspect: KsSignalAspect) -> KsSignalAspect {
+        if self.is_distant_suppressed {
+            return KsSignalAspect::Dark;
+        }
+        if !self.is_distant_at_main_mast {
+            return Self::announcing(aspect);
+        }
+        match aspect {
+            // with Zs1, the main signal still shows stop.
+            KsSignalAspect::Stop | KsSignalAspect::Substitution => KsSignalAspect::Dark,
+            KsSignalAspect::Deactivated | KsSignalAspect::Dark => Self::announcing(aspect),
+            // without a known next aspect that it can show, the distant signal expects the next main signal to show stop, which is always safe.
+            _ => self
+                .next_aspect
+                .map(Self::announcing)
+                .filter(|announced| self.distant_signal.supports_aspect(*announced))
+                .unwrap_or(KsSignalAspect::ExpectStop),
+        }
+    }
+
+    /// Returns the aspect that a distant signal shows to announce the given main signal aspect.
+    fn announcing(aspect: KsSignalAspect) -> KsSignalAspect {
+        match aspect {
+            // with Zs1, the main signal still shows stop.
+            KsSignalAspect::Stop | KsSignalAspect::Substitution => KsSignalAspect::ExpectStop,
+            // a main signal can't show Ks2 or Ks1 blinking itself, but if it could, it would still be passable.
+            KsSignalAspect::Proceed
+            | KsSignalAspect::ExpectStop
+            | KsSignalAspect::ExpectSpeedLimit => KsSignalAspect::Proceed,
+            KsSignalAspect::Deactivated => KsSignalAspect::Deactivated,
+            KsSignalAspect::Dark => KsSignalAspect::Dark,
+        }
+    }
+
+    fn switch_optionally(pin: &mut Option<PinType>, state: PinState) -> Result<(), Error> {
+        pin.as_mut().map(|pin| pin.set_state(state)).transpose()?;
+        Ok(())
+    }
+
+    /// Switches to the given aspect, with the speed indicator showing the given speed or nothing.
+    fn switch(
+        &mut self,
+        aspect: KsSignalAspect,
+        speed: Option<u8>,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        // for safety, the distant signal must show Ks2 (or be dark) at least while the main signal is switching
+        self.distant_signal
+            .switch_to_aspect(self.announced_aspect(KsSignalAspect::Stop), delay)?;
+        let announced_speed =
+            speed.filter(|_| !self.is_distant_at_main_mast && !self.is_distant_suppressed);
+        if let Some(speed_announcement_indicator) = &mut self.speed_announcement_indicator {
+            if speed_announcement_indicator.speed() != announced_speed {
+                speed_announcement_indicator.show(None)?;
+            }
+        }
+        // the speed indicator only changes while the main signal shows stop, so that Ks1 never comes with a wrong speed.
+        if let Some(speed_indicator) = &mut self.speed_indicator {
+            if speed_indicator.speed() != speed {
+                if !self.aspect.lights_lamp(LampRole::MainRed) {
+                    self.main_signal
+                        .switch_to_aspect(KsSignalAspect::Stop, delay)?;
+                }
+                speed_indicator.show(speed)?;
+            }
+        }
+        // switch main signal first to make sure that the distant signal never announces a main signal aspect that isn’t currently valid.
+        self.main_signal.switch_to_aspect(aspect, delay)?;
+        // if necessary, wait until the main signal aspect has settled; with Zs1, the main signal still shows stop.
+        if !matches!(aspect, KsSignalAspect::Stop | KsSignalAspect::Substitution) {
+            delay.delay_ms(800);
+        }
+        if let Some(speed_announcement_indicator) = &mut self.speed_announcement_indicator {
+            speed_announcement_indicator.show(announced_speed)?;
+        }
+        // a speed limit is announced with Ks1 blinking.
+        let announced_aspect = match self.announced_aspect(aspect) {
+            KsSignalAspect::Proceed if announced_speed.is_some() => {
+                KsSignalAspect::ExpectSpeedLimit
+            }
+            announced_aspect => announced_aspect,
+        };
+        self.distant_signal
+            .switch_to_aspect(announced_aspect, delay)?;
+        Self::switch_optionally(
+            &mut self.repeater_signal_additional_lamp,
+            if aspect == KsSignalAspect::Dark || self.is_distant_suppressed {
+                PinState::Low
+            } else {
+                PinState::High
+            },
+        )?;
+        self.aspect = aspect;
+        Ok(())
+    }
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> Signal for KsSignalGroup<Error, PinType> {
+    type Aspect = KsSignalAspect;
+    type Pin = PinType;
+    type Error = Error;
+
+    fn supports_aspect(&self, aspect: KsSignalAspect) -> bool {
+        self.main_signal.supports_aspect(aspect)
+            && self
+                .distant_signal
+                .supports_aspect(self.announced_aspect(aspect))
+    }
+
+    fn switch_to_aspect(
+        &mut self,
+        aspect: KsSignalAspect,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        self.switch(aspect, None, delay)
+    }
+
+    fn supports_speed(&self, aspect: KsSignalAspect, speed: u8) -> bool {
+        aspect == KsSignalAspect::Proceed
+            && self.supports_aspect(aspect)
+            && self
+                .speed_indicator
+                .as_ref()
+                .is_some_and(|speed_indicator| speed_indicator.supports_speed(speed))
+            && self
+                .speed_announcement_indicator
+                .as_ref()
+                .map_or(true, |indicator| indicator.supports_speed(speed))
+    }
+
+    fn switch_to_aspect_with_speed(
+        &mut self,
+        aspect: KsSignalAspect,
+        speed: u8,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        if !self.supports_speed(aspect, speed) {
+            panic!("illegal aspect for this light, speed can’t be shown");
+        }
+        self.switch(aspect, Some(speed), delay)
+    }
+
+    fn lamp(&mut self, role: LampRole) -> Option<&mut PinType> {
+        match role {
+            LampRole::MainRed
+            | LampRole::MainGreen
+            | LampRole::MainNotice
+            | LampRole::MainSubstitution => self.main_signal.lamp(role),
+            LampRole::AnnouncementGreenUpper => self.distant_signal.lamp(LampRole::MainGreen),
+            LampRole::AnnouncementYellowUpper => self.distant_signal.lamp(LampRole::MainYellow),
+            LampRole::AnnouncementNotice => self.distant_signal.lamp(LampRole::MainNotice),
+            LampRole::RepeaterNotice => self.repeater_signal_additional_lamp.as_mut(),
+            _ => None,
+        }
+    }
+
+    fn show_next_aspect(
+        &mut self,
+        next_aspect: KsSignalAspect,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        self.next_aspect = Some(next_aspect);
+        if self.is_distant_at_main_mast {
+            self.distant_signal
+                .switch_to_aspect(self.announced_aspect(self.aspect), delay)?;
+        }
+        Ok(())
+    }
+
+    /// The main signal keeps its aspect and speed.
+    fn suppress_announcement(
+        &mut self,
+        is_suppressed: bool,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        if self.is_distant_suppressed == is_suppressed {
+            return Ok(());
+        }
+        self.is_distant_suppressed = is_suppressed;
+        let speed = self
+            .speed_indicator
+            .as_ref()
+            .and_then(|speed_indicator| speed_indicator.speed());
+        self.switch(self.aspect, speed, delay)
+    }
+
+    fn update(&mut self, now: u32) -> Result<(), Error> {
+        self.main_signal.update(now)?;
+        self.distant_signal.update(now)
+    }
+}
+
+/// An aspect in the Sv signalling system of the Hamburg and Berlin S-Bahn, where every signal is both a main signal and the distant signal for the next one.
+///
+/// The left lamps show the aspect of this signal, and the right lamps announce the next signal: green means proceed, green and yellow means proceed slowly, and a single yellow lamp on the right means expect stop.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SvSignalAspect {
+    // Hp0: Halt
+    Stop,
+    // Sv0: Zughalt! Weiterfahrt auf Sicht
+    StopThenOnSight,
+    // Sv1: Fahrt! Fahrt erwarten
+    ProceedExpectProceed,
+    // Sv2: Fahrt! Halt erwarten
+    ProceedExpectStop,
+    // Sv3: Fahrt! Langsamfahrt erwarten
+    ProceedExpectProceedSlow,
+    // Sv4: Langsamfahrt! Fahrt erwarten
+    ProceedSlowExpectProceed,
+    // Sv5: Langsamfahrt! Langsamfahrt erwarten
+    ProceedSlowExpectProceedSlow,
+    // Sv6: Langsamfahrt! Halt erwarten
+    ProceedSlowExpectStop,
+    // Signal dunkel.
+    Dark,
+}
+
+impl SignalAspect for SvSignalAspect {
+    const STOP: Self = Self::Stop;
+    const SYSTEM_ID: &'static str = "SV";
+    // Sv signals have no notice lamp.
+    const ALLOWS_FLASHING_NOTICE_LAMPS: bool = false;
+
+    fn command_id(self) -> &'static str {
+        match self {
+            Self::Stop => "0",
+            Self::StopThenOnSight => "SV0",
+            Self::ProceedExpectProceed => "SV1",
+            Self::ProceedExpectStop => "SV2",
+            Self::ProceedExpectProceedSlow => "SV3",
+            Self::ProceedSlowExpectProceed => "SV4",
+            Self::ProceedSlowExpectProceedSlow => "SV5",
+            Self::ProceedSlowExpectStop => "SV6",
+            Self::Dark => "D",
+        }
+    }
+
+    fn from_command_id(command_id: &[u8]) -> Option<Self> {
+        AspectCommand::from_command_id(command_id).and_then(|command| command.try_into().ok())
+    }
+
+    fn blanks_signal(self) -> bool {
+        self == Self::Dark
+    }
+
+    fn is_temporary(self) -> bool {
+        false
+    }
+
+    fn clears_signal(self) -> bool {
+        !matches!(self, Self::Stop | Self::StopThenOnSight | Self::Dark)
+    }
+
+    fn restrictiveness(self) -> u8 {
+        match self {
+            Self::Stop => 0,
+            Self::StopThenOnSight => 1,
+            Self::ProceedSlowExpectStop => 2,
+            Self::ProceedSlowExpectProceedSlow => 3,
+            Self::ProceedSlowExpectProceed => 4,
+            Self::ProceedExpectStop => 5,
+            Self::ProceedExpectProceedSlow => 6,
+            Self::ProceedExpectProceed => 7,
+            Self::Dark => 8,
+        }
+    }
+
+    fn lights_lamp(self, role: LampRole) -> bool {
+        match role {
+            LampRole::MainRed => self == Self::Stop,
+            LampRole::MainGreen => !matches!(self, Self::Stop | Self::StopThenOnSight | Self::Dark),
+            LampRole::MainYellow => matches!(
+                self,
+                Self::StopThenOnSight
+                    | Self::ProceedSlowExpectProceed
+                    | Self::ProceedSlowExpectProceedSlow
+                    | Self::ProceedSlowExpectStop
+            ),
+            LampRole::AnnouncementGreenUpper => matches!(
+                self,
+                Self::ProceedExpectProceed
+                    | Self::ProceedExpectProceedSlow
+                    | Self::ProceedSlowExpectProceed
+                    | Self::ProceedSlowExpectProceedSlow
+            ),
+            LampRole::AnnouncementYellowUpper => matches!(
+                self,
+                Self::StopThenOnSight
+                    | Self::ProceedExpectStop
+                    | Self::ProceedExpectProceedSlow
+                    | Self::ProceedSlowExpectProceedSlow
+                    | Self::ProceedSlowExpectStop
+            ),
+            _ => false,
+        }
+    }
+
+    fn blinks_lamp(self, _role: LampRole) -> bool {
+        false
+    }
+}
+
+impl TryFrom<AspectCommand> for SvSignalAspect {
+    type Error = UnsupportedAspect;
+
+    fn try_from(value: AspectCommand) -> Result<Self, Self::Error> {
+        match value {
+            AspectCommand::Zero => Ok(Self::Stop),
+            AspectCommand::Sv0 => Ok(Self::StopThenOnSight),
+            AspectCommand::Sv1 => Ok(Self::ProceedExpectProceed),
+            AspectCommand::Sv2 => Ok(Self::ProceedExpectStop),
+            AspectCommand::Sv3 => Ok(Self::ProceedExpectProceedSlow),
+            AspectCommand::Sv4 => Ok(Self::ProceedSlowExpectProceed),
+            AspectCommand::Sv5 => Ok(Self::ProceedSlowExpectProceedSlow),
+            AspectCommand::Sv6 => Ok(Self::ProceedSlowExpectStop),
+            AspectCommand::Dark => Ok(Self::Dark),
+            _ => Err(UnsupportedAspect),
+        }
+    }
+}
+
+/// A signal in the Sv signalling system, with two red lamps for Hp0 (driven from a single pin) and a green and yellow lamp on each side.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used. Its parameters additionally include the output pin’s error type (which some functions also return).
+pub struct SvSignal<Error, PinType: OutputPin<Error = Error>> {
+    red_lamps: PinType,
+    left_green_lamp: PinType,
+    left_yellow_lamp: PinType,
+    right_green_lamp: PinType,
+    right_yellow_lamp: PinType,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> SvSignal<Error, PinType> {
+    /// Order in which the lamps are switched: red and yellow lamps of the new aspect go on first and the old ones go off last, so that every intermediate lamp combination is at least as restrictive as one of the two aspects.
+    const SWITCHING_ORDER: [(LampRole, PinState); 10] = [
+        (LampRole::MainRed, PinState::High),
+        (LampRole::MainYellow, PinState::High),
+        (LampRole::AnnouncementYellowUpper, PinState::High),
+        (LampRole::MainGreen, PinState::Low),
+        (LampRole::AnnouncementGreenUpper, PinState::Low),
+        (LampRole::MainGreen, PinState::High),
+        (LampRole::AnnouncementGreenUpper, PinState::High),
+        (LampRole::MainYellow, PinState::Low),
+        (LampRole::AnnouncementYellowUpper, PinState::Low),
+        (LampRole::MainRed, PinState::Low),
+    ];
+
+    pub fn new(
+        red_lamps: PinType,
+        left_green_lamp: PinType,
+        left_yellow_lamp: PinType,
+        right_green_lamp: PinType,
+        right_yellow_lamp: PinType,
+    ) -> Self {
+        Self {
+            red_lamps,
+            left_green_lamp,
+            left_yellow_lamp,
+            right_green_lamp,
+            right_yellow_lamp,
+        }
+    }
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> Signal for SvSignal<Error, PinType> {
+    type Aspect = SvSignalAspect;
+    type Pin = PinType;
+    type Error = Error;
+
+    fn supports_aspect(&self, _aspect: SvSignalAspect) -> bool {
+        true
+    }
+
+    fn switch_to_aspect(
+        &mut self,
+        aspect: SvSignalAspect,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        for (role, state) in Self::SWITCHING_ORDER {
+            if aspect.lights_lamp(role) == (state == PinState::High) {
+                if let Some(lamp) = self.lamp(role) {
+                    lamp.set_state(state)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn lamp(&mut self, role: LampRole) -> Option<&mut PinType> {
+        match role {
+            LampRole::MainRed => Some(&mut self.red_lamps),
+            LampRole::MainGreen => Some(&mut self.left_green_lamp),
+            LampRole::MainYellow => Some(&mut self.left_yellow_lamp),
+            LampRole::AnnouncementGreenUpper => Some(&mut self.right_green_lamp),
+            LampRole::AnnouncementYellowUpper => Some(&mut self.right_yellow_lamp),
+            _ => None,
+        }
+    }
+}
+
+/// An aspect of a dwarf signal (Sperrsignal), which only applies to shunting movements.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DwarfSignalAspect {
+    // Sh0: Halt! Fahrverbot.
+    Stop,
+    // Sh1: Fahrverbot aufgehoben.
+    Proceed,
+    // Signal betrieblich abgeschaltet, Kennlicht aktiv.
+    Deactivated,
+    // Signal dunkel.
+    Dark,
+}
+
+impl SignalAspect for DwarfSignalAspect {
+    const STOP: Self = Self::Stop;
+    const SYSTEM_ID: &'static str = "SH";
+    const ALLOWS_FLASHING_NOTICE_LAMPS: bool = false;
+
+    fn command_id(self) -> &'static str {
+        match self {
+            Self::Stop => "SH0",
+            Self::Proceed => "SH1",
+            Self::Deactivated => "A",
+            Self::Dark => "D",
+        }
+    }
+
+    fn from_command_id(command_id: &[u8]) -> Option<Self> {
+        match command_id {
+            b"SH0" => Some(Self::Stop),
+            b"SH1" => Some(Self::Proceed),
+            b"A" => Some(Self::Deactivated),
+            b"D" => Some(Self::Dark),
+            _ => None,
+        }
+    }
+
+    fn blanks_signal(self) -> bool {
+        matches!(self, Self::Deactivated | Self::Dark)
+    }
+
+    fn is_temporary(self) -> bool {
+        false
+    }
+
+    fn clears_signal(self) -> bool {
+        self == Self::Proceed
+    }
+
+    fn restrictiveness(self) -> u8 {
+        match self {
+            Self::Stop => 0,
+            Self::Proceed => 1,
+            Self::Deactivated => 2,
+            Self::Dark => 3,
+        }
+    }
+
+    fn lights_lamp(self, role: LampRole) -> bool {
+        match role {
+            LampRole::DwarfRed => self == Self::Stop,
+            LampRole::DwarfWhite => self == Self::Proceed,
+            LampRole::DwarfNotice => self == Self::Deactivated,
+            _ => false,
+        }
+    }
+
+    fn blinks_lamp(self, _role: LampRole) -> bool {
+        false
+    }
+}
+
+impl TryFrom<AspectCommand> for DwarfSignalAspect {
+    type Error = UnsupportedAspect;
+
+    fn try_from(value: AspectCommand) -> Result<Self, Self::Error> {
+        match value {
+            AspectCommand::ShuntingForbidden => Ok(Self::Stop),
+            AspectCommand::ShuntingAllowed => Ok(Self::Proceed),
+            AspectCommand::Deactivated => Ok(Self::Deactivated),
+            AspectCommand::Dark => Ok(Self::Dark),
+            _ => Err(UnsupportedAspect),
+        }
+    }
+}
+
+/// A dwarf signal next to an H/V main signal shows Sh1 whenever any movement may pass the main signal.
+impl From<HVMainSignalAspect> for DwarfSignalAspect {
+    fn from(value: HVMainSignalAspect) -> Self {
+        match value {
+            HVMainSignalAspect::Stop => Self::Stop,
+            HVMainSignalAspect::StopWithShunting
+            | HVMainSignalAspect::Substitution
+            | HVMainSignalAspect::Caution
+            | HVMainSignalAspect::CounterTrackSubstitution
+            | HVMainSignalAspect::Proceed
+            | HVMainSignalAspect::ProceedCounterTrack
+            | HVMainSignalAspect::ProceedSlow
+            | HVMainSignalAspect::ProceedSlowCounterTrack => Self::Proceed,
+            HVMainSignalAspect::Deactivated => Self::Deactivated,
+            HVMainSignalAspect::Dark => Self::Dark,
+        }
+    }
+}
+
+/// A dwarf signal next to a Ks main signal shows Sh1 whenever any movement may pass the main signal.
+impl From<KsSignalAspect> for DwarfSignalAspect {
+    fn from(value: KsSignalAspect) -> Self {
+        match value {
+            KsSignalAspect::Stop => Self::Stop,
+            KsSignalAspect::Substitution
+            | KsSignalAspect::Proceed
+            | KsSignalAspect::ExpectStop
+            | KsSignalAspect::ExpectSpeedLimit => Self::Proceed,
+            KsSignalAspect::Deactivated => Self::Deactivated,
+            KsSignalAspect::Dark => Self::Dark,
+        }
+    }
+}
+
+/// A dwarf signal (Sperrsignal, Ls), which shows Sh0 with two red lamps and Sh1 with two white lamps.
+///
+/// Each pair of lamps is driven from a single pin. On its own, a dwarf signal is commanded with its own aspect ids; next to a main signal, it can be added to the main signal’s [`Mast`](crate::mast::Mast) so that it follows the main signal aspect.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used. Its parameters additionally include the output pin’s error type (which some functions also return).
+pub struct DwarfSignal<Error, PinType: OutputPin<Error = Error>> {
+    red_lamps: PinType,
+    white_lamps: PinType,
+    // Kennlicht, used for Deactivated state.
+    notice_lamp: Option<PinType>,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> DwarfSignal<Error, PinType> {
+    pub fn new(red_lamps: PinType, white_lamps: PinType) -> Self {
+        Self {
+            red_lamps,
+            white_lamps,
+            notice_lamp: None,
+        }
+    }
+
+    /// Adds a notice lamp to this signal, which enables deactivation.
+    pub fn with_notice_lamp(mut self, notice_lamp: PinType) -> Self {
+        self.notice_lamp = Some(notice_lamp);
+        self
+    }
+
+    fn switch_optionally(pin: Option<&mut PinType>, state: PinState) -> Result<(), Error> {
+        pin.map(|pin| pin.set_state(state)).transpose()?;
+        Ok(())
+    }
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> Signal for DwarfSignal<Error, PinType> {
+    type Aspect = DwarfSignalAspect;
+    type Pin = PinType;
+    type Error = Error;
+
+    fn supports_aspect(&self, aspect: DwarfSignalAspect) -> bool {
+        aspect != DwarfSignalAspect::Deactivated || self.notice_lamp.is_some()
+    }
+
+    fn switch_to_aspect(
+        &mut self,
+        aspect: DwarfSignalAspect,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        // as with main signals, the red lamps are switched on first and switched off last.
+        match aspect {
+            DwarfSignalAspect::Stop => {
+                self.red_lamps.set_high()?;
+                self.white_lamps.set_low()?;
+                Self::switch_optionally(self.notice_lamp.as_mut(), PinState::Low)?;
+            }
+            DwarfSignalAspect::Proceed => {
+                self.white_lamps.set_high()?;
+                Self::switch_optionally(self.notice_lamp.as_mut(), PinState::Low)?;
+                self.red_lamps.set_low()?;
+            }
+            DwarfSignalAspect::Deactivated => {
+                if self.notice_lamp.is_none() {
+                    panic!("illegal aspect for this light, no notice lamp available");
+                }
+                Self::switch_optionally(self.notice_lamp.as_mut(), PinState::High)?;
+                self.white_lamps.set_low()?;
+                self.red_lamps.set_low()?;
+            }
+            DwarfSignalAspect::Dark => {
+                Self::switch_optionally(self.notice_lamp.as_mut(), PinState::Low)?;
+                self.white_lamps.set_low()?;
+                self.red_lamps.set_low()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn lamp(&mut self, role: LampRole) -> Option<&mut PinType> {
+        match role {
+            LampRole::DwarfRed => Some(&mut self.red_lamps),
+            LampRole::DwarfWhite => Some(&mut self.white_lamps),
+            LampRole::DwarfNotice => self.notice_lamp.as_mut(),
+            _ => None,
+        }
+    }
+}
+
+/// An aspect of a crossing supervision signal (Überwachungssignal), which tells the driver whether the level crossing ahead is secured.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CrossingSignalAspect {
+    // Bü0: Halt vor dem Bahnübergang! Weiterfahrt nach Sicherung.
+    Stop,
+    // Bü1: Der Bahnübergang darf befahren werden.
+    Proceed,
+    // Signal dunkel.
+    Dark,
+}
+
+impl SignalAspect for CrossingSignalAspect {
+    const STOP: Self = Self::Stop;
+    const SYSTEM_ID: &'static str = "BU";
+    const ALLOWS_FLASHING_NOTICE_LAMPS: bool = false;
+
+    fn command_id(self) -> &'static str {
+        match self {
+            Self::Stop => "BU0",
+            Self::Proceed => "BU1",
+            Self::Dark => "D",
+        }
+    }
+
+    fn from_command_id(command_id: &[u8]) -> Option<Self> {
+        AspectCommand::from_command_id(command_id).and_then(|command| command.try_into().ok())
+    }
+
+    fn blanks_signal(self) -> bool {
+        self == Self::Dark
+    }
+
+    fn is_temporary(self) -> bool {
+        false
+    }
+
+    fn clears_signal(self) -> bool {
+        self == Self::Proceed
+    }
+
+    fn restrictiveness(self) -> u8 {
+        match self {
+            Self::Stop => 0,
+            Self::Proceed => 1,
+            Self::Dark => 2,
+        }
+    }
+
+    fn lights_lamp(self, role: LampRole) -> bool {
+        match role {
+            LampRole::CrossingYellow => self == Self::Stop,
+            LampRole::CrossingWhite => self == Self::Proceed,
+            _ => false,
+        }
+    }
+
+    fn blinks_lamp(self, role: LampRole) -> bool {
+        role == LampRole::CrossingWhite && self == Self::Proceed
+    }
+}
+
+impl TryFrom<AspectCommand> for CrossingSignalAspect {
+    type Error = UnsupportedAspect;
+
+    fn try_from(value: AspectCommand) -> Result<Self, Self::Error> {
+        match value {
+            AspectCommand::CrossingStop => Ok(Self::Stop),
+            AspectCommand::CrossingProceed => Ok(Self::Proceed),
+            AspectCommand::Dark => Ok(Self::Dark),
+            _ => Err(UnsupportedAspect),
+        }
+    }
+}
+
+/// A crossing supervision signal (Überwachungssignal), which shows Bü1 with a blinking white lamp once the level crossing ahead is secured, and Bü0 otherwise.
+///
+/// Newer signals show Bü0 with a yellow lamp, while older ones stay dark for Bü0, so the yellow lamp is optional.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used. Its parameters additionally include the output pin’s error type (which some functions also return).
+pub struct CrossingSignal<Error, PinType: OutputPin<Error = Error>> {
+    white_lamp: PinType,
+    yellow_lamp: Option<PinType>,
+    // Blinks the white lamp in Bü1.
+    white_blinker: Option<Blinker>,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> CrossingSignal<Error, PinType> {
+    pub fn new(white_lamp: PinType) -> Self {
+        Self {
+            white_lamp,
+            yellow_lamp: None,
+            white_blinker: None,
+        }
+    }
+
+    /// Adds a yellow lamp to this signal, which is lit in Bü0.
+    pub fn with_yellow_lamp(mut self, yellow_lamp: PinType) -> Self {
+        self.yellow_lamp = Some(yellow_lamp);
+        self
+    }
+
+    fn switch_optionally(pin: Option<&mut PinType>, state: PinState) -> Result<(), Error> {
+        pin.map(|pin| pin.set_state(state)).transpose()?;
+        Ok(())
+    }
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> Signal for CrossingSignal<Error, PinType> {
+    type Aspect = CrossingSignalAspect;
+    type Pin = PinType;
+    type Error = Error;
+
+    fn supports_aspect(&self, _aspect: CrossingSignalAspect) -> bool {
+        true
+    }
+
+    fn switch_to_aspect(
+        &mut self,
+        aspect: CrossingSignalAspect,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        // the white lamp goes off first, so that the signal never shows Bü1 while the crossing isn’t secured.
+        match aspect {
+            CrossingSignalAspect::Stop => {
+                self.white_blinker = None;
+                self.white_lamp.set_low()?;
+                Self::switch_optionally(self.yellow_lamp.as_mut(), PinState::High)?;
+            }
+            CrossingSignalAspect::Proceed => {
+                Self::switch_optionally(self.yellow_lamp.as_mut(), PinState::Low)?;
+                self.white_lamp.set_high()?;
+                // the blinker starts in its off phase, so its first toggle keeps the lit lamp on and starts the blinking.
+                self.white_blinker = Some(Blinker::new(BLINK_HALF_PERIOD_MS));
+            }
+            CrossingSignalAspect::Dark => {
+                self.white_blinker = None;
+                self.white_lamp.set_low()?;
+                Self::switch_optionally(self.yellow_lamp.as_mut(), PinState::Low)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn lamp(&mut self, role: LampRole) -> Option<&mut PinType> {
+        match role {
+            LampRole::CrossingWhite => Some(&mut self.white_lamp),
+            LampRole::CrossingYellow => self.yellow_lamp.as_mut(),
+            _ => None,
+        }
+    }
+
+    fn update(&mut self, now: u32) -> Result<(), Error> {
+        if let Some(white_blinker) = &mut self.white_blinker {
+            if let Some(is_on) = white_blinker.update(now) {
+                self.white_lamp.set_state(is_on.into())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use super::CrossingSignal;
+    use super::CrossingSignalAspect;
+    use super::DwarfSignal;
+    use super::DwarfSignalAspect;
+    use super::HVAnnouncementSignalAspect;
+    use super::HVMainSignal;
+    use super::HVMainSignalAspect;
+    use super::HVSignalGroup;
+    use super::KsSignal;
+    use super::KsSignalAspect;
+    use super::KsSignalGroup;
+    use super::LampRole;
+    use super::Signal;
+    use super::SignalAspect;
+    use super::SvSignal;
+    use super::SvSignalAspect;
+    use crate::mock::MockDelay;
+    use crate::mock::MockPin;
+    use crate::mock::MockPins;
+    use crate::zs3::Zs3Indicator;
+
+    const ASPECTS: [HVMainSignalAspect; 11] = [
+        HVMainSignalAspect::Stop,
+        HVMainSignalAspect::StopWithShunting,
+        HVMainSignalAspect::Substitution,
+        HVMainSignalAspect::Caution,
+        HVMainSignalAspect::CounterTrackSubstitution,
+        HVMainSignalAspect::Proceed,
+        HVMainSignalAspect::ProceedCounterTrack,
+        HVMainSignalAspect::ProceedSlow,
+        HVMainSignalAspect::ProceedSlowCounterTrack,
+        HVMainSignalAspect::Deactivated,
+        HVMainSignalAspect::Dark,
+    ];
+
+    // Pin numbers of the main signal lamps.
+    const RED: usize = 0;
+    const GREEN: usize = 1;
+    const YELLOW: usize = 2;
+    const SUBSTITUTION: usize = 4;
+    const COUNTER_TRACK_SUBSTITUTION: usize = 11;
+
+    fn signal() -> (HVMainSignal<Infallible, MockPin>, MockPins) {
+        let pins = MockPins::new();
+        let signal = HVMainSignal::new(pins.pin(), pins.pin())
+            .with_yellow_lamp(pins.pin())
+            .with_notice_lamp(pins.pin())
+            .with_substitution_lamp(pins.pin())
+            .with_caution_lamps([pins.pin(), pins.pin(), pins.pin()])
+            .with_shunting_lamps([pins.pin(), pins.pin()])
+            .with_counter_track_lamp(pins.pin())
+            .with_counter_track_substitution_lamp(pins.pin());
+        (signal, pins)
+    }
+
+    // pins: red, green, yellow, notice, Zs1, three Zs7, two Sh1, Zs6, Zs8
+    fn lamps_of(aspect: HVMainSignalAspect) -> [bool; 12] {
+        match aspect {
+            HVMainSignalAspect::Stop => [
+                true, false, false, false, false, false, false, false, false, false, false, false,
+            ],
+            HVMainSignalAspect::StopWithShunting => [
+                true, false, false, false, false, false, false, false, true, true, false, false,
+            ],
+            HVMainSignalAspect::Substitution => [
+                true, false, false, false, true, false, false, false, false, false, false, false,
+            ],
+            HVMainSignalAspect::Caution => [
+                true, false, false, false, false, true, true, true, false, false, false, false,
+            ],
+            HVMainSignalAspect::CounterTrackSubstitution => [
+                true, false, false, false, false, false, false, false, false, false, false, true,
+            ],
+            HVMainSignalAspect::Proceed => [
+                false, true, false, false, false, false, false, false, false, false, false, false,
+            ],
+            HVMainSignalAspect::ProceedCounterTrack => [
+                false, true, false, false, false, false, false, false, false, false, true, false,
+            ],
+            HVMainSignalAspect::ProceedSlow => [
+                false, true, true, false, false, false, false, false, false, false, false, false,
+            ],
+            HVMainSignalAspect::ProceedSlowCounterTrack => [
+                false, true, true, false, false, false, false, false, false, false, true, false,
+            ],
+            HVMainSignalAspect::Deactivated => [
+                false, false, false, true, false, false, false, false, false, false, false, false,
+            ],
+            HVMainSignalAspect::Dark => [
+                false, false, false, false, false, false, false, false, false, false, false, false,
+            ],
+        }
+    }
+
+    /// How permissive the lamps are for a train driver: 0 for stop (or any unclear aspect), 1 for proceed slow and 2 for proceed.
+    fn permissiveness(lamps: [bool; 12]) -> u8 {
+        match (lamps[RED], lamps[GREEN], lamps[YELLOW]) {
+            (false, true, true) => 1,
+            (false, true, false) => 2,
+            _ => 0,
+        }
+    }
+
+    /// Switches a fresh signal from one aspect to another and returns every lamp state during the second switch.
+    fn transition(from: HVMainSignalAspect, to: HVMainSignalAspect) -> Vec<[bool; 12]> {
+        let (mut signal, pins) = signal();
+        let mut delay = MockDelay::default();
+        signal.switch_to_aspect(from, &mut delay).unwrap();
+        pins.clear_history();
+        signal.switch_to_aspect(to, &mut delay).unwrap();
+        pins.history()
+            .into_iter()
+            .map(|lamps| lamps.try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn every_transition_ends_in_target_aspect() {
+        for from in ASPECTS {
+            for to in ASPECTS {
+                let history = transition(from, to);
+                assert_eq!(
+                    history.last(),
+                    Some(&lamps_of(to)),
+                    "{} -> {}",
+                    from.command_id(),
+                    to.command_id()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn no_transition_shows_a_less_restrictive_aspect() {
+        for from in ASPECTS {
+            for to in ASPECTS {
+                let allowed = permissiveness(lamps_of(from)).max(permissiveness(lamps_of(to)));
+                for lamps in transition(from, to) {
+                    assert!(
+                        permissiveness(lamps) <= allowed,
+                        "{} -> {} passes through {:?}",
+                        from.command_id(),
+                        to.command_id(),
+                        lamps
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_transition_between_lit_aspects_goes_dark() {
+        for from in ASPECTS {
+            for to in ASPECTS {
+                if from == HVMainSignalAspect::Dark || to == HVMainSignalAspect::Dark {
+                    continue;
+                }
+                for lamps in transition(from, to) {
+                    assert!(
+                        lamps.contains(&true),
+                        "{} -> {} goes dark",
+                        from.command_id(),
+                        to.command_id()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Checks that stop is more restrictive than every other aspect, that aspects which blank the signal are less restrictive than every aspect that doesn’t, and that the most restrictive of two aspects is at least as restrictive as both.
+    fn assert_restrictiveness_order<Aspect: SignalAspect>() {
+        let aspects = crate::maintenance::PROOF_ASPECTS
+            .into_iter()
+            .filter_map(|command| Aspect::try_from(command).ok());
+        for first in aspects.clone() {
+            assert!(first == Aspect::STOP || Aspect::STOP.cmp_restrictiveness(first).is_lt());
+            for second in aspects.clone() {
+                let most_restrictive = first.most_restrictive(second);
+                assert!(
+                    most_restrictive.cmp_restrictiveness(first).is_le()
+                        && most_restrictive.cmp_restrictiveness(second).is_le(),
+                    "{} and {}",
+                    first.command_id(),
+                    second.command_id()
+                );
+                if first.blanks_signal() && !second.blanks_signal() {
+                    assert!(first.cmp_restrictiveness(second).is_gt());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_signalling_system_orders_its_aspects_by_restrictiveness() {
+        assert_restrictiveness_order::<HVMainSignalAspect>();
+        assert_restrictiveness_order::<HVAnnouncementSignalAspect>();
+        assert_restrictiveness_order::<KsSignalAspect>();
+        assert_restrictiveness_order::<SvSignalAspect>();
+        assert_restrictiveness_order::<DwarfSignalAspect>();
+        assert_restrictiveness_order::<CrossingSignalAspect>();
+        assert_restrictiveness_order::<super::sncf::SncfSignalAspect>();
+    }
+
+    #[test]
+    fn upgrade_to_proceed_extinguishes_yellow_after_green() {
+        let history = transition(HVMainSignalAspect::ProceedSlow, HVMainSignalAspect::Proceed);
+        let yellow_off = history.iter().position(|lamps| !lamps[YELLOW]).unwrap();
+        assert!(history[..yellow_off].iter().any(|lamps| lamps[GREEN]));
+        assert!(history[yellow_off][GREEN]);
+    }
+
+    #[test]
+    fn downgrade_to_proceed_slow_lights_yellow_before_green() {
+        let history = transition(HVMainSignalAspect::Proceed, HVMainSignalAspect::ProceedSlow);
+        assert_eq!(history[0], lamps_of(HVMainSignalAspect::ProceedSlow));
+    }
+
+    #[test]
+    fn substitution_signal_blinks_next_to_red_lamp() {
+        let (mut signal, pins) = signal();
+        let mut delay = MockDelay::default();
+        signal
+            .switch_to_aspect(HVMainSignalAspect::Substitution, &mut delay)
+            .unwrap();
+        let lamps_at = |signal: &mut HVMainSignal<Infallible, MockPin>, now| {
+            signal.update(now).unwrap();
+            pins.states()
+        };
+        assert!(lamps_at(&mut signal, 1000)[SUBSTITUTION]);
+        assert!(!lamps_at(&mut signal, 1500)[SUBSTITUTION]);
+        assert!(lamps_at(&mut signal, 2000)[SUBSTITUTION]);
+        assert!(pins.history().iter().all(|lamps| lamps[RED]));
+
+        signal
+            .switch_to_aspect(HVMainSignalAspect::Stop, &mut delay)
+            .unwrap();
+        assert_eq!(
+            lamps_at(&mut signal, 2500),
+            lamps_of(HVMainSignalAspect::Stop)
+        );
+    }
+
+    #[test]
+    fn counter_track_substitution_signal_blinks_instead_of_zs1() {
+        let (mut signal, pins) = signal();
+        let mut delay = MockDelay::default();
+        signal
+            .switch_to_aspect(HVMainSignalAspect::CounterTrackSubstitution, &mut delay)
+            .unwrap();
+        let lamps_at = |signal: &mut HVMainSignal<Infallible, MockPin>, now| {
+            signal.update(now).unwrap();
+            pins.states()
+        };
+        assert!(lamps_at(&mut signal, 1000)[COUNTER_TRACK_SUBSTITUTION]);
+        assert!(!lamps_at(&mut signal, 1500)[COUNTER_TRACK_SUBSTITUTION]);
+        assert!(lamps_at(&mut signal, 2000)[COUNTER_TRACK_SUBSTITUTION]);
+        assert!(pins
+            .history()
+            .iter()
+            .all(|lamps| lamps[RED] && !lamps[SUBSTITUTION]));
+    }
+
+    fn signal_group() -> (HVSignalGroup<Infallible, MockPin>, MockPins) {
+        let pins = MockPins::new();
+        let group = HVSignalGroup::new(
+            pins.pin(),
+            pins.pin(),
+            pins.pin(),
+            pins.pin(),
+            pins.pin(),
+            pins.pin(),
+        )
+        .with_slow_aspect(pins.pin());
+        (group, pins)
+    }
+
+    #[test]
+    fn group_announces_only_what_the_main_signal_shows() {
+        let (mut group, pins) = signal_group();
+        let mut delay = MockDelay::default();
+        for aspect in [
+            HVMainSignalAspect::Stop,
+            HVMainSignalAspect::Proceed,
+            HVMainSignalAspect::ProceedSlow,
+            HVMainSignalAspect::Proceed,
+            HVMainSignalAspect::Stop,
+        ] {
+            group.switch_to_aspect(aspect, &mut delay).unwrap();
+        }
+
+        // pins: main red, main green, announcement green upper and lower, announcement yellow upper and lower, main yellow
+        for state in pins.history() {
+            let main_signal = (state[0], state[1], state[6]);
+            match (state[2], state[3], state[4], state[5]) {
+                (true, true, false, false) => assert_eq!(main_signal, (false, true, false)),
+                (true, false, false, true) => assert_eq!(main_signal, (false, true, true)),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            pins.states(),
+            [true, false, false, false, true, true, false]
+        );
+    }
+
+    #[test]
+    fn group_waits_for_main_signal_unless_switching_to_stop() {
+        let (mut group, _pins) = signal_group();
+        let mut delay = MockDelay::default();
+        group
+            .switch_to_aspect(HVMainSignalAspect::Proceed, &mut delay)
+            .unwrap();
+        assert_eq!(delay.total_ns, 800_000_000);
+        group
+            .switch_to_aspect(HVMainSignalAspect::Stop, &mut delay)
+            .unwrap();
+        assert_eq!(delay.total_ns, 800_000_000);
+    }
+
+    #[test]
+    fn speed_indicator_only_changes_while_main_signal_shows_stop() {
+        let (group, pins) = signal_group();
+        let mut group = group.with_speed_indicator(Zs3Indicator::new([(); 7].map(|_| pins.pin())));
+        let mut delay = MockDelay::default();
+        assert!(group.supports_speed(HVMainSignalAspect::ProceedSlow, 6));
+        assert!(!group.supports_speed(HVMainSignalAspect::Proceed, 6));
+        group
+            .switch_to_aspect(HVMainSignalAspect::Stop, &mut delay)
+            .unwrap();
+        group
+            .switch_to_aspect_with_speed(HVMainSignalAspect::ProceedSlow, 6, &mut delay)
+            .unwrap();
+        group
+            .switch_to_aspect_with_speed(HVMainSignalAspect::ProceedSlow, 8, &mut delay)
+            .unwrap();
+        group
+            .switch_to_aspect(HVMainSignalAspect::Proceed, &mut delay)
+            .unwrap();
+
+        // pins 7 to 13: segments a to g
+        let history = pins.history();
+        for (before, after) in history.iter().zip(&history[1..]) {
+            if before[7..] != after[7..] {
+                assert!(after[0], "speed indicator changed without stop");
+            }
+        }
+        assert!(history
+            .iter()
+            .any(|state| state[7..] == [true, true, true, true, true, true, true]));
+        assert_eq!(&pins.states()[7..], [false; 7]);
+    }
+
+    #[test]
+    fn flashing_notice_lamps_blink_while_lit() {
+        let (group, pins) = signal_group();
+        let mut group = group
+            .with_deactivation_capability(pins.pin(), pins.pin())
+            .with_repeater_signal(pins.pin())
+            .with_flashing_notice_lamps();
+        let mut delay = MockDelay::default();
+        group
+            .switch_to_aspect(HVMainSignalAspect::Deactivated, &mut delay)
+            .unwrap();
+        // pins 7 to 9: main notice, announcement notice, repeater notice
+        assert_eq!(&pins.states()[7..], [true; 3]);
+        group.update(500).unwrap();
+        assert_eq!(&pins.states()[7..], [true; 3]);
+        group.update(1000).unwrap();
+        assert_eq!(&pins.states()[7..], [false; 3]);
+        group
+            .switch_to_aspect(HVMainSignalAspect::Stop, &mut delay)
+            .unwrap();
+        group.update(1500).unwrap();
+        group.update(2000).unwrap();
+        // only the repeater signal’s notice lamp is lit outside of Deactivated.
+        assert_eq!(&pins.states()[7..9], [false; 2]);
+    }
+
+    #[test]
+    fn announcement_at_main_mast_is_dark_while_main_signal_shows_stop() {
+        let (group, pins) = signal_group();
+        let mut group = group
+            .with_substitution_signal(pins.pin())
+            .with_announcement_at_main_mast();
+        let mut delay = MockDelay::default();
+        for aspect in [
+            HVMainSignalAspect::Stop,
+            HVMainSignalAspect::Proceed,
+            HVMainSignalAspect::Substitution,
+            HVMainSignalAspect::ProceedSlow,
+            HVMainSignalAspect::Stop,
+        ] {
+            group.switch_to_aspect(aspect, &mut delay).unwrap();
+        }
+
+        // pins: main red, main green, announcement green upper and lower, announcement yellow upper and lower, main yellow, main Zs1
+        for state in pins.history() {
+            if state[0] {
+                assert_eq!(&state[2..6], [false; 4]);
+            }
+        }
+        assert_eq!(
+            pins.states(),
+            [true, false, false, false, false, false, false, false]
+        );
+        // the next aspect is unknown, so Vr0 is shown.
+        group
+            .switch_to_aspect(HVMainSignalAspect::Proceed, &mut delay)
+            .unwrap();
+        assert_eq!(
+            pins.states(),
+            [false, true, false, false, true, true, false, false]
+        );
+    }
+
+    #[test]
+    fn ks_signal_lights_exactly_the_lamps_of_its_aspect() {
+        let pins = MockPins::new();
+        let mut signal: KsSignal<Infallible, MockPin> =
+            KsSignal::new_multi_block(pins.pin(), pins.pin(), pins.pin())
+                .with_notice_lamp(pins.pin())
+                .with_substitution_lamp(pins.pin());
+        let roles = [
+            LampRole::MainRed,
+            LampRole::MainGreen,
+            LampRole::MainYellow,
+            LampRole::MainNotice,
+            LampRole::MainSubstitution,
+        ];
+        let mut delay = MockDelay::default();
+        for command_id in [&b"0"[..], b"Z1", b"1", b"2", b"3", b"A", b"D"] {
+            let aspect = KsSignalAspect::from_command_id(command_id).unwrap();
+            assert!(signal.supports_aspect(aspect));
+            signal.switch_to_aspect(aspect, &mut delay).unwrap();
+            assert_eq!(
+                pins.states(),
+                roles.map(|role| aspect.lights_lamp(role)),
+                "{}",
+                aspect.command_id()
+            );
+            assert!(roles.into_iter().all(|role| signal.lamp(role).is_some()));
+        }
+    }
+
+    #[test]
+    fn ks_group_announces_only_what_the_main_signal_shows() {
+        let pins = MockPins::new();
+        let mut group: KsSignalGroup<Infallible, MockPin> =
+            KsSignalGroup::new(pins.pin(), pins.pin(), pins.pin(), pins.pin())
+                .with_repeater_signal(pins.pin());
+        let mut delay = MockDelay::default();
+        assert!(!group.supports_aspect(KsSignalAspect::ExpectStop));
+        for aspect in [
+            KsSignalAspect::Stop,
+            KsSignalAspect::Proceed,
+            KsSignalAspect::Stop,
+        ] {
+            group.switch_to_aspect(aspect, &mut delay).unwrap();
+        }
+
+        // pins: main red, main green, distant green, distant yellow, repeater additional light
+        for state in pins.history() {
+            if state[2] {
+                assert_eq!((state[0], state[1]), (false, true));
+            }
+        }
+        assert_eq!(pins.states(), [true, false, false, true, true]);
+        assert_eq!(delay.total_ns, 800_000_000);
+    }
+
+    #[test]
+    fn ks_distant_at_main_mast_is_dark_while_main_signal_shows_stop() {
+        let pins = MockPins::new();
+        let mut group: KsSignalGroup<Infallible, MockPin> =
+            KsSignalGroup::new(pins.pin(), pins.pin(), pins.pin(), pins.pin())
+                .with_distant_at_main_mast();
+        let mut delay = MockDelay::default();
+        group
+            .switch_to_aspect(KsSignalAspect::Proceed, &mut delay)
+            .unwrap();
+        // pins: main red, main green, distant green, distant yellow. The next aspect is unknown, so Ks2 is shown.
+        assert_eq!(pins.states(), [false, true, false, true]);
+        group
+            .switch_to_aspect(KsSignalAspect::Stop, &mut delay)
+            .unwrap();
+        for state in pins.history() {
+            if state[0] {
+                assert_eq!((state[2], state[3]), (false, false));
+            }
+        }
+        assert_eq!(pins.states(), [true, false, false, false]);
+    }
+
+    #[test]
+    fn ks_distant_at_main_mast_announces_the_next_signal() {
+        let pins = MockPins::new();
+        let mut group: KsSignalGroup<Infallible, MockPin> =
+            KsSignalGroup::new(pins.pin(), pins.pin(), pins.pin(), pins.pin())
+                .with_distant_at_main_mast();
+        let mut delay = MockDelay::default();
+        group
+            .show_next_aspect(KsSignalAspect::Stop, &mut delay)
+            .unwrap();
+        // pins: main red, main green, distant green, distant yellow
+        assert_eq!(pins.states(), [false, false, false, false]);
+        group
+            .switch_to_aspect(KsSignalAspect::Proceed, &mut delay)
+            .unwrap();
+        assert_eq!(pins.states(), [false, true, false, true]);
+        group
+            .show_next_aspect(KsSignalAspect::Proceed, &mut delay)
+            .unwrap();
+        assert_eq!(pins.states(), [false, true, true, false]);
+        group
+            .switch_to_aspect(KsSignalAspect::Stop, &mut delay)
+            .unwrap();
+        group
+            .show_next_aspect(KsSignalAspect::Stop, &mut delay)
+            .unwrap();
+        assert_eq!(pins.states(), [true, false, false, false]);
+    }
+
+    #[test]
+    fn suppressed_announcement_stays_dark() {
+        let (group, pins) = signal_group();
+        let mut group = group.with_repeater_signal(pins.pin());
+        let mut delay = MockDelay::default();
+        group
+            .switch_to_aspect(HVMainSignalAspect::Proceed, &mut delay)
+            .unwrap();
+        group.suppress_announcement(true, &mut delay).unwrap();
+        // pins: main red, main green, announcement green upper, green lower, yellow upper, yellow lower, main yellow, repeater notice
+        assert_eq!(
+            pins.states(),
+            [false, true, false, false, false, false, false, false]
+        );
+        group
+            .switch_to_aspect(HVMainSignalAspect::Stop, &mut delay)
+            .unwrap();
+        assert_eq!(
+            pins.states(),
+            [true, false, false, false, false, false, false, false]
+        );
+        group.suppress_announcement(false, &mut delay).unwrap();
+        assert_eq!(
+            pins.states(),
+            [true, false, false, false, true, true, false, true]
+        );
+    }
+
+    #[test]
+    fn ks_distant_signal_announces_the_speed_of_the_main_signal() {
+        let pins = MockPins::new();
+        let mut group: KsSignalGroup<Infallible, MockPin> =
+            KsSignalGroup::new(pins.pin(), pins.pin(), pins.pin(), pins.pin())
+                .with_speed_indicator(Zs3Indicator::new([(); 7].map(|_| pins.pin())))
+                .with_speed_announcement_indicator(Zs3Indicator::new([(); 7].map(|_| pins.pin())));
+        let mut delay = MockDelay::default();
+        assert!(group.supports_speed(KsSignalAspect::Proceed, 6));
+        group
+            .switch_to_aspect_with_speed(KsSignalAspect::Proceed, 6, &mut delay)
+            .unwrap();
+        // pins: main red, main green, distant green, distant yellow, Zs3 segments a to g, Zs3v segments a to g
+        let sixes = [true, false, true, true, true, true, true];
+        assert_eq!(pins.states()[..4], [false, true, true, false]);
+        assert_eq!(pins.states()[4..11], sixes);
+        assert_eq!(pins.states()[11..], sixes);
+        group.update(500).unwrap();
+        group.update(1000).unwrap();
+        assert!(!pins.states()[2], "distant green lamp doesn’t blink");
+        group
+            .switch_to_aspect(KsSignalAspect::Proceed, &mut delay)
+            .unwrap();
+        group.update(2000).unwrap();
+        assert_eq!(pins.states()[..4], [false, true, true, false]);
+        assert_eq!(pins.states()[4..], [false; 14]);
+    }
+
+    #[test]
+    fn ks_signal_blinks_green_lamp_for_speed_limit_announcement() {
+        let pins = MockPins::new();
+        let mut signal: KsSignal<Infallible, MockPin> =
+            KsSignal::new_announcement(pins.pin(), pins.pin());
+        let mut delay = MockDelay::default();
+        signal
+            .switch_to_aspect(KsSignalAspect::ExpectSpeedLimit, &mut delay)
+            .unwrap();
+        // pins: green, yellow
+        let green_at = |signal: &mut KsSignal<Infallible, MockPin>, now| {
+            signal.update(now).unwrap();
+            pins.states()[0]
+        };
+        assert!(green_at(&mut signal, 1000));
+        assert!(green_at(&mut signal, 1499));
+        assert!(!green_at(&mut signal, 1500));
+        assert!(green_at(&mut signal, 2000));
+
+        signal
+            .switch_to_aspect(KsSignalAspect::Proceed, &mut delay)
+            .unwrap();
+        assert!(green_at(&mut signal, 2500));
+        assert!(!pins.states()[1]);
+    }
+
+    #[test]
+    fn dwarf_signal_lights_exactly_the_lamps_of_its_aspect() {
+        let pins = MockPins::new();
+        let mut signal: DwarfSignal<Infallible, MockPin> =
+            DwarfSignal::new(pins.pin(), pins.pin()).with_notice_lamp(pins.pin());
+        let roles = [
+            LampRole::DwarfRed,
+            LampRole::DwarfWhite,
+            LampRole::DwarfNotice,
+        ];
+        let mut delay = MockDelay::default();
+        for command_id in [&b"SH0"[..], b"SH1", b"A", b"SH0", b"D"] {
+            let aspect = DwarfSignalAspect::from_command_id(command_id).unwrap();
+            assert!(signal.supports_aspect(aspect));
+            signal.switch_to_aspect(aspect, &mut delay).unwrap();
+            assert_eq!(
+                pins.states(),
+                roles.map(|role| aspect.lights_lamp(role)),
+                "{}",
+                aspect.command_id()
+            );
+        }
+    }
+
+    #[test]
+    fn crossing_signal_blinks_white_in_bue1_and_lights_yellow_in_bue0() {
+        let pins = MockPins::new();
+        let mut signal: CrossingSignal<Infallible, MockPin> =
+            CrossingSignal::new(pins.pin()).with_yellow_lamp(pins.pin());
+        let mut delay = MockDelay::default();
+        signal
+            .switch_to_aspect(CrossingSignalAspect::Stop, &mut delay)
+            .unwrap();
+        assert_eq!(pins.states(), [false, true]);
+
+        let aspect = CrossingSignalAspect::from_command_id(b"BU1").unwrap();
+        signal.switch_to_aspect(aspect, &mut delay).unwrap();
+        assert_eq!(pins.states(), [true, false]);
+        signal.update(1000).unwrap();
+        assert_eq!(pins.states(), [true, false]);
+        signal.update(1500).unwrap();
+        assert_eq!(pins.states(), [false, false]);
+        signal.update(2000).unwrap();
+        assert_eq!(pins.states(), [true, false]);
+
+        signal
+            .switch_to_aspect(CrossingSignalAspect::Stop, &mut delay)
+            .unwrap();
+        signal.update(2500).unwrap();
+        assert_eq!(pins.states(), [false, true]);
+    }
+
+    #[test]
+    fn sv_transitions_are_never_less_restrictive_than_both_aspects() {
+        const ASPECTS: [SvSignalAspect; 9] = [
+            SvSignalAspect::Stop,
+            SvSignalAspect::StopThenOnSight,
+            SvSignalAspect::ProceedExpectProceed,
+            SvSignalAspect::ProceedExpectStop,
+            SvSignalAspect::ProceedExpectProceedSlow,
+            SvSignalAspect::ProceedSlowExpectProceed,
+            SvSignalAspect::ProceedSlowExpectProceedSlow,
+            SvSignalAspect::ProceedSlowExpectStop,
+            SvSignalAspect::Dark,
+        ];
+        let roles = [
+            LampRole::MainRed,
+            LampRole::MainGreen,
+            LampRole::MainYellow,
+            LampRole::AnnouncementGreenUpper,
+            LampRole::AnnouncementYellowUpper,
+        ];
+        let lamps_of = |aspect: SvSignalAspect| roles.map(|role| aspect.lights_lamp(role)).to_vec();
+        let mut delay = MockDelay::default();
+        for from in ASPECTS {
+            for to in ASPECTS {
+                let pins = MockPins::new();
+                let mut signal: SvSignal<Infallible, MockPin> =
+                    SvSignal::new(pins.pin(), pins.pin(), pins.pin(), pins.pin(), pins.pin());
+                signal.switch_to_aspect(from, &mut delay).unwrap();
+                pins.clear_history();
+                signal.switch_to_aspect(to, &mut delay).unwrap();
+                assert_eq!(pins.states(), lamps_of(to));
+                for state in pins.history() {
+                    // a lit red lamp, or two lamps on one side that don’t form an aspect, are read as stop.
+                    let Some(shown) = ASPECTS
+                        .into_iter()
+                        .find(|aspect| lamps_of(*aspect) == state)
+                    else {
+                        continue;
+                    };
+                    assert!(
+                        shown.blanks_signal()
+                            || shown.cmp_restrictiveness(from).is_le()
+                            || shown.cmp_restrictiveness(to).is_le(),
+                        "{} → {} shows {}",
+                        from.command_id(),
+                        to.command_id(),
+                        shown.command_id()
+                    );
+                    assert!(
+                        !shown.blanks_signal() || from.blanks_signal() || to.blanks_signal(),
+                        "{} → {} goes dark",
+                        from.command_id(),
+                        to.command_id()
+                    );
+                }
+            }
+        }
+    }
+}
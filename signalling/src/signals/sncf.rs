@@ -0,0 +1,363 @@
+//! Module for the light signals of the French SNCF and the Belgian SNCB, whose heads (cibles) show the aspects of a main and a distant signal with one lamp each.
+//!
+//! The aspects are mapped onto the generic commands by their meaning: the carré is the absolute stop of `0`, the sémaphore stops the train before it proceeds on sight like `SV0`, and the flashing red lamp lets it pass on sight like `Z7`. The avertissement and the flashing green lamp announce a stop and a speed limit at the next signal like the Ks aspects `2` and `3`.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::digital::PinState;
+
+use super::LampRole;
+use super::Signal;
+use super::SignalAspect;
+use super::UnsupportedAspect;
+use super::BLINK_HALF_PERIOD_MS;
+use crate::blink::Blinker;
+use crate::commands::AspectCommand;
+
+/// An aspect of an SNCF or SNCB light signal.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SncfSignalAspect {
+    // C: Carré, arrêt absolu.
+    AbsoluteStop,
+    // S: Sémaphore, arrêt, puis marche à vue.
+    StopThenOnSight,
+    // (R): Feu rouge clignotant, marche à vue sans arrêt.
+    OnSight,
+    // A: Avertissement, arrêt au signal suivant.
+    ExpectStop,
+    // (VL): Feu vert clignotant, 160 km/h au plus au signal suivant.
+    ExpectSpeedLimit,
+    // VL: Voie libre.
+    Proceed,
+    // Signal éteint.
+    Dark,
+}
+
+impl SignalAspect for SncfSignalAspect {
+    const STOP: Self = Self::AbsoluteStop;
+    const SYSTEM_ID: &'static str = "SNCF";
+    // the signals have no notice lamp.
+    const ALLOWS_FLASHING_NOTICE_LAMPS: bool = false;
+
+    fn command_id(self) -> &'static str {
+        match self {
+            Self::AbsoluteStop => "0",
+            Self::StopThenOnSight => "SV0",
+            Self::OnSight => "Z7",
+            Self::ExpectStop => "2",
+            Self::ExpectSpeedLimit => "3",
+            Self::Proceed => "1",
+            Self::Dark => "D",
+        }
+    }
+
+    fn from_command_id(command_id: &[u8]) -> Option<Self> {
+        AspectCommand::from_command_id(command_id).and_then(|command| command.try_into().ok())
+    }
+
+    fn blanks_signal(self) -> bool {
+        self == Self::Dark
+    }
+
+    fn is_temporary(self) -> bool {
+        false
+    }
+
+    fn clears_signal(self) -> bool {
+        matches!(
+            self,
+            Self::ExpectStop | Self::ExpectSpeedLimit | Self::Proceed
+        )
+    }
+
+    fn restrictiveness(self) -> u8 {
+        match self {
+            Self::AbsoluteStop => 0,
+            Self::StopThenOnSight => 1,
+            Self::OnSight => 2,
+            Self::ExpectStop => 3,
+            Self::ExpectSpeedLimit => 4,
+            Self::Proceed => 5,
+            Self::Dark => 6,
+        }
+    }
+
+    fn lights_lamp(self, role: LampRole) -> bool {
+        match role {
+            // the flashing red lamp is lit at least half of the time.
+            LampRole::MainRed => matches!(
+                self,
+                Self::AbsoluteStop | Self::StopThenOnSight | Self::OnSight
+            ),
+            LampRole::DwarfRed => self == Self::AbsoluteStop,
+            LampRole::MainYellow => self == Self::ExpectStop,
+            // the flashing green lamp is lit at least half of the time.
+            LampRole::MainGreen => matches!(self, Self::ExpectSpeedLimit | Self::Proceed),
+            _ => false,
+        }
+    }
+
+    fn blinks_lamp(self, role: LampRole) -> bool {
+        match role {
+            LampRole::MainRed => self == Self::OnSight,
+            LampRole::MainGreen => self == Self::ExpectSpeedLimit,
+            _ => false,
+        }
+    }
+}
+
+impl TryFrom<AspectCommand> for SncfSignalAspect {
+    type Error = UnsupportedAspect;
+
+    fn try_from(value: AspectCommand) -> Result<Self, Self::Error> {
+        match value {
+            AspectCommand::Zero => Ok(Self::AbsoluteStop),
+            AspectCommand::Sv0 => Ok(Self::StopThenOnSight),
+            AspectCommand::Caution => Ok(Self::OnSight),
+            AspectCommand::Two => Ok(Self::ExpectStop),
+            AspectCommand::Three => Ok(Self::ExpectSpeedLimit),
+            AspectCommand::One => Ok(Self::Proceed),
+            AspectCommand::Dark => Ok(Self::Dark),
+            _ => Err(UnsupportedAspect),
+        }
+    }
+}
+
+/// An SNCF or SNCB light signal with the two red lamps of the carré, a green lamp, and optionally the yellow lamp of the avertissement. The sémaphore only lights the lower red lamp.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used. Its parameters additionally include the output pin’s error type (which some functions also return).
+pub struct SncfSignal<Error, PinType: OutputPin<Error = Error>> {
+    // Red lamp of the sémaphore, which is also the lower lamp of the carré.
+    red_lamp: PinType,
+    // Upper red lamp of the carré.
+    upper_red_lamp: PinType,
+    green_lamp: PinType,
+    yellow_lamp: Option<PinType>,
+    // Blinkers for the red and green lamp, while they flash.
+    red_blinker: Option<Blinker>,
+    green_blinker: Option<Blinker>,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> SncfSignal<Error, PinType> {
+    /// Order in which the lamps are switched: red and yellow lamps of the new aspect go on first and the old ones go off last, so that every intermediate lamp combination is at least as restrictive as one of the two aspects.
+    const SWITCHING_ORDER: [(LampRole, PinState); 8] = [
+        (LampRole::MainRed, PinState::High),
+        (LampRole::DwarfRed, PinState::High),
+        (LampRole::MainYellow, PinState::High),
+        (LampRole::MainGreen, PinState::Low),
+        (LampRole::MainGreen, PinState::High),
+        (LampRole::MainYellow, PinState::Low),
+        (LampRole::DwarfRed, PinState::Low),
+        (LampRole::MainRed, PinState::Low),
+    ];
+
+    pub fn new(red_lamp: PinType, upper_red_lamp: PinType, green_lamp: PinType) -> Self {
+        Self {
+            red_lamp,
+            upper_red_lamp,
+            green_lamp,
+            yellow_lamp: None,
+            red_blinker: None,
+            green_blinker: None,
+        }
+    }
+
+    /// Adds the yellow lamp, for announcing a stop at the next signal.
+    pub fn with_yellow_lamp(mut self, yellow_lamp: PinType) -> Self {
+        self.yellow_lamp = Some(yellow_lamp);
+        self
+    }
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> Signal for SncfSignal<Error, PinType> {
+    type Aspect = SncfSignalAspect;
+    type Pin = PinType;
+    type Error = Error;
+
+    fn supports_aspect(&self, aspect: SncfSignalAspect) -> bool {
+        aspect != SncfSignalAspect::ExpectStop || self.yellow_lamp.is_some()
+    }
+
+    fn switch_to_aspect(
+        &mut self,
+        aspect: SncfSignalAspect,
+        _delay: &mut impl DelayNs,
+    ) -> Result<(), Error> {
+        // logic bug, since user code should ensure to never try to enable illegal aspects on signals that don’t support them
+        if !self.supports_aspect(aspect) {
+            panic!("illegal aspect for this light, no yellow available");
+        }
+        self.red_blinker = None;
+        self.green_blinker = None;
+        for (role, state) in Self::SWITCHING_ORDER {
+            if aspect.lights_lamp(role) == (state == PinState::High) {
+                if let Some(lamp) = self.lamp(role) {
+                    lamp.set_state(state)?;
+                }
+            }
+        }
+        // the blinkers start in their off phase, so their first toggle keeps the lit lamp on and starts the blinking.
+        if aspect.blinks_lamp(LampRole::MainRed) {
+            self.red_blinker = Some(Blinker::new(BLINK_HALF_PERIOD_MS));
+        }
+        if aspect.blinks_lamp(LampRole::MainGreen) {
+            self.green_blinker = Some(Blinker::new(BLINK_HALF_PERIOD_MS));
+        }
+        Ok(())
+    }
+
+    fn lamp(&mut self, role: LampRole) -> Option<&mut PinType> {
+        match role {
+            LampRole::MainRed => Some(&mut self.red_lamp),
+            LampRole::DwarfRed => Some(&mut self.upper_red_lamp),
+            LampRole::MainGreen => Some(&mut self.green_lamp),
+            LampRole::MainYellow => self.yellow_lamp.as_mut(),
+            _ => None,
+        }
+    }
+
+    fn update(&mut self, now: u32) -> Result<(), Error> {
+        if let Some(red_blinker) = &mut self.red_blinker {
+            if let Some(is_on) = red_blinker.update(now) {
+                self.red_lamp.set_state(is_on.into())?;
+            }
+        }
+        if let Some(green_blinker) = &mut self.green_blinker {
+            if let Some(is_on) = green_blinker.update(now) {
+                self.green_lamp.set_state(is_on.into())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use super::SncfSignal;
+    use super::SncfSignalAspect;
+    use crate::mock::MockDelay;
+    use crate::mock::MockPin;
+    use crate::mock::MockPins;
+    use crate::signals::LampRole;
+    use crate::signals::Signal;
+    use crate::signals::SignalAspect;
+
+    // the sémaphore comes before the flashing red lamp, so that a lit red lamp is read as the more restrictive aspect.
+    const ASPECTS: [SncfSignalAspect; 7] = [
+        SncfSignalAspect::AbsoluteStop,
+        SncfSignalAspect::StopThenOnSight,
+        SncfSignalAspect::OnSight,
+        SncfSignalAspect::ExpectStop,
+        SncfSignalAspect::ExpectSpeedLimit,
+        SncfSignalAspect::Proceed,
+        SncfSignalAspect::Dark,
+    ];
+    // in the order of the pins.
+    const ROLES: [LampRole; 4] = [
+        LampRole::MainRed,
+        LampRole::DwarfRed,
+        LampRole::MainGreen,
+        LampRole::MainYellow,
+    ];
+
+    fn lamps_of(aspect: SncfSignalAspect) -> Vec<bool> {
+        ROLES.map(|role| aspect.lights_lamp(role)).to_vec()
+    }
+
+    #[test]
+    fn generic_commands_map_to_the_aspects() {
+        for (command_id, aspect) in [
+            (&b"0"[..], SncfSignalAspect::AbsoluteStop),
+            (b"SV0", SncfSignalAspect::StopThenOnSight),
+            (b"Z7", SncfSignalAspect::OnSight),
+            (b"2", SncfSignalAspect::ExpectStop),
+            (b"3", SncfSignalAspect::ExpectSpeedLimit),
+            (b"1", SncfSignalAspect::Proceed),
+            (b"D", SncfSignalAspect::Dark),
+        ] {
+            assert!(SncfSignalAspect::from_command_id(command_id) == Some(aspect));
+            assert_eq!(aspect.command_id().as_bytes(), command_id);
+        }
+        for command_id in [&b"S"[..], b"Z1", b"A", b"SV1", b"SH0"] {
+            assert!(SncfSignalAspect::from_command_id(command_id).is_none());
+        }
+    }
+
+    #[test]
+    fn sncf_transitions_are_never_less_restrictive_than_both_aspects() {
+        let mut delay = MockDelay::default();
+        for from in ASPECTS {
+            for to in ASPECTS {
+                let pins = MockPins::new();
+                let mut signal: SncfSignal<Infallible, MockPin> =
+                    SncfSignal::new(pins.pin(), pins.pin(), pins.pin())
+                        .with_yellow_lamp(pins.pin());
+                signal.switch_to_aspect(from, &mut delay).unwrap();
+                pins.clear_history();
+                signal.switch_to_aspect(to, &mut delay).unwrap();
+                assert_eq!(pins.states(), lamps_of(to));
+                for state in pins.history() {
+                    // a red lamp next to another lamp is read as stop.
+                    let Some(shown) = ASPECTS
+                        .into_iter()
+                        .find(|aspect| lamps_of(*aspect) == state)
+                    else {
+                        continue;
+                    };
+                    assert!(
+                        shown.blanks_signal()
+                            || shown.cmp_restrictiveness(from).is_le()
+                            || shown.cmp_restrictiveness(to).is_le(),
+                        "{} → {} shows {}",
+                        from.command_id(),
+                        to.command_id(),
+                        shown.command_id()
+                    );
+                    assert!(
+                        !shown.blanks_signal() || from.blanks_signal() || to.blanks_signal(),
+                        "{} → {} goes dark",
+                        from.command_id(),
+                        to.command_id()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn flashing_aspects_blink_their_lamp() {
+        let pins = MockPins::new();
+        let mut signal: SncfSignal<Infallible, MockPin> =
+            SncfSignal::new(pins.pin(), pins.pin(), pins.pin());
+        let mut delay = MockDelay::default();
+        assert!(!signal.supports_aspect(SncfSignalAspect::ExpectStop));
+
+        signal
+            .switch_to_aspect(SncfSignalAspect::OnSight, &mut delay)
+            .unwrap();
+        signal.update(1000).unwrap();
+        assert_eq!(pins.states(), [true, false, false]);
+        signal.update(1500).unwrap();
+        assert_eq!(pins.states(), [false, false, false]);
+
+        // the sémaphore lights the red lamp steadily, even if it was in its off phase.
+        signal
+            .switch_to_aspect(SncfSignalAspect::StopThenOnSight, &mut delay)
+            .unwrap();
+        signal.update(2000).unwrap();
+        signal.update(2500).unwrap();
+        assert_eq!(pins.states(), [true, false, false]);
+
+        signal
+            .switch_to_aspect(SncfSignalAspect::ExpectSpeedLimit, &mut delay)
+            .unwrap();
+        signal.update(3000).unwrap();
+        assert_eq!(pins.states(), [false, false, true]);
+        signal.update(3500).unwrap();
+        assert_eq!(pins.states(), [false, false, false]);
+    }
+}
@@ -0,0 +1,125 @@
+//! Module for limiting the slew rate of lamp outputs, which reduces the switching transients that long lamp cables radiate.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::ErrorType;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::digital::StatefulOutputPin;
+
+/// Period of the software PWM that ramps a lamp between its states, in microseconds.
+const PWM_PERIOD_US: u32 = 250;
+
+/// The longest ramp that can be configured for a lamp, in milliseconds. Ramps block the main loop, so they must stay short.
+pub const MAX_RAMP_MS: u8 = 20;
+
+/// An output pin that doesn’t switch instantly, but ramps to its new state with a software PWM whose duty cycle changes linearly.
+///
+/// The pin keeps track of its state, assuming that it is initially low. A ramp is only performed if the state actually changes and a ramp duration is set; by default, the pin switches instantly.
+pub struct SlewLimitedPin<PinType: OutputPin, DelayType: DelayNs> {
+    pin: PinType,
+    delay: DelayType,
+    // Duration of the ramp, in milliseconds. 0 switches instantly.
+    ramp_ms: u8,
+    // Whether the pin was last set high.
+    is_high: bool,
+}
+
+impl<PinType: OutputPin, DelayType: DelayNs> SlewLimitedPin<PinType, DelayType> {
+    pub fn new(pin: PinType, delay: DelayType) -> Self {
+        Self {
+            pin,
+            delay,
+            ramp_ms: 0,
+            is_high: false,
+        }
+    }
+
+    /// Changes the duration of future ramps. Durations longer than [`MAX_RAMP_MS`] are limited to it.
+    pub fn set_ramp_ms(&mut self, ramp_ms: u8) {
+        self.ramp_ms = ramp_ms.min(MAX_RAMP_MS);
+    }
+
+    /// Returns the wrapped pin, e.g. for reading back its state.
+    pub fn inner_mut(&mut self) -> &mut PinType {
+        &mut self.pin
+    }
+
+    fn switch_to(&mut self, is_high: bool) -> Result<(), PinType::Error> {
+        if is_high != self.is_high {
+            let periods = u32::from(self.ramp_ms) * 1000 / PWM_PERIOD_US;
+            for period in 0..periods {
+                // the new state’s share of each period grows until it is always on.
+                let new_state_us = PWM_PERIOD_US * (period + 1) / (periods + 1);
+                self.pin.set_state(is_high.into())?;
+                self.delay.delay_us(new_state_us);
+                self.pin.set_state((!is_high).into())?;
+                self.delay.delay_us(PWM_PERIOD_US - new_state_us);
+            }
+        }
+        self.pin.set_state(is_high.into())?;
+        self.is_high = is_high;
+        Ok(())
+    }
+}
+
+impl<PinType: OutputPin, DelayType: DelayNs> ErrorType for SlewLimitedPin<PinType, DelayType> {
+    type Error = PinType::Error;
+}
+
+impl<PinType: OutputPin, DelayType: DelayNs> OutputPin for SlewLimitedPin<PinType, DelayType> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.switch_to(false)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.switch_to(true)
+    }
+}
+
+/// The state is the one the pin was last set to, even if the wrapped pin was switched directly since then.
+impl<PinType: OutputPin, DelayType: DelayNs> StatefulOutputPin
+    for SlewLimitedPin<PinType, DelayType>
+{
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.is_high)
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.is_high)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal::digital::OutputPin;
+
+    use super::SlewLimitedPin;
+    use crate::mock::MockDelay;
+    use crate::mock::MockPins;
+
+    #[test]
+    fn ramps_only_when_the_state_changes() {
+        let pins = MockPins::new();
+        let mut pin = SlewLimitedPin::new(pins.pin(), MockDelay::default());
+        pin.set_ramp_ms(2);
+        pin.set_high().unwrap();
+        // two edges in each of the eight PWM periods, and the final state.
+        assert_eq!(pins.history().len(), 17);
+        assert_eq!(pin.delay.total_ns, 2_000_000);
+        assert_eq!(pins.states(), [true]);
+
+        pins.clear_history();
+        pin.set_high().unwrap();
+        assert_eq!(pins.history(), [[true]]);
+        assert_eq!(pin.delay.total_ns, 2_000_000);
+    }
+
+    #[test]
+    fn switches_instantly_without_ramp() {
+        let pins = MockPins::new();
+        let mut pin = SlewLimitedPin::new(pins.pin(), MockDelay::default());
+        pin.set_high().unwrap();
+        pin.set_low().unwrap();
+        assert_eq!(pins.history(), [[true], [false]]);
+        assert_eq!(pin.delay.total_ns, 0);
+    }
+}
@@ -0,0 +1,465 @@
+//! Module for SRCP, the Simple Railroad Command Protocol of srcpd and erddcd, in which the signal answers the layout software on the serial link like an SRCP server with a single bus of generic accessories (GA) and generic locomotives (GL).
+//!
+//! A session starts with a handshake: the client selects the protocol with `SET PROTOCOL SRCP 0.8.4` and the command mode with `SET CONNECTIONMODE SRCP COMMAND`, and starts the session with `GO`. Afterwards, every line is a command like `SET 1 GA 12 1 1 -1`, which sets the value of port 1 of accessory 12 on bus 1 to 1, with a delay of -1, i.e. without switching the port off again. Every command is answered with a line that starts with the time in seconds and a three-digit status: 1xx for information, 2xx for success and 4xx for errors.
+//!
+//! The accessories are the DCC accessory addresses, whose port 1 is the green and port 0 the red output. The signal’s Zs3 speed indicator is a locomotive at the signal’s first address, whose speed the signal shows in tens of km/h, scaled so that the highest speed is 150 km/h, with the aspect of the next accessory command. The serial link has no connections, so a new handshake may start at any time and replaces the current session.
+
+use ufmt::uDisplay;
+use ufmt::uWrite;
+use ufmt::Formatter;
+
+use crate::commands::parse_decimal;
+use crate::dcc::AccessoryCommand;
+
+/// Line that announces the server, which the client waits for before the handshake.
+pub const GREETING: &str = "train-signalling; SRCP 0.8.4\n";
+/// The only bus, which holds the accessories and the locomotive.
+const BUS: u32 = 1;
+/// Drive mode of a locomotive in an emergency stop, which shows no speed.
+const EMERGENCY_STOP: u8 = 2;
+/// Highest speed of the Zs3 speed indicator, in tens of km/h.
+const MAX_SPEED: u32 = 15;
+
+/// Errors of SRCP commands, with their status.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SrcpError {
+    UnsupportedProtocol = 400,
+    UnsupportedConnectionMode = 401,
+    InsufficientData = 402,
+    UnknownCommand = 410,
+    WrongValue = 412,
+    NoData = 416,
+    ListTooShort = 419,
+    UnsupportedDeviceGroup = 422,
+}
+
+impl SrcpError {
+    fn text(self) -> &'static str {
+        match self {
+            Self::UnsupportedProtocol => "unsupported protocol",
+            Self::UnsupportedConnectionMode => "unsupported connection mode",
+            Self::InsufficientData => "insufficient data",
+            Self::UnknownCommand => "unknown command",
+            Self::WrongValue => "wrong value",
+            Self::NoData => "no data",
+            Self::ListTooShort => "list too short",
+            Self::UnsupportedDeviceGroup => "unsupported device group",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Message {
+    Ok,
+    Protocol,
+    ConnectionMode,
+    Go(u16),
+    Accessory {
+        address: u16,
+        port: u8,
+        value: bool,
+    },
+    Locomotive {
+        address: u16,
+        state: LocomotiveState,
+    },
+    Error(SrcpError),
+}
+
+/// The state of the locomotive as set by the client: its drive mode, its speed, and its highest speed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct LocomotiveState {
+    drive_mode: u8,
+    speed: u16,
+    max_speed: u16,
+}
+
+/// The reply to an SRCP command, which is sent by formatting it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SrcpReply {
+    time_ms: u32,
+    message: Message,
+}
+
+impl uDisplay for SrcpReply {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        let milliseconds = self.time_ms % 1000;
+        ufmt::uwrite!(f, "{}.", self.time_ms / 1000)?;
+        if milliseconds < 100 {
+            f.write_char('0')?;
+        }
+        if milliseconds < 10 {
+            f.write_char('0')?;
+        }
+        ufmt::uwrite!(f, "{} ", milliseconds)?;
+        match self.message {
+            Message::Ok => f.write_str("200 OK")?,
+            Message::Protocol => f.write_str("201 OK PROTOCOL SRCP")?,
+            Message::ConnectionMode => f.write_str("202 OK CONNECTIONMODE")?,
+            Message::Go(session) => ufmt::uwrite!(f, "200 OK GO {}", session)?,
+            Message::Accessory {
+                address,
+                port,
+                value,
+            } => ufmt::uwrite!(
+                f,
+                "100 INFO {} GA {} {} {}",
+                BUS,
+                address,
+                port,
+                u8::from(value)
+            )?,
+            Message::Locomotive { address, state } => ufmt::uwrite!(
+                f,
+                "100 INFO {} GL {} {} {} {}",
+                BUS,
+                address,
+                state.drive_mode,
+                state.speed,
+                state.max_speed
+            )?,
+            Message::Error(error) => ufmt::uwrite!(f, "{} ERROR {}", error as u16, error.text())?,
+        }
+        f.write_char('\n')
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    // Waiting for the handshake to select the protocol and the connection mode, which are recorded, before GO.
+    Handshake {
+        protocol: bool,
+        connection_mode: bool,
+    },
+    Command,
+}
+
+/// A session of the SRCP server on the serial link.
+pub struct SrcpSession {
+    // The signal’s first address, which is also the address of its locomotive.
+    address: u16,
+    mode: Mode,
+    // Number of the last session.
+    session: u16,
+    locomotive: Option<LocomotiveState>,
+}
+
+impl SrcpSession {
+    pub const fn new(address: u16) -> Self {
+        Self {
+            address,
+            mode: Mode::Handshake {
+                protocol: false,
+                connection_mode: false,
+            },
+            session: 0,
+            locomotive: None,
+        }
+    }
+
+    /// Handles a line received at the given time, and returns the reply and the accessory command that it contained, if any, or None if the line isn’t an SRCP command. The given function returns whether an accessory output, given as its address and whether it is the green output, is switched on.
+    pub fn handle_line(
+        &mut self,
+        line: &[u8],
+        now: u32,
+        is_on: impl Fn(u16, bool) -> bool,
+    ) -> Option<(SrcpReply, Option<AccessoryCommand>)> {
+        let mut words = line
+            .split(u8::is_ascii_whitespace)
+            .filter(|word| !word.is_empty());
+        let command = words.next()?;
+        if !matches!(
+            command,
+            b"GET" | b"SET" | b"CHECK" | b"WAIT" | b"INIT" | b"TERM" | b"RESET" | b"VERIFY" | b"GO"
+        ) {
+            return None;
+        }
+        let mut accessory_command = None;
+        let message = self
+            .handle_command(command, &mut words, &is_on, &mut accessory_command)
+            .unwrap_or_else(Message::Error);
+        Some((
+            SrcpReply {
+                time_ms: now,
+                message,
+            },
+            accessory_command,
+        ))
+    }
+
+    fn handle_command<'a>(
+        &mut self,
+        command: &[u8],
+        words: &mut impl Iterator<Item = &'a [u8]>,
+        is_on: &impl Fn(u16, bool) -> bool,
+        accessory_command: &mut Option<AccessoryCommand>,
+    ) -> Result<Message, SrcpError> {
+        let first = words.next();
+        // a handshake ends the current session.
+        match (command, first) {
+            (b"SET", Some(b"PROTOCOL")) => {
+                if words.next() != Some(b"SRCP") || !words.next().is_some_and(is_version_0_8) {
+                    return Err(SrcpError::UnsupportedProtocol);
+                }
+                let connection_mode = matches!(
+                    self.mode,
+                    Mode::Handshake {
+                        connection_mode: true,
+                        ..
+                    }
+                );
+                self.mode = Mode::Handshake {
+                    protocol: true,
+                    connection_mode,
+                };
+                return Ok(Message::Protocol);
+            }
+            (b"SET", Some(b"CONNECTIONMODE")) => {
+                if words.next() != Some(b"SRCP") || words.next() != Some(b"COMMAND") {
+                    return Err(SrcpError::UnsupportedConnectionMode);
+                }
+                let protocol = matches!(self.mode, Mode::Handshake { protocol: true, .. });
+                self.mode = Mode::Handshake {
+                    protocol,
+                    connection_mode: true,
+                };
+                return Ok(Message::ConnectionMode);
+            }
+            (b"GO", None) => {
+                return match self.mode {
+                    Mode::Handshake {
+                        protocol: true,
+                        connection_mode: true,
+                    } => {
+                        self.mode = Mode::Command;
+                        self.session = self.session.wrapping_add(1);
+                        Ok(Message::Go(self.session))
+                    }
+                    _ => Err(SrcpError::InsufficientData),
+                };
+            }
+            _ => {}
+        }
+        if self.mode != Mode::Command {
+            return Err(SrcpError::UnknownCommand);
+        }
+        let bus = first.ok_or(SrcpError::ListTooShort)?;
+        // bus 0 is the server itself, of which only the session can be ended.
+        if command == b"TERM" && bus == b"0" && words.next() == Some(b"SESSION") {
+            self.mode = Mode::Handshake {
+                protocol: false,
+                connection_mode: false,
+            };
+            return Ok(Message::Ok);
+        }
+        if parse_decimal(bus) != Some(BUS) {
+            return Err(SrcpError::WrongValue);
+        }
+        let group = words.next().ok_or(SrcpError::ListTooShort)?;
+        let address = u16::try_from(next_number(words)?).map_err(|_| SrcpError::WrongValue)?;
+        match (command, group) {
+            // the protocol of the accessory or the locomotive doesn’t matter.
+            (b"INIT" | b"TERM", b"GA" | b"GL") => Ok(Message::Ok),
+            (b"SET", b"GA") => {
+                let port = next_flag(words)?;
+                let value = next_flag(words)?;
+                // the delay is ignored, since the signal keeps its aspect.
+                let delay = words.next().ok_or(SrcpError::ListTooShort)?;
+                if delay != b"-1" && parse_decimal(delay).is_none() {
+                    return Err(SrcpError::WrongValue);
+                }
+                *accessory_command = Some(AccessoryCommand::Basic {
+                    address,
+                    is_green: port,
+                    is_active: value,
+                });
+                Ok(Message::Ok)
+            }
+            (b"GET", b"GA") => {
+                let port = next_flag(words)?;
+                Ok(Message::Accessory {
+                    address,
+                    port: port.into(),
+                    value: is_on(address, port),
+                })
+            }
+            (b"SET", b"GL") => {
+                let drive_mode = u8::try_from(next_number(words)?)
+                    .ok()
+                    .filter(|drive_mode| *drive_mode <= EMERGENCY_STOP)
+                    .ok_or(SrcpError::WrongValue)?;
+                let speed = next_number(words)?;
+                let max_speed = next_number(words)?;
+                if max_speed == 0 || speed > max_speed || max_speed > u16::MAX.into() {
+                    return Err(SrcpError::WrongValue);
+                }
+                // the functions are ignored, since the speed indicator has none.
+                if address == self.address {
+                    self.locomotive = Some(LocomotiveState {
+                        drive_mode,
+                        speed: speed as u16,
+                        max_speed: max_speed as u16,
+                    });
+                }
+                Ok(Message::Ok)
+            }
+            (b"GET", b"GL") => self
+                .locomotive
+                .filter(|_| address == self.address)
+                .map(|state| Message::Locomotive { address, state })
+                .ok_or(SrcpError::NoData),
+            (_, b"GA" | b"GL") => Err(SrcpError::UnknownCommand),
+            _ => Err(SrcpError::UnsupportedDeviceGroup),
+        }
+    }
+
+    /// Returns the Zs3 speed that the client set with the speed of the signal’s locomotive, in tens of km/h, or None if it doesn’t show a speed.
+    pub fn speed(&self) -> Option<u8> {
+        let state = self
+            .locomotive
+            .filter(|state| state.drive_mode != EMERGENCY_STOP)?;
+        let scaled = (u32::from(state.speed) * MAX_SPEED + u32::from(state.max_speed) / 2)
+            / u32::from(state.max_speed);
+        // a slow but moving locomotive still shows the lowest speed.
+        (state.speed != 0).then_some(scaled.max(1) as u8)
+    }
+}
+
+fn is_version_0_8(version: &[u8]) -> bool {
+    version.starts_with(b"0.8")
+}
+
+fn next_number<'a>(words: &mut impl Iterator<Item = &'a [u8]>) -> Result<u32, SrcpError> {
+    parse_decimal(words.next().ok_or(SrcpError::ListTooShort)?).ok_or(SrcpError::WrongValue)
+}
+
+fn next_flag<'a>(words: &mut impl Iterator<Item = &'a [u8]>) -> Result<bool, SrcpError> {
+    match next_number(words)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(SrcpError::WrongValue),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SrcpSession;
+    use crate::dcc::AccessoryCommand;
+
+    /// Handles a line and returns the formatted reply, without the time, and the accessory command.
+    fn handle(session: &mut SrcpSession, line: &str) -> Option<(String, Option<AccessoryCommand>)> {
+        let (reply, command) =
+            session.handle_line(line.as_bytes(), 12_034, |address, is_green| {
+                address == 20 && is_green
+            })?;
+        let mut text = String::new();
+        ufmt::uwrite!(text, "{}", reply).unwrap();
+        let text = text.strip_prefix("12.034 ").unwrap().trim_end().to_string();
+        Some((text, command))
+    }
+
+    fn reply(session: &mut SrcpSession, line: &str) -> String {
+        handle(session, line).unwrap().0
+    }
+
+    #[test]
+    fn switches_accessories_after_the_handshake() {
+        let mut session = SrcpSession::new(20);
+        assert_eq!(
+            reply(&mut session, "SET 1 GA 20 1 1 -1"),
+            "410 ERROR unknown command"
+        );
+        assert_eq!(reply(&mut session, "GO"), "402 ERROR insufficient data");
+        assert_eq!(
+            reply(&mut session, "SET PROTOCOL SRCP 0.7.3"),
+            "400 ERROR unsupported protocol"
+        );
+        assert_eq!(
+            reply(&mut session, "SET PROTOCOL SRCP 0.8.4\r\n"),
+            "201 OK PROTOCOL SRCP"
+        );
+        assert_eq!(
+            reply(&mut session, "SET CONNECTIONMODE SRCP INFO"),
+            "401 ERROR unsupported connection mode"
+        );
+        assert_eq!(
+            reply(&mut session, "SET CONNECTIONMODE SRCP COMMAND"),
+            "202 OK CONNECTIONMODE"
+        );
+        assert_eq!(reply(&mut session, "GO"), "200 OK GO 1");
+
+        assert_eq!(reply(&mut session, "INIT 1 GA 20 N"), "200 OK");
+        assert_eq!(
+            handle(&mut session, "SET 1 GA 21 0 1 500"),
+            Some((
+                "200 OK".to_string(),
+                Some(AccessoryCommand::Basic {
+                    address: 21,
+                    is_green: false,
+                    is_active: true
+                })
+            ))
+        );
+        assert_eq!(reply(&mut session, "GET 1 GA 20 1"), "100 INFO 1 GA 20 1 1");
+        assert_eq!(reply(&mut session, "GET 1 GA 20 0"), "100 INFO 1 GA 20 0 0");
+        assert_eq!(
+            reply(&mut session, "SET 1 GA 20 2 1 -1"),
+            "412 ERROR wrong value"
+        );
+        assert_eq!(
+            reply(&mut session, "SET 2 GA 20 1 1 -1"),
+            "412 ERROR wrong value"
+        );
+        assert_eq!(
+            reply(&mut session, "SET 1 GA 20 1"),
+            "419 ERROR list too short"
+        );
+        assert_eq!(
+            reply(&mut session, "SET 1 FB 20 1"),
+            "422 ERROR unsupported device group"
+        );
+        // lines of the text protocol are left alone.
+        assert!(handle(&mut session, "F:1").is_none());
+
+        assert_eq!(reply(&mut session, "TERM 0 SESSION"), "200 OK");
+        assert_eq!(
+            reply(&mut session, "SET 1 GA 20 1 1 -1"),
+            "410 ERROR unknown command"
+        );
+    }
+
+    #[test]
+    fn shows_the_locomotive_speed_on_the_speed_indicator() {
+        let mut session = SrcpSession::new(20);
+        for line in [
+            "SET PROTOCOL SRCP 0.8.4",
+            "SET CONNECTIONMODE SRCP COMMAND",
+            "GO",
+        ] {
+            reply(&mut session, line);
+        }
+        assert_eq!(reply(&mut session, "GET 1 GL 20"), "416 ERROR no data");
+        assert_eq!(session.speed(), None);
+        assert_eq!(reply(&mut session, "SET 1 GL 20 1 40 150 0"), "200 OK");
+        assert_eq!(
+            reply(&mut session, "GET 1 GL 20"),
+            "100 INFO 1 GL 20 1 40 150"
+        );
+        assert_eq!(session.speed(), Some(4));
+        reply(&mut session, "SET 1 GL 20 0 1 126");
+        assert_eq!(session.speed(), Some(1));
+        reply(&mut session, "SET 1 GL 20 1 14 14");
+        assert_eq!(session.speed(), Some(15));
+        reply(&mut session, "SET 1 GL 20 1 0 14");
+        assert_eq!(session.speed(), None);
+        reply(&mut session, "SET 1 GL 20 2 14 14");
+        assert_eq!(session.speed(), None);
+        // other locomotives are ignored.
+        assert_eq!(reply(&mut session, "SET 1 GL 3 1 7 14"), "200 OK");
+        assert_eq!(session.speed(), None);
+        assert_eq!(
+            reply(&mut session, "SET 1 GL 20 1 15 14"),
+            "412 ERROR wrong value"
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Module for the minimum time that a signal holds stop before it may be cleared again, which real interlockings enforce, so that a signal that was just put back to stop in front of a train isn’t cleared again right away.
+
+use crate::signals::SignalAspect;
+
+/// Keeps track of how long the signal hasn’t been clear.
+pub struct StopHold<Aspect: SignalAspect> {
+    // Time at which the signal stopped showing an aspect that clears it, or None while it shows one.
+    held_since: Option<u32>,
+    aspect: core::marker::PhantomData<Aspect>,
+}
+
+impl<Aspect: SignalAspect> Default for StopHold<Aspect> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Aspect: SignalAspect> StopHold<Aspect> {
+    /// Creates the hold for a signal that shows stop since booting.
+    pub const fn new() -> Self {
+        Self {
+            held_since: Some(0),
+            aspect: core::marker::PhantomData,
+        }
+    }
+
+    /// Notes the aspect that the signal shows, whatever switched it, so that the hold time counts from the time it stopped being clear.
+    pub fn observe(&mut self, aspect: Aspect, now: u32) {
+        if aspect.clears_signal() {
+            self.held_since = None;
+        } else {
+            self.held_since.get_or_insert(now);
+        }
+    }
+
+    /// Returns whether the signal may switch to the given aspect, which is the case unless the aspect clears the signal and the signal hasn’t held stop for the given time yet.
+    pub fn allows(&self, aspect: Aspect, hold_ms: u32, now: u32) -> bool {
+        !aspect.clears_signal()
+            || self
+                .held_since
+                .map_or(true, |since| now.wrapping_sub(since) >= hold_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StopHold;
+    use crate::signals::HVMainSignalAspect;
+
+    #[test]
+    fn clears_only_after_the_hold_time() {
+        let mut hold = StopHold::new();
+        hold.observe(HVMainSignalAspect::Stop, 1200);
+        assert!(!hold.allows(HVMainSignalAspect::Proceed, 5000, 4999));
+        assert!(hold.allows(HVMainSignalAspect::Substitution, 5000, 4999));
+        assert!(hold.allows(HVMainSignalAspect::Proceed, 5000, 5000));
+        // a clear signal may switch to another clear aspect right away.
+        hold.observe(HVMainSignalAspect::Proceed, 6000);
+        assert!(hold.allows(HVMainSignalAspect::ProceedSlow, 5000, 6001));
+        // the substitution signal doesn’t clear the signal, so it doesn’t restart the hold time either.
+        hold.observe(HVMainSignalAspect::Stop, 7000);
+        hold.observe(HVMainSignalAspect::Substitution, 8000);
+        assert!(!hold.allows(HVMainSignalAspect::Proceed, 5000, 11_999));
+        assert!(hold.allows(HVMainSignalAspect::Proceed, 5000, 12_000));
+    }
+}
@@ -0,0 +1,126 @@
+//! Module for two-out-of-two (2oo2) voting of the red lamp, which is wired through two independent driver channels whose states are read back.
+
+use embedded_hal::digital::ErrorType;
+use embedded_hal::digital::InputPin;
+use embedded_hal::digital::OutputPin;
+
+/// An output pin that optionally drives its lamp through a second, independent channel, and reads back the state of both channels.
+///
+/// Without a second channel, the pin only passes its state on to the first channel, so that voted and unvoted lamps can have the same type. With a second channel, both channels are switched together, and [`VotedPin::agrees`] tells whether both read-backs confirm the commanded state.
+pub struct VotedPin<PinType: OutputPin, InputType: InputPin<Error = PinType::Error>> {
+    first_channel: PinType,
+    // Second channel and the read-backs of the first and second channel, which are high while their channel drives the lamp.
+    voting: Option<(PinType, [InputType; 2])>,
+    // Whether the pin was last set high.
+    is_high: bool,
+}
+
+impl<PinType: OutputPin, InputType: InputPin<Error = PinType::Error>> VotedPin<PinType, InputType> {
+    pub fn new(pin: PinType) -> Self {
+        Self {
+            first_channel: pin,
+            voting: None,
+            is_high: false,
+        }
+    }
+
+    /// Adds the second channel and the read-backs of both channels.
+    pub fn with_second_channel(
+        mut self,
+        second_channel: PinType,
+        read_back: [InputType; 2],
+    ) -> Self {
+        self.voting = Some((second_channel, read_back));
+        self
+    }
+
+    /// Moves the first channel to another pin, e.g. because the lamp was assigned to another pin at runtime, and returns the old pin. The new pin is only switched by the next state change.
+    pub fn replace_first_channel(&mut self, pin: PinType) -> PinType {
+        core::mem::replace(&mut self.first_channel, pin)
+    }
+
+    /// Reads back both channels, and returns whether both confirm the commanded state. Pins without a second channel always agree.
+    pub fn agrees(&mut self) -> Result<bool, PinType::Error> {
+        match &mut self.voting {
+            Some((_, [first_read_back, second_read_back])) => Ok(first_read_back.is_high()?
+                == self.is_high
+                && second_read_back.is_high()? == self.is_high),
+            None => Ok(true),
+        }
+    }
+}
+
+impl<PinType: OutputPin, InputType: InputPin<Error = PinType::Error>> ErrorType
+    for VotedPin<PinType, InputType>
+{
+    type Error = PinType::Error;
+}
+
+impl<PinType: OutputPin, InputType: InputPin<Error = PinType::Error>> OutputPin
+    for VotedPin<PinType, InputType>
+{
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.is_high = false;
+        self.first_channel.set_low()?;
+        if let Some((second_channel, _)) = &mut self.voting {
+            second_channel.set_low()?;
+        }
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.is_high = true;
+        self.first_channel.set_high()?;
+        if let Some((second_channel, _)) = &mut self.voting {
+            second_channel.set_high()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal::digital::OutputPin;
+
+    use super::VotedPin;
+    use crate::mock::MockPin;
+    use crate::mock::MockPins;
+
+    #[test]
+    fn agrees_only_if_both_read_backs_confirm_the_state() {
+        let pins = MockPins::new();
+        let mut unvoted: VotedPin<_, MockPin> = VotedPin::new(pins.pin());
+        unvoted.set_high().unwrap();
+        assert!(unvoted.agrees().unwrap());
+
+        let read_backs = MockPins::new();
+        let mut first_read_back = read_backs.pin();
+        let mut second_read_back = read_backs.pin();
+        let mut voted = VotedPin::new(pins.pin())
+            .with_second_channel(pins.pin(), [read_backs.pin_at(0), read_backs.pin_at(1)]);
+        voted.set_high().unwrap();
+        assert_eq!(pins.states(), [true, true, true]);
+        assert!(!voted.agrees().unwrap());
+
+        first_read_back.set_high().unwrap();
+        assert!(!voted.agrees().unwrap());
+        second_read_back.set_high().unwrap();
+        assert!(voted.agrees().unwrap());
+
+        // a channel that doesn’t switch off is a disagreement.
+        voted.set_low().unwrap();
+        first_read_back.set_low().unwrap();
+        assert!(!voted.agrees().unwrap());
+    }
+
+    #[test]
+    fn first_channel_moves_to_another_pin() {
+        let pins = MockPins::new();
+        let mut voted: VotedPin<_, MockPin> = VotedPin::new(pins.pin());
+        voted.set_high().unwrap();
+        voted.replace_first_channel(pins.pin());
+        // the old pin isn’t switched anymore.
+        voted.set_low().unwrap();
+        assert_eq!(pins.states(), [true, false]);
+    }
+}
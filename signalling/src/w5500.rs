@@ -0,0 +1,535 @@
+//! Module for the W5500 Ethernet controller, which connects the signal to a network over SPI, e.g. on the cheap W5500 modules, and on which the signal accepts the lines of the serial protocol.
+//!
+//! The W5500 implements TCP/IP on its own, with eight sockets that each have their own registers and buffers for receiving and sending. Every SPI transaction starts with a 16-bit address and a control byte, which selects the block of registers or the buffer and whether it is read or written. The buffers are rings, whose 16-bit read and write pointers wrap around on their own.
+//!
+//! The signal listens on one port with TCP, for one client at a time, and with UDP, where every datagram is a line.
+
+use arrayvec::ArrayVec;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiBus;
+
+/// Longest line that is received. Longer lines are ignored.
+pub const LINE_CAPACITY: usize = 96;
+
+// Blocks of the control byte: the common registers, and the registers and buffers of each socket.
+const BLOCK_COMMON: u8 = 0;
+const fn block_of_registers(socket: u8) -> u8 {
+    socket * 4 + 1
+}
+const fn block_of_transmit_buffer(socket: u8) -> u8 {
+    socket * 4 + 2
+}
+const fn block_of_receive_buffer(socket: u8) -> u8 {
+    socket * 4 + 3
+}
+/// Bit of the control byte that marks a write.
+const CONTROL_WRITE: u8 = 0x04;
+
+// Common registers.
+const MR: u16 = 0x0000;
+const GAR: u16 = 0x0001;
+const VERSIONR: u16 = 0x0039;
+const MR_RESET: u8 = 0x80;
+const VERSION: u8 = 0x04;
+
+// Socket registers.
+const SN_MR: u16 = 0x00;
+const SN_CR: u16 = 0x01;
+const SN_SR: u16 = 0x03;
+const SN_PORT: u16 = 0x04;
+const SN_DIPR: u16 = 0x0c;
+const SN_TX_FSR: u16 = 0x20;
+const SN_TX_WR: u16 = 0x24;
+const SN_RX_RSR: u16 = 0x26;
+const SN_RX_RD: u16 = 0x28;
+
+// Protocols of the sockets.
+const MODE_TCP: u8 = 0x01;
+const MODE_UDP: u8 = 0x02;
+
+// Socket commands, which are cleared once the W5500 accepted them.
+const COMMAND_OPEN: u8 = 0x01;
+const COMMAND_LISTEN: u8 = 0x02;
+const COMMAND_DISCONNECT: u8 = 0x08;
+const COMMAND_SEND: u8 = 0x20;
+const COMMAND_RECEIVE: u8 = 0x40;
+
+// Socket states.
+const STATE_CLOSED: u8 = 0x00;
+const STATE_INIT: u8 = 0x13;
+const STATE_ESTABLISHED: u8 = 0x17;
+const STATE_CLOSE_WAIT: u8 = 0x1c;
+const STATE_UDP: u8 = 0x22;
+
+const SOCKET_TCP: u8 = 0;
+const SOCKET_UDP: u8 = 1;
+/// Length of the header of every received datagram: the address and port of the sender and the length of the data.
+const UDP_HEADER_LENGTH: usize = 8;
+
+/// The addresses of the signal in the network, and the port that it listens on.
+pub struct NetworkSettings {
+    pub mac_address: [u8; 6],
+    pub ip_address: [u8; 4],
+    pub subnet_mask: [u8; 4],
+    pub gateway: [u8; 4],
+    pub port: u16,
+}
+
+/// A W5500 on an SPI bus, with its own chip select pin.
+///
+/// The W5500 is configured by [`Self::start`], so that creating the driver doesn’t touch the hardware. Lines are received with [`Self::receive_line`], which also opens the sockets again after a client left, and replies are sent with [`Self::send`].
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of SPI bus and chip select pin used. Its parameters additionally include their common error type (which some functions also return).
+pub struct W5500<Error, Bus: SpiBus<Error = Error>, ChipSelect: OutputPin<Error = Error>> {
+    bus: Bus,
+    chip_select: ChipSelect,
+    port: u16,
+    // The line of the TCP client received so far, and whether it is too long.
+    line: ArrayVec<u8, LINE_CAPACITY>,
+    is_overlong: bool,
+    // The sender of the last datagram, which gets the replies while no TCP client is connected.
+    udp_peer: Option<[u8; 6]>,
+}
+
+impl<Error, Bus: SpiBus<Error = Error>, ChipSelect: OutputPin<Error = Error>>
+    W5500<Error, Bus, ChipSelect>
+{
+    /// Creates a driver for the W5500, whose SPI inputs are connected to the bus, and whose CS input is connected to the given pin.
+    pub fn new(bus: Bus, chip_select: ChipSelect) -> Self {
+        Self {
+            bus,
+            chip_select,
+            port: 0,
+            line: ArrayVec::new(),
+            is_overlong: false,
+            udp_peer: None,
+        }
+    }
+
+    /// Resets the W5500 and sets up its addresses. Returns false if the W5500 didn’t respond, e.g. because it is not connected.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s SPI and digital I/O functions.
+    pub fn start(
+        &mut self,
+        delay: &mut impl DelayNs,
+        settings: &NetworkSettings,
+    ) -> Result<bool, Error> {
+        self.chip_select.set_high()?;
+        self.write(BLOCK_COMMON, MR, &[MR_RESET])?;
+        delay.delay_ms(1);
+        let mut version = [0];
+        self.read(BLOCK_COMMON, VERSIONR, &mut version)?;
+        if version[0] != VERSION {
+            return Ok(false);
+        }
+        // the gateway, subnet mask, MAC address and IP address follow each other.
+        let mut addresses: ArrayVec<u8, 18> = ArrayVec::new();
+        addresses.extend(settings.gateway);
+        addresses.extend(settings.subnet_mask);
+        addresses.extend(settings.mac_address);
+        addresses.extend(settings.ip_address);
+        self.write(BLOCK_COMMON, GAR, &addresses)?;
+        self.port = settings.port;
+        Ok(true)
+    }
+
+    /// Returns the next line that was received, if any, with its line feed. Opens the sockets whenever they are closed, and should therefore be called regularly.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s SPI and digital I/O functions.
+    pub fn receive_line(&mut self) -> Result<Option<ArrayVec<u8, LINE_CAPACITY>>, Error> {
+        match self.state_of(SOCKET_TCP)? {
+            STATE_CLOSED => {
+                self.line.clear();
+                self.open(SOCKET_TCP, MODE_TCP)?;
+            }
+            STATE_INIT => self.command(SOCKET_TCP, COMMAND_LISTEN)?,
+            // the client closed the connection, so the socket closes too, and listens again afterwards.
+            STATE_CLOSE_WAIT => self.command(SOCKET_TCP, COMMAND_DISCONNECT)?,
+            STATE_ESTABLISHED => {
+                if let Some(line) = self.receive_from_client()? {
+                    return Ok(Some(line));
+                }
+            }
+            _ => {}
+        }
+        match self.state_of(SOCKET_UDP)? {
+            STATE_CLOSED => self.open(SOCKET_UDP, MODE_UDP)?,
+            STATE_UDP => return self.receive_datagram(),
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /// Sends the bytes to the TCP client, or to the sender of the last datagram if there is no client. Bytes that don’t fit into the transmit buffer are dropped.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s SPI and digital I/O functions.
+    pub fn send(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let socket = if self.state_of(SOCKET_TCP)? == STATE_ESTABLISHED {
+            SOCKET_TCP
+        } else if let Some(peer) = self.udp_peer {
+            // the destination port follows the destination address.
+            self.write(block_of_registers(SOCKET_UDP), SN_DIPR, &peer)?;
+            SOCKET_UDP
+        } else {
+            return Ok(());
+        };
+        for chunk in bytes.chunks(LINE_CAPACITY) {
+            if usize::from(self.read_u16(socket, SN_TX_FSR)?) < chunk.len() {
+                return Ok(());
+            }
+            let pointer = self.read_u16(socket, SN_TX_WR)?;
+            self.write(block_of_transmit_buffer(socket), pointer, chunk)?;
+            self.write(
+                block_of_registers(socket),
+                SN_TX_WR,
+                &pointer.wrapping_add(chunk.len() as u16).to_be_bytes(),
+            )?;
+            self.command(socket, COMMAND_SEND)?;
+        }
+        Ok(())
+    }
+
+    /// Receives the bytes of the TCP client up to the next line feed, and returns the line if it is complete. The bytes after the line feed stay in the receive buffer.
+    fn receive_from_client(&mut self) -> Result<Option<ArrayVec<u8, LINE_CAPACITY>>, Error> {
+        let available = usize::from(self.read_u16(SOCKET_TCP, SN_RX_RSR)?);
+        if available == 0 {
+            return Ok(None);
+        }
+        let pointer = self.read_u16(SOCKET_TCP, SN_RX_RD)?;
+        let mut bytes = [0; LINE_CAPACITY];
+        let bytes = &mut bytes[..available.min(LINE_CAPACITY)];
+        self.read(block_of_receive_buffer(SOCKET_TCP), pointer, bytes)?;
+        let length = bytes
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map_or(bytes.len(), |position| position + 1);
+        let bytes = &bytes[..length];
+        self.release(SOCKET_TCP, pointer, length)?;
+        if self.line.try_extend_from_slice(bytes).is_err() {
+            self.line.clear();
+            self.is_overlong = true;
+        }
+        if bytes.last() != Some(&b'\n') {
+            return Ok(None);
+        }
+        let line = core::mem::take(&mut self.line);
+        Ok((!core::mem::take(&mut self.is_overlong)).then_some(line))
+    }
+
+    /// Receives the next datagram, if any, as a line, which gets a line feed if it doesn’t end with one.
+    fn receive_datagram(&mut self) -> Result<Option<ArrayVec<u8, LINE_CAPACITY>>, Error> {
+        if usize::from(self.read_u16(SOCKET_UDP, SN_RX_RSR)?) < UDP_HEADER_LENGTH {
+            return Ok(None);
+        }
+        let pointer = self.read_u16(SOCKET_UDP, SN_RX_RD)?;
+        let mut header = [0; UDP_HEADER_LENGTH];
+        self.read(block_of_receive_buffer(SOCKET_UDP), pointer, &mut header)?;
+        let [ref peer @ .., length_high, length_low] = header;
+        let length = usize::from(u16::from_be_bytes([length_high, length_low]));
+        let mut line = ArrayVec::new();
+        if length <= LINE_CAPACITY {
+            let mut bytes = [0; LINE_CAPACITY];
+            let bytes = &mut bytes[..length];
+            self.read(
+                block_of_receive_buffer(SOCKET_UDP),
+                pointer.wrapping_add(UDP_HEADER_LENGTH as u16),
+                bytes,
+            )?;
+            line.extend(bytes.iter().copied());
+        }
+        self.release(SOCKET_UDP, pointer, UDP_HEADER_LENGTH + length)?;
+        self.udp_peer = Some(*peer);
+        if line.last() != Some(&b'\n') && line.try_push(b'\n').is_err() {
+            line.clear();
+        }
+        Ok((line.len() > 1).then_some(line))
+    }
+
+    /// Frees the given number of received bytes in the receive buffer of the socket.
+    fn release(&mut self, socket: u8, pointer: u16, length: usize) -> Result<(), Error> {
+        self.write(
+            block_of_registers(socket),
+            SN_RX_RD,
+            &pointer.wrapping_add(length as u16).to_be_bytes(),
+        )?;
+        self.command(socket, COMMAND_RECEIVE)
+    }
+
+    fn open(&mut self, socket: u8, mode: u8) -> Result<(), Error> {
+        self.write(block_of_registers(socket), SN_MR, &[mode])?;
+        self.write(
+            block_of_registers(socket),
+            SN_PORT,
+            &self.port.to_be_bytes(),
+        )?;
+        self.command(socket, COMMAND_OPEN)
+    }
+
+    /// Runs a command of the socket, and waits until the W5500 accepted it, which takes a few microseconds.
+    fn command(&mut self, socket: u8, command: u8) -> Result<(), Error> {
+        self.write(block_of_registers(socket), SN_CR, &[command])?;
+        let mut pending = [command];
+        while pending[0] != 0 {
+            self.read(block_of_registers(socket), SN_CR, &mut pending)?;
+        }
+        Ok(())
+    }
+
+    fn state_of(&mut self, socket: u8) -> Result<u8, Error> {
+        let mut state = [0];
+        self.read(block_of_registers(socket), SN_SR, &mut state)?;
+        Ok(state[0])
+    }
+
+    fn read_u16(&mut self, socket: u8, address: u16) -> Result<u16, Error> {
+        let mut value = [0; 2];
+        self.read(block_of_registers(socket), address, &mut value)?;
+        Ok(u16::from_be_bytes(value))
+    }
+
+    fn read(&mut self, block: u8, address: u16, bytes: &mut [u8]) -> Result<(), Error> {
+        let mut transaction = self.transaction(block << 3, address, bytes);
+        self.transfer(&mut transaction)?;
+        bytes.copy_from_slice(&transaction[3..]);
+        Ok(())
+    }
+
+    fn write(&mut self, block: u8, address: u16, bytes: &[u8]) -> Result<(), Error> {
+        let mut transaction = self.transaction(block << 3 | CONTROL_WRITE, address, bytes);
+        self.transfer(&mut transaction)
+    }
+
+    /// Returns the bytes of a transaction with the given control byte and address.
+    ///
+    /// # Panics
+    /// This function will panic if there are more than [`LINE_CAPACITY`] bytes, which is a logic bug.
+    fn transaction(
+        &self,
+        control: u8,
+        address: u16,
+        bytes: &[u8],
+    ) -> ArrayVec<u8, { LINE_CAPACITY + 3 }> {
+        let mut transaction = ArrayVec::new();
+        transaction.extend(address.to_be_bytes());
+        transaction.push(control);
+        transaction.try_extend_from_slice(bytes).unwrap();
+        transaction
+    }
+
+    /// Runs an SPI transaction, in which the bytes are sent and replaced by the received ones.
+    fn transfer(&mut self, bytes: &mut [u8]) -> Result<(), Error> {
+        self.chip_select.set_low()?;
+        let result = self
+            .bus
+            .transfer_in_place(bytes)
+            .and_then(|()| self.bus.flush());
+        self.chip_select.set_high()?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use embedded_hal::spi::ErrorType;
+    use embedded_hal::spi::SpiBus;
+
+    use super::NetworkSettings;
+    use super::W5500;
+    use crate::mock::MockDelay;
+    use crate::mock::MockPin;
+    use crate::mock::MockPins;
+
+    /// A W5500 with two sockets that implements the commands used by the driver, with buffers of 2 KiB, and treats every transfer as a transaction.
+    struct FakeW5500 {
+        blocks: [[u8; 2048]; 8],
+        // Write pointers of the receive buffers, and read pointers of the transmit buffers.
+        received: [u16; 2],
+        sent: [u16; 2],
+        // Bytes that each socket sent.
+        transmitted: [Vec<u8>; 2],
+    }
+
+    impl FakeW5500 {
+        fn new() -> Self {
+            let mut blocks = [[0; 2048]; 8];
+            blocks[0][0x39] = 0x04;
+            for socket in 0..2 {
+                blocks[socket * 4 + 1][0x20..0x22].copy_from_slice(&2048u16.to_be_bytes());
+            }
+            Self {
+                blocks,
+                received: [0; 2],
+                sent: [0; 2],
+                transmitted: [Vec::new(), Vec::new()],
+            }
+        }
+
+        fn register(&self, socket: usize, address: usize) -> u16 {
+            let registers = &self.blocks[socket * 4 + 1];
+            u16::from_be_bytes([registers[address], registers[address + 1]])
+        }
+
+        /// Receives bytes on a socket.
+        fn deliver(&mut self, socket: usize, bytes: &[u8]) {
+            for byte in bytes {
+                self.blocks[socket * 4 + 3][usize::from(self.received[socket]) % 2048] = *byte;
+                self.received[socket] = self.received[socket].wrapping_add(1);
+            }
+            let available = self.received[socket].wrapping_sub(self.register(socket, 0x28));
+            self.blocks[socket * 4 + 1][0x26..0x28].copy_from_slice(&available.to_be_bytes());
+        }
+
+        fn run_command(&mut self, socket: usize, command: u8) {
+            let registers = &mut self.blocks[socket * 4 + 1];
+            match command {
+                0x01 => registers[0x03] = if registers[0x00] == 0x01 { 0x13 } else { 0x22 },
+                0x02 => registers[0x03] = 0x14,
+                0x08 => registers[0x03] = 0x00,
+                0x20 => {
+                    let end = self.register(socket, 0x24);
+                    while self.sent[socket] != end {
+                        let byte =
+                            self.blocks[socket * 4 + 2][usize::from(self.sent[socket]) % 2048];
+                        self.transmitted[socket].push(byte);
+                        self.sent[socket] = self.sent[socket].wrapping_add(1);
+                    }
+                }
+                0x40 => self.deliver(socket, &[]),
+                _ => unimplemented!(),
+            }
+        }
+    }
+
+    impl ErrorType for FakeW5500 {
+        type Error = Infallible;
+    }
+
+    impl SpiBus for FakeW5500 {
+        fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+            let address = usize::from(u16::from_be_bytes([words[0], words[1]]));
+            let block = usize::from(words[2] >> 3);
+            let is_write = words[2] & 0x04 != 0;
+            for (offset, word) in words[3..].iter_mut().enumerate() {
+                let byte = &mut self.blocks[block][(address + offset) % 2048];
+                if is_write {
+                    *byte = *word;
+                } else {
+                    *word = *byte;
+                }
+            }
+            // commands are accepted right away.
+            if is_write && block % 4 == 1 && address == 0x01 {
+                self.blocks[block][0x01] = 0;
+                self.run_command(block / 4, words[3]);
+            }
+            Ok(())
+        }
+
+        fn read(&mut self, _words: &mut [u8]) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
+        fn write(&mut self, _words: &[u8]) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
+        fn transfer(&mut self, _read: &mut [u8], _write: &[u8]) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    const SETTINGS: NetworkSettings = NetworkSettings {
+        mac_address: [0x02, 0, 0, 0, 0, 0x46],
+        ip_address: [192, 168, 1, 70],
+        subnet_mask: [255, 255, 255, 0],
+        gateway: [192, 168, 1, 1],
+        port: 2323,
+    };
+
+    fn started_controller() -> W5500<Infallible, FakeW5500, MockPin> {
+        let pins = MockPins::new();
+        let mut controller = W5500::new(FakeW5500::new(), pins.pin());
+        assert_eq!(
+            controller.start(&mut MockDelay::default(), &SETTINGS),
+            Ok(true)
+        );
+        assert_eq!(controller.bus.blocks[0][0x0f..0x13], [192, 168, 1, 70]);
+        // the first call opens both sockets, and the second one listens for a client.
+        assert_eq!(controller.receive_line(), Ok(None));
+        assert_eq!(controller.receive_line(), Ok(None));
+        assert_eq!(controller.bus.blocks[1][0x03], 0x14);
+        assert_eq!(controller.bus.blocks[1][0x04..0x06], [0x09, 0x13]);
+        assert_eq!(controller.bus.blocks[5][0x03], 0x22);
+        controller
+    }
+
+    #[test]
+    fn receives_lines_of_a_tcp_client_and_answers_it() {
+        let mut controller = started_controller();
+        // a client connects.
+        controller.bus.blocks[1][0x03] = 0x17;
+        controller.bus.deliver(0, b"F:1\nF:");
+        assert_eq!(
+            controller.receive_line().unwrap().as_deref(),
+            Some(&b"F:1\n"[..])
+        );
+        assert_eq!(controller.receive_line(), Ok(None));
+        controller.bus.deliver(0, b"2\n");
+        assert_eq!(
+            controller.receive_line().unwrap().as_deref(),
+            Some(&b"F:2\n"[..])
+        );
+        assert_eq!(controller.send(b"F:A:2\n"), Ok(()));
+        assert_eq!(controller.bus.transmitted[0], b"F:A:2\n");
+        // too long lines are ignored.
+        controller.bus.deliver(0, &[b'F'; 100]);
+        controller.bus.deliver(0, b"\nF:3\n");
+        while controller.bus.register(0, 0x26) > 4 {
+            assert_eq!(controller.receive_line(), Ok(None));
+        }
+        assert_eq!(
+            controller.receive_line().unwrap().as_deref(),
+            Some(&b"F:3\n"[..])
+        );
+        // the client leaves, and the socket listens again.
+        controller.bus.blocks[1][0x03] = 0x1c;
+        assert_eq!(controller.receive_line(), Ok(None));
+        assert_eq!(controller.receive_line(), Ok(None));
+        assert_eq!(controller.receive_line(), Ok(None));
+        assert_eq!(controller.bus.blocks[1][0x03], 0x14);
+    }
+
+    #[test]
+    fn receives_datagrams_and_answers_their_sender() {
+        let mut controller = started_controller();
+        // nothing is sent before the first datagram.
+        assert_eq!(controller.send(b"F:A:1\n"), Ok(()));
+        assert!(controller.bus.transmitted[1].is_empty());
+        controller
+            .bus
+            .deliver(1, &[192, 168, 1, 10, 0x12, 0x34, 0, 3, b'F', b':', b'1']);
+        assert_eq!(
+            controller.receive_line().unwrap().as_deref(),
+            Some(&b"F:1\n"[..])
+        );
+        assert_eq!(controller.receive_line(), Ok(None));
+        assert_eq!(controller.send(b"F:A:1\n"), Ok(()));
+        assert_eq!(controller.bus.transmitted[1], b"F:A:1\n");
+        assert_eq!(
+            controller.bus.blocks[5][0x0c..0x12],
+            [192, 168, 1, 10, 0x12, 0x34]
+        );
+    }
+}
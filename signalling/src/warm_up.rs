@@ -0,0 +1,124 @@
+//! Module for warming up incandescent bulbs that have been dark for a long time, which extends their life in heritage signal heads.
+//!
+//! A cold filament has a much lower resistance than a hot one, so switching it on at full voltage causes a large inrush current, which is what eventually breaks it. Lamps that were dark for a while are therefore first lit with a low duty cycle, so that the filament heats up gently before it gets the full voltage.
+
+use crate::signals::LampRole;
+
+/// How long a lamp must have been dark to be warmed up when it is lit again.
+pub const COLD_AFTER_MS: u32 = 60_000;
+/// How long a cold lamp is warmed up before it is lit at full brightness.
+pub const WARM_UP_MS: u32 = 300;
+/// During the warm-up, the lamp is lit for one millisecond in every this many.
+const WARM_UP_PERIOD_MS: u32 = 4;
+
+/// State of the warm-up of all lamps.
+///
+/// Like the lamp aging simulation, the warm-up doesn’t own any lamps; it only tells its user which lamp to switch and when. Time is given in milliseconds since boot, as returned by the clock.
+pub struct LampWarmUp {
+    // Time each lamp was last seen lit, indexed by lamp role, or None if it wasn’t lit since boot.
+    last_lit: [Option<u32>; LampRole::ALL.len()],
+    // Time each lamp started warming up, indexed by lamp role, if it is warming up.
+    warming_since: [Option<u32>; LampRole::ALL.len()],
+    // Lamps that finished warming up with the last update, as a bit set indexed by lamp role.
+    warmed_up: u32,
+}
+
+impl Default for LampWarmUp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LampWarmUp {
+    /// Creates a new warm-up, where all lamps are cold.
+    pub const fn new() -> Self {
+        Self {
+            last_lit: [None; LampRole::ALL.len()],
+            warming_since: [None; LampRole::ALL.len()],
+            warmed_up: 0,
+        }
+    }
+
+    /// Advances the warm-up to the current time, given which lamps the signal has lit. Should be called about every millisecond, followed by [`LampWarmUp::lamp_states`], since the warm-up switches the lamps with a low duty cycle.
+    pub fn update(&mut self, now: u32, mut is_lit: impl FnMut(LampRole) -> bool) {
+        self.warmed_up = 0;
+        for role in LampRole::ALL {
+            let index = role as usize;
+            if !is_lit(role) {
+                self.warming_since[index] = None;
+                continue;
+            }
+            let was_cold = self.last_lit[index]
+                .map_or(true, |last_lit| now.wrapping_sub(last_lit) >= COLD_AFTER_MS);
+            if was_cold {
+                self.warming_since[index] = Some(now);
+            } else if let Some(since) = self.warming_since[index] {
+                if now.wrapping_sub(since) >= WARM_UP_MS {
+                    self.warming_since[index] = None;
+                    self.warmed_up |= 1 << index;
+                }
+            }
+            self.last_lit[index] = Some(now);
+        }
+    }
+
+    /// Ends all warm-ups, e.g. because the warm-up was disabled, and returns the lamps that were warming up. The user has to switch them to their state again.
+    pub fn cancel(&mut self) -> impl Iterator<Item = LampRole> {
+        let was_warming = self.warming_since.map(|since| since.is_some());
+        self.warming_since = [None; LampRole::ALL.len()];
+        LampRole::ALL
+            .into_iter()
+            .filter(move |role| was_warming[*role as usize])
+    }
+
+    /// Returns the lamps that are warming up or just finished, with the state they have to be switched to (true meaning on).
+    pub fn lamp_states(&self, now: u32) -> impl Iterator<Item = (LampRole, bool)> + '_ {
+        LampRole::ALL.into_iter().filter_map(move |role| {
+            let index = role as usize;
+            match self.warming_since[index] {
+                Some(since) => Some((role, now.wrapping_sub(since) % WARM_UP_PERIOD_MS == 0)),
+                None if self.warmed_up & 1 << index != 0 => Some((role, true)),
+                None => None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LampWarmUp;
+    use super::COLD_AFTER_MS;
+    use super::WARM_UP_MS;
+    use crate::signals::LampRole;
+
+    fn green_state(warm_up: &mut LampWarmUp, now: u32, is_green_lit: bool) -> Option<bool> {
+        warm_up.update(now, |role| role == LampRole::MainGreen && is_green_lit);
+        warm_up
+            .lamp_states(now)
+            .find(|(role, _)| *role == LampRole::MainGreen)
+            .map(|(_, is_on)| is_on)
+    }
+
+    #[test]
+    fn only_lamps_that_were_dark_for_long_are_warmed_up() {
+        let mut warm_up = LampWarmUp::new();
+        // all lamps are cold after booting.
+        assert_eq!(green_state(&mut warm_up, 0, true), Some(true));
+        assert_eq!(green_state(&mut warm_up, 1, true), Some(false));
+        assert_eq!(green_state(&mut warm_up, 3, true), Some(false));
+        assert_eq!(green_state(&mut warm_up, 4, true), Some(true));
+        assert_eq!(green_state(&mut warm_up, WARM_UP_MS, true), Some(true));
+        assert_eq!(green_state(&mut warm_up, WARM_UP_MS + 1, true), None);
+
+        // a lamp that was only dark for a moment is still warm.
+        assert_eq!(green_state(&mut warm_up, 1000, false), None);
+        assert_eq!(green_state(&mut warm_up, 2000, true), None);
+
+        assert_eq!(green_state(&mut warm_up, 3000, false), None);
+        let now = 3000 + COLD_AFTER_MS;
+        assert_eq!(green_state(&mut warm_up, now, true), Some(true));
+        assert_eq!(green_state(&mut warm_up, now + 1, true), Some(false));
+        assert!(warm_up.cancel().eq([LampRole::MainGreen]));
+        assert_eq!(green_state(&mut warm_up, now + 2, true), None);
+    }
+}
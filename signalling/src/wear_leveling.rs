@@ -0,0 +1,133 @@
+//! Module for saving a byte that changes often, like the signal state that the signal shows after a reboot, without wearing out the EEPROM.
+//!
+//! Every write goes to the next cell of a ring of EEPROM cells, so that the cells wear out evenly, and the ring lasts for `RING_LENGTH` times as many writes as a single cell. The top bit of every cell flips on every lap around the ring, so the cell that was written last is the one before the first cell whose top bit differs from the first cell’s, or the last cell if none differs.
+//!
+//! Writes can also be deferred until the value has settled, so that a quick succession of changes only writes the last value.
+
+use crate::platform::Platform;
+
+/// Number of EEPROM cells of a ring.
+pub const RING_LENGTH: u16 = 16;
+/// Largest value that can be saved, since the top bit of every cell counts the laps.
+pub const MAX_VALUE: u8 = 0x7e;
+// Value of a cell that holds no value, as in an erased EEPROM.
+const NO_VALUE: u8 = 0x7f;
+const LAP_BIT: u8 = 0x80;
+
+/// A byte that is saved in a ring of EEPROM cells.
+///
+/// Time is given in milliseconds since boot, as returned by the clock.
+pub struct WearLevelledByte {
+    offset: u16,
+    // The cell that was written last, and its content.
+    cell: u16,
+    content: u8,
+    // Value that waits for its write, and the time at which it was deferred.
+    deferred: Option<(u8, u32)>,
+}
+
+impl WearLevelledByte {
+    /// Finds the cell that was written last in the ring at the given EEPROM offset.
+    pub fn load(platform: &mut impl Platform, offset: u16) -> Self {
+        let mut cells = [0; RING_LENGTH as usize];
+        platform.read_persistent(offset, &mut cells);
+        let first_lap = cells[0] & LAP_BIT;
+        let cell = cells
+            .iter()
+            .position(|content| content & LAP_BIT != first_lap)
+            .map_or(RING_LENGTH - 1, |next| next as u16 - 1);
+        Self {
+            offset,
+            cell,
+            content: cells[usize::from(cell)],
+            deferred: None,
+        }
+    }
+
+    /// Returns the value that was saved last, or None if none was saved yet. A deferred value only counts once it was written.
+    pub fn value(&self) -> Option<u8> {
+        Some(self.content & !LAP_BIT).filter(|value| *value != NO_VALUE)
+    }
+
+    /// Saves the value right away, and drops the deferred value. Returns whether the EEPROM was written, which it isn’t if the value is saved already.
+    pub fn write(&mut self, platform: &mut impl Platform, value: u8) -> bool {
+        debug_assert!(value <= MAX_VALUE);
+        self.deferred = None;
+        if self.value() == Some(value) {
+            return false;
+        }
+        let lap = self.content & LAP_BIT;
+        (self.cell, self.content) = if self.cell + 1 == RING_LENGTH {
+            (0, (lap ^ LAP_BIT) | value)
+        } else {
+            (self.cell + 1, lap | value)
+        };
+        platform.write_persistent(self.offset + self.cell, &[self.content]);
+        true
+    }
+
+    /// Saves the value once it has settled, i.e. no other value was deferred or written for the settling time (see [`Self::write_settled`]).
+    pub fn defer(&mut self, value: u8, now: u32) {
+        self.deferred = Some((value, now));
+    }
+
+    /// Writes the deferred value once it has settled for the given time. Returns whether the EEPROM was written.
+    pub fn write_settled(
+        &mut self,
+        platform: &mut impl Platform,
+        settle_ms: u32,
+        now: u32,
+    ) -> bool {
+        match self.deferred {
+            Some((value, since)) if now.wrapping_sub(since) >= settle_ms => {
+                self.write(platform, value)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WearLevelledByte;
+    use super::RING_LENGTH;
+    use crate::mock::MockPlatform;
+
+    #[test]
+    fn writes_go_around_the_ring() {
+        let mut platform = MockPlatform::new();
+        assert_eq!(WearLevelledByte::load(&mut platform, 100).value(), None);
+        for value in 0..3 * RING_LENGTH as u8 {
+            let mut byte = WearLevelledByte::load(&mut platform, 100);
+            assert!(byte.write(&mut platform, value));
+            assert!(!byte.write(&mut platform, value));
+            assert_eq!(
+                WearLevelledByte::load(&mut platform, 100).value(),
+                Some(value)
+            );
+        }
+        // every cell was written three times, and nothing outside the ring.
+        assert!(platform.storage[100..100 + usize::from(RING_LENGTH)]
+            .iter()
+            .all(|content| *content != 0xff));
+        assert_eq!(platform.storage[100 + usize::from(RING_LENGTH)], 0xff);
+        assert_eq!(platform.storage[99], 0xff);
+    }
+
+    #[test]
+    fn writes_only_the_settled_value() {
+        let mut platform = MockPlatform::new();
+        let mut byte = WearLevelledByte::load(&mut platform, 0);
+        byte.defer(1, 1000);
+        byte.defer(2, 1500);
+        assert!(!byte.write_settled(&mut platform, 2000, 3499));
+        assert!(byte.write_settled(&mut platform, 2000, 3500));
+        assert!(!byte.write_settled(&mut platform, 2000, 9000));
+        assert_eq!(byte.value(), Some(2));
+        // a value that is written right away replaces the deferred one.
+        byte.defer(3, 10_000);
+        byte.write(&mut platform, 4);
+        assert!(!byte.write_settled(&mut platform, 2000, 20_000));
+        assert_eq!(WearLevelledByte::load(&mut platform, 0).value(), Some(4));
+    }
+}
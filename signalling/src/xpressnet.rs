@@ -0,0 +1,159 @@
+//! Module for the XpressNet bus of Lenz and Roco command stations, on which the signal listens to the accessory operation requests of handsets and PC interfaces, like the command station itself.
+//!
+//! XpressNet is an RS-485 bus at 62 500 baud with nine data bits. The command station is the only master: it sends call bytes, which have their ninth bit set, and which address one of the devices 1 to 31 with a parity bit, a type and the address. After a normal inquiry, the addressed device may send one request, which starts with a header byte whose lower four bits are the number of data bytes, and ends with a byte that makes the XOR of all its bytes 0. Since every device sees every byte on the bus, the signal receives the requests for its addresses no matter which device sends them, and only sends something itself when the command station asks it for an acknowledgement.
+
+use arrayvec::ArrayVec;
+
+use crate::dcc::AccessoryCommand;
+
+/// Baud rate of the bus.
+pub const BAUD_RATE: u32 = 62_500;
+/// Response to a request for acknowledgement, which tells the command station that the device is present.
+pub const ACKNOWLEDGEMENT: [u8; 2] = [0x20, 0x20];
+/// Ninth bit of a byte, which is only set in call bytes.
+const CALL_BIT: u16 = 0x100;
+/// Types of call bytes, in bits 5 and 6.
+const CALL_REQUEST_ACKNOWLEDGEMENT: u8 = 0b00;
+const CALL_NORMAL_INQUIRY: u8 = 0b10;
+/// Header of an accessory decoder operation request, and its length with the header and the XOR byte. Other requests are ignored.
+const ACCESSORY_OPERATION_REQUEST: u8 = 0x52;
+const ACCESSORY_OPERATION_REQUEST_LENGTH: usize = 4;
+
+/// Something received on the bus that the signal handles.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum XpressNetEvent {
+    /// A device requested an accessory output.
+    Command(AccessoryCommand),
+    /// The command station asked the signal for an acknowledgement, which must be answered with [`ACKNOWLEDGEMENT`] right away.
+    AcknowledgementRequest,
+}
+
+/// Parses a complete request, and returns its accessory command, if it is an accessory decoder operation request with a valid XOR byte.
+fn parse(request: &[u8]) -> Option<AccessoryCommand> {
+    // the request contains the upper eight bits of the address, and the lower two bits with the output, numbered from 0, and whether it is switched on.
+    match *request {
+        [ACCESSORY_OPERATION_REQUEST, high_address, data, check]
+            if ACCESSORY_OPERATION_REQUEST ^ high_address ^ data == check
+                && data & 0xf0 == 0x80 =>
+        {
+            Some(AccessoryCommand::Basic {
+                address: u16::from(high_address) * 4 + u16::from(data >> 1 & 0b11) + 1,
+                is_green: data & 0x01 != 0,
+                is_active: data & 0x08 != 0,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Receives the requests on the bus from its nine-bit bytes.
+pub struct XpressNetReceiver {
+    // The signal’s own address on the bus, from 1 to 31.
+    address: u8,
+    // Received bytes of the request that a device may send after its normal inquiry, or None if no request is expected.
+    request: Option<ArrayVec<u8, ACCESSORY_OPERATION_REQUEST_LENGTH>>,
+}
+
+impl XpressNetReceiver {
+    pub const fn new(address: u8) -> Self {
+        Self {
+            address,
+            request: None,
+        }
+    }
+
+    /// Processes a byte received on the bus, with its ninth bit in bit 8, and returns what it completes, if anything.
+    pub fn receive(&mut self, byte: u16) -> Option<XpressNetEvent> {
+        if byte & CALL_BIT != 0 {
+            // a call byte ends every request, whether it was complete or not.
+            self.request = None;
+            let call = byte as u8;
+            if call.count_ones() % 2 != 0 {
+                return None;
+            }
+            match call >> 5 & 0b11 {
+                CALL_NORMAL_INQUIRY => self.request = Some(ArrayVec::new()),
+                CALL_REQUEST_ACKNOWLEDGEMENT if call & 0x1f == self.address => {
+                    return Some(XpressNetEvent::AcknowledgementRequest);
+                }
+                _ => {}
+            }
+            return None;
+        }
+        let request = self.request.as_mut()?;
+        request.push(byte as u8);
+        if request[0] != ACCESSORY_OPERATION_REQUEST {
+            self.request = None;
+            return None;
+        }
+        if !request.is_full() {
+            return None;
+        }
+        let request = self.request.take()?;
+        parse(&request).map(XpressNetEvent::Command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::XpressNetEvent;
+    use super::XpressNetReceiver;
+    use crate::dcc::AccessoryCommand;
+
+    /// Returns the call byte of the given type for the given address, with its parity and ninth bit.
+    fn call(kind: u8, address: u8) -> u16 {
+        let call = kind << 5 | address;
+        0x100 | u16::from(call) | u16::from(call.count_ones() as u8 % 2) << 7
+    }
+
+    fn receive(receiver: &mut XpressNetReceiver, bytes: &[u16]) -> Vec<XpressNetEvent> {
+        bytes
+            .iter()
+            .filter_map(|byte| receiver.receive(*byte))
+            .collect()
+    }
+
+    #[test]
+    fn receives_accessory_operation_requests_of_every_device() {
+        let mut receiver = XpressNetReceiver::new(20);
+        // address 1000 is output 3 of the decoder with the upper bits 249, green and on.
+        let check = 0x52 ^ 249 ^ 0x8f;
+        assert_eq!(
+            receive(&mut receiver, &[call(0b10, 3), 0x52, 249, 0x8f, check]),
+            [XpressNetEvent::Command(AccessoryCommand::Basic {
+                address: 1000,
+                is_green: true,
+                is_active: true
+            })]
+        );
+        // address 1, red and off.
+        assert_eq!(
+            receive(&mut receiver, &[call(0b10, 7), 0x52, 0, 0x80, 0x52 ^ 0x80]),
+            [XpressNetEvent::Command(AccessoryCommand::Basic {
+                address: 1,
+                is_green: false,
+                is_active: false
+            })]
+        );
+        // broken XOR bytes and parities, other requests, and the command station’s own messages.
+        let requests: [&[u16]; 4] = [
+            &[call(0b10, 3), 0x52, 249, 0x8f, 0],
+            &[call(0b10, 3) ^ 0x80, 0x52, 249, 0x8f, check],
+            &[call(0b10, 3), 0x21, 0x24, 0x05],
+            &[call(0b11, 3), 0x52, 249, 0x8f, check],
+        ];
+        for bytes in requests {
+            assert!(receive(&mut receiver, bytes).is_empty());
+        }
+    }
+
+    #[test]
+    fn only_acknowledges_its_own_address() {
+        let mut receiver = XpressNetReceiver::new(20);
+        assert!(receive(&mut receiver, &[call(0b00, 21), call(0b10, 20)]).is_empty());
+        assert_eq!(
+            receive(&mut receiver, &[call(0b00, 20)]),
+            [XpressNetEvent::AcknowledgementRequest]
+        );
+    }
+}
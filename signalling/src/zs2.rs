@@ -0,0 +1,134 @@
+//! Module for Zs2 direction indicators, which show the route of a train as a letter next to the main signal.
+
+use embedded_hal::digital::OutputPin;
+
+use crate::max7219::Max7219;
+
+// Lit segments for the letters that are readable on a seven-segment display, with segment a in bit 0 to segment g in bit 6. Some letters can only be shown in lowercase, and letters that look like digits are missing.
+const LETTER_SEGMENTS: [(u8, u8); 16] = [
+    (b'A', 0x77),
+    (b'B', 0x7c),
+    (b'C', 0x39),
+    (b'D', 0x5e),
+    (b'E', 0x79),
+    (b'F', 0x71),
+    (b'G', 0x3d),
+    (b'H', 0x76),
+    (b'J', 0x1e),
+    (b'L', 0x38),
+    (b'N', 0x54),
+    (b'P', 0x73),
+    (b'R', 0x50),
+    (b'T', 0x78),
+    (b'U', 0x3e),
+    (b'Y', 0x6e),
+];
+
+// How the letter of an indicator is displayed.
+enum Display<Error, PinType: OutputPin<Error = Error>> {
+    // Segments a to g, in the usual order: clockwise from the top, then the middle.
+    Segments([PinType; 7]),
+    Matrix(Max7219<Error, PinType>),
+}
+
+/// A Zs2 direction indicator, showing the route as an uppercase letter on a white seven-segment display or LED matrix. The indicator is dark while no route is shown.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used. Its parameters additionally include the output pin’s error type (which some functions also return).
+pub struct Zs2Indicator<Error, PinType: OutputPin<Error = Error>> {
+    display: Display<Error, PinType>,
+    // Route letter that is currently shown, if any.
+    route: Option<u8>,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> Zs2Indicator<Error, PinType> {
+    /// Creates a dark direction indicator with the given segments a to g. Only some letters can be shown on seven segments.
+    pub fn new(segments: [PinType; 7]) -> Self {
+        Self {
+            display: Display::Segments(segments),
+            route: None,
+        }
+    }
+
+    /// Creates a dark direction indicator on an LED matrix module, which can show every letter.
+    pub fn new_matrix(matrix: Max7219<Error, PinType>) -> Self {
+        Self {
+            display: Display::Matrix(matrix),
+            route: None,
+        }
+    }
+
+    /// Returns whether the indicator can show the given route, an uppercase ASCII letter.
+    pub fn supports_route(&self, route: u8) -> bool {
+        route.is_ascii_uppercase()
+            && match self.display {
+                Display::Segments(_) => Self::segments_for(route).is_some(),
+                Display::Matrix(_) => true,
+            }
+    }
+
+    /// Returns the route that is currently shown, if any.
+    pub fn route(&self) -> Option<u8> {
+        self.route
+    }
+
+    /// Shows the given route letter, or nothing.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    ///
+    /// # Panics
+    /// This function will panic if the route can’t be shown, which is a logic bug; see [`Self::supports_route`].
+    pub fn show(&mut self, route: Option<u8>) -> Result<(), Error> {
+        if let Some(route) = route {
+            if !self.supports_route(route) {
+                panic!("illegal route for this indicator, letter can’t be displayed");
+            }
+        }
+        match &mut self.display {
+            Display::Segments(segments) => {
+                let lit_segments = route.and_then(Self::segments_for).unwrap_or(0);
+                for (segment, lamp) in segments.iter_mut().enumerate() {
+                    lamp.set_state((lit_segments & 1 << segment != 0).into())?;
+                }
+            }
+            Display::Matrix(matrix) => matrix.show_character(route)?,
+        }
+        self.route = route;
+        Ok(())
+    }
+
+    fn segments_for(route: u8) -> Option<u8> {
+        LETTER_SEGMENTS
+            .iter()
+            .find(|(letter, _)| *letter == route)
+            .map(|(_, lit_segments)| *lit_segments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use super::Zs2Indicator;
+    use crate::mock::MockPin;
+    use crate::mock::MockPins;
+
+    #[test]
+    fn shows_readable_letters_on_segments() {
+        let pins = MockPins::new();
+        let mut indicator: Zs2Indicator<Infallible, MockPin> =
+            Zs2Indicator::new([(); 7].map(|_| pins.pin()));
+        indicator.show(Some(b'R')).unwrap();
+        assert_eq!(
+            pins.states(),
+            [false, false, false, false, true, false, true]
+        );
+        indicator.show(None).unwrap();
+        assert_eq!(pins.states(), [false; 7]);
+        assert!(indicator.supports_route(b'H'));
+        assert!(!indicator.supports_route(b'M'));
+        assert!(!indicator.supports_route(b'6'));
+    }
+}
@@ -0,0 +1,109 @@
+//! Module for Zs3 speed indicators, which show the permitted speed as a single digit next to the main signal, and the Zs3v indicators that announce it at the distant signal.
+
+use embedded_hal::digital::OutputPin;
+
+use crate::max7219::Max7219;
+
+// Lit segments for each digit, with segment a in bit 0 to segment g in bit 6.
+const DIGIT_SEGMENTS: [u8; 10] = [0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07, 0x7f, 0x6f];
+
+// How the digit of an indicator is displayed.
+enum Display<Error, PinType: OutputPin<Error = Error>> {
+    // Segments a to g, in the usual order: clockwise from the top, then the middle.
+    Segments([PinType; 7]),
+    Matrix(Max7219<Error, PinType>),
+}
+
+/// A Zs3 speed indicator, showing the speed in tens of km/h on a white seven-segment display or LED matrix. The indicator is dark while no speed is shown.
+///
+/// # Type parameters
+///
+/// This type is generic over the kind of output pin used. Its parameters additionally include the output pin’s error type (which some functions also return).
+pub struct Zs3Indicator<Error, PinType: OutputPin<Error = Error>> {
+    display: Display<Error, PinType>,
+    // Speed that is currently shown, if any.
+    speed: Option<u8>,
+}
+
+impl<Error, PinType: OutputPin<Error = Error>> Zs3Indicator<Error, PinType> {
+    /// Creates a dark speed indicator with the given segments a to g.
+    pub fn new(segments: [PinType; 7]) -> Self {
+        Self {
+            display: Display::Segments(segments),
+            speed: None,
+        }
+    }
+
+    /// Creates a dark speed indicator on an LED matrix module, which needs fewer pins than a seven-segment display.
+    pub fn new_matrix(matrix: Max7219<Error, PinType>) -> Self {
+        Self {
+            display: Display::Matrix(matrix),
+            speed: None,
+        }
+    }
+
+    /// Returns whether the indicator can show the given speed in tens of km/h. Speeds from 10 to 90 km/h can be shown.
+    pub fn supports_speed(&self, speed: u8) -> bool {
+        (1..=9).contains(&speed)
+    }
+
+    /// Returns the speed that is currently shown, if any.
+    pub fn speed(&self) -> Option<u8> {
+        self.speed
+    }
+
+    /// Shows the given speed in tens of km/h, or nothing.
+    ///
+    /// # Errors
+    /// Errors are returned from the HAL’s digital I/O functions.
+    ///
+    /// # Panics
+    /// This function will panic if the speed can’t be shown, which is a logic bug; see [`Self::supports_speed`].
+    pub fn show(&mut self, speed: Option<u8>) -> Result<(), Error> {
+        if let Some(speed) = speed {
+            if !self.supports_speed(speed) {
+                panic!("illegal speed for this indicator, only single digits available");
+            }
+        }
+        match &mut self.display {
+            Display::Segments(segments) => {
+                let lit_segments = speed.map_or(0, |speed| DIGIT_SEGMENTS[usize::from(speed)]);
+                for (segment, lamp) in segments.iter_mut().enumerate() {
+                    lamp.set_state((lit_segments & 1 << segment != 0).into())?;
+                }
+            }
+            Display::Matrix(matrix) => matrix.show_character(speed.map(|speed| b'0' + speed))?,
+        }
+        self.speed = speed;
+        Ok(())
+    }
+}
+
+/// A Zs3v speed announcement indicator next to a distant signal, showing the speed of the next main signal in tens of km/h. It is wired like a Zs3 indicator, but its segments or LEDs are yellow.
+pub type Zs3vIndicator<Error, PinType> = Zs3Indicator<Error, PinType>;
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use super::Zs3Indicator;
+    use crate::mock::MockPin;
+    use crate::mock::MockPins;
+
+    #[test]
+    fn shows_speed_digits() {
+        let pins = MockPins::new();
+        let mut indicator: Zs3Indicator<Infallible, MockPin> =
+            Zs3Indicator::new([(); 7].map(|_| pins.pin()));
+        indicator.show(Some(6)).unwrap();
+        assert_eq!(pins.states(), [true, false, true, true, true, true, true]);
+        indicator.show(Some(1)).unwrap();
+        assert_eq!(
+            pins.states(),
+            [false, true, true, false, false, false, false]
+        );
+        indicator.show(None).unwrap();
+        assert_eq!(pins.states(), [false; 7]);
+        assert!(!indicator.supports_speed(10));
+    }
+}